@@ -1,34 +1,293 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, LazyLock, Mutex, MutexGuard, PoisonError},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
 use async_trait::async_trait;
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, HistogramVec,
+    IntCounterVec,
+};
+use tokio::{runtime::Handle, task::JoinHandle};
+use tracing::error;
+
+use cim_slo::{errors, Result};
 
-use cim_slo::Result;
+static RUNS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "job_runs_total",
+        "Runs of the scheduled jobs by result",
+        &["job", "result"]
+    )
+    .unwrap()
+});
+
+static DURATION: LazyLock<HistogramVec> = LazyLock::new(|| {
+    register_histogram_vec!(
+        "job_duration_seconds",
+        "Duration of the runs of the scheduled jobs",
+        &["job"],
+        vec![0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0]
+    )
+    .unwrap()
+});
 
 #[async_trait]
-pub trait Job {
+pub trait Job: Send + Sync {
     async fn run(&self) -> Result<()>;
 }
 
 #[async_trait]
 impl<F> Job for F
 where
-    F: Fn() -> Result<()> + Sync,
+    F: Fn() -> Result<()> + Send + Sync,
 {
     async fn run(&self) -> Result<()> {
         (self)()
     }
 }
 
-pub trait Trigger {
-    fn next(self, now: i64) -> Result<i64>;
+/// Trigger tells when a job runs, times are unix milliseconds
+pub trait Trigger: Send + Sync {
+    /// next is when to run after now, an error stops running the job
+    fn next(&self, now: i64) -> Result<i64>;
+}
+
+/// Interval runs a job every period, the first run is a period after it's
+/// started
+#[derive(Clone, Copy, Debug)]
+pub struct Interval(pub Duration);
+
+impl Trigger for Interval {
+    fn next(&self, now: i64) -> Result<i64> {
+        if self.0.is_zero() {
+            return Err(errors::bad_request("interval must not be zero"));
+        }
+        Ok(now.saturating_add(self.0.as_millis() as i64))
+    }
 }
 
 pub trait Scheduler {
-    fn start() -> Result<()>;
-    fn schedule<J: Job, T: Trigger>(&self, job: J, trigger: T) -> Result<()>;
+    /// start runs the jobs scheduled, the ones scheduled later run as soon
+    /// as they are scheduled
+    fn start(&self) -> Result<()>;
+    /// schedule runs job under key when trigger tells, it replaces the job
+    /// scheduled under the same key
+    fn schedule<J, T>(&self, key: &str, job: J, trigger: T) -> Result<()>
+    where
+        J: Job + 'static,
+        T: Trigger + 'static;
     fn get_keys(&self) -> Vec<String>;
-    fn get_job(&self, key: &str) -> Option<()>;
+    fn get_job(&self, key: &str) -> Option<Arc<dyn Job>>;
     fn delete(&self, key: &str) -> Result<()>;
     fn has(&self, key: &str) -> bool;
 }
 
+/// LocalScheduler runs the jobs as tasks of the tokio runtime it's started
+/// in. A job never overlaps itself, its next run is asked of the trigger
+/// once the last one finished. The runs are counted by `job_runs_total`
+/// and timed by `job_duration_seconds`
+#[derive(Clone, Default)]
+pub struct LocalScheduler {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    runtime: Option<Handle>,
+    entries: HashMap<String, Entry>,
+}
+
+struct Entry {
+    job: Arc<dyn Job>,
+    trigger: Arc<dyn Trigger>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl Entry {
+    fn spawn(&mut self, runtime: &Handle, key: &str) {
+        self.task = Some(runtime.spawn(drive(
+            key.to_owned(),
+            Arc::clone(&self.job),
+            Arc::clone(&self.trigger),
+        )));
+    }
+
+    fn abort(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+}
+
+impl LocalScheduler {
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// stop aborts the running jobs, they stay scheduled and run again once
+    /// started
+    pub fn stop(&self) {
+        let mut inner = self.lock();
+        inner.runtime = None;
+        for entry in inner.entries.values_mut() {
+            entry.abort();
+        }
+    }
+}
+
+impl fmt::Debug for LocalScheduler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalScheduler")
+            .field("keys", &self.get_keys())
+            .finish()
+    }
+}
+
+impl Scheduler for LocalScheduler {
+    fn start(&self) -> Result<()> {
+        let runtime = Handle::try_current().map_err(errors::any)?;
+        let mut inner = self.lock();
+        for (key, entry) in inner.entries.iter_mut() {
+            if entry.task.is_none() {
+                entry.spawn(&runtime, key);
+            }
+        }
+        inner.runtime = Some(runtime);
+        Ok(())
+    }
+
+    fn schedule<J, T>(&self, key: &str, job: J, trigger: T) -> Result<()>
+    where
+        J: Job + 'static,
+        T: Trigger + 'static,
+    {
+        let mut entry = Entry {
+            job: Arc::new(job),
+            trigger: Arc::new(trigger),
+            task: None,
+        };
+        let mut inner = self.lock();
+        if let Some(runtime) = &inner.runtime {
+            entry.spawn(runtime, key);
+        }
+        if let Some(mut old) = inner.entries.insert(key.to_owned(), entry) {
+            old.abort();
+        }
+        Ok(())
+    }
+
+    fn get_keys(&self) -> Vec<String> {
+        self.lock().entries.keys().cloned().collect()
+    }
+
+    fn get_job(&self, key: &str) -> Option<Arc<dyn Job>> {
+        self.lock().entries.get(key).map(|v| Arc::clone(&v.job))
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        match self.lock().entries.remove(key) {
+            Some(mut entry) => {
+                entry.abort();
+                Ok(())
+            }
+            None => Err(errors::not_found(&format!("no job {key}"))),
+        }
+    }
+
+    fn has(&self, key: &str) -> bool {
+        self.lock().entries.contains_key(key)
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64
+}
+
+/// drive runs job whenever trigger tells until the trigger fails
+async fn drive(key: String, job: Arc<dyn Job>, trigger: Arc<dyn Trigger>) {
+    loop {
+        let now = now();
+        let at = match trigger.next(now) {
+            Ok(v) => v,
+            Err(err) => {
+                error!("job {} is no longer triggered: {}", key, err);
+                return;
+            }
+        };
+        tokio::time::sleep(Duration::from_millis(
+            at.saturating_sub(now).max(0) as u64,
+        ))
+        .await;
+
+        let timer = DURATION.with_label_values(&[&key]).start_timer();
+        let result = job.run().await;
+        timer.observe_duration();
+        match result {
+            Ok(()) => RUNS.with_label_values(&[&key, "ok"]).inc(),
+            Err(err) => {
+                RUNS.with_label_values(&[&key, "error"]).inc();
+                error!("job {} failed: {}", key, err);
+            }
+        }
+    }
+}
+
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn counter() -> (Arc<AtomicUsize>, impl Job) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let runs = Arc::clone(&count);
+        (count, move || {
+            runs.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        })
+    }
+
+    #[tokio::test]
+    async fn interval() {
+        let scheduler = LocalScheduler::default();
+        let (count, job) = counter();
+        scheduler
+            .schedule("count", job, Interval(Duration::from_millis(10)))
+            .unwrap();
+        assert!(scheduler.has("count"));
+        assert_eq!(scheduler.get_keys(), vec!["count".to_owned()]);
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+        scheduler.start().unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(count.load(Ordering::SeqCst) >= 2);
+
+        scheduler.delete("count").unwrap();
+        assert!(!scheduler.has("count"));
+        assert!(scheduler.delete("count").is_err());
+        let stopped = count.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(count.load(Ordering::SeqCst), stopped);
+    }
+
+    #[tokio::test]
+    async fn failed_trigger() {
+        let scheduler = LocalScheduler::default();
+        scheduler.start().unwrap();
+        let (count, job) = counter();
+        scheduler
+            .schedule("count", job, Interval(Duration::ZERO))
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+        scheduler.get_job("count").unwrap().run().await.unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde_json::value::RawValue;
+
+use crate::req::Request;
+
+/// AttributeProvider lazily resolves attributes referenced by statement
+/// conditions which the caller did not put into `Request.context`,
+/// such as user claims, group membership or the owner of a resource.
+#[async_trait]
+pub trait AttributeProvider: Send + Sync {
+    /// resolve return `None` when the provider doesn't know the attribute
+    async fn resolve(
+        &self,
+        key: &str,
+        req: &Request,
+    ) -> Result<Option<Box<RawValue>>>;
+}
+
+#[async_trait]
+impl<P: AttributeProvider + ?Sized> AttributeProvider for &P {
+    async fn resolve(
+        &self,
+        key: &str,
+        req: &Request,
+    ) -> Result<Option<Box<RawValue>>> {
+        (**self).resolve(key, req).await
+    }
+}
+
+#[async_trait]
+impl<P: AttributeProvider + ?Sized> AttributeProvider for Box<P> {
+    async fn resolve(
+        &self,
+        key: &str,
+        req: &Request,
+    ) -> Result<Option<Box<RawValue>>> {
+        (**self).resolve(key, req).await
+    }
+}
+
+/// providers are asked in order, the first one that knows the attribute wins
+#[async_trait]
+impl<P: AttributeProvider> AttributeProvider for Vec<P> {
+    async fn resolve(
+        &self,
+        key: &str,
+        req: &Request,
+    ) -> Result<Option<Box<RawValue>>> {
+        for provider in self.iter() {
+            if let Some(value) = provider.resolve(key, req).await? {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl AttributeProvider for HashMap<String, Box<RawValue>> {
+    async fn resolve(
+        &self,
+        key: &str,
+        _req: &Request,
+    ) -> Result<Option<Box<RawValue>>> {
+        Ok(self.get(key).cloned())
+    }
+}
+
+/// Attributes caches resolved attributes for one evaluation, so every
+/// attribute is fetched from the provider at most once.
+pub(crate) struct Attributes<'a, P: ?Sized> {
+    provider: &'a P,
+    cache: HashMap<String, Option<Box<RawValue>>>,
+}
+
+impl<'a, P: AttributeProvider + ?Sized> Attributes<'a, P> {
+    pub(crate) fn new(provider: &'a P) -> Self {
+        Self {
+            provider,
+            cache: HashMap::new(),
+        }
+    }
+
+    pub(crate) async fn get(
+        &mut self,
+        key: &str,
+        req: &Request,
+    ) -> Result<Option<Box<RawValue>>> {
+        if let Some(value) = req.context.get(key) {
+            return Ok(Some(value.clone()));
+        }
+        if let Some(value) = self.cache.get(key) {
+            return Ok(value.clone());
+        }
+        let value = self.provider.resolve(key, req).await?;
+        self.cache.insert(key.to_owned(), value.clone());
+        Ok(value)
+    }
+}
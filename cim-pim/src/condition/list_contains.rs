@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+
+use super::Condition;
+use crate::req::Request;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ListContains {
+    pub values: Vec<String>,
+}
+
+impl Condition for ListContains {
+    fn evaluate(&self, input: Box<RawValue>, _req: &Request) -> bool {
+        if let Ok(v) = serde_json::from_str::<Vec<String>>(input.get()) {
+            return self.values.iter().any(|value| v.contains(value));
+        }
+        false
+    }
+}
@@ -1,6 +1,9 @@
 pub(crate) mod boolean;
 pub(crate) mod cidr;
+pub(crate) mod equals_subject;
+pub(crate) mod list_contains;
 pub(crate) mod numeric_cmp;
+pub(crate) mod relation;
 pub(crate) mod resource_contains;
 pub(crate) mod string_cmp;
 pub(crate) mod string_match;
@@ -71,6 +74,14 @@ impl JsonCondition {
             "ResourceContains" => {
                 Ok(Box::new(resource_contains::ResourceContains))
             }
+            "Relation" => Ok(Box::new(relation::Relation)),
+            "EqualsSubject" => Ok(Box::new(equals_subject::EqualsSubject)),
+            "ListContains" => {
+                let result: list_contains::ListContains =
+                    serde_json::from_str(self.options.get())
+                        .context("Could not parse ListContains")?;
+                Ok(Box::new(result))
+            }
             v => Err(anyhow::anyhow!("Could not find condition type {}", v)),
         }
     }
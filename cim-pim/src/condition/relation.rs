@@ -0,0 +1,16 @@
+use serde_json::value::RawValue;
+
+use super::Condition;
+use crate::req::Request;
+
+/// Relation requires the relation named by the condition key, like
+/// `relation:viewer`, to be granted to the subject on the requested resource.
+/// The attribute is resolved by a relationship based access control provider.
+#[derive(Debug)]
+pub struct Relation;
+
+impl Condition for Relation {
+    fn evaluate(&self, input: Box<RawValue>, _req: &Request) -> bool {
+        serde_json::from_str::<bool>(input.get()).unwrap_or_default()
+    }
+}
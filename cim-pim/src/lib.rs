@@ -1,13 +1,18 @@
+mod attribute;
 mod condition;
 mod matcher;
+pub mod rebac;
 mod req;
 mod statement;
 
 use anyhow::Result;
 
+use attribute::Attributes;
+
+pub use attribute::AttributeProvider;
 pub use condition::JsonCondition;
 pub use matcher::{reg::Regexp, Matcher};
-pub use req::Request;
+pub use req::{PrincipalType, Request};
 pub use statement::{Effect, Statement};
 
 pub struct Pim<M> {
@@ -25,45 +30,85 @@ impl<M: Matcher> Pim<M> {
         tracing::debug!("input = {:?}, list = {:?}", input, list);
         let mut allowed = false;
         for statement in list.iter() {
-            if !self.matcher.matches(
-                statement.get_start_delimiter(),
-                statement.get_end_delimiter(),
-                statement.actions.clone(),
-                &input.action,
-            )? {
+            if !self.matches(statement, input)? {
                 continue;
             }
-            if !self.matcher.matches(
-                statement.get_start_delimiter(),
-                statement.get_end_delimiter(),
-                statement.subjects.clone(),
-                &input.subject,
-            )? {
+            if !evaluate_conditions(statement, input)? {
                 continue;
             }
-            if !self.matcher.matches(
-                statement.get_start_delimiter(),
-                statement.get_end_delimiter(),
-                statement.resources.clone(),
-                &input.resource,
-            )? {
+            if let Effect::Deny = statement.effect {
+                return Err(denied(statement));
+            }
+            allowed = true;
+        }
+        if !allowed {
+            return Err(not_matched());
+        }
+        Ok(())
+    }
+
+    /// is_allow_with works like is_allow, but the condition keys missing in
+    /// `input.context` are resolved through `provider`, at most once per key.
+    pub async fn is_allow_with<P: AttributeProvider + ?Sized>(
+        &self,
+        list: &[Statement],
+        input: &Request,
+        provider: &P,
+    ) -> Result<()>
+    where
+        M: Sync,
+    {
+        tracing::debug!("input = {:?}, list = {:?}", input, list);
+        let mut attributes = Attributes::new(provider);
+        let mut allowed = false;
+        for statement in list.iter() {
+            if !self.matches(statement, input)? {
                 continue;
             }
-            if !evaluate_conditions(statement, input)? {
+            if !evaluate_conditions_with(statement, input, &mut attributes)
+                .await?
+            {
                 continue;
             }
             if let Effect::Deny = statement.effect {
-                return Err(anyhow::anyhow!("The request was denied because a statement denied request.Please proofread the policy {:?}",statement));
+                return Err(denied(statement));
             }
             allowed = true;
         }
         if !allowed {
-            return Err(anyhow::anyhow!(
-                "The request was denied because no matching statement was found.",
-            ));
+            return Err(not_matched());
         }
         Ok(())
     }
+
+    fn matches(&self, statement: &Statement, input: &Request) -> Result<bool> {
+        Ok(self.matcher.matches(
+            statement.get_start_delimiter(),
+            statement.get_end_delimiter(),
+            statement.actions.clone(),
+            &input.action,
+        )? && self.matcher.matches(
+            statement.get_start_delimiter(),
+            statement.get_end_delimiter(),
+            statement.subjects.clone(),
+            &input.subject,
+        )? && self.matcher.matches(
+            statement.get_start_delimiter(),
+            statement.get_end_delimiter(),
+            statement.resources.clone(),
+            &input.resource,
+        )?)
+    }
+}
+
+fn denied(statement: &Statement) -> anyhow::Error {
+    anyhow::anyhow!("The request was denied because a statement denied request.Please proofread the policy {:?}",statement)
+}
+
+fn not_matched() -> anyhow::Error {
+    anyhow::anyhow!(
+        "The request was denied because no matching statement was found.",
+    )
 }
 
 fn evaluate_conditions(statement: &Statement, input: &Request) -> Result<bool> {
@@ -80,15 +125,32 @@ fn evaluate_conditions(statement: &Statement, input: &Request) -> Result<bool> {
     Ok(true)
 }
 
+async fn evaluate_conditions_with<P: AttributeProvider + ?Sized>(
+    statement: &Statement,
+    input: &Request,
+    attributes: &mut Attributes<'_, P>,
+) -> Result<bool> {
+    if let Some(conditions) = &statement.conditions {
+        for (key, value) in conditions {
+            if let Some(env) = attributes.get(key, input).await? {
+                if !value.into()?.evaluate(env, input) {
+                    return Ok(false);
+                }
+            }
+        }
+    }
+    Ok(true)
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
 
     use crate::condition::{
-        boolean::Boolean, cidr::Cidr, numeric_cmp::NumericCmp,
-        string_cmp::StringCmp, string_cmp::StringCmpInner,
-        string_match::StringMatch, time_cmp::TimeCmp, time_cmp::TimeCmpInner,
-        JsonCondition,
+        boolean::Boolean, cidr::Cidr, list_contains::ListContains,
+        numeric_cmp::NumericCmp, string_cmp::StringCmp,
+        string_cmp::StringCmpInner, string_match::StringMatch,
+        time_cmp::TimeCmp, time_cmp::TimeCmpInner, JsonCondition,
     };
 
     use super::*;
@@ -201,6 +263,7 @@ mod tests {
                 resource: "myrn:some.domain.com:resource:123".to_owned(),
                 action: "delete".to_owned(),
                 subject: "peter".to_owned(),
+                principal_type: PrincipalType::User,
                 context: HashMap::from([
                     (
                         "clientIP".to_owned(),
@@ -242,4 +305,76 @@ mod tests {
         )
         .unwrap();
     }
+
+    struct CountProvider {
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl AttributeProvider for CountProvider {
+        async fn resolve(
+            &self,
+            key: &str,
+            _req: &Request,
+        ) -> Result<Option<Box<serde_json::value::RawValue>>> {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            match key {
+                "owner" => Ok(Some(serde_json::value::to_raw_value("peter")?)),
+                "groups" => {
+                    Ok(Some(serde_json::value::to_raw_value(&["1", "2"])?))
+                }
+                _ => Ok(None),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn is_allow_with() {
+        let statement = Statement {
+            effect: Effect::Allow,
+            subjects: vec!["<.*>".to_owned()],
+            actions: vec!["get".to_owned()],
+            resources: vec!["<.*>".to_owned()],
+            conditions: Some(HashMap::from([
+                (
+                    "owner".to_owned(),
+                    JsonCondition {
+                        jtype: "EqualsSubject".to_owned(),
+                        options: serde_json::value::to_raw_value("{}").unwrap(),
+                    },
+                ),
+                (
+                    "groups".to_owned(),
+                    JsonCondition {
+                        jtype: "ListContains".to_owned(),
+                        options: serde_json::value::to_raw_value(
+                            &ListContains {
+                                values: vec!["2".to_owned()],
+                            },
+                        )
+                        .unwrap(),
+                    },
+                ),
+            ])),
+            meta: None,
+        };
+        let sts = vec![statement.clone(), statement];
+        let provider = CountProvider {
+            calls: std::sync::atomic::AtomicUsize::new(0),
+        };
+        let p = super::Pim::new(Regexp::new(256).unwrap());
+        let mut req = Request {
+            resource: "crn:iam:user:1".to_owned(),
+            action: "get".to_owned(),
+            subject: "peter".to_owned(),
+            principal_type: PrincipalType::User,
+            context: HashMap::new(),
+        };
+        p.is_allow_with(&sts, &req, &provider).await.unwrap();
+        // every attribute is resolved once per evaluation
+        assert_eq!(provider.calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        req.subject = "max".to_owned();
+        assert!(p.is_allow_with(&sts, &req, &provider).await.is_err());
+    }
 }
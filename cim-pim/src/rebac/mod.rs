@@ -0,0 +1,469 @@
+mod tuple;
+
+use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+pub use tuple::{Subject, Tuple};
+
+/// default depth of nested usersets followed by check and expand
+const DEFAULT_MAX_DEPTH: usize = 32;
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// TupleReader reads the direct subjects of `object#relation` from a tuple store
+#[async_trait]
+pub trait TupleReader: Send + Sync {
+    async fn read(&self, object: &str, relation: &str) -> Result<Vec<Subject>>;
+}
+
+#[async_trait]
+impl<R: TupleReader + ?Sized> TupleReader for Arc<R> {
+    async fn read(&self, object: &str, relation: &str) -> Result<Vec<Subject>> {
+        (**self).read(object, relation).await
+    }
+}
+
+/// Namespace describes how the relations of one object type are computed
+#[derive(Debug, Default, Deserialize, Serialize, Clone, utoipa::ToSchema)]
+pub struct Namespace {
+    pub name: String,
+    #[serde(default)]
+    #[schema(no_recursion)]
+    pub relations: HashMap<String, Rewrite>,
+}
+
+/// Rewrite is the userset rewrite rule of a relation
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, utoipa::ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Rewrite {
+    /// subjects written directly as `object#relation@subject`
+    This,
+    /// subjects of another relation of the same object
+    ComputedUserset { relation: String },
+    /// subjects of `computed_userset` on every object referenced
+    /// by `object#tupleset`, e.g. the viewers of a document's parent folder
+    TupleToUserset {
+        tupleset: String,
+        computed_userset: String,
+    },
+    Union {
+        #[schema(no_recursion)]
+        children: Vec<Rewrite>,
+    },
+    Intersection {
+        #[schema(no_recursion)]
+        children: Vec<Rewrite>,
+    },
+    Exclusion {
+        #[schema(no_recursion)]
+        base: Box<Rewrite>,
+        #[schema(no_recursion)]
+        subtract: Box<Rewrite>,
+    },
+}
+
+/// Tree is the expanded userset of `object#relation`
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, utoipa::ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Tree {
+    Leaf {
+        object: String,
+        relation: String,
+        #[schema(value_type = Vec<String>)]
+        subjects: Vec<Subject>,
+    },
+    Union {
+        #[schema(no_recursion)]
+        children: Vec<Tree>,
+    },
+    Intersection {
+        #[schema(no_recursion)]
+        children: Vec<Tree>,
+    },
+    Exclusion {
+        #[schema(no_recursion)]
+        base: Box<Tree>,
+        #[schema(no_recursion)]
+        subtract: Box<Tree>,
+    },
+}
+
+/// Rebac evaluates relationship based access control over a tuple store
+/// in the way of Zanzibar
+#[derive(Debug)]
+pub struct Rebac<R> {
+    namespaces: Arc<HashMap<String, Namespace>>,
+    reader: R,
+    max_depth: usize,
+}
+
+impl<R> Rebac<R> {
+    pub fn new(namespaces: Vec<Namespace>, reader: R) -> Self {
+        Self {
+            namespaces: Arc::new(
+                namespaces
+                    .into_iter()
+                    .map(|v| (v.name.clone(), v))
+                    .collect(),
+            ),
+            reader,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    /// with_reader evaluates the same namespaces over another reader, like
+    /// the tuple store of a transaction
+    pub fn with_reader<S>(&self, reader: S) -> Rebac<S> {
+        Rebac {
+            namespaces: Arc::clone(&self.namespaces),
+            reader,
+            max_depth: self.max_depth,
+        }
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    fn rewrite(&self, object: &str, relation: &str) -> Rewrite {
+        let namespace = object.split(':').next().unwrap_or_default();
+        self.namespaces
+            .get(namespace)
+            .and_then(|v| v.relations.get(relation))
+            .cloned()
+            .unwrap_or(Rewrite::This)
+    }
+}
+
+impl<R: TupleReader> Rebac<R> {
+    /// check return whether subject has relation on object
+    pub async fn check(
+        &self,
+        object: &str,
+        relation: &str,
+        subject: &str,
+    ) -> Result<bool> {
+        self.check_relation(object, relation, subject, 0).await
+    }
+
+    /// expand return the userset tree of `object#relation`
+    pub async fn expand(&self, object: &str, relation: &str) -> Result<Tree> {
+        self.expand_relation(object, relation, 0).await
+    }
+
+    fn check_relation<'a>(
+        &'a self,
+        object: &'a str,
+        relation: &'a str,
+        subject: &'a str,
+        depth: usize,
+    ) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            if depth > self.max_depth {
+                return Err(anyhow::anyhow!(
+                    "max depth exceeded while checking {}#{}",
+                    object,
+                    relation
+                ));
+            }
+            let rewrite = self.rewrite(object, relation);
+            self.check_rewrite(object, relation, rewrite, subject, depth)
+                .await
+        })
+    }
+
+    fn check_rewrite<'a>(
+        &'a self,
+        object: &'a str,
+        relation: &'a str,
+        rewrite: Rewrite,
+        subject: &'a str,
+        depth: usize,
+    ) -> BoxFuture<'a, Result<bool>> {
+        Box::pin(async move {
+            match rewrite {
+                Rewrite::This => {
+                    for v in self.reader.read(object, relation).await? {
+                        match v {
+                            Subject::Id(id) => {
+                                if id == subject {
+                                    return Ok(true);
+                                }
+                            }
+                            Subject::Set { object, relation } => {
+                                if self
+                                    .check_relation(
+                                        &object,
+                                        &relation,
+                                        subject,
+                                        depth + 1,
+                                    )
+                                    .await?
+                                {
+                                    return Ok(true);
+                                }
+                            }
+                        }
+                    }
+                    Ok(false)
+                }
+                Rewrite::ComputedUserset { relation } => {
+                    self.check_relation(object, &relation, subject, depth + 1)
+                        .await
+                }
+                Rewrite::TupleToUserset {
+                    tupleset,
+                    computed_userset,
+                } => {
+                    for v in self.reader.read(object, &tupleset).await? {
+                        if self
+                            .check_relation(
+                                v.object(),
+                                &computed_userset,
+                                subject,
+                                depth + 1,
+                            )
+                            .await?
+                        {
+                            return Ok(true);
+                        }
+                    }
+                    Ok(false)
+                }
+                Rewrite::Union { children } => {
+                    for child in children {
+                        if self
+                            .check_rewrite(
+                                object, relation, child, subject, depth,
+                            )
+                            .await?
+                        {
+                            return Ok(true);
+                        }
+                    }
+                    Ok(false)
+                }
+                Rewrite::Intersection { children } => {
+                    if children.is_empty() {
+                        return Ok(false);
+                    }
+                    for child in children {
+                        if !self
+                            .check_rewrite(
+                                object, relation, child, subject, depth,
+                            )
+                            .await?
+                        {
+                            return Ok(false);
+                        }
+                    }
+                    Ok(true)
+                }
+                Rewrite::Exclusion { base, subtract } => {
+                    if !self
+                        .check_rewrite(object, relation, *base, subject, depth)
+                        .await?
+                    {
+                        return Ok(false);
+                    }
+                    Ok(!self
+                        .check_rewrite(
+                            object, relation, *subtract, subject, depth,
+                        )
+                        .await?)
+                }
+            }
+        })
+    }
+
+    fn expand_relation<'a>(
+        &'a self,
+        object: &'a str,
+        relation: &'a str,
+        depth: usize,
+    ) -> BoxFuture<'a, Result<Tree>> {
+        Box::pin(async move {
+            if depth > self.max_depth {
+                return Err(anyhow::anyhow!(
+                    "max depth exceeded while expanding {}#{}",
+                    object,
+                    relation
+                ));
+            }
+            let rewrite = self.rewrite(object, relation);
+            self.expand_rewrite(object, relation, rewrite, depth).await
+        })
+    }
+
+    fn expand_rewrite<'a>(
+        &'a self,
+        object: &'a str,
+        relation: &'a str,
+        rewrite: Rewrite,
+        depth: usize,
+    ) -> BoxFuture<'a, Result<Tree>> {
+        Box::pin(async move {
+            match rewrite {
+                Rewrite::This => Ok(Tree::Leaf {
+                    object: object.to_owned(),
+                    relation: relation.to_owned(),
+                    subjects: self.reader.read(object, relation).await?,
+                }),
+                Rewrite::ComputedUserset { relation } => {
+                    self.expand_relation(object, &relation, depth + 1).await
+                }
+                Rewrite::TupleToUserset {
+                    tupleset,
+                    computed_userset,
+                } => {
+                    let mut children = Vec::new();
+                    for v in self.reader.read(object, &tupleset).await? {
+                        children.push(
+                            self.expand_relation(
+                                v.object(),
+                                &computed_userset,
+                                depth + 1,
+                            )
+                            .await?,
+                        );
+                    }
+                    Ok(Tree::Union { children })
+                }
+                Rewrite::Union { children } => {
+                    let mut result = Vec::with_capacity(children.len());
+                    for child in children {
+                        result.push(
+                            self.expand_rewrite(object, relation, child, depth)
+                                .await?,
+                        );
+                    }
+                    Ok(Tree::Union { children: result })
+                }
+                Rewrite::Intersection { children } => {
+                    let mut result = Vec::with_capacity(children.len());
+                    for child in children {
+                        result.push(
+                            self.expand_rewrite(object, relation, child, depth)
+                                .await?,
+                        );
+                    }
+                    Ok(Tree::Intersection { children: result })
+                }
+                Rewrite::Exclusion { base, subtract } => Ok(Tree::Exclusion {
+                    base: Box::new(
+                        self.expand_rewrite(object, relation, *base, depth)
+                            .await?,
+                    ),
+                    subtract: Box::new(
+                        self.expand_rewrite(object, relation, *subtract, depth)
+                            .await?,
+                    ),
+                }),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Tuples(Vec<Tuple>);
+
+    #[async_trait]
+    impl TupleReader for Tuples {
+        async fn read(
+            &self,
+            object: &str,
+            relation: &str,
+        ) -> Result<Vec<Subject>> {
+            Ok(self
+                .0
+                .iter()
+                .filter(|v| v.object == object && v.relation == relation)
+                .map(|v| v.subject.clone())
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn check() {
+        let namespaces: Vec<Namespace> =
+            serde_json::from_value(serde_json::json!([{
+                "name": "document",
+                "relations": {
+                    "owner": {"type": "this"},
+                    "editor": {"type": "union", "children": [
+                        {"type": "this"},
+                        {"type": "computed_userset", "relation": "owner"},
+                    ]},
+                    "viewer": {"type": "exclusion",
+                        "base": {"type": "union", "children": [
+                            {"type": "this"},
+                            {"type": "computed_userset", "relation": "editor"},
+                            {"type": "tuple_to_userset", "tupleset": "parent",
+                                "computed_userset": "viewer"},
+                        ]},
+                        "subtract": {"type": "computed_userset",
+                            "relation": "banned"},
+                    },
+                },
+            }]))
+            .unwrap();
+        let tuples = [
+            "document:readme#owner@1",
+            "document:readme#parent@folder:docs",
+            "document:readme#banned@4",
+            "folder:docs#viewer@group:eng#member",
+            "group:eng#member@2",
+            "group:eng#member@4",
+        ]
+        .iter()
+        .map(|v| v.parse::<Tuple>().unwrap())
+        .collect();
+        let rebac = Rebac::new(namespaces, Tuples(tuples));
+
+        assert!(rebac.check("document:readme", "viewer", "1").await.unwrap());
+        assert!(rebac.check("document:readme", "editor", "1").await.unwrap());
+        assert!(rebac.check("document:readme", "viewer", "2").await.unwrap());
+        assert!(!rebac.check("document:readme", "editor", "2").await.unwrap());
+        assert!(!rebac.check("document:readme", "viewer", "3").await.unwrap());
+        assert!(!rebac.check("document:readme", "viewer", "4").await.unwrap());
+
+        let tree = rebac.expand("document:readme", "editor").await.unwrap();
+        assert_eq!(
+            tree,
+            Tree::Union {
+                children: vec![
+                    Tree::Leaf {
+                        object: "document:readme".to_owned(),
+                        relation: "editor".to_owned(),
+                        subjects: vec![],
+                    },
+                    Tree::Leaf {
+                        object: "document:readme".to_owned(),
+                        relation: "owner".to_owned(),
+                        subjects: vec![Subject::Id("1".to_owned())],
+                    },
+                ]
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn check_cycle() {
+        let tuples = [
+            "group:a#member@group:b#member",
+            "group:b#member@group:a#member",
+        ]
+        .iter()
+        .map(|v| v.parse::<Tuple>().unwrap())
+        .collect();
+        let rebac = Rebac::new(vec![], Tuples(tuples)).max_depth(8);
+        assert!(rebac.check("group:a", "member", "1").await.is_err());
+    }
+}
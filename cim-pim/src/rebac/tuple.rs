@@ -0,0 +1,135 @@
+use std::{fmt, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Subject is the subject of a relation tuple, either a single id like `1`
+/// or every subject of another relation like `group:eng#member`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Subject {
+    Id(String),
+    Set { object: String, relation: String },
+}
+
+impl Subject {
+    /// object return the object referenced by the subject
+    pub fn object(&self) -> &str {
+        match self {
+            Subject::Id(id) => id,
+            Subject::Set { object, .. } => object,
+        }
+    }
+}
+
+impl fmt::Display for Subject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Subject::Id(id) => write!(f, "{}", id),
+            Subject::Set { object, relation } => {
+                write!(f, "{}#{}", object, relation)
+            }
+        }
+    }
+}
+
+impl FromStr for Subject {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(anyhow::anyhow!("empty subject"));
+        }
+        match s.split_once('#') {
+            Some((object, relation)) => {
+                if object.is_empty() || relation.is_empty() {
+                    return Err(anyhow::anyhow!("invalid subject set {}", s));
+                }
+                Ok(Subject::Set {
+                    object: object.to_owned(),
+                    relation: relation.to_owned(),
+                })
+            }
+            None => Ok(Subject::Id(s.to_owned())),
+        }
+    }
+}
+
+impl Serialize for Subject {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Subject {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Tuple is a relation tuple written as `object#relation@subject`,
+/// e.g. `document:readme#viewer@group:eng#member`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Tuple {
+    pub object: String,
+    pub relation: String,
+    pub subject: Subject,
+}
+
+impl fmt::Display for Tuple {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}#{}@{}", self.object, self.relation, self.subject)
+    }
+}
+
+impl FromStr for Tuple {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (object_relation, subject) = s
+            .split_once('@')
+            .ok_or_else(|| anyhow::anyhow!("invalid tuple {}", s))?;
+        let (object, relation) = object_relation
+            .split_once('#')
+            .ok_or_else(|| anyhow::anyhow!("invalid tuple {}", s))?;
+        if object.is_empty() || relation.is_empty() {
+            return Err(anyhow::anyhow!("invalid tuple {}", s));
+        }
+        Ok(Tuple {
+            object: object.to_owned(),
+            relation: relation.to_owned(),
+            subject: subject.parse()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tuple() {
+        let tuple = "document:readme#viewer@group:eng#member"
+            .parse::<Tuple>()
+            .unwrap();
+        assert_eq!(tuple.object, "document:readme");
+        assert_eq!(tuple.relation, "viewer");
+        assert_eq!(
+            tuple.subject,
+            Subject::Set {
+                object: "group:eng".to_owned(),
+                relation: "member".to_owned(),
+            }
+        );
+        assert_eq!(
+            tuple.to_string(),
+            "document:readme#viewer@group:eng#member"
+        );
+        assert!("document:readme#viewer".parse::<Tuple>().is_err());
+        assert!("document:readme@1".parse::<Tuple>().is_err());
+    }
+}
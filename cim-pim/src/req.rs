@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::value::RawValue;
 use validator::Validate;
 
@@ -9,5 +9,21 @@ pub struct Request {
     pub resource: String,
     pub action: String,
     pub subject: String,
+    /// principal_type is the kind of subject, a user when left out
+    #[serde(default)]
+    pub principal_type: PrincipalType,
     pub context: HashMap<String, Box<RawValue>>,
 }
+
+/// PrincipalType is the kind of the subject of a request, the subject of a
+/// user is its id while applications and federated users are free form
+/// strings, as the identity provider that authenticated them issued them
+#[derive(
+    Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash,
+)]
+pub enum PrincipalType {
+    #[default]
+    User,
+    Application,
+    FederatedUser,
+}
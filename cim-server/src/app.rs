@@ -1,20 +1,52 @@
-use std::{collections::HashSet, ops::Deref, sync::Arc};
+use std::{collections::HashSet, ops::Deref, sync::Arc, time::Duration};
 
 use anyhow::Result;
 use axum::{
     extract::{FromRef, FromRequestParts},
     http::request::Parts,
 };
-use sqlx::MySqlPool;
+use chrono::NaiveDateTime;
 use tracing::info;
 
-use cim_pim::{Pim, Regexp};
+use cim_job::{Interval, Scheduler};
+use cim_pim::{
+    rebac::{Rebac, TupleReader},
+    Pim, Regexp,
+};
 use cim_slo::errors;
+use cim_storage::{
+    audit::{AuditStore, Audited, Trail},
+    authcode::AuthCode,
+    authrequest::AuthRequest,
+    changelog::ChangeStore,
+    client::Client,
+    connector::{self, Connector},
+    gc::GcStore,
+    group::{self, Group},
+    group_user::{self, GroupUser},
+    key::{KeyRing, Keys},
+    offlinesession::{self, OfflineSession},
+    policy::{self, Policy, StatementStore},
+    policy_binding::{self, PolicyBinding},
+    refresh_token::RefreshToken,
+    relation_tuple::{self, RelationTuple},
+    role::{self, Role},
+    role_binding::{self, RoleBinding},
+    user::{self, User},
+    Cacher, DynInterface, Interface, Pool, StatementCacher, Tailer, Tx, TxConn,
+    WatchStore,
+};
 
 use crate::{
-    services::oidc::{
-        key::{KeyRotator, RotationStrategy},
-        token::AccessToken,
+    config::{load_key_ring, load_namespaces},
+    services::{
+        expiry::Expirer,
+        oidc::{
+            gc::Collector,
+            key::{KeyRotator, RotationStrategy},
+            token::AccessToken,
+        },
+        purge::Purger,
     },
     AppConfig,
 };
@@ -22,21 +54,29 @@ use crate::{
 pub struct App {
     pub config: AppConfig,
     pub matcher: Pim<Regexp>,
-    pub store: Store,
-    pub key_rotator: KeyRotator<cim_storage::KeysImpl>,
-    pub access_token: AccessToken<cim_storage::KeysImpl>,
+    pub store: Arc<Store>,
+    pub key_rotator: KeyRotator<DynInterface<Keys, ()>>,
+    pub access_token: AccessToken<DynInterface<Keys, ()>>,
+    pub rebac: Rebac<Arc<dyn TupleReader>>,
 }
 
 impl App {
-    pub fn new(pool: MySqlPool, config: AppConfig) -> Result<Self> {
+    pub fn new(pool: Pool, config: AppConfig) -> Result<Self> {
         info!("initializing utility services...");
 
         let matcher = Pim::new(Regexp::new(config.cache_size)?);
 
-        let store = Store::new(pool.clone());
+        let store = Store::new(
+            &pool,
+            CacheOpts {
+                size: config.store_cache_size,
+                ttl: Duration::from_secs(config.store_cache_ttl),
+            },
+            load_key_ring(&config)?,
+        );
 
         let key_rotator = KeyRotator::new(
-            cim_storage::KeysImpl::new(pool.clone()),
+            store.key.clone(),
             RotationStrategy {
                 rotation_frequency: 60 * 60,
                 keep: 60 * 60,
@@ -44,88 +84,436 @@ impl App {
         );
 
         let access_token = AccessToken::new(
-            cim_storage::KeysImpl::new(pool),
+            store.key.clone(),
             config.expiration,
             HashSet::new(),
         );
+
+        let namespaces = match config.relation_config {
+            Some(ref v) => load_namespaces(v)?,
+            None => Vec::new(),
+        };
+        let rebac = Rebac::new(namespaces, store.tuple_reader.clone());
         info!("feature services successfully initialized!");
         Ok(Self {
             config,
-            store,
+            store: Arc::new(store),
             matcher,
             key_rotator,
             access_token,
+            rebac,
         })
     }
 }
 
+impl App {
+    /// schedule puts the background jobs of the app on scheduler
+    pub fn schedule<S: Scheduler>(&self, scheduler: &S) -> cim_slo::Result<()> {
+        let mut collector = Collector::new(
+            self.store.gc.clone(),
+            self.store.refresh.clone(),
+            self.store.offline_session.clone(),
+        );
+        collector.absolute_lifetime =
+            chrono::Duration::seconds(self.config.absolute_lifetime);
+        collector.valid_if_not_used_for =
+            chrono::Duration::seconds(self.config.valid_if_not_used_for);
+        scheduler.schedule(
+            "oidc_gc",
+            collector,
+            Interval(Duration::from_secs(self.config.gc_interval)),
+        )?;
+        scheduler.schedule(
+            "binding_expiry",
+            Expirer::new(
+                self.store.group_user.clone(),
+                self.store.role_binding.clone(),
+                self.store.policy_binding.clone(),
+            ),
+            Interval(Duration::from_secs(self.config.expiry_interval)),
+        )?;
+        scheduler.schedule(
+            "deleted_purge",
+            Purger::new(
+                self.store.clone(),
+                chrono::Duration::seconds(self.config.deleted_retention),
+            ),
+            Interval(Duration::from_secs(60 * 60)),
+        )
+    }
+}
+
 pub struct Store {
-    pub user: cim_storage::WatchStore<cim_storage::UserImpl>,
-    pub role: cim_storage::WatchStore<cim_storage::RoleImpl>,
-    pub role_binding: cim_storage::WatchStore<cim_storage::RoleBindingImpl>,
-    pub group: cim_storage::WatchStore<cim_storage::GroupImpl>,
-    pub group_user: cim_storage::WatchStore<cim_storage::GroupUserImpl>,
-    pub statement: cim_storage::PolicyImpl,
-    pub policy: cim_storage::WatchStore<cim_storage::PolicyImpl>,
-    pub policy_binding: cim_storage::WatchStore<cim_storage::PolicyBindingImpl>,
-
-    pub key: cim_storage::KeysImpl,
-    pub auth_request: cim_storage::AuthRequestImpl,
-    pub auth_code: cim_storage::AuthCodeImpl,
-    pub connector: cim_storage::ConnectorImpl,
-    pub client: cim_storage::ClientImpl,
-    pub refresh: cim_storage::RefreshTokenImpl,
-    pub offline_session: cim_storage::OfflineSessionImpl,
+    pub user: WatchStore<DynInterface<User, user::ListParams>>,
+    pub role: WatchStore<DynInterface<Role, role::ListParams>>,
+    pub role_binding:
+        WatchStore<DynInterface<RoleBinding, role_binding::ListParams>>,
+    pub group: WatchStore<DynInterface<Group, group::ListParams>>,
+    pub group_user: WatchStore<DynInterface<GroupUser, group_user::ListParams>>,
+    pub statement: Arc<dyn StatementStore + Send + Sync>,
+    pub policy: WatchStore<DynInterface<Policy, policy::ListParams>>,
+    pub policy_binding:
+        WatchStore<DynInterface<PolicyBinding, policy_binding::ListParams>>,
+    pub relation_tuple:
+        WatchStore<DynInterface<RelationTuple, relation_tuple::ListParams>>,
+    pub tuple_reader: Arc<dyn TupleReader>,
+    pub changelog: Arc<dyn ChangeStore>,
+    pub audit: Arc<dyn AuditStore>,
+    pub gc: Arc<dyn GcStore>,
+
+    pub key: DynInterface<Keys, ()>,
+    pub auth_request: DynInterface<AuthRequest, ()>,
+    pub auth_code: DynInterface<AuthCode, ()>,
+    pub connector: DynInterface<Connector, connector::ListParams>,
+    pub client: DynInterface<Client, ()>,
+    pub refresh: DynInterface<RefreshToken, ()>,
+    pub offline_session:
+        DynInterface<OfflineSession, offlinesession::ListParams>,
+
+    caches: Caches,
+    // seals the signing keys of the key store
+    ring: KeyRing,
+    pool: Pool,
 }
 
-impl Store {
-    pub fn new(pool: MySqlPool) -> Self {
-        let user = cim_storage::WatchStore::new(cim_storage::UserImpl::new(
-            pool.clone(),
-        ));
-        let role = cim_storage::WatchStore::new(cim_storage::RoleImpl::new(
-            pool.clone(),
-        ));
-        let role_binding = cim_storage::WatchStore::new(
-            cim_storage::RoleBindingImpl::new(pool.clone()),
+/// Caches are the caches of the stores written in transactions, which
+/// evict from them on commit
+struct Caches {
+    user: Cacher<DynInterface<User, user::ListParams>>,
+    policy: Cacher<DynInterface<Policy, policy::ListParams>>,
+}
+
+/// CacheOpts sizes the caches in front of the stores read by every request
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CacheOpts {
+    pub size: usize,
+    pub ttl: Duration,
+}
+
+/// new_store builds every store from the `$backend` module of cim_storage
+macro_rules! new_store {
+    ($backend:ident, $pool:expr, $source:expr, $cache:expr, $ring:expr) => {{
+        use cim_storage::$backend;
+
+        let pool = $pool;
+        let cache: CacheOpts = $cache;
+        let ring: KeyRing = $ring;
+        let statement = $backend::PolicyImpl::new(pool.clone());
+        let user = Cacher::new(
+            "user",
+            shared($backend::UserImpl::new(pool.clone())),
+            |v| v.id.clone(),
+            cache.size,
+            cache.ttl,
         );
-        let group = cim_storage::WatchStore::new(cim_storage::GroupImpl::new(
-            pool.clone(),
-        ));
-        let group_user = cim_storage::WatchStore::new(
-            cim_storage::GroupUserImpl::new(pool.clone()),
+        let policy = Cacher::new(
+            "policy",
+            shared(statement.clone()),
+            |v| v.id.clone(),
+            cache.size,
+            cache.ttl,
         );
-        let statement = cim_storage::PolicyImpl::new(pool.clone());
-        let policy = cim_storage::WatchStore::new(statement.clone());
+        let statement =
+            Arc::new(StatementCacher::new(statement, cache.size, cache.ttl));
+        let relation_tuple = $backend::RelationTupleImpl::new(pool.clone());
+        let changelog: Arc<dyn ChangeStore> =
+            Arc::new($backend::ChangelogImpl::new(pool.clone()));
+        let store = Store {
+            user: watch(user.clone(), "user", &changelog).redact(redact_user),
+            role: watch(
+                $backend::RoleImpl::new(pool.clone()),
+                "role",
+                &changelog,
+            ),
+            role_binding: watch(
+                $backend::RoleBindingImpl::new(pool.clone()),
+                "role_binding",
+                &changelog,
+            ),
+            group: watch(
+                $backend::GroupImpl::new(pool.clone()),
+                "group",
+                &changelog,
+            ),
+            group_user: watch(
+                $backend::GroupUserImpl::new(pool.clone()),
+                "group_user",
+                &changelog,
+            ),
+            policy: watch(policy.clone(), "policy", &changelog),
+            statement: statement.clone(),
+            policy_binding: watch(
+                $backend::PolicyBindingImpl::new(pool.clone()),
+                "policy_binding",
+                &changelog,
+            ),
+            relation_tuple: watch(
+                relation_tuple.clone(),
+                "relation_tuple",
+                &changelog,
+            ),
+            tuple_reader: Arc::new(relation_tuple),
+            changelog,
+            audit: Arc::new($backend::AuditImpl::new(pool.clone())),
+            gc: Arc::new($backend::GcImpl::new(pool.clone())),
+            key: shared(
+                Cacher::new(
+                    "key",
+                    $backend::KeysImpl::new(pool.clone()).sealed(ring.clone()),
+                    |v| v.id.clone(),
+                    cache.size,
+                    cache.ttl,
+                )
+                .lists(|_| String::new()),
+            ),
+            auth_request: shared($backend::AuthRequestImpl::new(pool.clone())),
+            auth_code: shared($backend::AuthCodeImpl::new(pool.clone())),
+            connector: shared($backend::ConnectorImpl::new(pool.clone())),
+            client: shared($backend::ClientImpl::new(pool.clone())),
+            refresh: shared($backend::RefreshTokenImpl::new(pool.clone())),
+            offline_session: shared($backend::OfflineSessionImpl::new(pool)),
+            caches: Caches {
+                user: user.clone(),
+                policy: policy.clone(),
+            },
+            ring,
+            pool: $source,
+        };
+        user.invalidated_by(&store.user);
+        policy.invalidated_by(&store.policy);
+        statement.invalidated_by(&store.policy);
+        statement.invalidated_by(&store.policy_binding);
+        statement.invalidated_by(&store.group);
+        statement.invalidated_by(&store.group_user);
+        statement.invalidated_by(&store.role);
+        statement.invalidated_by(&store.role_binding);
+        store
+    }};
+}
 
-        let policy_binding = cim_storage::WatchStore::new(
-            cim_storage::PolicyBindingImpl::new(pool.clone()),
-        );
-        let key = cim_storage::KeysImpl::new(pool.clone());
-        let auth_request = cim_storage::AuthRequestImpl::new(pool.clone());
-        let auth_code = cim_storage::AuthCodeImpl::new(pool.clone());
-        let connector = cim_storage::ConnectorImpl::new(pool.clone());
-        let client = cim_storage::ClientImpl::new(pool.clone());
-        let refresh = cim_storage::RefreshTokenImpl::new(pool.clone());
-        let offline_session = cim_storage::OfflineSessionImpl::new(pool);
-        Self {
-            user,
-            role,
-            role_binding,
-            group,
-            group_user,
-            statement,
-            policy,
-            policy_binding,
-            key,
-            auth_request,
-            auth_code,
-            connector,
-            client,
-            refresh,
-            offline_session,
+impl Store {
+    /// new picks the storage backend matching the pool, the user, policy
+    /// and key stores and the statements are cached as sized by cache and
+    /// the signing keys are sealed by ring
+    pub fn new(pool: &Pool, cache: CacheOpts, ring: KeyRing) -> Self {
+        let source = pool.clone();
+        match pool {
+            Pool::MySql(v) => {
+                new_store!(mariadb, v.clone(), source, cache, ring)
+            }
+            #[cfg(feature = "sqlite")]
+            Pool::Sqlite(v) => {
+                new_store!(sqlite, v.clone(), source, cache, ring)
+            }
+            #[cfg(feature = "postgres")]
+            Pool::Postgres(v) => {
+                new_store!(postgres, v.clone(), source, cache, ring)
+            }
+            Pool::Memory(v) => {
+                new_store!(memory, v.clone(), source, cache, ring)
+            }
         }
     }
+
+    /// begin opens a transaction and returns the stores running in it,
+    /// watchers of self see their events and the caches of self are
+    /// evicted only after `Tx::commit`. They aren't cached, a transaction
+    /// reads what it wrote
+    pub async fn begin(&self) -> cim_slo::Result<(Self, Tx)> {
+        self.transaction(None).await
+    }
+
+    /// audited is begin whose stores append an audit record of every
+    /// change to the transaction, made by the actor of trail
+    pub async fn audited(&self, trail: &Trail) -> cim_slo::Result<(Self, Tx)> {
+        self.transaction(Some(trail)).await
+    }
+
+    async fn transaction(
+        &self,
+        trail: Option<&Trail>,
+    ) -> cim_slo::Result<(Self, Tx)> {
+        let tx = self.pool.begin().await?;
+        let pool = self.pool.clone();
+        let cache = CacheOpts::default();
+        let ring = self.ring.clone();
+        let store = match tx.conn() {
+            TxConn::MySql(v) => {
+                new_store!(mariadb, v.clone(), pool, cache, ring)
+            }
+            #[cfg(feature = "sqlite")]
+            TxConn::Sqlite(v) => {
+                new_store!(sqlite, v.clone(), pool, cache, ring)
+            }
+            #[cfg(feature = "postgres")]
+            TxConn::Postgres(v) => {
+                new_store!(postgres, v.clone(), pool, cache, ring)
+            }
+            TxConn::Memory(v) => {
+                new_store!(memory, v.clone(), pool, cache, ring)
+            }
+        };
+        let audit = store.audit.clone();
+        let store = Self {
+            user: self.user.transaction(
+                shared(self.caches.user.transaction(
+                    audited(
+                        store.user.into_inner(),
+                        "user",
+                        &audit,
+                        trail,
+                        Some(redact_user),
+                    ),
+                    &tx,
+                )),
+                &tx,
+            ),
+            role: self.role.transaction(
+                audited(store.role.into_inner(), "role", &audit, trail, None),
+                &tx,
+            ),
+            role_binding: self.role_binding.transaction(
+                audited(
+                    store.role_binding.into_inner(),
+                    "role_binding",
+                    &audit,
+                    trail,
+                    None,
+                ),
+                &tx,
+            ),
+            group: self.group.transaction(
+                audited(store.group.into_inner(), "group", &audit, trail, None),
+                &tx,
+            ),
+            group_user: self.group_user.transaction(
+                audited(
+                    store.group_user.into_inner(),
+                    "group_user",
+                    &audit,
+                    trail,
+                    None,
+                ),
+                &tx,
+            ),
+            policy: self.policy.transaction(
+                shared(self.caches.policy.transaction(
+                    audited(
+                        store.policy.into_inner(),
+                        "policy",
+                        &audit,
+                        trail,
+                        None,
+                    ),
+                    &tx,
+                )),
+                &tx,
+            ),
+            policy_binding: self.policy_binding.transaction(
+                audited(
+                    store.policy_binding.into_inner(),
+                    "policy_binding",
+                    &audit,
+                    trail,
+                    None,
+                ),
+                &tx,
+            ),
+            relation_tuple: self.relation_tuple.transaction(
+                audited(
+                    store.relation_tuple.into_inner(),
+                    "relation_tuple",
+                    &audit,
+                    trail,
+                    None,
+                ),
+                &tx,
+            ),
+            ..store
+        };
+        Ok((store, tx))
+    }
+
+    /// purge hard deletes the entities of every store soft deleted before,
+    /// returning how many there were
+    pub async fn purge(&self, before: NaiveDateTime) -> cim_slo::Result<u64> {
+        Ok(self.user.purge(before).await?
+            + self.role.purge(before).await?
+            + self.role_binding.purge(before).await?
+            + self.group.purge(before).await?
+            + self.group_user.purge(before).await?
+            + self.policy.purge(before).await?
+            + self.policy_binding.purge(before).await?
+            + self.relation_tuple.purge(before).await?
+            + self.client.purge(before).await?
+            + self.connector.purge(before).await?
+            + self.key.purge(before).await?
+            + self.refresh.purge(before).await?
+            + self.offline_session.purge(before).await?)
+    }
+
+    /// tailer feeds the watchers of every store from the changelog, a gap
+    /// in the changelog is waited for as long as grace
+    pub fn tailer(&self, grace: Duration) -> Tailer {
+        let mut tailer = Tailer::new(self.changelog.clone(), grace);
+        tailer.feed(&self.user);
+        tailer.feed(&self.role);
+        tailer.feed(&self.role_binding);
+        tailer.feed(&self.group);
+        tailer.feed(&self.group_user);
+        tailer.feed(&self.policy);
+        tailer.feed(&self.policy_binding);
+        tailer.feed(&self.relation_tuple);
+        tailer
+    }
+}
+
+fn shared<I>(store: I) -> DynInterface<I::T, I::L>
+where
+    I: Interface + Send + 'static,
+    I::L: 'static,
+{
+    Arc::new(store)
+}
+
+/// audited wraps store in Audited when there is a trail to record
+fn audited<I>(
+    store: I,
+    kind: &'static str,
+    audit: &Arc<dyn AuditStore>,
+    trail: Option<&Trail>,
+    redact: Option<fn(&mut I::T)>,
+) -> DynInterface<I::T, I::L>
+where
+    I: Interface + Send + 'static,
+    I::L: 'static,
+{
+    let Some(trail) = trail else {
+        return shared(store);
+    };
+    let store = Audited::new(store, kind, audit.clone(), trail.clone());
+    match redact {
+        Some(redact) => shared(store.redact(redact)),
+        None => shared(store),
+    }
+}
+
+/// redact_user clears the credentials of user
+fn redact_user(user: &mut User) {
+    user.secret = None;
+    user.password = None;
+}
+
+fn watch<I>(
+    store: I,
+    kind: &'static str,
+    changelog: &Arc<dyn ChangeStore>,
+) -> WatchStore<DynInterface<I::T, I::L>>
+where
+    I: Interface + Send + 'static,
+    I::L: 'static,
+{
+    WatchStore::new(shared(store)).changelog(kind, changelog.clone())
 }
 
 #[derive(Clone)]
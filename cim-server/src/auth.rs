@@ -8,22 +8,39 @@ use axum_extra::{
 use http::{request::Parts, Method};
 use validator::Validate;
 
-use cim_pim::{Matcher, Pim, Statement};
+use cim_pim::{AttributeProvider, PrincipalType, Statement};
 use cim_slo::{
     errors::{self, Code, WithBacktrace},
     Result,
 };
-use cim_storage::{policy::StatementStore, user::User, Interface};
+use cim_storage::{
+    audit::Trail, policy::StatementStore, user::User, Interface,
+};
 
 use crate::{
-    services::oidc::token::Token,
-    valid::{ClientIp, Host},
+    app::Store,
+    services::{authorization::attribute, oidc::token::Token},
+    valid::{ClientIp, Host, TraceId},
     AppState,
 };
 
+/// KINDS names the resource kind of the routes whose kind isn't the route
+/// without its trailing `s`
+const KINDS: &[(&str, &str)] = &[("policies", "policy")];
+
+/// kind is the resource kind of the route, `crn:iam:<kind>:<id>`
+fn kind(route: &str) -> &str {
+    match KINDS.iter().find(|(v, _)| *v == route) {
+        Some((_, kind)) => kind,
+        None => route.trim_end_matches('s'),
+    }
+}
+
 #[derive(Validate, Debug)]
 pub struct Info {
     pub user: User,
+    /// trail records the changes made by the request
+    pub trail: Trail,
     statements: Vec<Statement>,
     req: cim_pim::Request,
 }
@@ -57,6 +74,13 @@ where
 
         let host = Host::from_request_parts(parts, state).await?;
         let client_ip = ClientIp::from_request_parts(parts, state).await?;
+        let TraceId(trace_id) =
+            TraceId::from_request_parts(parts, state).await?;
+        let trail = Trail {
+            actor: user.id.clone(),
+            trace_id,
+            source_ip: client_ip.ip.to_string(),
+        };
         let action = match parts.method {
             Method::POST => "create",
             Method::GET => "get",
@@ -69,7 +93,7 @@ where
             parts.uri.path().trim_start_matches("/v").split('/').skip(1);
         let mut resource = String::from("crn:iam:");
         if let Some(v) = path.next() {
-            resource.push_str(v.trim_end_matches('s'));
+            resource.push_str(kind(v));
         };
         if let Some(v) = path.next() {
             resource.push(':');
@@ -80,6 +104,7 @@ where
             resource,
             action: action.to_owned(),
             subject,
+            principal_type: PrincipalType::User,
             context: HashMap::from([
                 (
                     "client_ip".to_owned(),
@@ -97,6 +122,7 @@ where
 
         let result = Self {
             user,
+            trail,
             statements,
             req,
         };
@@ -106,19 +132,35 @@ where
 }
 
 impl Info {
-    pub fn is_allow<M: Matcher>(
-        &mut self,
-        matcher: &Pim<M>,
-        hash_map: HashMap<String, String>,
+    /// is_allow checks the request, the attributes referenced by conditions
+    /// like the account owning the resource are resolved from the store
+    pub async fn is_allow(&self, app: &AppState) -> Result<()> {
+        let user = Some(self.user.clone());
+        self.check(app, attribute::providers(app, user)).await
+    }
+
+    /// is_allow_in is is_allow for a check made in a transaction, the
+    /// attributes are read from its store which sees what it wrote so far,
+    /// a read from the pool could wait on the transaction
+    pub async fn is_allow_in(
+        &self,
+        app: &AppState,
+        store: &Store,
     ) -> Result<()> {
-        for (k, v) in hash_map.iter() {
-            self.req.context.insert(
-                k.to_string(),
-                serde_json::value::to_raw_value(v).unwrap(),
-            );
-        }
-        matcher
-            .is_allow(&self.statements, &self.req)
+        let rebac = app.rebac.with_reader(store.tuple_reader.clone());
+        let user = Some(self.user.clone());
+        let providers = attribute::providers_in(store, &rebac, user);
+        self.check(app, providers).await
+    }
+
+    async fn check(
+        &self,
+        app: &AppState,
+        providers: Vec<Box<dyn AttributeProvider + '_>>,
+    ) -> Result<()> {
+        app.matcher
+            .is_allow_with(&self.statements, &self.req, &providers)
+            .await
             .map_err(|err| errors::forbidden(err.to_string().as_str()))
     }
 }
@@ -126,6 +168,7 @@ impl Info {
 #[derive(Debug)]
 pub struct Auth {
     pub user: User,
+    pub trail: Trail,
 }
 
 impl<S> FromRequestParts<S> for Auth
@@ -139,13 +182,22 @@ where
         state: &S,
     ) -> Result<Self, Self::Rejection> {
         let info = Info::from_request_parts(parts, state).await?;
-        if let Err(err) = AppState::from_ref(state)
+        let app = AppState::from_ref(state);
+        if let Err(err) = app
             .matcher
-            .is_allow(&info.statements, &info.req)
+            .is_allow_with(
+                &info.statements,
+                &info.req,
+                &attribute::providers(&app, Some(info.user.clone())),
+            )
+            .await
         {
             return Err(errors::forbidden(err.to_string().as_str()));
         }
 
-        Ok(Self { user: info.user })
+        Ok(Self {
+            user: info.user,
+            trail: info.trail,
+        })
     }
 }
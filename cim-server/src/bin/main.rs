@@ -1,11 +1,13 @@
-use std::{env, net::SocketAddr, sync::Arc};
+use std::{env, net::SocketAddr, sync::Arc, time::Duration};
 
 use anyhow::{Context, Result};
+use chrono::Utc;
 use clap::Parser;
 use tokio::net::TcpListener;
 use tracing::{debug, error, info};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+use cim_job::{LocalScheduler, Scheduler};
 use cim_storage::connection_manager;
 
 use cim_server::{
@@ -33,7 +35,7 @@ async fn main() -> Result<()> {
 }
 
 async fn run_server(config: AppConfig) -> Result<()> {
-    info!("environment loaded and configuration parsed, initializing Mariadb connection and running migrations...");
+    info!("environment loaded and configuration parsed, initializing database connection and running migrations...");
     let store = connection_manager(
         &config.database_url,
         config.max_size,
@@ -48,6 +50,11 @@ async fn run_server(config: AppConfig) -> Result<()> {
     let app = Arc::new(App::new(store, config.clone())?);
 
     key_rotate(app.clone());
+    tail_changelog(app.clone());
+    let scheduler = LocalScheduler::default();
+    app.schedule(&scheduler)
+        .context("could not schedule the background jobs")?;
+    scheduler.start().context("could not start the scheduler")?;
 
     let router = AppRouter::build(AppState(app))
         .context("could not initialize application routes")?;
@@ -64,12 +71,16 @@ async fn run_server(config: AppConfig) -> Result<()> {
     .with_graceful_shutdown(shutdown_signal())
     .await
     .context("error while starting API server")?;
+    scheduler.stop();
 
     Ok(())
 }
 
 fn key_rotate(app: Arc<App>) {
     tokio::spawn(async move {
+        if let Err(err) = app.key_rotator.reseal().await {
+            error!("{}", err);
+        }
         info!("start first rotate...");
         if let Err(err) = app.key_rotator.rotate().await {
             error!("{}", err);
@@ -94,3 +105,35 @@ fn key_rotate(app: Arc<App>) {
         info!("finish rotate...");
     });
 }
+
+fn tail_changelog(app: Arc<App>) {
+    let mut tailer = app.store.tailer(Duration::from_secs(10));
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_millis(
+            app.config.watch_interval.max(1),
+        ));
+        let mut purge = tokio::time::interval(Duration::from_secs(60 * 60));
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    if let Err(err) = tailer.poll().await {
+                        error!("{}", err);
+                    }
+                },
+                _ = purge.tick() => {
+                    let before = Utc::now().naive_utc()
+                        - chrono::Duration::seconds(
+                            app.config.changelog_retention,
+                        );
+                    match app.store.changelog.purge(before).await {
+                        Ok(purged) => info!("purged {} changes", purged),
+                        Err(err) => error!("{}", err),
+                    }
+                },
+                _ = shutdown_signal() => {
+                    break;
+                }
+            }
+        }
+    });
+}
@@ -1,9 +1,12 @@
-use std::{fs, ops::RangeInclusive};
+use std::{convert::Infallible, fmt, fs, ops::RangeInclusive, str::FromStr};
 
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use serde::Deserialize;
 
+use cim_pim::rebac::Namespace;
+use cim_storage::key::KeyRing;
+
 #[derive(Parser, Debug, Clone, Deserialize)]
 #[command(name = "server")]
 #[command(author, version, about, long_about = None)]
@@ -64,6 +67,116 @@ pub struct AppConfig {
     #[arg(default_value_t = true)]
     #[serde(default = "default_rotate")]
     pub rotate_refresh_tokens: bool,
+    /// milliseconds between two polls of the changelog feeding watchers
+    #[clap(long, env)]
+    #[arg(default_value_t = 1000)]
+    #[serde(default = "default_watch_interval")]
+    pub watch_interval: u64,
+    /// events a watch buffers for a client reading them slower than they
+    /// come
+    #[clap(long, env)]
+    #[arg(default_value_t = 256)]
+    #[serde(default = "default_watch_buffer")]
+    pub watch_buffer: usize,
+    /// what becomes of the events of a watch whose buffer is full
+    #[clap(long, env)]
+    #[arg(value_enum, default_value_t = WatchOverflow::Disconnect)]
+    #[serde(default)]
+    pub watch_overflow: WatchOverflow,
+    /// seconds the changelog keeps a change before it's purged
+    #[clap(long, env)]
+    #[arg(default_value_t = 60*60*24)]
+    #[serde(default = "default_changelog_retention")]
+    pub changelog_retention: i64,
+    /// seconds a soft deleted entity can be restored before it's purged
+    #[clap(long, env)]
+    #[arg(default_value_t = 60*60*24*30)]
+    #[serde(default = "default_deleted_retention")]
+    pub deleted_retention: i64,
+    /// seconds between two collections of the expired auth requests, auth
+    /// codes and refresh tokens
+    #[clap(long, env)]
+    #[arg(default_value_t = 60*10)]
+    #[serde(default = "default_gc_interval")]
+    pub gc_interval: u64,
+    /// seconds between two sweeps of the bindings past their expiry
+    #[clap(long, env)]
+    #[arg(default_value_t = 60)]
+    #[serde(default = "default_expiry_interval")]
+    pub expiry_interval: u64,
+    /// entries each of the user, policy and key stores caches, 0 turns
+    /// the caches off
+    #[clap(long, env)]
+    #[arg(default_value_t = 1024)]
+    #[serde(default = "default_store_cache_size")]
+    pub store_cache_size: usize,
+    /// seconds a cached entry is served before it's read again, it bounds
+    /// how stale the keys rotated by another replica can be
+    #[clap(long, env)]
+    #[arg(default_value_t = 60)]
+    #[serde(default = "default_store_cache_ttl")]
+    pub store_cache_ttl: u64,
+    /// key encryption keys sealing the signing keys at rest, `id:key` pairs
+    /// separated by commas where the key is 32 bytes in base64. The first
+    /// one seals, the others only open the keys they sealed until the keys
+    /// are sealed again at start
+    #[clap(long, env)]
+    #[serde(default)]
+    pub key_encryption_keys: Option<Secret>,
+    /// file holding the key encryption keys a pair a line, it's read when
+    /// key_encryption_keys isn't set
+    #[clap(long, env)]
+    #[serde(default)]
+    pub key_encryption_keys_file: Option<String>,
+    /// toml file describing the relation namespaces of rebac
+    #[clap(long, env)]
+    #[serde(default)]
+    pub relation_config: Option<String>,
+    /// account whose users operate the server, only they may purge what
+    /// every account deleted, nobody may when it's none
+    #[clap(long, env)]
+    #[serde(default)]
+    pub operator_account: Option<String>,
+}
+
+/// WatchOverflow is what a watch does with the events a slow client has no
+/// room for
+#[derive(Clone, Copy, Debug, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum WatchOverflow {
+    /// Drop discards them
+    Drop,
+    /// Disconnect ends the watch, the client resumes from its last event
+    #[default]
+    Disconnect,
+    /// Coalesce folds the older events of an entity into its newer ones,
+    /// disconnecting when that isn't enough
+    Coalesce,
+}
+
+/// Secret is a config value which is never printed
+#[derive(Clone, Deserialize)]
+#[serde(transparent)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+impl FromStr for Secret {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self(s.to_owned()))
+    }
 }
 
 fn default_rust_log() -> String {
@@ -106,6 +219,38 @@ fn default_rotate() -> bool {
     true
 }
 
+fn default_watch_interval() -> u64 {
+    1000
+}
+
+fn default_watch_buffer() -> usize {
+    256
+}
+
+fn default_changelog_retention() -> i64 {
+    60 * 60 * 24
+}
+
+fn default_deleted_retention() -> i64 {
+    60 * 60 * 24 * 30
+}
+
+fn default_gc_interval() -> u64 {
+    60 * 10
+}
+
+fn default_expiry_interval() -> u64 {
+    60
+}
+
+fn default_store_cache_size() -> usize {
+    1024
+}
+
+fn default_store_cache_ttl() -> u64 {
+    60
+}
+
 const PORT_RANGE: RangeInclusive<usize> = 1..=65535;
 
 fn port_in_range(s: &str) -> Result<u16, String> {
@@ -128,3 +273,33 @@ pub fn load(cfg: &str) -> Result<AppConfig> {
         fs::read_to_string(cfg).context("could not read config file")?;
     toml::from_str(&content).context("could not parse config file")
 }
+
+#[derive(Debug, Default, Deserialize)]
+struct RelationConfig {
+    #[serde(default)]
+    namespace: Vec<Namespace>,
+}
+
+/// load_key_ring reads the key encryption keys from the config or the file
+/// it names, without any the signing keys are kept in plain
+pub fn load_key_ring(config: &AppConfig) -> Result<KeyRing> {
+    let spec = match (
+        &config.key_encryption_keys,
+        &config.key_encryption_keys_file,
+    ) {
+        (Some(keys), _) => keys.expose().to_owned(),
+        (None, Some(file)) => fs::read_to_string(file)
+            .context("could not read key encryption keys file")?,
+        (None, None) => String::new(),
+    };
+    KeyRing::parse(&spec).context("could not parse key encryption keys")
+}
+
+/// load_namespaces reads the `[[namespace]]` tables of the relation config
+pub fn load_namespaces(cfg: &str) -> Result<Vec<Namespace>> {
+    let content = fs::read_to_string(cfg)
+        .context("could not read relation config file")?;
+    let config: RelationConfig = toml::from_str(&content)
+        .context("could not parse relation config file")?;
+    Ok(config.namespace)
+}
@@ -0,0 +1,25 @@
+use axum::{routing::get, Json, Router};
+
+use cim_slo::Result;
+use cim_storage::{
+    audit::{Audit, ListParams},
+    List,
+};
+
+use crate::{auth::Auth, valid::Valid, AppState};
+
+pub fn new_router(state: AppState) -> Router {
+    Router::new()
+        .route("/audits", get(list_audit))
+        .with_state(state)
+}
+
+async fn list_audit(
+    _auth: Auth,
+    app: AppState,
+    Valid(opts): Valid<ListParams>,
+) -> Result<Json<List<Audit>>> {
+    let mut list = List::default();
+    app.store.audit.list(&opts, &mut list).await?;
+    Ok(list.into())
+}
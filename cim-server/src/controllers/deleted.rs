@@ -0,0 +1,52 @@
+use axum::{routing::delete, Json, Router};
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use cim_slo::{errors, Result};
+
+use crate::{auth::Auth, valid::Valid, AppState};
+
+pub fn new_router(state: AppState) -> Router {
+    Router::new()
+        .route("/deleted", delete(purge_deleted))
+        .with_state(state)
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct PurgeParams {
+    /// before purges the entities deleted before it, it's brought back to
+    /// the retention when later or none
+    before: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize)]
+struct Purged {
+    purged: u64,
+}
+
+/// purge_deleted removes for good what every account deleted, it's left
+/// to the operator account. Nothing deleted within the retention is purged
+/// so that it can still be restored
+async fn purge_deleted(
+    auth: Auth,
+    app: AppState,
+    Valid(params): Valid<PurgeParams>,
+) -> Result<Json<Purged>> {
+    if app.config.operator_account.as_ref() != Some(&auth.user.account_id) {
+        return Err(errors::forbidden(&format!(
+            "user {} may not purge the deleted entities",
+            auth.user.id
+        )));
+    }
+    let retained = Utc::now().naive_utc()
+        - chrono::Duration::seconds(app.config.deleted_retention);
+    let before = params
+        .before
+        .filter(|before| before < &retained)
+        .unwrap_or(retained);
+    let (store, tx) = app.store.audited(&auth.trail).await?;
+    let purged = store.purge(before).await?;
+    tx.commit().await?;
+    Ok(Purged { purged }.into())
+}
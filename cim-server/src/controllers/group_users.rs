@@ -1,15 +1,20 @@
-use axum::{extract::Path, response::Response, routing::get, Json, Router};
+use axum::{
+    extract::Path,
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
 use http::StatusCode;
 
 use cim_slo::{errors, next_id, Result};
 use cim_storage::{
     group_user::{Content, GroupUser, ListParams},
-    Interface, WatchInterface, ID,
+    Interface, List, WatchInterface, ID,
 };
 
 use crate::{
-    auth::Auth,
-    valid::{ListWatch, Valid},
+    auth::{Auth, Info},
+    valid::{ETag, IfMatch, ListWatch, Valid},
     AppState,
 };
 
@@ -24,24 +29,30 @@ pub fn new_router(state: AppState) -> Router {
                 .delete(delete_group_user)
                 .put(put_group_user),
         )
+        .route("/group_users/deleted", get(list_deleted_group_user))
+        .route("/group_users/{id}/restore", post(restore_group_user))
         .with_state(state)
 }
 
 async fn create_group_user(
-    _auth: Auth,
+    auth: Auth,
     app: AppState,
     Valid(Json(input)): Valid<Json<Content>>,
 ) -> Result<(StatusCode, Json<ID>)> {
     let id = next_id().map_err(errors::any)?;
-    app.store
+    let (store, tx) = app.store.audited(&auth.trail).await?;
+    store
         .group_user
         .create(&GroupUser {
             id: id.to_string(),
             group_id: input.group_id,
             user_id: input.user_id,
+            not_before: input.not_before,
+            expires_at: input.expires_at,
             ..Default::default()
         })
         .await?;
+    tx.commit().await?;
     Ok((StatusCode::CREATED, ID { id: id.to_string() }.into()))
 }
 
@@ -50,14 +61,19 @@ async fn list_group_user(
     app: AppState,
     list_params: ListWatch<ListParams>,
 ) -> Result<Response> {
-    list_watch(app.store.group_user.clone(), list_params, |value, opts| {
-        if let Some(ref v) = opts.id {
-            if value.id.ne(v) {
-                return true;
+    list_watch(
+        &app.config,
+        app.store.group_user.clone(),
+        list_params,
+        |value, opts| {
+            if let Some(ref v) = opts.id {
+                if value.id.ne(v) {
+                    return true;
+                }
             }
-        }
-        false
-    })
+            false
+        },
+    )
     .await
 }
 
@@ -65,42 +81,89 @@ async fn get_group_user(
     _auth: Auth,
     app: AppState,
     Path(id): Path<String>,
-) -> Result<Json<GroupUser>> {
+) -> Result<(ETag, Json<GroupUser>)> {
     let mut result = GroupUser {
         id: id.clone(),
         ..Default::default()
     };
     app.store.group_user.get(&mut result).await?;
-    Ok(result.into())
+    Ok((ETag(result.resource_version), result.into()))
 }
 
 async fn delete_group_user(
-    _auth: Auth,
+    auth: Auth,
     app: AppState,
     Path(id): Path<String>,
+    if_match: IfMatch,
 ) -> Result<StatusCode> {
-    let result = GroupUser {
+    let mut result = GroupUser {
         id: id.clone(),
         ..Default::default()
     };
-    app.store.group_user.delete(&result).await?;
+    app.store.group_user.get(&mut result).await?;
+    if_match.check(result.resource_version)?;
+    let (store, tx) = app.store.audited(&auth.trail).await?;
+    store
+        .group_user
+        .delete_if(&result, result.resource_version)
+        .await?;
+    tx.commit().await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
 async fn put_group_user(
-    _auth: Auth,
+    auth: Auth,
     app: AppState,
     Path(id): Path<String>,
+    if_match: IfMatch,
     Valid(Json(content)): Valid<Json<Content>>,
-) -> Result<StatusCode> {
+) -> Result<(ETag, StatusCode)> {
     let mut result = GroupUser {
         id: id.clone(),
         ..Default::default()
     };
     app.store.group_user.get(&mut result).await?;
+    if_match.check(result.resource_version)?;
 
     result.user_id = content.user_id;
     result.group_id = content.group_id;
-    app.store.group_user.put(&result).await?;
-    Ok(StatusCode::NO_CONTENT)
+    result.not_before = content.not_before;
+    result.expires_at = content.expires_at;
+    let (store, tx) = app.store.audited(&auth.trail).await?;
+    store
+        .group_user
+        .put_if(&result, result.resource_version)
+        .await?;
+    tx.commit().await?;
+    Ok((ETag(result.resource_version + 1), StatusCode::NO_CONTENT))
+}
+
+async fn list_deleted_group_user(
+    auth: Auth,
+    app: AppState,
+    Valid(mut opts): Valid<ListParams>,
+) -> Result<Json<List<GroupUser>>> {
+    opts.account_id = Some(auth.user.account_id);
+    let mut list = List::default();
+    app.store.group_user.list_deleted(&opts, &mut list).await?;
+    Ok(list.into())
+}
+
+/// restore_group_user undeletes the group user, the transaction is rolled
+/// back unless the restored one is allowed
+async fn restore_group_user(
+    info: Info,
+    app: AppState,
+    Path(id): Path<String>,
+) -> Result<(ETag, StatusCode)> {
+    let mut result = GroupUser {
+        id: id.clone(),
+        ..Default::default()
+    };
+    let (store, tx) = app.store.audited(&info.trail).await?;
+    store.group_user.restore(&result).await?;
+    store.group_user.get(&mut result).await?;
+    info.is_allow_in(&app, &store).await?;
+    tx.commit().await?;
+    Ok((ETag(result.resource_version), StatusCode::NO_CONTENT))
 }
@@ -1,17 +1,21 @@
-use std::collections::HashMap;
-
-use axum::{extract::Path, response::Response, routing::get, Json, Router};
+use axum::{
+    extract::Path,
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
 use http::StatusCode;
 
 use cim_slo::{errors, next_id, Result};
 use cim_storage::{
     group::{Content, Group, ListParams},
-    Interface, WatchInterface, ID,
+    user::{self, User},
+    Interface, List, Pagination, WatchInterface, ID,
 };
 
 use crate::{
     auth::{Auth, Info},
-    valid::{ListWatch, Valid},
+    valid::{ETag, IfMatch, ListWatch, Valid},
     AppState,
 };
 
@@ -24,25 +28,59 @@ pub fn new_router(state: AppState) -> Router {
             "/groups/{id}",
             get(get_group).delete(delete_group).put(put_group),
         )
+        .route("/groups/deleted", get(list_deleted_group))
+        .route("/groups/{id}/restore", post(restore_group))
+        .route("/groups/{id}/members", get(list_group_member))
         .with_state(state)
 }
 
+/// check_parent checks the group a group is nested in is one of the account
+async fn check_parent(
+    app: &AppState,
+    account_id: &str,
+    parent_id: &Option<String>,
+) -> Result<()> {
+    let Some(parent_id) = parent_id else {
+        return Ok(());
+    };
+    let mut parent = Group {
+        id: parent_id.clone(),
+        ..Default::default()
+    };
+    match app.store.group.get(&mut parent).await {
+        Ok(()) if parent.account_id == account_id => Ok(()),
+        Ok(()) => Err(errors::forbidden(&format!(
+            "group {parent_id} is of another account"
+        ))),
+        Err(err) if err.eq(&errors::not_found("")) => Err(errors::bad_request(
+            &format!("no group {parent_id} to nest in"),
+        )),
+        Err(err) => Err(err),
+    }
+}
+
 async fn create_group(
     auth: Auth,
     app: AppState,
     Valid(Json(input)): Valid<Json<Content>>,
 ) -> Result<(StatusCode, Json<ID>)> {
+    check_parent(&app, &auth.user.account_id, &input.parent_id).await?;
     let id = next_id().map_err(errors::any)?;
-    app.store
+    let (store, tx) = app.store.audited(&auth.trail).await?;
+    store
         .group
         .create(&Group {
             id: id.to_string(),
             account_id: auth.user.account_id,
             name: input.name,
             desc: input.desc,
+            parent_id: input.parent_id,
+            labels: input.labels,
+            annotations: input.annotations,
             ..Default::default()
         })
         .await?;
+    tx.commit().await?;
     Ok((StatusCode::CREATED, ID { id: id.to_string() }.into()))
 }
 
@@ -51,75 +89,158 @@ async fn list_group(
     app: AppState,
     list_params: ListWatch<ListParams>,
 ) -> Result<Response> {
-    list_watch(app.store.group.clone(), list_params, |value, opts| {
-        if let Some(ref v) = opts.id {
-            if value.id.ne(v) {
-                return true;
+    list_watch(
+        &app.config,
+        app.store.group.clone(),
+        list_params,
+        |value, opts| {
+            if let Some(ref v) = opts.id {
+                if value.id.ne(v) {
+                    return true;
+                }
+            }
+            if let Some(ref v) = opts.account_id {
+                if value.account_id.ne(v) {
+                    return true;
+                }
             }
-        }
-        if let Some(ref v) = opts.account_id {
-            if value.account_id.ne(v) {
-                return true;
+            if let Some(ref v) = opts.parent_id {
+                if value.parent_id.as_ref() != Some(v) {
+                    return true;
+                }
             }
-        }
-        false
-    })
+            if let Some(ref v) = opts.label_selector {
+                if !v.matches(&value.labels) {
+                    return true;
+                }
+            }
+            false
+        },
+    )
     .await
 }
 
 async fn get_group(
-    mut info: Info,
+    info: Info,
     app: AppState,
     Path(id): Path<String>,
-) -> Result<Json<Group>> {
+) -> Result<(ETag, Json<Group>)> {
     let mut result = Group {
         id: id.clone(),
         ..Default::default()
     };
     app.store.group.get(&mut result).await?;
-    info.is_allow(
-        &app.matcher,
-        HashMap::from([("account_id".to_owned(), result.account_id.clone())]),
-    )?;
-    Ok(result.into())
+    info.is_allow(&app).await?;
+    Ok((ETag(result.resource_version), result.into()))
 }
 
 async fn delete_group(
-    mut info: Info,
+    info: Info,
     app: AppState,
     Path(id): Path<String>,
+    if_match: IfMatch,
 ) -> Result<StatusCode> {
     let mut result = Group {
         id: id.clone(),
         ..Default::default()
     };
     app.store.group.get(&mut result).await?;
-    info.is_allow(
-        &app.matcher,
-        HashMap::from([("account_id".to_owned(), result.account_id.clone())]),
-    )?;
-    app.store.group.delete(&result).await?;
+    info.is_allow(&app).await?;
+    if_match.check(result.resource_version)?;
+    let (store, tx) = app.store.audited(&info.trail).await?;
+    store
+        .group
+        .delete_if(&result, result.resource_version)
+        .await?;
+    tx.commit().await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
 async fn put_group(
-    mut info: Info,
+    info: Info,
     app: AppState,
     Path(id): Path<String>,
+    if_match: IfMatch,
     Valid(Json(content)): Valid<Json<Content>>,
-) -> Result<StatusCode> {
+) -> Result<(ETag, StatusCode)> {
     let mut result = Group {
         id: id.clone(),
         ..Default::default()
     };
     app.store.group.get(&mut result).await?;
-    info.is_allow(
-        &app.matcher,
-        HashMap::from([("account_id".to_owned(), result.account_id.clone())]),
-    )?;
+    info.is_allow(&app).await?;
+    if_match.check(result.resource_version)?;
+    check_parent(&app, &result.account_id, &content.parent_id).await?;
 
     result.name = content.name;
     result.desc = content.desc;
-    app.store.group.put(&result).await?;
-    Ok(StatusCode::NO_CONTENT)
+    result.parent_id = content.parent_id;
+    result.labels = content.labels;
+    result.annotations = content.annotations;
+    let (store, tx) = app.store.audited(&info.trail).await?;
+    store.group.put_if(&result, result.resource_version).await?;
+    tx.commit().await?;
+    Ok((ETag(result.resource_version + 1), StatusCode::NO_CONTENT))
+}
+
+async fn list_deleted_group(
+    auth: Auth,
+    app: AppState,
+    Valid(mut opts): Valid<ListParams>,
+) -> Result<Json<List<Group>>> {
+    opts.account_id = Some(auth.user.account_id);
+    let mut list = List::default();
+    app.store.group.list_deleted(&opts, &mut list).await?;
+    Ok(list.into())
+}
+
+/// restore_group undeletes the group, the transaction is rolled back
+/// unless the restored one is allowed
+async fn restore_group(
+    info: Info,
+    app: AppState,
+    Path(id): Path<String>,
+) -> Result<(ETag, StatusCode)> {
+    let mut result = Group {
+        id: id.clone(),
+        ..Default::default()
+    };
+    let (store, tx) = app.store.audited(&info.trail).await?;
+    store.group.restore(&result).await?;
+    store.group.get(&mut result).await?;
+    info.is_allow_in(&app, &store).await?;
+    tx.commit().await?;
+    Ok((ETag(result.resource_version), StatusCode::NO_CONTENT))
+}
+
+/// list_group_member lists the effective members of the group, the members
+/// of the groups nested in it at any depth too
+async fn list_group_member(
+    info: Info,
+    app: AppState,
+    Path(id): Path<String>,
+    Valid(pagination): Valid<Pagination>,
+) -> Result<Json<List<User>>> {
+    let mut group = Group {
+        id: id.clone(),
+        ..Default::default()
+    };
+    app.store.group.get(&mut group).await?;
+    info.is_allow(&app).await?;
+    let mut list = List::default();
+    app.store
+        .user
+        .list(
+            &user::ListParams {
+                id: None,
+                account_id: None,
+                group_id: None,
+                effective_group_id: Some(id),
+                label_selector: None,
+                pagination,
+            },
+            &mut list,
+        )
+        .await?;
+    Ok(list.into())
 }
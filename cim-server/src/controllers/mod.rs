@@ -1,10 +1,14 @@
+pub mod audits;
+pub mod deleted;
 pub mod group_users;
 pub mod groups;
 pub mod oidc;
 pub mod policies;
 pub mod policy_bindings;
+pub mod relation_tuples;
 pub mod role_bindings;
 pub mod roles;
+pub mod snapshots;
 pub mod users;
 
 use std::convert::Infallible;
@@ -16,18 +20,65 @@ use axum::{
     Json,
 };
 use cim_slo::Result;
-use cim_storage::{Event, List, WatchInterface};
+use cim_storage::{Event, Keyed, List, WatchInterface};
+use cim_watch::{Overflow, Subscription};
 use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
 
-use crate::{shutdown_signal, valid::ListWatch};
+use crate::{
+    config::WatchOverflow, shutdown_signal, valid::ListWatch, AppConfig,
+};
+
+/// Payload is an event as a watch sends it
+#[derive(Serialize)]
+struct Payload<'a, T> {
+    #[serde(flatten)]
+    event: &'a Event<T>,
+    /// patch is the JSON Patch of a put, when the watch asks for it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    patch: Option<json_patch::Patch>,
+}
+
+impl<'a, T: Serialize> Payload<'a, T> {
+    fn new(event: &'a Event<T>, patch: bool) -> Self {
+        Self {
+            event,
+            patch: patch.then(|| event.diff()).flatten(),
+        }
+    }
+}
+
+/// subscribe watches store after revision, buffering the events as config
+/// tells
+fn subscribe<W>(
+    store: &W,
+    revision: u64,
+    config: &AppConfig,
+) -> Result<Subscription<Event<W::T>>>
+where
+    W: WatchInterface,
+    W::T: Keyed,
+{
+    let overflow = match config.watch_overflow {
+        WatchOverflow::Drop => Overflow::Drop,
+        WatchOverflow::Disconnect => Overflow::Disconnect,
+        WatchOverflow::Coalesce => Overflow::Coalesce {
+            supersedes: Event::supersedes,
+            fold: Event::fold,
+        },
+    };
+    store.subscribe(revision, config.watch_buffer, overflow)
+}
 
 async fn list_watch<W, F>(
+    config: &AppConfig,
     store: W,
     list_params: ListWatch<W::L>,
     filter: F,
 ) -> Result<Response>
 where
     W: WatchInterface + Send + 'static + Sync,
+    W::T: Keyed,
     F: Fn(&W::T, &W::L) -> bool + Sync + Send + 'static,
 {
     match list_params {
@@ -36,29 +87,25 @@ where
             store.list(&opts, &mut list).await?;
             Ok(Json(list).into_response())
         }
-        ListWatch::Watch(opts) => {
-            let (tx, mut rx) =
-                tokio::sync::mpsc::unbounded_channel::<Event<W::T>>();
-            let remove = store.watch(0, move |event: Event<W::T>| {
-                let result = event.get();
-                if filter(result, &opts) {
-                    return;
-                }
-                tx.send(event).unwrap();
-            });
+        ListWatch::Watch((opts, since)) => {
+            let mut subscription = subscribe(&store, since.revision, config)?;
             let stream = stream! {
-                remove.noop();
                 loop{
                 tokio::select! {
                     _ = shutdown_signal() => {
                             return;
                     },
-                    result =rx.recv()=>{
-                        if let Some(item) = result {
-                            yield SseEvent::default().json_data(&item).unwrap();
-                        }else{
+                    result = subscription.next()=>{
+                        let Some(item) = result else {
                             return;
+                        };
+                        if filter(item.get(), &opts) {
+                            continue;
                         }
+                        yield SseEvent::default()
+                            .id(item.revision.to_string())
+                            .json_data(Payload::new(&item, since.patch))
+                            .unwrap();
                     }
                 }
                 }
@@ -68,20 +115,22 @@ where
             )
             .into_response())
         }
-        ListWatch::Ws((ws, opts)) => {
+        ListWatch::Ws((ws, opts, since)) => {
+            // subscribed before the upgrade, so that a revision too old is
+            // answered with an error
+            let mut subscription = subscribe(&store, since.revision, config)?;
             Ok(ws.on_upgrade(move |socket| async move {
-                let (wtx, wrx) = std::sync::mpsc::channel::<Event<W::T>>();
-                let _remove = store.watch(0, move |event: Event<W::T>| {
-                    let result = event.get();
-                    if filter(result, &opts) {
-                        return;
-                    }
-                    wtx.send(event).unwrap();
-                });
                 let (mut sender, mut receiver) = socket.split();
                 let mut send_task = tokio::spawn(async move {
-                    while let Ok(item) = wrx.recv() {
-                        let data = serde_json::to_vec(&item).unwrap();
+                    while let Some(item) = subscription.next().await {
+                        if filter(item.get(), &opts) {
+                            continue;
+                        }
+                        let data = serde_json::to_vec(&Payload::new(
+                            &item,
+                            since.patch,
+                        ))
+                        .unwrap();
                         if sender
                             .send(Message::Binary(data.into()))
                             .await
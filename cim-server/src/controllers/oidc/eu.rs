@@ -14,7 +14,7 @@ use tracing::info;
 
 use crate::{
     services::{
-        authorization,
+        authorization::{self, attribute},
         oidc::{
             auth, auth_page_callback, get_auth_request, get_connector,
             parse_auth_request, redirect_auth_page,
@@ -41,8 +41,13 @@ async fn authorize(
     Valid(Json(input)): Valid<Json<cim_pim::Request>>,
 ) -> Result<StatusCode> {
     info!("list query {:#?}", input);
-    authorization::authorize(&app.store.statement, &app.matcher, &input)
-        .await?;
+    authorization::authorize(
+        &app.store.statement,
+        &app.matcher,
+        &input,
+        &attribute::providers(&app, None),
+    )
+    .await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
@@ -1,17 +1,20 @@
-use std::collections::HashMap;
-
-use axum::{extract::Path, response::Response, routing::get, Json, Router};
+use axum::{
+    extract::Path,
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
 use http::StatusCode;
 
 use cim_slo::{errors, next_id, Result};
 use cim_storage::{
     policy::{Content, ListParams, Policy},
-    Interface, WatchInterface, ID,
+    Interface, List, WatchInterface, ID,
 };
 
 use crate::{
     auth::{Auth, Info},
-    valid::{ListWatch, Valid},
+    valid::{ETag, IfMatch, ListWatch, Valid},
     AppState,
 };
 
@@ -24,6 +27,8 @@ pub fn new_router(state: AppState) -> Router {
             "/policies/{id}",
             get(get_policy).delete(delete_policy).put(put_policy),
         )
+        .route("/policies/deleted", get(list_deleted_policy))
+        .route("/policies/{id}/restore", post(restore_policy))
         .with_state(state)
 }
 
@@ -33,17 +38,21 @@ async fn create_policy(
     Valid(Json(content)): Valid<Json<Content>>,
 ) -> Result<(StatusCode, Json<ID>)> {
     let id = next_id().map_err(errors::any)?;
-    app.store
+    let (store, tx) = app.store.audited(&auth.trail).await?;
+    store
         .policy
         .create(&Policy {
             id: id.to_string(),
             account_id: Some(auth.user.account_id),
             desc: content.desc,
+            labels: content.labels,
+            annotations: content.annotations,
             version: content.version,
             statement: content.statement,
             ..Default::default()
         })
         .await?;
+    tx.commit().await?;
     Ok((StatusCode::CREATED, ID { id: id.to_string() }.into()))
 }
 
@@ -52,78 +61,121 @@ async fn list_policy(
     app: AppState,
     list_params: ListWatch<ListParams>,
 ) -> Result<Response> {
-    list_watch(app.store.policy.clone(), list_params, |value, opts| {
-        if let Some(ref v) = opts.id {
-            if value.id.ne(v) {
-                return true;
+    list_watch(
+        &app.config,
+        app.store.policy.clone(),
+        list_params,
+        |value, opts| {
+            if let Some(ref v) = opts.id {
+                if value.id.ne(v) {
+                    return true;
+                }
+            }
+            if value.account_id != opts.account_id {
+                return false;
             }
-        }
-        if value.account_id != opts.account_id {
-            return false;
-        }
-        false
-    })
+            if let Some(ref v) = opts.label_selector {
+                if !v.matches(&value.labels) {
+                    return true;
+                }
+            }
+            false
+        },
+    )
     .await
 }
 
 async fn get_policy(
-    mut info: Info,
+    info: Info,
     app: AppState,
     Path(id): Path<String>,
-) -> Result<Json<Policy>> {
+) -> Result<(ETag, Json<Policy>)> {
     let mut result = Policy {
         id: id.clone(),
         ..Default::default()
     };
     app.store.policy.get(&mut result).await?;
-    let mut opts = HashMap::new();
-    if let Some(account_id) = &result.account_id {
-        opts.insert("account_id".to_owned(), account_id.clone());
-    }
-    info.is_allow(&app.matcher, opts)?;
-    Ok(result.into())
+    info.is_allow(&app).await?;
+    Ok((ETag(result.resource_version), result.into()))
 }
 
 async fn delete_policy(
-    mut info: Info,
+    info: Info,
     app: AppState,
     Path(id): Path<String>,
+    if_match: IfMatch,
 ) -> Result<StatusCode> {
     let mut result = Policy {
         id: id.clone(),
         ..Default::default()
     };
     app.store.policy.get(&mut result).await?;
-    let mut opts = HashMap::new();
-    if let Some(account_id) = &result.account_id {
-        opts.insert("account_id".to_owned(), account_id.clone());
-    }
-
-    info.is_allow(&app.matcher, opts)?;
-    app.store.policy.delete(&result).await?;
+    if_match.check(result.resource_version)?;
+    info.is_allow(&app).await?;
+    let (store, tx) = app.store.audited(&info.trail).await?;
+    store
+        .policy
+        .delete_if(&result, result.resource_version)
+        .await?;
+    tx.commit().await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
 async fn put_policy(
-    mut info: Info,
+    info: Info,
     app: AppState,
     Path(id): Path<String>,
+    if_match: IfMatch,
     Valid(Json(content)): Valid<Json<Content>>,
-) -> Result<StatusCode> {
+) -> Result<(ETag, StatusCode)> {
     let mut result = Policy {
         id: id.clone(),
         ..Default::default()
     };
     app.store.policy.get(&mut result).await?;
-    let mut opts = HashMap::new();
-    if let Some(account_id) = &result.account_id {
-        opts.insert("account_id".to_owned(), account_id.clone());
-    }
-    info.is_allow(&app.matcher, opts)?;
+    if_match.check(result.resource_version)?;
+    info.is_allow(&app).await?;
 
     result.desc = content.desc;
+    result.labels = content.labels;
+    result.annotations = content.annotations;
     result.version = content.version;
     result.statement = content.statement;
-    app.store.policy.put(&result).await?;
-    Ok(StatusCode::NO_CONTENT)
+    let (store, tx) = app.store.audited(&info.trail).await?;
+    store
+        .policy
+        .put_if(&result, result.resource_version)
+        .await?;
+    tx.commit().await?;
+    Ok((ETag(result.resource_version + 1), StatusCode::NO_CONTENT))
+}
+
+async fn list_deleted_policy(
+    auth: Auth,
+    app: AppState,
+    Valid(mut opts): Valid<ListParams>,
+) -> Result<Json<List<Policy>>> {
+    opts.account_id = Some(auth.user.account_id);
+    let mut list = List::default();
+    app.store.policy.list_deleted(&opts, &mut list).await?;
+    Ok(list.into())
+}
+
+/// restore_policy undeletes the policy, the transaction is rolled back
+/// unless the restored one is allowed
+async fn restore_policy(
+    info: Info,
+    app: AppState,
+    Path(id): Path<String>,
+) -> Result<(ETag, StatusCode)> {
+    let mut result = Policy {
+        id: id.clone(),
+        ..Default::default()
+    };
+    let (store, tx) = app.store.audited(&info.trail).await?;
+    store.policy.restore(&result).await?;
+    store.policy.get(&mut result).await?;
+    info.is_allow_in(&app, &store).await?;
+    tx.commit().await?;
+    Ok((ETag(result.resource_version), StatusCode::NO_CONTENT))
 }
@@ -1,15 +1,20 @@
-use axum::{extract::Path, response::Response, routing::get, Json, Router};
+use axum::{
+    extract::Path,
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
 use http::StatusCode;
 
 use cim_slo::{errors, next_id, Result};
 use cim_storage::{
     policy_binding::{Content, ListParams, PolicyBinding},
-    Interface, WatchInterface, ID,
+    Interface, List, WatchInterface, ID,
 };
 
 use crate::{
-    auth::Auth,
-    valid::{ListWatch, Valid},
+    auth::{Auth, Info},
+    valid::{ETag, IfMatch, ListWatch, Valid},
     AppState,
 };
 
@@ -27,25 +32,34 @@ pub fn new_router(state: AppState) -> Router {
                 .delete(delete_policy_binding)
                 .put(put_policy_binding),
         )
+        .route("/policy_bindings/deleted", get(list_deleted_policy_binding))
+        .route(
+            "/policy_bindings/{id}/restore",
+            post(restore_policy_binding),
+        )
         .with_state(state)
 }
 
 async fn create_policy_binding(
-    _auth: Auth,
+    auth: Auth,
     app: AppState,
     Valid(Json(input)): Valid<Json<Content>>,
 ) -> Result<(StatusCode, Json<ID>)> {
     let id = next_id().map_err(errors::any)?;
-    app.store
+    let (store, tx) = app.store.audited(&auth.trail).await?;
+    store
         .policy_binding
         .create(&PolicyBinding {
             id: id.to_string(),
             policy_id: input.policy_id,
             bindings_type: input.bindings_type,
             bindings_id: input.bindings_id,
+            not_before: input.not_before,
+            expires_at: input.expires_at,
             ..Default::default()
         })
         .await?;
+    tx.commit().await?;
     Ok((StatusCode::CREATED, ID { id: id.to_string() }.into()))
 }
 
@@ -55,6 +69,7 @@ async fn list_policy_binding(
     list_params: ListWatch<ListParams>,
 ) -> Result<Response> {
     list_watch(
+        &app.config,
         app.store.policy_binding.clone(),
         list_params,
         |value, opts| {
@@ -73,43 +88,93 @@ async fn get_policy_binding(
     _auth: Auth,
     app: AppState,
     Path(id): Path<String>,
-) -> Result<Json<PolicyBinding>> {
+) -> Result<(ETag, Json<PolicyBinding>)> {
     let mut result = PolicyBinding {
         id: id.clone(),
         ..Default::default()
     };
     app.store.policy_binding.get(&mut result).await?;
-    Ok(result.into())
+    Ok((ETag(result.resource_version), result.into()))
 }
 
 async fn delete_policy_binding(
-    _auth: Auth,
+    auth: Auth,
     app: AppState,
     Path(id): Path<String>,
+    if_match: IfMatch,
 ) -> Result<StatusCode> {
-    let result = PolicyBinding {
+    let mut result = PolicyBinding {
         id: id.clone(),
         ..Default::default()
     };
-    app.store.policy_binding.delete(&result).await?;
+    app.store.policy_binding.get(&mut result).await?;
+    if_match.check(result.resource_version)?;
+    let (store, tx) = app.store.audited(&auth.trail).await?;
+    store
+        .policy_binding
+        .delete_if(&result, result.resource_version)
+        .await?;
+    tx.commit().await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
 async fn put_policy_binding(
-    _auth: Auth,
+    auth: Auth,
     app: AppState,
     Path(id): Path<String>,
+    if_match: IfMatch,
     Valid(Json(content)): Valid<Json<Content>>,
-) -> Result<StatusCode> {
+) -> Result<(ETag, StatusCode)> {
     let mut result = PolicyBinding {
         id: id.clone(),
         ..Default::default()
     };
     app.store.policy_binding.get(&mut result).await?;
+    if_match.check(result.resource_version)?;
 
     result.policy_id = content.policy_id;
     result.bindings_type = content.bindings_type;
     result.bindings_id = content.bindings_id;
-    app.store.policy_binding.put(&result).await?;
-    Ok(StatusCode::NO_CONTENT)
+    result.not_before = content.not_before;
+    result.expires_at = content.expires_at;
+    let (store, tx) = app.store.audited(&auth.trail).await?;
+    store
+        .policy_binding
+        .put_if(&result, result.resource_version)
+        .await?;
+    tx.commit().await?;
+    Ok((ETag(result.resource_version + 1), StatusCode::NO_CONTENT))
+}
+
+async fn list_deleted_policy_binding(
+    auth: Auth,
+    app: AppState,
+    Valid(mut opts): Valid<ListParams>,
+) -> Result<Json<List<PolicyBinding>>> {
+    opts.account_id = Some(auth.user.account_id);
+    let mut list = List::default();
+    app.store
+        .policy_binding
+        .list_deleted(&opts, &mut list)
+        .await?;
+    Ok(list.into())
+}
+
+/// restore_policy_binding undeletes the policy binding, the transaction is rolled
+/// back unless the restored one is allowed
+async fn restore_policy_binding(
+    info: Info,
+    app: AppState,
+    Path(id): Path<String>,
+) -> Result<(ETag, StatusCode)> {
+    let mut result = PolicyBinding {
+        id: id.clone(),
+        ..Default::default()
+    };
+    let (store, tx) = app.store.audited(&info.trail).await?;
+    store.policy_binding.restore(&result).await?;
+    store.policy_binding.get(&mut result).await?;
+    info.is_allow_in(&app, &store).await?;
+    tx.commit().await?;
+    Ok((ETag(result.resource_version), StatusCode::NO_CONTENT))
 }
@@ -0,0 +1,335 @@
+use axum::{
+    extract::Path,
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use cim_pim::rebac::{Subject, Tree, Tuple};
+use cim_slo::{errors, next_id, Result};
+use cim_storage::{
+    relation_tuple::{Content, ListParams, RelationTuple},
+    Interface, List, Pagination, WatchInterface, ID,
+};
+
+use crate::{
+    auth::{Auth, Info},
+    services::authorization::attribute::StoreAttributes,
+    valid::{ETag, IfMatch, ListWatch, Valid},
+    AppState,
+};
+
+use super::list_watch;
+
+pub fn new_router(state: AppState) -> Router {
+    Router::new()
+        .route(
+            "/relation_tuples",
+            get(list_relation_tuple).post(create_relation_tuple),
+        )
+        .route(
+            "/relation_tuples/{id}",
+            get(get_relation_tuple)
+                .delete(delete_relation_tuple)
+                .put(put_relation_tuple),
+        )
+        .route("/relations/check", post(check_relation))
+        .route("/relations/expand", get(expand_relation))
+        .route("/relation_tuples/deleted", get(list_deleted_relation_tuple))
+        .route(
+            "/relation_tuples/{id}/restore",
+            post(restore_relation_tuple),
+        )
+        .with_state(state)
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct CheckParams {
+    #[validate(length(min = 3, max = 255))]
+    object: String,
+    #[validate(length(min = 1, max = 64))]
+    relation: String,
+    #[validate(length(min = 1, max = 255))]
+    subject: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Allowed {
+    allowed: bool,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct ExpandParams {
+    #[validate(length(min = 3, max = 255))]
+    object: String,
+    #[validate(length(min = 1, max = 64))]
+    relation: String,
+}
+
+/// check the content is a well-formed `object#relation@subject`
+fn parse_tuple(content: &Content) -> Result<Tuple> {
+    format!(
+        "{}#{}@{}",
+        content.object, content.relation, content.subject
+    )
+    .parse::<Tuple>()
+    .map_err(|err| errors::bad_request(&err))
+}
+
+/// object_account is the account the object `namespace:id` is of, the
+/// account of the entity `crn:iam:<namespace>:<id>` when there is one and
+/// else the account of its tuples, an object nobody wrote a tuple of yet is
+/// of none
+async fn object_account(
+    app: &AppState,
+    object: &str,
+) -> Result<Option<String>> {
+    let attributes = StoreAttributes::new(&app.store);
+    if let Some(account_id) =
+        attributes.account_id(&format!("crn:iam:{object}")).await?
+    {
+        return Ok(Some(account_id));
+    }
+    let mut list = List::default();
+    app.store
+        .relation_tuple
+        .list(
+            &ListParams {
+                id: None,
+                account_id: None,
+                object: Some(object.to_owned()),
+                relation: None,
+                subject: None,
+                pagination: Pagination {
+                    limit: 1,
+                    count_disable: true,
+                    ..Default::default()
+                },
+            },
+            &mut list,
+        )
+        .await?;
+    Ok(list.data.pop().map(|v| v.account_id))
+}
+
+/// check_object refuses the object of another account
+async fn check_object(
+    app: &AppState,
+    account_id: &str,
+    object: &str,
+) -> Result<()> {
+    match object_account(app, object).await? {
+        Some(v) if v != account_id => Err(errors::forbidden(&format!(
+            "object {object} is of another account"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// check_content checks the tuple names objects of the account only, its
+/// object and the object of its subject set
+async fn check_content(
+    app: &AppState,
+    account_id: &str,
+    content: &Content,
+) -> Result<()> {
+    let tuple = parse_tuple(content)?;
+    check_object(app, account_id, &tuple.object).await?;
+    if let Subject::Set { object, .. } = &tuple.subject {
+        check_object(app, account_id, object).await?;
+    }
+    Ok(())
+}
+
+async fn create_relation_tuple(
+    auth: Auth,
+    app: AppState,
+    Valid(Json(input)): Valid<Json<Content>>,
+) -> Result<(StatusCode, Json<ID>)> {
+    check_content(&app, &auth.user.account_id, &input).await?;
+    let id = next_id().map_err(errors::any)?;
+    let (store, tx) = app.store.audited(&auth.trail).await?;
+    store
+        .relation_tuple
+        .create(&RelationTuple {
+            id: id.to_string(),
+            account_id: auth.user.account_id,
+            object: input.object,
+            relation: input.relation,
+            subject: input.subject,
+            ..Default::default()
+        })
+        .await?;
+    tx.commit().await?;
+    Ok((StatusCode::CREATED, ID { id: id.to_string() }.into()))
+}
+
+async fn list_relation_tuple(
+    auth: Auth,
+    app: AppState,
+    mut list_params: ListWatch<ListParams>,
+) -> Result<Response> {
+    list_params.params_mut().account_id = Some(auth.user.account_id);
+    list_watch(
+        &app.config,
+        app.store.relation_tuple.clone(),
+        list_params,
+        |value, opts| {
+            if let Some(ref v) = opts.id {
+                if value.id.ne(v) {
+                    return true;
+                }
+            }
+            if let Some(ref v) = opts.account_id {
+                if value.account_id.ne(v) {
+                    return true;
+                }
+            }
+            if let Some(ref v) = opts.object {
+                if value.object.ne(v) {
+                    return true;
+                }
+            }
+            if let Some(ref v) = opts.relation {
+                if value.relation.ne(v) {
+                    return true;
+                }
+            }
+            if let Some(ref v) = opts.subject {
+                if value.subject.ne(v) {
+                    return true;
+                }
+            }
+            false
+        },
+    )
+    .await
+}
+
+async fn get_relation_tuple(
+    info: Info,
+    app: AppState,
+    Path(id): Path<String>,
+) -> Result<(ETag, Json<RelationTuple>)> {
+    let mut result = RelationTuple {
+        id: id.clone(),
+        ..Default::default()
+    };
+    app.store.relation_tuple.get(&mut result).await?;
+    info.is_allow(&app).await?;
+    Ok((ETag(result.resource_version), result.into()))
+}
+
+async fn delete_relation_tuple(
+    info: Info,
+    app: AppState,
+    Path(id): Path<String>,
+    if_match: IfMatch,
+) -> Result<StatusCode> {
+    let mut result = RelationTuple {
+        id: id.clone(),
+        ..Default::default()
+    };
+    app.store.relation_tuple.get(&mut result).await?;
+    info.is_allow(&app).await?;
+    if_match.check(result.resource_version)?;
+    let (store, tx) = app.store.audited(&info.trail).await?;
+    store
+        .relation_tuple
+        .delete_if(&result, result.resource_version)
+        .await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn put_relation_tuple(
+    info: Info,
+    app: AppState,
+    Path(id): Path<String>,
+    if_match: IfMatch,
+    Valid(Json(content)): Valid<Json<Content>>,
+) -> Result<(ETag, StatusCode)> {
+    let mut result = RelationTuple {
+        id: id.clone(),
+        ..Default::default()
+    };
+    app.store.relation_tuple.get(&mut result).await?;
+    info.is_allow(&app).await?;
+    if_match.check(result.resource_version)?;
+    check_content(&app, &result.account_id, &content).await?;
+
+    result.object = content.object;
+    result.relation = content.relation;
+    result.subject = content.subject;
+    let (store, tx) = app.store.audited(&info.trail).await?;
+    store
+        .relation_tuple
+        .put_if(&result, result.resource_version)
+        .await?;
+    tx.commit().await?;
+    Ok((ETag(result.resource_version + 1), StatusCode::NO_CONTENT))
+}
+
+async fn check_relation(
+    auth: Auth,
+    app: AppState,
+    Valid(Json(input)): Valid<Json<CheckParams>>,
+) -> Result<Json<Allowed>> {
+    check_object(&app, &auth.user.account_id, &input.object).await?;
+    let allowed = app
+        .rebac
+        .check(&input.object, &input.relation, &input.subject)
+        .await
+        .map_err(errors::anyhow)?;
+    Ok(Allowed { allowed }.into())
+}
+
+async fn expand_relation(
+    auth: Auth,
+    app: AppState,
+    Valid(input): Valid<ExpandParams>,
+) -> Result<Json<Tree>> {
+    check_object(&app, &auth.user.account_id, &input.object).await?;
+    let tree = app
+        .rebac
+        .expand(&input.object, &input.relation)
+        .await
+        .map_err(errors::anyhow)?;
+    Ok(tree.into())
+}
+
+async fn list_deleted_relation_tuple(
+    auth: Auth,
+    app: AppState,
+    Valid(mut opts): Valid<ListParams>,
+) -> Result<Json<List<RelationTuple>>> {
+    opts.account_id = Some(auth.user.account_id);
+    let mut list = List::default();
+    app.store
+        .relation_tuple
+        .list_deleted(&opts, &mut list)
+        .await?;
+    Ok(list.into())
+}
+
+/// restore_relation_tuple undeletes the relation tuple, the transaction is rolled
+/// back unless the restored one is allowed
+async fn restore_relation_tuple(
+    info: Info,
+    app: AppState,
+    Path(id): Path<String>,
+) -> Result<(ETag, StatusCode)> {
+    let mut result = RelationTuple {
+        id: id.clone(),
+        ..Default::default()
+    };
+    let (store, tx) = app.store.audited(&info.trail).await?;
+    store.relation_tuple.restore(&result).await?;
+    store.relation_tuple.get(&mut result).await?;
+    info.is_allow_in(&app, &store).await?;
+    tx.commit().await?;
+    Ok((ETag(result.resource_version), StatusCode::NO_CONTENT))
+}
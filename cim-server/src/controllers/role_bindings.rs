@@ -1,15 +1,20 @@
-use axum::{extract::Path, response::Response, routing::get, Json, Router};
+use axum::{
+    extract::Path,
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
 use http::StatusCode;
 
 use cim_slo::{errors, next_id, Result};
 use cim_storage::{
     role_binding::{Content, ListParams, RoleBinding},
-    Interface, WatchInterface, ID,
+    Interface, List, WatchInterface, ID,
 };
 
 use crate::{
-    auth::Auth,
-    valid::{ListWatch, Valid},
+    auth::{Auth, Info},
+    valid::{ETag, IfMatch, ListWatch, Valid},
     AppState,
 };
 
@@ -27,25 +32,31 @@ pub fn new_router(state: AppState) -> Router {
                 .delete(delete_role_binding)
                 .put(put_role_binding),
         )
+        .route("/role_bindings/deleted", get(list_deleted_role_binding))
+        .route("/role_bindings/{id}/restore", post(restore_role_binding))
         .with_state(state)
 }
 
 async fn create_role_binding(
-    _auth: Auth,
+    auth: Auth,
     app: AppState,
     Valid(Json(input)): Valid<Json<Content>>,
 ) -> Result<(StatusCode, Json<ID>)> {
     let id = next_id().map_err(errors::any)?;
-    app.store
+    let (store, tx) = app.store.audited(&auth.trail).await?;
+    store
         .role_binding
         .create(&RoleBinding {
             id: id.to_string(),
             role_id: input.role_id,
             user_type: input.user_type,
             user_id: input.user_id,
+            not_before: input.not_before,
+            expires_at: input.expires_at,
             ..Default::default()
         })
         .await?;
+    tx.commit().await?;
     Ok((StatusCode::CREATED, ID { id: id.to_string() }.into()))
 }
 
@@ -55,6 +66,7 @@ async fn list_role_binding(
     list_params: ListWatch<ListParams>,
 ) -> Result<Response> {
     list_watch(
+        &app.config,
         app.store.role_binding.clone(),
         list_params,
         |value, opts| {
@@ -73,43 +85,93 @@ async fn get_role_binding(
     _auth: Auth,
     app: AppState,
     Path(id): Path<String>,
-) -> Result<Json<RoleBinding>> {
+) -> Result<(ETag, Json<RoleBinding>)> {
     let mut result = RoleBinding {
         id: id.clone(),
         ..Default::default()
     };
     app.store.role_binding.get(&mut result).await?;
-    Ok(result.into())
+    Ok((ETag(result.resource_version), result.into()))
 }
 
 async fn delete_role_binding(
-    _auth: Auth,
+    auth: Auth,
     app: AppState,
     Path(id): Path<String>,
+    if_match: IfMatch,
 ) -> Result<StatusCode> {
-    let result = RoleBinding {
+    let mut result = RoleBinding {
         id: id.clone(),
         ..Default::default()
     };
-    app.store.role_binding.delete(&result).await?;
+    app.store.role_binding.get(&mut result).await?;
+    if_match.check(result.resource_version)?;
+    let (store, tx) = app.store.audited(&auth.trail).await?;
+    store
+        .role_binding
+        .delete_if(&result, result.resource_version)
+        .await?;
+    tx.commit().await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
 async fn put_role_binding(
-    _auth: Auth,
+    auth: Auth,
     app: AppState,
     Path(id): Path<String>,
+    if_match: IfMatch,
     Valid(Json(content)): Valid<Json<Content>>,
-) -> Result<StatusCode> {
+) -> Result<(ETag, StatusCode)> {
     let mut result = RoleBinding {
         id: id.clone(),
         ..Default::default()
     };
     app.store.role_binding.get(&mut result).await?;
+    if_match.check(result.resource_version)?;
 
     result.role_id = content.role_id;
     result.user_type = content.user_type;
     result.user_id = content.user_id;
-    app.store.role_binding.put(&result).await?;
-    Ok(StatusCode::NO_CONTENT)
+    result.not_before = content.not_before;
+    result.expires_at = content.expires_at;
+    let (store, tx) = app.store.audited(&auth.trail).await?;
+    store
+        .role_binding
+        .put_if(&result, result.resource_version)
+        .await?;
+    tx.commit().await?;
+    Ok((ETag(result.resource_version + 1), StatusCode::NO_CONTENT))
+}
+
+async fn list_deleted_role_binding(
+    auth: Auth,
+    app: AppState,
+    Valid(mut opts): Valid<ListParams>,
+) -> Result<Json<List<RoleBinding>>> {
+    opts.account_id = Some(auth.user.account_id);
+    let mut list = List::default();
+    app.store
+        .role_binding
+        .list_deleted(&opts, &mut list)
+        .await?;
+    Ok(list.into())
+}
+
+/// restore_role_binding undeletes the role binding, the transaction is rolled
+/// back unless the restored one is allowed
+async fn restore_role_binding(
+    info: Info,
+    app: AppState,
+    Path(id): Path<String>,
+) -> Result<(ETag, StatusCode)> {
+    let mut result = RoleBinding {
+        id: id.clone(),
+        ..Default::default()
+    };
+    let (store, tx) = app.store.audited(&info.trail).await?;
+    store.role_binding.restore(&result).await?;
+    store.role_binding.get(&mut result).await?;
+    info.is_allow_in(&app, &store).await?;
+    tx.commit().await?;
+    Ok((ETag(result.resource_version), StatusCode::NO_CONTENT))
 }
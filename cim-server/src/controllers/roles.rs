@@ -1,18 +1,21 @@
-use std::collections::HashMap;
-
-use axum::{extract::Path, response::Response, routing::get, Json, Router};
+use axum::{
+    extract::Path,
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
 use http::StatusCode;
 use tracing::info;
 
 use cim_slo::{errors, next_id, Result};
 use cim_storage::{
     role::{Content, ListParams, Role},
-    Interface, WatchInterface, ID,
+    Interface, List, WatchInterface, ID,
 };
 
 use crate::{
     auth::{Auth, Info},
-    valid::{ListWatch, Valid},
+    valid::{ETag, IfMatch, ListWatch, Valid},
     AppState,
 };
 
@@ -25,6 +28,8 @@ pub fn new_router(state: AppState) -> Router {
             "/roles/{id}",
             get(get_role).delete(delete_role).put(put_role),
         )
+        .route("/roles/deleted", get(list_deleted_role))
+        .route("/roles/{id}/restore", post(restore_role))
         .with_state(state)
 }
 
@@ -35,16 +40,20 @@ async fn create_role(
 ) -> Result<(StatusCode, Json<ID>)> {
     info!("list query {:#?}", input);
     let id = next_id().map_err(errors::any)?;
-    app.store
+    let (store, tx) = app.store.audited(&auth.trail).await?;
+    store
         .role
         .create(&Role {
             id: id.to_string(),
             account_id: auth.user.account_id,
             name: input.name,
             desc: input.desc,
+            labels: input.labels,
+            annotations: input.annotations,
             ..Default::default()
         })
         .await?;
+    tx.commit().await?;
     Ok((StatusCode::CREATED, ID { id: id.to_string() }.into()))
 }
 
@@ -53,74 +62,118 @@ async fn list_role(
     app: AppState,
     list_params: ListWatch<ListParams>,
 ) -> Result<Response> {
-    list_watch(app.store.role.clone(), list_params, |value, opts| {
-        if let Some(ref v) = opts.id {
-            if value.id.ne(v) {
-                return true;
+    list_watch(
+        &app.config,
+        app.store.role.clone(),
+        list_params,
+        |value, opts| {
+            if let Some(ref v) = opts.id {
+                if value.id.ne(v) {
+                    return true;
+                }
+            }
+            if let Some(ref v) = opts.account_id {
+                if value.account_id.ne(v) {
+                    return true;
+                }
             }
-        }
-        if let Some(ref v) = opts.account_id {
-            if value.account_id.ne(v) {
-                return true;
+            if let Some(ref v) = opts.label_selector {
+                if !v.matches(&value.labels) {
+                    return true;
+                }
             }
-        }
-        false
-    })
+            false
+        },
+    )
     .await
 }
 
 async fn get_role(
-    mut info: Info,
+    info: Info,
     app: AppState,
     Path(id): Path<String>,
-) -> Result<Json<Role>> {
+) -> Result<(ETag, Json<Role>)> {
     let mut result = Role {
         id: id.clone(),
         ..Default::default()
     };
     app.store.role.get(&mut result).await?;
-    info.is_allow(
-        &app.matcher,
-        HashMap::from([("account_id".to_owned(), result.account_id.clone())]),
-    )?;
-    Ok(result.into())
+    info.is_allow(&app).await?;
+    Ok((ETag(result.resource_version), result.into()))
 }
 
 async fn delete_role(
-    mut info: Info,
+    info: Info,
     app: AppState,
     Path(id): Path<String>,
+    if_match: IfMatch,
 ) -> Result<StatusCode> {
     let mut result = Role {
         id: id.clone(),
         ..Default::default()
     };
     app.store.role.get(&mut result).await?;
-    info.is_allow(
-        &app.matcher,
-        HashMap::from([("account_id".to_owned(), result.account_id.clone())]),
-    )?;
-    app.store.role.delete(&result).await?;
+    info.is_allow(&app).await?;
+    if_match.check(result.resource_version)?;
+    let (store, tx) = app.store.audited(&info.trail).await?;
+    store
+        .role
+        .delete_if(&result, result.resource_version)
+        .await?;
+    tx.commit().await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
 async fn put_role(
-    mut info: Info,
+    info: Info,
     app: AppState,
     Path(id): Path<String>,
+    if_match: IfMatch,
     Valid(Json(content)): Valid<Json<Content>>,
-) -> Result<StatusCode> {
+) -> Result<(ETag, StatusCode)> {
     let mut role = Role {
         id: id.clone(),
         ..Default::default()
     };
     app.store.role.get(&mut role).await?;
-    info.is_allow(
-        &app.matcher,
-        HashMap::from([("account_id".to_owned(), role.account_id.clone())]),
-    )?;
+    info.is_allow(&app).await?;
+    if_match.check(role.resource_version)?;
     role.name = content.name;
     role.desc = content.desc;
-    app.store.role.put(&role).await?;
-    Ok(StatusCode::NO_CONTENT)
+    role.labels = content.labels;
+    role.annotations = content.annotations;
+    let (store, tx) = app.store.audited(&info.trail).await?;
+    store.role.put_if(&role, role.resource_version).await?;
+    tx.commit().await?;
+    Ok((ETag(role.resource_version + 1), StatusCode::NO_CONTENT))
+}
+
+async fn list_deleted_role(
+    auth: Auth,
+    app: AppState,
+    Valid(mut opts): Valid<ListParams>,
+) -> Result<Json<List<Role>>> {
+    opts.account_id = Some(auth.user.account_id);
+    let mut list = List::default();
+    app.store.role.list_deleted(&opts, &mut list).await?;
+    Ok(list.into())
+}
+
+/// restore_role undeletes the role, the transaction is rolled back
+/// unless the restored one is allowed
+async fn restore_role(
+    info: Info,
+    app: AppState,
+    Path(id): Path<String>,
+) -> Result<(ETag, StatusCode)> {
+    let mut result = Role {
+        id: id.clone(),
+        ..Default::default()
+    };
+    let (store, tx) = app.store.audited(&info.trail).await?;
+    store.role.restore(&result).await?;
+    store.role.get(&mut result).await?;
+    info.is_allow_in(&app, &store).await?;
+    tx.commit().await?;
+    Ok((ETag(result.resource_version), StatusCode::NO_CONTENT))
 }
@@ -0,0 +1,117 @@
+use axum::{
+    body::Bytes,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use http::{header::CONTENT_TYPE, HeaderMap};
+use serde::Deserialize;
+use validator::Validate;
+
+use cim_slo::{errors, Result};
+use cim_storage::snapshot::{Change, Snapshot};
+
+use crate::{auth::Auth, services::snapshot, valid::Valid, AppState};
+
+pub fn new_router(state: AppState) -> Router {
+    Router::new()
+        .route("/snapshots", get(export_snapshot).post(import_snapshot))
+        .with_state(state)
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+    #[default]
+    Json,
+    Yaml,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct ExportParams {
+    #[serde(default)]
+    format: Format,
+    /// credentials exports the password hashes of the users, the secrets of
+    /// the clients and the configs of the connectors
+    #[serde(default)]
+    credentials: bool,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct ImportParams {
+    /// dry_run returns the changes without writing them
+    #[serde(default)]
+    dry_run: bool,
+}
+
+async fn export_snapshot(
+    auth: Auth,
+    app: AppState,
+    Valid(params): Valid<ExportParams>,
+) -> Result<Response> {
+    let snapshot = snapshot::export(
+        &app.store,
+        &auth.user.account_id,
+        params.credentials,
+        is_operator(&app, &auth),
+    )
+    .await?;
+    match params.format {
+        Format::Json => Ok(Json(snapshot).into_response()),
+        Format::Yaml => {
+            // through a json value as connectors hold raw json
+            let value = serde_json::to_value(&snapshot).map_err(errors::any)?;
+            let yaml = serde_yaml::to_string(&value).map_err(errors::any)?;
+            Ok(([(CONTENT_TYPE, "application/yaml")], yaml).into_response())
+        }
+    }
+}
+
+/// is_operator tells if the user of auth is of the operator account, whose
+/// snapshots carry the connectors
+fn is_operator(app: &AppState, auth: &Auth) -> bool {
+    app.config.operator_account.as_ref() == Some(&auth.user.account_id)
+}
+
+/// parse the snapshot in the body, it's yaml if the content type says so
+/// and json otherwise
+fn parse(headers: &HeaderMap, body: &[u8]) -> Result<Snapshot> {
+    let yaml = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("yaml"));
+    let snapshot = if yaml {
+        let value = serde_yaml::from_slice::<serde_json::Value>(body)
+            .map_err(|err| errors::bad_request(&err))?;
+        serde_json::from_str(&value.to_string())
+    } else {
+        serde_json::from_slice(body)
+    };
+    snapshot.map_err(|err| errors::bad_request(&err))
+}
+
+async fn import_snapshot(
+    auth: Auth,
+    app: AppState,
+    Valid(params): Valid<ImportParams>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<Vec<Change>>> {
+    let snapshot = parse(&headers, &body)?;
+    if snapshot.account_id != auth.user.account_id {
+        return Err(errors::forbidden(&format!(
+            "snapshot of account {} can not be imported",
+            snapshot.account_id
+        )));
+    }
+    let operator = is_operator(&app, &auth);
+    if params.dry_run {
+        return Ok(snapshot::import(&app.store, snapshot, true, operator)
+            .await?
+            .into());
+    }
+    let (store, tx) = app.store.audited(&auth.trail).await?;
+    let changes = snapshot::import(&store, snapshot, false, operator).await?;
+    tx.commit().await?;
+    Ok(changes.into())
+}
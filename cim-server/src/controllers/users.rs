@@ -1,18 +1,23 @@
-use std::collections::HashMap;
-
-use axum::{extract::Path, response::Response, routing::get, Json, Router};
+use axum::{
+    extract::Path,
+    response::Response,
+    routing::{get, post},
+    Json, Router,
+};
 use http::StatusCode;
 
 use cim_slo::Result;
 use cim_storage::{
+    audit::Trail,
+    group::{self, Group},
     user::{Content, ListParams, User},
-    Interface, ID,
+    Interface, List, Pagination, ID,
 };
 
 use crate::{
     auth::{Auth, Info},
     services::user,
-    valid::{ListWatch, Valid},
+    valid::{ClientIp, ETag, IfMatch, ListWatch, TraceId, Valid},
     AppState,
 };
 
@@ -25,14 +30,24 @@ pub fn new_router(state: AppState) -> Router {
             "/users/{id}",
             get(get_user).delete(delete_user).put(put_user),
         )
+        .route("/users/deleted", get(list_deleted_user))
+        .route("/users/{id}/restore", post(restore_user))
+        .route("/users/{id}/groups", get(list_user_group))
         .with_state(state)
 }
 
 async fn create_user(
     app: AppState,
+    client_ip: ClientIp,
+    TraceId(trace_id): TraceId,
     Valid(Json(input)): Valid<Json<Content>>,
 ) -> Result<(StatusCode, Json<ID>)> {
-    let id = user::create(app, input).await?;
+    let trail = Trail {
+        actor: String::new(),
+        trace_id,
+        source_ip: client_ip.ip.to_string(),
+    };
+    let id = user::create(app, trail, input).await?;
     Ok((StatusCode::CREATED, ID { id: id.to_string() }.into()))
 }
 
@@ -41,77 +56,154 @@ async fn list_user(
     app: AppState,
     list_params: ListWatch<ListParams>,
 ) -> Result<Response> {
-    list_watch(app.store.user.clone(), list_params, |value, opts| {
-        if let Some(ref v) = opts.id {
-            if value.id.ne(v) {
-                return true;
+    list_watch(
+        &app.config,
+        app.store.user.clone(),
+        list_params,
+        |value, opts| {
+            if let Some(ref v) = opts.id {
+                if value.id.ne(v) {
+                    return true;
+                }
+            }
+            if let Some(ref v) = opts.account_id {
+                if value.account_id.ne(v) {
+                    return true;
+                }
             }
-        }
-        if let Some(ref v) = opts.account_id {
-            if value.account_id.ne(v) {
-                return true;
+            if let Some(ref v) = opts.label_selector {
+                if !v.matches(&value.labels) {
+                    return true;
+                }
             }
-        }
-        false
-    })
+            false
+        },
+    )
     .await
 }
 
 async fn get_user(
-    mut info: Info,
+    info: Info,
     app: AppState,
     Path(id): Path<String>,
-) -> Result<Json<User>> {
+) -> Result<(ETag, Json<User>)> {
     let mut result = User {
         id: id.clone(),
         ..Default::default()
     };
     app.store.user.get(&mut result).await?;
-    info.is_allow(
-        &app.matcher,
-        HashMap::from([("account_id".to_owned(), result.account_id.clone())]),
-    )?;
+    info.is_allow(&app).await?;
     result.secret = None;
     result.password = None;
-    Ok(result.into())
+    Ok((ETag(result.resource_version), result.into()))
 }
 
 async fn delete_user(
-    mut info: Info,
+    info: Info,
     app: AppState,
     Path(id): Path<String>,
+    if_match: IfMatch,
 ) -> Result<StatusCode> {
     let mut result = User {
         id: id.clone(),
         ..Default::default()
     };
     app.store.user.get(&mut result).await?;
-    info.is_allow(
-        &app.matcher,
-        HashMap::from([("account_id".to_owned(), result.account_id.clone())]),
-    )?;
-    app.store.user.delete(&result).await?;
+    info.is_allow(&app).await?;
+    if_match.check(result.resource_version)?;
+    let (store, tx) = app.store.audited(&info.trail).await?;
+    store
+        .user
+        .delete_if(&result, result.resource_version)
+        .await?;
+    tx.commit().await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
 async fn put_user(
-    mut info: Info,
+    info: Info,
     app: AppState,
     Path(id): Path<String>,
+    if_match: IfMatch,
     Valid(Json(content)): Valid<Json<Content>>,
-) -> Result<StatusCode> {
+) -> Result<(ETag, StatusCode)> {
     let mut user = User {
         id: id.clone(),
         ..Default::default()
     };
     app.store.user.get(&mut user).await?;
-    info.is_allow(
-        &app.matcher,
-        HashMap::from([("account_id".to_owned(), user.account_id.clone())]),
-    )?;
+    info.is_allow(&app).await?;
+    if_match.check(user.resource_version)?;
     user.desc = content.desc;
     user.claim = content.claim;
     user.password = Some(content.password);
-    app.store.user.put(&user).await?;
-    Ok(StatusCode::NO_CONTENT)
+    user.labels = content.labels;
+    user.annotations = content.annotations;
+    let (store, tx) = app.store.audited(&info.trail).await?;
+    store.user.put_if(&user, user.resource_version).await?;
+    tx.commit().await?;
+    Ok((ETag(user.resource_version + 1), StatusCode::NO_CONTENT))
+}
+
+async fn list_deleted_user(
+    auth: Auth,
+    app: AppState,
+    Valid(mut opts): Valid<ListParams>,
+) -> Result<Json<List<User>>> {
+    opts.account_id = Some(auth.user.account_id);
+    let mut list = List::default();
+    app.store.user.list_deleted(&opts, &mut list).await?;
+    Ok(list.into())
+}
+
+/// restore_user undeletes the user, the transaction is rolled back
+/// unless the restored one is allowed
+async fn restore_user(
+    info: Info,
+    app: AppState,
+    Path(id): Path<String>,
+) -> Result<(ETag, StatusCode)> {
+    let mut result = User {
+        id: id.clone(),
+        ..Default::default()
+    };
+    let (store, tx) = app.store.audited(&info.trail).await?;
+    store.user.restore(&result).await?;
+    store.user.get(&mut result).await?;
+    info.is_allow_in(&app, &store).await?;
+    tx.commit().await?;
+    Ok((ETag(result.resource_version), StatusCode::NO_CONTENT))
+}
+
+/// list_user_group lists the effective groups of the user, the groups it's
+/// a member of and the groups they are nested in at any depth
+async fn list_user_group(
+    info: Info,
+    app: AppState,
+    Path(id): Path<String>,
+    Valid(pagination): Valid<Pagination>,
+) -> Result<Json<List<Group>>> {
+    let mut user = User {
+        id: id.clone(),
+        ..Default::default()
+    };
+    app.store.user.get(&mut user).await?;
+    info.is_allow(&app).await?;
+    let mut list = List::default();
+    app.store
+        .group
+        .list(
+            &group::ListParams {
+                id: None,
+                account_id: None,
+                parent_id: None,
+                user_id: None,
+                effective_user_id: Some(id),
+                label_selector: None,
+                pagination,
+            },
+            &mut list,
+        )
+        .await?;
+    Ok(list.into())
 }
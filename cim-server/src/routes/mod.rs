@@ -27,8 +27,8 @@ use cim_slo::errors;
 
 use crate::{
     controllers::{
-        group_users, groups, oidc, policies, policy_bindings, role_bindings,
-        roles, users,
+        audits, deleted, group_users, groups, oidc, policies, policy_bindings,
+        relation_tuples, role_bindings, roles, snapshots, users,
     },
     middlewares::MakeSpanWithTrace,
     var::{HTTP_REQUESTS_DURATION_SECONDS, HTTP_REQUESTS_TOTAL},
@@ -47,8 +47,8 @@ struct ApiDoc;
         (status = 200, description = "JSON file", body = ())
     )
 )]
-async fn openapi() -> Json<utoipa::openapi::OpenApi> {
-    Json(ApiDoc::openapi())
+async fn openapi() -> axum::Json<utoipa::openapi::OpenApi> {
+    axum::Json(<ApiDoc as utoipa::OpenApi>::openapi())
 }
 
 pub struct AppRouter;
@@ -70,6 +70,10 @@ impl AppRouter {
                     .merge(group_users::new_router(state.clone()))
                     .merge(role_bindings::new_router(state.clone()))
                     .merge(policy_bindings::new_router(state.clone()))
+                    .merge(relation_tuples::new_router(state.clone()))
+                    .merge(audits::new_router(state.clone()))
+                    .merge(deleted::new_router(state.clone()))
+                    .merge(snapshots::new_router(state.clone()))
                     .merge(groups::new_router(state)),
             )
             .layer(
@@ -0,0 +1,552 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde_json::value::RawValue;
+use tokio::sync::OnceCell;
+
+use cim_pim::{
+    rebac::{Rebac, TupleReader},
+    AttributeProvider, Request,
+};
+use cim_slo::{errors, Result};
+use cim_storage::{
+    client::Client,
+    expiry::is_active,
+    group::Group,
+    group_user::{self, GroupUser},
+    label::Labels,
+    policy::Policy,
+    policy_binding::{BindingsType, PolicyBinding},
+    relation_tuple::RelationTuple,
+    role::Role,
+    role_binding::RoleBinding,
+    user::User,
+    Interface, List, Pagination,
+};
+
+use crate::app::{App, Store};
+
+const USER_PREFIX: &str = "user:";
+const USER_GROUPS: &str = "user:groups";
+const ACCOUNT_ID: &str = "account_id";
+const LABEL_PREFIX: &str = "label:";
+const RELATION_PREFIX: &str = "relation:";
+
+/// providers chains the attribute providers of app, the subject user
+/// already loaded by the caller is reused when given
+pub fn providers<'a>(
+    app: &'a App,
+    user: Option<User>,
+) -> Vec<Box<dyn AttributeProvider + 'a>> {
+    providers_in(&app.store, &app.rebac, user)
+}
+
+/// providers_in chains the attribute providers reading from store, the
+/// ones of a transaction see what it wrote and don't wait on it
+pub fn providers_in<'a>(
+    store: &'a Store,
+    rebac: &'a Rebac<Arc<dyn TupleReader>>,
+    user: Option<User>,
+) -> Vec<Box<dyn AttributeProvider + 'a>> {
+    let store = StoreAttributes::new(store);
+    let store = match user {
+        Some(user) => store.with_user(user),
+        None => store,
+    };
+    vec![Box::new(store), Box::new(RelationAttributes::new(rebac))]
+}
+
+/// StoreAttributes resolves the attributes conditions may reference from
+/// `app::Store`:
+///
+/// - `user:<claim>`: a field of the subject user, like `user:email`
+/// - `user:groups`: the ids of the groups the subject user belongs to
+/// - `account_id`: the account owning the resource `crn:iam:<kind>:<id>`,
+///   a binding is of the account of its group, role or subject
+/// - `label:<key>`: the label `<key>` of that resource
+pub struct StoreAttributes<'a> {
+    store: &'a Store,
+    user: OnceCell<User>,
+    resource: OnceCell<Option<Owned>>,
+}
+
+/// Owned is what conditions may read of a resource
+struct Owned {
+    account_id: Option<String>,
+    labels: Labels,
+}
+
+impl<'a> StoreAttributes<'a> {
+    pub fn new(store: &'a Store) -> Self {
+        Self {
+            store,
+            user: OnceCell::new(),
+            resource: OnceCell::new(),
+        }
+    }
+
+    /// with_user seeds the subject user already loaded by the caller
+    pub fn with_user(mut self, user: User) -> Self {
+        self.user = OnceCell::new_with(Some(user));
+        self
+    }
+
+    async fn user(&self, id: &str) -> anyhow::Result<&User> {
+        self.user
+            .get_or_try_init(|| async {
+                let mut user = User {
+                    id: id.to_owned(),
+                    ..Default::default()
+                };
+                self.store.user.get(&mut user).await?;
+                Ok::<_, errors::WithBacktrace>(user)
+            })
+            .await
+            .map_err(|err| anyhow::anyhow!("{}", err))
+    }
+
+    async fn user_claim(
+        &self,
+        claim: &str,
+        req: &Request,
+    ) -> anyhow::Result<Option<Box<RawValue>>> {
+        let user = self.user(&req.subject).await?;
+        let mut value = serde_json::to_value(user)?;
+        let claim = match value.as_object_mut() {
+            Some(object) => match claim {
+                "secret" | "password" => None,
+                v => object.remove(v),
+            },
+            None => None,
+        };
+        match claim {
+            Some(v) => Ok(Some(serde_json::value::to_raw_value(&v)?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn user_groups(
+        &self,
+        req: &Request,
+    ) -> anyhow::Result<Option<Box<RawValue>>> {
+        let mut list = List::default();
+        self.store
+            .group_user
+            .list(
+                &group_user::ListParams {
+                    id: None,
+                    account_id: None,
+                    group_id: None,
+                    user_id: Some(req.subject.clone()),
+                    expires_before: None,
+                    pagination: Pagination {
+                        count_disable: true,
+                        ..Default::default()
+                    },
+                },
+                &mut list,
+            )
+            .await
+            .map_err(|err| anyhow::anyhow!("{}", err))?;
+        // the memberships outside of their window don't count
+        let now = chrono::Utc::now().naive_utc();
+        let groups = list
+            .data
+            .into_iter()
+            .filter(|v| is_active(v.not_before, v.expires_at, now))
+            .map(|v| v.group_id)
+            .collect::<Vec<_>>();
+        Ok(Some(serde_json::value::to_raw_value(&groups)?))
+    }
+
+    /// resource loads what conditions may read of the resource
+    /// `crn:iam:<kind>:<id>`, once per request
+    async fn resource(&self, req: &Request) -> anyhow::Result<Option<&Owned>> {
+        self.resource
+            .get_or_try_init(|| self.load_resource(&req.resource))
+            .await
+            .map(Option::as_ref)
+    }
+
+    /// account_id is the account owning the resource `crn:iam:<kind>:<id>`,
+    /// none when there is no such entity or it is of no account
+    pub async fn account_id(&self, resource: &str) -> Result<Option<String>> {
+        self.load_resource(resource)
+            .await
+            .map(|v| v.and_then(|v| v.account_id))
+            .map_err(errors::anyhow)
+    }
+
+    async fn load_resource(
+        &self,
+        resource: &str,
+    ) -> anyhow::Result<Option<Owned>> {
+        let mut parts = resource.split(':');
+        let (Some("crn"), Some("iam"), Some(kind), Some(id)) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Ok(None);
+        };
+        let id = id.to_owned();
+        let owned = match kind {
+            "group_user" => {
+                let mut value = GroupUser {
+                    id,
+                    ..Default::default()
+                };
+                match self.store.group_user.get(&mut value).await {
+                    Ok(()) => self.owner("group", value.group_id).await,
+                    Err(err) => Err(err),
+                }
+            }
+            "role_binding" => {
+                let mut value = RoleBinding {
+                    id,
+                    ..Default::default()
+                };
+                match self.store.role_binding.get(&mut value).await {
+                    Ok(()) => self.owner("role", value.role_id).await,
+                    Err(err) => Err(err),
+                }
+            }
+            "policy_binding" => {
+                let mut value = PolicyBinding {
+                    id,
+                    ..Default::default()
+                };
+                match self.store.policy_binding.get(&mut value).await {
+                    // the binding is of the account of what it binds,
+                    // whichever account the policy is of, or of the
+                    // policy's when what it binds is of no account
+                    Ok(()) => match value.bindings_type {
+                        BindingsType::User => {
+                            self.owner("user", value.bindings_id).await
+                        }
+                        BindingsType::Group => {
+                            self.owner("group", value.bindings_id).await
+                        }
+                        BindingsType::Role => {
+                            self.owner("role", value.bindings_id).await
+                        }
+                        _ => self.owner("policy", value.policy_id).await,
+                    },
+                    Err(err) => Err(err),
+                }
+            }
+            "relation_tuple" => {
+                let mut value = RelationTuple {
+                    id,
+                    ..Default::default()
+                };
+                self.store.relation_tuple.get(&mut value).await.map(|_| {
+                    Some(Owned {
+                        account_id: Some(value.account_id),
+                        labels: Labels::default(),
+                    })
+                })
+            }
+            kind => self.entity(kind, id).await,
+        };
+        // a missing resource owns nothing, the handler answers it with a
+        // 404, neither does a path segment which is no id like `deleted`
+        match owned {
+            Err(err)
+                if err.eq(&errors::not_found(""))
+                    || err.eq(&errors::bad_request("")) =>
+            {
+                Ok(None)
+            }
+            owned => owned.map_err(|err| anyhow::anyhow!("{}", err)),
+        }
+    }
+
+    /// owner is what conditions may read of the entity a binding belongs
+    /// to, the labels are the binding's which has none
+    async fn owner(&self, kind: &str, id: String) -> Result<Option<Owned>> {
+        Ok(self.entity(kind, id).await?.map(|v| Owned {
+            labels: Labels::default(),
+            ..v
+        }))
+    }
+
+    /// entity loads what conditions may read of the entity `<kind>:<id>`
+    async fn entity(&self, kind: &str, id: String) -> Result<Option<Owned>> {
+        match kind {
+            "user" => {
+                let mut value = User {
+                    id,
+                    ..Default::default()
+                };
+                self.store.user.get(&mut value).await.map(|_| {
+                    Some(Owned {
+                        account_id: Some(value.account_id),
+                        labels: value.labels,
+                    })
+                })
+            }
+            "group" => {
+                let mut value = Group {
+                    id,
+                    ..Default::default()
+                };
+                self.store.group.get(&mut value).await.map(|_| {
+                    Some(Owned {
+                        account_id: Some(value.account_id),
+                        labels: value.labels,
+                    })
+                })
+            }
+            "role" => {
+                let mut value = Role {
+                    id,
+                    ..Default::default()
+                };
+                self.store.role.get(&mut value).await.map(|_| {
+                    Some(Owned {
+                        account_id: Some(value.account_id),
+                        labels: value.labels,
+                    })
+                })
+            }
+            "policy" => {
+                let mut value = Policy {
+                    id,
+                    ..Default::default()
+                };
+                self.store.policy.get(&mut value).await.map(|_| {
+                    Some(Owned {
+                        account_id: value.account_id,
+                        labels: value.labels,
+                    })
+                })
+            }
+            "client" => {
+                let mut value = Client {
+                    id,
+                    ..Default::default()
+                };
+                self.store.client.get(&mut value).await.map(|_| {
+                    Some(Owned {
+                        account_id: Some(value.account_id),
+                        labels: value.labels,
+                    })
+                })
+            }
+            _ => Ok(None),
+        }
+    }
+
+    async fn resource_owner(
+        &self,
+        req: &Request,
+    ) -> anyhow::Result<Option<Box<RawValue>>> {
+        match self.resource(req).await? {
+            Some(Owned {
+                account_id: Some(v),
+                ..
+            }) => Ok(Some(serde_json::value::to_raw_value(v)?)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn resource_label(
+        &self,
+        key: &str,
+        req: &Request,
+    ) -> anyhow::Result<Option<Box<RawValue>>> {
+        match self.resource(req).await?.and_then(|v| v.labels.get(key)) {
+            Some(v) => Ok(Some(serde_json::value::to_raw_value(v)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[async_trait]
+impl AttributeProvider for StoreAttributes<'_> {
+    async fn resolve(
+        &self,
+        key: &str,
+        req: &Request,
+    ) -> anyhow::Result<Option<Box<RawValue>>> {
+        if key == USER_GROUPS {
+            return self.user_groups(req).await;
+        }
+        if let Some(claim) = key.strip_prefix(USER_PREFIX) {
+            return self.user_claim(claim, req).await;
+        }
+        if key == ACCOUNT_ID {
+            return self.resource_owner(req).await;
+        }
+        if let Some(label) = key.strip_prefix(LABEL_PREFIX) {
+            return self.resource_label(label, req).await;
+        }
+        Ok(None)
+    }
+}
+
+/// RelationAttributes resolves `relation:<name>` to whether the subject has
+/// the relation `<name>` on the resource `crn:iam:<namespace>:<id>`,
+/// which is checked as the object `<namespace>:<id>`. The subject has no
+/// relation on any other resource
+pub struct RelationAttributes<'a> {
+    rebac: &'a Rebac<Arc<dyn TupleReader>>,
+}
+
+impl<'a> RelationAttributes<'a> {
+    pub fn new(rebac: &'a Rebac<Arc<dyn TupleReader>>) -> Self {
+        Self { rebac }
+    }
+}
+
+#[async_trait]
+impl AttributeProvider for RelationAttributes<'_> {
+    async fn resolve(
+        &self,
+        key: &str,
+        req: &Request,
+    ) -> anyhow::Result<Option<Box<RawValue>>> {
+        let Some(relation) = key.strip_prefix(RELATION_PREFIX) else {
+            return Ok(None);
+        };
+        let mut parts = req.resource.split(':');
+        // a resource naming no object has no relations, and leaving the
+        // condition unresolved would skip it rather than deny
+        let (Some("crn"), Some("iam"), Some(namespace), Some(id), None) = (
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+            parts.next(),
+        ) else {
+            return Ok(Some(serde_json::value::to_raw_value(&false)?));
+        };
+        if namespace.is_empty() || id.is_empty() {
+            return Ok(Some(serde_json::value::to_raw_value(&false)?));
+        }
+        let object = format!("{}:{}", namespace, id);
+        let allowed = self.rebac.check(&object, relation, &req.subject).await?;
+        Ok(Some(serde_json::value::to_raw_value(&allowed)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Duration};
+
+    use cim_pim::PrincipalType;
+    use cim_storage::{connection_manager, key::KeyRing};
+
+    use super::*;
+    use crate::app::CacheOpts;
+
+    #[tokio::test]
+    async fn resource_label() {
+        let pool = connection_manager("memory://", 1, 1, true).await.unwrap();
+        let store = Store::new(&pool, CacheOpts::default(), KeyRing::default());
+        store
+            .group
+            .put(&Group {
+                id: "1".to_owned(),
+                account_id: "2".to_owned(),
+                labels: Labels::from([("env".to_owned(), "prod".to_owned())]),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let attributes = StoreAttributes::new(&store);
+        let req = Request {
+            resource: "crn:iam:group:1".to_owned(),
+            action: "get".to_owned(),
+            subject: "3".to_owned(),
+            principal_type: PrincipalType::User,
+            context: HashMap::new(),
+        };
+        let resolve = |key: &'static str| attributes.resolve(key, &req);
+        assert_eq!(
+            resolve("label:env").await.unwrap().unwrap().get(),
+            "\"prod\""
+        );
+        assert_eq!(
+            resolve("account_id").await.unwrap().unwrap().get(),
+            "\"2\""
+        );
+        assert!(resolve("label:tier").await.unwrap().is_none());
+
+        let req = Request {
+            resource: "crn:iam:group:4".to_owned(),
+            ..req
+        };
+        let attributes = StoreAttributes::new(&store);
+        assert!(attributes
+            .resolve("account_id", &req)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn transaction() {
+        let pool = connection_manager("sqlite::memory:", 1, 1, true)
+            .await
+            .unwrap();
+        let store = Store::new(&pool, CacheOpts::default(), KeyRing::default());
+        let (tx_store, tx) = store.begin().await.unwrap();
+        tx_store
+            .group
+            .put(&Group {
+                id: "1".to_owned(),
+                account_id: "2".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        // the single connection is the transaction's, it resolves through
+        // its store what it wrote so far
+        let rebac = Rebac::new(vec![], tx_store.tuple_reader.clone());
+        let providers = providers_in(&tx_store, &rebac, None);
+        let req = Request {
+            resource: "crn:iam:group:1".to_owned(),
+            action: "get".to_owned(),
+            subject: "3".to_owned(),
+            principal_type: PrincipalType::User,
+            context: HashMap::new(),
+        };
+        let resolve = providers[0].resolve("account_id", &req);
+        let account_id = tokio::time::timeout(Duration::from_secs(5), resolve)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(account_id.get(), "\"2\"");
+        tx.commit().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn relation_of_unparseable_resource() {
+        let pool = connection_manager("memory://", 1, 1, true).await.unwrap();
+        let store = Store::new(&pool, CacheOpts::default(), KeyRing::default());
+        let rebac = Rebac::new(vec![], store.tuple_reader.clone());
+        let attributes = RelationAttributes::new(&rebac);
+        for resource in [
+            "*",
+            "doc:1",
+            "crn:aws:doc:1",
+            "crn:iam:doc:",
+            "crn:iam::1",
+            "crn:iam:doc:1:2",
+        ] {
+            let req = Request {
+                resource: resource.to_owned(),
+                action: "get".to_owned(),
+                subject: "3".to_owned(),
+                principal_type: PrincipalType::User,
+                context: HashMap::new(),
+            };
+            let allowed = attributes.resolve("relation:owner", &req).await;
+            assert_eq!(allowed.unwrap().unwrap().get(), "false", "{resource}");
+        }
+    }
+}
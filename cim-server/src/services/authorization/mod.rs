@@ -1,24 +1,30 @@
+pub mod attribute;
+
 use tracing::debug;
 
 use cim_pim::Request;
-use cim_pim::{Matcher, Pim};
+use cim_pim::{AttributeProvider, Matcher, Pim};
 use cim_slo::{errors, Result};
 use cim_storage::policy::StatementStore;
 
-/// authorize return  ok or error
-pub async fn authorize<P, R>(
+/// authorize return  ok or error,the attributes missing in the request context
+/// are resolved through provider
+pub async fn authorize<P, R, A>(
     policy: &P,
     matcher: &Pim<R>,
     input: &Request,
+    provider: &A,
 ) -> Result<()>
 where
     P: StatementStore,
-    R: Matcher,
+    R: Matcher + Sync,
+    A: AttributeProvider + ?Sized,
 {
     let statements = policy.get_statement(input).await?;
     debug!("statements:{:#?}", statements);
     matcher
-        .is_allow(&statements, input)
+        .is_allow_with(&statements, input, provider)
+        .await
         .map_err(|err| errors::forbidden(&err.to_string()))
 }
 
@@ -149,11 +155,8 @@ mod tests {
                 resource: "myrn:some.domain.com:resource:123".to_owned(),
                 action: "delete".to_owned(),
                 subject: "peter".to_owned(),
+                principal_type: PrincipalType::User,
                 context: HashMap::from([
-                    (
-                        "owner".to_owned(),
-                        serde_json::value::to_raw_value("peter").unwrap(),
-                    ),
                     (
                         "clientIP".to_owned(),
                         serde_json::value::to_raw_value("192.168.1.67")
@@ -191,6 +194,10 @@ mod tests {
                     ),
                 ]),
             },
+            &HashMap::from([(
+                "owner".to_owned(),
+                serde_json::value::to_raw_value("peter").unwrap(),
+            )]),
         )
         .await
         .unwrap()
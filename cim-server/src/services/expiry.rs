@@ -0,0 +1,236 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use tracing::info;
+
+use cim_job::Job;
+use cim_slo::{errors, Result};
+use cim_storage::{
+    group_user::{self, GroupUser},
+    policy_binding::{self, PolicyBinding},
+    role_binding::{self, RoleBinding},
+    Interface, List, Pagination,
+};
+
+use crate::var::BINDINGS_EXPIRED_TOTAL;
+
+/// expire_all deletes the bindings listed by params from store, a binding
+/// changed since it was listed is left for the next sweep. It evaluates to
+/// how many were deleted
+macro_rules! expire_all {
+    ($store:expr, $params:expr) => {{
+        let mut expired = 0u64;
+        let mut params = $params;
+        loop {
+            let mut bindings = List::default();
+            $store.list(&params, &mut bindings).await?;
+            for binding in bindings.data {
+                match $store.delete_if(&binding, binding.resource_version).await
+                {
+                    Ok(()) => expired += 1,
+                    Err(err) if err.eq(&errors::conflict("")) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+            match bindings.next_cursor {
+                Some(next) => params.pagination.cursor = Some(next),
+                None => break expired,
+            }
+        }
+    }};
+}
+
+/// Expirer deletes the group memberships, role bindings and policy bindings
+/// past their expiry. The stores should be the watched ones, so the delete
+/// events evict what the caches hold for the bindings
+pub struct Expirer<G, R, P> {
+    group_user: G,
+    role_binding: R,
+    policy_binding: P,
+}
+
+impl<G, R, P> Expirer<G, R, P> {
+    pub fn new(group_user: G, role_binding: R, policy_binding: P) -> Self {
+        Self {
+            group_user,
+            role_binding,
+            policy_binding,
+        }
+    }
+}
+
+impl<G, R, P> Expirer<G, R, P>
+where
+    G: Interface<T = GroupUser, L = group_user::ListParams>,
+    R: Interface<T = RoleBinding, L = role_binding::ListParams>,
+    P: Interface<T = PolicyBinding, L = policy_binding::ListParams>,
+{
+    pub async fn expire(&self) -> Result<()> {
+        let now = Utc::now().naive_utc();
+        let group_users = expire_all!(
+            self.group_user,
+            group_user::ListParams {
+                id: None,
+                account_id: None,
+                group_id: None,
+                user_id: None,
+                expires_before: Some(now),
+                pagination: pagination(),
+            }
+        );
+        let role_bindings = expire_all!(
+            self.role_binding,
+            role_binding::ListParams {
+                id: None,
+                account_id: None,
+                role_id: None,
+                user_type: None,
+                user_id: None,
+                expires_before: Some(now),
+                pagination: pagination(),
+            }
+        );
+        let policy_bindings = expire_all!(
+            self.policy_binding,
+            policy_binding::ListParams {
+                id: None,
+                account_id: None,
+                policy_id: None,
+                bindings_type: None,
+                bindings_id: None,
+                expires_before: Some(now),
+                pagination: pagination(),
+            }
+        );
+
+        for (kind, expired) in [
+            ("group_user", group_users),
+            ("role_binding", role_bindings),
+            ("policy_binding", policy_bindings),
+        ] {
+            BINDINGS_EXPIRED_TOTAL
+                .with_label_values(&[kind])
+                .inc_by(expired);
+        }
+        info!(
+            "expired {} group users, {} role bindings and {} policy bindings",
+            group_users, role_bindings, policy_bindings
+        );
+        Ok(())
+    }
+}
+
+fn pagination() -> Pagination {
+    Pagination {
+        limit: 100,
+        count_disable: true,
+        ..Default::default()
+    }
+}
+
+#[async_trait]
+impl<G, R, P> Job for Expirer<G, R, P>
+where
+    G: Interface<T = GroupUser, L = group_user::ListParams> + Send + Sync,
+    R: Interface<T = RoleBinding, L = role_binding::ListParams> + Send + Sync,
+    P: Interface<T = PolicyBinding, L = policy_binding::ListParams>
+        + Send
+        + Sync,
+{
+    async fn run(&self) -> Result<()> {
+        self.expire().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use chrono::Duration;
+
+    use cim_storage::{memory, Action, Event, WatchInterface, WatchStore};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn expire() {
+        let db = memory::Database::default();
+        let group_user =
+            WatchStore::new(memory::GroupUserImpl::new(db.clone()));
+        let role_binding = memory::RoleBindingImpl::new(db.clone());
+        let policy_binding = memory::PolicyBindingImpl::new(db);
+        let expirer = Expirer::new(
+            group_user.clone(),
+            role_binding.clone(),
+            policy_binding.clone(),
+        );
+
+        let now = Utc::now().naive_utc();
+        let hour = Duration::hours(1);
+        for (id, expires_at) in [
+            ("1", Some(now - hour)),
+            ("2", Some(now + hour)),
+            ("3", None),
+        ] {
+            group_user
+                .put(&GroupUser {
+                    id: id.to_owned(),
+                    group_id: "1".to_owned(),
+                    user_id: id.to_owned(),
+                    expires_at,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            role_binding
+                .put(&RoleBinding {
+                    id: id.to_owned(),
+                    role_id: "1".to_owned(),
+                    user_id: id.to_owned(),
+                    expires_at,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            policy_binding
+                .put(&PolicyBinding {
+                    id: id.to_owned(),
+                    policy_id: "1".to_owned(),
+                    bindings_id: id.to_owned(),
+                    expires_at,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        let deleted = Arc::new(Mutex::new(Vec::new()));
+        let received = deleted.clone();
+        let _guard = group_user
+            .watch(0, move |event: Event<GroupUser>| {
+                if let Action::Delete(v) = event.action {
+                    received.lock().unwrap().push(v.id);
+                }
+            })
+            .unwrap();
+        expirer.expire().await.unwrap();
+
+        assert_eq!(*deleted.lock().unwrap(), vec!["1".to_owned()]);
+        for (id, kept) in [("1", false), ("2", true), ("3", true)] {
+            let mut v = GroupUser {
+                id: id.to_owned(),
+                ..Default::default()
+            };
+            assert_eq!(group_user.get(&mut v).await.is_ok(), kept);
+            let mut v = RoleBinding {
+                id: id.to_owned(),
+                ..Default::default()
+            };
+            assert_eq!(role_binding.get(&mut v).await.is_ok(), kept);
+            let mut v = PolicyBinding {
+                id: id.to_owned(),
+                ..Default::default()
+            };
+            assert_eq!(policy_binding.get(&mut v).await.is_ok(), kept);
+        }
+    }
+}
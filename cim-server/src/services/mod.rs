@@ -1,3 +1,6 @@
 pub mod authorization;
+pub mod expiry;
 pub mod oidc;
+pub mod purge;
+pub mod snapshot;
 pub mod user;
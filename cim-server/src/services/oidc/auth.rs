@@ -58,6 +58,7 @@ pub struct AuthRequestState {
     pub callback: Option<String>,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Deserialize, Serialize, Validate)]
 pub struct ReqHmac {
     pub req: String,
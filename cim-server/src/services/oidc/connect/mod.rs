@@ -38,7 +38,7 @@ pub trait CallbackConnector: Send + Sync {
     /// aware of the global set of user/connector interactions.
     async fn login_url(
         &self,
-        scopes: &Vec<String>,
+        scopes: &[String],
         callback_url: &str,
         state: &str,
     ) -> Result<String>;
@@ -46,7 +46,7 @@ pub trait CallbackConnector: Send + Sync {
     /// Handle the callback to the server and return an identity.
     async fn handle_callback(
         &self,
-        scopes: &Vec<String>,
+        scopes: &[String],
         req: Request,
     ) -> Result<Identity>;
 
@@ -56,7 +56,7 @@ pub trait CallbackConnector: Send + Sync {
 
     async fn refresh(
         &self,
-        _scopes: &Vec<String>,
+        _scopes: &[String],
         _identity: &Identity,
     ) -> Result<Identity> {
         Err(errors::unauthorized())
@@ -24,7 +24,7 @@ where
 {
     async fn login_url(
         &self,
-        _scopes: &Vec<String>,
+        _scopes: &[String],
         callback_url: &str,
         state: &str,
     ) -> Result<String> {
@@ -34,7 +34,7 @@ where
     /// Handle the callback to the server and return an identity.
     async fn handle_callback(
         &self,
-        _scopes: &Vec<String>,
+        _scopes: &[String],
         req: Request,
     ) -> Result<Identity> {
         let hv = req
@@ -68,7 +68,7 @@ where
     }
     async fn refresh(
         &self,
-        _scopes: &Vec<String>,
+        _scopes: &[String],
         identity: &Identity,
     ) -> Result<Identity> {
         let mut user = User {
@@ -0,0 +1,209 @@
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use tracing::info;
+
+use cim_job::Job;
+use cim_slo::{errors, Result};
+use cim_storage::{
+    gc::GcStore,
+    offlinesession::{self, OfflineSession},
+    refresh_token::RefreshToken,
+    Interface, List, Pagination,
+};
+
+use crate::var::OIDC_GC_COLLECTED_TOTAL;
+
+/// Collector removes the auth requests and codes past their expiry and the
+/// refresh tokens a refresh grant would reject as expired, then drops the
+/// references offline sessions keep to refresh tokens which are gone
+pub struct Collector<G, R, O> {
+    gc: G,
+    refresh_store: R,
+    offline_session_store: O,
+    pub absolute_lifetime: Duration,
+    pub valid_if_not_used_for: Duration,
+}
+
+impl<G, R, O> Collector<G, R, O> {
+    pub fn new(gc: G, refresh_store: R, offline_session_store: O) -> Self {
+        Self {
+            gc,
+            refresh_store,
+            offline_session_store,
+            absolute_lifetime: Duration::zero(),
+            valid_if_not_used_for: Duration::zero(),
+        }
+    }
+}
+
+impl<G, R, O> Collector<G, R, O>
+where
+    G: GcStore,
+    R: Interface<T = RefreshToken>,
+    O: Interface<T = OfflineSession, L = offlinesession::ListParams>,
+{
+    pub async fn collect(&self) -> Result<()> {
+        let now = Utc::now();
+        let auth_requests =
+            self.gc.purge_auth_requests(now.timestamp()).await?;
+        let auth_codes = self.gc.purge_auth_codes(now.timestamp()).await?;
+        // a zero lifetime never expires, like in the refresh grant
+        let created_before = (!self.absolute_lifetime.is_zero())
+            .then(|| (now - self.absolute_lifetime).naive_utc());
+        let used_before = (!self.valid_if_not_used_for.is_zero())
+            .then(|| (now - self.valid_if_not_used_for).naive_utc());
+        let refresh_tokens = self
+            .gc
+            .purge_refresh_tokens(created_before, used_before)
+            .await?;
+        let refresh_refs = self.prune_offline_sessions().await?;
+
+        for (kind, collected) in [
+            ("auth_request", auth_requests),
+            ("auth_code", auth_codes),
+            ("refresh_token", refresh_tokens),
+            ("refresh_ref", refresh_refs),
+        ] {
+            OIDC_GC_COLLECTED_TOTAL
+                .with_label_values(&[kind])
+                .inc_by(collected);
+        }
+        info!(
+            "collected {} auth requests, {} auth codes, {} refresh tokens and {} refresh references",
+            auth_requests, auth_codes, refresh_tokens, refresh_refs
+        );
+        Ok(())
+    }
+
+    /// prune_offline_sessions removes the refresh references whose token
+    /// is gone, returning how many
+    async fn prune_offline_sessions(&self) -> Result<u64> {
+        let mut pruned = 0;
+        let mut cursor = None;
+        loop {
+            let mut sessions = List::default();
+            self.offline_session_store
+                .list(
+                    &offlinesession::ListParams {
+                        user_id: None,
+                        conn_id: None,
+                        pagination: Pagination {
+                            limit: 100,
+                            cursor,
+                            count_disable: true,
+                            ..Default::default()
+                        },
+                    },
+                    &mut sessions,
+                )
+                .await?;
+            for mut session in sessions.data {
+                let before = session.refresh.len();
+                let mut kept = session.refresh.clone();
+                for (key, reference) in session.refresh.iter() {
+                    let mut token = RefreshToken {
+                        id: reference.id.clone(),
+                        ..Default::default()
+                    };
+                    match self.refresh_store.get(&mut token).await {
+                        Ok(()) => {}
+                        Err(err) if err.eq(&errors::not_found("")) => {
+                            kept.remove(key);
+                        }
+                        Err(err) => return Err(err),
+                    }
+                }
+                if kept.len() != before {
+                    pruned += (before - kept.len()) as u64;
+                    session.refresh = kept;
+                    self.offline_session_store.put(&session).await?;
+                }
+            }
+            match sessions.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return Ok(pruned),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<G, R, O> Job for Collector<G, R, O>
+where
+    G: GcStore,
+    R: Interface<T = RefreshToken> + Send + Sync,
+    O: Interface<T = OfflineSession, L = offlinesession::ListParams>
+        + Send
+        + Sync,
+{
+    async fn run(&self) -> Result<()> {
+        self.collect().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use cim_storage::{memory, offlinesession::RefreshTokenRef};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn collect() {
+        let db = memory::Database::default();
+        let refresh_store = memory::RefreshTokenImpl::new(db.clone());
+        let offline_session_store = memory::OfflineSessionImpl::new(db.clone());
+        let mut collector = Collector::new(
+            memory::GcImpl::new(db),
+            refresh_store.clone(),
+            offline_session_store.clone(),
+        );
+        collector.valid_if_not_used_for = Duration::days(1);
+
+        let now = Utc::now().naive_utc();
+        let mut refresh = HashMap::new();
+        for (id, last_used_at) in
+            [("1", now - Duration::days(2)), ("2", now), ("3", now)]
+        {
+            refresh_store
+                .put(&RefreshToken {
+                    id: id.to_owned(),
+                    last_used_at,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            refresh.insert(
+                format!("client{id}"),
+                RefreshTokenRef {
+                    id: id.to_owned(),
+                    ..Default::default()
+                },
+            );
+        }
+        refresh_store
+            .delete(&RefreshToken {
+                id: "3".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        offline_session_store
+            .put(&OfflineSession {
+                id: "1".to_owned(),
+                refresh,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        collector.collect().await.unwrap();
+        let mut session = OfflineSession {
+            id: "1".to_owned(),
+            ..Default::default()
+        };
+        offline_session_store.get(&mut session).await.unwrap();
+        assert_eq!(session.refresh.keys().collect::<Vec<_>>(), vec!["client2"]);
+    }
+}
@@ -40,6 +40,8 @@ impl<S> KeyRotator<S>
 where
     S: Interface<T = Keys, L = ()>,
 {
+    /// rotate replaces the signing key once it's due, the keys are written
+    /// at the version read so that only one replica rotates them
     pub async fn rotate(&self) -> Result<()> {
         let mut output: List<Keys> = List::default();
         self.store.list(&(), &mut output).await?;
@@ -50,8 +52,9 @@ where
                 info!("Skipping key rotation");
                 return Ok(());
             }
+            let resource_version = keys.resource_version;
             self.update_key(&mut keys)?;
-            return self.store.put(&keys).await;
+            return self.store.put_if(&keys, resource_version).await;
         }
         let (signing_key, signing_key_pub) = self.create_key()?;
         let now_time = Self::time_now();
@@ -65,10 +68,30 @@ where
                     public_key: signing_key_pub,
                 }],
                 next_rotation: now_time + self.strategy.rotation_frequency,
+                resource_version: 0,
             })
             .await
     }
 
+    /// reseal writes the keys back, sealing them by the first key
+    /// encryption key. A rotated out key encryption key can be dropped once
+    /// the keys it sealed are sealed again. The keys are written at the
+    /// version read, the ones another replica wrote meanwhile were sealed
+    /// by it
+    pub async fn reseal(&self) -> Result<()> {
+        let mut output: List<Keys> = List::default();
+        self.store.list(&(), &mut output).await?;
+        for keys in output.data.iter() {
+            match self.store.put_if(keys, keys.resource_version).await {
+                Err(err) if err.eq(&errors::conflict("")) => {
+                    info!("keys {} were written meanwhile", keys.id)
+                }
+                result => result?,
+            }
+        }
+        Ok(())
+    }
+
     fn time_now() -> i64 {
         Utc::now().timestamp()
     }
@@ -82,7 +105,7 @@ where
     }
 
     fn create_key(&self) -> Result<(jwk::JsonWebKey, jwk::JsonWebKey)> {
-        let mut rng = rand::rng();
+        let mut rng = rsa::rand_core::OsRng;
         let private_key =
             rsa::RsaPrivateKey::new(&mut rng, 2048).map_err(errors::any)?;
 
@@ -1,5 +1,6 @@
 pub mod auth;
 pub mod connect;
+pub mod gc;
 pub mod key;
 pub mod token;
 
@@ -97,6 +98,7 @@ pub async fn redirect_auth_page<
         .await
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn auth_page_callback<
     S: Interface<T = authrequest::AuthRequest>,
     A: Interface<T = authcode::AuthCode>,
@@ -500,48 +502,19 @@ pub fn jwk_to_public(key: Box<jwk::Key>) -> Result<Box<jwk::Key>> {
 mod tests {
     use super::*;
 
-    use async_trait::async_trait;
-    use cim_storage::{client::Client, Interface, List};
-    use mockall::mock;
-
-    mock! {
-        pub ClientStore {
-            fn get(&self, output: &mut Client) -> Result<()>;
-        }
-    }
-
-    #[async_trait]
-    impl Interface for MockClientStore {
-        type T = Client;
-        type L = ();
-        async fn put(&self, _input: &Self::T) -> Result<()> {
-            unimplemented!()
-        }
-        async fn delete(&self, _input: &Self::T) -> Result<()> {
-            unimplemented!()
-        }
-        async fn get(&self, output: &mut Self::T) -> Result<()> {
-            self.get(output)
-        }
-        async fn list(
-            &self,
-            _opts: &Self::L,
-            _output: &mut List<Self::T>,
-        ) -> Result<()> {
-            unimplemented!()
-        }
-        async fn count(&self, _opts: &Self::L, _unscoped: bool) -> Result<i64> {
-            unimplemented!()
-        }
-    }
+    use cim_storage::{client::Client, memory, Interface};
 
     #[tokio::test]
     async fn test_parse_auth_request() {
-        let mut client_store = MockClientStore::new();
-        client_store.expect_get().returning(|output| {
-            output.id = "client_id".to_owned();
-            Ok(())
-        });
+        let client_store = memory::ClientImpl::new(Default::default());
+        client_store
+            .put(&Client {
+                id: "client_id".to_owned(),
+                redirect_uris: vec!["http://localhost:3000/callback".to_owned()],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
 
         let req = AuthRequest {
             client_id: "client_id".to_owned(),
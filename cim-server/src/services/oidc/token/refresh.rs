@@ -191,7 +191,7 @@ where
         refresh_token: &mut refresh_token::RefreshToken,
         connector_value: &connector::Connector,
         offline_session: &mut offlinesession::OfflineSession,
-        scopes: &Vec<String>,
+        scopes: &[String],
     ) -> Result<connect::Identity> {
         let mut ident = connect::Identity {
             claim: refresh_token.claim.clone(),
@@ -230,7 +230,7 @@ where
         let connector_impl =
             open_connector(self.user_store, Some(connector_value))?;
         if connector_impl.support_refresh() {
-            ident = connector_impl.refresh(&scopes, &ident).await?;
+            ident = connector_impl.refresh(scopes, &ident).await?;
         }
         refresh_token.claim = ident.claim.clone();
         self.refresh_store.put(refresh_token).await?;
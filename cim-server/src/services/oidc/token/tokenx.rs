@@ -195,50 +195,18 @@ mod tests {
     use crate::services::oidc::jwk_to_public;
     use cim_storage::{
         key::{Keys, VerificationKey},
-        Claim, List,
+        memory, Claim,
     };
-    use mockall::mock;
-
-    mock! {
-        pub KeyStore {
-            fn list(&self, opts: &(), output: &mut List<Keys>) -> Result<()>;
-        }
-    }
-
-    #[async_trait]
-    impl Interface for MockKeyStore {
-        type T = Keys;
-        type L = ();
-        async fn put(&self, _input: &Self::T) -> Result<()> {
-            unimplemented!()
-        }
-        async fn delete(&self, _input: &Self::T) -> Result<()> {
-            unimplemented!()
-        }
-        async fn get(&self, _output: &mut Self::T) -> Result<()> {
-            unimplemented!()
-        }
-        async fn list(
-            &self,
-            opts: &Self::L,
-            output: &mut List<Self::T>,
-        ) -> Result<()> {
-            self.list(opts, output)
-        }
-        async fn count(&self, _opts: &Self::L, _unscoped: bool) -> Result<i64> {
-            unimplemented!()
-        }
-    }
 
     #[tokio::test]
     async fn token_encode_decode() {
-        let mut key_store = MockKeyStore::new();
-        let key_id = rand::thread_rng()
-            .sample_iter(&rand::distributions::Alphanumeric)
+        let key_store = memory::KeysImpl::new(Default::default());
+        let key_id = rand::rng()
+            .sample_iter(&rand::distr::Alphanumeric)
             .take(40)
             .map(char::from)
             .collect::<String>();
-        let mut rng = rand::thread_rng();
+        let mut rng = rsa::rand_core::OsRng;
         let private_key = rsa::RsaPrivateKey::new(&mut rng, 2048).unwrap();
         let mut p = None;
         let mut q = None;
@@ -276,23 +244,24 @@ mod tests {
         let mut signing_key_pub = signing_key.clone();
         signing_key_pub.key = jwk_to_public(signing_key.key.clone()).unwrap();
 
-        key_store.expect_list().returning(move |_, output| {
-            output.data.push(Keys {
+        key_store
+            .put(&Keys {
                 id: 1.to_string(),
-                signing_key: signing_key.clone(),
+                signing_key,
                 signing_key_pub: signing_key_pub.clone(),
                 verification_keys: vec![VerificationKey {
                     expiry: 12,
-                    public_key: signing_key_pub.clone(),
+                    public_key: signing_key_pub,
                 }],
                 next_rotation: 0,
-            });
-            Ok(())
-        });
+                resource_version: 0,
+            })
+            .await
+            .unwrap();
         let t =
             AccessToken::new(key_store, 30, HashSet::from(["IO".to_owned()]));
-        let access_token = rand::thread_rng()
-            .sample_iter(&rand::distributions::Alphanumeric)
+        let access_token = rand::rng()
+            .sample_iter(&rand::distr::Alphanumeric)
             .take(255)
             .map(char::from)
             .collect::<String>();
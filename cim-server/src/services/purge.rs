@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{Duration, Utc};
+use tracing::info;
+
+use cim_job::Job;
+use cim_slo::Result;
+
+use crate::app::Store;
+
+/// Purger hard deletes the entities soft deleted longer than retention ago,
+/// they can't be restored any more
+pub struct Purger {
+    store: Arc<Store>,
+    retention: Duration,
+}
+
+impl Purger {
+    pub fn new(store: Arc<Store>, retention: Duration) -> Self {
+        Self { store, retention }
+    }
+
+    /// purge returns how many entities were purged
+    pub async fn purge(&self) -> Result<u64> {
+        let before = Utc::now().naive_utc() - self.retention;
+        let purged = self.store.purge(before).await?;
+        info!("purged {} deleted", purged);
+        Ok(purged)
+    }
+}
+
+#[async_trait]
+impl Job for Purger {
+    async fn run(&self) -> Result<()> {
+        self.purge().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cim_storage::{
+        connection_manager, key::KeyRing, user::User, Interface,
+    };
+
+    use super::*;
+    use crate::app::CacheOpts;
+
+    #[tokio::test]
+    async fn purge() {
+        let pool = connection_manager("memory://", 1, 1, true).await.unwrap();
+        let store = Arc::new(Store::new(
+            &pool,
+            CacheOpts::default(),
+            KeyRing::default(),
+        ));
+        for id in ["1", "2"] {
+            store
+                .user
+                .put(&User {
+                    id: id.to_owned(),
+                    password: Some("Pa$$w0rd".to_owned()),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        let user = User {
+            id: "1".to_owned(),
+            ..Default::default()
+        };
+        store.user.delete(&user).await.unwrap();
+
+        // deleted within the retention, it can still be restored
+        let purge = |hours| Purger::new(store.clone(), Duration::hours(hours));
+        assert_eq!(purge(1).purge().await.unwrap(), 0);
+        assert_eq!(purge(-1).purge().await.unwrap(), 1);
+        assert!(store.user.restore(&user).await.is_err());
+    }
+}
@@ -0,0 +1,412 @@
+use cim_slo::{errors, Result};
+use cim_storage::{
+    connector, group, group_user, policy, policy_binding,
+    policy_binding::BindingsType,
+    role, role_binding,
+    snapshot::{Action, Change, Entity, Snapshot},
+    user, Interface, List, Pagination, WatchInterface,
+};
+
+use crate::app::Store;
+
+/// all is every row the store lists with opts
+async fn all<S: Interface>(store: &S, opts: &S::L) -> Result<Vec<S::T>> {
+    let mut list = List::default();
+    store.list(opts, &mut list).await?;
+    Ok(list.data)
+}
+
+fn unpaginated() -> Pagination {
+    Pagination {
+        count_disable: true,
+        ..Default::default()
+    }
+}
+
+/// export snapshots the IAM state of account_id, the passwords of the
+/// users, the secrets of the clients and the configs of the connectors are
+/// left out unless credentials. The connectors are shared by every
+/// account, they are carried only for an operator
+pub async fn export(
+    store: &Store,
+    account_id: &str,
+    credentials: bool,
+    operator: bool,
+) -> Result<Snapshot> {
+    let mut snapshot = Snapshot::new(account_id);
+    let account = Some(account_id.to_owned());
+    snapshot.users = all(
+        &store.user,
+        &user::ListParams {
+            id: None,
+            account_id: account.clone(),
+            group_id: None,
+            effective_group_id: None,
+            label_selector: None,
+            pagination: unpaginated(),
+        },
+    )
+    .await?;
+    snapshot.groups = all(
+        &store.group,
+        &group::ListParams {
+            id: None,
+            account_id: account.clone(),
+            parent_id: None,
+            user_id: None,
+            effective_user_id: None,
+            label_selector: None,
+            pagination: unpaginated(),
+        },
+    )
+    .await?;
+    snapshot.roles = all(
+        &store.role,
+        &role::ListParams {
+            id: None,
+            account_id: account.clone(),
+            user_id: None,
+            label_selector: None,
+            pagination: unpaginated(),
+        },
+    )
+    .await?;
+    snapshot.policies = all(
+        &store.policy,
+        &policy::ListParams {
+            id: None,
+            version: None,
+            account_id: account,
+            group_id: None,
+            user_id: None,
+            role_id: None,
+            label_selector: None,
+            pagination: unpaginated(),
+        },
+    )
+    .await?;
+
+    for group in &snapshot.groups {
+        snapshot.group_users.extend(
+            all(
+                &store.group_user,
+                &group_user::ListParams {
+                    id: None,
+                    account_id: None,
+                    group_id: Some(group.id.clone()),
+                    user_id: None,
+                    expires_before: None,
+                    pagination: unpaginated(),
+                },
+            )
+            .await?,
+        );
+    }
+    for role in &snapshot.roles {
+        snapshot.role_bindings.extend(
+            all(
+                &store.role_binding,
+                &role_binding::ListParams {
+                    id: None,
+                    account_id: None,
+                    role_id: Some(role.id.clone()),
+                    user_type: None,
+                    user_id: None,
+                    expires_before: None,
+                    pagination: unpaginated(),
+                },
+            )
+            .await?,
+        );
+    }
+    // the bindings of the account, which may bind global policies
+    let bound = snapshot
+        .users
+        .iter()
+        .map(|v| (BindingsType::User, &v.id))
+        .chain(snapshot.groups.iter().map(|v| (BindingsType::Group, &v.id)))
+        .chain(snapshot.roles.iter().map(|v| (BindingsType::Role, &v.id)));
+    for (bindings_type, bindings_id) in bound {
+        snapshot.policy_bindings.extend(
+            all(
+                &store.policy_binding,
+                &policy_binding::ListParams {
+                    id: None,
+                    account_id: None,
+                    policy_id: None,
+                    bindings_type: Some(bindings_type),
+                    bindings_id: Some(bindings_id.clone()),
+                    expires_before: None,
+                    pagination: unpaginated(),
+                },
+            )
+            .await?,
+        );
+    }
+
+    snapshot.clients = all(&store.client, &())
+        .await?
+        .into_iter()
+        .filter(|v| v.account_id == account_id)
+        .collect();
+    if operator {
+        snapshot.connectors = all(
+            &store.connector,
+            &connector::ListParams {
+                connector_type: None,
+                pagination: unpaginated(),
+            },
+        )
+        .await?;
+    }
+
+    if credentials {
+        for user in snapshot.users.iter_mut() {
+            store.user.get(user).await?;
+        }
+    } else {
+        for client in snapshot.clients.iter_mut() {
+            client.secret = String::new();
+        }
+        for connector in snapshot.connectors.iter_mut() {
+            connector.config = String::new();
+            connector.connector_data = None;
+        }
+    }
+    Ok(snapshot)
+}
+
+/// plan pairs the entities which change the stored state with their change,
+/// the entities adopt from the stored ones what importing doesn't change
+async fn plan<S>(store: &S, entities: Vec<S::T>) -> Result<Vec<(Change, S::T)>>
+where
+    S: Interface,
+    S::T: Entity,
+{
+    let mut planned = Vec::new();
+    for mut entity in entities {
+        let mut stored = S::T::keyed(entity.id());
+        let stored = match store.get(&mut stored).await {
+            Ok(()) => Some(stored),
+            Err(err) if err.eq(&errors::not_found("")) => None,
+            Err(err) => return Err(err),
+        };
+        if let Some(change) = entity.diff(stored.as_ref())? {
+            planned.push((change, entity));
+        }
+    }
+    Ok(planned)
+}
+
+/// import_watched writes the planned entities of a watched store, the new
+/// ones are created for the watchers to see them added and the others are
+/// put only if they weren't written since planned
+macro_rules! import_watched {
+    ($store:expr, $entities:expr, $changes:ident, $dry_run:expr) => {
+        for (change, entity) in plan(&$store, $entities).await? {
+            if !$dry_run {
+                match change.action {
+                    Action::Create => $store.create(&entity).await?,
+                    Action::Update => {
+                        $store.put_if(&entity, entity.resource_version).await?
+                    }
+                }
+            }
+            $changes.push(change);
+        }
+    };
+}
+
+/// import makes the stored state of the snapshot account match the
+/// snapshot, the entities it doesn't carry are left alone. It's idempotent,
+/// importing a snapshot twice changes nothing the second time. A dry run
+/// writes nothing, it only returns the changes
+///
+/// The store should be one of a transaction, committed once import returns.
+/// Only an operator may import connectors
+pub async fn import(
+    store: &Store,
+    snapshot: Snapshot,
+    dry_run: bool,
+    operator: bool,
+) -> Result<Vec<Change>> {
+    snapshot.check()?;
+    if !operator && !snapshot.connectors.is_empty() {
+        return Err(errors::forbidden(
+            &"only an operator may import connectors",
+        ));
+    }
+    let mut changes = Vec::new();
+    import_watched!(store.user, snapshot.users, changes, dry_run);
+    import_watched!(store.group, snapshot.groups, changes, dry_run);
+    import_watched!(store.group_user, snapshot.group_users, changes, dry_run);
+    import_watched!(store.role, snapshot.roles, changes, dry_run);
+    import_watched!(
+        store.role_binding,
+        snapshot.role_bindings,
+        changes,
+        dry_run
+    );
+    import_watched!(store.policy, snapshot.policies, changes, dry_run);
+    import_watched!(
+        store.policy_binding,
+        snapshot.policy_bindings,
+        changes,
+        dry_run
+    );
+    for (change, client) in plan(&store.client, snapshot.clients).await? {
+        if !dry_run {
+            store.client.put(&client).await?;
+        }
+        changes.push(change);
+    }
+    for (change, connector) in
+        plan(&store.connector, snapshot.connectors).await?
+    {
+        if !dry_run {
+            store.connector.put(&connector).await?;
+        }
+        changes.push(change);
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use cim_storage::{
+        client::Client, connection_manager, connector::Connector, group::Group,
+        group_user::GroupUser, key::KeyRing, user::User,
+    };
+
+    use super::*;
+    use crate::app::CacheOpts;
+
+    #[tokio::test]
+    async fn export_import() {
+        let pool = connection_manager("memory://", 1, 1, true).await.unwrap();
+        let store = Store::new(&pool, CacheOpts::default(), KeyRing::default());
+        store
+            .user
+            .create(&User {
+                id: "1".to_owned(),
+                account_id: "1".to_owned(),
+                password: Some("ag1234567890123456789".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        store
+            .group
+            .create(&Group {
+                id: "2".to_owned(),
+                account_id: "1".to_owned(),
+                name: "admin".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        store
+            .group_user
+            .create(&GroupUser {
+                id: "3".to_owned(),
+                group_id: "2".to_owned(),
+                user_id: "1".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        store
+            .client
+            .put(&Client {
+                id: "4".to_owned(),
+                secret: "secret".to_owned(),
+                account_id: "1".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let snapshot = export(&store, "1", false, false).await.unwrap();
+        assert_eq!(snapshot.users.len(), 1);
+        assert!(snapshot.users[0].password.is_none());
+        assert_eq!(snapshot.group_users.len(), 1);
+        assert!(snapshot.clients[0].secret.is_empty());
+        assert!(import(&store, snapshot, false, false)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // the credentials move the users to another environment
+        let mut snapshot = export(&store, "1", true, false).await.unwrap();
+        assert!(snapshot.users[0].password.is_some());
+        let other = connection_manager("memory://", 1, 1, true).await.unwrap();
+        let other =
+            Store::new(&other, CacheOpts::default(), KeyRing::default());
+        snapshot.groups[0].desc = "admins".to_owned();
+        let changes = import(&other, snapshot, true, false).await.unwrap();
+        assert_eq!(changes.len(), 4);
+        assert!(changes.iter().all(|v| v.action == Action::Create));
+        let mut group = Group {
+            id: "2".to_owned(),
+            ..Default::default()
+        };
+        assert!(other.group.get(&mut group).await.is_err());
+
+        let snapshot = export(&store, "1", true, false).await.unwrap();
+        let changes = import(&other, snapshot, false, false).await.unwrap();
+        assert_eq!(changes.len(), 4);
+        let mut user = User {
+            id: "1".to_owned(),
+            ..Default::default()
+        };
+        other.user.get(&mut user).await.unwrap();
+        let mut stored = user.clone();
+        store.user.get(&mut stored).await.unwrap();
+        assert_eq!(user.password, stored.password);
+
+        let mut snapshot = export(&store, "1", true, false).await.unwrap();
+        assert!(import(&other, snapshot.clone(), false, false)
+            .await
+            .unwrap()
+            .is_empty());
+        snapshot.groups[0].desc = "admins".to_owned();
+        let changes = import(&other, snapshot, false, false).await.unwrap();
+        assert_eq!(
+            changes,
+            vec![Change {
+                kind: "group",
+                id: "2".to_owned(),
+                action: Action::Update,
+                fields: vec!["desc".to_owned()],
+            }]
+        );
+
+        // the connectors go with an operator only, their configs with the
+        // credentials only
+        store
+            .connector
+            .put(&Connector {
+                id: "5".to_owned(),
+                connector_type: "local".to_owned(),
+                config: r#"{"secret":"s"}"#.to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(export(&store, "1", true, false)
+            .await
+            .unwrap()
+            .connectors
+            .is_empty());
+        let snapshot = export(&store, "1", false, true).await.unwrap();
+        assert_eq!(snapshot.connectors.len(), 1);
+        assert!(snapshot.connectors[0].config.is_empty());
+        assert!(import(&store, snapshot.clone(), true, false).await.is_err());
+        assert!(import(&store, snapshot, false, true)
+            .await
+            .unwrap()
+            .is_empty());
+        let snapshot = export(&store, "1", true, true).await.unwrap();
+        assert_eq!(snapshot.connectors[0].config, r#"{"secret":"s"}"#);
+    }
+}
@@ -6,13 +6,19 @@ use serde_json::json;
 use cim_pim::{Effect, JsonCondition, Statement};
 use cim_slo::{errors, next_id, Result};
 use cim_storage::{
-    client, connector, group, group_user, policy, policy_binding, user,
-    Interface, WatchInterface,
+    audit::Trail, client, connector, group, group_user, policy, policy_binding,
+    user, Interface, WatchInterface,
 };
 
 use crate::AppState;
 
-pub async fn create(app: AppState, input: user::Content) -> Result<u64> {
+/// create signs up a user, the changes are audited as made by the new user
+/// from where trail says
+pub async fn create(
+    app: AppState,
+    mut trail: Trail,
+    input: user::Content,
+) -> Result<u64> {
     if let Some(account_id) = &input.account_id {
         let mut user = user::User {
             id: account_id.to_owned(),
@@ -20,7 +26,9 @@ pub async fn create(app: AppState, input: user::Content) -> Result<u64> {
         };
         app.store.user.get(&mut user).await?;
         let id = next_id().map_err(errors::any)?;
-        app.store
+        trail.actor = id.to_string();
+        let (store, tx) = app.store.audited(&trail).await?;
+        store
             .user
             .create(&user::User {
                 id: id.to_string(),
@@ -29,13 +37,19 @@ pub async fn create(app: AppState, input: user::Content) -> Result<u64> {
                 claim: input.claim,
                 secret: None,
                 password: Some(input.password),
+                labels: input.labels,
+                annotations: input.annotations,
                 ..Default::default()
             })
             .await?;
+        tx.commit().await?;
         return Ok(id);
     }
     let user_id = next_id().map_err(errors::any)?;
-    app.store
+    trail.actor = user_id.to_string();
+    // the account with its Admin group and policy is created all or nothing
+    let (store, tx) = app.store.audited(&trail).await?;
+    store
         .user
         .create(&user::User {
             id: user_id.to_string(),
@@ -44,12 +58,14 @@ pub async fn create(app: AppState, input: user::Content) -> Result<u64> {
             claim: input.claim,
             secret: None,
             password: Some(input.password),
+            labels: input.labels,
+            annotations: input.annotations,
             ..Default::default()
         })
         .await?;
 
     let group_id = next_id().map_err(errors::any)?;
-    app.store
+    store
         .group
         .create(&group::Group {
             id: group_id.to_string(),
@@ -61,7 +77,7 @@ pub async fn create(app: AppState, input: user::Content) -> Result<u64> {
         .await?;
 
     let group_user_id = next_id().map_err(errors::any)?;
-    app.store
+    store
         .group_user
         .create(&group_user::GroupUser {
             id: group_user_id.to_string(),
@@ -71,7 +87,7 @@ pub async fn create(app: AppState, input: user::Content) -> Result<u64> {
         })
         .await?;
     let policy_id = next_id().map_err(errors::any)?;
-    app.store
+    store
         .policy
         .create(&policy::Policy {
             id: policy_id.to_string(),
@@ -104,7 +120,7 @@ pub async fn create(app: AppState, input: user::Content) -> Result<u64> {
         .await?;
 
     let policy_binding_id = next_id().map_err(errors::any)?;
-    app.store
+    store
         .policy_binding
         .create(&policy_binding::PolicyBinding {
             id: policy_binding_id.to_string(),
@@ -116,7 +132,7 @@ pub async fn create(app: AppState, input: user::Content) -> Result<u64> {
         .await?;
 
     let connector_id = next_id().map_err(errors::any)?;
-    app.store
+    store
         .connector
         .put(&connector::Connector {
             id: connector_id.to_string(),
@@ -125,16 +141,17 @@ pub async fn create(app: AppState, input: user::Content) -> Result<u64> {
             response_version: "v1.0.0".to_owned(),
             config: "{}".to_owned(),
             connector_data: None,
+            resource_version: 0,
         })
         .await?;
 
     let client_id = next_id().map_err(errors::any)?;
-    let secret = rand::thread_rng()
-        .sample_iter(&rand::distributions::Alphanumeric)
+    let secret = rand::rng()
+        .sample_iter(&rand::distr::Alphanumeric)
         .take(12)
         .map(char::from)
         .collect::<String>();
-    app.store
+    store
         .client
         .put(&client::Client {
             id: client_id.to_string(),
@@ -146,6 +163,7 @@ pub async fn create(app: AppState, input: user::Content) -> Result<u64> {
             ..Default::default()
         })
         .await?;
+    tx.commit().await?;
 
     Ok(user_id)
 }
@@ -8,9 +8,10 @@ use axum::{
         ConnectInfo, FromRequest, FromRequestParts, Query, Request,
         WebSocketUpgrade,
     },
+    response::{IntoResponseParts, ResponseParts},
     Form, Json,
 };
-use http::{header, request::Parts, HeaderMap, HeaderName};
+use http::{header, request::Parts, HeaderMap, HeaderName, HeaderValue};
 use serde::{de::DeserializeOwned, Deserialize};
 use validator::Validate;
 
@@ -75,14 +76,37 @@ where
 #[derive(Deserialize, Validate)]
 pub struct ListWatchParam<T> {
     pub watch: Option<bool>,
+    /// revision a watch resumes after, the `Last-Event-ID` of a reconnecting
+    /// event source takes precedence. Without one only the new events are
+    /// watched, one whose events are gone is answered with 410 Gone
+    pub revision: Option<u64>,
+    /// patch adds to the put events the JSON Patch of the change
+    pub patch: Option<bool>,
     #[serde(flatten)]
     pub param: T,
 }
 
+/// Since is where a watch resumes and what its events carry
+#[derive(Clone, Copy, Debug)]
+pub struct Since {
+    pub revision: u64,
+    pub patch: bool,
+}
+
+/// ListWatch is a list, or a watch of the events after a revision
 pub enum ListWatch<T> {
     List(T),
-    Watch(T),
-    Ws((WebSocketUpgrade, T)),
+    Watch((T, Since)),
+    Ws((WebSocketUpgrade, T, Since)),
+}
+
+impl<T> ListWatch<T> {
+    /// params_mut are the params of the list or the watch
+    pub fn params_mut(&mut self) -> &mut T {
+        match self {
+            Self::List(v) | Self::Watch((v, _)) | Self::Ws((_, v, _)) => v,
+        }
+    }
 }
 
 impl<S, T> FromRequestParts<S> for ListWatch<T>
@@ -99,16 +123,28 @@ where
         let Valid(param) =
             Valid::<ListWatchParam<T>>::from_request_parts(parts, state)
                 .await?;
+        let revision = match parts.headers.get("Last-Event-ID") {
+            Some(id) => {
+                id.to_str().ok().and_then(|v| v.parse().ok()).ok_or_else(
+                    || errors::bad_request("invalid Last-Event-ID"),
+                )?
+            }
+            None => param.revision.unwrap_or_default(),
+        };
+        let since = Since {
+            revision,
+            patch: param.patch.unwrap_or_default(),
+        };
         if header_eq(&parts.headers, header::UPGRADE, "websocket")
             && header_contains(&parts.headers, header::CONNECTION, "upgrade")
         {
             let ws = WebSocketUpgrade::from_request_parts(parts, state)
                 .await
                 .map_err(errors::any)?;
-            return Ok(Self::Ws((ws, param.param)));
+            return Ok(Self::Ws((ws, param.param, since)));
         }
         if param.watch.unwrap_or_default() {
-            return Ok(Self::Watch(param.param));
+            return Ok(Self::Watch((param.param, since)));
         }
         Ok(Self::List(param.param))
     }
@@ -218,3 +254,96 @@ where
         Ok(Self { ip })
     }
 }
+
+/// TraceId is the `X-Trace-Id` of the request, the trace middleware makes
+/// sure every request has one
+pub struct TraceId(pub String);
+
+impl<S> FromRequestParts<S> for TraceId
+where
+    S: Send + Sync,
+{
+    type Rejection = WithBacktrace;
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let trace_id = parts
+            .headers
+            .get("X-Trace-Id")
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        Ok(Self(trace_id.to_owned()))
+    }
+}
+
+/// IfMatch holds the resource versions of the If-Match header, a request
+/// without the header or with `*` matches any version
+pub struct IfMatch(Option<Vec<u64>>);
+
+impl IfMatch {
+    /// check fails with precondition failed when resource_version is not
+    /// one the client asked for
+    pub fn check(&self, resource_version: u64) -> Result<(), WithBacktrace> {
+        match &self.0 {
+            Some(versions) if !versions.contains(&resource_version) => {
+                Err(errors::precondition_failed(&format!(
+                    "resource version is {resource_version}"
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<S> FromRequestParts<S> for IfMatch
+where
+    S: Send + Sync,
+{
+    type Rejection = WithBacktrace;
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let header = match parts.headers.get(header::IF_MATCH) {
+            Some(header) => {
+                header.to_str().map_err(|err| errors::bad_request(&err))?
+            }
+            None => return Ok(Self(None)),
+        };
+        if header.trim() == "*" {
+            return Ok(Self(None));
+        }
+        let mut versions = Vec::new();
+        for tag in header.split(',') {
+            let tag = tag.trim();
+            let tag = tag.strip_prefix("W/").unwrap_or(tag);
+            let version = tag
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .and_then(|v| v.parse().ok())
+                .ok_or_else(|| {
+                    errors::bad_request(&format!("invalid If-Match {tag}"))
+                })?;
+            versions.push(version);
+        }
+        Ok(Self(Some(versions)))
+    }
+}
+
+/// ETag sets the resource version of the response entity as its ETag
+pub struct ETag(pub u64);
+
+impl IntoResponseParts for ETag {
+    type Error = WithBacktrace;
+
+    fn into_response_parts(
+        self,
+        mut res: ResponseParts,
+    ) -> Result<ResponseParts, Self::Error> {
+        let value = HeaderValue::from_str(&format!("\"{}\"", self.0))
+            .map_err(errors::any)?;
+        res.headers_mut().insert(header::ETAG, value);
+        Ok(res)
+    }
+}
@@ -15,4 +15,14 @@ lazy_static::lazy_static! {
         &["method", "path"],
         vec![0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]).unwrap();
 
+    pub static ref OIDC_GC_COLLECTED_TOTAL: IntCounterVec=register_int_counter_vec!(
+        "oidc_gc_collected_total",
+        "Total number of expired oidc artifacts removed",
+        &["kind"]).unwrap();
+
+    pub static ref BINDINGS_EXPIRED_TOTAL: IntCounterVec=register_int_counter_vec!(
+        "bindings_expired_total",
+        "Total number of bindings deleted past their expiry",
+        &["kind"]).unwrap();
+
 }
@@ -17,6 +17,12 @@ pub fn encrypt(password: &str, secret: &str) -> Result<String> {
         .map_err(errors::any)
 }
 
+/// is_encoded reports whether password is an argon2 hash already, no
+/// password passing `check_password` is one as it starts with a letter
+pub fn is_encoded(password: &str) -> bool {
+    password.starts_with("$argon2")
+}
+
 pub fn verify(encoded: &str, pwd: &str, secret: &str) -> Result<bool> {
     argon2::verify_encoded_ext(encoded, pwd.as_bytes(), secret.as_bytes(), &[])
         .map_err(errors::any)
@@ -36,6 +42,8 @@ mod tests {
             .collect::<String>();
         let h = encrypt(&password, &secret).unwrap();
         assert_eq!(h.len(), 415);
-        assert!(verify(&h, &password, &secret).unwrap())
+        assert!(verify(&h, &password, &secret).unwrap());
+        assert!(is_encoded(&h));
+        assert!(!is_encoded(&password));
     }
 }
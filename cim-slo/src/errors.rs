@@ -22,6 +22,12 @@ pub enum Code {
     Validates(#[source] validator::ValidationErrors),
     #[error("Please recheck the request.see: {0}")]
     BadRequest(String),
+    #[error("Conflict. {0}")]
+    Conflict(String),
+    #[error("Precondition failed. {0}")]
+    PreconditionFailed(String),
+    #[error("Gone. {0}")]
+    Gone(String),
 }
 
 impl ErrorCode for Code {
@@ -33,6 +39,11 @@ impl ErrorCode for Code {
             Self::Validates(_) => (StatusCode::UNPROCESSABLE_ENTITY, "1010004"),
             Self::Forbidden(_) => (StatusCode::FORBIDDEN, "1010005"),
             Self::BadRequest(_) => (StatusCode::BAD_REQUEST, "1010006"),
+            Self::Conflict(_) => (StatusCode::CONFLICT, "1010007"),
+            Self::PreconditionFailed(_) => {
+                (StatusCode::PRECONDITION_FAILED, "1010008")
+            }
+            Self::Gone(_) => (StatusCode::GONE, "1010009"),
         }
     }
 }
@@ -89,7 +100,7 @@ impl PartialEq for WithBacktrace {
 #[inline]
 pub fn any<E: StdError>(err: E) -> WithBacktrace {
     WithBacktrace {
-        source: Code::Any(anyhow::anyhow!("{}", err.to_string())),
+        source: Code::Any(anyhow::anyhow!("{}", err)),
         backtrace: Backtrace::new(),
     }
 }
@@ -134,6 +145,30 @@ pub fn bad_request<S: ToString + ?Sized>(err: &S) -> WithBacktrace {
     }
 }
 
+#[inline]
+pub fn conflict<S: ToString + ?Sized>(err: &S) -> WithBacktrace {
+    WithBacktrace {
+        source: Code::Conflict(err.to_string()),
+        backtrace: Backtrace::new(),
+    }
+}
+
+#[inline]
+pub fn precondition_failed<S: ToString + ?Sized>(err: &S) -> WithBacktrace {
+    WithBacktrace {
+        source: Code::PreconditionFailed(err.to_string()),
+        backtrace: Backtrace::new(),
+    }
+}
+
+#[inline]
+pub fn gone<S: ToString + ?Sized>(err: &S) -> WithBacktrace {
+    WithBacktrace {
+        source: Code::Gone(err.to_string()),
+        backtrace: Backtrace::new(),
+    }
+}
+
 #[cfg(feature = "axum-resp")]
 mod axum {
     use axum::response::IntoResponse;
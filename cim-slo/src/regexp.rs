@@ -9,6 +9,15 @@ lazy_static::lazy_static! {
     static ref PASSWORD_REGEX: Regex = Regex::new(
         r"^[a-zA-Z][a-zA-Z0-9_#@\$]{14,254}$",
     ).unwrap();
+
+    // [prefix/]name, the prefix is a dns subdomain
+    static ref LABEL_KEY_REGEX: Regex = Regex::new(
+        r"^([a-z0-9]([-a-z0-9]*[a-z0-9])?(\.[a-z0-9]([-a-z0-9]*[a-z0-9])?)*/)?[A-Za-z0-9]([-A-Za-z0-9_.]*[A-Za-z0-9])?$",
+    ).unwrap();
+
+    static ref LABEL_VALUE_REGEX: Regex = Regex::new(
+        r"^([A-Za-z0-9]([-A-Za-z0-9_.]*[A-Za-z0-9])?)?$",
+    ).unwrap();
 }
 
 // 以字母开头，需要包含数字，字母，特殊字符（_,#,@,$）之一，长度不少于15位，最大不超过255位
@@ -25,3 +34,21 @@ pub fn check_order_by(sort: &str) -> Result<(), ValidationError> {
     }
     Err(ValidationError::new("invalid order by"))
 }
+
+// 标签键为可选的dns子域名前缀（不超过253位）加名称（不超过63位），以字母或数字开头和结尾
+pub fn check_label_key(key: &str) -> Result<(), ValidationError> {
+    let (prefix, name) = key.rsplit_once('/').unwrap_or(("", key));
+    if prefix.len() <= 253 && name.len() <= 63 && LABEL_KEY_REGEX.is_match(key)
+    {
+        return Ok(());
+    }
+    Err(ValidationError::new("invalid label key"))
+}
+
+// 标签值可以为空，不超过63位，以字母或数字开头和结尾
+pub fn check_label_value(value: &str) -> Result<(), ValidationError> {
+    if value.len() <= 63 && LABEL_VALUE_REGEX.is_match(value) {
+        return Ok(());
+    }
+    Err(ValidationError::new("invalid label value"))
+}
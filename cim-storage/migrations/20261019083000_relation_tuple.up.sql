@@ -0,0 +1,16 @@
+-- Add up migration script here
+CREATE TABLE `relation_tuple` (
+    `id` BIGINT(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'relation_tuple ID',
+    `account_id` BIGINT(20) UNSIGNED NOT NULL COMMENT 'account id',
+    `object` VARCHAR(255) NOT NULL COMMENT 'object namespace:id',
+    `relation` VARCHAR(64) NOT NULL COMMENT 'relation name',
+    `subject` VARCHAR(255) NOT NULL COMMENT 'subject id or subject set namespace:id#relation',
+    `deleted` BIGINT(20) UNSIGNED NOT NULL DEFAULT '0' COMMENT 'soft delete flag',
+    `created_at` DATETIME(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT 'create time',
+    `updated_at` DATETIME(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) ON UPDATE CURRENT_TIMESTAMP(3) COMMENT 'update time',
+    `deleted_at` DATETIME(3) NULL DEFAULT NULL COMMENT 'delete time',
+    PRIMARY KEY (`id`),
+    UNIQUE `idx_object_relation_subject_deleted` (`object`, `relation`, `subject`, `deleted`) USING BTREE,
+    INDEX `idx_subject` (`subject`) USING BTREE,
+    INDEX `idx_deleted` (`deleted`) USING BTREE
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_general_ci COMMENT = 'relation_tuple info';
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS `policy_binding`;
+DROP TABLE IF EXISTS `role_binding`;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- The init migration creates `role_binding` and `policy_binding` but its down
+-- script drops `role_bindings` and `policy_bindings`, so the down script of
+-- this migration drops the tables under their real names first.
+SELECT 1;
@@ -0,0 +1,9 @@
+-- Add down migration script here
+ALTER TABLE `user` DROP COLUMN `resource_version`;
+ALTER TABLE `group` DROP COLUMN `resource_version`;
+ALTER TABLE `role` DROP COLUMN `resource_version`;
+ALTER TABLE `policy` DROP COLUMN `resource_version`;
+ALTER TABLE `group_user` DROP COLUMN `resource_version`;
+ALTER TABLE `role_binding` DROP COLUMN `resource_version`;
+ALTER TABLE `policy_binding` DROP COLUMN `resource_version`;
+ALTER TABLE `relation_tuple` DROP COLUMN `resource_version`;
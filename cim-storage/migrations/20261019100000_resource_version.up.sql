@@ -0,0 +1,9 @@
+-- Add up migration script here
+ALTER TABLE `user` ADD COLUMN `resource_version` BIGINT(20) UNSIGNED NOT NULL DEFAULT '1' COMMENT 'resource version' AFTER `deleted`;
+ALTER TABLE `group` ADD COLUMN `resource_version` BIGINT(20) UNSIGNED NOT NULL DEFAULT '1' COMMENT 'resource version' AFTER `deleted`;
+ALTER TABLE `role` ADD COLUMN `resource_version` BIGINT(20) UNSIGNED NOT NULL DEFAULT '1' COMMENT 'resource version' AFTER `deleted`;
+ALTER TABLE `policy` ADD COLUMN `resource_version` BIGINT(20) UNSIGNED NOT NULL DEFAULT '1' COMMENT 'resource version' AFTER `deleted`;
+ALTER TABLE `group_user` ADD COLUMN `resource_version` BIGINT(20) UNSIGNED NOT NULL DEFAULT '1' COMMENT 'resource version' AFTER `deleted`;
+ALTER TABLE `role_binding` ADD COLUMN `resource_version` BIGINT(20) UNSIGNED NOT NULL DEFAULT '1' COMMENT 'resource version' AFTER `deleted`;
+ALTER TABLE `policy_binding` ADD COLUMN `resource_version` BIGINT(20) UNSIGNED NOT NULL DEFAULT '1' COMMENT 'resource version' AFTER `deleted`;
+ALTER TABLE `relation_tuple` ADD COLUMN `resource_version` BIGINT(20) UNSIGNED NOT NULL DEFAULT '1' COMMENT 'resource version' AFTER `deleted`;
@@ -0,0 +1,9 @@
+-- Add up migration script here
+CREATE TABLE `changelog` (
+    `id` BIGINT(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'change sequence',
+    `kind` VARCHAR(64) NOT NULL COMMENT 'table the change belongs to',
+    `payload` MEDIUMTEXT NOT NULL COMMENT 'json encoded event',
+    `created_at` DATETIME(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT 'create time',
+    PRIMARY KEY (`id`),
+    INDEX `idx_created_at` (`created_at`) USING BTREE
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_general_ci COMMENT = 'outbox of store events';
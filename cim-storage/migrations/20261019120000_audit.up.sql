@@ -0,0 +1,17 @@
+-- Add up migration script here
+CREATE TABLE `audit` (
+    `id` BIGINT(20) UNSIGNED NOT NULL AUTO_INCREMENT COMMENT 'audit ID',
+    `actor` VARCHAR(255) NOT NULL COMMENT 'user who made the change',
+    `trace_id` VARCHAR(255) NOT NULL COMMENT 'trace id of the request',
+    `source_ip` VARCHAR(64) NOT NULL COMMENT 'client ip of the request',
+    `kind` VARCHAR(64) NOT NULL COMMENT 'table of the entity',
+    `entity_id` VARCHAR(255) NOT NULL COMMENT 'id of the entity',
+    `action` VARCHAR(16) NOT NULL COMMENT 'create, put or delete',
+    `before` LONGTEXT NULL COMMENT 'entity before the change' CHECK (json_valid(`before`)),
+    `after` LONGTEXT NULL COMMENT 'entity after the change' CHECK (json_valid(`after`)),
+    `created_at` DATETIME(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3) COMMENT 'create time',
+    PRIMARY KEY (`id`),
+    INDEX `idx_actor` (`actor`) USING BTREE,
+    INDEX `idx_kind_entity_id` (`kind`, `entity_id`) USING BTREE,
+    INDEX `idx_created_at` (`created_at`) USING BTREE
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_general_ci COMMENT = 'audit log of the changes';
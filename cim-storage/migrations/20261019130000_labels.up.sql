@@ -0,0 +1,20 @@
+-- Add up migration script here
+ALTER TABLE `user` ADD COLUMN `labels` LONGTEXT NOT NULL DEFAULT '{}' COMMENT 'labels' CHECK (json_valid(`labels`)) AFTER `resource_version`,
+    ADD COLUMN `annotations` LONGTEXT NOT NULL DEFAULT '{}' COMMENT 'annotations' CHECK (json_valid(`annotations`)) AFTER `labels`;
+ALTER TABLE `group` ADD COLUMN `labels` LONGTEXT NOT NULL DEFAULT '{}' COMMENT 'labels' CHECK (json_valid(`labels`)) AFTER `resource_version`,
+    ADD COLUMN `annotations` LONGTEXT NOT NULL DEFAULT '{}' COMMENT 'annotations' CHECK (json_valid(`annotations`)) AFTER `labels`;
+ALTER TABLE `role` ADD COLUMN `labels` LONGTEXT NOT NULL DEFAULT '{}' COMMENT 'labels' CHECK (json_valid(`labels`)) AFTER `resource_version`,
+    ADD COLUMN `annotations` LONGTEXT NOT NULL DEFAULT '{}' COMMENT 'annotations' CHECK (json_valid(`annotations`)) AFTER `labels`;
+ALTER TABLE `policy` ADD COLUMN `labels` LONGTEXT NOT NULL DEFAULT '{}' COMMENT 'labels' CHECK (json_valid(`labels`)) AFTER `resource_version`,
+    ADD COLUMN `annotations` LONGTEXT NOT NULL DEFAULT '{}' COMMENT 'annotations' CHECK (json_valid(`annotations`)) AFTER `labels`;
+ALTER TABLE `client` ADD COLUMN `labels` LONGTEXT NOT NULL DEFAULT '{}' COMMENT 'labels' CHECK (json_valid(`labels`)) AFTER `account_id`,
+    ADD COLUMN `annotations` LONGTEXT NOT NULL DEFAULT '{}' COMMENT 'annotations' CHECK (json_valid(`annotations`)) AFTER `labels`;
+
+CREATE TABLE `label` (
+    `kind` VARCHAR(64) NOT NULL COMMENT 'table of the entity',
+    `entity_id` BIGINT(20) UNSIGNED NOT NULL COMMENT 'id of the entity',
+    `name` VARCHAR(317) NOT NULL COMMENT 'label key',
+    `value` VARCHAR(63) NOT NULL COMMENT 'label value',
+    PRIMARY KEY (`kind`, `entity_id`, `name`),
+    INDEX `idx_kind_name_value` (`kind`, `name`, `value`) USING BTREE
+) ENGINE = InnoDB DEFAULT CHARSET = utf8mb4 COLLATE = utf8mb4_general_ci COMMENT = 'label index of the entities';
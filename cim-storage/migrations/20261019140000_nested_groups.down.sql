@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE `group` DROP INDEX `idx_parent_id`, DROP COLUMN `parent_id`;
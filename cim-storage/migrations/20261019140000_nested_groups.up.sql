@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE `group` ADD COLUMN `parent_id` BIGINT(20) UNSIGNED NULL DEFAULT NULL COMMENT 'id of the group it is nested in' AFTER `desc`,
+    ADD INDEX `idx_parent_id` (`parent_id`) USING BTREE;
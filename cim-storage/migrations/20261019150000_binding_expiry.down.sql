@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE `group_user` DROP INDEX `idx_expires_at`, DROP COLUMN `expires_at`, DROP COLUMN `not_before`;
+ALTER TABLE `role_binding` DROP INDEX `idx_expires_at`, DROP COLUMN `expires_at`, DROP COLUMN `not_before`;
+ALTER TABLE `policy_binding` DROP INDEX `idx_expires_at`, DROP COLUMN `expires_at`, DROP COLUMN `not_before`;
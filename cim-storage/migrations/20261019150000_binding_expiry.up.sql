@@ -0,0 +1,10 @@
+-- Add up migration script here
+ALTER TABLE `group_user` ADD COLUMN `not_before` DATETIME(3) NULL DEFAULT NULL COMMENT 'time the binding takes effect' AFTER `user_id`,
+    ADD COLUMN `expires_at` DATETIME(3) NULL DEFAULT NULL COMMENT 'time the binding expires' AFTER `not_before`,
+    ADD INDEX `idx_expires_at` (`expires_at`) USING BTREE;
+ALTER TABLE `role_binding` ADD COLUMN `not_before` DATETIME(3) NULL DEFAULT NULL COMMENT 'time the binding takes effect' AFTER `user_id`,
+    ADD COLUMN `expires_at` DATETIME(3) NULL DEFAULT NULL COMMENT 'time the binding expires' AFTER `not_before`,
+    ADD INDEX `idx_expires_at` (`expires_at`) USING BTREE;
+ALTER TABLE `policy_binding` ADD COLUMN `not_before` DATETIME(3) NULL DEFAULT NULL COMMENT 'time the binding takes effect' AFTER `bindings_id`,
+    ADD COLUMN `expires_at` DATETIME(3) NULL DEFAULT NULL COMMENT 'time the binding expires' AFTER `not_before`,
+    ADD INDEX `idx_expires_at` (`expires_at`) USING BTREE;
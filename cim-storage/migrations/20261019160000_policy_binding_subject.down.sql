@@ -0,0 +1,3 @@
+-- Add down migration script here
+DELETE FROM `policy_binding` WHERE `bindings_type` > 3;
+ALTER TABLE `policy_binding` MODIFY `bindings_id` BIGINT(20) NOT NULL COMMENT 'be bindings object id';
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE `policy_binding` MODIFY `bindings_id` VARCHAR(255) NOT NULL COMMENT 'be bindings object id, or the subject of an application or federated user';
@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE `client` DROP COLUMN `resource_version`;
+ALTER TABLE `connector` DROP COLUMN `resource_version`;
+ALTER TABLE `connector` CHANGE COLUMN `response_version` `resource_version` TEXT NOT NULL;
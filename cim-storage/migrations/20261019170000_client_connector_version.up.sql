@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE `connector` CHANGE COLUMN `resource_version` `response_version` TEXT NOT NULL;
+ALTER TABLE `connector` ADD COLUMN `resource_version` BIGINT(20) UNSIGNED NOT NULL DEFAULT '1' COMMENT 'resource version' AFTER `deleted`;
+ALTER TABLE `client` ADD COLUMN `resource_version` BIGINT(20) UNSIGNED NOT NULL DEFAULT '1' COMMENT 'resource version' AFTER `deleted`;
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE `key` ADD COLUMN `resource_version` BIGINT(20) UNSIGNED NOT NULL DEFAULT '1' COMMENT 'resource version' AFTER `deleted`;
@@ -0,0 +1,16 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS "user";
+DROP TABLE IF EXISTS "policy";
+DROP TABLE IF EXISTS "role";
+DROP TABLE IF EXISTS "group";
+DROP TABLE IF EXISTS "role_binding";
+DROP TABLE IF EXISTS "policy_binding";
+DROP TABLE IF EXISTS "group_user";
+DROP TABLE IF EXISTS "client";
+DROP TABLE IF EXISTS "auth_request";
+DROP TABLE IF EXISTS "auth_code";
+DROP TABLE IF EXISTS "refresh_token";
+DROP TABLE IF EXISTS "key";
+DROP TABLE IF EXISTS "offline_session";
+DROP TABLE IF EXISTS "connector";
+DROP FUNCTION IF EXISTS set_updated_at;
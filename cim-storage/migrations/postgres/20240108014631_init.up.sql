@@ -0,0 +1,253 @@
+-- Add up migration script here
+CREATE FUNCTION set_updated_at() RETURNS TRIGGER AS $$
+BEGIN
+    NEW."updated_at" = CURRENT_TIMESTAMP(3);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TABLE "user" (
+    "id" BIGINT NOT NULL PRIMARY KEY,
+    "account_id" BIGINT NOT NULL,
+    "desc" VARCHAR(255) NOT NULL,
+    "email" VARCHAR(255) NULL DEFAULT NULL,
+    "email_verified" BOOLEAN NULL DEFAULT NULL,
+    "name" VARCHAR(255) NULL DEFAULT NULL,
+    "given_name" VARCHAR(255) NULL DEFAULT NULL,
+    "family_name" VARCHAR(255) NULL DEFAULT NULL,
+    "middle_name" VARCHAR(255) NULL DEFAULT NULL,
+    "nickname" VARCHAR(255) NULL DEFAULT NULL,
+    "preferred_username" VARCHAR(255) NULL DEFAULT NULL,
+    "profile" VARCHAR(255) NULL DEFAULT NULL,
+    "picture" VARCHAR(255) NULL DEFAULT NULL,
+    "website" VARCHAR(255) NULL DEFAULT NULL,
+    "gender" VARCHAR(10) NULL DEFAULT NULL,
+    "birthday" VARCHAR(255) NULL DEFAULT NULL,
+    "birthdate" VARCHAR(255) NULL DEFAULT NULL,
+    "zoneinfo" VARCHAR(255) NULL DEFAULT NULL,
+    "locale" VARCHAR(255) NULL DEFAULT NULL,
+    "phone_number" VARCHAR(255) NULL DEFAULT NULL,
+    "phone_number_verified" BOOLEAN NULL DEFAULT NULL,
+    "address" TEXT NULL DEFAULT NULL,
+    "secret" CHAR(64) NOT NULL,
+    "password" TEXT NOT NULL,
+    "deleted" BIGINT NOT NULL DEFAULT 0,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "updated_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "deleted_at" TIMESTAMP(3) NULL DEFAULT NULL
+);
+CREATE INDEX "idx_user_deleted" ON "user" ("deleted");
+CREATE TRIGGER "user_updated_at" BEFORE UPDATE ON "user"
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+CREATE TABLE "policy" (
+    "id" BIGINT NOT NULL PRIMARY KEY,
+    "account_id" BIGINT NULL,
+    "desc" VARCHAR(255) NOT NULL,
+    "version" VARCHAR(255) NOT NULL,
+    "statement" JSONB NOT NULL,
+    "deleted" BIGINT NOT NULL DEFAULT 0,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "updated_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "deleted_at" TIMESTAMP(3) NULL DEFAULT NULL
+);
+CREATE INDEX "idx_policy_deleted" ON "policy" ("deleted");
+CREATE TRIGGER "policy_updated_at" BEFORE UPDATE ON "policy"
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+CREATE TABLE "role" (
+    "id" BIGINT NOT NULL PRIMARY KEY,
+    "name" VARCHAR(255) NOT NULL,
+    "desc" VARCHAR(255) NOT NULL,
+    "account_id" BIGINT NOT NULL,
+    "deleted" BIGINT NOT NULL DEFAULT 0,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "updated_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "deleted_at" TIMESTAMP(3) NULL DEFAULT NULL
+);
+CREATE INDEX "idx_role_deleted" ON "role" ("deleted");
+CREATE TRIGGER "role_updated_at" BEFORE UPDATE ON "role"
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+CREATE TABLE "group" (
+    "id" BIGINT NOT NULL PRIMARY KEY,
+    "name" VARCHAR(255) NOT NULL,
+    "desc" VARCHAR(255) NOT NULL,
+    "account_id" BIGINT NOT NULL,
+    "deleted" BIGINT NOT NULL DEFAULT 0,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "updated_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "deleted_at" TIMESTAMP(3) NULL DEFAULT NULL
+);
+CREATE INDEX "idx_group_deleted" ON "group" ("deleted");
+CREATE TRIGGER "group_updated_at" BEFORE UPDATE ON "group"
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+CREATE TABLE "role_binding" (
+    "id" BIGINT NOT NULL PRIMARY KEY,
+    "role_id" BIGINT NOT NULL,
+    "user_type" SMALLINT NOT NULL,
+    "user_id" VARCHAR(255) NOT NULL,
+    "deleted" BIGINT NOT NULL DEFAULT 0,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "updated_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "deleted_at" TIMESTAMP(3) NULL DEFAULT NULL
+);
+CREATE UNIQUE INDEX "idx_role_binding_role_id_user_type_user_id_deleted" ON "role_binding" ("role_id", "user_type", "user_id", "deleted");
+CREATE INDEX "idx_role_binding_deleted" ON "role_binding" ("deleted");
+CREATE TRIGGER "role_binding_updated_at" BEFORE UPDATE ON "role_binding"
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+CREATE TABLE "policy_binding" (
+    "id" BIGINT NOT NULL PRIMARY KEY,
+    "policy_id" BIGINT NOT NULL,
+    "bindings_type" SMALLINT NOT NULL,
+    "bindings_id" BIGINT NOT NULL,
+    "deleted" BIGINT NOT NULL DEFAULT 0,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "updated_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "deleted_at" TIMESTAMP(3) NULL DEFAULT NULL
+);
+CREATE UNIQUE INDEX "idx_policy_binding_policy_id_bindings_type_bindings_id_deleted" ON "policy_binding" ("policy_id", "bindings_type", "bindings_id", "deleted");
+CREATE INDEX "idx_policy_binding_deleted" ON "policy_binding" ("deleted");
+CREATE TRIGGER "policy_binding_updated_at" BEFORE UPDATE ON "policy_binding"
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+CREATE TABLE "group_user" (
+    "id" BIGINT NOT NULL PRIMARY KEY,
+    "group_id" BIGINT NOT NULL,
+    "user_id" BIGINT NOT NULL,
+    "deleted" BIGINT NOT NULL DEFAULT 0,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "updated_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "deleted_at" TIMESTAMP(3) NULL DEFAULT NULL
+);
+CREATE UNIQUE INDEX "idx_group_user_group_id_user_id_deleted" ON "group_user" ("group_id", "user_id", "deleted");
+CREATE INDEX "idx_group_user_deleted" ON "group_user" ("deleted");
+CREATE TRIGGER "group_user_updated_at" BEFORE UPDATE ON "group_user"
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+CREATE TABLE "client" (
+    "id" BIGINT NOT NULL PRIMARY KEY,
+    "secret" TEXT NOT NULL,
+    "redirect_uris" JSONB NOT NULL,
+    "trusted_peers" JSONB NOT NULL,
+    "name" VARCHAR(255) NOT NULL,
+    "logo_url" TEXT NOT NULL,
+    "account_id" VARCHAR(255) NOT NULL DEFAULT '',
+    "deleted" BIGINT NOT NULL DEFAULT 0,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "updated_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "deleted_at" TIMESTAMP(3) NULL DEFAULT NULL
+);
+CREATE UNIQUE INDEX "idx_client_name_deleted" ON "client" ("name", "deleted");
+CREATE INDEX "idx_client_deleted" ON "client" ("deleted");
+CREATE TRIGGER "client_updated_at" BEFORE UPDATE ON "client"
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+CREATE TABLE "auth_request" (
+    "id" CHAR(36) NOT NULL PRIMARY KEY,
+    "client_id" BIGINT NOT NULL,
+    "response_types" JSONB NOT NULL,
+    "scopes" JSONB NOT NULL,
+    "redirect_uri" TEXT NOT NULL,
+    "code_challenge" TEXT NOT NULL DEFAULT '',
+    "code_challenge_method" TEXT NOT NULL DEFAULT '',
+    "nonce" TEXT NOT NULL,
+    "state" VARCHAR(4096),
+    "claim" JSONB NOT NULL,
+    "connector_id" TEXT NOT NULL,
+    "connector_data" TEXT,
+    "expiry" BIGINT NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "updated_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "deleted_at" TIMESTAMP(3) NULL DEFAULT NULL
+);
+CREATE TRIGGER "auth_request_updated_at" BEFORE UPDATE ON "auth_request"
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+CREATE TABLE "auth_code" (
+    "id" CHAR(36) NOT NULL PRIMARY KEY,
+    "client_id" BIGINT NOT NULL,
+    "scopes" JSONB NOT NULL,
+    "nonce" TEXT NOT NULL,
+    "redirect_uri" TEXT NOT NULL,
+    "code_challenge" TEXT NOT NULL DEFAULT '',
+    "code_challenge_method" TEXT NOT NULL DEFAULT '',
+    "claim" JSONB NOT NULL,
+    "connector_id" TEXT NOT NULL,
+    "connector_data" TEXT,
+    "expiry" BIGINT NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "updated_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "deleted_at" TIMESTAMP(3) NULL DEFAULT NULL
+);
+CREATE TRIGGER "auth_code_updated_at" BEFORE UPDATE ON "auth_code"
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+CREATE TABLE "refresh_token" (
+    "id" BIGINT NOT NULL PRIMARY KEY,
+    "client_id" BIGINT NOT NULL,
+    "scopes" JSONB NOT NULL,
+    "nonce" TEXT NOT NULL,
+    "token" TEXT NOT NULL DEFAULT '',
+    "obsolete_token" TEXT NOT NULL DEFAULT '',
+    "claim" JSONB NOT NULL,
+    "connector_id" TEXT NOT NULL,
+    "connector_data" TEXT,
+    "last_used_at" TIMESTAMP(3) NOT NULL,
+    "deleted" BIGINT NOT NULL DEFAULT 0,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "updated_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "deleted_at" TIMESTAMP(3) NULL DEFAULT NULL
+);
+CREATE INDEX "idx_refresh_token_deleted" ON "refresh_token" ("deleted");
+CREATE TRIGGER "refresh_token_updated_at" BEFORE UPDATE ON "refresh_token"
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+CREATE TABLE "key" (
+    "id" BIGINT NOT NULL PRIMARY KEY,
+    "verification_keys" JSONB NOT NULL,
+    "signing_key" JSONB NOT NULL,
+    "signing_key_pub" JSONB NOT NULL,
+    "next_rotation" BIGINT NOT NULL,
+    "deleted" BIGINT NOT NULL DEFAULT 0,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "updated_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "deleted_at" TIMESTAMP(3) NULL DEFAULT NULL
+);
+CREATE INDEX "idx_key_deleted" ON "key" ("deleted");
+CREATE TRIGGER "key_updated_at" BEFORE UPDATE ON "key"
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+CREATE TABLE "offline_session" (
+    "id" BIGINT NOT NULL PRIMARY KEY,
+    "user_id" VARCHAR(255) NOT NULL,
+    "conn_id" VARCHAR(255) NOT NULL,
+    "refresh" JSONB NOT NULL,
+    "connector_data" TEXT,
+    "deleted" BIGINT NOT NULL DEFAULT 0,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "updated_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "deleted_at" TIMESTAMP(3) NULL DEFAULT NULL
+);
+CREATE UNIQUE INDEX "idx_offline_session_user_id_conn_id" ON "offline_session" ("user_id", "conn_id");
+CREATE INDEX "idx_offline_session_deleted" ON "offline_session" ("deleted");
+CREATE TRIGGER "offline_session_updated_at" BEFORE UPDATE ON "offline_session"
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
+
+CREATE TABLE "connector" (
+    "id" BIGINT NOT NULL PRIMARY KEY,
+    "type" VARCHAR(255) NOT NULL,
+    "name" TEXT NOT NULL,
+    "resource_version" TEXT NOT NULL,
+    "config" TEXT,
+    "connector_data" TEXT,
+    "deleted" BIGINT NOT NULL DEFAULT 0,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "updated_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "deleted_at" TIMESTAMP(3) NULL DEFAULT NULL
+);
+CREATE INDEX "idx_connector_deleted" ON "connector" ("deleted");
+CREATE TRIGGER "connector_updated_at" BEFORE UPDATE ON "connector"
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
@@ -0,0 +1,17 @@
+-- Add up migration script here
+CREATE TABLE "relation_tuple" (
+    "id" BIGINT NOT NULL PRIMARY KEY,
+    "account_id" BIGINT NOT NULL,
+    "object" VARCHAR(255) NOT NULL,
+    "relation" VARCHAR(64) NOT NULL,
+    "subject" VARCHAR(255) NOT NULL,
+    "deleted" BIGINT NOT NULL DEFAULT 0,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "updated_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3),
+    "deleted_at" TIMESTAMP(3) NULL DEFAULT NULL
+);
+CREATE UNIQUE INDEX "idx_relation_tuple_object_relation_subject_deleted" ON "relation_tuple" ("object", "relation", "subject", "deleted");
+CREATE INDEX "idx_relation_tuple_subject" ON "relation_tuple" ("subject");
+CREATE INDEX "idx_relation_tuple_deleted" ON "relation_tuple" ("deleted");
+CREATE TRIGGER "relation_tuple_updated_at" BEFORE UPDATE ON "relation_tuple"
+FOR EACH ROW EXECUTE FUNCTION set_updated_at();
@@ -0,0 +1,8 @@
+-- Add up migration script here
+CREATE TABLE "changelog" (
+    "id" BIGSERIAL NOT NULL PRIMARY KEY,
+    "kind" VARCHAR(64) NOT NULL,
+    "payload" TEXT NOT NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3)
+);
+CREATE INDEX "idx_changelog_created_at" ON "changelog" ("created_at");
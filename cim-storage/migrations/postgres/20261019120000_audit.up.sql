@@ -0,0 +1,16 @@
+-- Add up migration script here
+CREATE TABLE "audit" (
+    "id" BIGSERIAL NOT NULL PRIMARY KEY,
+    "actor" VARCHAR(255) NOT NULL,
+    "trace_id" VARCHAR(255) NOT NULL,
+    "source_ip" VARCHAR(64) NOT NULL,
+    "kind" VARCHAR(64) NOT NULL,
+    "entity_id" VARCHAR(255) NOT NULL,
+    "action" VARCHAR(16) NOT NULL,
+    "before" JSONB NULL,
+    "after" JSONB NULL,
+    "created_at" TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP(3)
+);
+CREATE INDEX "idx_audit_actor" ON "audit" ("actor");
+CREATE INDEX "idx_audit_kind_entity_id" ON "audit" ("kind", "entity_id");
+CREATE INDEX "idx_audit_created_at" ON "audit" ("created_at");
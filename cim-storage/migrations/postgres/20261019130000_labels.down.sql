@@ -0,0 +1,7 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS "label";
+ALTER TABLE "user" DROP COLUMN "labels", DROP COLUMN "annotations";
+ALTER TABLE "group" DROP COLUMN "labels", DROP COLUMN "annotations";
+ALTER TABLE "role" DROP COLUMN "labels", DROP COLUMN "annotations";
+ALTER TABLE "policy" DROP COLUMN "labels", DROP COLUMN "annotations";
+ALTER TABLE "client" DROP COLUMN "labels", DROP COLUMN "annotations";
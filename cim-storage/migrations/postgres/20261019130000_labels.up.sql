@@ -0,0 +1,15 @@
+-- Add up migration script here
+ALTER TABLE "user" ADD COLUMN "labels" JSONB NOT NULL DEFAULT '{}', ADD COLUMN "annotations" JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE "group" ADD COLUMN "labels" JSONB NOT NULL DEFAULT '{}', ADD COLUMN "annotations" JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE "role" ADD COLUMN "labels" JSONB NOT NULL DEFAULT '{}', ADD COLUMN "annotations" JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE "policy" ADD COLUMN "labels" JSONB NOT NULL DEFAULT '{}', ADD COLUMN "annotations" JSONB NOT NULL DEFAULT '{}';
+ALTER TABLE "client" ADD COLUMN "labels" JSONB NOT NULL DEFAULT '{}', ADD COLUMN "annotations" JSONB NOT NULL DEFAULT '{}';
+
+CREATE TABLE "label" (
+    "kind" VARCHAR(64) NOT NULL,
+    "entity_id" BIGINT NOT NULL,
+    "name" VARCHAR(317) NOT NULL,
+    "value" VARCHAR(63) NOT NULL,
+    PRIMARY KEY ("kind", "entity_id", "name")
+);
+CREATE INDEX "idx_label_kind_name_value" ON "label" ("kind", "name", "value");
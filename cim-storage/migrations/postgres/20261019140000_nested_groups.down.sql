@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS "idx_group_parent_id";
+ALTER TABLE "group" DROP COLUMN "parent_id";
@@ -0,0 +1,7 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS "idx_group_user_expires_at";
+DROP INDEX IF EXISTS "idx_role_binding_expires_at";
+DROP INDEX IF EXISTS "idx_policy_binding_expires_at";
+ALTER TABLE "group_user" DROP COLUMN "not_before", DROP COLUMN "expires_at";
+ALTER TABLE "role_binding" DROP COLUMN "not_before", DROP COLUMN "expires_at";
+ALTER TABLE "policy_binding" DROP COLUMN "not_before", DROP COLUMN "expires_at";
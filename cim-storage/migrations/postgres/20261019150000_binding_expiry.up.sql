@@ -0,0 +1,10 @@
+-- Add up migration script here
+ALTER TABLE "group_user" ADD COLUMN "not_before" TIMESTAMP(3) NULL DEFAULT NULL,
+    ADD COLUMN "expires_at" TIMESTAMP(3) NULL DEFAULT NULL;
+CREATE INDEX "idx_group_user_expires_at" ON "group_user" ("expires_at");
+ALTER TABLE "role_binding" ADD COLUMN "not_before" TIMESTAMP(3) NULL DEFAULT NULL,
+    ADD COLUMN "expires_at" TIMESTAMP(3) NULL DEFAULT NULL;
+CREATE INDEX "idx_role_binding_expires_at" ON "role_binding" ("expires_at");
+ALTER TABLE "policy_binding" ADD COLUMN "not_before" TIMESTAMP(3) NULL DEFAULT NULL,
+    ADD COLUMN "expires_at" TIMESTAMP(3) NULL DEFAULT NULL;
+CREATE INDEX "idx_policy_binding_expires_at" ON "policy_binding" ("expires_at");
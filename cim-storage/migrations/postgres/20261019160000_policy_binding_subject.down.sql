@@ -0,0 +1,4 @@
+-- Add down migration script here
+DELETE FROM "policy_binding" WHERE "bindings_type" > 3;
+ALTER TABLE "policy_binding" ALTER COLUMN "bindings_id" TYPE BIGINT
+    USING "bindings_id"::BIGINT;
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE "policy_binding" ALTER COLUMN "bindings_id" TYPE VARCHAR(255);
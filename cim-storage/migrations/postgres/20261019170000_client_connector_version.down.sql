@@ -0,0 +1,4 @@
+-- Add down migration script here
+ALTER TABLE "client" DROP COLUMN "resource_version";
+ALTER TABLE "connector" DROP COLUMN "resource_version";
+ALTER TABLE "connector" RENAME COLUMN "response_version" TO "resource_version";
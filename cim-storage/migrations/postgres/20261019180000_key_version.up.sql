@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE "key" ADD COLUMN "resource_version" BIGINT NOT NULL DEFAULT 1;
@@ -0,0 +1,288 @@
+-- Add up migration script here
+CREATE TABLE `user` (
+    `id` INTEGER NOT NULL PRIMARY KEY,
+    `account_id` INTEGER NOT NULL,
+    `desc` VARCHAR(255) NOT NULL,
+    `email` VARCHAR(255) NULL DEFAULT NULL,
+    `email_verified` BOOLEAN NULL DEFAULT NULL,
+    `name` VARCHAR(255) NULL DEFAULT NULL,
+    `given_name` VARCHAR(255) NULL DEFAULT NULL,
+    `family_name` VARCHAR(255) NULL DEFAULT NULL,
+    `middle_name` VARCHAR(255) NULL DEFAULT NULL,
+    `nickname` VARCHAR(255) NULL DEFAULT NULL,
+    `preferred_username` VARCHAR(255) NULL DEFAULT NULL,
+    `profile` VARCHAR(255) NULL DEFAULT NULL,
+    `picture` VARCHAR(255) NULL DEFAULT NULL,
+    `website` VARCHAR(255) NULL DEFAULT NULL,
+    `gender` VARCHAR(10) NULL DEFAULT NULL,
+    `birthday` VARCHAR(255) NULL DEFAULT NULL,
+    `birthdate` VARCHAR(255) NULL DEFAULT NULL,
+    `zoneinfo` VARCHAR(255) NULL DEFAULT NULL,
+    `locale` VARCHAR(255) NULL DEFAULT NULL,
+    `phone_number` VARCHAR(255) NULL DEFAULT NULL,
+    `phone_number_verified` BOOLEAN NULL DEFAULT NULL,
+    `address` TEXT NULL DEFAULT NULL,
+    `secret` CHAR(64) NOT NULL,
+    `password` TEXT NOT NULL,
+    `deleted` INTEGER NOT NULL DEFAULT 0,
+    `created_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `updated_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `deleted_at` DATETIME NULL DEFAULT NULL
+);
+CREATE INDEX `idx_user_deleted` ON `user` (`deleted`);
+CREATE TRIGGER `user_updated_at` AFTER UPDATE ON `user`
+FOR EACH ROW WHEN NEW.`updated_at` = OLD.`updated_at`
+BEGIN
+    UPDATE `user` SET `updated_at` = (strftime('%Y-%m-%d %H:%M:%f', 'now')) WHERE `id` = NEW.`id`;
+END;
+
+CREATE TABLE `policy` (
+    `id` INTEGER NOT NULL PRIMARY KEY,
+    `account_id` INTEGER NULL,
+    `desc` VARCHAR(255) NOT NULL,
+    `version` VARCHAR(255) NOT NULL,
+    `statement` TEXT NOT NULL CHECK (json_valid(`statement`)),
+    `deleted` INTEGER NOT NULL DEFAULT 0,
+    `created_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `updated_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `deleted_at` DATETIME NULL DEFAULT NULL
+);
+CREATE INDEX `idx_policy_deleted` ON `policy` (`deleted`);
+CREATE TRIGGER `policy_updated_at` AFTER UPDATE ON `policy`
+FOR EACH ROW WHEN NEW.`updated_at` = OLD.`updated_at`
+BEGIN
+    UPDATE `policy` SET `updated_at` = (strftime('%Y-%m-%d %H:%M:%f', 'now')) WHERE `id` = NEW.`id`;
+END;
+
+CREATE TABLE `role` (
+    `id` INTEGER NOT NULL PRIMARY KEY,
+    `name` VARCHAR(255) NOT NULL,
+    `desc` VARCHAR(255) NOT NULL,
+    `account_id` INTEGER NOT NULL,
+    `deleted` INTEGER NOT NULL DEFAULT 0,
+    `created_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `updated_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `deleted_at` DATETIME NULL DEFAULT NULL
+);
+CREATE INDEX `idx_role_deleted` ON `role` (`deleted`);
+CREATE TRIGGER `role_updated_at` AFTER UPDATE ON `role`
+FOR EACH ROW WHEN NEW.`updated_at` = OLD.`updated_at`
+BEGIN
+    UPDATE `role` SET `updated_at` = (strftime('%Y-%m-%d %H:%M:%f', 'now')) WHERE `id` = NEW.`id`;
+END;
+
+CREATE TABLE `group` (
+    `id` INTEGER NOT NULL PRIMARY KEY,
+    `name` VARCHAR(255) NOT NULL,
+    `desc` VARCHAR(255) NOT NULL,
+    `account_id` INTEGER NOT NULL,
+    `deleted` INTEGER NOT NULL DEFAULT 0,
+    `created_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `updated_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `deleted_at` DATETIME NULL DEFAULT NULL
+);
+CREATE INDEX `idx_group_deleted` ON `group` (`deleted`);
+CREATE TRIGGER `group_updated_at` AFTER UPDATE ON `group`
+FOR EACH ROW WHEN NEW.`updated_at` = OLD.`updated_at`
+BEGIN
+    UPDATE `group` SET `updated_at` = (strftime('%Y-%m-%d %H:%M:%f', 'now')) WHERE `id` = NEW.`id`;
+END;
+
+CREATE TABLE `role_binding` (
+    `id` INTEGER NOT NULL PRIMARY KEY,
+    `role_id` INTEGER NOT NULL,
+    `user_type` TINYINT NOT NULL,
+    `user_id` VARCHAR(255) NOT NULL,
+    `deleted` INTEGER NOT NULL DEFAULT 0,
+    `created_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `updated_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `deleted_at` DATETIME NULL DEFAULT NULL
+);
+CREATE UNIQUE INDEX `idx_role_binding_role_id_user_type_user_id_deleted` ON `role_binding` (`role_id`, `user_type`, `user_id`, `deleted`);
+CREATE INDEX `idx_role_binding_deleted` ON `role_binding` (`deleted`);
+CREATE TRIGGER `role_binding_updated_at` AFTER UPDATE ON `role_binding`
+FOR EACH ROW WHEN NEW.`updated_at` = OLD.`updated_at`
+BEGIN
+    UPDATE `role_binding` SET `updated_at` = (strftime('%Y-%m-%d %H:%M:%f', 'now')) WHERE `id` = NEW.`id`;
+END;
+
+CREATE TABLE `policy_binding` (
+    `id` INTEGER NOT NULL PRIMARY KEY,
+    `policy_id` INTEGER NOT NULL,
+    `bindings_type` TINYINT NOT NULL,
+    `bindings_id` INTEGER NOT NULL,
+    `deleted` INTEGER NOT NULL DEFAULT 0,
+    `created_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `updated_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `deleted_at` DATETIME NULL DEFAULT NULL
+);
+CREATE UNIQUE INDEX `idx_policy_binding_policy_id_bindings_type_bindings_id_deleted` ON `policy_binding` (`policy_id`, `bindings_type`, `bindings_id`, `deleted`);
+CREATE INDEX `idx_policy_binding_deleted` ON `policy_binding` (`deleted`);
+CREATE TRIGGER `policy_binding_updated_at` AFTER UPDATE ON `policy_binding`
+FOR EACH ROW WHEN NEW.`updated_at` = OLD.`updated_at`
+BEGIN
+    UPDATE `policy_binding` SET `updated_at` = (strftime('%Y-%m-%d %H:%M:%f', 'now')) WHERE `id` = NEW.`id`;
+END;
+
+CREATE TABLE `group_user` (
+    `id` INTEGER NOT NULL PRIMARY KEY,
+    `group_id` INTEGER NOT NULL,
+    `user_id` INTEGER NOT NULL,
+    `deleted` INTEGER NOT NULL DEFAULT 0,
+    `created_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `updated_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `deleted_at` DATETIME NULL DEFAULT NULL
+);
+CREATE UNIQUE INDEX `idx_group_user_group_id_user_id_deleted` ON `group_user` (`group_id`, `user_id`, `deleted`);
+CREATE INDEX `idx_group_user_deleted` ON `group_user` (`deleted`);
+CREATE TRIGGER `group_user_updated_at` AFTER UPDATE ON `group_user`
+FOR EACH ROW WHEN NEW.`updated_at` = OLD.`updated_at`
+BEGIN
+    UPDATE `group_user` SET `updated_at` = (strftime('%Y-%m-%d %H:%M:%f', 'now')) WHERE `id` = NEW.`id`;
+END;
+
+CREATE TABLE `client` (
+    `id` INTEGER NOT NULL PRIMARY KEY,
+    `secret` TEXT NOT NULL,
+    `redirect_uris` TEXT NOT NULL CHECK (json_valid(`redirect_uris`)),
+    `trusted_peers` TEXT NOT NULL CHECK (json_valid(`trusted_peers`)),
+    `name` VARCHAR(255) NOT NULL,
+    `logo_url` TEXT NOT NULL,
+    `account_id` VARCHAR(255) NOT NULL DEFAULT '',
+    `deleted` INTEGER NOT NULL DEFAULT 0,
+    `created_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `updated_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `deleted_at` DATETIME NULL DEFAULT NULL
+);
+CREATE UNIQUE INDEX `idx_client_name_deleted` ON `client` (`name`, `deleted`);
+CREATE INDEX `idx_client_deleted` ON `client` (`deleted`);
+CREATE TRIGGER `client_updated_at` AFTER UPDATE ON `client`
+FOR EACH ROW WHEN NEW.`updated_at` = OLD.`updated_at`
+BEGIN
+    UPDATE `client` SET `updated_at` = (strftime('%Y-%m-%d %H:%M:%f', 'now')) WHERE `id` = NEW.`id`;
+END;
+
+CREATE TABLE `auth_request` (
+    `id` CHAR(36) NOT NULL PRIMARY KEY,
+    `client_id` INTEGER NOT NULL,
+    `response_types` TEXT NOT NULL CHECK (json_valid(`response_types`)),
+    `scopes` TEXT NOT NULL CHECK (json_valid(`scopes`)),
+    `redirect_uri` TEXT NOT NULL,
+    `code_challenge` TEXT NOT NULL DEFAULT '',
+    `code_challenge_method` TEXT NOT NULL DEFAULT '',
+    `nonce` TEXT NOT NULL,
+    `state` VARCHAR(4096),
+    `claim` TEXT NOT NULL CHECK (json_valid(`claim`)),
+    `connector_id` TEXT NOT NULL,
+    `connector_data` TEXT,
+    `expiry` INTEGER NOT NULL,
+    `created_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `updated_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `deleted_at` DATETIME NULL DEFAULT NULL
+);
+CREATE TRIGGER `auth_request_updated_at` AFTER UPDATE ON `auth_request`
+FOR EACH ROW WHEN NEW.`updated_at` = OLD.`updated_at`
+BEGIN
+    UPDATE `auth_request` SET `updated_at` = (strftime('%Y-%m-%d %H:%M:%f', 'now')) WHERE `id` = NEW.`id`;
+END;
+
+CREATE TABLE `auth_code` (
+    `id` CHAR(36) NOT NULL PRIMARY KEY,
+    `client_id` INTEGER NOT NULL,
+    `scopes` TEXT NOT NULL CHECK (json_valid(`scopes`)),
+    `nonce` TEXT NOT NULL,
+    `redirect_uri` TEXT NOT NULL,
+    `code_challenge` TEXT NOT NULL DEFAULT '',
+    `code_challenge_method` TEXT NOT NULL DEFAULT '',
+    `claim` TEXT NOT NULL CHECK (json_valid(`claim`)),
+    `connector_id` TEXT NOT NULL,
+    `connector_data` TEXT,
+    `expiry` INTEGER NOT NULL,
+    `created_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `updated_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `deleted_at` DATETIME NULL DEFAULT NULL
+);
+CREATE TRIGGER `auth_code_updated_at` AFTER UPDATE ON `auth_code`
+FOR EACH ROW WHEN NEW.`updated_at` = OLD.`updated_at`
+BEGIN
+    UPDATE `auth_code` SET `updated_at` = (strftime('%Y-%m-%d %H:%M:%f', 'now')) WHERE `id` = NEW.`id`;
+END;
+
+CREATE TABLE `refresh_token` (
+    `id` INTEGER NOT NULL PRIMARY KEY,
+    `client_id` INTEGER NOT NULL,
+    `scopes` TEXT NOT NULL CHECK (json_valid(`scopes`)),
+    `nonce` TEXT NOT NULL,
+    `token` TEXT NOT NULL DEFAULT '',
+    `obsolete_token` TEXT NOT NULL DEFAULT '',
+    `claim` TEXT NOT NULL CHECK (json_valid(`claim`)),
+    `connector_id` TEXT NOT NULL,
+    `connector_data` TEXT,
+    `last_used_at` DATETIME NOT NULL,
+    `deleted` INTEGER NOT NULL DEFAULT 0,
+    `created_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `updated_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `deleted_at` DATETIME NULL DEFAULT NULL
+);
+CREATE INDEX `idx_refresh_token_deleted` ON `refresh_token` (`deleted`);
+CREATE TRIGGER `refresh_token_updated_at` AFTER UPDATE ON `refresh_token`
+FOR EACH ROW WHEN NEW.`updated_at` = OLD.`updated_at`
+BEGIN
+    UPDATE `refresh_token` SET `updated_at` = (strftime('%Y-%m-%d %H:%M:%f', 'now')) WHERE `id` = NEW.`id`;
+END;
+
+CREATE TABLE `key` (
+    `id` INTEGER NOT NULL PRIMARY KEY,
+    `verification_keys` TEXT NOT NULL CHECK (json_valid(`verification_keys`)),
+    `signing_key` TEXT NOT NULL CHECK (json_valid(`signing_key`)),
+    `signing_key_pub` TEXT NOT NULL CHECK (json_valid(`signing_key_pub`)),
+    `next_rotation` INTEGER NOT NULL,
+    `deleted` INTEGER NOT NULL DEFAULT 0,
+    `created_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `updated_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `deleted_at` DATETIME NULL DEFAULT NULL
+);
+CREATE INDEX `idx_key_deleted` ON `key` (`deleted`);
+CREATE TRIGGER `key_updated_at` AFTER UPDATE ON `key`
+FOR EACH ROW WHEN NEW.`updated_at` = OLD.`updated_at`
+BEGIN
+    UPDATE `key` SET `updated_at` = (strftime('%Y-%m-%d %H:%M:%f', 'now')) WHERE `id` = NEW.`id`;
+END;
+
+CREATE TABLE `offline_session` (
+    `id` INTEGER NOT NULL PRIMARY KEY,
+    `user_id` VARCHAR(255) NOT NULL,
+    `conn_id` VARCHAR(255) NOT NULL,
+    `refresh` TEXT NOT NULL CHECK (json_valid(`refresh`)),
+    `connector_data` TEXT,
+    `deleted` INTEGER NOT NULL DEFAULT 0,
+    `created_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `updated_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `deleted_at` DATETIME NULL DEFAULT NULL
+);
+CREATE UNIQUE INDEX `idx_offline_session_user_id_conn_id` ON `offline_session` (`user_id`, `conn_id`);
+CREATE INDEX `idx_offline_session_deleted` ON `offline_session` (`deleted`);
+CREATE TRIGGER `offline_session_updated_at` AFTER UPDATE ON `offline_session`
+FOR EACH ROW WHEN NEW.`updated_at` = OLD.`updated_at`
+BEGIN
+    UPDATE `offline_session` SET `updated_at` = (strftime('%Y-%m-%d %H:%M:%f', 'now')) WHERE `id` = NEW.`id`;
+END;
+
+CREATE TABLE `connector` (
+    `id` INTEGER NOT NULL PRIMARY KEY,
+    `type` VARCHAR(255) NOT NULL,
+    `name` TEXT NOT NULL,
+    `resource_version` TEXT NOT NULL,
+    `config` TEXT,
+    `connector_data` TEXT,
+    `deleted` INTEGER NOT NULL DEFAULT 0,
+    `created_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `updated_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `deleted_at` DATETIME NULL DEFAULT NULL
+);
+CREATE INDEX `idx_connector_deleted` ON `connector` (`deleted`);
+CREATE TRIGGER `connector_updated_at` AFTER UPDATE ON `connector`
+FOR EACH ROW WHEN NEW.`updated_at` = OLD.`updated_at`
+BEGIN
+    UPDATE `connector` SET `updated_at` = (strftime('%Y-%m-%d %H:%M:%f', 'now')) WHERE `id` = NEW.`id`;
+END;
@@ -0,0 +1,20 @@
+-- Add up migration script here
+CREATE TABLE `relation_tuple` (
+    `id` INTEGER NOT NULL PRIMARY KEY,
+    `account_id` INTEGER NOT NULL,
+    `object` VARCHAR(255) NOT NULL,
+    `relation` VARCHAR(64) NOT NULL,
+    `subject` VARCHAR(255) NOT NULL,
+    `deleted` INTEGER NOT NULL DEFAULT 0,
+    `created_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `updated_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `deleted_at` DATETIME NULL DEFAULT NULL
+);
+CREATE UNIQUE INDEX `idx_relation_tuple_object_relation_subject_deleted` ON `relation_tuple` (`object`, `relation`, `subject`, `deleted`);
+CREATE INDEX `idx_relation_tuple_subject` ON `relation_tuple` (`subject`);
+CREATE INDEX `idx_relation_tuple_deleted` ON `relation_tuple` (`deleted`);
+CREATE TRIGGER `relation_tuple_updated_at` AFTER UPDATE ON `relation_tuple`
+FOR EACH ROW WHEN NEW.`updated_at` = OLD.`updated_at`
+BEGIN
+    UPDATE `relation_tuple` SET `updated_at` = (strftime('%Y-%m-%d %H:%M:%f', 'now')) WHERE `id` = NEW.`id`;
+END;
@@ -0,0 +1,9 @@
+-- Add up migration script here
+ALTER TABLE `user` ADD COLUMN `resource_version` INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE `group` ADD COLUMN `resource_version` INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE `role` ADD COLUMN `resource_version` INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE `policy` ADD COLUMN `resource_version` INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE `group_user` ADD COLUMN `resource_version` INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE `role_binding` ADD COLUMN `resource_version` INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE `policy_binding` ADD COLUMN `resource_version` INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE `relation_tuple` ADD COLUMN `resource_version` INTEGER NOT NULL DEFAULT 1;
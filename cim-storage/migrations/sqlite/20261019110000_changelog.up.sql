@@ -0,0 +1,8 @@
+-- Add up migration script here
+CREATE TABLE `changelog` (
+    `id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    `kind` VARCHAR(64) NOT NULL,
+    `payload` TEXT NOT NULL,
+    `created_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now'))
+);
+CREATE INDEX `idx_changelog_created_at` ON `changelog` (`created_at`);
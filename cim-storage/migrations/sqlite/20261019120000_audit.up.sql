@@ -0,0 +1,16 @@
+-- Add up migration script here
+CREATE TABLE `audit` (
+    `id` INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    `actor` VARCHAR(255) NOT NULL,
+    `trace_id` VARCHAR(255) NOT NULL,
+    `source_ip` VARCHAR(64) NOT NULL,
+    `kind` VARCHAR(64) NOT NULL,
+    `entity_id` VARCHAR(255) NOT NULL,
+    `action` VARCHAR(16) NOT NULL,
+    `before` TEXT NULL,
+    `after` TEXT NULL,
+    `created_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now'))
+);
+CREATE INDEX `idx_audit_actor` ON `audit` (`actor`);
+CREATE INDEX `idx_audit_kind_entity_id` ON `audit` (`kind`, `entity_id`);
+CREATE INDEX `idx_audit_created_at` ON `audit` (`created_at`);
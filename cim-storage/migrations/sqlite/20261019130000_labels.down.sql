@@ -0,0 +1,12 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS `label`;
+ALTER TABLE `user` DROP COLUMN `labels`;
+ALTER TABLE `user` DROP COLUMN `annotations`;
+ALTER TABLE `group` DROP COLUMN `labels`;
+ALTER TABLE `group` DROP COLUMN `annotations`;
+ALTER TABLE `role` DROP COLUMN `labels`;
+ALTER TABLE `role` DROP COLUMN `annotations`;
+ALTER TABLE `policy` DROP COLUMN `labels`;
+ALTER TABLE `policy` DROP COLUMN `annotations`;
+ALTER TABLE `client` DROP COLUMN `labels`;
+ALTER TABLE `client` DROP COLUMN `annotations`;
@@ -0,0 +1,20 @@
+-- Add up migration script here
+ALTER TABLE `user` ADD COLUMN `labels` TEXT NOT NULL DEFAULT '{}' CHECK (json_valid(`labels`));
+ALTER TABLE `user` ADD COLUMN `annotations` TEXT NOT NULL DEFAULT '{}' CHECK (json_valid(`annotations`));
+ALTER TABLE `group` ADD COLUMN `labels` TEXT NOT NULL DEFAULT '{}' CHECK (json_valid(`labels`));
+ALTER TABLE `group` ADD COLUMN `annotations` TEXT NOT NULL DEFAULT '{}' CHECK (json_valid(`annotations`));
+ALTER TABLE `role` ADD COLUMN `labels` TEXT NOT NULL DEFAULT '{}' CHECK (json_valid(`labels`));
+ALTER TABLE `role` ADD COLUMN `annotations` TEXT NOT NULL DEFAULT '{}' CHECK (json_valid(`annotations`));
+ALTER TABLE `policy` ADD COLUMN `labels` TEXT NOT NULL DEFAULT '{}' CHECK (json_valid(`labels`));
+ALTER TABLE `policy` ADD COLUMN `annotations` TEXT NOT NULL DEFAULT '{}' CHECK (json_valid(`annotations`));
+ALTER TABLE `client` ADD COLUMN `labels` TEXT NOT NULL DEFAULT '{}' CHECK (json_valid(`labels`));
+ALTER TABLE `client` ADD COLUMN `annotations` TEXT NOT NULL DEFAULT '{}' CHECK (json_valid(`annotations`));
+
+CREATE TABLE `label` (
+    `kind` VARCHAR(64) NOT NULL,
+    `entity_id` INTEGER NOT NULL,
+    `name` VARCHAR(317) NOT NULL,
+    `value` VARCHAR(63) NOT NULL,
+    PRIMARY KEY (`kind`, `entity_id`, `name`)
+);
+CREATE INDEX `idx_label_kind_name_value` ON `label` (`kind`, `name`, `value`);
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE `group` ADD COLUMN `parent_id` INTEGER NULL DEFAULT NULL;
+CREATE INDEX `idx_group_parent_id` ON `group` (`parent_id`);
@@ -0,0 +1,10 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS `idx_group_user_expires_at`;
+DROP INDEX IF EXISTS `idx_role_binding_expires_at`;
+DROP INDEX IF EXISTS `idx_policy_binding_expires_at`;
+ALTER TABLE `group_user` DROP COLUMN `not_before`;
+ALTER TABLE `group_user` DROP COLUMN `expires_at`;
+ALTER TABLE `role_binding` DROP COLUMN `not_before`;
+ALTER TABLE `role_binding` DROP COLUMN `expires_at`;
+ALTER TABLE `policy_binding` DROP COLUMN `not_before`;
+ALTER TABLE `policy_binding` DROP COLUMN `expires_at`;
@@ -0,0 +1,31 @@
+-- Add down migration script here
+DELETE FROM `policy_binding` WHERE `bindings_type` > 3;
+CREATE TABLE `policy_binding_new` (
+    `id` INTEGER NOT NULL PRIMARY KEY,
+    `policy_id` INTEGER NOT NULL,
+    `bindings_type` TINYINT NOT NULL,
+    `bindings_id` INTEGER NOT NULL,
+    `not_before` DATETIME NULL DEFAULT NULL,
+    `expires_at` DATETIME NULL DEFAULT NULL,
+    `resource_version` INTEGER NOT NULL DEFAULT 1,
+    `deleted` INTEGER NOT NULL DEFAULT 0,
+    `created_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `updated_at` DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    `deleted_at` DATETIME NULL DEFAULT NULL
+);
+INSERT INTO `policy_binding_new`
+    (`id`,`policy_id`,`bindings_type`,`bindings_id`,`not_before`,`expires_at`,
+    `resource_version`,`deleted`,`created_at`,`updated_at`,`deleted_at`)
+SELECT `id`,`policy_id`,`bindings_type`,`bindings_id`,`not_before`,`expires_at`,
+    `resource_version`,`deleted`,`created_at`,`updated_at`,`deleted_at`
+FROM `policy_binding`;
+DROP TABLE `policy_binding`;
+ALTER TABLE `policy_binding_new` RENAME TO `policy_binding`;
+CREATE UNIQUE INDEX `idx_policy_binding_policy_id_bindings_type_bindings_id_deleted` ON `policy_binding` (`policy_id`, `bindings_type`, `bindings_id`, `deleted`);
+CREATE INDEX `idx_policy_binding_deleted` ON `policy_binding` (`deleted`);
+CREATE INDEX `idx_policy_binding_expires_at` ON `policy_binding` (`expires_at`);
+CREATE TRIGGER `policy_binding_updated_at` AFTER UPDATE ON `policy_binding`
+FOR EACH ROW WHEN NEW.`updated_at` = OLD.`updated_at`
+BEGIN
+    UPDATE `policy_binding` SET `updated_at` = (strftime('%Y-%m-%d %H:%M:%f', 'now')) WHERE `id` = NEW.`id`;
+END;
@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE `connector` RENAME COLUMN `resource_version` TO `response_version`;
+ALTER TABLE `connector` ADD COLUMN `resource_version` INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE `client` ADD COLUMN `resource_version` INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,389 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use cim_slo::{errors, Result};
+
+use crate::{stored, Filter, Interface, Keyed, List, Pagination, Param};
+
+/// Audit records a change of an entity made through the server, records
+/// are appended and never changed
+#[derive(
+    Debug, Default, Deserialize, Serialize, PartialEq, Clone, utoipa::ToSchema,
+)]
+pub struct Audit {
+    /// id is given by the store, it grows with every record
+    pub id: String,
+    /// actor is the id of the user who made the change
+    pub actor: String,
+    /// trace_id is the `X-Trace-Id` of the request
+    pub trace_id: String,
+    pub source_ip: String,
+    /// kind is the entity changed, e.g. `policy`
+    pub kind: String,
+    pub entity_id: String,
+    /// action is `create`, `put`, `delete`, `restore` or `purge`, a purge
+    /// is of no single entity
+    pub action: String,
+    /// before is the entity before the change, none when it's created
+    #[schema(value_type = Option<Object>)]
+    pub before: Option<serde_json::Value>,
+    /// after is the entity after the change, none when it's deleted
+    #[schema(value_type = Option<Object>)]
+    pub after: Option<serde_json::Value>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ListParams {
+    #[validate(length(min = 1))]
+    pub actor: Option<String>,
+    #[validate(length(min = 1))]
+    pub kind: Option<String>,
+    #[validate(length(min = 1))]
+    pub entity_id: Option<String>,
+    /// since keeps the records created at or after it
+    pub since: Option<NaiveDateTime>,
+    /// until keeps the records created before it
+    pub until: Option<NaiveDateTime>,
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub pagination: Pagination,
+}
+
+impl ListParams {
+    /// SORTABLE are the columns order_by may name
+    pub const SORTABLE: &'static [&'static str] =
+        &["id", "actor", "kind", "created_at"];
+
+    /// filter is the WHERE clause of the sql stores
+    pub(crate) fn filter(&self) -> Filter {
+        Filter::default()
+            .eq("actor", self.actor.as_ref())
+            .eq("kind", self.kind.as_ref())
+            .eq("entity_id", self.entity_id.as_ref())
+            .ge("created_at", self.since)
+            .lt("created_at", self.until)
+    }
+}
+
+impl Keyed for Audit {
+    fn key(&self, column: &str) -> Option<Param> {
+        match column {
+            "id" => Some(Param::id(&self.id)),
+            "actor" => Some((&self.actor).into()),
+            "kind" => Some((&self.kind).into()),
+            "created_at" => Some(self.created_at.into()),
+            _ => None,
+        }
+    }
+}
+
+#[async_trait]
+pub trait AuditStore: Send + Sync {
+    /// append adds a record, its id and created_at are set by the store. The
+    /// record belongs to the transaction of the store
+    async fn append(&self, input: &Audit) -> Result<()>;
+    async fn list(
+        &self,
+        opts: &ListParams,
+        output: &mut List<Audit>,
+    ) -> Result<()>;
+}
+
+#[async_trait]
+impl<T: AuditStore + ?Sized> AuditStore for Arc<T> {
+    async fn append(&self, input: &Audit) -> Result<()> {
+        (**self).append(input).await
+    }
+
+    async fn list(
+        &self,
+        opts: &ListParams,
+        output: &mut List<Audit>,
+    ) -> Result<()> {
+        (**self).list(opts, output).await
+    }
+}
+
+/// Trail is who made a change and from where, every record written by an
+/// Audited store carries it
+#[derive(Clone, Debug, Default)]
+pub struct Trail {
+    pub actor: String,
+    pub trace_id: String,
+    pub source_ip: String,
+}
+
+/// Audited appends a record of every put and delete of store to audit,
+/// it should share the transaction of store so that the record commits
+/// with the change
+pub struct Audited<I: Interface> {
+    store: I,
+    kind: &'static str,
+    audit: Arc<dyn AuditStore>,
+    trail: Trail,
+    redact: Option<fn(&mut I::T)>,
+}
+
+impl<I: Interface> Audited<I> {
+    pub fn new(
+        store: I,
+        kind: &'static str,
+        audit: Arc<dyn AuditStore>,
+        trail: Trail,
+    ) -> Self {
+        Self {
+            store,
+            kind,
+            audit,
+            trail,
+            redact: None,
+        }
+    }
+
+    /// redact clears the secrets of an entity before it is recorded
+    pub fn redact(mut self, redact: fn(&mut I::T)) -> Self {
+        self.redact = Some(redact);
+        self
+    }
+
+    fn snapshot(
+        &self,
+        value: Option<&I::T>,
+    ) -> Result<Option<serde_json::Value>> {
+        let Some(value) = value else {
+            return Ok(None);
+        };
+        let mut value = value.clone();
+        if let Some(redact) = self.redact {
+            redact(&mut value);
+        }
+        serde_json::to_value(value).map(Some).map_err(errors::any)
+    }
+
+    async fn record(
+        &self,
+        action: &str,
+        before: Option<&I::T>,
+        after: Option<&I::T>,
+    ) -> Result<()> {
+        let before = self.snapshot(before)?;
+        let after = self.snapshot(after)?;
+        let entity_id = after
+            .as_ref()
+            .or(before.as_ref())
+            .and_then(|v| v.get("id"))
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_owned();
+        self.audit
+            .append(&Audit {
+                actor: self.trail.actor.clone(),
+                trace_id: self.trail.trace_id.clone(),
+                source_ip: self.trail.source_ip.clone(),
+                kind: self.kind.to_owned(),
+                entity_id,
+                action: action.to_owned(),
+                before,
+                after,
+                ..Default::default()
+            })
+            .await
+    }
+
+    async fn record_put(
+        &self,
+        before: Option<&I::T>,
+        input: &I::T,
+    ) -> Result<()> {
+        let action = if before.is_some() { "put" } else { "create" };
+        self.record(action, before, Some(input)).await
+    }
+}
+
+#[async_trait]
+impl<I: Interface + Send> Interface for Audited<I> {
+    type T = I::T;
+    type L = I::L;
+
+    async fn put(&self, input: &I::T) -> Result<()> {
+        self.replace(input, None).await.map(drop)
+    }
+
+    async fn delete(&self, input: &I::T) -> Result<()> {
+        let before = stored(&self.store, input).await?;
+        self.store.delete(input).await?;
+        self.record("delete", before.as_ref(), None).await
+    }
+
+    async fn get(&self, output: &mut I::T) -> Result<()> {
+        self.store.get(output).await
+    }
+
+    async fn list(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.store.list(opts, output).await
+    }
+
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        self.store.count(opts, unscoped).await
+    }
+
+    async fn put_if(&self, input: &I::T, resource_version: u64) -> Result<()> {
+        self.replace(input, Some(resource_version)).await.map(drop)
+    }
+
+    async fn replace(
+        &self,
+        input: &I::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<I::T>> {
+        let before = self.store.replace(input, resource_version).await?;
+        self.record_put(before.as_ref(), input).await?;
+        Ok(before)
+    }
+
+    async fn delete_if(
+        &self,
+        input: &I::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        let before = stored(&self.store, input).await?;
+        self.store.delete_if(input, resource_version).await?;
+        self.record("delete", before.as_ref(), None).await
+    }
+
+    async fn discard(
+        &self,
+        input: &I::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<I::T>> {
+        let before = self.store.discard(input, resource_version).await?;
+        self.record("delete", before.as_ref(), None).await?;
+        Ok(before)
+    }
+
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.store.list_deleted(opts, output).await
+    }
+
+    async fn restore(&self, input: &I::T) -> Result<()> {
+        self.store.restore(input).await?;
+        let after = stored(&self.store, input).await?;
+        self.record("restore", None, after.as_ref()).await
+    }
+
+    /// purge is recorded once for the kind, after holds the cutoff and how
+    /// many it removed since their deletions were recorded one by one
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        let purged = self.store.purge(before).await?;
+        if purged == 0 {
+            return Ok(0);
+        }
+        self.audit
+            .append(&Audit {
+                actor: self.trail.actor.clone(),
+                trace_id: self.trail.trace_id.clone(),
+                source_ip: self.trail.source_ip.clone(),
+                kind: self.kind.to_owned(),
+                action: "purge".to_owned(),
+                after: Some(serde_json::json!({
+                    "before": before,
+                    "purged": purged,
+                })),
+                ..Default::default()
+            })
+            .await?;
+        Ok(purged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{connection_manager, group::Group, memory, Pool, TxConn};
+
+    #[tokio::test]
+    async fn audited() {
+        let pool = connection_manager("memory://", 1, 1, false).await.unwrap();
+        let Pool::Memory(db) = &pool else {
+            unreachable!()
+        };
+        let tx = pool.begin().await.unwrap();
+        let TxConn::Memory(conn) = tx.conn() else {
+            unreachable!()
+        };
+        let trail = Trail {
+            actor: "1".to_owned(),
+            trace_id: "trace".to_owned(),
+            source_ip: "127.0.0.1".to_owned(),
+        };
+        let redact: fn(&mut Group) = |v| v.name.clear();
+        let store = Audited::new(
+            memory::GroupImpl::new(conn.clone()),
+            "group",
+            Arc::new(memory::AuditImpl::new(conn.clone())),
+            trail,
+        )
+        .redact(redact);
+        let mut group = Group {
+            id: "2".to_owned(),
+            name: "a".to_owned(),
+            ..Default::default()
+        };
+        store.put(&group).await.unwrap();
+        group.name = "b".to_owned();
+        store.put(&group).await.unwrap();
+        store.delete(&group).await.unwrap();
+        let before =
+            chrono::Utc::now().naive_utc() + chrono::Duration::seconds(1);
+        assert_eq!(store.purge(before).await.unwrap(), 1);
+        assert_eq!(store.purge(before).await.unwrap(), 0);
+
+        let audit = memory::AuditImpl::new(db.clone());
+        let opts = ListParams {
+            actor: Some("1".to_owned()),
+            kind: None,
+            entity_id: None,
+            since: None,
+            until: None,
+            pagination: Pagination::default(),
+        };
+        let mut output = List::default();
+        audit.list(&opts, &mut output).await.unwrap();
+        assert!(output.data.is_empty());
+
+        tx.commit().await.unwrap();
+        audit.list(&opts, &mut output).await.unwrap();
+        let actions: Vec<_> = output
+            .data
+            .iter()
+            .map(|v| (v.action.as_str(), v.before.is_some(), v.after.is_some()))
+            .collect();
+        assert_eq!(
+            actions,
+            [
+                ("create", false, true),
+                ("put", true, true),
+                ("delete", true, false),
+                ("purge", false, true)
+            ]
+        );
+        assert_eq!(output.data[3].after.as_ref().unwrap()["purged"], 1);
+        let record = &output.data[1];
+        assert_eq!(record.entity_id, "2");
+        assert_eq!(record.trace_id, "trace");
+        assert_eq!(record.after.as_ref().unwrap()["name"], "");
+    }
+}
@@ -1,94 +1,1052 @@
-use std::{collections::HashMap, sync::RwLock};
+use std::{
+    num::NonZeroUsize,
+    sync::{Arc, LazyLock, Mutex, PoisonError, Weak},
+    time::{Duration, Instant},
+};
 
 use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use lru::LruCache;
+use prometheus::{register_int_counter_vec, IntCounterVec};
 
-use cim_slo::{errors, type_name, Result};
+use cim_pim::{PrincipalType, Request, Statement};
+use cim_slo::Result;
 use cim_watch::{WatchGuard, Watcher};
 
-use crate::{Event, Interface, List};
+use crate::{
+    group::Group,
+    group_user::GroupUser,
+    policy::{Bindings, Policy, StatementStore},
+    policy_binding::{BindingsType, PolicyBinding},
+    role::Role,
+    role_binding::RoleBinding,
+    transaction::Hook,
+    Event, Interface, List, Tx, WatchInterface,
+};
 
-pub struct Cacher<I> {
-    storage: I,
-    cache: RwLock<HashMap<String, String>>,
-    prefix: String,
+static REQUESTS: LazyLock<IntCounterVec> = LazyLock::new(|| {
+    register_int_counter_vec!(
+        "store_cache_requests_total",
+        "Lookups of the store caches by result",
+        &["store", "result"]
+    )
+    .unwrap()
+});
+
+/// Cacher is a read-through cache in front of store. An entry expires after
+/// ttl and the least recently used ones are evicted beyond capacity, a
+/// capacity of 0 turns the cache off.
+///
+/// Writes through the cacher evict what they touch, writes of other
+/// replicas are only seen once they expire unless the cacher is
+/// `invalidated_by` the watch store carrying their events.
+pub struct Cacher<I: Interface> {
+    store: I,
+    name: &'static str,
+    key: fn(&I::T) -> String,
+    list_key: Option<fn(&I::L) -> String>,
+    entries: Option<Arc<Mutex<Entries<I::T>>>>,
+    ttl: Duration,
+    guard: Arc<Mutex<Option<Box<dyn WatchGuard + Send>>>>,
+    // evictions held back until the transaction commits
+    pending: Option<Arc<Mutex<Vec<Hook>>>>,
+}
+
+struct Entries<T> {
+    values: LruCache<String, (Instant, T)>,
+    lists: LruCache<String, (Instant, List<T>)>,
+    /// generation grows with every eviction, a read that raced a write
+    /// must not cache what it read before the write
+    generation: u64,
+}
+
+type Slot<T, V> = fn(&mut Entries<T>) -> &mut LruCache<String, (Instant, V)>;
+
+impl<T> Entries<T> {
+    fn evict(&mut self, key: &str) {
+        self.values.pop(key);
+        self.lists.clear();
+        self.generation += 1;
+    }
+}
+
+impl<I: Interface + Clone> Clone for Cacher<I> {
+    fn clone(&self) -> Self {
+        Self {
+            store: self.store.clone(),
+            name: self.name,
+            key: self.key,
+            list_key: self.list_key,
+            entries: self.entries.clone(),
+            ttl: self.ttl,
+            guard: Arc::clone(&self.guard),
+            pending: self.pending.clone(),
+        }
+    }
+}
+
+impl<I: Interface> std::fmt::Debug for Cacher<I> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cacher").field("name", &self.name).finish()
+    }
 }
 
-impl<I> Cacher<I> {
-    pub fn new(storage: I, prefix: String) -> Self {
+impl<I: Interface> Cacher<I> {
+    /// new caches the entities of store by key, name labels its metrics
+    pub fn new(
+        name: &'static str,
+        store: I,
+        key: fn(&I::T) -> String,
+        capacity: usize,
+        ttl: Duration,
+    ) -> Self {
+        let entries = NonZeroUsize::new(capacity).map(|capacity| {
+            Arc::new(Mutex::new(Entries {
+                values: LruCache::new(capacity),
+                lists: LruCache::new(capacity),
+                generation: 0,
+            }))
+        });
         Self {
-            storage,
-            cache: RwLock::new(HashMap::new()),
-            prefix,
+            store,
+            name,
+            key,
+            list_key: None,
+            entries,
+            ttl,
+            guard: Arc::default(),
+            pending: None,
+        }
+    }
+
+    /// transaction wraps store, running in tx, it reads past the cache of
+    /// self and evicts what it writes from it once tx is committed
+    pub fn transaction<J>(&self, store: J, tx: &Tx) -> Cacher<J>
+    where
+        J: Interface<T = I::T, L = I::L>,
+    {
+        Cacher {
+            store,
+            name: self.name,
+            key: self.key,
+            list_key: self.list_key,
+            entries: self.entries.clone(),
+            ttl: self.ttl,
+            guard: Arc::default(),
+            pending: Some(tx.hooks()),
+        }
+    }
+
+    /// lists caches the lists too, under the key list_key gives their
+    /// options, any write drops every cached list
+    pub fn lists(mut self, list_key: fn(&I::L) -> String) -> Self {
+        self.list_key = Some(list_key);
+        self
+    }
+
+    /// invalidated_by evicts the entities of the events of store, which is
+    /// usually the watch store wrapping self
+    pub fn invalidated_by<W>(&self, store: &W)
+    where
+        W: WatchInterface<T = I::T>,
+    {
+        let Some(entries) = &self.entries else {
+            return;
+        };
+        let entries = Arc::downgrade(entries);
+        let key = self.key;
+        // a watch of the new events only can't be too old
+        let guard = store
+            .watch(0, Invalidate { entries, key })
+            .expect("watch from 0");
+        *self.guard.lock().unwrap_or_else(PoisonError::into_inner) =
+            Some(guard);
+    }
+
+    fn lock(&self) -> Option<std::sync::MutexGuard<'_, Entries<I::T>>> {
+        self.entries
+            .as_ref()
+            .map(|v| v.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    fn evict(&self, input: &I::T) {
+        let (Some(entries), key) = (&self.entries, (self.key)(input)) else {
+            return;
+        };
+        let Some(pending) = &self.pending else {
+            entries
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .evict(&key);
+            return;
+        };
+        let entries = Arc::clone(entries);
+        pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Box::new(move || {
+                entries
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .evict(&key)
+            }));
+    }
+
+    /// lookup returns the fresh entry of key, or the generation to fill it
+    /// at after a miss, None when the cache is off or self is in a
+    /// transaction
+    fn lookup<V: Clone>(
+        &self,
+        slot: Slot<I::T, V>,
+        key: &str,
+    ) -> Option<std::result::Result<V, u64>> {
+        if self.pending.is_some() {
+            return None;
         }
+        let mut entries = self.lock()?;
+        let generation = entries.generation;
+        let cache = slot(&mut entries);
+        let hit = match cache.get(key) {
+            Some((cached_at, value)) if cached_at.elapsed() < self.ttl => {
+                Some(value.clone())
+            }
+            Some(_) => {
+                cache.pop(key);
+                None
+            }
+            None => None,
+        };
+        drop(entries);
+        self.record(hit.is_some());
+        Some(hit.ok_or(generation))
+    }
+
+    /// fill caches value unless something was evicted since generation
+    fn fill<V: Clone>(
+        &self,
+        slot: Slot<I::T, V>,
+        key: String,
+        generation: u64,
+        value: &V,
+    ) {
+        if let Some(mut entries) = self.lock() {
+            if entries.generation == generation {
+                slot(&mut entries).put(key, (Instant::now(), value.clone()));
+            }
+        }
+    }
+
+    fn record(&self, hit: bool) {
+        let result = if hit { "hit" } else { "miss" };
+        REQUESTS.with_label_values(&[self.name, result]).inc();
     }
+}
+
+struct Invalidate<T> {
+    entries: Weak<Mutex<Entries<T>>>,
+    key: fn(&T) -> String,
+}
 
-    pub fn key(&self, uid: Option<&str>) -> String {
-        let type_name_str = type_name!(Self::I::T);
-        let mut buf = String::new();
-        buf.push_str(&self.prefix);
-        buf.push('/');
-        buf.push_str(type_name_str);
-        if let Some(uid) = uid {
-            buf.push('/');
-            buf.push_str(uid);
+impl<T: Send + Sync + 'static> Watcher<Event<T>> for Invalidate<T> {
+    fn notify(&self, event: Event<T>) {
+        if let Some(entries) = self.entries.upgrade() {
+            entries
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .evict(&(self.key)(event.get()));
         }
-        buf
     }
 }
 
 #[async_trait]
-impl<I> Interface for Cacher<I>
+impl<I: Interface + Send> Interface for Cacher<I>
 where
-    I: Interface,
+    I::L: Sync,
 {
     type T = I::T;
     type L = I::L;
 
-    async fn put(&self, input: &Self::T, ttl: u64) -> Result<()> {
-        self.storage.put(input, ttl).await?;
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        self.store.put(input).await?;
+        self.evict(input);
         Ok(())
     }
 
-    async fn delete(&self, id: &str) -> Result<()> {
-        self.storage.delete(id).await?;
-        let mut cache = self.cache.write().map_err(errors::any)?;
-        let key = self.key(Some(id));
-        cache.remove(&key);
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        self.store.delete(input).await?;
+        self.evict(input);
         Ok(())
     }
-    async fn get(&self, id: &str, output: &mut Self::T) -> Result<()> {
-        let key = self.key(Some(id));
-        {
-            let cache = self.cache.read().map_err(errors::any)?;
-            if let Some(v) = cache.get(&key) {
-                *output =
-                    serde_json::from_str::<Self::T>(v).map_err(errors::any)?;
+
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        let key = (self.key)(output);
+        let generation = match self.lookup(|v| &mut v.values, &key) {
+            Some(Ok(value)) => {
+                *output = value;
                 return Ok(());
             }
-        }
-
-        self.storage.get(id, output).await?;
-        let mut cache = self.cache.write().map_err(errors::any)?;
-        let value = serde_json::to_string(output).map_err(errors::any)?;
-        cache.insert(key, value);
+            Some(Err(generation)) => generation,
+            None => return self.store.get(output).await,
+        };
+        self.store.get(output).await?;
+        self.fill(|v| &mut v.values, key, generation, output);
         Ok(())
     }
+
     async fn list(
         &self,
         opts: &Self::L,
         output: &mut List<Self::T>,
     ) -> Result<()> {
-        self.storage.list(opts, output).await?;
-        // TODO: cache all
+        let Some(list_key) = self.list_key else {
+            return self.store.list(opts, output).await;
+        };
+        let key = list_key(opts);
+        let generation = match self.lookup(|v| &mut v.lists, &key) {
+            Some(Ok(list)) => {
+                *output = list;
+                return Ok(());
+            }
+            Some(Err(generation)) => generation,
+            None => return self.store.list(opts, output).await,
+        };
+        self.store.list(opts, output).await?;
+        self.fill(|v| &mut v.lists, key, generation, output);
+        Ok(())
+    }
+
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        self.store.count(opts, unscoped).await
+    }
+
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.store.put_if(input, resource_version).await?;
+        self.evict(input);
         Ok(())
     }
-    fn watch<W: Watcher<Event<Self::T>>>(
+
+    async fn replace(
         &self,
-        handler: W,
-    ) -> Box<dyn WatchGuard + Send> {
-        self.storage.watch(handler)
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        let previous = self.store.replace(input, resource_version).await?;
+        self.evict(input);
+        Ok(previous)
     }
-    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
-        self.storage.count(opts, unscoped).await
+
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.store.delete_if(input, resource_version).await?;
+        self.evict(input);
+        Ok(())
+    }
+
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        let previous = self.store.discard(input, resource_version).await?;
+        self.evict(input);
+        Ok(previous)
+    }
+
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.store.list_deleted(opts, output).await
+    }
+
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        self.store.restore(input).await?;
+        self.evict(input);
+        Ok(())
+    }
+
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        self.store.purge(before).await
+    }
+}
+
+/// StatementCacher caches the statements of each subject, keyed by its
+/// principal type too, in front of store, like Cacher it is bounded by
+/// capacity and ttl.
+///
+/// An entry remembers the bindings its statements were derived from, it's
+/// evicted by the events of the stores it's `invalidated_by` that touch
+/// one of them and only by those.
+pub struct StatementCacher<S> {
+    store: S,
+    subjects: Option<Arc<Mutex<Subjects>>>,
+    ttl: Duration,
+    guards: Arc<Mutex<Vec<Box<dyn WatchGuard + Send>>>>,
+}
+
+struct Subjects {
+    entries: LruCache<Principal, (Instant, Vec<Statement>, Bindings)>,
+    generation: u64,
+}
+
+impl Subjects {
+    fn evict(&mut self, binding: Binding<'_>) {
+        self.generation += 1;
+        let (ids, id): (fn(&Bindings) -> &Vec<String>, _) = match binding {
+            Binding::Subject(principal_type, subject) => {
+                self.entries.pop(&(principal_type, subject.to_owned()));
+                return;
+            }
+            Binding::Group(id) => (|v| &v.groups, id),
+            Binding::Role(id) => (|v| &v.roles, id),
+            Binding::Policy(id) => (|v| &v.policies, id),
+        };
+        let subjects: Vec<Principal> = self
+            .entries
+            .iter()
+            .filter(|(_, (_, _, bindings))| {
+                ids(bindings).iter().any(|v| v == id)
+            })
+            .map(|(subject, _)| subject.clone())
+            .collect();
+        for subject in subjects {
+            self.entries.pop(&subject);
+        }
+    }
+}
+
+/// Principal is the key of the cached statements of a subject
+type Principal = (PrincipalType, String);
+
+/// Binding is what an event invalidates among the cached statements
+pub enum Binding<'a> {
+    Subject(PrincipalType, &'a str),
+    Group(&'a str),
+    Role(&'a str),
+    Policy(&'a str),
+}
+
+/// Invalidates is implemented by the entities the statements of a subject
+/// are derived from
+pub trait Invalidates {
+    fn invalidates(&self) -> Binding<'_>;
+}
+
+impl Invalidates for Policy {
+    fn invalidates(&self) -> Binding<'_> {
+        Binding::Policy(&self.id)
+    }
+}
+
+impl Invalidates for PolicyBinding {
+    fn invalidates(&self) -> Binding<'_> {
+        match self.bindings_type {
+            BindingsType::User => {
+                Binding::Subject(PrincipalType::User, &self.bindings_id)
+            }
+            BindingsType::Group => Binding::Group(&self.bindings_id),
+            BindingsType::Role => Binding::Role(&self.bindings_id),
+            BindingsType::Application => {
+                Binding::Subject(PrincipalType::Application, &self.bindings_id)
+            }
+            BindingsType::FederatedUser => Binding::Subject(
+                PrincipalType::FederatedUser,
+                &self.bindings_id,
+            ),
+        }
+    }
+}
+
+impl Invalidates for Group {
+    fn invalidates(&self) -> Binding<'_> {
+        Binding::Group(&self.id)
+    }
+}
+
+impl Invalidates for GroupUser {
+    fn invalidates(&self) -> Binding<'_> {
+        Binding::Subject(PrincipalType::User, &self.user_id)
+    }
+}
+
+impl Invalidates for Role {
+    fn invalidates(&self) -> Binding<'_> {
+        Binding::Role(&self.id)
+    }
+}
+
+impl Invalidates for RoleBinding {
+    fn invalidates(&self) -> Binding<'_> {
+        Binding::Subject((&self.user_type).into(), &self.user_id)
+    }
+}
+
+impl<S> StatementCacher<S> {
+    pub fn new(store: S, capacity: usize, ttl: Duration) -> Self {
+        let subjects = NonZeroUsize::new(capacity).map(|capacity| {
+            Arc::new(Mutex::new(Subjects {
+                entries: LruCache::new(capacity),
+                generation: 0,
+            }))
+        });
+        Self {
+            store,
+            subjects,
+            ttl,
+            guards: Arc::default(),
+        }
+    }
+
+    /// invalidated_by evicts the subjects whose bindings the events of
+    /// store touch
+    pub fn invalidated_by<W>(&self, store: &W)
+    where
+        W: WatchInterface,
+        W::T: Invalidates,
+    {
+        let Some(subjects) = &self.subjects else {
+            return;
+        };
+        let subjects = Arc::downgrade(subjects);
+        let guard = store.watch(0, move |event: Event<W::T>| {
+            if let Some(subjects) = subjects.upgrade() {
+                let mut subjects =
+                    subjects.lock().unwrap_or_else(PoisonError::into_inner);
+                subjects.evict(event.get().invalidates());
+                // a put moving a binding evicts its former subject too
+                if let Some(previous) = &event.previous {
+                    subjects.evict(previous.invalidates());
+                }
+            }
+        });
+        // a watch of the new events only can't be too old
+        let guard = guard.expect("watch from 0");
+        self.guards
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(guard);
+    }
+
+    fn lock(&self) -> Option<std::sync::MutexGuard<'_, Subjects>> {
+        self.subjects
+            .as_ref()
+            .map(|v| v.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// lookup works as `Cacher::lookup`
+    fn lookup(
+        &self,
+        principal: &Principal,
+    ) -> Option<std::result::Result<Vec<Statement>, u64>> {
+        let mut subjects = self.lock()?;
+        let generation = subjects.generation;
+        let now = chrono::Utc::now().naive_utc();
+        // an entry is stale past a binding taking effect or expiring too
+        let hit = match subjects.entries.get(principal) {
+            Some((cached_at, statements, bindings))
+                if cached_at.elapsed() < self.ttl
+                    && bindings.changes_at.is_none_or(|v| v > now) =>
+            {
+                Some(statements.clone())
+            }
+            Some(_) => {
+                subjects.entries.pop(principal);
+                None
+            }
+            None => None,
+        };
+        drop(subjects);
+        REQUESTS
+            .with_label_values(&[
+                "statement",
+                if hit.is_some() { "hit" } else { "miss" },
+            ])
+            .inc();
+        Some(hit.ok_or(generation))
+    }
+}
+
+#[async_trait]
+impl<S: StatementStore + Send + Sync> StatementStore for StatementCacher<S> {
+    async fn get_statement(&self, req: &Request) -> Result<Vec<Statement>> {
+        let principal = (req.principal_type, req.subject.clone());
+        let generation = match self.lookup(&principal) {
+            Some(Ok(statements)) => return Ok(statements),
+            Some(Err(generation)) => generation,
+            None => return self.store.get_statement(req).await,
+        };
+        let statements = self.store.get_statement(req).await?;
+        let bindings = self
+            .store
+            .get_bindings(req.principal_type, &req.subject)
+            .await?;
+        if let Some(mut subjects) = self.lock() {
+            if subjects.generation == generation {
+                subjects.entries.put(
+                    principal,
+                    (Instant::now(), statements.clone(), bindings),
+                );
+            }
+        }
+        Ok(statements)
+    }
+
+    async fn get_bindings(
+        &self,
+        principal_type: PrincipalType,
+        subject: &str,
+    ) -> Result<Bindings> {
+        self.store.get_bindings(principal_type, subject).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, thread::sleep};
+
+    use super::*;
+    use cim_pim::Effect;
+
+    use crate::{
+        changelog::ChangeStore, connection_manager, group::Group, memory,
+        role_binding::UserType, Pool, Tailer, TxConn, WatchStore,
+    };
+
+    /// Groups counts the reads reaching the store behind the cache
+    #[derive(Default)]
+    struct Groups {
+        rows: Mutex<HashMap<String, Group>>,
+        reads: Mutex<usize>,
+    }
+
+    impl Groups {
+        fn reads(&self) -> usize {
+            *self.reads.lock().unwrap()
+        }
+
+        fn set(&self, id: &str, name: &str) {
+            self.rows
+                .lock()
+                .unwrap()
+                .insert(id.to_owned(), group(id, name));
+        }
+    }
+
+    #[async_trait]
+    impl Interface for Groups {
+        type T = Group;
+        type L = ();
+
+        async fn put(&self, input: &Group) -> Result<()> {
+            self.set(&input.id, &input.name);
+            Ok(())
+        }
+
+        async fn delete(&self, input: &Group) -> Result<()> {
+            self.rows.lock().unwrap().remove(&input.id);
+            Ok(())
+        }
+
+        async fn get(&self, output: &mut Group) -> Result<()> {
+            *self.reads.lock().unwrap() += 1;
+            match self.rows.lock().unwrap().get(&output.id) {
+                Some(row) => *output = row.clone(),
+                None => return Err(cim_slo::errors::not_found("no rows")),
+            }
+            Ok(())
+        }
+
+        async fn list(&self, _: &(), output: &mut List<Group>) -> Result<()> {
+            *self.reads.lock().unwrap() += 1;
+            output.data = self.rows.lock().unwrap().values().cloned().collect();
+            Ok(())
+        }
+
+        async fn count(&self, _: &(), _: bool) -> Result<i64> {
+            Ok(self.rows.lock().unwrap().len() as i64)
+        }
+
+        async fn replace(
+            &self,
+            input: &Group,
+            _: Option<u64>,
+        ) -> Result<Option<Group>> {
+            Ok(self
+                .rows
+                .lock()
+                .unwrap()
+                .insert(input.id.clone(), group(&input.id, &input.name)))
+        }
+    }
+
+    fn group(id: &str, name: &str) -> Group {
+        Group {
+            id: id.to_owned(),
+            name: name.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn cacher(capacity: usize, ttl: Duration) -> Cacher<Arc<Groups>> {
+        let key: fn(&Group) -> String = |v| v.id.clone();
+        Cacher::new("group", Arc::new(Groups::default()), key, capacity, ttl)
+            .lists(|_| String::new())
+    }
+
+    async fn name(cache: &impl Interface<T = Group>, id: &str) -> String {
+        let mut output = group(id, "");
+        cache.get(&mut output).await.unwrap();
+        output.name
+    }
+
+    #[tokio::test]
+    async fn read_through() {
+        let cache = cacher(8, Duration::from_secs(60));
+        cache.store.set("1", "a");
+        assert_eq!(name(&cache, "1").await, "a");
+        cache.store.set("1", "b");
+        assert_eq!(name(&cache, "1").await, "a");
+        assert_eq!(cache.store.reads(), 1);
+
+        cache.put(&group("1", "c")).await.unwrap();
+        assert_eq!(name(&cache, "1").await, "c");
+        assert_eq!(cache.store.reads(), 2);
+
+        let mut list = List::default();
+        cache.list(&(), &mut list).await.unwrap();
+        cache.list(&(), &mut list).await.unwrap();
+        assert_eq!(list.data.len(), 1);
+        assert_eq!(cache.store.reads(), 3);
+        cache.delete(&group("1", "")).await.unwrap();
+        cache.list(&(), &mut list).await.unwrap();
+        assert!(list.data.is_empty());
+        assert!(cache.get(&mut group("1", "")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn ttl_and_capacity() {
+        let cache = cacher(1, Duration::from_millis(20));
+        cache.store.set("1", "a");
+        cache.store.set("2", "b");
+        name(&cache, "1").await;
+        name(&cache, "2").await;
+        name(&cache, "1").await;
+        assert_eq!(cache.store.reads(), 3);
+        name(&cache, "1").await;
+        assert_eq!(cache.store.reads(), 3);
+        sleep(Duration::from_millis(30));
+        name(&cache, "1").await;
+        assert_eq!(cache.store.reads(), 4);
+
+        let off = cacher(0, Duration::from_secs(60));
+        off.store.set("1", "a");
+        name(&off, "1").await;
+        name(&off, "1").await;
+        assert_eq!(off.store.reads(), 2);
+    }
+
+    #[tokio::test]
+    async fn invalidated_by_events() {
+        let changelog: Arc<dyn ChangeStore> =
+            Arc::new(memory::ChangelogImpl::new(memory::Database::default()));
+        let cache = cacher(8, Duration::from_secs(60));
+        let groups = Arc::clone(&cache.store);
+        let store = WatchStore::new(cache.clone())
+            .changelog("group", changelog.clone());
+        cache.invalidated_by(&store);
+        let mut tailer = Tailer::new(changelog.clone(), Duration::ZERO);
+        tailer.feed(&store);
+        tailer.poll().await.unwrap();
+
+        groups.set("1", "a");
+        assert_eq!(name(&store, "1").await, "a");
+        // another replica writes past the cache, its event evicts the entry
+        let replica =
+            WatchStore::new(groups.clone()).changelog("group", changelog);
+        replica.put(&group("1", "b")).await.unwrap();
+        assert_eq!(name(&store, "1").await, "a");
+        tailer.poll().await.unwrap();
+        assert_eq!(name(&store, "1").await, "b");
+        assert_eq!(groups.reads(), 2);
+
+        let hits = REQUESTS.with_label_values(&["group", "hit"]).get();
+        assert!(hits >= 1);
+    }
+
+    #[tokio::test]
+    async fn transaction_evicts_on_commit() {
+        let pool = connection_manager("memory://", 1, 1, false).await.unwrap();
+        let Pool::Memory(db) = &pool else {
+            unreachable!()
+        };
+        let key: fn(&Group) -> String = |v| v.id.clone();
+        let cache = Cacher::new(
+            "group",
+            memory::GroupImpl::new(db.clone()),
+            key,
+            8,
+            Duration::from_secs(60),
+        );
+        cache.put(&group("1", "a")).await.unwrap();
+        assert_eq!(name(&cache, "1").await, "a");
+
+        let tx = pool.begin().await.unwrap();
+        let TxConn::Memory(conn) = tx.conn() else {
+            unreachable!()
+        };
+        let tx_cache =
+            cache.transaction(memory::GroupImpl::new(conn.clone()), &tx);
+        tx_cache.put(&group("1", "b")).await.unwrap();
+        assert_eq!(name(&tx_cache, "1").await, "b");
+        assert_eq!(name(&cache, "1").await, "a");
+        tx.commit().await.unwrap();
+        assert_eq!(name(&cache, "1").await, "b");
+    }
+
+    fn statements(actions: &[&str]) -> Vec<Statement> {
+        actions
+            .iter()
+            .map(|action| Statement {
+                effect: Effect::Allow,
+                subjects: Vec::new(),
+                actions: vec![action.to_string()],
+                resources: Vec::new(),
+                conditions: None,
+                meta: None,
+            })
+            .collect()
+    }
+
+    async fn actions(cache: &impl StatementStore, subject: &str) -> usize {
+        principal_actions(cache, PrincipalType::User, subject).await
+    }
+
+    async fn principal_actions(
+        cache: &impl StatementStore,
+        principal_type: PrincipalType,
+        subject: &str,
+    ) -> usize {
+        let req = Request {
+            resource: String::new(),
+            action: String::new(),
+            subject: subject.to_owned(),
+            principal_type,
+            context: HashMap::new(),
+        };
+        cache.get_statement(&req).await.unwrap().len()
+    }
+
+    fn member(id: &str, group_id: &str, user_id: &str) -> GroupUser {
+        GroupUser {
+            id: id.to_owned(),
+            group_id: group_id.to_owned(),
+            user_id: user_id.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn statements_by_subject() {
+        let db = memory::Database::default();
+        let cache = StatementCacher::new(
+            memory::PolicyImpl::new(db.clone()),
+            8,
+            Duration::from_secs(60),
+        );
+        let policies = WatchStore::new(memory::PolicyImpl::new(db.clone()));
+        let groups = WatchStore::new(memory::GroupImpl::new(db.clone()));
+        let members = WatchStore::new(memory::GroupUserImpl::new(db.clone()));
+        let bindings =
+            WatchStore::new(memory::PolicyBindingImpl::new(db.clone()));
+        cache.invalidated_by(&policies);
+        cache.invalidated_by(&groups);
+        cache.invalidated_by(&members);
+        cache.invalidated_by(&bindings);
+
+        let mut policy = Policy {
+            id: "p".to_owned(),
+            statement: statements(&["a"]),
+            ..Default::default()
+        };
+        policies.put(&policy).await.unwrap();
+        groups.put(&group("g", "")).await.unwrap();
+        groups.put(&group("h", "")).await.unwrap();
+        members.put(&member("1", "g", "1")).await.unwrap();
+        members.put(&member("2", "g", "2")).await.unwrap();
+        let binding = PolicyBinding {
+            id: "1".to_owned(),
+            policy_id: "p".to_owned(),
+            bindings_type: BindingsType::Group,
+            bindings_id: "g".to_owned(),
+            ..Default::default()
+        };
+        bindings.put(&binding).await.unwrap();
+        assert_eq!(actions(&cache, "1").await, 1);
+        assert_eq!(actions(&cache, "2").await, 1);
+
+        // written past the watch stores, the cache doesn't know
+        policy.statement = statements(&["a", "b"]);
+        memory::PolicyImpl::new(db.clone())
+            .put(&policy)
+            .await
+            .unwrap();
+        assert_eq!(actions(&cache, "1").await, 1);
+
+        // bindings of other subjects leave the entries alone
+        members.put(&member("3", "h", "3")).await.unwrap();
+        groups.put(&group("h", "renamed")).await.unwrap();
+        assert_eq!(actions(&cache, "1").await, 1);
+
+        policies.put(&policy).await.unwrap();
+        assert_eq!(actions(&cache, "1").await, 2);
+        assert_eq!(actions(&cache, "2").await, 2);
+
+        policy.statement = statements(&["a", "b", "c"]);
+        memory::PolicyImpl::new(db.clone())
+            .put(&policy)
+            .await
+            .unwrap();
+        members.delete(&member("1", "g", "1")).await.unwrap();
+        assert_eq!(actions(&cache, "1").await, 0);
+        assert_eq!(actions(&cache, "2").await, 2);
+        bindings.delete(&binding).await.unwrap();
+        assert_eq!(actions(&cache, "2").await, 0);
+    }
+
+    #[tokio::test]
+    async fn statements_by_principal() {
+        let db = memory::Database::default();
+        let cache = StatementCacher::new(
+            memory::PolicyImpl::new(db.clone()),
+            8,
+            Duration::from_secs(60),
+        );
+        let role_bindings =
+            WatchStore::new(memory::RoleBindingImpl::new(db.clone()));
+        cache.invalidated_by(&role_bindings);
+
+        let mut policy = Policy {
+            id: "p".to_owned(),
+            statement: statements(&["a"]),
+            ..Default::default()
+        };
+        memory::PolicyImpl::new(db.clone())
+            .put(&policy)
+            .await
+            .unwrap();
+        memory::RoleImpl::new(db.clone())
+            .put(&Role {
+                id: "r".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        memory::PolicyBindingImpl::new(db.clone())
+            .put(&PolicyBinding {
+                id: "1".to_owned(),
+                policy_id: "p".to_owned(),
+                bindings_type: BindingsType::Role,
+                bindings_id: "r".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let binding = RoleBinding {
+            id: "1".to_owned(),
+            role_id: "r".to_owned(),
+            user_type: UserType::Application,
+            user_id: "1".to_owned(),
+            ..Default::default()
+        };
+        role_bindings.put(&binding).await.unwrap();
+
+        // the user and the application are cached apart
+        let app = PrincipalType::Application;
+        assert_eq!(principal_actions(&cache, app, "1").await, 1);
+        assert_eq!(actions(&cache, "1").await, 0);
+
+        policy.statement = statements(&["a", "b"]);
+        memory::PolicyImpl::new(db.clone())
+            .put(&policy)
+            .await
+            .unwrap();
+        role_bindings
+            .put(&RoleBinding {
+                id: "2".to_owned(),
+                user_type: UserType::User,
+                ..binding.clone()
+            })
+            .await
+            .unwrap();
+        assert_eq!(actions(&cache, "1").await, 2);
+        assert_eq!(principal_actions(&cache, app, "1").await, 1);
+        role_bindings.delete(&binding).await.unwrap();
+        assert_eq!(principal_actions(&cache, app, "1").await, 0);
+    }
+
+    #[tokio::test]
+    async fn moved_binding_evicts_former_subject() {
+        let db = memory::Database::default();
+        let cache = StatementCacher::new(
+            memory::PolicyImpl::new(db.clone()),
+            8,
+            Duration::from_secs(60),
+        );
+        let role_bindings =
+            WatchStore::new(memory::RoleBindingImpl::new(db.clone()));
+        cache.invalidated_by(&role_bindings);
+
+        memory::PolicyImpl::new(db.clone())
+            .put(&Policy {
+                id: "p".to_owned(),
+                statement: statements(&["a"]),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        memory::RoleImpl::new(db.clone())
+            .put(&Role {
+                id: "r".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        memory::PolicyBindingImpl::new(db.clone())
+            .put(&PolicyBinding {
+                id: "1".to_owned(),
+                policy_id: "p".to_owned(),
+                bindings_type: BindingsType::Role,
+                bindings_id: "r".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let binding = RoleBinding {
+            id: "1".to_owned(),
+            role_id: "r".to_owned(),
+            user_id: "1".to_owned(),
+            ..Default::default()
+        };
+        role_bindings.put(&binding).await.unwrap();
+        assert_eq!(actions(&cache, "1").await, 1);
+
+        // the binding moves from user 1 to user 2
+        role_bindings
+            .put(&RoleBinding {
+                user_id: "2".to_owned(),
+                ..binding
+            })
+            .await
+            .unwrap();
+        assert_eq!(actions(&cache, "1").await, 0);
+        assert_eq!(actions(&cache, "2").await, 1);
     }
 }
@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use cim_slo::Result;
+
+/// Change is a row of the changelog, the outbox the watched stores append
+/// their events to so that every replica can tail them
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone)]
+pub struct Change {
+    /// id grows with every append, watchers see it as the modify index
+    pub id: u64,
+    /// kind is the table of the entity, e.g. `user`
+    pub kind: String,
+    /// payload is the json encoded `Event` of the entity
+    pub payload: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[async_trait]
+pub trait ChangeStore: Send + Sync {
+    /// append adds a change, it belongs to the transaction of the store
+    async fn append(&self, kind: &str, payload: &str) -> Result<()>;
+    /// since returns up to limit changes with an id above id, oldest first
+    async fn since(&self, id: u64, limit: i64) -> Result<Vec<Change>>;
+    /// last returns the id of the newest change, 0 for an empty changelog
+    async fn last(&self) -> Result<u64>;
+    /// purge removes the changes created before, returning how many
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64>;
+}
+
+#[async_trait]
+impl<T: ChangeStore + ?Sized> ChangeStore for Arc<T> {
+    async fn append(&self, kind: &str, payload: &str) -> Result<()> {
+        (**self).append(kind, payload).await
+    }
+
+    async fn since(&self, id: u64, limit: i64) -> Result<Vec<Change>> {
+        (**self).since(id, limit).await
+    }
+
+    async fn last(&self) -> Result<u64> {
+        (**self).last().await
+    }
+
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        (**self).purge(before).await
+    }
+}
@@ -1,7 +1,11 @@
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone, utoipa::ToSchema)]
+use crate::label::Labels;
+
+#[derive(
+    Debug, Default, Deserialize, Serialize, PartialEq, Clone, utoipa::ToSchema,
+)]
 pub struct Client {
     pub id: String,
     pub secret: String,
@@ -10,6 +14,12 @@ pub struct Client {
     pub name: String,
     pub logo_url: String,
     pub account_id: String,
+    #[serde(default)]
+    pub labels: Labels,
+    #[serde(default)]
+    pub annotations: Labels,
+    #[serde(default)]
+    pub resource_version: u64,
 
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
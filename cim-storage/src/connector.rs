@@ -2,7 +2,9 @@ use serde::{Deserialize, Serialize};
 use serde_json::value::RawValue;
 use validator::Validate;
 
-use crate::Pagination;
+use cim_slo::Result;
+
+use crate::{Filter, Keyed, Pagination, Param};
 
 #[derive(Debug, Default, Deserialize, Serialize, Clone, utoipa::ToSchema)]
 pub struct Connector {
@@ -14,6 +16,8 @@ pub struct Connector {
     pub config: String,
     #[schema(format = Binary, value_type = String)]
     pub connector_data: Option<Box<RawValue>>,
+    #[serde(default)]
+    pub resource_version: u64,
 }
 
 impl PartialEq for Connector {
@@ -23,6 +27,7 @@ impl PartialEq for Connector {
             && self.name == other.name
             && self.response_version == other.response_version
             && self.config == other.config
+            && self.resource_version == other.resource_version
         {
             if let Some(connector_data) = &self.connector_data {
                 if let Some(other_connector_data) = &other.connector_data {
@@ -43,3 +48,25 @@ pub struct ListParams {
     #[validate(nested)]
     pub pagination: Pagination,
 }
+
+impl ListParams {
+    /// SORTABLE are the columns order_by may name
+    pub const SORTABLE: &'static [&'static str] =
+        &["id", "type", "name", "created_at", "updated_at"];
+
+    /// filter is the WHERE clause of the sql stores
+    pub(crate) fn filter(&self) -> Result<Filter> {
+        Ok(Filter::default().eq("type", self.connector_type.as_ref()))
+    }
+}
+
+impl Keyed for Connector {
+    fn key(&self, column: &str) -> Option<Param> {
+        match column {
+            "id" => Some(Param::id(&self.id)),
+            "type" => Some((&self.connector_type).into()),
+            "name" => Some((&self.name).into()),
+            _ => None,
+        }
+    }
+}
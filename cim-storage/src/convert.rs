@@ -59,6 +59,14 @@ pub fn convert_param(buf: &mut String, s: &str) {
     buf.push('\'');
 }
 
+/// convert_standard_param quotes s as a standard sql string literal,
+/// which escapes `'` by doubling it instead of the backslash used by mysql
+pub fn convert_standard_param(buf: &mut String, s: &str) {
+    buf.push('\'');
+    buf.push_str(&s.replace('\'', "''"));
+    buf.push('\'');
+}
+
 pub fn convert_field(s: &str) -> String {
     let mut buf = String::new();
     convert_param(&mut buf, s);
@@ -83,6 +91,13 @@ pub fn update_set_param(buf: &mut String, name: &str, value: &Option<String>) {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_convert_standard_param() {
+        let mut buf = String::new();
+        convert_standard_param(&mut buf, "a'b");
+        assert_eq!(buf, "'a''b'");
+    }
+
     #[test]
     fn test_quote_to() {
         let mut writer = String::new();
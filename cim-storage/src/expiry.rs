@@ -0,0 +1,73 @@
+use chrono::NaiveDateTime;
+use validator::ValidationError;
+
+/// is_active tells if a binding in effect from not_before until expires_at
+/// is at now, a none bound leaves its side open
+pub fn is_active(
+    not_before: Option<NaiveDateTime>,
+    expires_at: Option<NaiveDateTime>,
+    now: NaiveDateTime,
+) -> bool {
+    not_before.is_none_or(|v| v <= now) && expires_at.is_none_or(|v| v > now)
+}
+
+/// next_change is the earliest bound of the windows after now, when one of
+/// the bindings takes effect or expires
+pub fn next_change<I>(windows: I, now: NaiveDateTime) -> Option<NaiveDateTime>
+where
+    I: IntoIterator<Item = (Option<NaiveDateTime>, Option<NaiveDateTime>)>,
+{
+    windows
+        .into_iter()
+        .flat_map(|(not_before, expires_at)| [not_before, expires_at])
+        .flatten()
+        .filter(|v| *v > now)
+        .min()
+}
+
+/// check_window rejects a binding which expires before it takes effect
+pub fn check_window(
+    not_before: Option<NaiveDateTime>,
+    expires_at: Option<NaiveDateTime>,
+) -> Result<(), ValidationError> {
+    match (not_before, expires_at) {
+        (Some(not_before), Some(expires_at)) if expires_at <= not_before => {
+            Err(ValidationError::new("expires_at must be after not_before"))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::*;
+
+    #[test]
+    fn window() {
+        let now = Utc::now().naive_utc();
+        let hour = Duration::hours(1);
+        assert!(is_active(None, None, now));
+        assert!(is_active(Some(now), Some(now + hour), now));
+        assert!(!is_active(Some(now + hour), None, now));
+        assert!(!is_active(None, Some(now), now));
+
+        assert_eq!(
+            next_change(
+                [
+                    (Some(now - hour), Some(now + hour * 2)),
+                    (Some(now + hour), None),
+                    (None, None),
+                ],
+                now
+            ),
+            Some(now + hour)
+        );
+        assert_eq!(next_change([(Some(now - hour), None)], now), None);
+
+        assert!(check_window(Some(now), Some(now + hour)).is_ok());
+        assert!(check_window(Some(now), Some(now)).is_err());
+        assert!(check_window(None, Some(now)).is_ok());
+    }
+}
@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use cim_slo::Result;
+
+/// GcStore removes the oidc artifacts which can't be used anymore, they are
+/// deleted for good rather than soft deleted
+#[async_trait]
+pub trait GcStore: Send + Sync {
+    /// purge_auth_requests removes the auth requests whose expiry, in unix
+    /// seconds, is before now, returning how many
+    async fn purge_auth_requests(&self, now: i64) -> Result<u64>;
+    /// purge_auth_codes removes the auth codes whose expiry, in unix
+    /// seconds, is before now, returning how many
+    async fn purge_auth_codes(&self, now: i64) -> Result<u64>;
+    /// purge_refresh_tokens removes the refresh tokens created before
+    /// created_before or last used before used_before, a none bound never
+    /// matches
+    async fn purge_refresh_tokens(
+        &self,
+        created_before: Option<NaiveDateTime>,
+        used_before: Option<NaiveDateTime>,
+    ) -> Result<u64>;
+}
+
+#[async_trait]
+impl<T: GcStore + ?Sized> GcStore for Arc<T> {
+    async fn purge_auth_requests(&self, now: i64) -> Result<u64> {
+        (**self).purge_auth_requests(now).await
+    }
+
+    async fn purge_auth_codes(&self, now: i64) -> Result<u64> {
+        (**self).purge_auth_codes(now).await
+    }
+
+    async fn purge_refresh_tokens(
+        &self,
+        created_before: Option<NaiveDateTime>,
+        used_before: Option<NaiveDateTime>,
+    ) -> Result<u64> {
+        (**self)
+            .purge_refresh_tokens(created_before, used_before)
+            .await
+    }
+}
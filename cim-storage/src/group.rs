@@ -1,15 +1,32 @@
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
-use crate::Pagination;
+use cim_slo::Result;
 
-#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone, utoipa::ToSchema)]
+use crate::{
+    label::{check_annotations, check_labels, Labels, Selector},
+    Filter, Keyed, Pagination, Param,
+};
+
+#[derive(
+    Debug, Default, Deserialize, Serialize, PartialEq, Clone, utoipa::ToSchema,
+)]
 pub struct Group {
     pub id: String,
     pub account_id: String,
     pub name: String,
     pub desc: String,
+    /// parent_id is the group this one is nested in, its members are
+    /// members of the parent too
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    #[serde(default)]
+    pub labels: Labels,
+    #[serde(default)]
+    pub annotations: Labels,
+    #[serde(default)]
+    pub resource_version: u64,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
@@ -20,6 +37,14 @@ pub struct Content {
     pub name: String,
     #[validate(length(min = 1, max = 255))]
     pub desc: String,
+    #[validate(length(min = 1))]
+    pub parent_id: Option<String>,
+    #[serde(default)]
+    #[validate(custom(function = "check_labels"))]
+    pub labels: Labels,
+    #[serde(default)]
+    #[validate(custom(function = "check_annotations"))]
+    pub annotations: Labels,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -28,7 +53,74 @@ pub struct ListParams {
     pub id: Option<String>,
     #[validate(length(min = 1))]
     pub account_id: Option<String>,
+    /// parent_id selects the groups nested right in a group
+    #[validate(length(min = 1))]
+    pub parent_id: Option<String>,
+    /// user_id selects the groups a user is a member of
+    #[validate(length(min = 1))]
+    pub user_id: Option<String>,
+    /// effective_user_id selects the groups a user is a member of, and the
+    /// groups they are nested in, through the memberships in effect
+    #[validate(length(min = 1))]
+    pub effective_user_id: Option<String>,
+    /// label_selector selects by labels, e.g. `env=prod,tier in (web,api)`
+    pub label_selector: Option<Selector>,
     #[serde(flatten)]
     #[validate(nested)]
     pub pagination: Pagination,
 }
+
+impl ListParams {
+    /// SORTABLE are the columns order_by may name
+    pub const SORTABLE: &'static [&'static str] =
+        &["id", "account_id", "name", "created_at", "updated_at"];
+
+    /// filter is the WHERE clause of the sql stores
+    pub(crate) fn filter(&self) -> Result<Filter> {
+        let mut filter = Filter::default()
+            .id("id", self.id.as_deref())?
+            .id("account_id", self.account_id.as_deref())?
+            .id("parent_id", self.parent_id.as_deref())?;
+        if let Some(user_id) = &self.user_id {
+            filter = filter.in_select(
+                "id",
+                "group_user",
+                "group_id",
+                Filter::default()
+                    .id("user_id", Some(user_id))?
+                    .not_deleted(),
+            );
+        }
+        if let Some(user_id) = &self.effective_user_id {
+            let groups = Filter::default()
+                .in_select(
+                    "id",
+                    "group_user",
+                    "group_id",
+                    Filter::default()
+                        .id("user_id", Some(user_id))?
+                        .not_deleted()
+                        .active(Utc::now().naive_utc()),
+                )
+                .not_deleted();
+            filter = filter.in_tree("id", "group", "parent_id", groups, true);
+        }
+        if let Some(selector) = &self.label_selector {
+            filter = selector.filter(filter, "group");
+        }
+        Ok(filter)
+    }
+}
+
+impl Keyed for Group {
+    fn key(&self, column: &str) -> Option<Param> {
+        match column {
+            "id" => Some(Param::id(&self.id)),
+            "account_id" => Some(Param::id(&self.account_id)),
+            "name" => Some((&self.name).into()),
+            "created_at" => Some(self.created_at.into()),
+            "updated_at" => Some(self.updated_at.into()),
+            _ => None,
+        }
+    }
+}
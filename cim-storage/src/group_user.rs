@@ -1,35 +1,96 @@
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
-use crate::Pagination;
+use cim_slo::Result;
 
-#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone, utoipa::ToSchema)]
+use crate::{expiry::check_window, Filter, Keyed, Pagination, Param};
+
+#[derive(
+    Debug, Default, Deserialize, Serialize, PartialEq, Clone, utoipa::ToSchema,
+)]
 pub struct GroupUser {
     pub id: String,
     pub group_id: String,
     pub user_id: String,
+    /// not_before is when the binding takes effect, right away when none
+    #[serde(default)]
+    pub not_before: Option<NaiveDateTime>,
+    /// expires_at is when the binding ends, it's deleted once past it
+    #[serde(default)]
+    pub expires_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub resource_version: u64,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
 
 #[derive(Debug, Deserialize, Validate)]
+#[validate(schema(function = "check_content"))]
 pub struct Content {
     #[validate(length(min = 1, max = 255))]
     pub group_id: String,
     #[validate(length(min = 1, max = 255))]
     pub user_id: String,
+    pub not_before: Option<NaiveDateTime>,
+    pub expires_at: Option<NaiveDateTime>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
 pub struct ListParams {
     #[validate(length(min = 1))]
     pub id: Option<String>,
+    /// account_id lists the memberships of the groups of the account
+    #[validate(length(min = 1))]
+    pub account_id: Option<String>,
     #[validate(length(min = 1))]
     pub group_id: Option<String>,
     #[validate(length(min = 1))]
     pub user_id: Option<String>,
+    /// expires_before lists the bindings which expire before it
+    pub expires_before: Option<NaiveDateTime>,
     #[serde(flatten)]
     #[validate(nested)]
     pub pagination: Pagination,
 }
+
+impl ListParams {
+    /// SORTABLE are the columns order_by may name
+    pub const SORTABLE: &'static [&'static str] =
+        &["id", "group_id", "user_id", "created_at", "updated_at"];
+
+    /// filter is the WHERE clause of the sql stores
+    pub(crate) fn filter(&self) -> Result<Filter> {
+        let mut filter = Filter::default()
+            .id("id", self.id.as_deref())?
+            .id("group_id", self.group_id.as_deref())?
+            .id("user_id", self.user_id.as_deref())?
+            .lt("expires_at", self.expires_before);
+        if let Some(account_id) = &self.account_id {
+            filter = filter.in_select(
+                "group_id",
+                "group",
+                "id",
+                Filter::default().id("account_id", Some(account_id))?,
+            );
+        }
+        Ok(filter)
+    }
+}
+
+fn check_content(content: &Content) -> Result<(), ValidationError> {
+    check_window(content.not_before, content.expires_at)
+}
+
+impl Keyed for GroupUser {
+    fn key(&self, column: &str) -> Option<Param> {
+        match column {
+            "id" => Some(Param::id(&self.id)),
+            "group_id" => Some(Param::id(&self.group_id)),
+            "user_id" => Some(Param::id(&self.user_id)),
+            "created_at" => Some(self.created_at.into()),
+            "updated_at" => Some(self.updated_at.into()),
+            _ => None,
+        }
+    }
+}
@@ -1,15 +1,64 @@
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
 use jsonwebkey as jwk;
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, Serialize, PartialEq, Clone, utoipa::ToSchema)]
+use cim_slo::{errors, Result};
+
+/// Keys are the keys the tokens are signed with, they are handed out as
+/// PublicKeys since they hold the private key
+#[derive(Deserialize, Serialize, PartialEq, Clone)]
 pub struct Keys {
     pub id: String,
-    #[schema(format = Binary, value_type = String)]
+    /// signing_key is the private key, it's sealed by the key ring of the
+    /// store
     pub signing_key: jwk::JsonWebKey,
+    pub signing_key_pub: jwk::JsonWebKey,
+    pub verification_keys: Vec<VerificationKey>,
+    pub next_rotation: i64,
+    #[serde(default)]
+    pub resource_version: u64,
+}
+
+/// PublicKeys is the view of Keys without the private key
+#[derive(Debug, Serialize, PartialEq, Clone, utoipa::ToSchema)]
+pub struct PublicKeys {
+    pub id: String,
     #[schema(format = Binary, value_type = String)]
     pub signing_key_pub: jwk::JsonWebKey,
     pub verification_keys: Vec<VerificationKey>,
     pub next_rotation: i64,
+    pub resource_version: u64,
+}
+
+impl From<Keys> for PublicKeys {
+    fn from(keys: Keys) -> Self {
+        Self {
+            id: keys.id,
+            signing_key_pub: keys.signing_key_pub,
+            verification_keys: keys.verification_keys,
+            next_rotation: keys.next_rotation,
+            resource_version: keys.resource_version,
+        }
+    }
+}
+
+impl fmt::Debug for Keys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keys")
+            .field("id", &self.id)
+            .field("signing_key", &"<redacted>")
+            .field("signing_key_pub", &self.signing_key_pub)
+            .field("verification_keys", &self.verification_keys)
+            .field("next_rotation", &self.next_rotation)
+            .field("resource_version", &self.resource_version)
+            .finish()
+    }
 }
 
 impl Default for Keys {
@@ -20,6 +69,7 @@ impl Default for Keys {
             signing_key_pub: jwk::JsonWebKey::new(jwk::Key::generate_p256()),
             verification_keys: vec![],
             next_rotation: 0,
+            resource_version: 0,
         }
     }
 }
@@ -39,3 +89,141 @@ impl Default for VerificationKey {
         }
     }
 }
+
+/// KeyRing holds the key encryption keys sealing the signing keys at rest.
+/// The first key seals, the others are kept to open what they sealed until
+/// the rows are sealed again. An empty ring keeps signing keys in plain
+#[derive(Clone, Default)]
+pub struct KeyRing {
+    primary: Option<String>,
+    keys: Arc<HashMap<String, LessSafeKey>>,
+}
+
+/// Envelope is a signing key sealed by a data key of its own, which is
+/// sealed by the key encryption key kek
+#[derive(Deserialize, Serialize)]
+struct Envelope {
+    kek: String,
+    dek: String,
+    data: String,
+}
+
+impl KeyRing {
+    /// parse reads `id:key` pairs separated by commas or lines, keys are 32
+    /// bytes encoded in base64 and the first one seals
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut primary = None;
+        let mut keys = HashMap::new();
+        for entry in spec
+            .split([',', '\n'])
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+        {
+            let (id, key) = entry.split_once(':').ok_or_else(|| {
+                errors::anyhow(anyhow::anyhow!(
+                    "key encryption key must be id:key"
+                ))
+            })?;
+            let key = STANDARD.decode(key).map_err(errors::any)?;
+            if keys.insert(id.to_owned(), cipher(&key)?).is_some() {
+                return Err(errors::anyhow(anyhow::anyhow!(
+                    "key encryption key {id} is given twice"
+                )));
+            }
+            primary.get_or_insert_with(|| id.to_owned());
+        }
+        Ok(Self {
+            primary,
+            keys: Arc::new(keys),
+        })
+    }
+
+    /// seal encrypts the signing key with the first key of the ring
+    pub(crate) fn seal(
+        &self,
+        signing_key: &jwk::JsonWebKey,
+    ) -> Result<serde_json::Value> {
+        let Some(kek) = &self.primary else {
+            return serde_json::to_value(signing_key).map_err(errors::any);
+        };
+        let mut dek = [0; 32];
+        SystemRandom::new().fill(&mut dek).map_err(errors::any)?;
+        let data = serde_json::to_vec(signing_key).map_err(errors::any)?;
+        let envelope = Envelope {
+            kek: kek.clone(),
+            dek: encrypt(&self.keys[kek], kek, &dek)?,
+            data: encrypt(&cipher(&dek)?, kek, &data)?,
+        };
+        serde_json::to_value(envelope).map_err(errors::any)
+    }
+
+    /// open decrypts a signing key sealed by any key of the ring, a plain
+    /// one is returned as it is
+    pub(crate) fn open(
+        &self,
+        value: serde_json::Value,
+    ) -> Result<jwk::JsonWebKey> {
+        if value.get("kek").is_none() {
+            return serde_json::from_value(value).map_err(errors::any);
+        }
+        let envelope: Envelope =
+            serde_json::from_value(value).map_err(errors::any)?;
+        let kek = self.keys.get(&envelope.kek).ok_or_else(|| {
+            errors::anyhow(anyhow::anyhow!(
+                "key encryption key {} is not in the ring",
+                envelope.kek
+            ))
+        })?;
+        let dek = decrypt(kek, &envelope.kek, &envelope.dek)?;
+        let data = decrypt(&cipher(&dek)?, &envelope.kek, &envelope.data)?;
+        serde_json::from_slice(&data).map_err(errors::any)
+    }
+}
+
+impl fmt::Debug for KeyRing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyRing")
+            .field("primary", &self.primary)
+            .field("keys", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+fn cipher(key: &[u8]) -> Result<LessSafeKey> {
+    let key = UnboundKey::new(&AES_256_GCM, key).map_err(|_| {
+        errors::anyhow(anyhow::anyhow!("key encryption key must be 32 bytes"))
+    })?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// encrypt seals plain with key, the base64 output is prefixed by the nonce
+/// and bound to the key encryption key kek
+fn encrypt(key: &LessSafeKey, kek: &str, plain: &[u8]) -> Result<String> {
+    let mut nonce = [0; NONCE_LEN];
+    SystemRandom::new().fill(&mut nonce).map_err(errors::any)?;
+    let mut data = plain.to_vec();
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::from(kek.as_bytes()),
+        &mut data,
+    )
+    .map_err(errors::any)?;
+    Ok(STANDARD.encode([nonce.as_slice(), &data].concat()))
+}
+
+fn decrypt(key: &LessSafeKey, kek: &str, sealed: &str) -> Result<Vec<u8>> {
+    let mut data = STANDARD.decode(sealed).map_err(errors::any)?;
+    if data.len() < NONCE_LEN {
+        return Err(errors::anyhow(anyhow::anyhow!("sealed key is truncated")));
+    }
+    let mut nonce = [0; NONCE_LEN];
+    nonce.copy_from_slice(&data[..NONCE_LEN]);
+    let plain = key
+        .open_in_place(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(kek.as_bytes()),
+            &mut data[NONCE_LEN..],
+        )
+        .map_err(errors::any)?;
+    Ok(plain.to_vec())
+}
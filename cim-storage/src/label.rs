@@ -0,0 +1,325 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer};
+use validator::ValidationError;
+
+use cim_slo::regexp::{check_label_key, check_label_value};
+
+use crate::{Filter, Param};
+
+/// Labels tag an entity with identifying key/value pairs, they are indexed
+/// and can be selected on. Annotations share the type but are not indexed
+pub type Labels = BTreeMap<String, String>;
+
+/// KINDS are the tables whose rows are labeled, the `label` table indexes
+/// their labels by `kind`
+pub(crate) const KINDS: &[&str] =
+    &["user", "group", "role", "policy", "client"];
+
+/// ANNOTATIONS_MAX is the total size in bytes of the annotations of an
+/// entity
+const ANNOTATIONS_MAX: usize = 256 * 1024;
+
+pub fn check_labels(labels: &Labels) -> Result<(), ValidationError> {
+    for (key, value) in labels {
+        check_label_key(key)?;
+        check_label_value(value)?;
+    }
+    Ok(())
+}
+
+pub fn check_annotations(annotations: &Labels) -> Result<(), ValidationError> {
+    let mut size = 0;
+    for (key, value) in annotations {
+        check_label_key(key)?;
+        size += key.len() + value.len();
+    }
+    if size > ANNOTATIONS_MAX {
+        return Err(ValidationError::new("annotations are too large"));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Op {
+    In(Vec<String>),
+    NotIn(Vec<String>),
+    Exists,
+    NotExists,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Requirement {
+    key: String,
+    op: Op,
+}
+
+impl Requirement {
+    fn matches(&self, labels: &Labels) -> bool {
+        let value = labels.get(&self.key);
+        match &self.op {
+            Op::In(values) => value.is_some_and(|v| values.contains(v)),
+            Op::NotIn(values) => value.is_none_or(|v| !values.contains(v)),
+            Op::Exists => value.is_some(),
+            Op::NotExists => value.is_none(),
+        }
+    }
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.op {
+            Op::In(values) if values.len() == 1 => {
+                write!(f, "{}={}", self.key, values[0])
+            }
+            Op::NotIn(values) if values.len() == 1 => {
+                write!(f, "{}!={}", self.key, values[0])
+            }
+            Op::In(values) => {
+                write!(f, "{} in ({})", self.key, values.join(","))
+            }
+            Op::NotIn(values) => {
+                write!(f, "{} notin ({})", self.key, values.join(","))
+            }
+            Op::Exists => write!(f, "{}", self.key),
+            Op::NotExists => write!(f, "!{}", self.key),
+        }
+    }
+}
+
+impl FromStr for Requirement {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || anyhow::anyhow!("invalid label requirement {}", s);
+        let (key, op) = if let Some(key) = s.strip_prefix('!') {
+            (key.trim(), Op::NotExists)
+        } else if let Some((key, value)) = s.split_once("!=") {
+            (key.trim(), Op::NotIn(vec![value.trim().to_owned()]))
+        } else if let Some((key, value)) =
+            s.split_once("==").or_else(|| s.split_once('='))
+        {
+            (key.trim(), Op::In(vec![value.trim().to_owned()]))
+        } else if let Some((key, rest)) = s.split_once(char::is_whitespace) {
+            let rest = rest.trim_start();
+            let (notin, values) = match rest.strip_prefix("notin") {
+                Some(values) => (true, values),
+                None => (false, rest.strip_prefix("in").ok_or_else(invalid)?),
+            };
+            let values: Vec<String> = values
+                .trim()
+                .strip_prefix('(')
+                .and_then(|v| v.strip_suffix(')'))
+                .ok_or_else(invalid)?
+                .split(',')
+                .map(|v| v.trim().to_owned())
+                .collect();
+            (
+                key,
+                if notin {
+                    Op::NotIn(values)
+                } else {
+                    Op::In(values)
+                },
+            )
+        } else {
+            (s, Op::Exists)
+        };
+        check_label_key(key).map_err(|_| invalid())?;
+        if let Op::In(values) | Op::NotIn(values) = &op {
+            for value in values {
+                check_label_value(value).map_err(|_| invalid())?;
+            }
+        }
+        Ok(Requirement {
+            key: key.to_owned(),
+            op,
+        })
+    }
+}
+
+/// Selector selects the entities by their labels with the Kubernetes
+/// syntax, comma separated requirements which must all match:
+/// `env=prod`, `env==prod`, `env!=prod`, `env in (prod,test)`,
+/// `env notin (prod,test)`, `env` and `!env`. Like Kubernetes, `!=` and
+/// `notin` match the entities without the label
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selector {
+    requirements: Vec<Requirement>,
+}
+
+impl Selector {
+    pub fn is_empty(&self) -> bool {
+        self.requirements.is_empty()
+    }
+
+    /// matches reports whether labels satisfy every requirement, it's how
+    /// the memory store and the watchers select
+    pub fn matches(&self, labels: &Labels) -> bool {
+        self.requirements.iter().all(|v| v.matches(labels))
+    }
+
+    /// filter narrows filter to the rows of kind whose labels, as indexed
+    /// in the `label` table, satisfy every requirement
+    pub(crate) fn filter(
+        &self,
+        mut filter: Filter,
+        kind: &'static str,
+    ) -> Filter {
+        for requirement in &self.requirements {
+            let labels = Filter::default()
+                .eq("kind", Some(kind))
+                .eq("name", Some(&requirement.key));
+            let values = |values: &Vec<String>| -> Vec<Param> {
+                values.iter().map(Param::from).collect()
+            };
+            filter = match &requirement.op {
+                Op::In(v) => filter.in_select(
+                    "id",
+                    "label",
+                    "entity_id",
+                    labels.one_of("value", values(v)),
+                ),
+                Op::NotIn(v) => filter.not_in_select(
+                    "id",
+                    "label",
+                    "entity_id",
+                    labels.one_of("value", values(v)),
+                ),
+                Op::Exists => {
+                    filter.in_select("id", "label", "entity_id", labels)
+                }
+                Op::NotExists => {
+                    filter.not_in_select("id", "label", "entity_id", labels)
+                }
+            };
+        }
+        filter
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, requirement) in self.requirements.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", requirement)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Selector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut requirements = Vec::new();
+        if s.trim().is_empty() {
+            return Ok(Selector { requirements });
+        }
+        // commas inside the parentheses of in and notin separate values
+        let mut depth = 0;
+        let mut start = 0;
+        for (i, c) in s.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    requirements.push(s[start..i].trim().parse()?);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        requirements.push(s[start..].trim().parse()?);
+        Ok(Selector { requirements })
+    }
+}
+
+impl<'de> Deserialize<'de> for Selector {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{Execute, MySql, QueryBuilder};
+
+    use super::*;
+
+    #[test]
+    fn selector() {
+        let selector = "env = prod, tier in (web, api),team notin (ops),\
+                        owner,!legacy,example.com/app!=cim"
+            .parse::<Selector>()
+            .unwrap();
+        assert_eq!(
+            selector.to_string(),
+            "env=prod,tier in (web,api),team!=ops,owner,!legacy,\
+             example.com/app!=cim"
+        );
+        let labels = Labels::from([
+            ("env".to_owned(), "prod".to_owned()),
+            ("tier".to_owned(), "web".to_owned()),
+            ("owner".to_owned(), "alice".to_owned()),
+        ]);
+        assert!(selector.matches(&labels));
+        let mut other = labels.clone();
+        other.insert("team".to_owned(), "ops".to_owned());
+        assert!(!selector.matches(&other));
+        let mut other = labels.clone();
+        other.remove("owner");
+        assert!(!selector.matches(&other));
+        assert!(Selector::default().matches(&Labels::new()));
+        assert!("".parse::<Selector>().unwrap().is_empty());
+
+        for invalid in [
+            "env in prod",
+            "env in (prod",
+            "-env",
+            "env=prod,",
+            "env=pr od",
+            "env between (a,b)",
+        ] {
+            assert!(invalid.parse::<Selector>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn filter() {
+        let selector = "env=prod,team notin (ops,dev),!legacy"
+            .parse::<Selector>()
+            .unwrap();
+        let mut builder = QueryBuilder::<MySql>::new("SELECT * FROM `user`");
+        selector
+            .filter(Filter::default(), "user")
+            .not_deleted()
+            .push(&mut builder);
+        assert_eq!(
+            builder.build().sql(),
+            "SELECT * FROM `user` WHERE `id` IN (SELECT `entity_id` FROM \
+             `label` WHERE `kind` = ? AND `name` = ? AND `value` IN (?)) AND \
+             `id` NOT IN (SELECT `entity_id` FROM `label` WHERE `kind` = ? \
+             AND `name` = ? AND `value` IN (?,?)) AND `id` NOT IN (SELECT \
+             `entity_id` FROM `label` WHERE `kind` = ? AND `name` = ?) AND \
+             `deleted` = 0"
+        );
+    }
+
+    #[test]
+    fn check() {
+        let labels = |k: &str, v: &str| Labels::from([(k.into(), v.into())]);
+        assert!(check_labels(&labels("app.example.com/env", "prod")).is_ok());
+        assert!(check_labels(&labels("env", "")).is_ok());
+        assert!(check_labels(&labels("env", "a b")).is_err());
+        assert!(check_labels(&labels(&"a".repeat(64), "prod")).is_err());
+        assert!(check_labels(&labels("env", &"a".repeat(64))).is_err());
+        assert!(check_annotations(&labels("note", "any text.")).is_ok());
+        assert!(check_annotations(&labels("a b", "")).is_err());
+    }
+}
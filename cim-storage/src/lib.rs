@@ -1,34 +1,54 @@
+pub mod audit;
 pub mod authcode;
 pub mod authrequest;
-// mod cache;
+mod cache;
+pub mod changelog;
 pub mod client;
 pub mod connector;
 pub mod convert;
+pub mod expiry;
+pub mod gc;
 pub mod group;
 pub mod group_user;
 pub mod key;
-mod mariadb;
+pub mod label;
+pub mod mariadb;
+pub mod memory;
 mod model;
 pub mod offlinesession;
 pub mod policy;
 pub mod policy_binding;
 mod pool;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+mod query;
 pub mod refresh_token;
+pub mod relation_tuple;
 pub mod role;
 pub mod role_binding;
+pub mod snapshot;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+mod transaction;
 pub mod user;
 mod watch;
 
+use std::sync::Arc;
+
 use async_trait::async_trait;
-use cim_watch::{WatchGuard, Watcher};
+use chrono::NaiveDateTime;
+use cim_watch::{Overflow, Subscription, WatchGuard, Watcher};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use cim_slo::Result;
+use cim_slo::{errors, Result};
 
+pub use cache::{Binding, Cacher, Invalidates, StatementCacher};
 pub use mariadb::*;
 pub use model::{Claim, ClaimOpts, List, Pagination, ID};
-pub use pool::connection_manager;
-pub use watch::WatchStore;
+pub use pool::{connection_manager, Pool};
+pub use query::{Dialect, Filter, Keyed, Param};
+pub use transaction::{Conn, ConnGuard, Tx, TxConn};
+pub use watch::{Tailer, WatchStore};
 
 #[async_trait]
 pub trait Interface: Sync {
@@ -52,32 +72,309 @@ pub trait Interface: Sync {
         output: &mut List<Self::T>,
     ) -> Result<()>;
     async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64>;
+
+    /// put_if puts input only while the stored resource_version still
+    /// equals resource_version, it fails with a conflict otherwise
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        let _ = (input, resource_version);
+        Err(errors::bad_request("conditional put is not supported"))
+    }
+
+    /// replace is put, or put_if when resource_version is given, returning
+    /// the live entity input replaced. It is read in the transaction of the
+    /// write with the row locked, no other write lands in between
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        let _ = (input, resource_version);
+        Err(errors::bad_request("replace is not supported"))
+    }
+
+    /// delete_if is the conditional counterpart of delete, see put_if
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        let _ = (input, resource_version);
+        Err(errors::bad_request("conditional delete is not supported"))
+    }
+
+    /// discard is delete, or delete_if when resource_version is given,
+    /// returning the live entity it deleted. It is read in the transaction
+    /// of the delete with the row locked, like replace does
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        let _ = (input, resource_version);
+        Err(errors::bad_request("discard is not supported"))
+    }
+
+    /// list_deleted lists the soft deleted entities matching opts
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        let _ = (opts, output);
+        Err(errors::bad_request("listing deleted is not supported"))
+    }
+
+    /// restore undeletes the soft deleted entity of input, it fails with
+    /// not found when there is none
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        let _ = input;
+        Err(errors::bad_request("restore is not supported"))
+    }
+
+    /// purge hard deletes the entities soft deleted before, returning how
+    /// many there were
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        let _ = before;
+        Err(errors::bad_request("purge is not supported"))
+    }
+}
+
+/// DynInterface is an Interface whose backend is chosen at runtime
+pub type DynInterface<T, L> = Arc<dyn Interface<T = T, L = L> + Send>;
+
+#[async_trait]
+impl<I: Interface + Send + ?Sized> Interface for Arc<I> {
+    type T = I::T;
+    type L = I::L;
+
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        (**self).put(input).await
+    }
+
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        (**self).delete(input).await
+    }
+
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        (**self).get(output).await
+    }
+
+    async fn list(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        (**self).list(opts, output).await
+    }
+
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        (**self).count(opts, unscoped).await
+    }
+
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        (**self).put_if(input, resource_version).await
+    }
+
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        (**self).replace(input, resource_version).await
+    }
+
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        (**self).delete_if(input, resource_version).await
+    }
+
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        (**self).discard(input, resource_version).await
+    }
+
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        (**self).list_deleted(opts, output).await
+    }
+
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        (**self).restore(input).await
+    }
+
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        (**self).purge(before).await
+    }
+}
+
+/// stored is the live entity of input in store, none if there is none
+pub(crate) async fn stored<I: Interface + ?Sized>(
+    store: &I,
+    input: &I::T,
+) -> Result<Option<I::T>> {
+    let mut output = input.clone();
+    match store.get(&mut output).await {
+        Ok(()) => Ok(Some(output)),
+        Err(err) if err.eq(&errors::not_found("")) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// discarded deletes input from store, with resource_version only if the
+/// stored one is still at it, returning the live entity it deleted
+pub(crate) async fn discarded<I: Interface + ?Sized>(
+    store: &I,
+    input: &I::T,
+    resource_version: Option<u64>,
+) -> Result<Option<I::T>> {
+    let previous = stored(store, input).await?;
+    match resource_version {
+        Some(v) => store.delete_if(input, v).await?,
+        None => store.delete(input).await?,
+    }
+    Ok(previous)
+}
+
+/// Event is a change of an entity, its revision orders it among the
+/// events of every store and is where a watch resumes after
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Event<T> {
+    #[serde(default)]
+    pub revision: u64,
+    #[serde(flatten)]
+    pub action: Action<T>,
+    /// previous is the state a put replaced, none when there was no entity
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<T>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
-pub enum Event<T> {
+pub enum Action<T> {
     Add(T),
     Put(T),
     Delete(T),
 }
 
 impl<T> Event<T> {
+    /// add is the event of a created entity, the revision is stamped when
+    /// it's delivered
+    pub fn add(value: T) -> Self {
+        Self {
+            revision: 0,
+            action: Action::Add(value),
+            previous: None,
+        }
+    }
+
+    /// put is the event of an updated entity, previous is the state it
+    /// replaced
+    pub fn put(previous: Option<T>, value: T) -> Self {
+        Self {
+            revision: 0,
+            action: Action::Put(value),
+            previous,
+        }
+    }
+
+    pub fn delete(value: T) -> Self {
+        Self {
+            revision: 0,
+            action: Action::Delete(value),
+            previous: None,
+        }
+    }
+
     pub fn get(&self) -> &T {
-        match self {
-            Event::Add(t) => t,
-            Event::Put(t) => t,
-            Event::Delete(t) => t,
+        match &self.action {
+            Action::Add(t) => t,
+            Action::Put(t) => t,
+            Action::Delete(t) => t,
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        match &mut self.action {
+            Action::Add(t) => t,
+            Action::Put(t) => t,
+            Action::Delete(t) => t,
         }
     }
 }
 
+impl<T: Serialize> Event<T> {
+    /// diff is the JSON Patch turning the previous state of a put into the
+    /// new one, none for the other events
+    pub fn diff(&self) -> Option<json_patch::Patch> {
+        let (Action::Put(value), Some(previous)) =
+            (&self.action, &self.previous)
+        else {
+            return None;
+        };
+        let previous = serde_json::to_value(previous).ok()?;
+        let value = serde_json::to_value(value).ok()?;
+        Some(json_patch::diff(&previous, &value))
+    }
+}
+
+impl<T: Keyed> Event<T> {
+    /// supersedes tells if self is about the entity of older, a slow
+    /// subscriber may get self only
+    pub fn supersedes(&self, older: &Self) -> bool {
+        self.get().key("id") == older.get().key("id")
+    }
+}
+
+impl<T: Clone> Event<T> {
+    /// fold makes self stand for older too, for a subscriber that won't
+    /// get older: a put replaces what older replaced, and adds the entity
+    /// if older did
+    pub fn fold(&mut self, older: Self) {
+        let Action::Put(value) = &self.action else {
+            return;
+        };
+        if let Action::Add(_) = older.action {
+            self.action = Action::Add(value.clone());
+        }
+        self.previous = older.previous;
+    }
+}
+
 #[async_trait]
 pub trait WatchInterface: Interface {
+    /// watch notifies handler of the events with a revision above since,
+    /// the ones already published first. It fails with gone when some of
+    /// them aren't kept anymore, a since of 0 watches the new events only
     fn watch<W: Watcher<Event<Self::T>>>(
         &self,
-        since_modify: usize,
+        since: u64,
         handler: W,
-    ) -> Box<dyn WatchGuard + Send>;
+    ) -> Result<Box<dyn WatchGuard + Send>>;
+
+    /// subscribe is watch as a stream buffering up to capacity events,
+    /// overflow tells what becomes of the next ones until it's polled
+    fn subscribe(
+        &self,
+        since: u64,
+        capacity: usize,
+        overflow: Overflow<Event<Self::T>>,
+    ) -> Result<Subscription<Event<Self::T>>>;
 
     async fn create(&self, input: &Self::T) -> Result<()>;
 }
@@ -1,19 +1,19 @@
 use async_trait::async_trait;
 use serde_json::value::RawValue;
-use sqlx::{types::Json, MySqlPool, Row};
+use sqlx::{types::Json, MySql, Row};
 
 use cim_slo::{errors, Result};
 
-use crate::{authcode::AuthCode, Claim, Interface, List};
+use crate::{authcode::AuthCode, Claim, Conn, Interface, List};
 
 #[derive(Clone, Debug)]
 pub struct AuthCodeImpl {
-    pool: MySqlPool,
+    conn: Conn<MySql>,
 }
 
 impl AuthCodeImpl {
-    pub fn new(pool: MySqlPool) -> Self {
-        Self { pool }
+    pub fn new(pool: impl Into<Conn<MySql>>) -> Self {
+        Self { conn: pool.into() }
     }
 }
 
@@ -46,7 +46,7 @@ impl Interface for AuthCodeImpl {
         .bind(&content.connector_id)
         .bind(content.connector_data.as_ref().map(|v| v.to_string()))
         .bind(content.expiry)
-        .execute(&self.pool)
+        .execute(&mut *self.conn.acquire().await?)
         .await
         .map_err(errors::any)?;
 
@@ -60,7 +60,7 @@ impl Interface for AuthCodeImpl {
             WHERE id = ?;"#,
         )
         .bind(&input.id)
-        .execute(&self.pool)
+        .execute(&mut *self.conn.acquire().await?)
         .await
         .map_err(errors::any)?;
         Ok(())
@@ -74,7 +74,7 @@ impl Interface for AuthCodeImpl {
             FROM `auth_code`
             WHERE id = ?;"#)
         .bind(&output.id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.conn.acquire().await?)
         .await
         {
             Ok(v) => match v {
@@ -1,19 +1,19 @@
 use async_trait::async_trait;
 use serde_json::value::RawValue;
-use sqlx::{types::Json, MySqlPool, Row};
+use sqlx::{types::Json, MySql, Row};
 
 use cim_slo::{errors, Result};
 
-use crate::{authrequest::AuthRequest, Claim, Interface, List};
+use crate::{authrequest::AuthRequest, Claim, Conn, Interface, List};
 
 #[derive(Clone, Debug)]
 pub struct AuthRequestImpl {
-    pool: MySqlPool,
+    conn: Conn<MySql>,
 }
 
 impl AuthRequestImpl {
-    pub fn new(pool: MySqlPool) -> Self {
-        Self { pool }
+    pub fn new(pool: impl Into<Conn<MySql>>) -> Self {
+        Self { conn: pool.into() }
     }
 }
 
@@ -47,7 +47,7 @@ impl Interface for AuthRequestImpl {
         .bind(&content.connector_id)
         .bind(content.connector_data.as_ref().map(|v| v.to_string()))
         .bind(content.expiry)
-        .execute(&self.pool)
+        .execute(&mut *self.conn.acquire().await?)
         .await
         .map_err(errors::any)?;
 
@@ -58,7 +58,7 @@ impl Interface for AuthRequestImpl {
     async fn delete(&self, input: &Self::T) -> Result<()> {
         sqlx::query(r#"DELETE FROM `auth_request` WHERE id = ?;"#)
             .bind(&input.id)
-            .execute(&self.pool)
+            .execute(&mut *self.conn.acquire().await?)
             .await
             .map_err(errors::any)?;
         Ok(())
@@ -73,7 +73,7 @@ impl Interface for AuthRequestImpl {
             FROM `auth_request`
             WHERE id = ?;"#)
         .bind(&output.id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.conn.acquire().await?)
         .await
         {
             Ok(v) => match v {
@@ -0,0 +1,83 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use sqlx::{MySql, Row};
+
+use cim_slo::{errors, Result};
+
+use crate::{
+    changelog::{Change, ChangeStore},
+    Conn,
+};
+
+#[derive(Clone, Debug)]
+pub struct ChangelogImpl {
+    conn: Conn<MySql>,
+}
+
+impl ChangelogImpl {
+    pub fn new(pool: impl Into<Conn<MySql>>) -> Self {
+        Self { conn: pool.into() }
+    }
+}
+
+#[async_trait]
+impl ChangeStore for ChangelogImpl {
+    #[tracing::instrument]
+    async fn append(&self, kind: &str, payload: &str) -> Result<()> {
+        sqlx::query(
+            r#"INSERT INTO `changelog` (`kind`,`payload`) VALUES(?,?);"#,
+        )
+        .bind(kind)
+        .bind(payload)
+        .execute(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn since(&self, id: u64, limit: i64) -> Result<Vec<Change>> {
+        let rows = sqlx::query(
+            r#"SELECT `id`,`kind`,`payload`,`created_at` FROM `changelog`
+                WHERE `id` > ? ORDER BY `id` LIMIT ?;"#,
+        )
+        .bind(id)
+        .bind(limit)
+        .fetch_all(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+        let mut output = Vec::with_capacity(rows.len());
+        for row in rows {
+            output.push(Change {
+                id: row.try_get("id").map_err(errors::any)?,
+                kind: row.try_get("kind").map_err(errors::any)?,
+                payload: row.try_get("payload").map_err(errors::any)?,
+                created_at: row.try_get("created_at").map_err(errors::any)?,
+            });
+        }
+        Ok(output)
+    }
+
+    #[tracing::instrument]
+    async fn last(&self) -> Result<u64> {
+        let row = sqlx::query(
+            r#"SELECT CAST(COALESCE(MAX(`id`), 0) AS UNSIGNED) AS `id`
+                FROM `changelog`;"#,
+        )
+        .fetch_one(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+        row.try_get("id").map_err(errors::any)
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        let result =
+            sqlx::query(r#"DELETE FROM `changelog` WHERE `created_at` < ?;"#)
+                .bind(before)
+                .execute(&mut *self.conn.acquire().await?)
+                .await
+                .map_err(errors::any)?;
+        Ok(result.rows_affected())
+    }
+}
@@ -1,19 +1,121 @@
 use async_trait::async_trait;
-use sqlx::{types::Json, MySqlPool, Row};
+use chrono::NaiveDateTime;
+use sqlx::{mysql::MySqlRow, types::Json, MySql, Row};
 
 use cim_slo::{errors, Result};
 
-use crate::{client::Client, Interface, List};
+use super::{label, lock, purge, restore, soft_delete, write_query, write_row};
+use crate::{
+    client::Client, discarded, label::Labels, stored, Conn, Interface, List,
+};
 
 #[derive(Clone, Debug)]
 pub struct ClientImpl {
-    pool: MySqlPool,
+    conn: Conn<MySql>,
 }
 
 impl ClientImpl {
-    pub fn new(pool: MySqlPool) -> Self {
-        Self { pool }
+    pub fn new(pool: impl Into<Conn<MySql>>) -> Self {
+        Self { conn: pool.into() }
     }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it
+    async fn write(
+        &self,
+        input: &Client,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
+        let query = write_query(
+            "client",
+            &[
+                "secret",
+                "redirect_uris",
+                "trusted_peers",
+                "name",
+                "logo_url",
+                "account_id",
+                "labels",
+                "annotations",
+            ],
+            resource_version.is_some(),
+        );
+        let query = sqlx::query(&query)
+            .bind(&input.id)
+            .bind(&input.secret)
+            .bind(Json(&input.redirect_uris))
+            .bind(Json(&input.trusted_peers))
+            .bind(&input.name)
+            .bind(&input.logo_url)
+            .bind(&input.account_id)
+            .bind(Json(&input.labels))
+            .bind(Json(&input.annotations))
+            .bind(&input.id);
+        write_row(&self.conn, query, "client", &input.id, resource_version)
+            .await?;
+        label(&self.conn, "client", &input.id, &input.labels).await?;
+        Ok(())
+    }
+
+    /// select lists the soft deleted rows when deleted, the live ones
+    /// otherwise
+    async fn select(
+        &self,
+        output: &mut List<Client>,
+        deleted: bool,
+    ) -> Result<()> {
+        let deleted = match deleted {
+            true => "<> 0",
+            false => "= 0",
+        };
+        let rows = sqlx::query(&format!(
+            r#"SELECT `id`,`secret`,`redirect_uris`,`trusted_peers`,`name`,`logo_url`,`account_id`,
+                `resource_version`,`labels`,`annotations`,`created_at`,`updated_at`
+                FROM `client`
+                WHERE `deleted` {deleted};"#
+        ))
+        .fetch_all(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+        for row in rows.iter() {
+            output.data.push(client(row)?);
+        }
+        Ok(())
+    }
+}
+
+fn client(row: &MySqlRow) -> Result<Client> {
+    Ok(Client {
+        id: row
+            .try_get::<u64, _>("id")
+            .map_err(errors::any)?
+            .to_string(),
+        secret: row.try_get("secret").map_err(errors::any)?,
+        redirect_uris: row
+            .try_get::<Json<Vec<String>>, _>("redirect_uris")
+            .map_err(errors::any)?
+            .0,
+        trusted_peers: row
+            .try_get::<Json<Vec<String>>, _>("trusted_peers")
+            .map_err(errors::any)?
+            .0,
+        name: row.try_get("name").map_err(errors::any)?,
+        logo_url: row.try_get("logo_url").map_err(errors::any)?,
+        account_id: row.try_get("account_id").map_err(errors::any)?,
+        labels: row
+            .try_get::<Json<Labels>, _>("labels")
+            .map_err(errors::any)?
+            .0,
+        annotations: row
+            .try_get::<Json<Labels>, _>("annotations")
+            .map_err(errors::any)?
+            .0,
+        resource_version: row
+            .try_get("resource_version")
+            .map_err(errors::any)?,
+        created_at: row.try_get("created_at").map_err(errors::any)?,
+        updated_at: row.try_get("updated_at").map_err(errors::any)?,
+    })
 }
 
 #[async_trait]
@@ -23,40 +125,17 @@ impl Interface for ClientImpl {
 
     #[tracing::instrument]
     async fn put(&self, input: &Self::T) -> Result<()> {
-        sqlx::query(
-            r#"REPLACE INTO `client`
-            (`id`,`secret`,`redirect_uris`,`trusted_peers`,`name`,`logo_url`,`account_id`)
-            VALUES(?,?,?,?,?,?,?);"#,
-        )
-        .bind(&input.id)
-        .bind(&input.secret)
-        .bind(Json(&input.redirect_uris))
-        .bind(Json(&input.trusted_peers))
-        .bind(&input.name)
-        .bind(&input.logo_url)
-        .bind(&input.account_id)
-        .execute(&self.pool)
-        .await
-        .map_err(errors::any)?;
-        Ok(())
+        // the row and its labels are indexed in the same transaction
+        self.conn
+            .scoped(
+                |conn| async move { Self { conn }.write(input, None).await },
+            )
+            .await
     }
 
     #[tracing::instrument]
     async fn delete(&self, input: &Self::T) -> Result<()> {
-        let id = input
-            .id
-            .parse::<u64>()
-            .map_err(|err| errors::bad_request(&err))?;
-
-        sqlx::query(
-            r#"UPDATE `client` SET `deleted` = `id`,`deleted_at`= now()
-            WHERE id = ? AND `deleted` = 0;"#,
-        )
-        .bind(id)
-        .execute(&self.pool)
-        .await
-        .map_err(errors::any)?;
-        Ok(())
+        soft_delete(&self.conn, "client", &input.id, None).await
     }
 
     #[tracing::instrument]
@@ -66,12 +145,13 @@ impl Interface for ClientImpl {
             .parse::<u64>()
             .map_err(|err| errors::bad_request(&err))?;
         let row = match sqlx::query(
-            r#"SELECT `id`,`secret`,`redirect_uris`,`trusted_peers`,`name`,`logo_url`,`account_id`,`created_at`,`updated_at`
+            r#"SELECT `id`,`secret`,`redirect_uris`,`trusted_peers`,`name`,`logo_url`,`account_id`,
+                `resource_version`,`labels`,`annotations`,`created_at`,`updated_at`
                 FROM `client`
                 WHERE id = ? AND `deleted` = 0;"#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.conn.acquire().await?)
         .await
         {
             Ok(v) => match v {
@@ -80,70 +160,105 @@ impl Interface for ClientImpl {
             },
             Err(err) => Err(errors::any(err)),
         }?;
+        *output = client(&row)?;
+        Ok(())
+    }
 
-        output.id = row
-            .try_get::<u64, _>("id")
-            .map_err(errors::any)?
-            .to_string();
-        output.secret = row.try_get("secret").map_err(errors::any)?;
-        output.redirect_uris = row
-            .try_get::<Json<Vec<String>>, _>("redirect_uris")
-            .map_err(errors::any)?
-            .0;
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        _opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(output, false).await
+    }
 
-        output.trusted_peers = row
-            .try_get::<Json<Vec<String>>, _>("trusted_peers")
+    #[tracing::instrument]
+    async fn count(&self, _opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let sql = if unscoped {
+            r#"SELECT COUNT(*) as count FROM `client`;"#
+        } else {
+            r#"SELECT COUNT(*) as count FROM `client` WHERE `deleted` = 0;"#
+        };
+        sqlx::query(sql)
+            .fetch_one(&mut *self.conn.acquire().await?)
+            .await
             .map_err(errors::any)?
-            .0;
-        output.name = row.try_get("name").map_err(errors::any)?;
-        output.logo_url = row.try_get("logo_url").map_err(errors::any)?;
-        output.account_id = row.try_get("account_id").map_err(errors::any)?;
-        output.created_at = row.try_get("created_at").map_err(errors::any)?;
-        output.updated_at = row.try_get("updated_at").map_err(errors::any)?;
-        Ok(())
+            .try_get("count")
+            .map_err(errors::any)
     }
 
     #[tracing::instrument]
-    async fn list(
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.conn
+            .scoped(|conn| async move {
+                Self { conn }.write(input, Some(resource_version)).await
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "client", &input.id).await?;
+                let previous = stored(&store, input).await?;
+                store.write(input, resource_version).await?;
+                Ok(previous)
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "client", &input.id).await?;
+                discarded(&store, input, resource_version).await
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        soft_delete(&self.conn, "client", &input.id, Some(resource_version))
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn list_deleted(
         &self,
         _opts: &Self::L,
         output: &mut List<Self::T>,
     ) -> Result<()> {
-        let rows = sqlx::query(
-                    r#"SELECT `id`,`secret`,`redirect_uris`,`trusted_peers`,`name`,`logo_url`,`account_id`,`created_at`,`updated_at`
-                FROM `client`
-                WHERE `deleted` = 0;"#,
-        )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(errors::any)?;
-        for row in rows.iter() {
-            output.data.push(Self::T {
-                id: row
-                    .try_get::<u64, _>("id")
-                    .map_err(errors::any)?
-                    .to_string(),
-                account_id: row.try_get("account_id").map_err(errors::any)?,
-                secret: row.try_get("secret").map_err(errors::any)?,
-                logo_url: row.try_get("logo_url").map_err(errors::any)?,
-                redirect_uris: row
-                    .try_get::<Json<Vec<String>>, _>("redirect_uris")
-                    .map_err(errors::any)?
-                    .0,
-                trusted_peers: row
-                    .try_get::<Json<Vec<String>>, _>("trusted_peers")
-                    .map_err(errors::any)?
-                    .0,
-                name: row.try_get("name").map_err(errors::any)?,
-                created_at: row.try_get("created_at").map_err(errors::any)?,
-                updated_at: row.try_get("updated_at").map_err(errors::any)?,
-            });
-        }
+        self.select(output, true).await
+    }
 
-        Ok(())
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        restore(&self.conn, "client", &input.id).await
     }
 
-    async fn count(&self, _opts: &Self::L, _unscoped: bool) -> Result<i64> {
-        todo!()
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        purge(&self.conn, "client", before).await
     }
 }
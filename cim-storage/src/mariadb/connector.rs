@@ -1,23 +1,129 @@
 use async_trait::async_trait;
+use chrono::NaiveDateTime;
 use serde_json::value::RawValue;
-use sqlx::{MySqlPool, Row};
+use sqlx::{MySql, QueryBuilder, Row};
 
 use cim_slo::{errors, Result};
 
+use super::{lock, purge, restore, soft_delete, write_query, write_row};
 use crate::{
     connector::{Connector, ListParams},
-    convert::convert_param,
-    Interface, List,
+    discarded, stored, Conn, Interface, List,
 };
 
 #[derive(Clone, Debug)]
 pub struct ConnectorImpl {
-    pool: MySqlPool,
+    conn: Conn<MySql>,
 }
 
 impl ConnectorImpl {
-    pub fn new(pool: MySqlPool) -> Self {
-        Self { pool }
+    pub fn new(pool: impl Into<Conn<MySql>>) -> Self {
+        Self { conn: pool.into() }
+    }
+
+    /// write puts content, when resource_version is given only if the
+    /// stored one is still at it
+    async fn write(
+        &self,
+        content: &Connector,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
+        let query = write_query(
+            "connector",
+            &[
+                "type",
+                "name",
+                "response_version",
+                "config",
+                "connector_data",
+            ],
+            resource_version.is_some(),
+        );
+        let query = sqlx::query(&query)
+            .bind(&content.id)
+            .bind(&content.connector_type)
+            .bind(&content.name)
+            .bind(&content.response_version)
+            .bind(&content.config)
+            .bind(content.connector_data.as_ref().map(|v| v.to_string()))
+            .bind(&content.id);
+        write_row(
+            &self.conn,
+            query,
+            "connector",
+            &content.id,
+            resource_version,
+        )
+        .await
+    }
+
+    /// select lists the soft deleted rows when deleted, the live ones
+    /// otherwise
+    async fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<Connector>,
+        deleted: bool,
+    ) -> Result<()> {
+        let filter = opts.filter()?.deleted(deleted);
+        // 查询total
+        if !opts.pagination.count_disable {
+            let mut builder = QueryBuilder::new(
+                r#"SELECT COUNT(*) as count FROM `connector`"#,
+            );
+            filter.push(&mut builder);
+            output.total = builder
+                .build()
+                .fetch_one(&mut *self.conn.acquire().await?)
+                .await
+                .map_err(errors::any)?
+                .try_get("count")
+                .map_err(errors::any)?;
+        }
+
+        // 查询列表
+        output.limit = opts.pagination.limit;
+        output.offset = opts.pagination.offset;
+
+        let mut builder = QueryBuilder::new(
+            r#"SELECT `id`,`type`,`name`,`response_version`,`config`,`connector_data`,
+                `resource_version`
+            FROM `connector`"#,
+        );
+        opts.pagination
+            .seek(filter, ListParams::SORTABLE)?
+            .push(&mut builder);
+        opts.pagination.push(&mut builder, ListParams::SORTABLE)?;
+        let rows = builder
+            .build()
+            .fetch_all(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?;
+        for row in rows.iter() {
+            output.data.push(Connector {
+                id: row
+                    .try_get::<u64, _>("id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                connector_type: row.try_get("type").map_err(errors::any)?,
+                name: row.try_get("name").map_err(errors::any)?,
+                response_version: row
+                    .try_get("response_version")
+                    .map_err(errors::any)?,
+                config: row.try_get("config").map_err(errors::any)?,
+                connector_data: row
+                    .try_get::<Option<String>, _>("connector_data")
+                    .map_err(errors::any)?
+                    .map(|v| RawValue::from_string(v).unwrap()),
+                resource_version: row
+                    .try_get("resource_version")
+                    .map_err(errors::any)?,
+            });
+        }
+        output.next_cursor = opts
+            .pagination
+            .next_cursor(&output.data, ListParams::SORTABLE)?;
+        Ok(())
     }
 }
 
@@ -28,38 +134,12 @@ impl Interface for ConnectorImpl {
 
     #[tracing::instrument]
     async fn put(&self, content: &Self::T) -> Result<()> {
-        sqlx::query(
-            r#"REPLACE INTO `connector`
-            (`id`,`type`,`name`,`resource_version`,`config`,`connector_data`)
-            VALUES(?,?,?,?,?,?);"#,
-        )
-        .bind(&content.id)
-        .bind(&content.connector_type)
-        .bind(&content.name)
-        .bind(&content.response_version)
-        .bind(&content.config)
-        .bind(content.connector_data.as_ref().map(|v| v.to_string()))
-        .execute(&self.pool)
-        .await
-        .map_err(errors::any)?;
-        Ok(())
+        self.write(content, None).await
     }
 
     #[tracing::instrument]
     async fn delete(&self, input: &Self::T) -> Result<()> {
-        let id = input
-            .id
-            .parse::<u64>()
-            .map_err(|err| errors::bad_request(&err))?;
-        sqlx::query(
-            r#"UPDATE `connector` SET `deleted` = `id`,`deleted_at`= now()
-            WHERE id = ? AND `deleted` = 0;"#,
-        )
-        .bind(id)
-        .execute(&self.pool)
-        .await
-        .map_err(errors::any)?;
-        Ok(())
+        soft_delete(&self.conn, "connector", &input.id, None).await
     }
 
     #[tracing::instrument]
@@ -70,12 +150,13 @@ impl Interface for ConnectorImpl {
             .map_err(|err| errors::bad_request(&err))?;
 
         let row = match sqlx::query(
-            r#"SELECT `id`,`type`,`name`,`resource_version`,`config`,`connector_data`
+            r#"SELECT `id`,`type`,`name`,`response_version`,`config`,`connector_data`,
+                `resource_version`
             FROM `connector`
             WHERE id = ? AND deleted = 0;"#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.conn.acquire().await?)
         .await
         {
             Ok(v) => match v {
@@ -96,8 +177,10 @@ impl Interface for ConnectorImpl {
         output.connector_type = row.try_get("type").map_err(errors::any)?;
         output.name = row.try_get("name").map_err(errors::any)?;
         output.response_version =
-            row.try_get("resource_version").map_err(errors::any)?;
+            row.try_get("response_version").map_err(errors::any)?;
         output.config = row.try_get("config").map_err(errors::any)?;
+        output.resource_version =
+            row.try_get("resource_version").map_err(errors::any)?;
         Ok(())
     }
 
@@ -107,83 +190,93 @@ impl Interface for ConnectorImpl {
         opts: &Self::L,
         output: &mut List<Self::T>,
     ) -> Result<()> {
-        let mut wheres = String::new();
-        combine_param(&mut wheres, opts)?;
+        self.select(opts, output, false).await
+    }
 
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let mut filter = opts.filter()?;
+        if !unscoped {
+            filter = filter.not_deleted();
         }
-        wheres.push_str(r#"`deleted` = 0"#);
-        // 查询total
-        if !opts.pagination.count_disable {
-            let policy_result = sqlx::query(
-                format!(
-                    r#"SELECT COUNT(*) as count FROM `connector`
-            WHERE {};"#,
-                    wheres,
-                )
-                .as_str(),
-            )
-            .fetch_one(&self.pool)
+        let mut builder =
+            QueryBuilder::new(r#"SELECT COUNT(*) as count FROM `connector`"#);
+        filter.push(&mut builder);
+        builder
+            .build()
+            .fetch_one(&mut *self.conn.acquire().await?)
             .await
-            .map_err(errors::any)?;
-
-            output.total =
-                policy_result.try_get("count").map_err(errors::any)?;
-        }
+            .map_err(errors::any)?
+            .try_get("count")
+            .map_err(errors::any)
+    }
 
-        // 查询列表
-        opts.pagination.convert(&mut wheres);
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.write(input, Some(resource_version)).await
+    }
 
-        output.limit = opts.pagination.limit;
-        output.offset = opts.pagination.offset;
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "connector", &input.id).await?;
+                let previous = stored(&store, input).await?;
+                store.write(input, resource_version).await?;
+                Ok(previous)
+            })
+            .await
+    }
 
-        let rows = sqlx::query(
-            format!(
-            r#"SELECT `id`,`type`,`name`,`resource_version`,`config`,`connector_data`
-            FROM `connector`
-            WHERE {};"#,
-            wheres
-            ).as_str()
-        )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(errors::any)?;
-        for row in rows.iter() {
-            output.data.push(Self::T {
-                id: row
-                    .try_get::<u64, _>("id")
-                    .map_err(errors::any)?
-                    .to_string(),
-                connector_type: row.try_get("type").map_err(errors::any)?,
-                name: row.try_get("name").map_err(errors::any)?,
-                response_version: row
-                    .try_get("resource_version")
-                    .map_err(errors::any)?,
-                config: row.try_get("config").map_err(errors::any)?,
-                connector_data: row
-                    .try_get::<Option<String>, _>("connector_data")
-                    .map_err(errors::any)?
-                    .map(|v| RawValue::from_string(v).unwrap()),
-            });
-        }
-        Ok(())
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "connector", &input.id).await?;
+                discarded(&store, input, resource_version).await
+            })
+            .await
     }
 
-    async fn count(&self, _opts: &Self::L, _unscoped: bool) -> Result<i64> {
-        todo!()
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        soft_delete(&self.conn, "connector", &input.id, Some(resource_version))
+            .await
     }
-}
 
-fn combine_param(wheres: &mut String, opts: &ListParams) -> Result<()> {
-    if let Some(v) = &opts.connector_type {
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true).await
+    }
 
-        wheres.push_str(r#"`type` = "#);
-        convert_param(wheres, v);
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        restore(&self.conn, "connector", &input.id).await
     }
 
-    Ok(())
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        purge(&self.conn, "connector", before).await
+    }
 }
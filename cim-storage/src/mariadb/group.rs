@@ -1,49 +1,193 @@
 use async_trait::async_trait;
-use sqlx::{MySqlPool, Row};
+use chrono::NaiveDateTime;
+use sqlx::{types::Json, MySql, QueryBuilder, Row};
 
 use cim_slo::{errors, Result};
 
+use super::{label, lock, purge, restore, soft_delete, write_query, write_row};
 use crate::{
+    discarded,
     group::{Group, ListParams},
-    Interface, List,
+    label::Labels,
+    stored, Conn, Interface, List,
 };
 
 #[derive(Clone, Debug)]
 pub struct GroupImpl {
-    pool: MySqlPool,
+    conn: Conn<MySql>,
 }
 
 impl GroupImpl {
-    pub fn new(pool: MySqlPool) -> Self {
-        Self { pool }
+    pub fn new(pool: impl Into<Conn<MySql>>) -> Self {
+        Self { conn: pool.into() }
     }
-}
 
-#[async_trait]
-impl Interface for GroupImpl {
-    type T = Group;
-    type L = ListParams;
+    /// select lists the rows matching opts, the soft deleted ones when
+    /// deleted
+    async fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<Group>,
+        deleted: bool,
+    ) -> Result<()> {
+        let filter = opts.filter()?.deleted(deleted);
+        // 查询total
+        if !opts.pagination.count_disable {
+            let mut builder =
+                QueryBuilder::new(r#"SELECT COUNT(*) as count FROM `group`"#);
+            filter.push(&mut builder);
+            output.total = builder
+                .build()
+                .fetch_one(&mut *self.conn.acquire().await?)
+                .await
+                .map_err(errors::any)?
+                .try_get("count")
+                .map_err(errors::any)?;
+        }
 
-    #[tracing::instrument]
-    async fn put(&self, input: &Self::T) -> Result<()> {
-        sqlx::query(
-            r#"REPLACE INTO `group`
-            (`id`,`account_id`,`name`,`desc`)
-            VALUES(?,?,?,?);"#,
-        )
-        .bind(&input.id)
-        .bind(&input.account_id)
-        .bind(&input.name)
-        .bind(&input.desc)
-        .execute(&self.pool)
-        .await
-        .map_err(errors::any)?;
+        // 查询列表
+        output.limit = opts.pagination.limit;
+        output.offset = opts.pagination.offset;
+
+        let mut builder = QueryBuilder::new(
+            r#"SELECT `id`,`account_id`,`name`,`desc`,`parent_id`,
+                `resource_version`,`labels`,`annotations`,`created_at`,`updated_at`
+                FROM `group`"#,
+        );
+        opts.pagination
+            .seek(filter, ListParams::SORTABLE)?
+            .push(&mut builder);
+        opts.pagination.push(&mut builder, ListParams::SORTABLE)?;
+        let rows = builder
+            .build()
+            .fetch_all(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?;
+        for row in rows.iter() {
+            output.data.push(Group {
+                id: row
+                    .try_get::<u64, _>("id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                account_id: row
+                    .try_get::<u64, _>("account_id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                name: row.try_get("name").map_err(errors::any)?,
+                desc: row.try_get("desc").map_err(errors::any)?,
+                parent_id: row
+                    .try_get::<Option<u64>, _>("parent_id")
+                    .map_err(errors::any)?
+                    .map(|v| v.to_string()),
+                resource_version: row
+                    .try_get("resource_version")
+                    .map_err(errors::any)?,
+                labels: row
+                    .try_get::<Json<Labels>, _>("labels")
+                    .map_err(errors::any)?
+                    .0,
+                annotations: row
+                    .try_get::<Json<Labels>, _>("annotations")
+                    .map_err(errors::any)?
+                    .0,
+                created_at: row.try_get("created_at").map_err(errors::any)?,
+                updated_at: row.try_get("updated_at").map_err(errors::any)?,
+            });
+        }
 
+        output.next_cursor = opts
+            .pagination
+            .next_cursor(&output.data, ListParams::SORTABLE)?;
         Ok(())
     }
 
-    #[tracing::instrument]
-    async fn delete(&self, input: &Self::T) -> Result<()> {
+    /// nest checks group id can be nested in group parent_id, which must not
+    /// be id or a group nested in it. The ancestors are locked as they are
+    /// walked until the transaction of the write ends, so that no
+    /// concurrent nesting closes a cycle
+    async fn nest(&self, id: i64, parent_id: i64) -> Result<()> {
+        let mut ancestors = Vec::new();
+        let mut next = Some(parent_id);
+        while let Some(ancestor) = next {
+            if ancestor == id {
+                return Err(errors::bad_request(&format!(
+                    "group {id} can't be nested in group {parent_id}, it contains it"
+                )));
+            }
+            if ancestors.contains(&ancestor) {
+                break;
+            }
+            ancestors.push(ancestor);
+            next = sqlx::query(
+                r#"SELECT `parent_id` FROM `group` WHERE `id` = ? FOR UPDATE;"#,
+            )
+            .bind(ancestor)
+            .fetch_optional(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?
+            .map(|row| row.try_get::<Option<i64>, _>("parent_id"))
+            .transpose()
+            .map_err(errors::any)?
+            .flatten();
+        }
+        Ok(())
+    }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it
+    async fn write(
+        &self,
+        input: &Group,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
+        let parent_id = input
+            .parent_id
+            .as_deref()
+            .map(str::parse::<i64>)
+            .transpose()
+            .map_err(|err| errors::bad_request(&err))?;
+        if let Some(parent_id) = parent_id {
+            let id = input
+                .id
+                .parse::<i64>()
+                .map_err(|err| errors::bad_request(&err))?;
+            self.nest(id, parent_id).await?;
+        }
+        let query = write_query(
+            "group",
+            &[
+                "account_id",
+                "name",
+                "desc",
+                "parent_id",
+                "labels",
+                "annotations",
+            ],
+            resource_version.is_some(),
+        );
+        let query = sqlx::query(&query)
+            .bind(&input.id)
+            .bind(&input.account_id)
+            .bind(&input.name)
+            .bind(&input.desc)
+            .bind(parent_id)
+            .bind(Json(&input.labels))
+            .bind(Json(&input.annotations))
+            .bind(&input.id);
+        write_row(&self.conn, query, "group", &input.id, resource_version)
+            .await?;
+        label(&self.conn, "group", &input.id, &input.labels).await?;
+
+        Ok(())
+    }
+
+    /// remove soft deletes input, when resource_version is given only
+    /// if the stored one is still at it
+    async fn remove(
+        &self,
+        input: &Group,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
         let id = input
             .id
             .parse::<u64>()
@@ -52,31 +196,54 @@ impl Interface for GroupImpl {
             r#"SELECT COUNT(*) as count FROM `group_user` WHERE `group_id` = ? AND `deleted` = 0"#,
         )
         .bind(id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.conn.acquire().await?)
         .await
         .map_err(errors::any)?.try_get::<i64,_>("count").map_err(errors::any)?!=0{
             return Err(errors::forbidden(&"can't delete group, because it is used by user".to_string()));
         };
         if sqlx::query(
-            r#"SELECT COUNT(*) as count FROM `policy_bindings` WHERE `bindings_type` = 2 AND `bindings_id` = ? AND `deleted` = 0"#,
+            r#"SELECT COUNT(*) as count FROM `policy_binding` WHERE `bindings_type` = 2 AND `bindings_id` = ? AND `deleted` = 0"#,
         )
-        .bind(id)
-        .fetch_one(&self.pool)
+        .bind(id.to_string())
+        .fetch_one(&mut *self.conn.acquire().await?)
         .await
         .map_err(errors::any)?.try_get::<i64,_>("count").map_err(errors::any)?!=0{
             return Err(errors::forbidden(&"can't delete group, because it is attached by policy".to_string()));
         };
-        sqlx::query(
-            r#"UPDATE `group` SET `deleted` = `id`,`deleted_at`= now()
-            WHERE id = ? AND `deleted` = 0;"#,
+        if sqlx::query(
+            r#"SELECT COUNT(*) as count FROM `group` WHERE `parent_id` = ? AND `deleted` = 0"#,
         )
         .bind(id)
-        .execute(&self.pool)
+        .fetch_one(&mut *self.conn.acquire().await?)
         .await
-        .map_err(errors::any)?;
+        .map_err(errors::any)?.try_get::<i64,_>("count").map_err(errors::any)?!=0{
+            return Err(errors::forbidden(&"can't delete group, because it has nested groups".to_string()));
+        };
+        soft_delete(&self.conn, "group", &input.id, resource_version).await?;
 
         Ok(())
     }
+}
+
+#[async_trait]
+impl Interface for GroupImpl {
+    type T = Group;
+    type L = ListParams;
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        // the row and its labels are indexed in the same transaction
+        self.conn
+            .scoped(
+                |conn| async move { Self { conn }.write(input, None).await },
+            )
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        self.remove(input, None).await
+    }
 
     #[tracing::instrument]
     async fn get(&self, output: &mut Self::T) -> Result<()> {
@@ -85,12 +252,13 @@ impl Interface for GroupImpl {
             .parse::<u64>()
             .map_err(|err| errors::bad_request(&err))?;
         let row = match sqlx::query(
-            r#"SELECT `id`,`account_id`,`name`,`desc`,`created_at`,`updated_at`
+            r#"SELECT `id`,`account_id`,`name`,`desc`,`parent_id`,
+                `resource_version`,`labels`,`annotations`,`created_at`,`updated_at`
                 FROM `group`
                 WHERE id = ? AND `deleted` = 0;"#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.conn.acquire().await?)
         .await
         {
             Ok(v) => match v {
@@ -110,6 +278,20 @@ impl Interface for GroupImpl {
             .to_string();
         output.name = row.try_get("name").map_err(errors::any)?;
         output.desc = row.try_get("desc").map_err(errors::any)?;
+        output.parent_id = row
+            .try_get::<Option<u64>, _>("parent_id")
+            .map_err(errors::any)?
+            .map(|v| v.to_string());
+        output.resource_version =
+            row.try_get("resource_version").map_err(errors::any)?;
+        output.labels = row
+            .try_get::<Json<Labels>, _>("labels")
+            .map_err(errors::any)?
+            .0;
+        output.annotations = row
+            .try_get::<Json<Labels>, _>("annotations")
+            .map_err(errors::any)?
+            .0;
         output.created_at = row.try_get("created_at").map_err(errors::any)?;
         output.updated_at = row.try_get("updated_at").map_err(errors::any)?;
         Ok(())
@@ -121,118 +303,97 @@ impl Interface for GroupImpl {
         opts: &Self::L,
         output: &mut List<Self::T>,
     ) -> Result<()> {
-        let mut wheres = String::new();
-        combine_param(&mut wheres, opts)?;
+        self.select(opts, output, false).await
+    }
 
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
+    #[tracing::instrument]
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let mut filter = opts.filter()?;
+        if !unscoped {
+            filter = filter.not_deleted();
         }
-        wheres.push_str(r#"`deleted` = 0"#);
-        // 查询total
-        if !opts.pagination.count_disable {
-            let policy_result = sqlx::query(
-                format!(
-                    r#"SELECT COUNT(*) as count FROM `group`
-                    WHERE {};"#,
-                    wheres,
-                )
-                .as_str(),
-            )
-            .fetch_one(&self.pool)
+        let mut builder =
+            QueryBuilder::new(r#"SELECT COUNT(*) as count FROM `group`"#);
+        filter.push(&mut builder);
+        builder
+            .build()
+            .fetch_one(&mut *self.conn.acquire().await?)
             .await
-            .map_err(errors::any)?;
-
-            output.total =
-                policy_result.try_get("count").map_err(errors::any)?;
-        }
-
-        // 查询列表
-        opts.pagination.convert(&mut wheres);
-
-        output.limit = opts.pagination.limit;
-        output.offset = opts.pagination.offset;
-
-        let rows = sqlx::query(
-            format!(
-                r#"SELECT `id`,`account_id`,`name`,`desc`,`created_at`,`updated_at`
-                FROM `group`
-                WHERE {};"#,
-                wheres,
-            )
-            .as_str(),
-        )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(errors::any)?;
-        for row in rows.iter() {
-            output.data.push(Self::T {
-                id: row
-                    .try_get::<u64, _>("id")
-                    .map_err(errors::any)?
-                    .to_string(),
-                account_id: row
-                    .try_get::<u64, _>("account_id")
-                    .map_err(errors::any)?
-                    .to_string(),
-                name: row.try_get("name").map_err(errors::any)?,
-                desc: row.try_get("desc").map_err(errors::any)?,
-                created_at: row.try_get("created_at").map_err(errors::any)?,
-                updated_at: row.try_get("updated_at").map_err(errors::any)?,
-            });
-        }
+            .map_err(errors::any)?
+            .try_get("count")
+            .map_err(errors::any)
+    }
 
-        Ok(())
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.conn
+            .scoped(|conn| async move {
+                Self { conn }.write(input, Some(resource_version)).await
+            })
+            .await
     }
 
     #[tracing::instrument]
-    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
-        let mut wheres = String::new();
-        combine_param(&mut wheres, opts)?;
-        if !unscoped {
-            wheres.push_str(" AND ");
-            wheres.push_str(r#"`deleted` = 0"#);
-        }
-        let result = sqlx::query(
-            format!(
-                r#"SELECT COUNT(*) as count FROM `group`
-            WHERE {} LIMIT 1;"#,
-                wheres,
-            )
-            .as_str(),
-        )
-        .fetch_one(&self.pool)
-        .await
-        .map_err(errors::any)?;
-        result.try_get("count").map_err(errors::any)
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "group", &input.id).await?;
+                let previous = stored(&store, input).await?;
+                store.write(input, resource_version).await?;
+                Ok(previous)
+            })
+            .await
     }
-}
 
-fn combine_param(wheres: &mut String, opts: &ListParams) -> Result<()> {
-    if let Some(v) = &opts.id {
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "group", &input.id).await?;
+                discarded(&store, input, resource_version).await
+            })
+            .await
+    }
 
-        wheres.push_str(
-            format!(
-                r#"`id` = {}"#,
-                v.parse::<u64>().map_err(|err| errors::bad_request(&err))?
-            )
-            .as_str(),
-        );
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.remove(input, Some(resource_version)).await
     }
 
-    if let Some(account_id) = &opts.account_id {
-        let account_id_u64: u64 = account_id
-            .parse()
-            .map_err(|err| errors::bad_request(&err))?;
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true).await
+    }
 
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
-        wheres
-            .push_str(format!(r#"`account_id` = {}"#, account_id_u64).as_str());
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        restore(&self.conn, "group", &input.id).await
     }
 
-    Ok(())
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        purge(&self.conn, "group", before).await
+    }
 }
@@ -1,21 +1,122 @@
 use async_trait::async_trait;
-use sqlx::{MySqlPool, Row};
+use chrono::NaiveDateTime;
+use sqlx::{MySql, QueryBuilder, Row};
 
 use cim_slo::{errors, Result};
 
+use super::{lock, purge, restore, soft_delete, write_query, write_row};
 use crate::{
+    discarded,
     group_user::{GroupUser, ListParams},
-    Interface, List,
+    stored, Conn, Interface, List,
 };
 
 #[derive(Clone, Debug)]
 pub struct GroupUserImpl {
-    pool: MySqlPool,
+    conn: Conn<MySql>,
 }
 
 impl GroupUserImpl {
-    pub fn new(pool: MySqlPool) -> Self {
-        Self { pool }
+    pub fn new(pool: impl Into<Conn<MySql>>) -> Self {
+        Self { conn: pool.into() }
+    }
+
+    /// select lists the rows matching opts, the soft deleted ones when
+    /// deleted
+    async fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<GroupUser>,
+        deleted: bool,
+    ) -> Result<()> {
+        let filter = opts.filter()?.deleted(deleted);
+        // 查询total
+        if !opts.pagination.count_disable {
+            let mut builder = QueryBuilder::new(
+                r#"SELECT COUNT(*) as count FROM `group_user`"#,
+            );
+            filter.push(&mut builder);
+            output.total = builder
+                .build()
+                .fetch_one(&mut *self.conn.acquire().await?)
+                .await
+                .map_err(errors::any)?
+                .try_get("count")
+                .map_err(errors::any)?;
+        }
+
+        // 查询列表
+        output.limit = opts.pagination.limit;
+        output.offset = opts.pagination.offset;
+
+        let mut builder = QueryBuilder::new(
+            r#"SELECT `id`,`group_id`,`user_id`,
+                `not_before`,`expires_at`,
+                `resource_version`,`created_at`,`updated_at`
+                FROM `group_user`"#,
+        );
+        opts.pagination
+            .seek(filter, ListParams::SORTABLE)?
+            .push(&mut builder);
+        opts.pagination.push(&mut builder, ListParams::SORTABLE)?;
+        let rows = builder
+            .build()
+            .fetch_all(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?;
+        for row in rows.iter() {
+            output.data.push(GroupUser {
+                id: row
+                    .try_get::<u64, _>("id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                group_id: row
+                    .try_get::<u64, _>("group_id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                user_id: row
+                    .try_get::<u64, _>("user_id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                resource_version: row
+                    .try_get("resource_version")
+                    .map_err(errors::any)?,
+                not_before: row.try_get("not_before").map_err(errors::any)?,
+                expires_at: row.try_get("expires_at").map_err(errors::any)?,
+                created_at: row.try_get("created_at").map_err(errors::any)?,
+                updated_at: row.try_get("updated_at").map_err(errors::any)?,
+            });
+        }
+
+        output.next_cursor = opts
+            .pagination
+            .next_cursor(&output.data, ListParams::SORTABLE)?;
+        Ok(())
+    }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it
+    async fn write(
+        &self,
+        input: &GroupUser,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
+        let query = write_query(
+            "group_user",
+            &["group_id", "user_id", "not_before", "expires_at"],
+            resource_version.is_some(),
+        );
+        let query = sqlx::query(&query)
+            .bind(&input.id)
+            .bind(&input.group_id)
+            .bind(&input.user_id)
+            .bind(input.not_before)
+            .bind(input.expires_at)
+            .bind(&input.id);
+        write_row(&self.conn, query, "group_user", &input.id, resource_version)
+            .await?;
+
+        Ok(())
     }
 }
 
@@ -26,37 +127,12 @@ impl Interface for GroupUserImpl {
 
     #[tracing::instrument]
     async fn put(&self, input: &Self::T) -> Result<()> {
-        sqlx::query(
-            r#"REPLACE INTO `group_user`
-            (`id`,`group_id`,`user_id`)
-            VALUES(?,?,?);"#,
-        )
-        .bind(&input.id)
-        .bind(&input.group_id)
-        .bind(&input.user_id)
-        .execute(&self.pool)
-        .await
-        .map_err(errors::any)?;
-
-        Ok(())
+        self.write(input, None).await
     }
 
     #[tracing::instrument]
     async fn delete(&self, input: &Self::T) -> Result<()> {
-        let id = input
-            .id
-            .parse::<u64>()
-            .map_err(|err| errors::bad_request(&err))?;
-        sqlx::query(
-            r#"UPDATE `group_user` SET `deleted` = `id`,`deleted_at`= now()
-            WHERE id = ? AND `deleted` = 0;"#,
-        )
-        .bind(id)
-        .execute(&self.pool)
-        .await
-        .map_err(errors::any)?;
-
-        Ok(())
+        soft_delete(&self.conn, "group_user", &input.id, None).await
     }
 
     #[tracing::instrument]
@@ -66,12 +142,14 @@ impl Interface for GroupUserImpl {
             .parse::<u64>()
             .map_err(|err| errors::bad_request(&err))?;
         let row = match sqlx::query(
-            r#"SELECT `id`,`group_id`,`user_id`,`created_at`,`updated_at`
+            r#"SELECT `id`,`group_id`,`user_id`,
+                `not_before`,`expires_at`,
+                `resource_version`,`created_at`,`updated_at`
                 FROM `group_user`
                 WHERE id = ? AND `deleted` = 0;"#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.conn.acquire().await?)
         .await
         {
             Ok(v) => match v {
@@ -93,6 +171,10 @@ impl Interface for GroupUserImpl {
             .try_get::<u64, _>("user_id")
             .map_err(errors::any)?
             .to_string();
+        output.resource_version =
+            row.try_get("resource_version").map_err(errors::any)?;
+        output.not_before = row.try_get("not_before").map_err(errors::any)?;
+        output.expires_at = row.try_get("expires_at").map_err(errors::any)?;
         output.created_at = row.try_get("created_at").map_err(errors::any)?;
         output.updated_at = row.try_get("updated_at").map_err(errors::any)?;
         Ok(())
@@ -104,128 +186,94 @@ impl Interface for GroupUserImpl {
         opts: &Self::L,
         output: &mut List<Self::T>,
     ) -> Result<()> {
-        let mut wheres = String::new();
-        combine_param(&mut wheres, opts)?;
-
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
-        wheres.push_str(r#"`deleted` = 0"#);
-        // 查询total
-        if !opts.pagination.count_disable {
-            let policy_result = sqlx::query(
-                format!(
-                    r#"SELECT COUNT(*) as count FROM `group_user`
-            WHERE {};"#,
-                    wheres,
-                )
-                .as_str(),
-            )
-            .fetch_one(&self.pool)
-            .await
-            .map_err(errors::any)?;
-
-            output.total =
-                policy_result.try_get("count").map_err(errors::any)?;
-        }
-
-        // 查询列表
-        opts.pagination.convert(&mut wheres);
-
-        output.limit = opts.pagination.limit;
-        output.offset = opts.pagination.offset;
-
-        let rows = sqlx::query(
-            format!(
-                r#"SELECT `id`,`group_id`,`user_id`,`created_at`,`updated_at`
-                FROM `group_user`
-                WHERE {};"#,
-                wheres,
-            )
-            .as_str(),
-        )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(errors::any)?;
-        for row in rows.iter() {
-            output.data.push(Self::T {
-                id: row
-                    .try_get::<u64, _>("id")
-                    .map_err(errors::any)?
-                    .to_string(),
-                group_id: row
-                    .try_get::<u64, _>("group_id")
-                    .map_err(errors::any)?
-                    .to_string(),
-                user_id: row
-                    .try_get::<u64, _>("user_id")
-                    .map_err(errors::any)?
-                    .to_string(),
-                created_at: row.try_get("created_at").map_err(errors::any)?,
-                updated_at: row.try_get("updated_at").map_err(errors::any)?,
-            });
-        }
-
-        Ok(())
+        self.select(opts, output, false).await
     }
 
     #[tracing::instrument]
     async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
-        let mut wheres = String::new();
-        combine_param(&mut wheres, opts)?;
-
+        let mut filter = opts.filter()?;
         if !unscoped {
-            wheres.push_str(" AND ");
-            wheres.push_str(r#"`deleted` = 0"#);
+            filter = filter.not_deleted();
         }
-        let result = sqlx::query(
-            format!(
-                r#"SELECT COUNT(*) as count FROM `group_user`
-            WHERE {} LIMIT 1;"#,
-                wheres,
-            )
-            .as_str(),
-        )
-        .fetch_one(&self.pool)
-        .await
-        .map_err(errors::any)?;
-        result.try_get("count").map_err(errors::any)
+        let mut builder =
+            QueryBuilder::new(r#"SELECT COUNT(*) as count FROM `group_user`"#);
+        filter.push(&mut builder);
+        builder
+            .build()
+            .fetch_one(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?
+            .try_get("count")
+            .map_err(errors::any)
     }
-}
 
-fn combine_param(wheres: &mut String, opts: &ListParams) -> Result<()> {
-    if let Some(v) = &opts.id {
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.write(input, Some(resource_version)).await
+    }
 
-        wheres.push_str(
-            format!(
-                r#"`id` = {}"#,
-                v.parse::<u64>().map_err(|err| errors::bad_request(&err))?
-            )
-            .as_str(),
-        );
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "group_user", &input.id).await?;
+                let previous = stored(&store, input).await?;
+                store.write(input, resource_version).await?;
+                Ok(previous)
+            })
+            .await
     }
 
-    if let Some(group_id) = &opts.group_id {
-        let group_id_u64: u64 =
-            group_id.parse().map_err(|err| errors::bad_request(&err))?;
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "group_user", &input.id).await?;
+                discarded(&store, input, resource_version).await
+            })
+            .await
+    }
 
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
-        wheres.push_str(format!(r#"`group_id` = {}"#, group_id_u64).as_str());
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        soft_delete(&self.conn, "group_user", &input.id, Some(resource_version))
+            .await
     }
-    if let Some(user_id) = &opts.user_id {
-        let user_id_u64: u64 =
-            user_id.parse().map_err(|err| errors::bad_request(&err))?;
 
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
-        wheres.push_str(format!(r#"`user_id` = {}"#, user_id_u64).as_str());
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true).await
     }
 
-    Ok(())
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        restore(&self.conn, "group_user", &input.id).await
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        purge(&self.conn, "group_user", before).await
+    }
 }
@@ -1,7 +1,10 @@
+mod audit;
 mod authcode;
 mod authrequest;
+mod changelog;
 mod client;
 mod connector;
+mod gc;
 mod group;
 mod group_user;
 mod key;
@@ -9,14 +12,25 @@ mod offlinesession;
 mod policy;
 mod policy_binding;
 mod refresh_token;
+mod relation_tuple;
 mod role;
 mod role_binding;
 mod user;
 
+use chrono::NaiveDateTime;
+use sqlx::{mysql::MySqlArguments, query::Query, MySql, QueryBuilder};
+
+use cim_slo::{errors, Result};
+
+use crate::{label, Conn};
+
+pub use audit::AuditImpl;
 pub use authcode::AuthCodeImpl;
 pub use authrequest::AuthRequestImpl;
+pub use changelog::ChangelogImpl;
 pub use client::ClientImpl;
 pub use connector::ConnectorImpl;
+pub use gc::GcImpl;
 pub use group::GroupImpl;
 pub use group_user::GroupUserImpl;
 pub use key::KeysImpl;
@@ -24,6 +38,208 @@ pub use offlinesession::OfflineSessionImpl;
 pub use policy::PolicyImpl;
 pub use policy_binding::PolicyBindingImpl;
 pub use refresh_token::RefreshTokenImpl;
+pub use relation_tuple::RelationTupleImpl;
 pub use role::RoleImpl;
 pub use role_binding::RoleBindingImpl;
 pub use user::UserImpl;
+
+/// write_query writes row `id` of table with columns, binding the id, the
+/// columns and the id again. The resource_version is bumped from the stored
+/// one and created_at is kept, a soft deleted row is left alone. When
+/// guarded only the live row still at the resource_version bound last is
+/// written
+fn write_query(table: &str, columns: &[&str], guarded: bool) -> String {
+    if guarded {
+        let set: Vec<_> =
+            columns.iter().map(|v| format!("`{v}` = ?")).collect();
+        format!(
+            "UPDATE `{table}` SET `id` = ?, {},
+                `resource_version` = `resource_version` + 1
+                WHERE `id` = ? AND `resource_version` = ? AND `deleted` = 0;",
+            set.join(",")
+        )
+    } else {
+        let names: Vec<_> = columns.iter().map(|v| format!("`{v}`")).collect();
+        let set: Vec<_> = columns
+            .iter()
+            .map(|v| format!("`{v}` = VALUES(`{v}`)"))
+            .collect();
+        format!(
+            "INSERT INTO `{table}` (`id`,{},`resource_version`)
+                SELECT ?,{},1 FROM DUAL WHERE NOT EXISTS (
+                    SELECT 1 FROM `{table}` WHERE `id` = ? AND `deleted` <> 0
+                )
+                ON DUPLICATE KEY UPDATE {},
+                `resource_version` = `resource_version` + 1;",
+            names.join(","),
+            vec!["?"; columns.len()].join(","),
+            set.join(",")
+        )
+    }
+}
+
+/// write_row runs query built on write_query, it conflicts when the row isn't
+/// at resource_version any more or is soft deleted
+async fn write_row<'q>(
+    conn: &Conn<MySql>,
+    query: Query<'q, MySql, MySqlArguments>,
+    table: &str,
+    id: &str,
+    resource_version: Option<u64>,
+) -> Result<()> {
+    let query = match resource_version {
+        Some(v) => query.bind(v),
+        None => query,
+    };
+    let result = query
+        .execute(&mut *conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+    if result.rows_affected() != 0 {
+        return Ok(());
+    }
+    Err(match resource_version {
+        Some(v) => errors::conflict(&format!(
+            "{table} {id} is not at resource version {v}"
+        )),
+        None => errors::conflict(&format!(
+            "{table} {id} is deleted, it can only be restored"
+        )),
+    })
+}
+
+/// soft_delete marks row id of table deleted, when resource_version is
+/// given only if it is still at it, bumping it
+async fn soft_delete(
+    conn: &Conn<MySql>,
+    table: &str,
+    id: &str,
+    resource_version: Option<u64>,
+) -> Result<()> {
+    let key = id.parse::<u64>().map_err(|err| errors::bad_request(&err))?;
+    let guard = match resource_version {
+        Some(_) => "AND `resource_version` = ?",
+        None => "",
+    };
+    let query = format!(
+        "UPDATE `{table}` SET `deleted` = `id`,
+            `deleted_at` = now(),
+            `resource_version` = `resource_version` + 1
+            WHERE `id` = ? {guard} AND `deleted` = 0;"
+    );
+    let query = sqlx::query(&query).bind(key);
+    let query = match resource_version {
+        Some(v) => query.bind(v),
+        None => query,
+    };
+    let result = query
+        .execute(&mut *conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+    match resource_version {
+        Some(v) if result.rows_affected() == 0 => Err(errors::conflict(
+            &format!("{table} {id} is not at resource version {v}"),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// lock locks row id of table, or the gap it would take, until the
+/// transaction of conn ends, so that no other write lands in between
+async fn lock(conn: &Conn<MySql>, table: &str, id: &str) -> Result<()> {
+    let key = id.parse::<u64>().map_err(|err| errors::bad_request(&err))?;
+    sqlx::query(&format!(
+        "SELECT 1 FROM `{table}` WHERE `id` = ? FOR UPDATE;"
+    ))
+    .bind(key)
+    .fetch_optional(&mut *conn.acquire().await?)
+    .await
+    .map_err(errors::any)?;
+    Ok(())
+}
+
+/// restore undeletes row id of table, bumping its resource_version. It
+/// conflicts with a live row holding the same unique columns
+async fn restore(conn: &Conn<MySql>, table: &str, id: &str) -> Result<()> {
+    let key = id.parse::<u64>().map_err(|err| errors::bad_request(&err))?;
+    let result = sqlx::query(&format!(
+        "UPDATE `{table}` SET `deleted` = 0, `deleted_at` = NULL,
+            `resource_version` = `resource_version` + 1
+            WHERE `id` = ? AND `deleted` <> 0;"
+    ))
+    .bind(key)
+    .execute(&mut *conn.acquire().await?)
+    .await
+    .map_err(|err| match err.as_database_error() {
+        Some(v) if v.is_unique_violation() => {
+            errors::conflict(&format!("{table} {id} is taken by a live one"))
+        }
+        _ => errors::any(err),
+    })?;
+    if result.rows_affected() == 0 {
+        return Err(errors::not_found(&format!("{table} {id} is not deleted")));
+    }
+    Ok(())
+}
+
+/// purge hard deletes the rows of table soft deleted before
+async fn purge(
+    conn: &Conn<MySql>,
+    table: &str,
+    before: NaiveDateTime,
+) -> Result<u64> {
+    let result = sqlx::query(&format!(
+        "DELETE FROM `{table}`
+            WHERE `deleted` <> 0 AND `deleted_at` < ?;"
+    ))
+    .bind(before)
+    .execute(&mut *conn.acquire().await?)
+    .await
+    .map_err(errors::any)?;
+    if label::KINDS.contains(&table) {
+        sqlx::query(&format!(
+            r#"DELETE FROM `label` WHERE `kind` = ?
+                AND `entity_id` NOT IN (SELECT `id` FROM `{table}`);"#
+        ))
+        .bind(table)
+        .execute(&mut *conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+    }
+    Ok(result.rows_affected())
+}
+
+/// label indexes the labels of row id of kind for the label selectors,
+/// replacing the ones it had
+async fn label(
+    conn: &Conn<MySql>,
+    kind: &str,
+    id: &str,
+    labels: &label::Labels,
+) -> Result<()> {
+    let key = id.parse::<u64>().map_err(|err| errors::bad_request(&err))?;
+    sqlx::query(r#"DELETE FROM `label` WHERE `kind` = ? AND `entity_id` = ?;"#)
+        .bind(kind)
+        .bind(key)
+        .execute(&mut *conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+    if labels.is_empty() {
+        return Ok(());
+    }
+    let mut builder = QueryBuilder::new(
+        r#"INSERT INTO `label` (`kind`,`entity_id`,`name`,`value`) "#,
+    );
+    builder.push_values(labels, |mut b, (name, value)| {
+        b.push_bind(kind)
+            .push_bind(key)
+            .push_bind(name)
+            .push_bind(value);
+    });
+    builder
+        .build()
+        .execute(&mut *conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+    Ok(())
+}
@@ -1,25 +1,26 @@
 use std::collections::HashMap;
 
 use async_trait::async_trait;
+use chrono::NaiveDateTime;
 use serde_json::value::RawValue;
-use sqlx::{types::Json, MySqlPool, Row};
+use sqlx::{types::Json, MySql, QueryBuilder, Row};
 
 use cim_slo::{errors, Result};
 
+use super::purge;
 use crate::{
-    convert::convert_param,
     offlinesession::{ListParams, OfflineSession, RefreshTokenRef},
-    Interface, List,
+    Conn, Interface, List,
 };
 
 #[derive(Clone, Debug)]
 pub struct OfflineSessionImpl {
-    pool: MySqlPool,
+    conn: Conn<MySql>,
 }
 
 impl OfflineSessionImpl {
-    pub fn new(pool: MySqlPool) -> Self {
-        Self { pool }
+    pub fn new(pool: impl Into<Conn<MySql>>) -> Self {
+        Self { conn: pool.into() }
     }
 }
 
@@ -40,7 +41,7 @@ impl Interface for OfflineSessionImpl {
         .bind(&content.conn_id)
         .bind(Json(&content.refresh))
         .bind(content.connector_data.as_ref().map(|v| v.to_string()))
-        .execute(&self.pool)
+        .execute(&mut *self.conn.acquire().await?)
         .await
         .map_err(errors::any)?;
         Ok(())
@@ -58,7 +59,7 @@ impl Interface for OfflineSessionImpl {
             WHERE id = ? AND `deleted` = 0;"#,
         )
         .bind(id)
-        .execute(&self.pool)
+        .execute(&mut *self.conn.acquire().await?)
         .await
         .map_err(errors::any)?;
         Ok(())
@@ -76,7 +77,7 @@ impl Interface for OfflineSessionImpl {
             WHERE id = ? AND deleted = 0;"#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.conn.acquire().await?)
         .await
         {
             Ok(v) => match v {
@@ -110,49 +111,39 @@ impl Interface for OfflineSessionImpl {
         opts: &Self::L,
         output: &mut List<Self::T>,
     ) -> Result<()> {
-        let mut wheres = String::new();
-        combine_param(&mut wheres, opts)?;
-
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
-        wheres.push_str(r#"`deleted` = 0"#);
+        let filter = opts.filter()?.not_deleted();
         // 查询total
         if !opts.pagination.count_disable {
-            let policy_result = sqlx::query(
-                format!(
-                    r#"SELECT COUNT(*) as count FROM `offline_session`
-            WHERE {};"#,
-                    wheres,
-                )
-                .as_str(),
-            )
-            .fetch_one(&self.pool)
-            .await
-            .map_err(errors::any)?;
-
-            output.total =
-                policy_result.try_get("count").map_err(errors::any)?;
+            let mut builder = QueryBuilder::new(
+                r#"SELECT COUNT(*) as count FROM `offline_session`"#,
+            );
+            filter.push(&mut builder);
+            output.total = builder
+                .build()
+                .fetch_one(&mut *self.conn.acquire().await?)
+                .await
+                .map_err(errors::any)?
+                .try_get("count")
+                .map_err(errors::any)?;
         }
 
         // 查询列表
-        opts.pagination.convert(&mut wheres);
-
         output.limit = opts.pagination.limit;
         output.offset = opts.pagination.offset;
 
-        let rows = sqlx::query(
-            format!(
-                r#"SELECT `id`,`user_id`,`conn_id`,`refresh`,`connector_data`
-            FROM `offline_session`
-            WHERE {};"#,
-                wheres,
-            )
-            .as_str(),
-        )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(errors::any)?;
+        let mut builder = QueryBuilder::new(
+            r#"SELECT `id`,`user_id`,`conn_id`,`refresh`,`connector_data`
+            FROM `offline_session`"#,
+        );
+        opts.pagination
+            .seek(filter, ListParams::SORTABLE)?
+            .push(&mut builder);
+        opts.pagination.push(&mut builder, ListParams::SORTABLE)?;
+        let rows = builder
+            .build()
+            .fetch_all(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?;
         for row in rows.iter() {
             output.data.push(Self::T {
                 id: row
@@ -177,28 +168,32 @@ impl Interface for OfflineSessionImpl {
                     .map(|v| RawValue::from_string(v).unwrap()),
             })
         }
+        output.next_cursor = opts
+            .pagination
+            .next_cursor(&output.data, ListParams::SORTABLE)?;
         Ok(())
     }
 
-    async fn count(&self, _opts: &Self::L, _unscoped: bool) -> Result<i64> {
-        todo!()
-    }
-}
-
-fn combine_param(wheres: &mut String, opts: &ListParams) -> Result<()> {
-    if let Some(user_id) = &opts.user_id {
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let mut filter = opts.filter()?;
+        if !unscoped {
+            filter = filter.not_deleted();
         }
-        wheres.push_str(r#"`user_id` = "#);
-        convert_param(wheres, user_id);
+        let mut builder = QueryBuilder::new(
+            r#"SELECT COUNT(*) as count FROM `offline_session`"#,
+        );
+        filter.push(&mut builder);
+        builder
+            .build()
+            .fetch_one(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?
+            .try_get("count")
+            .map_err(errors::any)
     }
-    if let Some(conn_id) = &opts.conn_id {
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
-        wheres.push_str(r#"`conn_id` = "#);
-        convert_param(wheres, conn_id);
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        purge(&self.conn, "offline_session", before).await
     }
-    Ok(())
 }
@@ -1,76 +1,190 @@
 use async_trait::async_trait;
-use sqlx::{types::Json, MySqlPool, Row};
+use chrono::{NaiveDateTime, Utc};
+use sqlx::{types::Json, MySql, QueryBuilder, Row};
 
-use cim_pim::{Request, Statement};
+use cim_pim::{PrincipalType, Request, Statement};
 use cim_slo::{errors, Result};
 
+use super::{label, lock, purge, restore, soft_delete, write_query, write_row};
 use crate::{
-    convert::convert_param,
-    policy::{ListParams, Policy, StatementStore},
-    Interface, List,
+    discarded,
+    expiry::next_change,
+    label::Labels,
+    policy::{self, Bindings, ListParams, Policy, StatementStore},
+    policy_binding::BindingsType,
+    role_binding::UserType,
+    stored, Conn, Interface, List,
 };
 
 #[derive(Clone, Debug)]
 pub struct PolicyImpl {
-    pool: MySqlPool,
+    conn: Conn<MySql>,
 }
 
 impl PolicyImpl {
-    pub fn new(pool: MySqlPool) -> Self {
-        Self { pool }
+    pub fn new(pool: impl Into<Conn<MySql>>) -> Self {
+        Self { conn: pool.into() }
     }
-}
 
-#[async_trait]
-impl Interface for PolicyImpl {
-    type T = Policy;
-    type L = ListParams;
+    /// select lists the rows matching opts, the soft deleted ones when
+    /// deleted
+    async fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<Policy>,
+        deleted: bool,
+    ) -> Result<()> {
+        let filter = opts.filter()?.deleted(deleted);
+        // 查询total
+        if !opts.pagination.count_disable {
+            let mut builder =
+                QueryBuilder::new(r#"SELECT COUNT(*) as count FROM `policy`"#);
+            filter.push(&mut builder);
+            output.total = builder
+                .build()
+                .fetch_one(&mut *self.conn.acquire().await?)
+                .await
+                .map_err(errors::any)?
+                .try_get("count")
+                .map_err(errors::any)?;
+        }
 
-    #[tracing::instrument]
-    async fn put(&self, input: &Self::T) -> Result<()> {
-        sqlx::query(
-            r#"REPLACE INTO `policy`
-            (`id`,`account_id`,`desc`,`version`,`statement`)
-            VALUES(?,?,?,?,?);"#,
-        )
-        .bind(&input.id)
-        .bind(&input.account_id)
-        .bind(&input.desc)
-        .bind(&input.version)
-        .bind(Json(&input.statement))
-        .execute(&self.pool)
-        .await
-        .map_err(errors::any)?;
+        // 查询列表
+        output.limit = opts.pagination.limit;
+        output.offset = opts.pagination.offset;
+
+        let mut builder = QueryBuilder::new(
+            r#"SELECT `id`,`account_id`,`desc`,`version`,`statement`,
+                `resource_version`,`labels`,`annotations`,`created_at`,`updated_at`
+                FROM `policy`"#,
+        );
+        opts.pagination
+            .seek(filter, ListParams::SORTABLE)?
+            .push(&mut builder);
+        opts.pagination.push(&mut builder, ListParams::SORTABLE)?;
+        let rows = builder
+            .build()
+            .fetch_all(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?;
+        for row in rows.iter() {
+            output.data.push(Policy {
+                id: row
+                    .try_get::<u64, _>("id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                account_id: row
+                    .try_get::<Option<u64>, _>("account_id")
+                    .map_err(errors::any)?
+                    .map(|v| v.to_string()),
+                desc: row.try_get("desc").map_err(errors::any)?,
+                version: row.try_get("version").map_err(errors::any)?,
+                statement: row
+                    .try_get::<Json<Vec<Statement>>, _>("statement")
+                    .map_err(errors::any)?
+                    .0,
+                resource_version: row
+                    .try_get("resource_version")
+                    .map_err(errors::any)?,
+                labels: row
+                    .try_get::<Json<Labels>, _>("labels")
+                    .map_err(errors::any)?
+                    .0,
+                annotations: row
+                    .try_get::<Json<Labels>, _>("annotations")
+                    .map_err(errors::any)?
+                    .0,
+                created_at: row.try_get("created_at").map_err(errors::any)?,
+                updated_at: row.try_get("updated_at").map_err(errors::any)?,
+            });
+        }
 
+        output.next_cursor = opts
+            .pagination
+            .next_cursor(&output.data, ListParams::SORTABLE)?;
         Ok(())
     }
 
-    #[tracing::instrument]
-    async fn delete(&self, input: &Self::T) -> Result<()> {
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it
+    async fn write(
+        &self,
+        input: &Policy,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
+        let query = write_query(
+            "policy",
+            &[
+                "account_id",
+                "desc",
+                "version",
+                "statement",
+                "labels",
+                "annotations",
+            ],
+            resource_version.is_some(),
+        );
+        let query = sqlx::query(&query)
+            .bind(&input.id)
+            .bind(&input.account_id)
+            .bind(&input.desc)
+            .bind(&input.version)
+            .bind(Json(&input.statement))
+            .bind(Json(&input.labels))
+            .bind(Json(&input.annotations))
+            .bind(&input.id);
+        write_row(&self.conn, query, "policy", &input.id, resource_version)
+            .await?;
+        label(&self.conn, "policy", &input.id, &input.labels).await?;
+
+        Ok(())
+    }
+
+    /// remove soft deletes input, when resource_version is given only
+    /// if the stored one is still at it
+    async fn remove(
+        &self,
+        input: &Policy,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
         let id = input
             .id
             .parse::<u64>()
             .map_err(|err| errors::bad_request(&err))?;
         if sqlx::query(
-            r#"SELECT COUNT(*) as count FROM `policy_bindings` WHERE `policy_id` = ? AND `deleted` = 0"#,
+            r#"SELECT COUNT(*) as count FROM `policy_binding` WHERE `policy_id` = ? AND `deleted` = 0"#,
         )
         .bind(id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.conn.acquire().await?)
         .await
         .map_err(errors::any)?.try_get::<i64,_>("count").map_err(errors::any)?!=0{
             return Err(errors::forbidden(&"can't delete policy, because it is used".to_string()));
         };
-        sqlx::query(
-            r#"UPDATE `policy` SET `deleted` = `id`,`deleted_at`= now()
-            WHERE id = ? AND `deleted` = 0;"#,
-        )
-        .bind(id)
-        .execute(&self.pool)
-        .await
-        .map_err(errors::any)?;
+        soft_delete(&self.conn, "policy", &input.id, resource_version).await?;
 
         Ok(())
     }
+}
+
+#[async_trait]
+impl Interface for PolicyImpl {
+    type T = Policy;
+    type L = ListParams;
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        // the row and its labels are indexed in the same transaction
+        self.conn
+            .scoped(
+                |conn| async move { Self { conn }.write(input, None).await },
+            )
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        self.remove(input, None).await
+    }
 
     #[tracing::instrument]
     async fn get(&self, output: &mut Self::T) -> Result<()> {
@@ -79,12 +193,13 @@ impl Interface for PolicyImpl {
             .parse::<u64>()
             .map_err(|err| errors::bad_request(&err))?;
         let row = match sqlx::query(
-            r#"SELECT `id`,`account_id`,`desc`,`version`,`statement`,`created_at`,`updated_at`
+            r#"SELECT `id`,`account_id`,`desc`,`version`,`statement`,
+                `resource_version`,`labels`,`annotations`,`created_at`,`updated_at`
                 FROM `policy`
                 WHERE id = ? AND `deleted` = 0;"#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.conn.acquire().await?)
         .await
         {
             Ok(v) => match v {
@@ -108,6 +223,16 @@ impl Interface for PolicyImpl {
             .try_get::<Json<Vec<Statement>>, _>("statement")
             .map_err(errors::any)?
             .0;
+        output.resource_version =
+            row.try_get("resource_version").map_err(errors::any)?;
+        output.labels = row
+            .try_get::<Json<Labels>, _>("labels")
+            .map_err(errors::any)?
+            .0;
+        output.annotations = row
+            .try_get::<Json<Labels>, _>("annotations")
+            .map_err(errors::any)?
+            .0;
         output.created_at = row.try_get("created_at").map_err(errors::any)?;
         output.updated_at = row.try_get("updated_at").map_err(errors::any)?;
         Ok(())
@@ -119,185 +244,152 @@ impl Interface for PolicyImpl {
         opts: &Self::L,
         output: &mut List<Self::T>,
     ) -> Result<()> {
-        let mut wheres = String::new();
-        combine_param(&mut wheres, opts)?;
+        self.select(opts, output, false).await
+    }
 
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
+    #[tracing::instrument]
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let mut filter = opts.filter()?;
+        if !unscoped {
+            filter = filter.not_deleted();
         }
-        wheres.push_str(r#"`deleted` = 0"#);
-        // 查询total
-        if !opts.pagination.count_disable {
-            let policy_result = sqlx::query(
-                format!(
-                    r#"SELECT COUNT(*) as count FROM `policy`
-            WHERE {};"#,
-                    wheres,
-                )
-                .as_str(),
-            )
-            .fetch_one(&self.pool)
+        let mut builder =
+            QueryBuilder::new(r#"SELECT COUNT(*) as count FROM `policy`"#);
+        filter.push(&mut builder);
+        builder
+            .build()
+            .fetch_one(&mut *self.conn.acquire().await?)
             .await
-            .map_err(errors::any)?;
-
-            output.total =
-                policy_result.try_get("count").map_err(errors::any)?;
-        }
-
-        // 查询列表
-        opts.pagination.convert(&mut wheres);
-
-        output.limit = opts.pagination.limit;
-        output.offset = opts.pagination.offset;
-
-        let rows = sqlx::query(
-            format!(
-                r#"SELECT `id`,`account_id`,`desc`,`version`,`statement`,`created_at`,`updated_at`
-                FROM `policy`
-                WHERE {};"#,
-                wheres,
-            )
-            .as_str(),
-        )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(errors::any)?;
-        for row in rows.iter() {
-            output.data.push(Self::T {
-                id: row
-                    .try_get::<u64, _>("id")
-                    .map_err(errors::any)?
-                    .to_string(),
-                account_id: row
-                    .try_get::<Option<u64>, _>("account_id")
-                    .map_err(errors::any)?
-                    .map(|v| v.to_string()),
-                desc: row.try_get("desc").map_err(errors::any)?,
-                version: row.try_get("version").map_err(errors::any)?,
-                statement: row
-                    .try_get::<Json<Vec<Statement>>, _>("statement")
-                    .map_err(errors::any)?
-                    .0,
-                created_at: row.try_get("created_at").map_err(errors::any)?,
-                updated_at: row.try_get("updated_at").map_err(errors::any)?,
-            });
-        }
+            .map_err(errors::any)?
+            .try_get("count")
+            .map_err(errors::any)
+    }
 
-        Ok(())
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.conn
+            .scoped(|conn| async move {
+                Self { conn }.write(input, Some(resource_version)).await
+            })
+            .await
     }
 
     #[tracing::instrument]
-    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
-        let mut wheres = String::new();
-        combine_param(&mut wheres, opts)?;
-        if !unscoped {
-            wheres.push_str(" AND ");
-            wheres.push_str(r#"`deleted` = 0"#);
-        }
-        let result = sqlx::query(
-            format!(
-                r#"SELECT COUNT(*) as count FROM `policy`
-            WHERE {} LIMIT 1;"#,
-                wheres,
-            )
-            .as_str(),
-        )
-        .fetch_one(&self.pool)
-        .await
-        .map_err(errors::any)?;
-        result.try_get("count").map_err(errors::any)
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "policy", &input.id).await?;
+                let previous = stored(&store, input).await?;
+                store.write(input, resource_version).await?;
+                Ok(previous)
+            })
+            .await
     }
-}
 
-fn combine_param(wheres: &mut String, opts: &ListParams) -> Result<()> {
-    if let Some(v) = &opts.id {
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "policy", &input.id).await?;
+                discarded(&store, input, resource_version).await
+            })
+            .await
+    }
 
-        wheres.push_str(
-            format!(
-                r#"`id` = {}"#,
-                v.parse::<u64>().map_err(|err| errors::bad_request(&err))?
-            )
-            .as_str(),
-        );
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.remove(input, Some(resource_version)).await
     }
 
-    if let Some(version) = &opts.version {
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
-        wheres.push_str(r#"`version` = "#);
-        convert_param(wheres, version);
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true).await
     }
-    if let Some(account_id) = &opts.account_id {
-        let account_id_u64: u64 = account_id
-            .parse()
-            .map_err(|err| errors::bad_request(&err))?;
 
-        wheres
-            .push_str(format!(r#"`account_id` = {}"#, account_id_u64).as_str());
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        restore(&self.conn, "policy", &input.id).await
     }
-    if let Some(group_id) = &opts.group_id {
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
-        let group_id_u64: u64 =
-            group_id.parse().map_err(|err| errors::bad_request(&err))?;
-        wheres.push_str(format!(r#"`id` IN (SELECT `policy_id` FROM `policy_bindings` WHERE `bindings_id` = {} AND `bindings_type` = 2 AND `deleted` = 0)"#,
-            group_id_u64).as_str());
-    };
-    if let Some(user_id) = &opts.user_id {
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
-        let user_id_u64: u64 =
-            user_id.parse().map_err(|err| errors::bad_request(&err))?;
-        wheres.push_str(format!(r#"`id` IN (SELECT `bindings_id` FROM `policy_bindings` WHERE `bingdings_id` = {} AND `bindings_type` = 1 AND `deleted` = 0)"#,
-            user_id_u64).as_str());
-    };
-    if let Some(role_id) = &opts.role_id {
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
-        let role_id_u64: u64 =
-            role_id.parse().map_err(|err| errors::bad_request(&err))?;
-        wheres.push_str(format!(r#"`id` IN (SELECT `bindings_id` FROM `policy_bindings` WHERE `bingdings_id` = {} AND `bindings_type` = 3 AND `deleted` = 0)"#,
-            role_id_u64).as_str());
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        purge(&self.conn, "policy", before).await
     }
-    Ok(())
 }
 
 #[async_trait]
 impl StatementStore for PolicyImpl {
     async fn get_statement(&self, req: &Request) -> Result<Vec<Statement>> {
-        let user_id = req.subject.parse::<u64>().map_err(errors::any)?;
-        let rows = sqlx::query(r#"SELECT t2.`statement`
+        let user_id = policy::user_id(req.principal_type, &req.subject)?;
+        let user_type = u8::from(&UserType::from(req.principal_type));
+        let bindings_type = u8::from(&BindingsType::from(req.principal_type));
+        let rows = sqlx::query(r#"WITH RECURSIVE `member_group` (`id`) AS (
+                SELECT `group_id` FROM `group_user`
+                WHERE `user_id` = ? AND `deleted` = 0
+                AND (`not_before` IS NULL OR `not_before` <= UTC_TIMESTAMP(3))
+                AND (`expires_at` IS NULL OR `expires_at` > UTC_TIMESTAMP(3))
+                UNION
+                SELECT g.`parent_id` FROM `group` g
+                JOIN `member_group` m ON g.`id` = m.`id`
+                WHERE g.`deleted` = 0 AND g.`parent_id` IS NOT NULL
+            )
+            SELECT t2.`statement`
             FROM (
                 (
-                SELECT `policy_id` FROM `policy_binding` WHERE `bindings_id` = ? AND `bindings_type` = 1 AND `deleted` = 0
+                SELECT `policy_id` FROM `policy_binding` WHERE `bindings_type` = ? AND `bindings_id` = ? AND `deleted` = 0
+                AND (`not_before` IS NULL OR `not_before` <= UTC_TIMESTAMP(3))
+                AND (`expires_at` IS NULL OR `expires_at` > UTC_TIMESTAMP(3))
                 )
                 UNION
                 (
-                SELECT a3.`policy_id` FROM `group_user` a1 RIGHT JOIN `group` a2 ON a1.`group_id` = a2.`id`
+                SELECT a3.`policy_id` FROM `member_group` a1 RIGHT JOIN `group` a2 ON a1.`id` = a2.`id`
                 RIGHT JOIN `policy_binding` a3 ON a2.`id` = a3.`bindings_id`
-                WHERE a1.`user_id` = ? AND a1.`deleted` = 0 AND
+                WHERE a1.`id` IS NOT NULL AND
                 a2.`deleted` = 0 AND a3.`bindings_type` = 2 AND a3.`deleted` = 0
+                AND (a3.`not_before` IS NULL OR a3.`not_before` <= UTC_TIMESTAMP(3))
+                AND (a3.`expires_at` IS NULL OR a3.`expires_at` > UTC_TIMESTAMP(3))
                 )
                 UNION
                 (
                 SELECT b3.`policy_id` FROM `role_binding` b1 RIGHT JOIN `role` b2 ON b1.`role_id` = b2.`id`
                 RIGHT JOIN `policy_binding` b3 ON b2.`id` = b3.`bindings_id`
-                WHERE b1.`user_id` = ? AND b1.`deleted` = 0 AND
+                WHERE b1.`user_type` = ? AND b1.`user_id` = ? AND b1.`deleted` = 0 AND
                 b2.`deleted` = 0 AND b3.`bindings_type` = 3 AND b3.`deleted` = 0
+                AND (b1.`not_before` IS NULL OR b1.`not_before` <= UTC_TIMESTAMP(3))
+                AND (b1.`expires_at` IS NULL OR b1.`expires_at` > UTC_TIMESTAMP(3))
+                AND (b3.`not_before` IS NULL OR b3.`not_before` <= UTC_TIMESTAMP(3))
+                AND (b3.`expires_at` IS NULL OR b3.`expires_at` > UTC_TIMESTAMP(3))
                 )
             )
             t1 RIGHT JOIN `policy` t2 ON t1.`policy_id`=t2.`id` WHERE t2.`deleted`=0;"#)
             .bind(user_id)
-            .bind(user_id)
-            .bind(user_id)
-            .fetch_all(&self.pool)
+            .bind(bindings_type)
+            .bind(&req.subject)
+            .bind(user_type)
+            .bind(&req.subject)
+            .fetch_all(&mut *self.conn.acquire().await?)
             .await
             .map_err(errors::any)?;
 
@@ -312,15 +404,74 @@ impl StatementStore for PolicyImpl {
         }
         Ok(result)
     }
-}
 
-#[async_trait]
-impl<T: StatementStore + Sync> StatementStore for Vec<T> {
-    async fn get_statement(&self, req: &Request) -> Result<Vec<Statement>> {
-        let mut result = Vec::with_capacity(self.len());
-        for store in self.iter() {
-            result.append(&mut store.get_statement(req).await?);
+    async fn get_bindings(
+        &self,
+        principal_type: PrincipalType,
+        subject: &str,
+    ) -> Result<Bindings> {
+        let user_id = policy::user_id(principal_type, subject)?;
+        let user_type = u8::from(&UserType::from(principal_type));
+        let bindings_type = u8::from(&BindingsType::from(principal_type));
+        let rows = sqlx::query(
+            r#"WITH RECURSIVE `member_group` (`id`) AS (
+                SELECT `group_id` FROM `group_user`
+                WHERE `user_id` = ? AND `deleted` = 0
+                UNION
+                SELECT g.`parent_id` FROM `group` g
+                JOIN `member_group` m ON g.`id` = m.`id`
+                WHERE g.`deleted` = 0 AND g.`parent_id` IS NOT NULL
+            )
+            SELECT 'group' AS `kind`, `id`, NULL AS `not_before`,
+                NULL AS `expires_at`
+            FROM `member_group`
+            UNION ALL
+            SELECT 'window', `id`, `not_before`, `expires_at`
+            FROM `group_user` WHERE `user_id` = ? AND `deleted` = 0
+            UNION ALL
+            SELECT 'role', `role_id`, `not_before`, `expires_at`
+            FROM `role_binding`
+            WHERE `user_type` = ? AND `user_id` = ? AND `deleted` = 0
+            UNION ALL
+            SELECT 'policy', `policy_id`, `not_before`, `expires_at`
+            FROM `policy_binding`
+            WHERE `deleted` = 0 AND (
+                (`bindings_type` = ? AND `bindings_id` = ?)
+                OR (`bindings_type` = 2 AND `bindings_id` IN (
+                    SELECT `id` FROM `member_group`))
+                OR (`bindings_type` = 3 AND `bindings_id` IN (
+                    SELECT `role_id` FROM `role_binding`
+                    WHERE `user_type` = ? AND `user_id` = ? AND `deleted` = 0))
+            );"#,
+        )
+        .bind(user_id)
+        .bind(user_id)
+        .bind(user_type)
+        .bind(subject)
+        .bind(bindings_type)
+        .bind(subject)
+        .bind(user_type)
+        .bind(subject)
+        .fetch_all(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+
+        let mut result = Bindings::default();
+        let mut windows = Vec::with_capacity(rows.len());
+        for row in rows.iter() {
+            windows.push((
+                row.try_get("not_before").map_err(errors::any)?,
+                row.try_get("expires_at").map_err(errors::any)?,
+            ));
+            let id = row.try_get::<i64, _>("id").map_err(errors::any)?;
+            match row.try_get::<&str, _>("kind").map_err(errors::any)? {
+                "group" => result.groups.push(id.to_string()),
+                "role" => result.roles.push(id.to_string()),
+                "policy" => result.policies.push(id.to_string()),
+                _ => {}
+            }
         }
+        result.changes_at = next_change(windows, Utc::now().naive_utc());
         Ok(result)
     }
 }
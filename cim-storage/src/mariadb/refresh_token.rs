@@ -1,19 +1,21 @@
 use async_trait::async_trait;
+use chrono::NaiveDateTime;
 use serde_json::value::RawValue;
-use sqlx::{types::Json, MySqlPool, Row};
+use sqlx::{types::Json, MySql, Row};
 
 use cim_slo::{errors, Result};
 
-use crate::{refresh_token::RefreshToken, Claim, Interface, List};
+use super::purge;
+use crate::{refresh_token::RefreshToken, Claim, Conn, Interface, List};
 
 #[derive(Clone, Debug)]
 pub struct RefreshTokenImpl {
-    pool: MySqlPool,
+    conn: Conn<MySql>,
 }
 
 impl RefreshTokenImpl {
-    pub fn new(pool: MySqlPool) -> Self {
-        Self { pool }
+    pub fn new(pool: impl Into<Conn<MySql>>) -> Self {
+        Self { conn: pool.into() }
     }
 }
 
@@ -45,7 +47,7 @@ impl Interface for RefreshTokenImpl {
         .bind(&content.connector_id)
         .bind(content.connector_data.as_ref().map(|v| v.to_string()))
         .bind(content.last_used_at)
-        .execute(&self.pool)
+        .execute(&mut *self.conn.acquire().await?)
         .await
         .map_err(errors::any)?;
         Ok(())
@@ -63,7 +65,7 @@ impl Interface for RefreshTokenImpl {
             WHERE id = ? AND `deleted` = 0;"#,
         )
         .bind(id)
-        .execute(&self.pool)
+        .execute(&mut *self.conn.acquire().await?)
         .await
         .map_err(errors::any)?;
         Ok(())
@@ -82,7 +84,7 @@ impl Interface for RefreshTokenImpl {
             WHERE id = ? AND deleted = 0;"#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.conn.acquire().await?)
         .await
         {
             Ok(v) => match v {
@@ -135,4 +137,9 @@ impl Interface for RefreshTokenImpl {
     async fn count(&self, _opts: &Self::L, _unscoped: bool) -> Result<i64> {
         todo!()
     }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        purge(&self.conn, "refresh_token", before).await
+    }
 }
@@ -1,49 +1,136 @@
 use async_trait::async_trait;
-use sqlx::{MySqlPool, Row};
+use chrono::NaiveDateTime;
+use sqlx::{types::Json, MySql, QueryBuilder, Row};
 
 use cim_slo::{errors, Result};
 
+use super::{label, lock, purge, restore, soft_delete, write_query, write_row};
 use crate::{
+    discarded,
+    label::Labels,
     role::{ListParams, Role},
-    Interface, List,
+    stored, Conn, Interface, List,
 };
 
 #[derive(Clone, Debug)]
 pub struct RoleImpl {
-    pool: MySqlPool,
+    conn: Conn<MySql>,
 }
 
 impl RoleImpl {
-    pub fn new(pool: MySqlPool) -> Self {
-        Self { pool }
+    pub fn new(pool: impl Into<Conn<MySql>>) -> Self {
+        Self { conn: pool.into() }
     }
-}
 
-#[async_trait]
-impl Interface for RoleImpl {
-    type T = Role;
-    type L = ListParams;
+    /// select lists the rows matching opts, the soft deleted ones when
+    /// deleted
+    async fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<Role>,
+        deleted: bool,
+    ) -> Result<()> {
+        let filter = opts.filter()?.deleted(deleted);
+        // 查询total
+        if !opts.pagination.count_disable {
+            let mut builder =
+                QueryBuilder::new(r#"SELECT COUNT(*) as count FROM `role`"#);
+            filter.push(&mut builder);
+            output.total = builder
+                .build()
+                .fetch_one(&mut *self.conn.acquire().await?)
+                .await
+                .map_err(errors::any)?
+                .try_get("count")
+                .map_err(errors::any)?;
+        }
 
-    #[tracing::instrument]
-    async fn put(&self, input: &Self::T) -> Result<()> {
-        sqlx::query(
-            r#"REPLACE INTO `role`
-            (`id`,`account_id`,`name`,`desc`)
-            VALUES(?,?,?,?);"#,
-        )
-        .bind(&input.id)
-        .bind(&input.account_id)
-        .bind(&input.name)
-        .bind(&input.desc)
-        .execute(&self.pool)
-        .await
-        .map_err(errors::any)?;
+        // 查询列表
+        output.limit = opts.pagination.limit;
+        output.offset = opts.pagination.offset;
 
+        let mut builder = QueryBuilder::new(
+            r#"SELECT `id`,`account_id`,`name`,`desc`,
+                `resource_version`,`labels`,`annotations`,`created_at`,`updated_at`
+                FROM `role`"#,
+        );
+        opts.pagination
+            .seek(filter, ListParams::SORTABLE)?
+            .push(&mut builder);
+        opts.pagination.push(&mut builder, ListParams::SORTABLE)?;
+        let rows = builder
+            .build()
+            .fetch_all(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?;
+        for row in rows.iter() {
+            output.data.push(Role {
+                id: row
+                    .try_get::<u64, _>("id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                account_id: row
+                    .try_get::<u64, _>("account_id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                name: row.try_get("name").map_err(errors::any)?,
+                desc: row.try_get("desc").map_err(errors::any)?,
+                resource_version: row
+                    .try_get("resource_version")
+                    .map_err(errors::any)?,
+                labels: row
+                    .try_get::<Json<Labels>, _>("labels")
+                    .map_err(errors::any)?
+                    .0,
+                annotations: row
+                    .try_get::<Json<Labels>, _>("annotations")
+                    .map_err(errors::any)?
+                    .0,
+                created_at: row.try_get("created_at").map_err(errors::any)?,
+                updated_at: row.try_get("updated_at").map_err(errors::any)?,
+            });
+        }
+
+        output.next_cursor = opts
+            .pagination
+            .next_cursor(&output.data, ListParams::SORTABLE)?;
         Ok(())
     }
 
-    #[tracing::instrument]
-    async fn delete(&self, input: &Self::T) -> Result<()> {
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it
+    async fn write(
+        &self,
+        input: &Role,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
+        let query = write_query(
+            "role",
+            &["account_id", "name", "desc", "labels", "annotations"],
+            resource_version.is_some(),
+        );
+        let query = sqlx::query(&query)
+            .bind(&input.id)
+            .bind(&input.account_id)
+            .bind(&input.name)
+            .bind(&input.desc)
+            .bind(Json(&input.labels))
+            .bind(Json(&input.annotations))
+            .bind(&input.id);
+        write_row(&self.conn, query, "role", &input.id, resource_version)
+            .await?;
+        label(&self.conn, "role", &input.id, &input.labels).await?;
+
+        Ok(())
+    }
+
+    /// remove soft deletes input, when resource_version is given only
+    /// if the stored one is still at it
+    async fn remove(
+        &self,
+        input: &Role,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
         let id = input
             .id
             .parse::<u64>()
@@ -52,31 +139,45 @@ impl Interface for RoleImpl {
             r#"SELECT COUNT(*) as count FROM `role_bindings` WHERE `role_id` = ? AND `deleted` = 0"#,
         )
         .bind(id)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *self.conn.acquire().await?)
         .await
         .map_err(errors::any)?.try_get::<i64,_>("count").map_err(errors::any)?!=0{
             return Err(errors::forbidden(&"can't delete role, because it is attached to user".to_string()));
         };
         if sqlx::query(
-            r#"SELECT COUNT(*) as count FROM `policy_bindings` WHERE `bindings_type` = 3 AND `bindings_id` = ? AND `deleted` = 0"#,
+            r#"SELECT COUNT(*) as count FROM `policy_binding` WHERE `bindings_type` = 3 AND `bindings_id` = ? AND `deleted` = 0"#,
         )
-        .bind(id)
-        .fetch_one(&self.pool)
+        .bind(id.to_string())
+        .fetch_one(&mut *self.conn.acquire().await?)
         .await
         .map_err(errors::any)?.try_get::<i64,_>("count").map_err(errors::any)?!=0{
             return Err(errors::forbidden(&"can't delete role, because it is attached by policy".to_string()));
         };
-        sqlx::query(
-            r#"UPDATE `role` SET `deleted` = `id`,`deleted_at`= now()
-            WHERE id = ? AND `deleted` = 0;"#,
-        )
-        .bind(id)
-        .execute(&self.pool)
-        .await
-        .map_err(errors::any)?;
+        soft_delete(&self.conn, "role", &input.id, resource_version).await?;
 
         Ok(())
     }
+}
+
+#[async_trait]
+impl Interface for RoleImpl {
+    type T = Role;
+    type L = ListParams;
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        // the row and its labels are indexed in the same transaction
+        self.conn
+            .scoped(
+                |conn| async move { Self { conn }.write(input, None).await },
+            )
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        self.remove(input, None).await
+    }
     #[tracing::instrument]
     async fn get(&self, output: &mut Self::T) -> Result<()> {
         let id = output
@@ -84,12 +185,13 @@ impl Interface for RoleImpl {
             .parse::<u64>()
             .map_err(|err| errors::bad_request(&err))?;
         let row = match sqlx::query(
-            r#"SELECT `id`,`account_id`,`name`,`desc`,`created_at`,`updated_at`
+            r#"SELECT `id`,`account_id`,`name`,`desc`,
+                `resource_version`,`labels`,`annotations`,`created_at`,`updated_at`
                 FROM `role`
                 WHERE id = ? AND `deleted` = 0;"#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.conn.acquire().await?)
         .await
         {
             Ok(v) => match v {
@@ -109,6 +211,16 @@ impl Interface for RoleImpl {
             .to_string();
         output.name = row.try_get("name").map_err(errors::any)?;
         output.desc = row.try_get("desc").map_err(errors::any)?;
+        output.resource_version =
+            row.try_get("resource_version").map_err(errors::any)?;
+        output.labels = row
+            .try_get::<Json<Labels>, _>("labels")
+            .map_err(errors::any)?
+            .0;
+        output.annotations = row
+            .try_get::<Json<Labels>, _>("annotations")
+            .map_err(errors::any)?
+            .0;
         output.created_at = row.try_get("created_at").map_err(errors::any)?;
         output.updated_at = row.try_get("updated_at").map_err(errors::any)?;
         Ok(())
@@ -120,128 +232,97 @@ impl Interface for RoleImpl {
         opts: &Self::L,
         output: &mut List<Self::T>,
     ) -> Result<()> {
-        let mut wheres = String::new();
-        combine_param(&mut wheres, opts)?;
+        self.select(opts, output, false).await
+    }
 
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
+    #[tracing::instrument]
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let mut filter = opts.filter()?;
+        if !unscoped {
+            filter = filter.not_deleted();
         }
-        wheres.push_str(r#"`deleted` = 0"#);
-        // 查询total
-        if !opts.pagination.count_disable {
-            let policy_result = sqlx::query(
-                format!(
-                    r#"SELECT COUNT(*) as count FROM `role`
-            WHERE {};"#,
-                    wheres,
-                )
-                .as_str(),
-            )
-            .fetch_one(&self.pool)
+        let mut builder =
+            QueryBuilder::new(r#"SELECT COUNT(*) as count FROM `role`"#);
+        filter.push(&mut builder);
+        builder
+            .build()
+            .fetch_one(&mut *self.conn.acquire().await?)
             .await
-            .map_err(errors::any)?;
-
-            output.total =
-                policy_result.try_get("count").map_err(errors::any)?;
-        }
-
-        // 查询列表
-        opts.pagination.convert(&mut wheres);
-
-        output.limit = opts.pagination.limit;
-        output.offset = opts.pagination.offset;
-
-        let rows = sqlx::query(
-            format!(
-                r#"SELECT `id`,`account_id`,`name`,`desc`,`created_at`,`updated_at`
-                FROM `role`
-                WHERE {};"#,
-                wheres,
-            )
-            .as_str(),
-        )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(errors::any)?;
-        for row in rows.iter() {
-            output.data.push(Self::T {
-                id: row
-                    .try_get::<u64, _>("id")
-                    .map_err(errors::any)?
-                    .to_string(),
-                account_id: row
-                    .try_get::<u64, _>("account_id")
-                    .map_err(errors::any)?
-                    .to_string(),
-                name: row.try_get("name").map_err(errors::any)?,
-                desc: row.try_get("desc").map_err(errors::any)?,
-                created_at: row.try_get("created_at").map_err(errors::any)?,
-                updated_at: row.try_get("updated_at").map_err(errors::any)?,
-            });
-        }
-
-        Ok(())
+            .map_err(errors::any)?
+            .try_get("count")
+            .map_err(errors::any)
     }
 
     #[tracing::instrument]
-    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
-        let mut wheres = String::new();
-        combine_param(&mut wheres, opts)?;
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.conn
+            .scoped(|conn| async move {
+                Self { conn }.write(input, Some(resource_version)).await
+            })
+            .await
+    }
 
-        if !unscoped {
-            wheres.push_str(" AND ");
-            wheres.push_str(r#"`deleted` = 0"#);
-        }
-        let result = sqlx::query(
-            format!(
-                r#"SELECT COUNT(*) as count FROM `role`
-            WHERE {} LIMIT 1;"#,
-                wheres,
-            )
-            .as_str(),
-        )
-        .fetch_one(&self.pool)
-        .await
-        .map_err(errors::any)?;
-        result.try_get("count").map_err(errors::any)
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "role", &input.id).await?;
+                let previous = stored(&store, input).await?;
+                store.write(input, resource_version).await?;
+                Ok(previous)
+            })
+            .await
     }
-}
 
-fn combine_param(wheres: &mut String, opts: &ListParams) -> Result<()> {
-    if let Some(v) = &opts.id {
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "role", &input.id).await?;
+                discarded(&store, input, resource_version).await
+            })
+            .await
+    }
 
-        wheres.push_str(
-            format!(
-                r#"`id` = {}"#,
-                v.parse::<u64>().map_err(|err| errors::bad_request(&err))?
-            )
-            .as_str(),
-        );
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.remove(input, Some(resource_version)).await
     }
 
-    if let Some(account_id) = &opts.account_id {
-        let account_id_u64: u64 = account_id
-            .parse()
-            .map_err(|err| errors::bad_request(&err))?;
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true).await
+    }
 
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
-        wheres
-            .push_str(format!(r#"`account_id` = {}"#, account_id_u64).as_str());
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        restore(&self.conn, "role", &input.id).await
     }
 
-    if let Some(user_id) = &opts.user_id {
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
-        let user_id_u64: u64 =
-            user_id.parse().map_err(|err| errors::bad_request(&err))?;
-        wheres.push_str(format!(r#"`id` IN (SELECT `role_id` FROM `role_bindings` WHERE `user_id` = {} AND `deleted` = 0)"#,
-            user_id_u64).as_str());
-    };
-    Ok(())
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        purge(&self.conn, "role", before).await
+    }
 }
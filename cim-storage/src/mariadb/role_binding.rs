@@ -1,22 +1,135 @@
 use async_trait::async_trait;
-use sqlx::{MySqlPool, Row};
+use chrono::NaiveDateTime;
+use sqlx::{MySql, QueryBuilder, Row};
 
 use cim_slo::{errors, Result};
 
+use super::{lock, purge, restore, soft_delete, write_query, write_row};
 use crate::{
-    convert::convert_param,
+    discarded,
     role_binding::{ListParams, RoleBinding},
-    Interface, List,
+    stored, Conn, Interface, List,
 };
 
 #[derive(Clone, Debug)]
 pub struct RoleBindingImpl {
-    pool: MySqlPool,
+    conn: Conn<MySql>,
 }
 
 impl RoleBindingImpl {
-    pub fn new(pool: MySqlPool) -> Self {
-        Self { pool }
+    pub fn new(pool: impl Into<Conn<MySql>>) -> Self {
+        Self { conn: pool.into() }
+    }
+
+    /// select lists the rows matching opts, the soft deleted ones when
+    /// deleted
+    async fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<RoleBinding>,
+        deleted: bool,
+    ) -> Result<()> {
+        let filter = opts.filter()?.deleted(deleted);
+        // 查询total
+        if !opts.pagination.count_disable {
+            let mut builder = QueryBuilder::new(
+                r#"SELECT COUNT(*) as count FROM `role_binding`"#,
+            );
+            filter.push(&mut builder);
+            output.total = builder
+                .build()
+                .fetch_one(&mut *self.conn.acquire().await?)
+                .await
+                .map_err(errors::any)?
+                .try_get("count")
+                .map_err(errors::any)?;
+        }
+
+        // 查询列表
+        output.limit = opts.pagination.limit;
+        output.offset = opts.pagination.offset;
+
+        let mut builder = QueryBuilder::new(
+            r#"SELECT `id`,`role_id`,`user_type`,`user_id`,
+                `not_before`,`expires_at`,
+                `resource_version`,`created_at`,`updated_at`
+                FROM `role_binding`"#,
+        );
+        opts.pagination
+            .seek(filter, ListParams::SORTABLE)?
+            .push(&mut builder);
+        opts.pagination.push(&mut builder, ListParams::SORTABLE)?;
+        let rows = builder
+            .build()
+            .fetch_all(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?;
+        for row in rows.iter() {
+            output.data.push(RoleBinding {
+                id: row
+                    .try_get::<u64, _>("id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                role_id: row
+                    .try_get::<u64, _>("role_id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                user_type: row
+                    .try_get::<u8, _>("user_type")
+                    .map_err(errors::any)?
+                    .into(),
+                user_id: row.try_get("user_id").map_err(errors::any)?,
+                resource_version: row
+                    .try_get("resource_version")
+                    .map_err(errors::any)?,
+                not_before: row.try_get("not_before").map_err(errors::any)?,
+                expires_at: row.try_get("expires_at").map_err(errors::any)?,
+                created_at: row.try_get("created_at").map_err(errors::any)?,
+                updated_at: row.try_get("updated_at").map_err(errors::any)?,
+            });
+        }
+        output.next_cursor = opts
+            .pagination
+            .next_cursor(&output.data, ListParams::SORTABLE)?;
+        Ok(())
+    }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it
+    async fn write(
+        &self,
+        input: &RoleBinding,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
+        let user_type: u8 = (&input.user_type).into();
+        let query = write_query(
+            "role_binding",
+            &[
+                "role_id",
+                "user_type",
+                "user_id",
+                "not_before",
+                "expires_at",
+            ],
+            resource_version.is_some(),
+        );
+        let query = sqlx::query(&query)
+            .bind(&input.id)
+            .bind(&input.role_id)
+            .bind(user_type)
+            .bind(&input.user_id)
+            .bind(input.not_before)
+            .bind(input.expires_at)
+            .bind(&input.id);
+        write_row(
+            &self.conn,
+            query,
+            "role_binding",
+            &input.id,
+            resource_version,
+        )
+        .await?;
+        Ok(())
     }
 }
 
@@ -27,38 +140,12 @@ impl Interface for RoleBindingImpl {
 
     #[tracing::instrument]
     async fn put(&self, input: &Self::T) -> Result<()> {
-        let user_type: u8 = (&input.user_type).into();
-        sqlx::query(
-            r#"REPLACE INTO `role_binding`
-            (`id`,`role_id`,`user_type`,`user_id`)
-            VALUES(?,?,?,?);"#,
-        )
-        .bind(&input.id)
-        .bind(&input.role_id)
-        .bind(user_type)
-        .bind(&input.user_id)
-        .execute(&self.pool)
-        .await
-        .map_err(errors::any)?;
-        Ok(())
+        self.write(input, None).await
     }
 
     #[tracing::instrument]
     async fn delete(&self, input: &Self::T) -> Result<()> {
-        let id = input
-            .id
-            .parse::<u64>()
-            .map_err(|err| errors::bad_request(&err))?;
-        sqlx::query(
-            r#"UPDATE `role_binding` SET `deleted` = `id`,`deleted_at`= now()
-            WHERE id = ? AND `deleted` = 0;"#,
-        )
-        .bind(id)
-        .execute(&self.pool)
-        .await
-        .map_err(errors::any)?;
-
-        Ok(())
+        soft_delete(&self.conn, "role_binding", &input.id, None).await
     }
 
     #[tracing::instrument]
@@ -68,12 +155,14 @@ impl Interface for RoleBindingImpl {
             .parse::<u64>()
             .map_err(|err| errors::bad_request(&err))?;
         let row = match sqlx::query(
-            r#"SELECT `id`,`role_id`,`user_type`,`user_id`,`created_at`,`updated_at`
+            r#"SELECT `id`,`role_id`,`user_type`,`user_id`,
+                `not_before`,`expires_at`,
+                `resource_version`,`created_at`,`updated_at`
                 FROM `role_binding`
                 WHERE id = ? AND `deleted` = 0;"#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_optional(&mut *self.conn.acquire().await?)
         .await
         {
             Ok(v) => match v {
@@ -96,6 +185,10 @@ impl Interface for RoleBindingImpl {
             .map_err(errors::any)?
             .into();
         output.user_id = row.try_get("user_id").map_err(errors::any)?;
+        output.resource_version =
+            row.try_get("resource_version").map_err(errors::any)?;
+        output.not_before = row.try_get("not_before").map_err(errors::any)?;
+        output.expires_at = row.try_get("expires_at").map_err(errors::any)?;
         output.created_at = row.try_get("created_at").map_err(errors::any)?;
         output.updated_at = row.try_get("updated_at").map_err(errors::any)?;
         Ok(())
@@ -107,131 +200,100 @@ impl Interface for RoleBindingImpl {
         opts: &Self::L,
         output: &mut List<Self::T>,
     ) -> Result<()> {
-        let mut wheres = String::new();
-        combine_param(&mut wheres, opts)?;
+        self.select(opts, output, false).await
+    }
 
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
+    #[tracing::instrument]
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let mut filter = opts.filter()?;
+        if !unscoped {
+            filter = filter.not_deleted();
         }
-        wheres.push_str(r#"`deleted` = 0"#);
-        // 查询total
-        if !opts.pagination.count_disable {
-            let policy_result = sqlx::query(
-                format!(
-                    r#"SELECT COUNT(*) as count FROM `role_binding`
-            WHERE {};"#,
-                    wheres,
-                )
-                .as_str(),
-            )
-            .fetch_one(&self.pool)
+        let mut builder = QueryBuilder::new(
+            r#"SELECT COUNT(*) as count FROM `role_binding`"#,
+        );
+        filter.push(&mut builder);
+        builder
+            .build()
+            .fetch_one(&mut *self.conn.acquire().await?)
             .await
-            .map_err(errors::any)?;
-
-            output.total =
-                policy_result.try_get("count").map_err(errors::any)?;
-        }
+            .map_err(errors::any)?
+            .try_get("count")
+            .map_err(errors::any)
+    }
 
-        // 查询列表
-        opts.pagination.convert(&mut wheres);
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.write(input, Some(resource_version)).await
+    }
 
-        output.limit = opts.pagination.limit;
-        output.offset = opts.pagination.offset;
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "role_binding", &input.id).await?;
+                let previous = stored(&store, input).await?;
+                store.write(input, resource_version).await?;
+                Ok(previous)
+            })
+            .await
+    }
 
-        let rows = sqlx::query(
-            format!(
-                r#"SELECT `id`,`role_id`,`user_type`,`user_id`,`created_at`,`updated_at`
-                FROM `role_binding`
-                WHERE {};"#,
-                wheres,
-            )
-            .as_str(),
-        )
-        .fetch_all(&self.pool)
-        .await
-        .map_err(errors::any)?;
-        for row in rows.iter() {
-            output.data.push(Self::T {
-                id: row
-                    .try_get::<u64, _>("id")
-                    .map_err(errors::any)?
-                    .to_string(),
-                role_id: row
-                    .try_get::<u64, _>("role_id")
-                    .map_err(errors::any)?
-                    .to_string(),
-                user_type: row
-                    .try_get::<u8, _>("user_type")
-                    .map_err(errors::any)?
-                    .into(),
-                user_id: row.try_get("user_id").map_err(errors::any)?,
-                created_at: row.try_get("created_at").map_err(errors::any)?,
-                updated_at: row.try_get("updated_at").map_err(errors::any)?,
-            });
-        }
-        Ok(())
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "role_binding", &input.id).await?;
+                discarded(&store, input, resource_version).await
+            })
+            .await
     }
 
     #[tracing::instrument]
-    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
-        let mut wheres = String::new();
-        combine_param(&mut wheres, opts)?;
-        if !unscoped {
-            wheres.push_str(" AND ");
-            wheres.push_str(r#"`deleted` = 0"#);
-        }
-        let result = sqlx::query(
-            format!(
-                r#"SELECT COUNT(*) as count FROM `role_binding`
-            WHERE {} LIMIT 1;"#,
-                wheres,
-            )
-            .as_str(),
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        soft_delete(
+            &self.conn,
+            "role_binding",
+            &input.id,
+            Some(resource_version),
         )
-        .fetch_one(&self.pool)
         .await
-        .map_err(errors::any)?;
-        result.try_get("count").map_err(errors::any)
     }
-}
 
-fn combine_param(wheres: &mut String, opts: &ListParams) -> Result<()> {
-    if let Some(v) = &opts.id {
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
-
-        wheres.push_str(
-            format!(
-                r#"`id` = {}"#,
-                v.parse::<u64>().map_err(|err| errors::bad_request(&err))?
-            )
-            .as_str(),
-        );
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true).await
     }
 
-    if let Some(role_id) = &opts.role_id {
-        let role_id_u64: u64 =
-            role_id.parse().map_err(|err| errors::bad_request(&err))?;
-
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
-        wheres.push_str(format!(r#"`role_id` = {}"#, role_id_u64).as_str());
-    }
-    if let Some(user_type) = &opts.user_type {
-        let user_type_u8: u8 = user_type.into();
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
-        wheres.push_str(format!(r#"`user_type` = {}"#, user_type_u8).as_str());
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        restore(&self.conn, "role_binding", &input.id).await
     }
-    if let Some(user_id) = &opts.user_id {
-        if !wheres.is_empty() {
-            wheres.push_str(" AND ");
-        }
-        wheres.push_str(r#"`user_id` = "#);
-        convert_param(wheres, user_id);
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        purge(&self.conn, "role_binding", before).await
     }
-    Ok(())
 }
@@ -0,0 +1,97 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use cim_slo::Result;
+
+use super::{matches, now, paginate, Database};
+use crate::{
+    audit::{Audit, AuditStore, ListParams},
+    List,
+};
+
+/// Audits is the append only audit table, ids keep growing like the ones
+/// of the changelog
+#[derive(Clone, Debug, Default)]
+pub(super) struct Audits {
+    // shared with the transactions begun on it until either side appends
+    records: Arc<Vec<Audit>>,
+    last: u64,
+    // index of the first record appended by a transaction
+    begin: Option<usize>,
+}
+
+impl Audits {
+    fn append(&mut self, input: Audit) {
+        self.last += 1;
+        Arc::make_mut(&mut self.records).push(Audit {
+            id: self.last.to_string(),
+            created_at: now(),
+            ..input
+        });
+    }
+
+    pub(super) fn begin(&self) -> Self {
+        Self {
+            records: Arc::clone(&self.records),
+            last: self.last,
+            begin: Some(self.records.len()),
+        }
+    }
+
+    /// merge appends the records of the transaction tx, they get their ids
+    /// on commit
+    pub(super) fn merge(&mut self, tx: &mut Self) {
+        if let Some(begin) = tx.begin.take() {
+            for record in tx.records[begin..].iter().cloned() {
+                self.append(record);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AuditImpl {
+    db: Database,
+}
+
+impl AuditImpl {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl AuditStore for AuditImpl {
+    #[tracing::instrument]
+    async fn append(&self, input: &Audit) -> Result<()> {
+        self.db.write().audit.append(input.clone());
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        opts: &ListParams,
+        output: &mut List<Audit>,
+    ) -> Result<()> {
+        let rows = self
+            .db
+            .read()
+            .audit
+            .records
+            .iter()
+            .filter(|v| filter(opts, v))
+            .cloned()
+            .collect();
+        paginate(rows, &opts.pagination, ListParams::SORTABLE, output)
+    }
+}
+
+fn filter(opts: &ListParams, v: &Audit) -> bool {
+    matches(&opts.actor, &v.actor)
+        && matches(&opts.kind, &v.kind)
+        && matches(&opts.entity_id, &v.entity_id)
+        && opts.since.is_none_or(|since| v.created_at >= since)
+        && opts.until.is_none_or(|until| v.created_at < until)
+}
@@ -0,0 +1,66 @@
+use async_trait::async_trait;
+
+use cim_slo::Result;
+
+use super::{now, Database};
+use crate::{authcode::AuthCode, Interface, List};
+
+#[derive(Clone, Debug)]
+pub struct AuthCodeImpl {
+    db: Database,
+}
+
+impl AuthCodeImpl {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl Interface for AuthCodeImpl {
+    type T = AuthCode;
+    type L = ();
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        let now = now();
+        self.db.write().auth_code.put(
+            &input.id,
+            AuthCode {
+                created_at: now,
+                updated_at: now,
+                ..input.clone()
+            },
+        );
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        self.db.write().auth_code.remove(&input.id);
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        *output = self.db.read().auth_code.get(&output.id)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        _opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        let tables = self.db.read();
+        output.data.extend(tables.auth_code.iter(false).cloned());
+        output.total = output.data.len() as i64;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, _opts: &Self::L, unscoped: bool) -> Result<i64> {
+        Ok(self.db.read().auth_code.count(unscoped, |_| true))
+    }
+}
@@ -0,0 +1,108 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use cim_slo::Result;
+
+use super::{now, Database};
+use crate::changelog::{Change, ChangeStore};
+
+/// Changelog is the changelog table, ids keep growing after a purge
+#[derive(Clone, Debug, Default)]
+pub(super) struct Changelog {
+    // shared with the transactions begun on it until either side appends
+    changes: Arc<Vec<Change>>,
+    last: u64,
+    // index of the first change appended by a transaction
+    begin: Option<usize>,
+}
+
+impl Changelog {
+    fn append(&mut self, kind: String, payload: String) {
+        self.last += 1;
+        Arc::make_mut(&mut self.changes).push(Change {
+            id: self.last,
+            kind,
+            payload,
+            created_at: now(),
+        });
+    }
+
+    pub(super) fn begin(&self) -> Self {
+        Self {
+            changes: Arc::clone(&self.changes),
+            last: self.last,
+            begin: Some(self.changes.len()),
+        }
+    }
+
+    /// merge appends the changes of the transaction tx, they get their
+    /// ids on commit like rows of an auto increment column
+    pub(super) fn merge(&mut self, tx: &mut Self) {
+        if let Some(begin) = tx.begin.take() {
+            for change in tx.changes[begin..].iter().cloned() {
+                self.append(change.kind, change.payload);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ChangelogImpl {
+    db: Database,
+}
+
+impl ChangelogImpl {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl ChangeStore for ChangelogImpl {
+    #[tracing::instrument]
+    async fn append(&self, kind: &str, payload: &str) -> Result<()> {
+        self.db
+            .write()
+            .changelog
+            .append(kind.to_owned(), payload.to_owned());
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn since(&self, id: u64, limit: i64) -> Result<Vec<Change>> {
+        Ok(self
+            .db
+            .read()
+            .changelog
+            .changes
+            .iter()
+            .filter(|v| v.id > id)
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    #[tracing::instrument]
+    async fn last(&self) -> Result<u64> {
+        let tables = self.db.read();
+        Ok(tables.changelog.changes.last().map(|v| v.id).unwrap_or(0))
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        let mut tables = self.db.write();
+        let changelog = &mut tables.changelog;
+        let purged = changelog
+            .changes
+            .iter()
+            .take_while(|v| v.created_at < before)
+            .count();
+        Arc::make_mut(&mut changelog.changes).drain(..purged);
+        if let Some(begin) = &mut changelog.begin {
+            *begin = begin.saturating_sub(purged);
+        }
+        Ok(purged as u64)
+    }
+}
@@ -0,0 +1,164 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use cim_slo::Result;
+
+use super::{now, Database};
+use crate::{client::Client, Interface, List};
+
+#[derive(Clone, Debug)]
+pub struct ClientImpl {
+    db: Database,
+}
+
+impl ClientImpl {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it, returning the live entity it replaced
+    fn write(
+        &self,
+        input: &Client,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Client>> {
+        let now = now();
+        let mut tables = self.db.write();
+        let stored =
+            tables
+                .client
+                .version(&input.id, resource_version, |v| v.resource_version)?;
+        let previous = tables.client.get(&input.id).ok();
+        tables.client.put(
+            &input.id,
+            Client {
+                resource_version: stored + 1,
+                created_at: previous
+                    .as_ref()
+                    .map(|v| v.created_at)
+                    .unwrap_or(now),
+                updated_at: now,
+                ..input.clone()
+            },
+        );
+        Ok(previous)
+    }
+
+    /// remove soft deletes input, when resource_version is given only
+    /// if the stored one is still at it, returning the live entity it
+    /// deleted
+    fn remove(
+        &self,
+        input: &Client,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Client>> {
+        self.db
+            .write()
+            .client
+            .delete_at(&input.id, resource_version, |v| &mut v.resource_version)
+    }
+
+    /// select lists the soft deleted rows when deleted, the live ones
+    /// otherwise
+    fn select(&self, output: &mut List<Client>, deleted: bool) -> Result<()> {
+        let tables = self.db.read();
+        output.data.extend(tables.client.select(deleted).cloned());
+        output.total = output.data.len() as i64;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Interface for ClientImpl {
+    type T = Client;
+    type L = ();
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        self.write(input, None).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        self.remove(input, None).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        *output = self.db.read().client.get(&output.id)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        _opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(output, false)
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, _opts: &Self::L, unscoped: bool) -> Result<i64> {
+        Ok(self.db.read().client.count(unscoped, |_| true))
+    }
+
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.write(input, Some(resource_version)).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.write(input, resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.remove(input, Some(resource_version)).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.remove(input, resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        _opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(output, true)
+    }
+
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        self.db
+            .write()
+            .client
+            .restore(&input.id, |v| &mut v.resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        Ok(self.db.write().client.purge(before))
+    }
+}
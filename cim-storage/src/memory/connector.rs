@@ -0,0 +1,179 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use cim_slo::Result;
+
+use super::{matches, paginate, Database};
+use crate::{
+    connector::{Connector, ListParams},
+    Interface, List,
+};
+
+#[derive(Clone, Debug)]
+pub struct ConnectorImpl {
+    db: Database,
+}
+
+impl ConnectorImpl {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it, returning the live entity it replaced
+    fn write(
+        &self,
+        input: &Connector,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Connector>> {
+        let mut tables = self.db.write();
+        let stored =
+            tables
+                .connector
+                .version(&input.id, resource_version, |v| v.resource_version)?;
+        let previous = tables.connector.get(&input.id).ok();
+        tables.connector.put(
+            &input.id,
+            Connector {
+                resource_version: stored + 1,
+                ..input.clone()
+            },
+        );
+        Ok(previous)
+    }
+
+    /// remove soft deletes input, when resource_version is given only
+    /// if the stored one is still at it, returning the live entity it
+    /// deleted
+    fn remove(
+        &self,
+        input: &Connector,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Connector>> {
+        self.db
+            .write()
+            .connector
+            .delete_at(&input.id, resource_version, |v| &mut v.resource_version)
+    }
+
+    /// select lists the soft deleted rows when deleted, the live ones
+    /// otherwise
+    fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<Connector>,
+        deleted: bool,
+    ) -> Result<()> {
+        let rows = self
+            .db
+            .read()
+            .connector
+            .select(deleted)
+            .filter(|v| filter(opts, v))
+            .cloned()
+            .collect();
+        paginate(rows, &opts.pagination, ListParams::SORTABLE, output)
+    }
+}
+
+#[async_trait]
+impl Interface for ConnectorImpl {
+    type T = Connector;
+    type L = ListParams;
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        self.write(input, None).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        self.remove(input, None).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        *output = self.db.read().connector.get(&output.id)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, false)
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        Ok(self
+            .db
+            .read()
+            .connector
+            .count(unscoped, |v| filter(opts, v)))
+    }
+
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.write(input, Some(resource_version)).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.write(input, resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.remove(input, Some(resource_version)).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.remove(input, resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true)
+    }
+
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        self.db
+            .write()
+            .connector
+            .restore(&input.id, |v| &mut v.resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        Ok(self.db.write().connector.purge(before))
+    }
+}
+
+fn filter(opts: &ListParams, v: &Connector) -> bool {
+    matches(&opts.connector_type, &v.connector_type)
+}
@@ -0,0 +1,43 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use cim_slo::Result;
+
+use super::Database;
+use crate::gc::GcStore;
+
+#[derive(Clone, Debug)]
+pub struct GcImpl {
+    db: Database,
+}
+
+impl GcImpl {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl GcStore for GcImpl {
+    #[tracing::instrument]
+    async fn purge_auth_requests(&self, now: i64) -> Result<u64> {
+        Ok(self.db.write().auth_request.remove_if(|v| v.expiry < now))
+    }
+
+    #[tracing::instrument]
+    async fn purge_auth_codes(&self, now: i64) -> Result<u64> {
+        Ok(self.db.write().auth_code.remove_if(|v| v.expiry < now))
+    }
+
+    #[tracing::instrument]
+    async fn purge_refresh_tokens(
+        &self,
+        created_before: Option<NaiveDateTime>,
+        used_before: Option<NaiveDateTime>,
+    ) -> Result<u64> {
+        Ok(self.db.write().refresh_token.remove_if(|v| {
+            created_before.is_some_and(|before| v.created_at < before)
+                || used_before.is_some_and(|before| v.last_used_at < before)
+        }))
+    }
+}
@@ -0,0 +1,292 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use cim_slo::{errors, Result};
+
+use super::{matches, now, paginate, Database, Tables};
+use crate::{
+    expiry::is_active,
+    group::{Group, ListParams},
+    policy_binding::BindingsType,
+    Interface, List,
+};
+
+#[derive(Clone, Debug)]
+pub struct GroupImpl {
+    db: Database,
+}
+
+impl GroupImpl {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// select lists the rows matching opts, the soft deleted ones when
+    /// deleted
+    fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<Group>,
+        deleted: bool,
+    ) -> Result<()> {
+        let tables = self.db.read();
+        let rows = tables
+            .group
+            .select(deleted)
+            .filter(|v| filter(&tables, opts, v))
+            .cloned()
+            .collect();
+        paginate(rows, &opts.pagination, ListParams::SORTABLE, output)
+    }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it, returning the live entity it replaced
+    fn write(
+        &self,
+        input: &Group,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Group>> {
+        let now = now();
+        let mut tables = self.db.write();
+        // the soft deleted groups are walked too, they may be restored
+        let mut parent_id = input.parent_id.clone();
+        while let Some(id) = parent_id {
+            if id == input.id {
+                return Err(errors::bad_request(&format!(
+                    "group {} can't be nested in group {}, it contains it",
+                    input.id,
+                    input.parent_id.as_deref().unwrap_or_default()
+                )));
+            }
+            parent_id = tables
+                .group
+                .iter(true)
+                .find(|v| v.id == id)
+                .and_then(|v| v.parent_id.clone());
+        }
+        let stored =
+            tables
+                .group
+                .version(&input.id, resource_version, |v| v.resource_version)?;
+        let previous = tables.group.get(&input.id).ok();
+        tables.group.put(
+            &input.id,
+            Group {
+                resource_version: stored + 1,
+                created_at: previous
+                    .as_ref()
+                    .map(|v| v.created_at)
+                    .unwrap_or(now),
+                updated_at: now,
+                ..input.clone()
+            },
+        );
+        Ok(previous)
+    }
+
+    /// remove soft deletes input, when resource_version is given only
+    /// if the stored one is still at it, returning the live entity it
+    /// deleted
+    fn remove(
+        &self,
+        input: &Group,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Group>> {
+        let mut tables = self.db.write();
+        if tables.group_user.count(false, |v| v.group_id == input.id) != 0 {
+            return Err(errors::forbidden(
+                &"can't delete group, because it is used by user".to_string(),
+            ));
+        }
+        if tables.policy_binding.count(false, |v| {
+            v.bindings_type == BindingsType::Group && v.bindings_id == input.id
+        }) != 0
+        {
+            return Err(errors::forbidden(
+                &"can't delete group, because it is attached by policy"
+                    .to_string(),
+            ));
+        }
+        if tables
+            .group
+            .count(false, |v| v.parent_id.as_ref() == Some(&input.id))
+            != 0
+        {
+            return Err(errors::forbidden(
+                &"can't delete group, because it has nested groups".to_string(),
+            ));
+        }
+        tables
+            .group
+            .delete_at(&input.id, resource_version, |v| &mut v.resource_version)
+    }
+}
+
+/// ancestors are groups and the groups they are nested in at any depth,
+/// soft deleted groups are not followed
+pub(super) fn ancestors(tables: &Tables, groups: Vec<String>) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut next = groups;
+    while let Some(id) = next.pop() {
+        if result.contains(&id) {
+            continue;
+        }
+        if let Ok(group) = tables.group.get(&id) {
+            next.extend(group.parent_id);
+        }
+        result.push(id);
+    }
+    result
+}
+
+/// descendants are group id, if live, and the live groups nested in it at
+/// any depth
+pub(super) fn descendants(tables: &Tables, id: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    if tables.group.get(id).is_err() {
+        return result;
+    }
+    let mut next = vec![id.to_owned()];
+    while let Some(id) = next.pop() {
+        if result.contains(&id) {
+            continue;
+        }
+        next.extend(
+            tables
+                .group
+                .iter(false)
+                .filter(|v| v.parent_id.as_ref() == Some(&id))
+                .map(|v| v.id.clone()),
+        );
+        result.push(id);
+    }
+    result
+}
+
+#[async_trait]
+impl Interface for GroupImpl {
+    type T = Group;
+    type L = ListParams;
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        self.write(input, None).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        self.remove(input, None).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        *output = self.db.read().group.get(&output.id)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, false)
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let tables = self.db.read();
+        Ok(tables.group.count(unscoped, |v| filter(&tables, opts, v)))
+    }
+
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.write(input, Some(resource_version)).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.write(input, resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.remove(input, Some(resource_version)).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.remove(input, resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true)
+    }
+
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        self.db
+            .write()
+            .group
+            .restore(&input.id, |v| &mut v.resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        Ok(self.db.write().group.purge(before))
+    }
+}
+
+fn filter(tables: &Tables, opts: &ListParams, v: &Group) -> bool {
+    let member = |user_id: &String| {
+        tables
+            .group_user
+            .iter(false)
+            .any(|gu| &gu.user_id == user_id && gu.group_id == v.id)
+    };
+    matches(&opts.id, &v.id)
+        && matches(&opts.account_id, &v.account_id)
+        && opts
+            .parent_id
+            .as_ref()
+            .is_none_or(|id| v.parent_id.as_ref() == Some(id))
+        && opts.user_id.as_ref().is_none_or(member)
+        && opts.effective_user_id.as_ref().is_none_or(|user_id| {
+            let now = now();
+            let groups = tables
+                .group_user
+                .iter(false)
+                .filter(|gu| {
+                    &gu.user_id == user_id
+                        && is_active(gu.not_before, gu.expires_at, now)
+                })
+                .map(|gu| gu.group_id.clone())
+                .collect();
+            ancestors(tables, groups).contains(&v.id)
+        })
+        && opts
+            .label_selector
+            .as_ref()
+            .is_none_or(|selector| selector.matches(&v.labels))
+}
@@ -0,0 +1,194 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use cim_slo::Result;
+
+use super::{matches, now, paginate, Database, Tables};
+use crate::{
+    group_user::{GroupUser, ListParams},
+    Interface, List,
+};
+
+#[derive(Clone, Debug)]
+pub struct GroupUserImpl {
+    db: Database,
+}
+
+impl GroupUserImpl {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// select lists the rows matching opts, the soft deleted ones when
+    /// deleted
+    fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<GroupUser>,
+        deleted: bool,
+    ) -> Result<()> {
+        let tables = self.db.read();
+        let rows = tables
+            .group_user
+            .select(deleted)
+            .filter(|v| filter(&tables, opts, v))
+            .cloned()
+            .collect();
+        paginate(rows, &opts.pagination, ListParams::SORTABLE, output)
+    }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it, returning the live entity it replaced
+    fn write(
+        &self,
+        input: &GroupUser,
+        resource_version: Option<u64>,
+    ) -> Result<Option<GroupUser>> {
+        let now = now();
+        let mut tables = self.db.write();
+        let stored =
+            tables
+                .group_user
+                .version(&input.id, resource_version, |v| v.resource_version)?;
+        let previous = tables.group_user.get(&input.id).ok();
+        tables.group_user.put(
+            &input.id,
+            GroupUser {
+                resource_version: stored + 1,
+                created_at: previous
+                    .as_ref()
+                    .map(|v| v.created_at)
+                    .unwrap_or(now),
+                updated_at: now,
+                ..input.clone()
+            },
+        );
+        Ok(previous)
+    }
+
+    /// remove soft deletes input, when resource_version is given only
+    /// if the stored one is still at it, returning the live entity it
+    /// deleted
+    fn remove(
+        &self,
+        input: &GroupUser,
+        resource_version: Option<u64>,
+    ) -> Result<Option<GroupUser>> {
+        self.db
+            .write()
+            .group_user
+            .delete_at(&input.id, resource_version, |v| &mut v.resource_version)
+    }
+}
+
+#[async_trait]
+impl Interface for GroupUserImpl {
+    type T = GroupUser;
+    type L = ListParams;
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        self.write(input, None).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        self.remove(input, None).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        *output = self.db.read().group_user.get(&output.id)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, false)
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let tables = self.db.read();
+        Ok(tables
+            .group_user
+            .count(unscoped, |v| filter(&tables, opts, v)))
+    }
+
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.write(input, Some(resource_version)).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.write(input, resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.remove(input, Some(resource_version)).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.remove(input, resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true)
+    }
+
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        self.db
+            .write()
+            .group_user
+            .restore(&input.id, |v| &mut v.resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        Ok(self.db.write().group_user.purge(before))
+    }
+}
+
+fn filter(tables: &Tables, opts: &ListParams, v: &GroupUser) -> bool {
+    matches(&opts.id, &v.id)
+        && opts.account_id.as_ref().is_none_or(|account_id| {
+            tables
+                .group
+                .find(&v.group_id)
+                .is_some_and(|row| &row.value.account_id == account_id)
+        })
+        && matches(&opts.group_id, &v.group_id)
+        && matches(&opts.user_id, &v.user_id)
+        && opts
+            .expires_before
+            .is_none_or(|before| v.expires_at.is_some_and(|at| at < before))
+}
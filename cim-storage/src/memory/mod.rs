@@ -0,0 +1,1477 @@
+mod audit;
+mod authcode;
+mod authrequest;
+mod changelog;
+mod client;
+mod connector;
+mod gc;
+mod group;
+mod group_user;
+mod key;
+mod offlinesession;
+mod policy;
+mod policy_binding;
+mod refresh_token;
+mod relation_tuple;
+mod role;
+mod role_binding;
+mod user;
+
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    sync::{
+        atomic::{self, AtomicU64},
+        Arc, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+};
+
+use chrono::NaiveDateTime;
+
+use cim_slo::{errors, Result};
+
+use audit::Audits;
+use changelog::Changelog;
+
+use crate::{
+    authcode::AuthCode,
+    authrequest::AuthRequest,
+    client::Client,
+    connector::Connector,
+    group::Group,
+    group_user::GroupUser,
+    key::Keys,
+    offlinesession::OfflineSession,
+    policy::Policy,
+    policy_binding::PolicyBinding,
+    query::{is_after, Keyed},
+    refresh_token::RefreshToken,
+    relation_tuple::RelationTuple,
+    role::Role,
+    role_binding::RoleBinding,
+    user::User,
+    List, Pagination,
+};
+
+pub use audit::AuditImpl;
+pub use authcode::AuthCodeImpl;
+pub use authrequest::AuthRequestImpl;
+pub use changelog::ChangelogImpl;
+pub use client::ClientImpl;
+pub use connector::ConnectorImpl;
+pub use gc::GcImpl;
+pub use group::GroupImpl;
+pub use group_user::GroupUserImpl;
+pub use key::KeysImpl;
+pub use offlinesession::OfflineSessionImpl;
+pub use policy::PolicyImpl;
+pub use policy_binding::PolicyBindingImpl;
+pub use refresh_token::RefreshTokenImpl;
+pub use relation_tuple::RelationTupleImpl;
+pub use role::RoleImpl;
+pub use role_binding::RoleBindingImpl;
+pub use user::UserImpl;
+
+/// Database keeps every table in process memory, clones share the same
+/// tables so it plays the role of the connection pool of sql backends
+#[derive(Clone, Debug, Default)]
+pub struct Database {
+    tables: Arc<RwLock<Tables>>,
+    // set when the tables are a transaction copy of the parent
+    parent: Option<Arc<RwLock<Tables>>>,
+}
+
+impl Database {
+    fn read(&self) -> RwLockReadGuard<'_, Tables> {
+        self.tables.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Tables> {
+        self.tables.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// begin starts a transaction on a copy of the tables, the rows it
+    /// writes are merged back on commit
+    pub(crate) fn begin(&self) -> Self {
+        Self {
+            tables: Arc::new(RwLock::new(self.read().begin())),
+            parent: Some(Arc::clone(&self.tables)),
+        }
+    }
+
+    /// commit merges the rows written by the transaction, it conflicts when
+    /// another one committed a change to a row this one wrote or got by id
+    /// since it began, like the serialization failures of the sql backends
+    pub(crate) fn commit(&self) -> Result<()> {
+        if let Some(parent) = &self.parent {
+            let mut parent =
+                parent.write().unwrap_or_else(PoisonError::into_inner);
+            let mut tx = self.write();
+            parent.validate(&tx)?;
+            parent.merge(&mut tx);
+        }
+        Ok(())
+    }
+}
+
+macro_rules! tables {
+    ($($name:ident: $ty:ty,)*) => {
+        #[derive(Debug, Default)]
+        struct Tables {
+            $($name: Table<$ty>,)*
+            changelog: Changelog,
+            audit: Audits,
+        }
+
+        impl Tables {
+            fn begin(&self) -> Self {
+                Self {
+                    $($name: self.$name.begin(),)*
+                    changelog: self.changelog.begin(),
+                    audit: self.audit.begin(),
+                }
+            }
+
+            fn validate(&self, tx: &Self) -> Result<()> {
+                $(self.$name.validate(&tx.$name)?;)*
+                Ok(())
+            }
+
+            fn merge(&mut self, tx: &mut Self) {
+                $(self.$name.merge(&mut tx.$name);)*
+                self.changelog.merge(&mut tx.changelog);
+                self.audit.merge(&mut tx.audit);
+            }
+        }
+    };
+}
+
+tables! {
+    user: User,
+    role: Role,
+    role_binding: RoleBinding,
+    group: Group,
+    group_user: GroupUser,
+    policy: Policy,
+    policy_binding: PolicyBinding,
+    relation_tuple: RelationTuple,
+    key: Keys,
+    auth_request: AuthRequest,
+    auth_code: AuthCode,
+    connector: Connector,
+    client: Client,
+    refresh_token: RefreshToken,
+    offline_session: OfflineSession,
+}
+
+/// Table maps the id to a row, deleted rows are kept like the `deleted`
+/// column of the sql backends so that unscoped counts still see them, until
+/// they are purged
+#[derive(Debug)]
+struct Table<T> {
+    // shared with the transactions begun on the table, the first write on
+    // either side copies them
+    rows: Arc<BTreeMap<String, Row<T>>>,
+    // set when the table is a transaction copy
+    snapshot: Option<Snapshot<T>>,
+}
+
+#[derive(Clone, Debug)]
+struct Row<T> {
+    value: T,
+    // when the row was soft deleted
+    deleted: Option<NaiveDateTime>,
+    // changes on every write, a transaction commits only if the rows it
+    // wrote or read still have the stamps they had when it began
+    stamp: u64,
+}
+
+/// Snapshot is what a transaction began on, with the ids it wrote and the
+/// ones it got
+#[derive(Debug)]
+struct Snapshot<T> {
+    rows: Arc<BTreeMap<String, Row<T>>>,
+    dirty: BTreeSet<String>,
+    // get only borrows the table
+    read: Mutex<BTreeSet<String>>,
+}
+
+impl<T> Default for Table<T> {
+    fn default() -> Self {
+        Self {
+            rows: Arc::default(),
+            snapshot: None,
+        }
+    }
+}
+
+fn stamp() -> u64 {
+    static STAMP: AtomicU64 = AtomicU64::new(0);
+    STAMP.fetch_add(1, atomic::Ordering::Relaxed) + 1
+}
+
+impl<T: Clone> Table<T> {
+    /// put replaces the row
+    fn put(&mut self, id: &str, value: T) {
+        self.touch(id);
+        Arc::make_mut(&mut self.rows).insert(
+            id.to_owned(),
+            Row {
+                value,
+                deleted: None,
+                stamp: stamp(),
+            },
+        );
+    }
+
+    fn get(&self, id: &str) -> Result<T> {
+        match self.find(id) {
+            Some(row) if row.deleted.is_none() => Ok(row.value.clone()),
+            _ => Err(errors::not_found("no rows")),
+        }
+    }
+
+    /// find is row id whether it is soft deleted or not, like the rows a
+    /// subquery of the sql backends reads
+    fn find(&self, id: &str) -> Option<&Row<T>> {
+        if let Some(snapshot) = &self.snapshot {
+            snapshot
+                .read
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(id.to_owned());
+        }
+        self.rows.get(id)
+    }
+
+    /// version is the stored version of row id, which version reads. It
+    /// conflicts when the row is soft deleted, only restore brings it back,
+    /// and with resource_version unless the row is still at it, like the
+    /// writes of the sql backends
+    fn version<F: Fn(&T) -> u64>(
+        &self,
+        id: &str,
+        resource_version: Option<u64>,
+        version: F,
+    ) -> Result<u64> {
+        let row = self.rows.get(id);
+        if resource_version.is_none()
+            && row.is_some_and(|v| v.deleted.is_some())
+        {
+            return Err(errors::conflict(&format!(
+                "{id} is deleted, it can only be restored"
+            )));
+        }
+        if let Some(resource_version) = resource_version {
+            match row {
+                Some(row)
+                    if row.deleted.is_none()
+                        && version(&row.value) == resource_version => {}
+                _ => {
+                    return Err(errors::conflict(&format!(
+                        "{id} is not at resource version {resource_version}"
+                    )))
+                }
+            }
+        }
+        Ok(row.map(|row| version(&row.value)).unwrap_or_default())
+    }
+
+    /// delete_at soft deletes row id, with resource_version only if it is
+    /// live and still at it, bumping the version version points into like
+    /// the sql backends do. It returns the live value it deleted
+    fn delete_at<F: Fn(&mut T) -> &mut u64>(
+        &mut self,
+        id: &str,
+        resource_version: Option<u64>,
+        version: F,
+    ) -> Result<Option<T>> {
+        let previous = self.get(id).ok();
+        if let Some(resource_version) = resource_version {
+            let row = self
+                .row_mut(id)
+                .filter(|row| row.deleted.is_none())
+                .map(|row| version(&mut row.value))
+                .filter(|v| **v == resource_version);
+            match row {
+                Some(v) => *v += 1,
+                None => {
+                    return Err(errors::conflict(&format!(
+                        "{id} is not at resource version {resource_version}"
+                    )))
+                }
+            }
+        } else if let Some(row) =
+            self.row_mut(id).filter(|row| row.deleted.is_none())
+        {
+            *version(&mut row.value) += 1;
+        }
+        self.delete(id);
+        Ok(previous)
+    }
+
+    fn delete(&mut self, id: &str) {
+        if let Some(row) = self.row_mut(id) {
+            row.deleted.get_or_insert_with(now);
+        }
+    }
+
+    /// restore undeletes row id and bumps the version version points into
+    fn restore<F: Fn(&mut T) -> &mut u64>(
+        &mut self,
+        id: &str,
+        version: F,
+    ) -> Result<()> {
+        if self.rows.get(id).is_none_or(|row| row.deleted.is_none()) {
+            return Err(errors::not_found(&format!("{id} is not deleted")));
+        }
+        if let Some(row) = self.row_mut(id) {
+            row.deleted = None;
+            *version(&mut row.value) += 1;
+        }
+        Ok(())
+    }
+
+    /// purge removes the rows soft deleted before, returning how many
+    fn purge(&mut self, before: NaiveDateTime) -> u64 {
+        let ids: Vec<_> = self
+            .rows
+            .iter()
+            .filter(|(_, row)| row.deleted.is_some_and(|v| v < before))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in ids.iter() {
+            self.remove(id);
+        }
+        ids.len() as u64
+    }
+
+    /// remove_if removes the rows, soft deleted or not, which filter
+    /// matches, returning how many
+    fn remove_if<F: Fn(&T) -> bool>(&mut self, filter: F) -> u64 {
+        let ids: Vec<_> = self
+            .rows
+            .iter()
+            .filter(|(_, row)| filter(&row.value))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in ids.iter() {
+            self.remove(id);
+        }
+        ids.len() as u64
+    }
+
+    fn remove(&mut self, id: &str) {
+        self.touch(id);
+        Arc::make_mut(&mut self.rows).remove(id);
+    }
+
+    /// row_mut is row id for a write, it gets a new stamp
+    fn row_mut(&mut self, id: &str) -> Option<&mut Row<T>> {
+        if !self.rows.contains_key(id) {
+            return None;
+        }
+        self.touch(id);
+        let row = Arc::make_mut(&mut self.rows).get_mut(id)?;
+        row.stamp = stamp();
+        Some(row)
+    }
+
+    fn touch(&mut self, id: &str) {
+        if let Some(snapshot) = &mut self.snapshot {
+            snapshot.dirty.insert(id.to_owned());
+        }
+    }
+
+    fn begin(&self) -> Self {
+        Self {
+            rows: Arc::clone(&self.rows),
+            snapshot: Some(Snapshot {
+                rows: Arc::clone(&self.rows),
+                dirty: BTreeSet::new(),
+                read: Mutex::default(),
+            }),
+        }
+    }
+
+    /// validate conflicts when a row the transaction tx wrote or got
+    /// changed since it began
+    fn validate(&self, tx: &Self) -> Result<()> {
+        let Some(snapshot) = &tx.snapshot else {
+            return Ok(());
+        };
+        // untouched since the transaction began
+        if Arc::ptr_eq(&self.rows, &snapshot.rows) {
+            return Ok(());
+        }
+        let read = snapshot.read.lock().unwrap_or_else(PoisonError::into_inner);
+        for id in snapshot.dirty.iter().chain(read.iter()) {
+            let stamp = |rows: &BTreeMap<String, Row<T>>| {
+                rows.get(id).map(|row| row.stamp)
+            };
+            if stamp(&self.rows) != stamp(&snapshot.rows) {
+                return Err(errors::conflict(&format!(
+                    "{id} was changed by a concurrent transaction"
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// merge copies the rows written by the transaction tx
+    fn merge(&mut self, tx: &mut Self) {
+        let Some(snapshot) = tx.snapshot.take() else {
+            return;
+        };
+        for id in snapshot.dirty {
+            self.touch(&id);
+            let rows = Arc::make_mut(&mut self.rows);
+            match tx.rows.get(&id) {
+                Some(row) => rows.insert(id, row.clone()),
+                None => rows.remove(&id),
+            };
+        }
+    }
+
+    fn iter(&self, unscoped: bool) -> impl Iterator<Item = &T> {
+        self.rows
+            .values()
+            .filter(move |row| unscoped || row.deleted.is_none())
+            .map(|row| &row.value)
+    }
+
+    /// select iterates the soft deleted rows when deleted, the others
+    /// otherwise
+    fn select(&self, deleted: bool) -> impl Iterator<Item = &T> {
+        self.rows
+            .values()
+            .filter(move |row| row.deleted.is_some() == deleted)
+            .map(|row| &row.value)
+    }
+
+    fn count<F: Fn(&T) -> bool>(&self, unscoped: bool, filter: F) -> i64 {
+        self.iter(unscoped).filter(|v| filter(v)).count() as i64
+    }
+}
+
+fn now() -> NaiveDateTime {
+    chrono::Utc::now().naive_utc()
+}
+
+/// paginate sorts the rows and fills the requested page of them into
+/// output, the same way `Pagination::push` does in sql
+fn paginate<T: Keyed>(
+    mut rows: Vec<T>,
+    pagination: &Pagination,
+    sortable: &[&'static str],
+    output: &mut List<T>,
+) -> Result<()> {
+    if !pagination.count_disable {
+        output.total = rows.len() as i64;
+    }
+    output.limit = pagination.limit;
+    output.offset = pagination.offset;
+
+    let keys = pagination.keys(sortable)?;
+    rows.sort_by(|a, b| {
+        for (column, desc) in keys.iter() {
+            let ordering = a
+                .key(column)
+                .partial_cmp(&b.key(column))
+                .unwrap_or(Ordering::Equal);
+            if ordering != Ordering::Equal {
+                return if *desc { ordering.reverse() } else { ordering };
+            }
+        }
+        Ordering::Equal
+    });
+    let offset = match pagination.after(sortable)? {
+        Some(after) => {
+            rows.retain(|row| is_after(&after, row));
+            0
+        }
+        None => pagination.offset,
+    };
+    let rows = rows.into_iter().skip(offset as usize);
+    if pagination.limit > 0 {
+        output.data.extend(rows.take(pagination.limit as usize));
+    } else {
+        output.data.extend(rows);
+    }
+    output.next_cursor = pagination.next_cursor(&output.data, sortable)?;
+    Ok(())
+}
+
+/// matches reports whether an optional filter accepts the value
+fn matches(filter: &Option<String>, value: &str) -> bool {
+    filter.as_ref().is_none_or(|v| v == value)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Timelike, Utc};
+    use cim_pim::{rebac::TupleReader, PrincipalType, Request};
+
+    use super::*;
+    use crate::{
+        connection_manager,
+        gc::GcStore,
+        group::{self, Group},
+        group_user::{self, GroupUser},
+        label::Labels,
+        policy::StatementStore,
+        policy_binding::{self, BindingsType},
+        role_binding::{RoleBinding, UserType},
+        user, Interface, Pool,
+    };
+
+    async fn database() -> Database {
+        match connection_manager("memory://", 1, 1, true).await.unwrap() {
+            Pool::Memory(db) => db,
+            _ => unreachable!(),
+        }
+    }
+
+    fn params(pagination: Pagination) -> user::ListParams {
+        user::ListParams {
+            id: None,
+            account_id: Some("1".to_owned()),
+            group_id: None,
+            effective_group_id: None,
+            label_selector: None,
+            pagination,
+        }
+    }
+
+    #[tokio::test]
+    async fn user_crud() {
+        let store = UserImpl::new(database().await);
+        for id in ["1", "2", "10"] {
+            store
+                .put(&User {
+                    id: id.to_owned(),
+                    account_id: "1".to_owned(),
+                    password: Some("Pa$$w0rd".to_owned()),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        let mut output = User {
+            id: "1".to_owned(),
+            ..Default::default()
+        };
+        store.get(&mut output).await.unwrap();
+        assert!(output.secret.is_some());
+        assert_ne!(output.password.as_deref(), Some("Pa$$w0rd"));
+
+        let mut list = List::default();
+        store
+            .list(
+                &params(Pagination {
+                    limit: 2,
+                    offset: 1,
+                    order_by: Some("id desc".to_owned()),
+                    cursor: None,
+                    count_disable: false,
+                }),
+                &mut list,
+            )
+            .await
+            .unwrap();
+        assert_eq!(list.total, 3);
+        let ids: Vec<&str> = list.data.iter().map(|v| v.id.as_str()).collect();
+        assert_eq!(ids, vec!["2", "1"]);
+        assert!(list.data[0].password.is_none());
+
+        store.delete(&output).await.unwrap();
+        assert!(store.get(&mut output).await.is_err());
+        let opts = params(Pagination::default());
+        assert_eq!(store.count(&opts, false).await.unwrap(), 2);
+        assert_eq!(store.count(&opts, true).await.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn cursor() {
+        let store = UserImpl::new(database().await);
+        for id in 1..=5 {
+            store
+                .put(&User {
+                    id: id.to_string(),
+                    account_id: "1".to_owned(),
+                    password: Some("Pa$$w0rd".to_owned()),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        let mut opts = params(Pagination {
+            limit: 2,
+            order_by: Some("created_at desc".to_owned()),
+            ..Default::default()
+        });
+        let mut ids = Vec::new();
+        loop {
+            let mut list = List::default();
+            store.list(&opts, &mut list).await.unwrap();
+            assert_eq!(list.total, 5);
+            ids.extend(list.data.into_iter().map(|v| v.id));
+            match list.next_cursor {
+                Some(cursor) => opts.pagination.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(ids, vec!["5", "4", "3", "2", "1"]);
+
+        opts.pagination.order_by = Some("id".to_owned());
+        assert!(store.list(&opts, &mut List::default()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn get_statement() {
+        let db = database().await;
+        GroupImpl::new(db.clone())
+            .put(&Group {
+                id: "10".to_owned(),
+                account_id: "1".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let group_user = GroupUserImpl::new(db.clone());
+        group_user
+            .put(&GroupUser {
+                id: "20".to_owned(),
+                group_id: "10".to_owned(),
+                user_id: "2".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        RoleImpl::new(db.clone())
+            .put(&Role {
+                id: "50".to_owned(),
+                account_id: "1".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        RoleBindingImpl::new(db.clone())
+            .put(&RoleBinding {
+                id: "60".to_owned(),
+                role_id: "50".to_owned(),
+                user_id: "2".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let policy = PolicyImpl::new(db.clone());
+        for id in ["30", "31", "32", "33"] {
+            policy
+                .put(&Policy {
+                    id: id.to_owned(),
+                    statement: serde_json::from_value(serde_json::json!([{
+                        "effect": "Allow",
+                        "subjects": ["2"],
+                        "actions": ["get"],
+                        "resources": [format!("crn:iam:policy:{}", id)],
+                    }]))
+                    .unwrap(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        let policy_binding = PolicyBindingImpl::new(db.clone());
+        for (id, policy_id, bindings_type, bindings_id) in [
+            ("40", "30", BindingsType::Group, "10"),
+            ("41", "31", BindingsType::Role, "50"),
+            ("42", "32", BindingsType::User, "2"),
+            ("43", "33", BindingsType::User, "3"),
+        ] {
+            policy_binding
+                .put(&PolicyBinding {
+                    id: id.to_owned(),
+                    policy_id: policy_id.to_owned(),
+                    bindings_type,
+                    bindings_id: bindings_id.to_owned(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        // the bindings of the account are the ones of its groups and roles,
+        // the users 2 and 3 are of none
+        let bound = |account_id: &str| policy_binding::ListParams {
+            id: None,
+            account_id: Some(account_id.to_owned()),
+            policy_id: None,
+            bindings_type: None,
+            bindings_id: None,
+            expires_before: None,
+            pagination: Pagination::default(),
+        };
+        assert_eq!(policy_binding.count(&bound("1"), false).await.unwrap(), 2);
+        assert_eq!(policy_binding.count(&bound("2"), false).await.unwrap(), 0);
+        let members = group_user::ListParams {
+            id: None,
+            account_id: Some("1".to_owned()),
+            group_id: None,
+            user_id: None,
+            expires_before: None,
+            pagination: Pagination::default(),
+        };
+        assert_eq!(group_user.count(&members, false).await.unwrap(), 1);
+
+        let req = Request {
+            resource: "crn:iam:policy:30".to_owned(),
+            action: "get".to_owned(),
+            subject: "2".to_owned(),
+            principal_type: PrincipalType::User,
+            context: Default::default(),
+        };
+        let statements = policy.get_statement(&req).await.unwrap();
+        let resources: Vec<&str> =
+            statements.iter().map(|v| v.resources[0].as_str()).collect();
+        assert_eq!(
+            resources,
+            vec![
+                "crn:iam:policy:30",
+                "crn:iam:policy:31",
+                "crn:iam:policy:32"
+            ]
+        );
+
+        // bindings keep the group from being deleted
+        assert!(GroupImpl::new(db.clone())
+            .delete(&Group {
+                id: "10".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .is_err());
+        group_user
+            .delete(&GroupUser {
+                id: "20".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let opts = group_user::ListParams {
+            id: None,
+            account_id: None,
+            group_id: Some("10".to_owned()),
+            user_id: None,
+            expires_before: None,
+            pagination: Pagination::default(),
+        };
+        assert_eq!(group_user.count(&opts, false).await.unwrap(), 0);
+        assert_eq!(policy.get_statement(&req).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn resource_version() {
+        let store = GroupImpl::new(database().await);
+        let mut group = Group {
+            id: "1".to_owned(),
+            account_id: "1".to_owned(),
+            ..Default::default()
+        };
+        store.put(&group).await.unwrap();
+        store.get(&mut group).await.unwrap();
+        assert_eq!(group.resource_version, 1);
+
+        group.name = "first".to_owned();
+        store.put_if(&group, 1).await.unwrap();
+        group.name = "second".to_owned();
+        let err = store.put_if(&group, 1).await.unwrap_err();
+        assert!(err.to_string().starts_with("Conflict."));
+        store.get(&mut group).await.unwrap();
+        assert_eq!(group.resource_version, 2);
+        assert_eq!(group.name, "first");
+
+        // a put bumps the stored version, whatever version it is given
+        store
+            .put(&Group {
+                resource_version: 0,
+                ..group.clone()
+            })
+            .await
+            .unwrap();
+        store.get(&mut group).await.unwrap();
+        assert_eq!(group.resource_version, 3);
+
+        assert!(store.delete_if(&group, 2).await.is_err());
+        store.delete_if(&group, 3).await.unwrap();
+        assert!(store.get(&mut group).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn transaction() {
+        let db = database().await;
+        let store = GroupImpl::new(db.clone());
+        let group = |id: &str, name: &str| Group {
+            id: id.to_owned(),
+            account_id: "1".to_owned(),
+            name: name.to_owned(),
+            ..Default::default()
+        };
+        store.put(&group("1", "first")).await.unwrap();
+
+        // the first of two transactions writing a row wins
+        let (a, b, c) = (db.begin(), db.begin(), db.begin());
+        GroupImpl::new(a.clone())
+            .put(&group("1", "a"))
+            .await
+            .unwrap();
+        GroupImpl::new(b.clone())
+            .put(&group("1", "b"))
+            .await
+            .unwrap();
+        GroupImpl::new(c.clone())
+            .put(&group("2", "c"))
+            .await
+            .unwrap();
+        a.commit().unwrap();
+        let err = b.commit().unwrap_err();
+        assert!(err.to_string().starts_with("Conflict."));
+        c.commit().unwrap();
+
+        let mut output = group("1", "");
+        store.get(&mut output).await.unwrap();
+        assert_eq!(output.name, "a");
+        assert_eq!(output.resource_version, 2);
+        store.get(&mut group("2", "")).await.unwrap();
+
+        // so does a row got by id
+        let tx = db.begin();
+        GroupImpl::new(tx.clone()).get(&mut output).await.unwrap();
+        GroupImpl::new(tx.clone())
+            .put(&group("3", "tx"))
+            .await
+            .unwrap();
+        store.put(&group("1", "outside")).await.unwrap();
+        assert!(tx.commit().is_err());
+        output = group("3", "");
+        assert!(store.get(&mut output).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn relation_tuple() {
+        let store = RelationTupleImpl::new(database().await);
+        store
+            .put(&RelationTuple {
+                id: "1".to_owned(),
+                account_id: "1".to_owned(),
+                object: "document:readme".to_owned(),
+                relation: "viewer".to_owned(),
+                subject: "group:eng#member".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let subjects = store.read("document:readme", "viewer").await.unwrap();
+        assert_eq!(subjects.len(), 1);
+        assert_eq!(subjects[0].to_string(), "group:eng#member");
+    }
+
+    #[tokio::test]
+    async fn soft_delete() {
+        let store = GroupImpl::new(database().await);
+        let mut group = Group {
+            id: "1".to_owned(),
+            account_id: "1".to_owned(),
+            ..Default::default()
+        };
+        store.put(&group).await.unwrap();
+        store.delete(&group).await.unwrap();
+        let opts = group::ListParams {
+            id: None,
+            account_id: Some("1".to_owned()),
+            parent_id: None,
+            user_id: None,
+            effective_user_id: None,
+            label_selector: None,
+            pagination: Pagination::default(),
+        };
+        let mut output = List::default();
+        store.list(&opts, &mut output).await.unwrap();
+        assert!(output.data.is_empty());
+        store.list_deleted(&opts, &mut output).await.unwrap();
+        assert_eq!(output.data.len(), 1);
+
+        store.restore(&group).await.unwrap();
+        store.get(&mut group).await.unwrap();
+        assert_eq!(group.resource_version, 3);
+        assert!(store.restore(&group).await.is_err());
+
+        store.delete(&group).await.unwrap();
+        let before =
+            chrono::Utc::now().naive_utc() - chrono::Duration::hours(1);
+        assert_eq!(store.purge(before).await.unwrap(), 0);
+        let before =
+            chrono::Utc::now().naive_utc() + chrono::Duration::hours(1);
+        assert!(store.purge(before).await.unwrap() == 1);
+        assert_eq!(store.count(&opts, true).await.unwrap(), 0);
+        assert!(store.restore(&group).await.is_err());
+
+        let db = database().await;
+        let clients = ClientImpl::new(db.clone());
+        let mut client = Client {
+            id: "1".to_owned(),
+            ..Default::default()
+        };
+        clients.put(&client).await.unwrap();
+        clients.delete(&client).await.unwrap();
+        let mut output = List::default();
+        clients.list_deleted(&(), &mut output).await.unwrap();
+        assert_eq!(output.data.len(), 1);
+        clients.restore(&client).await.unwrap();
+        clients.get(&mut client).await.unwrap();
+        assert_eq!(client.resource_version, 3);
+
+        let keys = KeysImpl::new(db);
+        let key = Keys {
+            id: "1".to_owned(),
+            ..Default::default()
+        };
+        keys.put(&key).await.unwrap();
+        keys.delete(&key).await.unwrap();
+        keys.restore(&key).await.unwrap();
+        assert!(keys.restore(&key).await.is_err());
+        keys.delete(&key).await.unwrap();
+        assert_eq!(keys.purge(before).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn labels() {
+        let store = GroupImpl::new(database().await);
+        for (id, env, tier) in [
+            ("11", "prod", "web"),
+            ("12", "prod", "api"),
+            ("13", "test", "web"),
+        ] {
+            store
+                .put(&Group {
+                    id: id.to_owned(),
+                    account_id: "1".to_owned(),
+                    labels: Labels::from([
+                        ("env".to_owned(), env.to_owned()),
+                        ("tier".to_owned(), tier.to_owned()),
+                    ]),
+                    annotations: Labels::from([(
+                        "note".to_owned(),
+                        format!("group {id}"),
+                    )]),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        let mut group = Group {
+            id: "13".to_owned(),
+            ..Default::default()
+        };
+        store.get(&mut group).await.unwrap();
+        assert_eq!(group.labels["env"], "test");
+        assert_eq!(group.annotations["note"], "group 13");
+        group.labels.remove("env");
+        store.put(&group).await.unwrap();
+
+        for (selector, ids) in [
+            ("env=prod", vec!["11", "12"]),
+            ("env=prod,tier!=web", vec!["12"]),
+            ("tier in (web,api),env notin (prod)", vec!["13"]),
+            ("!env", vec!["13"]),
+            ("env", vec!["11", "12"]),
+            ("owner", vec![]),
+        ] {
+            let mut output = List::default();
+            store
+                .list(
+                    &group::ListParams {
+                        id: None,
+                        account_id: Some("1".to_owned()),
+                        parent_id: None,
+                        user_id: None,
+                        effective_user_id: None,
+                        label_selector: Some(selector.parse().unwrap()),
+                        pagination: Pagination::default(),
+                    },
+                    &mut output,
+                )
+                .await
+                .unwrap();
+            let selected: Vec<_> =
+                output.data.iter().map(|v| v.id.as_str()).collect();
+            assert_eq!(selected, ids, "{}", selector);
+        }
+
+        store.delete(&group).await.unwrap();
+        let before =
+            chrono::Utc::now().naive_utc() + chrono::Duration::hours(1);
+        assert_eq!(store.purge(before).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn nested_groups() {
+        let db = database().await;
+        let group = GroupImpl::new(db.clone());
+        for (id, parent_id) in
+            [("11", None), ("12", Some("11")), ("13", Some("12"))]
+        {
+            group
+                .put(&Group {
+                    id: id.to_owned(),
+                    account_id: "1".to_owned(),
+                    parent_id: parent_id.map(str::to_owned),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        UserImpl::new(db.clone())
+            .put(&User {
+                id: "3".to_owned(),
+                account_id: "1".to_owned(),
+                password: Some("Pa$$w0rd".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        GroupUserImpl::new(db.clone())
+            .put(&GroupUser {
+                id: "20".to_owned(),
+                group_id: "13".to_owned(),
+                user_id: "3".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let policy = PolicyImpl::new(db.clone());
+        policy
+            .put(&Policy {
+                id: "30".to_owned(),
+                version: "v1.0.0".to_owned(),
+                statement: serde_json::from_value(serde_json::json!([{
+                    "effect": "Allow",
+                    "subjects": ["3"],
+                    "actions": ["get"],
+                    "resources": ["crn:iam:group:11"],
+                }]))
+                .unwrap(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        PolicyBindingImpl::new(db.clone())
+            .put(&PolicyBinding {
+                id: "40".to_owned(),
+                policy_id: "30".to_owned(),
+                bindings_type: BindingsType::Group,
+                bindings_id: "11".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        // the member of the nested group gets the policy of its ancestors
+        let statements = policy
+            .get_statement(&Request {
+                resource: "crn:iam:group:11".to_owned(),
+                action: "get".to_owned(),
+                subject: "3".to_owned(),
+                principal_type: PrincipalType::User,
+                context: Default::default(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(statements.len(), 1);
+        let mut bindings =
+            policy.get_bindings(PrincipalType::User, "3").await.unwrap();
+        bindings.groups.sort();
+        assert_eq!(bindings.groups, vec!["11", "12", "13"]);
+        assert_eq!(bindings.policies, vec!["30"]);
+
+        // a membership not in effect yet doesn't make a member
+        group
+            .put(&Group {
+                id: "14".to_owned(),
+                account_id: "1".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        GroupUserImpl::new(db.clone())
+            .put(&GroupUser {
+                id: "21".to_owned(),
+                group_id: "14".to_owned(),
+                user_id: "3".to_owned(),
+                not_before: Some(Utc::now().naive_utc() + Duration::hours(1)),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let mut users = List::default();
+        UserImpl::new(db.clone())
+            .list(
+                &user::ListParams {
+                    id: None,
+                    account_id: None,
+                    group_id: None,
+                    effective_group_id: Some("11".to_owned()),
+                    label_selector: None,
+                    pagination: Pagination::default(),
+                },
+                &mut users,
+            )
+            .await
+            .unwrap();
+        assert_eq!(users.data.len(), 1);
+        for (parent_id, user_id, effective_user_id, ids) in [
+            (Some("11"), None, None, vec!["12"]),
+            (None, Some("3"), None, vec!["13", "14"]),
+            (None, None, Some("3"), vec!["11", "12", "13"]),
+        ] {
+            let mut output = List::default();
+            group
+                .list(
+                    &group::ListParams {
+                        id: None,
+                        account_id: Some("1".to_owned()),
+                        parent_id: parent_id.map(str::to_owned),
+                        user_id: user_id.map(str::to_owned),
+                        effective_user_id: effective_user_id.map(str::to_owned),
+                        label_selector: None,
+                        pagination: Pagination::default(),
+                    },
+                    &mut output,
+                )
+                .await
+                .unwrap();
+            let mut listed: Vec<_> =
+                output.data.iter().map(|v| v.id.as_str()).collect();
+            listed.sort();
+            assert_eq!(listed, ids);
+        }
+
+        let mut users = List::default();
+        UserImpl::new(db.clone())
+            .list(
+                &user::ListParams {
+                    id: None,
+                    account_id: None,
+                    group_id: None,
+                    effective_group_id: Some("14".to_owned()),
+                    label_selector: None,
+                    pagination: Pagination::default(),
+                },
+                &mut users,
+            )
+            .await
+            .unwrap();
+        assert!(users.data.is_empty());
+
+        let mut root = Group {
+            id: "11".to_owned(),
+            ..Default::default()
+        };
+        group.get(&mut root).await.unwrap();
+        root.parent_id = Some("13".to_owned());
+        assert!(group.put(&root).await.is_err());
+        let middle = Group {
+            id: "12".to_owned(),
+            ..Default::default()
+        };
+        assert!(group.delete(&middle).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn principals() {
+        let db = database().await;
+        RoleImpl::new(db.clone())
+            .put(&Role {
+                id: "61".to_owned(),
+                account_id: "1".to_owned(),
+                name: "deployer-61".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let policy = PolicyImpl::new(db.clone());
+        policy
+            .put(&Policy {
+                id: "62".to_owned(),
+                version: "v1.0.0".to_owned(),
+                statement: serde_json::from_value(serde_json::json!([{
+                    "effect": "Allow",
+                    "subjects": ["<.*>"],
+                    "actions": ["update"],
+                    "resources": ["crn:iam:deployment:62"],
+                }]))
+                .unwrap(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        PolicyBindingImpl::new(db.clone())
+            .put(&PolicyBinding {
+                id: "63".to_owned(),
+                policy_id: "62".to_owned(),
+                bindings_type: BindingsType::Role,
+                bindings_id: "61".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let role_binding = RoleBindingImpl::new(db.clone());
+        for (id, user_type, user_id) in [
+            ("64", UserType::Application, "66"),
+            ("65", UserType::FederatedUser, "github|66"),
+        ] {
+            role_binding
+                .put(&RoleBinding {
+                    id: id.to_owned(),
+                    role_id: "61".to_owned(),
+                    user_type,
+                    user_id: user_id.to_owned(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        // applications and federated users are bound policies directly by
+        // their subject
+        policy
+            .put(&Policy {
+                id: "67".to_owned(),
+                version: "v1.0.0".to_owned(),
+                statement: serde_json::from_value(serde_json::json!([{
+                    "effect": "Allow",
+                    "subjects": ["<.*>"],
+                    "actions": ["get"],
+                    "resources": ["crn:iam:deployment:67"],
+                }]))
+                .unwrap(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let policy_binding = PolicyBindingImpl::new(db.clone());
+        for (id, bindings_type, bindings_id) in [
+            ("68", BindingsType::Application, "66"),
+            ("69", BindingsType::FederatedUser, "github|66"),
+        ] {
+            policy_binding
+                .put(&PolicyBinding {
+                    id: id.to_owned(),
+                    policy_id: "67".to_owned(),
+                    bindings_type,
+                    bindings_id: bindings_id.to_owned(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        // the user sharing its id with the application is bound neither the
+        // role nor the policy
+        for (principal_type, subject, bound) in [
+            (PrincipalType::Application, "66", 1),
+            (PrincipalType::FederatedUser, "github|66", 1),
+            (PrincipalType::User, "66", 0),
+            (PrincipalType::Application, "github|66", 0),
+        ] {
+            let statements = policy
+                .get_statement(&Request {
+                    resource: "crn:iam:deployment:62".to_owned(),
+                    action: "update".to_owned(),
+                    subject: subject.to_owned(),
+                    principal_type,
+                    context: Default::default(),
+                })
+                .await
+                .unwrap();
+            assert_eq!(
+                statements.len(),
+                2 * bound,
+                "{:?} {}",
+                principal_type,
+                subject
+            );
+            let bindings =
+                policy.get_bindings(principal_type, subject).await.unwrap();
+            assert!(bindings.groups.is_empty());
+            assert_eq!(bindings.roles.len(), bound);
+            assert_eq!(bindings.policies.len(), 2 * bound);
+        }
+    }
+
+    #[tokio::test]
+    async fn windows() {
+        let db = database().await;
+        // whole seconds, which every store keeps as is
+        let now = Utc::now().naive_utc().with_nanosecond(0).unwrap();
+        let hour = Duration::hours(1);
+        GroupImpl::new(db.clone())
+            .put(&Group {
+                id: "71".to_owned(),
+                account_id: "1".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let policy = PolicyImpl::new(db.clone());
+        policy
+            .put(&Policy {
+                id: "72".to_owned(),
+                version: "v1.0.0".to_owned(),
+                statement: serde_json::from_value(serde_json::json!([{
+                    "effect": "Allow",
+                    "subjects": ["<.*>"],
+                    "actions": ["read"],
+                    "resources": ["crn:iam:document:72"],
+                }]))
+                .unwrap(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        // the membership takes effect in an hour
+        GroupUserImpl::new(db.clone())
+            .put(&GroupUser {
+                id: "73".to_owned(),
+                group_id: "71".to_owned(),
+                user_id: "76".to_owned(),
+                not_before: Some(now + hour),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        // the role binding expired an hour ago
+        RoleBindingImpl::new(db.clone())
+            .put(&RoleBinding {
+                id: "74".to_owned(),
+                role_id: "75".to_owned(),
+                user_type: UserType::User,
+                user_id: "77".to_owned(),
+                expires_at: Some(now - hour),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let policy_binding = PolicyBindingImpl::new(db.clone());
+        for (id, bindings_type, bindings_id, expires_at) in [
+            ("81", BindingsType::Group, "71", None),
+            ("82", BindingsType::Role, "75", None),
+            ("83", BindingsType::User, "78", Some(now + hour * 2)),
+        ] {
+            policy_binding
+                .put(&PolicyBinding {
+                    id: id.to_owned(),
+                    policy_id: "72".to_owned(),
+                    bindings_type,
+                    bindings_id: bindings_id.to_owned(),
+                    expires_at,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        for (subject, bound, changes_at) in [
+            ("76", 0, Some(now + hour)),
+            ("77", 0, None),
+            ("78", 1, Some(now + hour * 2)),
+        ] {
+            let statements = policy
+                .get_statement(&Request {
+                    resource: "crn:iam:document:72".to_owned(),
+                    action: "read".to_owned(),
+                    subject: subject.to_owned(),
+                    principal_type: PrincipalType::User,
+                    context: Default::default(),
+                })
+                .await
+                .unwrap();
+            assert_eq!(statements.len(), bound, "{}", subject);
+            let bindings = policy
+                .get_bindings(PrincipalType::User, subject)
+                .await
+                .unwrap();
+            assert_eq!(bindings.changes_at, changes_at, "{}", subject);
+        }
+    }
+
+    #[tokio::test]
+    async fn gc() {
+        let db = database().await;
+        let gc = GcImpl::new(db.clone());
+        let requests = AuthRequestImpl::new(db.clone());
+        let codes = AuthCodeImpl::new(db.clone());
+        let tokens = RefreshTokenImpl::new(db);
+        for (id, expiry) in [("1", 100), ("2", 300)] {
+            requests
+                .put(&AuthRequest {
+                    id: id.to_owned(),
+                    client_id: "1".to_owned(),
+                    expiry,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            codes
+                .put(&AuthCode {
+                    id: id.to_owned(),
+                    client_id: "1".to_owned(),
+                    expiry,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        assert_eq!(gc.purge_auth_requests(200).await.unwrap(), 1);
+        assert_eq!(gc.purge_auth_codes(200).await.unwrap(), 1);
+        let mut request = AuthRequest {
+            id: "1".to_owned(),
+            ..Default::default()
+        };
+        assert!(requests.get(&mut request).await.is_err());
+        request.id = "2".to_owned();
+        requests.get(&mut request).await.unwrap();
+
+        let now = chrono::Utc::now().naive_utc();
+        for (id, last_used_at) in
+            [("1", now - chrono::Duration::days(2)), ("2", now)]
+        {
+            tokens
+                .put(&RefreshToken {
+                    id: id.to_owned(),
+                    client_id: "1".to_owned(),
+                    last_used_at,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        assert_eq!(gc.purge_refresh_tokens(None, None).await.unwrap(), 0);
+        let used_before = now - chrono::Duration::days(1);
+        assert_eq!(
+            gc.purge_refresh_tokens(None, Some(used_before))
+                .await
+                .unwrap(),
+            1
+        );
+        let created_before = now + chrono::Duration::hours(1);
+        assert_eq!(
+            gc.purge_refresh_tokens(Some(created_before), Some(used_before))
+                .await
+                .unwrap(),
+            1
+        );
+    }
+}
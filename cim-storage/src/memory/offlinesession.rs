@@ -0,0 +1,82 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use cim_slo::Result;
+
+use super::{matches, paginate, Database};
+use crate::{
+    offlinesession::{ListParams, OfflineSession},
+    Interface, List,
+};
+
+#[derive(Clone, Debug)]
+pub struct OfflineSessionImpl {
+    db: Database,
+}
+
+impl OfflineSessionImpl {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl Interface for OfflineSessionImpl {
+    type T = OfflineSession;
+    type L = ListParams;
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        self.db
+            .write()
+            .offline_session
+            .put(&input.id, input.clone());
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        self.db.write().offline_session.delete(&input.id);
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        *output = self.db.read().offline_session.get(&output.id)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        let rows = self
+            .db
+            .read()
+            .offline_session
+            .iter(false)
+            .filter(|v| filter(opts, v))
+            .cloned()
+            .collect();
+        paginate(rows, &opts.pagination, ListParams::SORTABLE, output)
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        Ok(self
+            .db
+            .read()
+            .offline_session
+            .count(unscoped, |v| filter(opts, v)))
+    }
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        Ok(self.db.write().offline_session.purge(before))
+    }
+}
+
+fn filter(opts: &ListParams, v: &OfflineSession) -> bool {
+    matches(&opts.user_id, &v.user_id) && matches(&opts.conn_id, &v.conn_id)
+}
@@ -0,0 +1,328 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use cim_pim::{PrincipalType, Request, Statement};
+use cim_slo::{errors, Result};
+
+use super::{group::ancestors, matches, now, paginate, Database, Tables};
+use crate::{
+    expiry::{is_active, next_change},
+    policy::{Bindings, ListParams, Policy, StatementStore},
+    policy_binding::BindingsType,
+    role_binding::UserType,
+    Interface, List,
+};
+
+#[derive(Clone, Debug)]
+pub struct PolicyImpl {
+    db: Database,
+}
+
+impl PolicyImpl {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// select lists the rows matching opts, the soft deleted ones when
+    /// deleted
+    fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<Policy>,
+        deleted: bool,
+    ) -> Result<()> {
+        let tables = self.db.read();
+        let rows = tables
+            .policy
+            .select(deleted)
+            .filter(|v| filter(&tables, opts, v))
+            .cloned()
+            .collect();
+        paginate(rows, &opts.pagination, ListParams::SORTABLE, output)
+    }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it, returning the live entity it replaced
+    fn write(
+        &self,
+        input: &Policy,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Policy>> {
+        let now = now();
+        let mut tables = self.db.write();
+        let stored =
+            tables
+                .policy
+                .version(&input.id, resource_version, |v| v.resource_version)?;
+        let previous = tables.policy.get(&input.id).ok();
+        tables.policy.put(
+            &input.id,
+            Policy {
+                resource_version: stored + 1,
+                created_at: previous
+                    .as_ref()
+                    .map(|v| v.created_at)
+                    .unwrap_or(now),
+                updated_at: now,
+                ..input.clone()
+            },
+        );
+        Ok(previous)
+    }
+
+    /// remove soft deletes input, when resource_version is given only
+    /// if the stored one is still at it, returning the live entity it
+    /// deleted
+    fn remove(
+        &self,
+        input: &Policy,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Policy>> {
+        let mut tables = self.db.write();
+        if tables
+            .policy_binding
+            .count(false, |v| v.policy_id == input.id)
+            != 0
+        {
+            return Err(errors::forbidden(
+                &"can't delete policy, because it is used".to_string(),
+            ));
+        }
+        tables
+            .policy
+            .delete_at(&input.id, resource_version, |v| &mut v.resource_version)
+    }
+}
+
+#[async_trait]
+impl Interface for PolicyImpl {
+    type T = Policy;
+    type L = ListParams;
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        self.write(input, None).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        self.remove(input, None).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        *output = self.db.read().policy.get(&output.id)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, false)
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let tables = self.db.read();
+        Ok(tables.policy.count(unscoped, |v| filter(&tables, opts, v)))
+    }
+
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.write(input, Some(resource_version)).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.write(input, resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.remove(input, Some(resource_version)).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.remove(input, resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true)
+    }
+
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        self.db
+            .write()
+            .policy
+            .restore(&input.id, |v| &mut v.resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        Ok(self.db.write().policy.purge(before))
+    }
+}
+
+#[async_trait]
+impl StatementStore for PolicyImpl {
+    async fn get_statement(&self, req: &Request) -> Result<Vec<Statement>> {
+        let tables = self.db.read();
+        let now = now();
+        // only users are members of groups
+        let user = req.principal_type == PrincipalType::User;
+        let bindings_type = BindingsType::from(req.principal_type);
+        let groups = tables
+            .group_user
+            .iter(false)
+            .filter(|v| user && v.user_id == req.subject)
+            .filter(|v| is_active(v.not_before, v.expires_at, now))
+            .map(|v| v.group_id.clone())
+            .collect();
+        let groups: Vec<String> = ancestors(&tables, groups)
+            .into_iter()
+            .filter(|v| tables.group.get(v).is_ok())
+            .collect();
+        let user_type = UserType::from(req.principal_type);
+        let roles: Vec<&str> = tables
+            .role_binding
+            .iter(false)
+            .filter(|v| v.user_type == user_type && v.user_id == req.subject)
+            .filter(|v| is_active(v.not_before, v.expires_at, now))
+            .filter(|v| tables.role.get(&v.role_id).is_ok())
+            .map(|v| v.role_id.as_str())
+            .collect();
+
+        let mut result = Vec::new();
+        for policy in tables.policy.iter(false) {
+            if tables.policy_binding.iter(false).any(|v| {
+                v.policy_id == policy.id
+                    && is_active(v.not_before, v.expires_at, now)
+                    && match v.bindings_type {
+                        BindingsType::Group => groups.contains(&v.bindings_id),
+                        BindingsType::Role => {
+                            roles.contains(&v.bindings_id.as_str())
+                        }
+                        _ => {
+                            v.bindings_type == bindings_type
+                                && v.bindings_id == req.subject
+                        }
+                    }
+            }) {
+                result.extend(policy.statement.iter().cloned());
+            }
+        }
+        Ok(result)
+    }
+
+    async fn get_bindings(
+        &self,
+        principal_type: PrincipalType,
+        subject: &str,
+    ) -> Result<Bindings> {
+        let tables = self.db.read();
+        let user = principal_type == PrincipalType::User;
+        let bindings_type = BindingsType::from(principal_type);
+        let members: Vec<_> = tables
+            .group_user
+            .iter(false)
+            .filter(|v| user && v.user_id == subject)
+            .collect();
+        let groups = members.iter().map(|v| v.group_id.clone()).collect();
+        // a change to a group a subject is a member of through nested
+        // groups evicts it too
+        let groups = ancestors(&tables, groups);
+        let user_type = UserType::from(principal_type);
+        let role_bindings: Vec<_> = tables
+            .role_binding
+            .iter(false)
+            .filter(|v| v.user_type == user_type && v.user_id == subject)
+            .collect();
+        let roles: Vec<String> =
+            role_bindings.iter().map(|v| v.role_id.clone()).collect();
+        let policy_bindings: Vec<_> = tables
+            .policy_binding
+            .iter(false)
+            .filter(|v| match v.bindings_type {
+                BindingsType::Group => groups.contains(&v.bindings_id),
+                BindingsType::Role => roles.contains(&v.bindings_id),
+                _ => {
+                    v.bindings_type == bindings_type && v.bindings_id == subject
+                }
+            })
+            .collect();
+        let windows = members
+            .iter()
+            .map(|v| (v.not_before, v.expires_at))
+            .chain(role_bindings.iter().map(|v| (v.not_before, v.expires_at)))
+            .chain(
+                policy_bindings.iter().map(|v| (v.not_before, v.expires_at)),
+            );
+        Ok(Bindings {
+            changes_at: next_change(windows, now()),
+            policies: policy_bindings
+                .iter()
+                .map(|v| v.policy_id.clone())
+                .collect(),
+            groups,
+            roles,
+        })
+    }
+}
+
+fn filter(tables: &Tables, opts: &ListParams, v: &Policy) -> bool {
+    let bound = |bindings_type: BindingsType, bindings_id: &String| {
+        tables.policy_binding.iter(false).any(|pb| {
+            pb.policy_id == v.id
+                && pb.bindings_type == bindings_type
+                && &pb.bindings_id == bindings_id
+        })
+    };
+    matches(&opts.id, &v.id)
+        && matches(&opts.version, &v.version)
+        && opts
+            .account_id
+            .as_ref()
+            .is_none_or(|account_id| v.account_id.as_ref() == Some(account_id))
+        && opts
+            .group_id
+            .as_ref()
+            .is_none_or(|id| bound(BindingsType::Group, id))
+        && opts
+            .user_id
+            .as_ref()
+            .is_none_or(|id| bound(BindingsType::User, id))
+        && opts
+            .role_id
+            .as_ref()
+            .is_none_or(|id| bound(BindingsType::Role, id))
+        && opts
+            .label_selector
+            .as_ref()
+            .is_none_or(|selector| selector.matches(&v.labels))
+}
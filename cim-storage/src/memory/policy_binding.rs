@@ -0,0 +1,215 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use cim_slo::Result;
+
+use super::{matches, now, paginate, Database, Tables};
+use crate::{
+    policy_binding::{BindingsType, ListParams, PolicyBinding},
+    Interface, List,
+};
+
+#[derive(Clone, Debug)]
+pub struct PolicyBindingImpl {
+    db: Database,
+}
+
+impl PolicyBindingImpl {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// select lists the rows matching opts, the soft deleted ones when
+    /// deleted
+    fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<PolicyBinding>,
+        deleted: bool,
+    ) -> Result<()> {
+        let tables = self.db.read();
+        let rows = tables
+            .policy_binding
+            .select(deleted)
+            .filter(|v| filter(&tables, opts, v))
+            .cloned()
+            .collect();
+        paginate(rows, &opts.pagination, ListParams::SORTABLE, output)
+    }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it, returning the live entity it replaced
+    fn write(
+        &self,
+        input: &PolicyBinding,
+        resource_version: Option<u64>,
+    ) -> Result<Option<PolicyBinding>> {
+        let now = now();
+        let mut tables = self.db.write();
+        let stored = tables.policy_binding.version(
+            &input.id,
+            resource_version,
+            |v| v.resource_version,
+        )?;
+        let previous = tables.policy_binding.get(&input.id).ok();
+        tables.policy_binding.put(
+            &input.id,
+            PolicyBinding {
+                resource_version: stored + 1,
+                created_at: previous
+                    .as_ref()
+                    .map(|v| v.created_at)
+                    .unwrap_or(now),
+                updated_at: now,
+                ..input.clone()
+            },
+        );
+        Ok(previous)
+    }
+
+    /// remove soft deletes input, when resource_version is given only
+    /// if the stored one is still at it, returning the live entity it
+    /// deleted
+    fn remove(
+        &self,
+        input: &PolicyBinding,
+        resource_version: Option<u64>,
+    ) -> Result<Option<PolicyBinding>> {
+        self.db.write().policy_binding.delete_at(
+            &input.id,
+            resource_version,
+            |v| &mut v.resource_version,
+        )
+    }
+}
+
+#[async_trait]
+impl Interface for PolicyBindingImpl {
+    type T = PolicyBinding;
+    type L = ListParams;
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        self.write(input, None).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        self.remove(input, None).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        *output = self.db.read().policy_binding.get(&output.id)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, false)
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let tables = self.db.read();
+        Ok(tables
+            .policy_binding
+            .count(unscoped, |v| filter(&tables, opts, v)))
+    }
+
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.write(input, Some(resource_version)).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.write(input, resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.remove(input, Some(resource_version)).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.remove(input, resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true)
+    }
+
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        self.db
+            .write()
+            .policy_binding
+            .restore(&input.id, |v| &mut v.resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        Ok(self.db.write().policy_binding.purge(before))
+    }
+}
+
+fn filter(tables: &Tables, opts: &ListParams, v: &PolicyBinding) -> bool {
+    matches(&opts.id, &v.id)
+        && opts
+            .account_id
+            .as_ref()
+            .is_none_or(|account_id| owner(tables, v) == Some(account_id))
+        && matches(&opts.policy_id, &v.policy_id)
+        && opts
+            .bindings_type
+            .as_ref()
+            .is_none_or(|t| t == &v.bindings_type)
+        && matches(&opts.bindings_id, &v.bindings_id)
+        && opts
+            .expires_before
+            .is_none_or(|before| v.expires_at.is_some_and(|at| at < before))
+}
+
+/// owner is the account of the user, group or role v binds, or of its
+/// policy when it binds a subject of no account
+fn owner<'a>(tables: &'a Tables, v: &PolicyBinding) -> Option<&'a String> {
+    let id = &v.bindings_id;
+    match v.bindings_type {
+        BindingsType::User => tables.user.find(id).map(|v| &v.value.account_id),
+        BindingsType::Group => {
+            tables.group.find(id).map(|v| &v.value.account_id)
+        }
+        BindingsType::Role => tables.role.find(id).map(|v| &v.value.account_id),
+        _ => tables
+            .policy
+            .find(&v.policy_id)
+            .and_then(|v| v.value.account_id.as_ref()),
+    }
+}
@@ -0,0 +1,73 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use cim_slo::Result;
+
+use super::{now, Database};
+use crate::{refresh_token::RefreshToken, Interface, List};
+
+#[derive(Clone, Debug)]
+pub struct RefreshTokenImpl {
+    db: Database,
+}
+
+impl RefreshTokenImpl {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl Interface for RefreshTokenImpl {
+    type T = RefreshToken;
+    type L = ();
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        let now = now();
+        self.db.write().refresh_token.put(
+            &input.id,
+            RefreshToken {
+                created_at: now,
+                updated_at: now,
+                ..input.clone()
+            },
+        );
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        self.db.write().refresh_token.delete(&input.id);
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        *output = self.db.read().refresh_token.get(&output.id)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        _opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        let tables = self.db.read();
+        output
+            .data
+            .extend(tables.refresh_token.iter(false).cloned());
+        output.total = output.data.len() as i64;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, _opts: &Self::L, unscoped: bool) -> Result<i64> {
+        Ok(self.db.read().refresh_token.count(unscoped, |_| true))
+    }
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        Ok(self.db.write().refresh_token.purge(before))
+    }
+}
@@ -0,0 +1,210 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use cim_pim::rebac::{Subject, TupleReader};
+use cim_slo::Result;
+
+use super::{matches, now, paginate, Database};
+use crate::{
+    relation_tuple::{ListParams, RelationTuple},
+    Interface, List,
+};
+
+#[derive(Clone, Debug)]
+pub struct RelationTupleImpl {
+    db: Database,
+}
+
+impl RelationTupleImpl {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// select lists the rows matching opts, the soft deleted ones when
+    /// deleted
+    fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<RelationTuple>,
+        deleted: bool,
+    ) -> Result<()> {
+        let rows = self
+            .db
+            .read()
+            .relation_tuple
+            .select(deleted)
+            .filter(|v| filter(opts, v))
+            .cloned()
+            .collect();
+        paginate(rows, &opts.pagination, ListParams::SORTABLE, output)
+    }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it, returning the live entity it replaced
+    fn write(
+        &self,
+        input: &RelationTuple,
+        resource_version: Option<u64>,
+    ) -> Result<Option<RelationTuple>> {
+        let now = now();
+        let mut tables = self.db.write();
+        let stored = tables.relation_tuple.version(
+            &input.id,
+            resource_version,
+            |v| v.resource_version,
+        )?;
+        let previous = tables.relation_tuple.get(&input.id).ok();
+        tables.relation_tuple.put(
+            &input.id,
+            RelationTuple {
+                resource_version: stored + 1,
+                created_at: previous
+                    .as_ref()
+                    .map(|v| v.created_at)
+                    .unwrap_or(now),
+                updated_at: now,
+                ..input.clone()
+            },
+        );
+        Ok(previous)
+    }
+
+    /// remove soft deletes input, when resource_version is given only
+    /// if the stored one is still at it, returning the live entity it
+    /// deleted
+    fn remove(
+        &self,
+        input: &RelationTuple,
+        resource_version: Option<u64>,
+    ) -> Result<Option<RelationTuple>> {
+        self.db.write().relation_tuple.delete_at(
+            &input.id,
+            resource_version,
+            |v| &mut v.resource_version,
+        )
+    }
+}
+
+#[async_trait]
+impl Interface for RelationTupleImpl {
+    type T = RelationTuple;
+    type L = ListParams;
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        self.write(input, None).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        self.remove(input, None).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        *output = self.db.read().relation_tuple.get(&output.id)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, false)
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        Ok(self
+            .db
+            .read()
+            .relation_tuple
+            .count(unscoped, |v| filter(opts, v)))
+    }
+
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.write(input, Some(resource_version)).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.write(input, resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.remove(input, Some(resource_version)).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.remove(input, resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true)
+    }
+
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        self.db
+            .write()
+            .relation_tuple
+            .restore(&input.id, |v| &mut v.resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        Ok(self.db.write().relation_tuple.purge(before))
+    }
+}
+
+#[async_trait]
+impl TupleReader for RelationTupleImpl {
+    async fn read(
+        &self,
+        object: &str,
+        relation: &str,
+    ) -> anyhow::Result<Vec<Subject>> {
+        let tables = self.db.read();
+        let mut result = Vec::new();
+        for v in tables.relation_tuple.iter(false) {
+            if v.object == object && v.relation == relation {
+                result.push(v.subject.parse()?);
+            }
+        }
+        Ok(result)
+    }
+}
+
+fn filter(opts: &ListParams, v: &RelationTuple) -> bool {
+    matches(&opts.id, &v.id)
+        && matches(&opts.account_id, &v.account_id)
+        && matches(&opts.object, &v.object)
+        && matches(&opts.relation, &v.relation)
+        && matches(&opts.subject, &v.subject)
+}
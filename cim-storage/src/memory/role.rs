@@ -0,0 +1,207 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+
+use cim_slo::{errors, Result};
+
+use super::{matches, now, paginate, Database, Tables};
+use crate::{
+    policy_binding::BindingsType,
+    role::{ListParams, Role},
+    Interface, List,
+};
+
+#[derive(Clone, Debug)]
+pub struct RoleImpl {
+    db: Database,
+}
+
+impl RoleImpl {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// select lists the rows matching opts, the soft deleted ones when
+    /// deleted
+    fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<Role>,
+        deleted: bool,
+    ) -> Result<()> {
+        let tables = self.db.read();
+        let rows = tables
+            .role
+            .select(deleted)
+            .filter(|v| filter(&tables, opts, v))
+            .cloned()
+            .collect();
+        paginate(rows, &opts.pagination, ListParams::SORTABLE, output)
+    }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it, returning the live entity it replaced
+    fn write(
+        &self,
+        input: &Role,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Role>> {
+        let now = now();
+        let mut tables = self.db.write();
+        let stored = tables
+            .role
+            .version(&input.id, resource_version, |v| v.resource_version)?;
+        let previous = tables.role.get(&input.id).ok();
+        tables.role.put(
+            &input.id,
+            Role {
+                resource_version: stored + 1,
+                created_at: previous
+                    .as_ref()
+                    .map(|v| v.created_at)
+                    .unwrap_or(now),
+                updated_at: now,
+                ..input.clone()
+            },
+        );
+        Ok(previous)
+    }
+
+    /// remove soft deletes input, when resource_version is given only
+    /// if the stored one is still at it, returning the live entity it
+    /// deleted
+    fn remove(
+        &self,
+        input: &Role,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Role>> {
+        let mut tables = self.db.write();
+        if tables.role_binding.count(false, |v| v.role_id == input.id) != 0 {
+            return Err(errors::forbidden(
+                &"can't delete role, because it is attached to user"
+                    .to_string(),
+            ));
+        }
+        if tables.policy_binding.count(false, |v| {
+            v.bindings_type == BindingsType::Role && v.bindings_id == input.id
+        }) != 0
+        {
+            return Err(errors::forbidden(
+                &"can't delete role, because it is attached by policy"
+                    .to_string(),
+            ));
+        }
+        tables
+            .role
+            .delete_at(&input.id, resource_version, |v| &mut v.resource_version)
+    }
+}
+
+#[async_trait]
+impl Interface for RoleImpl {
+    type T = Role;
+    type L = ListParams;
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        self.write(input, None).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        self.remove(input, None).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        *output = self.db.read().role.get(&output.id)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, false)
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let tables = self.db.read();
+        Ok(tables.role.count(unscoped, |v| filter(&tables, opts, v)))
+    }
+
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.write(input, Some(resource_version)).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.write(input, resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.remove(input, Some(resource_version)).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.remove(input, resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true)
+    }
+
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        self.db
+            .write()
+            .role
+            .restore(&input.id, |v| &mut v.resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        Ok(self.db.write().role.purge(before))
+    }
+}
+
+fn filter(tables: &Tables, opts: &ListParams, v: &Role) -> bool {
+    matches(&opts.id, &v.id)
+        && matches(&opts.account_id, &v.account_id)
+        && opts.user_id.as_ref().is_none_or(|user_id| {
+            tables
+                .role_binding
+                .iter(false)
+                .any(|rb| &rb.user_id == user_id && rb.role_id == v.id)
+        })
+        && opts
+            .label_selector
+            .as_ref()
+            .is_none_or(|selector| selector.matches(&v.labels))
+}
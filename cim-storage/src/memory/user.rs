@@ -0,0 +1,240 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rand::Rng;
+
+use cim_slo::{
+    crypto::password::{encrypt, is_encoded},
+    errors, Result,
+};
+
+use super::{group::descendants, matches, now, paginate, Database, Tables};
+use crate::{
+    expiry::is_active,
+    policy_binding::BindingsType,
+    user::{ListParams, User},
+    Interface, List,
+};
+
+#[derive(Clone, Debug)]
+pub struct UserImpl {
+    db: Database,
+}
+
+impl UserImpl {
+    pub fn new(db: Database) -> Self {
+        Self { db }
+    }
+
+    /// select lists the rows matching opts, the soft deleted ones when
+    /// deleted
+    fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<User>,
+        deleted: bool,
+    ) -> Result<()> {
+        let tables = self.db.read();
+        let rows = tables
+            .user
+            .select(deleted)
+            .filter(|v| filter(&tables, opts, v))
+            .map(|v| User {
+                secret: None,
+                password: None,
+                ..v.clone()
+            })
+            .collect();
+        paginate(rows, &opts.pagination, ListParams::SORTABLE, output)
+    }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it, returning the live entity it replaced
+    fn write(
+        &self,
+        input: &User,
+        resource_version: Option<u64>,
+    ) -> Result<Option<User>> {
+        let password = input
+            .password
+            .as_ref()
+            .ok_or_else(|| errors::bad_request("password is required"))?;
+        // a hashed password is kept along with the secret it was hashed with
+        let (secret, password) = match &input.secret {
+            Some(secret) if is_encoded(password) => {
+                (secret.clone(), password.clone())
+            }
+            _ => {
+                let secret = rand::rng()
+                    .sample_iter(&rand::distr::Alphanumeric)
+                    .take(64)
+                    .map(char::from)
+                    .collect::<String>();
+                let password = encrypt(password, &secret)?;
+                (secret, password)
+            }
+        };
+
+        let now = now();
+        let mut value = input.clone();
+        value.secret = Some(secret);
+        value.password = Some(password);
+        value.updated_at = now;
+        let mut tables = self.db.write();
+        let stored = tables
+            .user
+            .version(&input.id, resource_version, |v| v.resource_version)?;
+        value.resource_version = stored + 1;
+        let previous = tables.user.get(&input.id).ok();
+        value.created_at =
+            previous.as_ref().map(|v| v.created_at).unwrap_or(now);
+        tables.user.put(&input.id, value);
+        Ok(previous)
+    }
+
+    /// remove soft deletes input, when resource_version is given only
+    /// if the stored one is still at it, returning the live entity it
+    /// deleted
+    fn remove(
+        &self,
+        input: &User,
+        resource_version: Option<u64>,
+    ) -> Result<Option<User>> {
+        let mut tables = self.db.write();
+        if tables.group_user.count(false, |v| v.user_id == input.id) != 0 {
+            return Err(errors::forbidden(
+                &"can't delete user, because it is attached to group"
+                    .to_string(),
+            ));
+        }
+        if tables.policy_binding.count(false, |v| {
+            v.bindings_type == BindingsType::User && v.bindings_id == input.id
+        }) != 0
+        {
+            return Err(errors::forbidden(
+                &"can't delete user, because it is attached by policy"
+                    .to_string(),
+            ));
+        }
+        tables
+            .user
+            .delete_at(&input.id, resource_version, |v| &mut v.resource_version)
+    }
+}
+
+#[async_trait]
+impl Interface for UserImpl {
+    type T = User;
+    type L = ListParams;
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        self.write(input, None).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        self.remove(input, None).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        *output = self.db.read().user.get(&output.id)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, false)
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let tables = self.db.read();
+        Ok(tables.user.count(unscoped, |v| filter(&tables, opts, v)))
+    }
+
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.write(input, Some(resource_version)).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.write(input, resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.remove(input, Some(resource_version)).map(drop)
+    }
+
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.remove(input, resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true)
+    }
+
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        self.db
+            .write()
+            .user
+            .restore(&input.id, |v| &mut v.resource_version)
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        Ok(self.db.write().user.purge(before))
+    }
+}
+
+fn filter(tables: &Tables, opts: &ListParams, v: &User) -> bool {
+    matches(&opts.id, &v.id)
+        && matches(&opts.account_id, &v.account_id)
+        && opts.group_id.as_ref().is_none_or(|group_id| {
+            tables
+                .group_user
+                .iter(false)
+                .any(|gu| &gu.group_id == group_id && gu.user_id == v.id)
+        })
+        && opts.effective_group_id.as_ref().is_none_or(|group_id| {
+            let (groups, now) = (descendants(tables, group_id), now());
+            tables.group_user.iter(false).any(|gu| {
+                groups.contains(&gu.group_id)
+                    && gu.user_id == v.id
+                    && is_active(gu.not_before, gu.expires_at, now)
+            })
+        })
+        && opts
+            .label_selector
+            .as_ref()
+            .is_none_or(|selector| selector.matches(&v.labels))
+}
@@ -6,14 +6,18 @@ use serde::de::{
 use serde::Serialize;
 use validator::Validate;
 
-use cim_slo::regexp::check_order_by;
+use cim_slo::{errors, regexp::check_order_by};
 
-#[derive(Debug, Serialize, Default, utoipa::ToSchema)]
+#[derive(Debug, Clone, Serialize, Default, utoipa::ToSchema)]
 pub struct List<T> {
     pub data: Vec<T>,
     pub limit: u64,
     pub offset: u64,
     pub total: i64,
+    /// next_cursor continues the list after this page, it's set when the
+    /// page is full
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
 }
 
 #[derive(Debug, Serialize, utoipa::ToSchema)]
@@ -27,6 +31,8 @@ pub struct Pagination {
     pub offset: u64,
     #[validate(custom(function = "check_order_by"))]
     pub order_by: Option<String>,
+    // 上一页返回的 next_cursor, 设置后忽略 offset
+    pub cursor: Option<String>,
     // 内部字段，不参与序列化, 标识不启用计数查询
     pub count_disable: bool,
 }
@@ -40,6 +46,7 @@ impl<'de> Deserialize<'de> for Pagination {
             Limit,
             Offset,
             OrderBy,
+            Cursor,
             Ignore,
         }
         impl<'de> Deserialize<'de> for Field {
@@ -67,6 +74,7 @@ impl<'de> Deserialize<'de> for Pagination {
                             "limit" => Ok(Field::Limit),
                             "offset" => Ok(Field::Offset),
                             "order_by" => Ok(Field::OrderBy),
+                            "cursor" => Ok(Field::Cursor),
                             _ => Ok(Field::Ignore),
                         }
                     }
@@ -81,6 +89,7 @@ impl<'de> Deserialize<'de> for Pagination {
                             b"limit" => Ok(Field::Limit),
                             b"offset" => Ok(Field::Offset),
                             b"order_by" => Ok(Field::OrderBy),
+                            b"cursor" => Ok(Field::Cursor),
                             _ => Ok(Field::Ignore),
                         }
                     }
@@ -112,6 +121,7 @@ impl<'de> Deserialize<'de> for Pagination {
                 let order_by = seq.next_element()?.unwrap_or_else(|| {
                     Some(Pagination::DEFAULT_ORDER_BY.to_string())
                 });
+                let cursor = seq.next_element()?.unwrap_or_default();
                 Ok(Pagination {
                     limit: limit.parse().map_err(|err| {
                         de::Error::custom(format_args!(
@@ -126,6 +136,7 @@ impl<'de> Deserialize<'de> for Pagination {
                         ))
                     })?,
                     order_by,
+                    cursor,
                     count_disable: false,
                 })
             }
@@ -137,6 +148,7 @@ impl<'de> Deserialize<'de> for Pagination {
                 let mut limit: Option<String> = None;
                 let mut offset: Option<String> = None;
                 let mut order_by: Option<Option<String>> = None;
+                let mut cursor: Option<String> = None;
                 while let Some(key) = MapAccess::next_key::<Field>(&mut map)? {
                     match key {
                         Field::Limit => {
@@ -176,6 +188,18 @@ impl<'de> Deserialize<'de> for Pagination {
                                     &mut map,
                                 )?);
                         }
+                        Field::Cursor => {
+                            if Option::is_some(&cursor) {
+                                return Err(
+                                    <V::Error as de::Error>::duplicate_field(
+                                        "cursor",
+                                    ),
+                                );
+                            }
+                            cursor = Some(MapAccess::next_value::<String>(
+                                &mut map,
+                            )?);
+                        }
                         _ => {
                             let _ = MapAccess::next_value::<de::IgnoredAny>(
                                 &mut map,
@@ -205,12 +229,13 @@ impl<'de> Deserialize<'de> for Pagination {
                         ))
                     })?,
                     order_by,
+                    cursor,
                     count_disable: false,
                 })
             }
         }
 
-        const FIELDS: &[&str] = &["limit", "offset", "order_by"];
+        const FIELDS: &[&str] = &["limit", "offset", "order_by", "cursor"];
         deserializer.deserialize_struct("Pagination", FIELDS, PaginationVisitor)
     }
 }
@@ -220,19 +245,48 @@ impl Pagination {
     const DEFAULT_OFFSET: &'static str = "0";
     const DEFAULT_ORDER_BY: &'static str = "created_at DESC";
 
-    pub fn convert(&self, wheres: &mut String) {
-        if let Some(order_by) = &self.order_by {
-            wheres.push_str(" ORDER BY ");
-            wheres.push_str(order_by);
-        }
-        if self.limit > 0 {
-            wheres.push_str(" LIMIT ");
-            wheres.push_str(self.limit.to_string().as_str());
-        }
-        if self.offset > 0 {
-            wheres.push_str(" OFFSET ");
-            wheres.push_str(self.offset.to_string().as_str());
+    /// sort parses order_by into columns and whether they are descending,
+    /// every column must be one of sortable
+    pub fn sort(
+        &self,
+        sortable: &[&'static str],
+    ) -> cim_slo::Result<Vec<(&'static str, bool)>> {
+        let Some(order_by) = &self.order_by else {
+            return Ok(Vec::new());
+        };
+        let mut result = Vec::new();
+        for order in order_by.split(',') {
+            let mut parts = order.split_whitespace();
+            let field = parts.next().unwrap_or_default();
+            let column = sortable
+                .iter()
+                .find(|column| **column == field)
+                .ok_or_else(|| {
+                    errors::bad_request(&format!(
+                        "invalid order by column {:?}",
+                        field
+                    ))
+                })?;
+            let desc = match parts.next() {
+                None => false,
+                Some(v) if v.eq_ignore_ascii_case("asc") => false,
+                Some(v) if v.eq_ignore_ascii_case("desc") => true,
+                Some(v) => {
+                    return Err(errors::bad_request(&format!(
+                        "invalid order by direction {:?}",
+                        v
+                    )))
+                }
+            };
+            if parts.next().is_some() {
+                return Err(errors::bad_request(&format!(
+                    "invalid order by {:?}",
+                    order
+                )));
+            }
+            result.push((*column, desc));
         }
+        Ok(result)
     }
 }
 
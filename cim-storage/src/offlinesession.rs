@@ -5,7 +5,9 @@ use serde::{Deserialize, Serialize};
 use serde_json::value::RawValue;
 use validator::Validate;
 
-use crate::Pagination;
+use cim_slo::Result;
+
+use crate::{Filter, Keyed, Pagination, Param};
 
 #[derive(Debug, Default, Deserialize, Serialize, Clone, utoipa::ToSchema)]
 pub struct OfflineSession {
@@ -55,3 +57,27 @@ pub struct ListParams {
     #[validate(nested)]
     pub pagination: Pagination,
 }
+
+impl ListParams {
+    /// SORTABLE are the columns order_by may name
+    pub const SORTABLE: &'static [&'static str] =
+        &["id", "user_id", "conn_id", "created_at", "updated_at"];
+
+    /// filter is the WHERE clause of the sql stores
+    pub(crate) fn filter(&self) -> Result<Filter> {
+        Ok(Filter::default()
+            .eq("user_id", self.user_id.as_ref())
+            .eq("conn_id", self.conn_id.as_ref()))
+    }
+}
+
+impl Keyed for OfflineSession {
+    fn key(&self, column: &str) -> Option<Param> {
+        match column {
+            "id" => Some(Param::id(&self.id)),
+            "user_id" => Some((&self.user_id).into()),
+            "conn_id" => Some((&self.conn_id).into()),
+            _ => None,
+        }
+    }
+}
@@ -1,15 +1,23 @@
+use std::sync::Arc;
+
 use async_trait::async_trait;
 use chrono::NaiveDateTime;
 use mockall::automock;
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
-use cim_pim::{Request, Statement};
-use cim_slo::Result;
+use cim_pim::{PrincipalType, Request, Statement};
+use cim_slo::{errors, Result};
 
-use crate::Pagination;
+use crate::{
+    label::{check_annotations, check_labels, Labels, Selector},
+    policy_binding::BindingsType,
+    Filter, Keyed, Pagination, Param,
+};
 
-#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone, utoipa::ToSchema)]
+#[derive(
+    Debug, Default, Deserialize, Serialize, PartialEq, Clone, utoipa::ToSchema,
+)]
 pub struct Policy {
     pub id: String,
     pub account_id: Option<String>,
@@ -17,6 +25,12 @@ pub struct Policy {
     // 指定要使用的策略语言版本
     pub version: String,
     pub statement: Vec<Statement>,
+    #[serde(default)]
+    pub labels: Labels,
+    #[serde(default)]
+    pub annotations: Labels,
+    #[serde(default)]
+    pub resource_version: u64,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
@@ -32,6 +46,12 @@ pub struct Content {
     pub version: String,
     #[validate(nested)]
     pub statement: Vec<Statement>,
+    #[serde(default)]
+    #[validate(custom(function = "check_labels"))]
+    pub labels: Labels,
+    #[serde(default)]
+    #[validate(custom(function = "check_annotations"))]
+    pub annotations: Labels,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -48,13 +68,151 @@ pub struct ListParams {
     pub user_id: Option<String>,
     #[validate(length(min = 1))]
     pub role_id: Option<String>,
+    /// label_selector selects by labels, e.g. `env=prod,tier in (web,api)`
+    pub label_selector: Option<Selector>,
     #[serde(flatten)]
     #[validate(nested)]
     pub pagination: Pagination,
 }
 
+impl ListParams {
+    /// SORTABLE are the columns order_by may name
+    pub const SORTABLE: &'static [&'static str] =
+        &["id", "account_id", "version", "created_at", "updated_at"];
+
+    /// filter is the WHERE clause of the sql stores
+    pub(crate) fn filter(&self) -> Result<Filter> {
+        let mut filter = Filter::default()
+            .id("id", self.id.as_deref())?
+            .eq("version", self.version.as_ref())
+            .id("account_id", self.account_id.as_deref())?;
+        for (bindings_type, bindings_id) in [
+            (BindingsType::User, &self.user_id),
+            (BindingsType::Group, &self.group_id),
+            (BindingsType::Role, &self.role_id),
+        ] {
+            if let Some(bindings_id) = bindings_id {
+                filter = filter.in_select(
+                    "id",
+                    "policy_binding",
+                    "policy_id",
+                    Filter::default()
+                        .eq("bindings_id", Some(bindings_id))
+                        .eq("bindings_type", Some(u8::from(&bindings_type)))
+                        .not_deleted(),
+                );
+            }
+        }
+        if let Some(selector) = &self.label_selector {
+            filter = selector.filter(filter, "policy");
+        }
+        Ok(filter)
+    }
+}
+
+impl Keyed for Policy {
+    fn key(&self, column: &str) -> Option<Param> {
+        match column {
+            "id" => Some(Param::id(&self.id)),
+            "version" => Some((&self.version).into()),
+            "created_at" => Some(self.created_at.into()),
+            "updated_at" => Some(self.updated_at.into()),
+            _ => None,
+        }
+    }
+}
+
+/// Bindings are the ids the statements of a subject are derived from, a
+/// change to any of them may change the statements
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Bindings {
+    /// groups the subject is a member of
+    pub groups: Vec<String>,
+    /// roles bound to the subject
+    pub roles: Vec<String>,
+    /// policies bound to the subject, its groups or its roles
+    pub policies: Vec<String>,
+    /// changes_at is when the first of the bindings takes effect or expires,
+    /// the statements may change then without a write to tell
+    pub changes_at: Option<NaiveDateTime>,
+}
+
+impl Bindings {
+    fn append(&mut self, other: &mut Self) {
+        self.groups.append(&mut other.groups);
+        self.roles.append(&mut other.roles);
+        self.policies.append(&mut other.policies);
+        self.changes_at = match (self.changes_at, other.changes_at) {
+            (Some(v), Some(other)) => Some(v.min(other)),
+            (v, other) => v.or(other),
+        };
+    }
+}
+
+/// user_id is the id of subject when principal_type is a user, the other
+/// principals aren't members of groups, they are bound policies directly by
+/// their subject
+pub(crate) fn user_id(
+    principal_type: PrincipalType,
+    subject: &str,
+) -> Result<Option<i64>> {
+    match principal_type {
+        PrincipalType::User => subject.parse().map(Some).map_err(errors::any),
+        _ => Ok(None),
+    }
+}
+
 #[automock]
 #[async_trait]
 pub trait StatementStore {
+    /// get_statement returns the statements bound to the subject of req, the
+    /// principal type of req tells which role bindings are the subject's
     async fn get_statement(&self, req: &Request) -> Result<Vec<Statement>>;
+    /// get_bindings returns what the statements of subject are derived from,
+    /// bindings to deleted groups, roles or policies included
+    async fn get_bindings(
+        &self,
+        principal_type: PrincipalType,
+        subject: &str,
+    ) -> Result<Bindings>;
+}
+
+#[async_trait]
+impl<T: StatementStore + Send + Sync + ?Sized> StatementStore for Arc<T> {
+    async fn get_statement(&self, req: &Request) -> Result<Vec<Statement>> {
+        (**self).get_statement(req).await
+    }
+
+    async fn get_bindings(
+        &self,
+        principal_type: PrincipalType,
+        subject: &str,
+    ) -> Result<Bindings> {
+        (**self).get_bindings(principal_type, subject).await
+    }
+}
+
+#[async_trait]
+impl<T: StatementStore + Sync> StatementStore for Vec<T> {
+    async fn get_statement(&self, req: &Request) -> Result<Vec<Statement>> {
+        let mut result = Vec::with_capacity(self.len());
+        for store in self.iter() {
+            result.append(&mut store.get_statement(req).await?);
+        }
+        Ok(result)
+    }
+
+    async fn get_bindings(
+        &self,
+        principal_type: PrincipalType,
+        subject: &str,
+    ) -> Result<Bindings> {
+        let mut result = Bindings::default();
+        for store in self.iter() {
+            result.append(
+                &mut store.get_bindings(principal_type, subject).await?,
+            );
+        }
+        Ok(result)
+    }
 }
@@ -1,8 +1,11 @@
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
-use crate::Pagination;
+use cim_pim::PrincipalType;
+use cim_slo::Result;
+
+use crate::{expiry::check_window, Filter, Keyed, Pagination, Param};
 
 #[derive(
     Debug, Default, Deserialize, Serialize, PartialEq, Clone, utoipa::ToSchema,
@@ -12,33 +15,134 @@ pub struct PolicyBinding {
     pub policy_id: String,
     pub bindings_type: BindingsType,
     pub bindings_id: String,
+    /// not_before is when the binding takes effect, right away when none
+    #[serde(default)]
+    pub not_before: Option<NaiveDateTime>,
+    /// expires_at is when the binding ends, it's deleted once past it
+    #[serde(default)]
+    pub expires_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub resource_version: u64,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
 
 #[derive(Debug, Deserialize, Validate)]
+#[validate(schema(function = "check_content"))]
 pub struct Content {
     #[validate(length(min = 1, max = 255))]
     pub policy_id: String,
     pub bindings_type: BindingsType,
     #[validate(length(min = 1, max = 255))]
     pub bindings_id: String,
+    pub not_before: Option<NaiveDateTime>,
+    pub expires_at: Option<NaiveDateTime>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
 pub struct ListParams {
     #[validate(length(min = 1))]
     pub id: Option<String>,
+    /// account_id lists the bindings of the users, groups and roles of the
+    /// account whichever account the policy is of, and the bindings of the
+    /// policies of the account to subjects of no account
+    #[validate(length(min = 1))]
+    pub account_id: Option<String>,
     #[validate(length(min = 1))]
     pub policy_id: Option<String>,
     pub bindings_type: Option<BindingsType>,
     #[validate(length(min = 1))]
     pub bindings_id: Option<String>,
+    /// expires_before lists the bindings which expire before it
+    pub expires_before: Option<NaiveDateTime>,
     #[serde(flatten)]
     #[validate(nested)]
     pub pagination: Pagination,
 }
 
+/// OWNERS are the kinds of subject which belong to an account and their
+/// tables, the binding belongs to the account of its subject
+const OWNERS: [(&BindingsType, &str); 3] = [
+    (&BindingsType::User, "user"),
+    (&BindingsType::Group, "group"),
+    (&BindingsType::Role, "role"),
+];
+
+/// UNOWNED are the kinds of subject of no account, the binding belongs to
+/// the account of its policy
+const UNOWNED: [u8; 2] = [
+    BindingsType::Application as u8,
+    BindingsType::FederatedUser as u8,
+];
+
+impl ListParams {
+    /// SORTABLE are the columns order_by may name
+    pub const SORTABLE: &'static [&'static str] = &[
+        "id",
+        "policy_id",
+        "bindings_type",
+        "bindings_id",
+        "created_at",
+        "updated_at",
+    ];
+
+    /// filter is the WHERE clause of the sql stores
+    pub(crate) fn filter(&self) -> Result<Filter> {
+        let mut filter = Filter::default()
+            .id("id", self.id.as_deref())?
+            .id("policy_id", self.policy_id.as_deref())?
+            .eq("bindings_type", self.bindings_type.as_ref().map(u8::from))
+            .eq("bindings_id", self.bindings_id.as_ref())
+            .lt("expires_at", self.expires_before);
+        if let Some(account_id) = &self.account_id {
+            let mut owners = Vec::new();
+            for (bindings_type, table) in OWNERS {
+                owners.push(
+                    Filter::default()
+                        .eq("bindings_type", Some(u8::from(bindings_type)))
+                        .in_text_select(
+                            "bindings_id",
+                            table,
+                            "id",
+                            Filter::default()
+                                .id("account_id", Some(account_id))?,
+                        ),
+                );
+            }
+            owners.push(
+                Filter::default()
+                    .one_of("bindings_type", UNOWNED.map(Param::from).into())
+                    .in_select(
+                        "policy_id",
+                        "policy",
+                        "id",
+                        Filter::default().id("account_id", Some(account_id))?,
+                    ),
+            );
+            filter = filter.any(owners);
+        }
+        Ok(filter)
+    }
+}
+
+fn check_content(content: &Content) -> Result<(), ValidationError> {
+    check_window(content.not_before, content.expires_at)
+}
+
+impl Keyed for PolicyBinding {
+    fn key(&self, column: &str) -> Option<Param> {
+        match column {
+            "id" => Some(Param::id(&self.id)),
+            "policy_id" => Some(Param::id(&self.policy_id)),
+            "bindings_type" => Some(u8::from(&self.bindings_type).into()),
+            "bindings_id" => Some((&self.bindings_id).into()),
+            "created_at" => Some(self.created_at.into()),
+            "updated_at" => Some(self.updated_at.into()),
+            _ => None,
+        }
+    }
+}
+
 #[derive(
     Debug, Default, Deserialize, Serialize, Clone, PartialEq, utoipa::ToSchema,
 )]
@@ -47,6 +151,9 @@ pub enum BindingsType {
     User = 1,
     Group = 2,
     Role = 3,
+    /// the bindings id of applications and federated users is their subject
+    Application = 4,
+    FederatedUser = 5,
 }
 
 impl From<&BindingsType> for u8 {
@@ -55,6 +162,8 @@ impl From<&BindingsType> for u8 {
             BindingsType::User => 1,
             BindingsType::Group => 2,
             BindingsType::Role => 3,
+            BindingsType::Application => 4,
+            BindingsType::FederatedUser => 5,
         }
     }
 }
@@ -65,7 +174,19 @@ impl From<u8> for BindingsType {
             1 => BindingsType::User,
             2 => BindingsType::Group,
             3 => BindingsType::Role,
+            4 => BindingsType::Application,
+            5 => BindingsType::FederatedUser,
             _ => BindingsType::User,
         }
     }
 }
+
+impl From<PrincipalType> for BindingsType {
+    fn from(principal_type: PrincipalType) -> Self {
+        match principal_type {
+            PrincipalType::User => BindingsType::User,
+            PrincipalType::Application => BindingsType::Application,
+            PrincipalType::FederatedUser => BindingsType::FederatedUser,
+        }
+    }
+}
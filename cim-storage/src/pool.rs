@@ -2,12 +2,64 @@ use anyhow::Context;
 use sqlx::{mysql::MySqlPoolOptions, MySqlPool};
 use tracing::info;
 
+use crate::Tx;
+
+/// Pool is the connection pool of the backend chosen by the scheme of
+/// the database url, `mysql://`, `sqlite:`, `postgres://` or `memory://`
+#[derive(Clone, Debug)]
+pub enum Pool {
+    MySql(MySqlPool),
+    #[cfg(feature = "sqlite")]
+    Sqlite(sqlx::SqlitePool),
+    #[cfg(feature = "postgres")]
+    Postgres(sqlx::PgPool),
+    Memory(crate::memory::Database),
+}
+
+impl Pool {
+    /// begin starts a transaction, build the stores from `Tx::conn` to run
+    /// them in it
+    pub async fn begin(&self) -> cim_slo::Result<Tx> {
+        Tx::begin(self).await
+    }
+}
+
 pub async fn connection_manager(
     uri: &str,
     max_size: u32,
     min_idle: u32,
     run_migrations: bool,
-) -> anyhow::Result<MySqlPool> {
+) -> anyhow::Result<Pool> {
+    let scheme = uri.split_once(':').map(|(v, _)| v).unwrap_or_default();
+    match scheme {
+        "mysql" | "mariadb" => {
+            mysql_pool(uri, max_size, min_idle, run_migrations).await
+        }
+        "sqlite" => {
+            #[cfg(feature = "sqlite")]
+            return sqlite_pool(uri, max_size, min_idle, run_migrations).await;
+            #[cfg(not(feature = "sqlite"))]
+            anyhow::bail!("sqlite backend requires the `sqlite` feature")
+        }
+        "postgres" | "postgresql" => {
+            #[cfg(feature = "postgres")]
+            return postgres_pool(uri, max_size, min_idle, run_migrations)
+                .await;
+            #[cfg(not(feature = "postgres"))]
+            anyhow::bail!("postgres backend requires the `postgres` feature")
+        }
+        // nothing is persisted, so migrations have nothing to do
+        "memory" => Ok(Pool::Memory(Default::default())),
+        _ => anyhow::bail!("unsupported database url scheme `{}`", scheme),
+    }
+}
+
+async fn mysql_pool(
+    uri: &str,
+    max_size: u32,
+    min_idle: u32,
+    run_migrations: bool,
+) -> anyhow::Result<Pool> {
     let pool = MySqlPoolOptions::new()
         .max_connections(max_size)
         .min_connections(min_idle)
@@ -23,5 +75,77 @@ pub async fn connection_manager(
             .context("error while running database migrations")?;
     }
 
-    Ok(pool)
+    Ok(Pool::MySql(pool))
+}
+
+#[cfg(feature = "sqlite")]
+async fn sqlite_pool(
+    uri: &str,
+    max_size: u32,
+    min_idle: u32,
+    run_migrations: bool,
+) -> anyhow::Result<Pool> {
+    use std::str::FromStr;
+
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
+    let options = SqliteConnectOptions::from_str(uri)
+        .context("invalid sqlite database url")?
+        .create_if_missing(true);
+    // an in-memory database lives as long as a connection to it, so keep a
+    // single one alive for the whole lifetime of the pool. A transaction
+    // holds it until it ends: what is read meanwhile goes through the
+    // stores of the transaction, a read from the pool would wait on it
+    let pool_options = if uri.contains(":memory:") {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+    } else {
+        SqlitePoolOptions::new()
+            .max_connections(max_size)
+            .min_connections(min_idle)
+    };
+    let pool = pool_options
+        .connect_with(options)
+        .await
+        .context("error while initializing the database connection pool")?;
+
+    if run_migrations {
+        info!("migrations enabled, running...");
+        sqlx::migrate!("./migrations/sqlite")
+            .run(&pool)
+            .await
+            .context("error while running database migrations")?;
+    }
+
+    Ok(Pool::Sqlite(pool))
+}
+
+#[cfg(feature = "postgres")]
+async fn postgres_pool(
+    uri: &str,
+    max_size: u32,
+    min_idle: u32,
+    run_migrations: bool,
+) -> anyhow::Result<Pool> {
+    use sqlx::postgres::PgPoolOptions;
+
+    let pool = PgPoolOptions::new()
+        .max_connections(max_size)
+        .min_connections(min_idle)
+        .connect(uri)
+        .await
+        .context("error while initializing the database connection pool")?;
+
+    if run_migrations {
+        info!("migrations enabled, running...");
+        sqlx::migrate!("./migrations/postgres")
+            .run(&pool)
+            .await
+            .context("error while running database migrations")?;
+    }
+
+    Ok(Pool::Postgres(pool))
 }
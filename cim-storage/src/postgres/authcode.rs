@@ -0,0 +1,206 @@
+use async_trait::async_trait;
+use serde_json::value::RawValue;
+use sqlx::{types::Json, Postgres, Row};
+
+use cim_slo::{errors, Result};
+
+use crate::{authcode::AuthCode, Claim, Conn, Interface, List};
+
+#[derive(Clone, Debug)]
+pub struct AuthCodeImpl {
+    conn: Conn<Postgres>,
+}
+
+impl AuthCodeImpl {
+    pub fn new(pool: impl Into<Conn<Postgres>>) -> Self {
+        Self { conn: pool.into() }
+    }
+}
+
+#[async_trait]
+impl Interface for AuthCodeImpl {
+    type T = AuthCode;
+    type L = ();
+
+    #[tracing::instrument]
+    async fn put(&self, content: &Self::T) -> Result<()> {
+        let client_id = content
+            .client_id
+            .parse::<i64>()
+            .map_err(|err| errors::bad_request(&err))?;
+
+        sqlx::query(
+            r#"INSERT INTO "auth_code"
+            ("id","client_id","scopes","nonce","redirect_uri","code_challenge","code_challenge_method",
+            "claim","connector_id","connector_data","expiry")
+            VALUES($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11)
+            ON CONFLICT ("id") DO UPDATE SET
+            "client_id" = EXCLUDED."client_id",
+            "scopes" = EXCLUDED."scopes",
+            "nonce" = EXCLUDED."nonce",
+            "redirect_uri" = EXCLUDED."redirect_uri",
+            "code_challenge" = EXCLUDED."code_challenge",
+            "code_challenge_method" = EXCLUDED."code_challenge_method",
+            "claim" = EXCLUDED."claim",
+            "connector_id" = EXCLUDED."connector_id",
+            "connector_data" = EXCLUDED."connector_data",
+            "expiry" = EXCLUDED."expiry",
+            "updated_at" = CURRENT_TIMESTAMP;"#,
+        )
+        .bind(&content.id)
+        .bind(client_id)
+        .bind(Json(&content.scopes))
+        .bind(&content.nonce)
+        .bind(&content.redirect_uri)
+        .bind(&content.code_challenge)
+        .bind(&content.code_challenge_method)
+        .bind(Json(&content.claim))
+        .bind(&content.connector_id)
+        .bind(content.connector_data.as_ref().map(|v| v.to_string()))
+        .bind(content.expiry)
+        .execute(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        sqlx::query(
+            r#"DELETE FROM "auth_code"
+            WHERE id = $1;"#,
+        )
+        .bind(&input.id)
+        .execute(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        let row = match sqlx::query(
+            r#"SELECT "id","client_id","scopes","nonce","redirect_uri","code_challenge","code_challenge_method",
+            "claim","connector_id","connector_data","expiry","created_at","updated_at"
+            FROM "auth_code"
+            WHERE id = $1;"#)
+        .bind(&output.id)
+        .fetch_optional(&mut *self.conn.acquire().await?)
+        .await
+        {
+            Ok(v) => match v {
+                Some(value) => Ok(value),
+                None => Err(errors::not_found("no rows")),
+            },
+            Err(err) => Err(errors::any(err)),
+        }?;
+
+        // the id is a char column, blank padded to its length
+        output.id = row
+            .try_get::<String, _>("id")
+            .map_err(errors::any)?
+            .trim_end()
+            .to_owned();
+        output.client_id = row
+            .try_get::<i64, _>("client_id")
+            .map_err(errors::any)?
+            .to_string();
+        output.scopes = row
+            .try_get::<Json<Vec<String>>, _>("scopes")
+            .map_err(errors::any)?
+            .0;
+        output.nonce = row.try_get("nonce").map_err(errors::any)?;
+        output.redirect_uri =
+            row.try_get("redirect_uri").map_err(errors::any)?;
+        output.code_challenge =
+            row.try_get("code_challenge").map_err(errors::any)?;
+        output.code_challenge_method =
+            row.try_get("code_challenge_method").map_err(errors::any)?;
+        output.claim = row
+            .try_get::<Json<Claim>, _>("claim")
+            .map_err(errors::any)?
+            .0;
+        output.connector_id =
+            row.try_get("connector_id").map_err(errors::any)?;
+        output.connector_data = row
+            .try_get::<Option<String>, _>("connector_data")
+            .map_err(errors::any)?
+            .map(|v| RawValue::from_string(v).unwrap());
+        output.expiry = row.try_get("expiry").map_err(errors::any)?;
+        output.created_at = row.try_get("created_at").map_err(errors::any)?;
+        output.updated_at = row.try_get("updated_at").map_err(errors::any)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        _opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        let rows = sqlx::query(
+            r#"SELECT "id","client_id","scopes","nonce","redirect_uri","code_challenge","code_challenge_method",
+            "claim","connector_id","connector_data","expiry","created_at","updated_at"
+            FROM "auth_code";"#,
+        )
+        .fetch_all(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+        for row in rows.iter() {
+            output.data.push(Self::T {
+                id: row
+                    .try_get::<String, _>("id")
+                    .map_err(errors::any)?
+                    .trim_end()
+                    .to_owned(),
+                client_id: row
+                    .try_get::<i64, _>("client_id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                scopes: row
+                    .try_get::<Json<Vec<String>>, _>("scopes")
+                    .map_err(errors::any)?
+                    .0,
+                nonce: row.try_get("nonce").map_err(errors::any)?,
+                redirect_uri: row
+                    .try_get("redirect_uri")
+                    .map_err(errors::any)?,
+                code_challenge: row
+                    .try_get("code_challenge")
+                    .map_err(errors::any)?,
+                code_challenge_method: row
+                    .try_get("code_challenge_method")
+                    .map_err(errors::any)?,
+                claim: row
+                    .try_get::<Json<Claim>, _>("claim")
+                    .map_err(errors::any)?
+                    .0,
+                connector_id: row
+                    .try_get("connector_id")
+                    .map_err(errors::any)?,
+                connector_data: row
+                    .try_get::<Option<String>, _>("connector_data")
+                    .map_err(errors::any)?
+                    .map(|v| RawValue::from_string(v).unwrap()),
+                expiry: row.try_get("expiry").map_err(errors::any)?,
+                created_at: row.try_get("created_at").map_err(errors::any)?,
+                updated_at: row.try_get("updated_at").map_err(errors::any)?,
+            });
+        }
+        output.total = output.data.len() as i64;
+        Ok(())
+    }
+
+    // auth codes are deleted for good, so unscoped counts the same rows
+    #[tracing::instrument]
+    async fn count(&self, _opts: &Self::L, _unscoped: bool) -> Result<i64> {
+        sqlx::query(r#"SELECT COUNT(*) as count FROM "auth_code";"#)
+            .fetch_one(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?
+            .try_get("count")
+            .map_err(errors::any)
+    }
+}
@@ -0,0 +1,285 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use serde_json::value::RawValue;
+use sqlx::{Postgres, QueryBuilder, Row};
+
+use cim_slo::{errors, Result};
+
+use super::{
+    lock, parse_id, purge, restore, soft_delete, write_query, write_row,
+};
+use crate::{
+    connector::{Connector, ListParams},
+    discarded, stored, Conn, Interface, List,
+};
+
+#[derive(Clone, Debug)]
+pub struct ConnectorImpl {
+    conn: Conn<Postgres>,
+}
+
+impl ConnectorImpl {
+    pub fn new(pool: impl Into<Conn<Postgres>>) -> Self {
+        Self { conn: pool.into() }
+    }
+
+    /// write puts content, when resource_version is given only if the
+    /// stored one is still at it
+    async fn write(
+        &self,
+        content: &Connector,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
+        let query = write_query(
+            "connector",
+            &[
+                "type",
+                "name",
+                "response_version",
+                "config",
+                "connector_data",
+            ],
+            resource_version.is_some(),
+        );
+        let query = sqlx::query(&query)
+            .bind(parse_id(&content.id)?)
+            .bind(&content.connector_type)
+            .bind(&content.name)
+            .bind(&content.response_version)
+            .bind(&content.config)
+            .bind(content.connector_data.as_ref().map(|v| v.to_string()));
+        write_row(
+            &self.conn,
+            query,
+            "connector",
+            &content.id,
+            resource_version,
+        )
+        .await
+    }
+
+    /// select lists the soft deleted rows when deleted, the live ones
+    /// otherwise
+    async fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<Connector>,
+        deleted: bool,
+    ) -> Result<()> {
+        let filter = opts.filter()?.deleted(deleted);
+        // 查询total
+        if !opts.pagination.count_disable {
+            let mut builder = QueryBuilder::new(
+                r#"SELECT COUNT(*) as count FROM "connector""#,
+            );
+            filter.push(&mut builder);
+            output.total = builder
+                .build()
+                .fetch_one(&mut *self.conn.acquire().await?)
+                .await
+                .map_err(errors::any)?
+                .try_get("count")
+                .map_err(errors::any)?;
+        }
+
+        // 查询列表
+        output.limit = opts.pagination.limit;
+        output.offset = opts.pagination.offset;
+
+        let mut builder = QueryBuilder::new(
+            r#"SELECT "id","type","name","response_version","config","connector_data",
+                "resource_version"
+            FROM "connector""#,
+        );
+        opts.pagination
+            .seek(filter, ListParams::SORTABLE)?
+            .push(&mut builder);
+        opts.pagination.push(&mut builder, ListParams::SORTABLE)?;
+        let rows = builder
+            .build()
+            .fetch_all(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?;
+        for row in rows.iter() {
+            output.data.push(Connector {
+                id: row
+                    .try_get::<i64, _>("id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                connector_type: row.try_get("type").map_err(errors::any)?,
+                name: row.try_get("name").map_err(errors::any)?,
+                response_version: row
+                    .try_get("response_version")
+                    .map_err(errors::any)?,
+                config: row.try_get("config").map_err(errors::any)?,
+                connector_data: row
+                    .try_get::<Option<String>, _>("connector_data")
+                    .map_err(errors::any)?
+                    .map(|v| RawValue::from_string(v).unwrap()),
+                resource_version: row
+                    .try_get::<i64, _>("resource_version")
+                    .map_err(errors::any)?
+                    as u64,
+            });
+        }
+        output.next_cursor = opts
+            .pagination
+            .next_cursor(&output.data, ListParams::SORTABLE)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Interface for ConnectorImpl {
+    type T = Connector;
+    type L = ListParams;
+
+    #[tracing::instrument]
+    async fn put(&self, content: &Self::T) -> Result<()> {
+        self.write(content, None).await
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        soft_delete(&self.conn, "connector", &input.id, None).await
+    }
+
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        let id = output
+            .id
+            .parse::<i64>()
+            .map_err(|err| errors::bad_request(&err))?;
+
+        let row = match sqlx::query(
+            r#"SELECT "id","type","name","response_version","config","connector_data",
+                "resource_version"
+            FROM "connector"
+            WHERE id = $1 AND deleted = 0;"#,
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.conn.acquire().await?)
+        .await
+        {
+            Ok(v) => match v {
+                Some(value) => Ok(value),
+                None => Err(errors::not_found("no rows")),
+            },
+            Err(err) => Err(errors::any(err)),
+        }?;
+        output.connector_data = row
+            .try_get::<Option<String>, _>("connector_data")
+            .map_err(errors::any)?
+            .map(|v| RawValue::from_string(v).unwrap());
+
+        output.id = row
+            .try_get::<i64, _>("id")
+            .map_err(errors::any)?
+            .to_string();
+        output.connector_type = row.try_get("type").map_err(errors::any)?;
+        output.name = row.try_get("name").map_err(errors::any)?;
+        output.response_version =
+            row.try_get("response_version").map_err(errors::any)?;
+        output.config = row.try_get("config").map_err(errors::any)?;
+        output.resource_version = row
+            .try_get::<i64, _>("resource_version")
+            .map_err(errors::any)? as u64;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, false).await
+    }
+
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let mut filter = opts.filter()?;
+        if !unscoped {
+            filter = filter.not_deleted();
+        }
+        let mut builder =
+            QueryBuilder::new(r#"SELECT COUNT(*) as count FROM "connector""#);
+        filter.push(&mut builder);
+        builder
+            .build()
+            .fetch_one(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?
+            .try_get("count")
+            .map_err(errors::any)
+    }
+
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.write(input, Some(resource_version)).await
+    }
+
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "connector", &input.id).await?;
+                let previous = stored(&store, input).await?;
+                store.write(input, resource_version).await?;
+                Ok(previous)
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "connector", &input.id).await?;
+                discarded(&store, input, resource_version).await
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        soft_delete(&self.conn, "connector", &input.id, Some(resource_version))
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true).await
+    }
+
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        restore(&self.conn, "connector", &input.id).await
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        purge(&self.conn, "connector", before).await
+    }
+}
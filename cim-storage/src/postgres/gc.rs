@@ -0,0 +1,62 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use sqlx::Postgres;
+
+use cim_slo::{errors, Result};
+
+use crate::{gc::GcStore, Conn};
+
+#[derive(Clone, Debug)]
+pub struct GcImpl {
+    conn: Conn<Postgres>,
+}
+
+impl GcImpl {
+    pub fn new(pool: impl Into<Conn<Postgres>>) -> Self {
+        Self { conn: pool.into() }
+    }
+}
+
+#[async_trait]
+impl GcStore for GcImpl {
+    #[tracing::instrument]
+    async fn purge_auth_requests(&self, now: i64) -> Result<u64> {
+        let result =
+            sqlx::query(r#"DELETE FROM "auth_request" WHERE "expiry" < $1;"#)
+                .bind(now)
+                .execute(&mut *self.conn.acquire().await?)
+                .await
+                .map_err(errors::any)?;
+        Ok(result.rows_affected())
+    }
+
+    #[tracing::instrument]
+    async fn purge_auth_codes(&self, now: i64) -> Result<u64> {
+        let result =
+            sqlx::query(r#"DELETE FROM "auth_code" WHERE "expiry" < $1;"#)
+                .bind(now)
+                .execute(&mut *self.conn.acquire().await?)
+                .await
+                .map_err(errors::any)?;
+        Ok(result.rows_affected())
+    }
+
+    #[tracing::instrument]
+    async fn purge_refresh_tokens(
+        &self,
+        created_before: Option<NaiveDateTime>,
+        used_before: Option<NaiveDateTime>,
+    ) -> Result<u64> {
+        // comparing with a null bound is never true
+        let result = sqlx::query(
+            r#"DELETE FROM "refresh_token"
+            WHERE "created_at" < $1 OR "last_used_at" < $2;"#,
+        )
+        .bind(created_before)
+        .bind(used_before)
+        .execute(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,390 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use sqlx::{types::Json, Postgres, QueryBuilder, Row};
+
+use cim_slo::{errors, Result};
+
+use super::{
+    label, lock, parse_id, parse_option_id, purge, restore, soft_delete,
+    write_query, write_row,
+};
+use crate::{
+    discarded,
+    group::{Group, ListParams},
+    label::Labels,
+    stored, Conn, Interface, List,
+};
+
+#[derive(Clone, Debug)]
+pub struct GroupImpl {
+    conn: Conn<Postgres>,
+}
+
+impl GroupImpl {
+    pub fn new(pool: impl Into<Conn<Postgres>>) -> Self {
+        Self { conn: pool.into() }
+    }
+
+    /// select lists the rows matching opts, the soft deleted ones when
+    /// deleted
+    async fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<Group>,
+        deleted: bool,
+    ) -> Result<()> {
+        let filter = opts.filter()?.deleted(deleted);
+        // 查询total
+        if !opts.pagination.count_disable {
+            let mut builder =
+                QueryBuilder::new(r#"SELECT COUNT(*) as count FROM "group""#);
+            filter.push(&mut builder);
+            output.total = builder
+                .build()
+                .fetch_one(&mut *self.conn.acquire().await?)
+                .await
+                .map_err(errors::any)?
+                .try_get("count")
+                .map_err(errors::any)?;
+        }
+
+        // 查询列表
+        output.limit = opts.pagination.limit;
+        output.offset = opts.pagination.offset;
+
+        let mut builder = QueryBuilder::new(
+            r#"SELECT "id","account_id","name","desc","parent_id",
+                "resource_version","labels","annotations","created_at","updated_at"
+                FROM "group""#,
+        );
+        opts.pagination
+            .seek(filter, ListParams::SORTABLE)?
+            .push(&mut builder);
+        opts.pagination.push(&mut builder, ListParams::SORTABLE)?;
+        let rows = builder
+            .build()
+            .fetch_all(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?;
+        for row in rows.iter() {
+            output.data.push(Group {
+                id: row
+                    .try_get::<i64, _>("id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                account_id: row
+                    .try_get::<i64, _>("account_id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                name: row.try_get("name").map_err(errors::any)?,
+                desc: row.try_get("desc").map_err(errors::any)?,
+                parent_id: row
+                    .try_get::<Option<i64>, _>("parent_id")
+                    .map_err(errors::any)?
+                    .map(|v| v.to_string()),
+                resource_version: row
+                    .try_get::<i64, _>("resource_version")
+                    .map_err(errors::any)?
+                    as u64,
+                labels: row
+                    .try_get::<Json<Labels>, _>("labels")
+                    .map_err(errors::any)?
+                    .0,
+                annotations: row
+                    .try_get::<Json<Labels>, _>("annotations")
+                    .map_err(errors::any)?
+                    .0,
+                created_at: row.try_get("created_at").map_err(errors::any)?,
+                updated_at: row.try_get("updated_at").map_err(errors::any)?,
+            });
+        }
+
+        output.next_cursor = opts
+            .pagination
+            .next_cursor(&output.data, ListParams::SORTABLE)?;
+        Ok(())
+    }
+
+    /// nest checks group id can be nested in group parent_id, which must not
+    /// be id or a group nested in it
+    async fn nest(&self, id: i64, parent_id: i64) -> Result<()> {
+        let count: i64 = sqlx::query(
+            r#"WITH RECURSIVE "ancestor" ("id","parent_id") AS (
+                SELECT "id","parent_id" FROM "group" WHERE "id" = $1
+                UNION
+                SELECT g."id",g."parent_id" FROM "group" g
+                JOIN "ancestor" a ON g."id" = a."parent_id"
+            )
+            SELECT COUNT(*) as count FROM "ancestor" WHERE "id" = $2;"#,
+        )
+        .bind(parent_id)
+        .bind(id)
+        .fetch_one(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?
+        .try_get("count")
+        .map_err(errors::any)?;
+        if count != 0 {
+            return Err(errors::bad_request(&format!(
+                "group {id} can't be nested in group {parent_id}, it contains it"
+            )));
+        }
+        Ok(())
+    }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it
+    async fn write(
+        &self,
+        input: &Group,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
+        let id = parse_id(&input.id)?;
+        let parent_id = parse_option_id(&input.parent_id)?;
+        if let Some(parent_id) = parent_id {
+            self.nest(id, parent_id).await?;
+        }
+        let query = write_query(
+            "group",
+            &[
+                "account_id",
+                "name",
+                "desc",
+                "parent_id",
+                "labels",
+                "annotations",
+            ],
+            resource_version.is_some(),
+        );
+        let query = sqlx::query(&query)
+            .bind(id)
+            .bind(parse_id(&input.account_id)?)
+            .bind(&input.name)
+            .bind(&input.desc)
+            .bind(parent_id)
+            .bind(Json(&input.labels))
+            .bind(Json(&input.annotations));
+        write_row(&self.conn, query, "group", &input.id, resource_version)
+            .await?;
+        label(&self.conn, "group", &input.id, &input.labels).await?;
+
+        Ok(())
+    }
+
+    /// remove soft deletes input, when resource_version is given only
+    /// if the stored one is still at it
+    async fn remove(
+        &self,
+        input: &Group,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
+        let id = input
+            .id
+            .parse::<i64>()
+            .map_err(|err| errors::bad_request(&err))?;
+        if sqlx::query(
+            r#"SELECT COUNT(*) as count FROM "group_user" WHERE "group_id" = $1 AND "deleted" = 0"#,
+        )
+        .bind(id)
+        .fetch_one(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?.try_get::<i64,_>("count").map_err(errors::any)?!=0{
+            return Err(errors::forbidden(&"can't delete group, because it is used by user".to_string()));
+        };
+        if sqlx::query(
+            r#"SELECT COUNT(*) as count FROM "policy_binding" WHERE "bindings_type" = 2 AND "bindings_id" = $1 AND "deleted" = 0"#,
+        )
+        .bind(id.to_string())
+        .fetch_one(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?.try_get::<i64,_>("count").map_err(errors::any)?!=0{
+            return Err(errors::forbidden(&"can't delete group, because it is attached by policy".to_string()));
+        };
+        if sqlx::query(
+            r#"SELECT COUNT(*) as count FROM "group" WHERE "parent_id" = $1 AND "deleted" = 0"#,
+        )
+        .bind(id)
+        .fetch_one(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?.try_get::<i64,_>("count").map_err(errors::any)?!=0{
+            return Err(errors::forbidden(&"can't delete group, because it has nested groups".to_string()));
+        };
+        soft_delete(&self.conn, "group", &input.id, resource_version).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Interface for GroupImpl {
+    type T = Group;
+    type L = ListParams;
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        // the row and its labels are indexed in the same transaction
+        self.conn
+            .scoped(
+                |conn| async move { Self { conn }.write(input, None).await },
+            )
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        self.remove(input, None).await
+    }
+
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        let id = output
+            .id
+            .parse::<i64>()
+            .map_err(|err| errors::bad_request(&err))?;
+        let row = match sqlx::query(
+            r#"SELECT "id","account_id","name","desc","parent_id",
+                "resource_version","labels","annotations","created_at","updated_at"
+                FROM "group"
+                WHERE id = $1 AND "deleted" = 0;"#,
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.conn.acquire().await?)
+        .await
+        {
+            Ok(v) => match v {
+                Some(value) => Ok(value),
+                None => Err(errors::not_found("no rows")),
+            },
+            Err(err) => Err(errors::any(err)),
+        }?;
+
+        output.id = row
+            .try_get::<i64, _>("id")
+            .map_err(errors::any)?
+            .to_string();
+        output.account_id = row
+            .try_get::<i64, _>("account_id")
+            .map_err(errors::any)?
+            .to_string();
+        output.name = row.try_get("name").map_err(errors::any)?;
+        output.desc = row.try_get("desc").map_err(errors::any)?;
+        output.parent_id = row
+            .try_get::<Option<i64>, _>("parent_id")
+            .map_err(errors::any)?
+            .map(|v| v.to_string());
+        output.resource_version = row
+            .try_get::<i64, _>("resource_version")
+            .map_err(errors::any)? as u64;
+        output.labels = row
+            .try_get::<Json<Labels>, _>("labels")
+            .map_err(errors::any)?
+            .0;
+        output.annotations = row
+            .try_get::<Json<Labels>, _>("annotations")
+            .map_err(errors::any)?
+            .0;
+        output.created_at = row.try_get("created_at").map_err(errors::any)?;
+        output.updated_at = row.try_get("updated_at").map_err(errors::any)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, false).await
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let mut filter = opts.filter()?;
+        if !unscoped {
+            filter = filter.not_deleted();
+        }
+        let mut builder =
+            QueryBuilder::new(r#"SELECT COUNT(*) as count FROM "group""#);
+        filter.push(&mut builder);
+        builder
+            .build()
+            .fetch_one(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?
+            .try_get("count")
+            .map_err(errors::any)
+    }
+
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.conn
+            .scoped(|conn| async move {
+                Self { conn }.write(input, Some(resource_version)).await
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "group", &input.id).await?;
+                let previous = stored(&store, input).await?;
+                store.write(input, resource_version).await?;
+                Ok(previous)
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "group", &input.id).await?;
+                discarded(&store, input, resource_version).await
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.remove(input, Some(resource_version)).await
+    }
+
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true).await
+    }
+
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        restore(&self.conn, "group", &input.id).await
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        purge(&self.conn, "group", before).await
+    }
+}
@@ -0,0 +1,258 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use jsonwebkey::JsonWebKey;
+use sqlx::{postgres::PgRow, types::Json, Postgres, Row};
+
+use cim_slo::{errors, Result};
+
+use super::{
+    lock, parse_id, purge, restore, soft_delete, write_query, write_row,
+};
+use crate::{
+    discarded,
+    key::{KeyRing, Keys, VerificationKey},
+    stored, Conn, Interface, List,
+};
+
+#[derive(Clone, Debug)]
+pub struct KeysImpl {
+    conn: Conn<Postgres>,
+    ring: KeyRing,
+}
+
+impl KeysImpl {
+    pub fn new(pool: impl Into<Conn<Postgres>>) -> Self {
+        Self {
+            conn: pool.into(),
+            ring: KeyRing::default(),
+        }
+    }
+
+    /// sealed encrypts the signing keys written with ring and decrypts the
+    /// ones read, rows sealed by a key no longer in ring can't be read
+    pub fn sealed(mut self, ring: KeyRing) -> Self {
+        self.ring = ring;
+        self
+    }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it
+    async fn write(
+        &self,
+        input: &Keys,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
+        let query = write_query(
+            "key",
+            &[
+                "verification_keys",
+                "signing_key",
+                "signing_key_pub",
+                "next_rotation",
+            ],
+            resource_version.is_some(),
+        );
+        let query = sqlx::query(&query)
+            .bind(parse_id(&input.id)?)
+            .bind(Json(&input.verification_keys))
+            .bind(Json(self.ring.seal(&input.signing_key)?))
+            .bind(Json(&input.signing_key_pub))
+            .bind(input.next_rotation);
+        write_row(&self.conn, query, "key", &input.id, resource_version).await
+    }
+
+    /// select lists the soft deleted rows when deleted, the live ones
+    /// otherwise
+    async fn select(
+        &self,
+        output: &mut List<Keys>,
+        deleted: bool,
+    ) -> Result<()> {
+        let deleted = match deleted {
+            true => "<> 0",
+            false => "= 0",
+        };
+        let rows = sqlx::query(&format!(
+            r#"SELECT "id","verification_keys","signing_key","signing_key_pub","next_rotation",
+                "resource_version"
+                FROM "key"
+                WHERE "deleted" {deleted};"#
+        ))
+        .fetch_all(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+        for row in rows.iter() {
+            output.data.push(self.keys(row)?);
+        }
+        Ok(())
+    }
+
+    fn keys(&self, row: &PgRow) -> Result<Keys> {
+        Ok(Keys {
+            id: row
+                .try_get::<i64, _>("id")
+                .map_err(errors::any)?
+                .to_string(),
+            signing_key: self.ring.open(
+                row.try_get::<Json<serde_json::Value>, _>("signing_key")
+                    .map_err(errors::any)?
+                    .0,
+            )?,
+            signing_key_pub: row
+                .try_get::<Json<JsonWebKey>, _>("signing_key_pub")
+                .map_err(errors::any)?
+                .0,
+            verification_keys: row
+                .try_get::<Json<Vec<VerificationKey>>, _>("verification_keys")
+                .map_err(errors::any)?
+                .0,
+            next_rotation: row
+                .try_get::<i64, _>("next_rotation")
+                .map_err(errors::any)? as i64,
+            resource_version: row
+                .try_get::<i64, _>("resource_version")
+                .map_err(errors::any)? as u64,
+        })
+    }
+}
+
+#[async_trait]
+impl Interface for KeysImpl {
+    type T = Keys;
+    type L = ();
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        self.write(input, None).await
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        soft_delete(&self.conn, "key", &input.id, None).await
+    }
+
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        let id = output
+            .id
+            .parse::<i64>()
+            .map_err(|err| errors::bad_request(&err))?;
+        let row = match sqlx::query(
+            r#"SELECT "id","verification_keys","signing_key","signing_key_pub","next_rotation",
+            "resource_version"
+            FROM "key"
+            WHERE id = $1 AND "deleted" = 0;"#,
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.conn.acquire().await?)
+        .await
+        {
+            Ok(v) => match v {
+                Some(value) => Ok(value),
+                None => Err(errors::not_found("no rows")),
+            },
+            Err(err) => Err(errors::any(err)),
+        }?;
+        *output = self.keys(&row)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        _opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(output, false).await
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, _opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let sql = if unscoped {
+            r#"SELECT COUNT(*) as count FROM "key";"#
+        } else {
+            r#"SELECT COUNT(*) as count FROM "key" WHERE "deleted" = 0;"#
+        };
+        sqlx::query(sql)
+            .fetch_one(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?
+            .try_get("count")
+            .map_err(errors::any)
+    }
+
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.write(input, Some(resource_version)).await
+    }
+
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self {
+                    conn,
+                    ring: self.ring.clone(),
+                };
+                lock(&store.conn, "key", &input.id).await?;
+                let previous = stored(&store, input).await?;
+                store.write(input, resource_version).await?;
+                Ok(previous)
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self {
+                    conn,
+                    ring: self.ring.clone(),
+                };
+                lock(&store.conn, "key", &input.id).await?;
+                discarded(&store, input, resource_version).await
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        soft_delete(&self.conn, "key", &input.id, Some(resource_version)).await
+    }
+
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        _opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(output, true).await
+    }
+
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        restore(&self.conn, "key", &input.id).await
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        purge(&self.conn, "key", before).await
+    }
+}
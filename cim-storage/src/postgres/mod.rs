@@ -0,0 +1,1483 @@
+mod audit;
+mod authcode;
+mod authrequest;
+mod changelog;
+mod client;
+mod connector;
+mod gc;
+mod group;
+mod group_user;
+mod key;
+mod offlinesession;
+mod policy;
+mod policy_binding;
+mod refresh_token;
+mod relation_tuple;
+mod role;
+mod role_binding;
+mod user;
+
+use chrono::NaiveDateTime;
+use sqlx::{postgres::PgArguments, query::Query, Postgres, QueryBuilder};
+
+use crate::{label, Conn};
+
+pub use audit::AuditImpl;
+pub use authcode::AuthCodeImpl;
+pub use authrequest::AuthRequestImpl;
+pub use changelog::ChangelogImpl;
+pub use client::ClientImpl;
+pub use connector::ConnectorImpl;
+pub use gc::GcImpl;
+pub use group::GroupImpl;
+pub use group_user::GroupUserImpl;
+pub use key::KeysImpl;
+pub use offlinesession::OfflineSessionImpl;
+pub use policy::PolicyImpl;
+pub use policy_binding::PolicyBindingImpl;
+pub use refresh_token::RefreshTokenImpl;
+pub use relation_tuple::RelationTupleImpl;
+pub use role::RoleImpl;
+pub use role_binding::RoleBindingImpl;
+pub use user::UserImpl;
+
+/// parse_id converts the string id of an entity into the BIGINT column,
+/// postgres doesn't cast text parameters implicitly like mysql
+fn parse_id(id: &str) -> cim_slo::Result<i64> {
+    id.parse().map_err(|err| cim_slo::errors::bad_request(&err))
+}
+
+fn parse_option_id(id: &Option<String>) -> cim_slo::Result<Option<i64>> {
+    match id {
+        Some(v) => parse_id(v).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// write_query writes row `id` of table with columns, binding the id then
+/// the columns. The resource_version is bumped from the stored one and
+/// created_at is kept, a soft deleted row is left alone. When guarded only
+/// the live row still at the resource_version bound last is written
+fn write_query(table: &str, columns: &[&str], guarded: bool) -> String {
+    if guarded {
+        let set: Vec<_> = columns
+            .iter()
+            .enumerate()
+            .map(|(i, v)| format!(r#""{v}" = ${}"#, i + 2))
+            .collect();
+        format!(
+            r#"UPDATE "{table}" SET {},
+                "resource_version" = "resource_version" + 1,
+                "updated_at" = CURRENT_TIMESTAMP
+                WHERE "id" = $1 AND "resource_version" = ${}
+                AND "deleted" = 0;"#,
+            set.join(","),
+            columns.len() + 2
+        )
+    } else {
+        let names: Vec<_> =
+            columns.iter().map(|v| format!(r#""{v}""#)).collect();
+        let values: Vec<_> =
+            (0..columns.len()).map(|i| format!("${}", i + 2)).collect();
+        let set: Vec<_> = columns
+            .iter()
+            .map(|v| format!(r#""{v}" = EXCLUDED."{v}""#))
+            .collect();
+        format!(
+            r#"INSERT INTO "{table}" ("id",{},"resource_version")
+                VALUES($1,{},1)
+                ON CONFLICT ("id") DO UPDATE SET {},
+                "resource_version" = "{table}"."resource_version" + 1,
+                "updated_at" = CURRENT_TIMESTAMP
+                WHERE "{table}"."deleted" = 0;"#,
+            names.join(","),
+            values.join(","),
+            set.join(",")
+        )
+    }
+}
+
+/// write_row runs query built on write_query, it conflicts when the row
+/// isn't at resource_version any more or is soft deleted
+async fn write_row<'q>(
+    conn: &Conn<Postgres>,
+    query: Query<'q, Postgres, PgArguments>,
+    table: &str,
+    id: &str,
+    resource_version: Option<u64>,
+) -> cim_slo::Result<()> {
+    let query = match resource_version {
+        Some(v) => query.bind(v as i64),
+        None => query,
+    };
+    let result = query
+        .execute(&mut *conn.acquire().await?)
+        .await
+        .map_err(cim_slo::errors::any)?;
+    if result.rows_affected() != 0 {
+        return Ok(());
+    }
+    Err(match resource_version {
+        Some(v) => cim_slo::errors::conflict(&format!(
+            "{table} {id} is not at resource version {v}"
+        )),
+        None => cim_slo::errors::conflict(&format!(
+            "{table} {id} is deleted, it can only be restored"
+        )),
+    })
+}
+
+/// soft_delete marks row id of table deleted, when resource_version is
+/// given only if it is still at it, bumping it
+async fn soft_delete(
+    conn: &Conn<Postgres>,
+    table: &str,
+    id: &str,
+    resource_version: Option<u64>,
+) -> cim_slo::Result<()> {
+    let guard = match resource_version {
+        Some(_) => r#"AND "resource_version" = $2"#,
+        None => "",
+    };
+    let query = format!(
+        r#"UPDATE "{table}" SET "deleted" = "id",
+            "deleted_at" = CURRENT_TIMESTAMP,
+            "resource_version" = "resource_version" + 1
+            WHERE "id" = $1 {guard} AND "deleted" = 0;"#
+    );
+    let query = sqlx::query(&query).bind(parse_id(id)?);
+    let query = match resource_version {
+        Some(v) => query.bind(v as i64),
+        None => query,
+    };
+    let result = query
+        .execute(&mut *conn.acquire().await?)
+        .await
+        .map_err(cim_slo::errors::any)?;
+    match resource_version {
+        Some(v) if result.rows_affected() == 0 => {
+            Err(cim_slo::errors::conflict(&format!(
+                "{table} {id} is not at resource version {v}"
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// lock locks row id of table until the transaction of conn ends, so that
+/// no other write lands in between. An id without a row yet is locked by
+/// an advisory lock, the writers of a new row queue on it too
+async fn lock(
+    conn: &Conn<Postgres>,
+    table: &str,
+    id: &str,
+) -> cim_slo::Result<()> {
+    sqlx::query(&format!(
+        r#"SELECT pg_advisory_xact_lock(hashtext($1)),
+            (SELECT 1 FROM "{table}" WHERE "id" = $2 FOR UPDATE);"#
+    ))
+    .bind(format!("{table}/{id}"))
+    .bind(parse_id(id)?)
+    .fetch_optional(&mut *conn.acquire().await?)
+    .await
+    .map_err(cim_slo::errors::any)?;
+    Ok(())
+}
+
+/// restore undeletes row id of table, bumping its resource_version. It
+/// conflicts with a live row holding the same unique columns
+async fn restore(
+    conn: &Conn<Postgres>,
+    table: &str,
+    id: &str,
+) -> cim_slo::Result<()> {
+    let result = sqlx::query(&format!(
+        r#"UPDATE "{table}" SET "deleted" = 0, "deleted_at" = NULL,
+            "resource_version" = "resource_version" + 1
+            WHERE "id" = $1 AND "deleted" <> 0;"#
+    ))
+    .bind(parse_id(id)?)
+    .execute(&mut *conn.acquire().await?)
+    .await
+    .map_err(|err| match err.as_database_error() {
+        Some(v) if v.is_unique_violation() => cim_slo::errors::conflict(
+            &format!("{table} {id} is taken by a live one"),
+        ),
+        _ => cim_slo::errors::any(err),
+    })?;
+    if result.rows_affected() == 0 {
+        return Err(cim_slo::errors::not_found(&format!(
+            "{table} {id} is not deleted"
+        )));
+    }
+    Ok(())
+}
+
+/// purge hard deletes the rows of table soft deleted before
+async fn purge(
+    conn: &Conn<Postgres>,
+    table: &str,
+    before: NaiveDateTime,
+) -> cim_slo::Result<u64> {
+    let result = sqlx::query(&format!(
+        r#"DELETE FROM "{table}"
+            WHERE "deleted" <> 0 AND "deleted_at" < $1;"#
+    ))
+    .bind(before)
+    .execute(&mut *conn.acquire().await?)
+    .await
+    .map_err(cim_slo::errors::any)?;
+    if label::KINDS.contains(&table) {
+        sqlx::query(&format!(
+            r#"DELETE FROM "label" WHERE "kind" = $1
+                AND "entity_id" NOT IN (SELECT "id" FROM "{table}");"#
+        ))
+        .bind(table)
+        .execute(&mut *conn.acquire().await?)
+        .await
+        .map_err(cim_slo::errors::any)?;
+    }
+    Ok(result.rows_affected())
+}
+
+/// label indexes the labels of row id of kind for the label selectors,
+/// replacing the ones it had
+async fn label(
+    conn: &Conn<Postgres>,
+    kind: &str,
+    id: &str,
+    labels: &label::Labels,
+) -> cim_slo::Result<()> {
+    let key = parse_id(id)?;
+    sqlx::query(
+        r#"DELETE FROM "label" WHERE "kind" = $1 AND "entity_id" = $2;"#,
+    )
+    .bind(kind)
+    .bind(key)
+    .execute(&mut *conn.acquire().await?)
+    .await
+    .map_err(cim_slo::errors::any)?;
+    if labels.is_empty() {
+        return Ok(());
+    }
+    let mut builder = QueryBuilder::new(
+        r#"INSERT INTO "label" ("kind","entity_id","name","value") "#,
+    );
+    builder.push_values(labels, |mut b, (name, value)| {
+        b.push_bind(kind)
+            .push_bind(key)
+            .push_bind(name)
+            .push_bind(value);
+    });
+    builder
+        .build()
+        .execute(&mut *conn.acquire().await?)
+        .await
+        .map_err(cim_slo::errors::any)?;
+    Ok(())
+}
+
+/// integration tests against a live database, run them with
+/// `POSTGRES_URL=postgres://... cargo test -p cim-storage --features postgres -- --ignored`
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Timelike, Utc};
+    use cim_pim::{rebac::TupleReader, PrincipalType, Request};
+
+    use super::*;
+    use crate::{
+        audit::{self, Audit, AuditStore},
+        authcode::AuthCode,
+        authrequest::AuthRequest,
+        changelog::ChangeStore,
+        client::Client,
+        connection_manager,
+        gc::GcStore,
+        group::{self, Group},
+        group_user::GroupUser,
+        key::Keys,
+        label::Labels,
+        policy::{self, Policy, StatementStore},
+        policy_binding::{self, BindingsType, PolicyBinding},
+        refresh_token::RefreshToken,
+        relation_tuple::RelationTuple,
+        role::Role,
+        role_binding::{RoleBinding, UserType},
+        user::{self, User},
+        Interface, List, Pagination, Pool,
+    };
+
+    async fn pool() -> sqlx::PgPool {
+        let uri = std::env::var("POSTGRES_URL").unwrap_or_else(|_| {
+            "postgres://postgres@127.0.0.1:5432/cim_test".to_owned()
+        });
+        match connection_manager(&uri, 2, 1, true).await.unwrap() {
+            Pool::Postgres(pool) => pool,
+            _ => unreachable!(),
+        }
+    }
+
+    /// clear drops the rows a previous run left behind, a put doesn't
+    /// bring back a soft deleted row
+    async fn clear(pool: &sqlx::PgPool, table: &str, id: i64) {
+        sqlx::query(&format!(r#"DELETE FROM "{table}" WHERE "id" = $1"#))
+            .bind(id)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    fn statement(subject: &str, resource: &str) -> Vec<cim_pim::Statement> {
+        serde_json::from_value(serde_json::json!([{
+            "effect": "Allow",
+            "subjects": [subject],
+            "actions": ["get"],
+            "resources": [resource],
+        }]))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn user_crud() {
+        let db = pool().await;
+        clear(&db, "user", 1001).await;
+        let store = UserImpl::new(db);
+        let mut input = User {
+            id: "1001".to_owned(),
+            account_id: "1001".to_owned(),
+            desc: "admin".to_owned(),
+            password: Some("Pa$$w0rd".to_owned()),
+            ..Default::default()
+        };
+        input.claim.name = Some("o'neil".to_owned());
+        store.put(&input).await.unwrap();
+
+        let mut output = User {
+            id: "1001".to_owned(),
+            ..Default::default()
+        };
+        store.get(&mut output).await.unwrap();
+        assert_eq!(output.account_id, "1001");
+        assert_eq!(output.claim.name.as_deref(), Some("o'neil"));
+        assert!(output.secret.is_some());
+
+        let mut list = List::default();
+        store
+            .list(
+                &user::ListParams {
+                    id: None,
+                    account_id: Some("1001".to_owned()),
+                    group_id: None,
+                    effective_group_id: None,
+                    label_selector: None,
+                    pagination: Pagination::default(),
+                },
+                &mut list,
+            )
+            .await
+            .unwrap();
+        assert_eq!(list.total, 1);
+        assert_eq!(list.data.len(), 1);
+
+        store.delete(&output).await.unwrap();
+        assert!(store.get(&mut output).await.is_err());
+
+        // a deleted entity is only brought back by a restore
+        assert!(store.put(&input).await.is_err());
+        store.restore(&input).await.unwrap();
+        store.get(&mut output).await.unwrap();
+        store.delete(&output).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn client_json_columns() {
+        let store = ClientImpl::new(pool().await);
+        store
+            .put(&Client {
+                id: "2001".to_owned(),
+                secret: "secret".to_owned(),
+                redirect_uris: vec!["http://127.0.0.1/callback".to_owned()],
+                trusted_peers: vec!["2002".to_owned()],
+                name: "pg-client".to_owned(),
+                logo_url: String::new(),
+                account_id: "1".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let mut output = Client {
+            id: "2001".to_owned(),
+            ..Default::default()
+        };
+        store.get(&mut output).await.unwrap();
+        assert_eq!(output.redirect_uris, vec!["http://127.0.0.1/callback"]);
+        assert_eq!(output.trusted_peers, vec!["2002"]);
+        assert_eq!(output.account_id, "1");
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn cursor() {
+        let store = UserImpl::new(pool().await);
+        for id in 3101..=3105 {
+            store
+                .put(&User {
+                    id: id.to_string(),
+                    account_id: "31".to_owned(),
+                    password: Some("Pa$$w0rd".to_owned()),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        let mut opts = user::ListParams {
+            id: None,
+            account_id: Some("31".to_owned()),
+            group_id: None,
+            effective_group_id: None,
+            label_selector: None,
+            pagination: Pagination {
+                limit: 2,
+                order_by: Some("created_at desc".to_owned()),
+                ..Default::default()
+            },
+        };
+        let mut ids = Vec::new();
+        loop {
+            let mut list = List::default();
+            store.list(&opts, &mut list).await.unwrap();
+            assert_eq!(list.total, 5);
+            ids.extend(list.data.into_iter().map(|v| v.id));
+            match list.next_cursor {
+                Some(cursor) => opts.pagination.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        ids.sort();
+        assert_eq!(ids, vec!["3101", "3102", "3103", "3104", "3105"]);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn get_statement() {
+        let pool = pool().await;
+        GroupImpl::new(pool.clone())
+            .put(&Group {
+                id: "3010".to_owned(),
+                account_id: "1".to_owned(),
+                name: "pg-dev".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        GroupUserImpl::new(pool.clone())
+            .put(&GroupUser {
+                id: "3020".to_owned(),
+                group_id: "3010".to_owned(),
+                user_id: "3002".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        RoleImpl::new(pool.clone())
+            .put(&Role {
+                id: "3050".to_owned(),
+                account_id: "1".to_owned(),
+                name: "pg-reader".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        RoleBindingImpl::new(pool.clone())
+            .put(&RoleBinding {
+                id: "3060".to_owned(),
+                role_id: "3050".to_owned(),
+                user_id: "3002".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let policy = PolicyImpl::new(pool.clone());
+        for id in ["3030", "3031", "3032"] {
+            policy
+                .put(&Policy {
+                    id: id.to_owned(),
+                    version: "v1.0.0".to_owned(),
+                    statement: statement(
+                        "3002",
+                        &format!("crn:iam:policy:{}", id),
+                    ),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        let policy_binding = PolicyBindingImpl::new(pool.clone());
+        for (id, policy_id, bindings_type, bindings_id) in [
+            ("3040", "3030", BindingsType::Group, "3010"),
+            ("3041", "3031", BindingsType::Role, "3050"),
+        ] {
+            policy_binding
+                .put(&PolicyBinding {
+                    id: id.to_owned(),
+                    policy_id: policy_id.to_owned(),
+                    bindings_type,
+                    bindings_id: bindings_id.to_owned(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        let mut output = PolicyBinding {
+            id: "3041".to_owned(),
+            ..Default::default()
+        };
+        policy_binding.get(&mut output).await.unwrap();
+        assert_eq!(output.bindings_type, BindingsType::Role);
+        assert_eq!(output.bindings_id, "3050");
+
+        // the bindings of the account are the ones of its groups and roles
+        let bound = |account_id: &str| policy_binding::ListParams {
+            id: None,
+            account_id: Some(account_id.to_owned()),
+            policy_id: None,
+            bindings_type: None,
+            bindings_id: None,
+            expires_before: None,
+            pagination: Pagination::default(),
+        };
+        let mut list = List::default();
+        policy_binding.list(&bound("1"), &mut list).await.unwrap();
+        let ids: Vec<_> = list.data.iter().map(|v| v.id.as_str()).collect();
+        assert!(ids.contains(&"3040") && ids.contains(&"3041"));
+        let mut list = List::default();
+        policy_binding.list(&bound("2"), &mut list).await.unwrap();
+        assert!(!list.data.iter().any(|v| v.id == "3040"));
+
+        let mut statements = policy
+            .get_statement(&Request {
+                resource: "crn:iam:policy:3030".to_owned(),
+                action: "get".to_owned(),
+                subject: "3002".to_owned(),
+                principal_type: PrincipalType::User,
+                context: Default::default(),
+            })
+            .await
+            .unwrap();
+        statements.sort_by(|a, b| a.resources.cmp(&b.resources));
+        assert_eq!(statements.len(), 2);
+        assert_eq!(statements[0].resources, vec!["crn:iam:policy:3030"]);
+        assert_eq!(statements[1].resources, vec!["crn:iam:policy:3031"]);
+
+        let mut bindings = policy
+            .get_bindings(PrincipalType::User, "3002")
+            .await
+            .unwrap();
+        bindings.policies.sort();
+        assert_eq!(bindings.groups, vec!["3010"]);
+        assert_eq!(bindings.roles, vec!["3050"]);
+        assert_eq!(bindings.policies, vec!["3030", "3031"]);
+
+        let mut list = List::default();
+        let mut opts = policy::ListParams {
+            id: None,
+            version: None,
+            account_id: None,
+            group_id: None,
+            user_id: None,
+            role_id: None,
+            label_selector: None,
+            pagination: Pagination {
+                order_by: Some("id desc".to_owned()),
+                ..Default::default()
+            },
+        };
+        opts.role_id = Some("3050".to_owned());
+        policy.list(&opts, &mut list).await.unwrap();
+        assert_eq!(list.total, 1);
+        assert_eq!(list.data[0].id, "3031");
+
+        opts.pagination.order_by = Some("statement desc".to_owned());
+        assert!(policy.list(&opts, &mut List::default()).await.is_err());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn resource_version() {
+        let store = GroupImpl::new(pool().await);
+        let mut group = Group {
+            id: "4001".to_owned(),
+            account_id: "40".to_owned(),
+            ..Default::default()
+        };
+        // the row is kept soft deleted by the former runs, the versions go
+        // on from its one
+        store.put(&group).await.unwrap();
+        store.get(&mut group).await.unwrap();
+        let version = group.resource_version;
+
+        group.name = "first".to_owned();
+        store.put_if(&group, version).await.unwrap();
+        group.name = "second".to_owned();
+        let err = store.put_if(&group, version).await.unwrap_err();
+        assert!(err.to_string().starts_with("Conflict."));
+        store.get(&mut group).await.unwrap();
+        assert_eq!(group.resource_version, version + 1);
+        assert_eq!(group.name, "first");
+
+        // a put bumps the stored version, whatever version it is given
+        store
+            .put(&Group {
+                resource_version: 0,
+                ..group.clone()
+            })
+            .await
+            .unwrap();
+        store.get(&mut group).await.unwrap();
+        assert_eq!(group.resource_version, version + 2);
+
+        // only one of the concurrent writes at a version wins it
+        let writes = (0..8).map(|i| {
+            let store = store.clone();
+            let group = Group {
+                name: format!("concurrent-{i}"),
+                ..group.clone()
+            };
+            tokio::spawn(async move { store.put_if(&group, version + 2).await })
+        });
+        let mut won = 0;
+        for write in writes.collect::<Vec<_>>() {
+            if write.await.unwrap().is_ok() {
+                won += 1;
+            }
+        }
+        assert_eq!(won, 1);
+        store.get(&mut group).await.unwrap();
+        assert_eq!(group.resource_version, version + 3);
+
+        // concurrent replaces each read what the one before them wrote
+        let replaces = (0..8).map(|_| {
+            let (store, group) = (store.clone(), group.clone());
+            tokio::spawn(async move { store.replace(&group, None).await })
+        });
+        let mut previous = Vec::new();
+        for replace in replaces.collect::<Vec<_>>() {
+            let replaced = replace.await.unwrap().unwrap().unwrap();
+            previous.push(replaced.resource_version);
+        }
+        previous.sort();
+        assert_eq!(previous, (version + 3..version + 11).collect::<Vec<_>>());
+        store.get(&mut group).await.unwrap();
+        assert_eq!(group.resource_version, version + 11);
+
+        assert!(store.delete_if(&group, version + 10).await.is_err());
+        store.delete_if(&group, version + 11).await.unwrap();
+        assert!(store.get(&mut group).await.is_err());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn audit() {
+        let store = AuditImpl::new(pool().await);
+        // appended records stay, so every run audits as another actor
+        let actor = chrono::Utc::now().timestamp_micros().to_string();
+        for (kind, entity_id) in
+            [("policy", "1"), ("policy", "2"), ("group", "1")]
+        {
+            store
+                .append(&Audit {
+                    actor: actor.clone(),
+                    trace_id: "trace".to_owned(),
+                    source_ip: "127.0.0.1".to_owned(),
+                    kind: kind.to_owned(),
+                    entity_id: entity_id.to_owned(),
+                    action: "put".to_owned(),
+                    before: None,
+                    after: Some(serde_json::json!({"id": entity_id})),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        let mut opts = audit::ListParams {
+            actor: Some(actor.clone()),
+            kind: Some("policy".to_owned()),
+            entity_id: None,
+            since: None,
+            until: None,
+            pagination: Pagination {
+                order_by: Some("id desc".to_owned()),
+                ..Default::default()
+            },
+        };
+        let mut list = List::default();
+        store.list(&opts, &mut list).await.unwrap();
+        assert_eq!(list.total, 2);
+        assert_eq!(list.data[0].entity_id, "2");
+        assert_eq!(list.data[0].before, None);
+        assert_eq!(list.data[0].after, Some(serde_json::json!({"id": "2"})));
+
+        let created_at = list.data[0].created_at;
+        opts.kind = None;
+        opts.until = Some(created_at - chrono::Duration::hours(1));
+        let mut list = List::default();
+        store.list(&opts, &mut list).await.unwrap();
+        assert_eq!(list.total, 0);
+        opts.since = Some(created_at - chrono::Duration::hours(1));
+        opts.until = None;
+        store.list(&opts, &mut list).await.unwrap();
+        assert_eq!(list.total, 3);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn changelog() {
+        let store = ChangelogImpl::new(pool().await);
+        let last = store.last().await.unwrap();
+        store.append("group", "{}").await.unwrap();
+        store.append("user", "[]").await.unwrap();
+        let changes = store.since(last, 10).await.unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].kind, "group");
+        assert!(changes[0].id < changes[1].id);
+        assert_eq!(store.last().await.unwrap(), changes[1].id);
+        assert_eq!(store.since(changes[0].id, 10).await.unwrap().len(), 1);
+
+        let future =
+            chrono::Utc::now().naive_utc() + chrono::Duration::hours(1);
+        assert!(store.purge(future).await.unwrap() >= 2);
+        assert!(store.since(last, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn relation_tuple() {
+        let store = RelationTupleImpl::new(pool().await);
+        store
+            .put(&RelationTuple {
+                id: "4001".to_owned(),
+                account_id: "1".to_owned(),
+                object: "document:pg-readme".to_owned(),
+                relation: "viewer".to_owned(),
+                subject: "group:eng#member".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let subjects =
+            store.read("document:pg-readme", "viewer").await.unwrap();
+        assert_eq!(subjects.len(), 1);
+        assert_eq!(subjects[0].to_string(), "group:eng#member");
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn soft_delete() {
+        let db = pool().await;
+        clear(&db, "group", 4101).await;
+        let store = GroupImpl::new(db);
+        let mut group = Group {
+            id: "4101".to_owned(),
+            account_id: "41".to_owned(),
+            ..Default::default()
+        };
+        store.put(&group).await.unwrap();
+        store.delete(&group).await.unwrap();
+        let opts = group::ListParams {
+            id: None,
+            account_id: Some("41".to_owned()),
+            parent_id: None,
+            user_id: None,
+            effective_user_id: None,
+            label_selector: None,
+            pagination: Pagination::default(),
+        };
+        let mut output = List::default();
+        store.list(&opts, &mut output).await.unwrap();
+        assert!(output.data.is_empty());
+        store.list_deleted(&opts, &mut output).await.unwrap();
+        assert_eq!(output.data.len(), 1);
+
+        store.restore(&group).await.unwrap();
+        store.get(&mut group).await.unwrap();
+        assert_eq!(group.resource_version, 3);
+        assert!(store.restore(&group).await.is_err());
+
+        // discard returns the live entity it deleted, none when there is
+        // none
+        let previous = store.discard(&group, Some(3)).await.unwrap();
+        assert_eq!(previous.unwrap().resource_version, 3);
+        assert!(store.discard(&group, None).await.unwrap().is_none());
+        store.restore(&group).await.unwrap();
+        store.delete(&group).await.unwrap();
+        let before =
+            chrono::Utc::now().naive_utc() - chrono::Duration::hours(1);
+        assert_eq!(store.purge(before).await.unwrap(), 0);
+        let before =
+            chrono::Utc::now().naive_utc() + chrono::Duration::hours(1);
+        assert!(store.purge(before).await.unwrap() >= 1);
+        assert_eq!(store.count(&opts, true).await.unwrap(), 0);
+        assert!(store.restore(&group).await.is_err());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn labels() {
+        let db = pool().await;
+        let store = GroupImpl::new(db.clone());
+        for (id, env, tier) in [
+            ("4301", "prod", "web"),
+            ("4302", "prod", "api"),
+            ("4303", "test", "web"),
+        ] {
+            store
+                .put(&Group {
+                    id: id.to_owned(),
+                    account_id: "43".to_owned(),
+                    labels: Labels::from([
+                        ("env".to_owned(), env.to_owned()),
+                        ("tier".to_owned(), tier.to_owned()),
+                    ]),
+                    annotations: Labels::from([(
+                        "note".to_owned(),
+                        format!("group {id}"),
+                    )]),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        let mut group = Group {
+            id: "4303".to_owned(),
+            ..Default::default()
+        };
+        store.get(&mut group).await.unwrap();
+        assert_eq!(group.labels["env"], "test");
+        assert_eq!(group.annotations["note"], "group 4303");
+        group.labels.remove("env");
+        store.put(&group).await.unwrap();
+
+        for (selector, ids) in [
+            ("env=prod", vec!["4301", "4302"]),
+            ("env=prod,tier!=web", vec!["4302"]),
+            ("tier in (web,api),env notin (prod)", vec!["4303"]),
+            ("!env", vec!["4303"]),
+            ("env", vec!["4301", "4302"]),
+            ("owner", vec![]),
+        ] {
+            let mut output = List::default();
+            store
+                .list(
+                    &group::ListParams {
+                        id: None,
+                        account_id: Some("43".to_owned()),
+                        parent_id: None,
+                        user_id: None,
+                        effective_user_id: None,
+                        label_selector: Some(selector.parse().unwrap()),
+                        pagination: Pagination::default(),
+                    },
+                    &mut output,
+                )
+                .await
+                .unwrap();
+            let selected: Vec<_> =
+                output.data.iter().map(|v| v.id.as_str()).collect();
+            assert_eq!(selected, ids, "{}", selector);
+        }
+
+        store.delete(&group).await.unwrap();
+        let before =
+            chrono::Utc::now().naive_utc() + chrono::Duration::hours(1);
+        assert!(store.purge(before).await.unwrap() >= 1);
+        let indexed: i64 = sqlx::query_scalar(
+            r#"SELECT COUNT(*) FROM "label" WHERE "entity_id" = $1"#,
+        )
+        .bind(4303i64)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert_eq!(indexed, 0);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn nested_groups() {
+        let db = pool().await;
+        clear(&db, "group_user", 4521).await;
+        let group = GroupImpl::new(db.clone());
+        for (id, parent_id) in [
+            ("4511", None),
+            ("4512", Some("4511")),
+            ("4513", Some("4512")),
+        ] {
+            group
+                .put(&Group {
+                    id: id.to_owned(),
+                    account_id: "45".to_owned(),
+                    parent_id: parent_id.map(str::to_owned),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        UserImpl::new(db.clone())
+            .put(&User {
+                id: "4503".to_owned(),
+                account_id: "45".to_owned(),
+                password: Some("Pa$$w0rd".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        GroupUserImpl::new(db.clone())
+            .put(&GroupUser {
+                id: "4520".to_owned(),
+                group_id: "4513".to_owned(),
+                user_id: "4503".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let policy = PolicyImpl::new(db.clone());
+        policy
+            .put(&Policy {
+                id: "4530".to_owned(),
+                version: "v1.0.0".to_owned(),
+                statement: serde_json::from_value(serde_json::json!([{
+                    "effect": "Allow",
+                    "subjects": ["4503"],
+                    "actions": ["get"],
+                    "resources": ["crn:iam:group:4511"],
+                }]))
+                .unwrap(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        PolicyBindingImpl::new(db.clone())
+            .put(&PolicyBinding {
+                id: "4540".to_owned(),
+                policy_id: "4530".to_owned(),
+                bindings_type: BindingsType::Group,
+                bindings_id: "4511".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        // the member of the nested group gets the policy of its ancestors
+        let statements = policy
+            .get_statement(&Request {
+                resource: "crn:iam:group:4511".to_owned(),
+                action: "get".to_owned(),
+                subject: "4503".to_owned(),
+                principal_type: PrincipalType::User,
+                context: Default::default(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(statements.len(), 1);
+        let mut bindings = policy
+            .get_bindings(PrincipalType::User, "4503")
+            .await
+            .unwrap();
+        bindings.groups.sort();
+        assert_eq!(bindings.groups, vec!["4511", "4512", "4513"]);
+        assert_eq!(bindings.policies, vec!["4530"]);
+
+        // a membership not in effect yet doesn't make a member
+        group
+            .put(&Group {
+                id: "4514".to_owned(),
+                account_id: "45".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        GroupUserImpl::new(db.clone())
+            .put(&GroupUser {
+                id: "4521".to_owned(),
+                group_id: "4514".to_owned(),
+                user_id: "4503".to_owned(),
+                not_before: Some(Utc::now().naive_utc() + Duration::hours(1)),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let mut users = List::default();
+        UserImpl::new(db.clone())
+            .list(
+                &user::ListParams {
+                    id: None,
+                    account_id: None,
+                    group_id: None,
+                    effective_group_id: Some("4511".to_owned()),
+                    label_selector: None,
+                    pagination: Pagination::default(),
+                },
+                &mut users,
+            )
+            .await
+            .unwrap();
+        assert_eq!(users.data.len(), 1);
+        for (parent_id, user_id, effective_user_id, ids) in [
+            (Some("4511"), None, None, vec!["4512"]),
+            (None, Some("4503"), None, vec!["4513", "4514"]),
+            (None, None, Some("4503"), vec!["4511", "4512", "4513"]),
+        ] {
+            let mut output = List::default();
+            group
+                .list(
+                    &group::ListParams {
+                        id: None,
+                        account_id: Some("45".to_owned()),
+                        parent_id: parent_id.map(str::to_owned),
+                        user_id: user_id.map(str::to_owned),
+                        effective_user_id: effective_user_id.map(str::to_owned),
+                        label_selector: None,
+                        pagination: Pagination::default(),
+                    },
+                    &mut output,
+                )
+                .await
+                .unwrap();
+            let mut listed: Vec<_> =
+                output.data.iter().map(|v| v.id.as_str()).collect();
+            listed.sort();
+            assert_eq!(listed, ids);
+        }
+
+        let mut users = List::default();
+        UserImpl::new(db.clone())
+            .list(
+                &user::ListParams {
+                    id: None,
+                    account_id: None,
+                    group_id: None,
+                    effective_group_id: Some("4514".to_owned()),
+                    label_selector: None,
+                    pagination: Pagination::default(),
+                },
+                &mut users,
+            )
+            .await
+            .unwrap();
+        assert!(users.data.is_empty());
+
+        let mut root = Group {
+            id: "4511".to_owned(),
+            ..Default::default()
+        };
+        group.get(&mut root).await.unwrap();
+        root.parent_id = Some("4513".to_owned());
+        assert!(group.put(&root).await.is_err());
+        let middle = Group {
+            id: "4512".to_owned(),
+            ..Default::default()
+        };
+        assert!(group.delete(&middle).await.is_err());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn principals() {
+        let db = pool().await;
+        RoleImpl::new(db.clone())
+            .put(&Role {
+                id: "4661".to_owned(),
+                account_id: "1".to_owned(),
+                name: "deployer-4661".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let policy = PolicyImpl::new(db.clone());
+        policy
+            .put(&Policy {
+                id: "4662".to_owned(),
+                version: "v1.0.0".to_owned(),
+                statement: serde_json::from_value(serde_json::json!([{
+                    "effect": "Allow",
+                    "subjects": ["<.*>"],
+                    "actions": ["update"],
+                    "resources": ["crn:iam:deployment:4662"],
+                }]))
+                .unwrap(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        PolicyBindingImpl::new(db.clone())
+            .put(&PolicyBinding {
+                id: "4663".to_owned(),
+                policy_id: "4662".to_owned(),
+                bindings_type: BindingsType::Role,
+                bindings_id: "4661".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let role_binding = RoleBindingImpl::new(db.clone());
+        for (id, user_type, user_id) in [
+            ("4664", UserType::Application, "4666"),
+            ("4665", UserType::FederatedUser, "github|4666"),
+        ] {
+            role_binding
+                .put(&RoleBinding {
+                    id: id.to_owned(),
+                    role_id: "4661".to_owned(),
+                    user_type,
+                    user_id: user_id.to_owned(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        // applications and federated users are bound policies directly by
+        // their subject
+        policy
+            .put(&Policy {
+                id: "4667".to_owned(),
+                version: "v1.0.0".to_owned(),
+                statement: serde_json::from_value(serde_json::json!([{
+                    "effect": "Allow",
+                    "subjects": ["<.*>"],
+                    "actions": ["get"],
+                    "resources": ["crn:iam:deployment:4667"],
+                }]))
+                .unwrap(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let policy_binding = PolicyBindingImpl::new(db.clone());
+        for (id, bindings_type, bindings_id) in [
+            ("4668", BindingsType::Application, "4666"),
+            ("4669", BindingsType::FederatedUser, "github|4666"),
+        ] {
+            policy_binding
+                .put(&PolicyBinding {
+                    id: id.to_owned(),
+                    policy_id: "4667".to_owned(),
+                    bindings_type,
+                    bindings_id: bindings_id.to_owned(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        // the user sharing its id with the application is bound neither the
+        // role nor the policy
+        for (principal_type, subject, bound) in [
+            (PrincipalType::Application, "4666", 1),
+            (PrincipalType::FederatedUser, "github|4666", 1),
+            (PrincipalType::User, "4666", 0),
+            (PrincipalType::Application, "github|4666", 0),
+        ] {
+            let statements = policy
+                .get_statement(&Request {
+                    resource: "crn:iam:deployment:4662".to_owned(),
+                    action: "update".to_owned(),
+                    subject: subject.to_owned(),
+                    principal_type,
+                    context: Default::default(),
+                })
+                .await
+                .unwrap();
+            assert_eq!(
+                statements.len(),
+                2 * bound,
+                "{:?} {}",
+                principal_type,
+                subject
+            );
+            let bindings =
+                policy.get_bindings(principal_type, subject).await.unwrap();
+            assert!(bindings.groups.is_empty());
+            assert_eq!(bindings.roles.len(), bound);
+            assert_eq!(bindings.policies.len(), 2 * bound);
+        }
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn windows() {
+        let db = pool().await;
+        // whole seconds, which every store keeps as is
+        let now = Utc::now().naive_utc().with_nanosecond(0).unwrap();
+        let hour = Duration::hours(1);
+        GroupImpl::new(db.clone())
+            .put(&Group {
+                id: "4771".to_owned(),
+                account_id: "1".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let policy = PolicyImpl::new(db.clone());
+        policy
+            .put(&Policy {
+                id: "4772".to_owned(),
+                version: "v1.0.0".to_owned(),
+                statement: serde_json::from_value(serde_json::json!([{
+                    "effect": "Allow",
+                    "subjects": ["<.*>"],
+                    "actions": ["read"],
+                    "resources": ["crn:iam:document:4772"],
+                }]))
+                .unwrap(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        // the membership takes effect in an hour
+        GroupUserImpl::new(db.clone())
+            .put(&GroupUser {
+                id: "4773".to_owned(),
+                group_id: "4771".to_owned(),
+                user_id: "4776".to_owned(),
+                not_before: Some(now + hour),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        // the role binding expired an hour ago
+        RoleBindingImpl::new(db.clone())
+            .put(&RoleBinding {
+                id: "4774".to_owned(),
+                role_id: "4775".to_owned(),
+                user_type: UserType::User,
+                user_id: "4777".to_owned(),
+                expires_at: Some(now - hour),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let policy_binding = PolicyBindingImpl::new(db.clone());
+        for (id, bindings_type, bindings_id, expires_at) in [
+            ("4781", BindingsType::Group, "4771", None),
+            ("4782", BindingsType::Role, "4775", None),
+            ("4783", BindingsType::User, "4778", Some(now + hour * 2)),
+        ] {
+            policy_binding
+                .put(&PolicyBinding {
+                    id: id.to_owned(),
+                    policy_id: "4772".to_owned(),
+                    bindings_type,
+                    bindings_id: bindings_id.to_owned(),
+                    expires_at,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        for (subject, bound, changes_at) in [
+            ("4776", 0, Some(now + hour)),
+            ("4777", 0, None),
+            ("4778", 1, Some(now + hour * 2)),
+        ] {
+            let statements = policy
+                .get_statement(&Request {
+                    resource: "crn:iam:document:4772".to_owned(),
+                    action: "read".to_owned(),
+                    subject: subject.to_owned(),
+                    principal_type: PrincipalType::User,
+                    context: Default::default(),
+                })
+                .await
+                .unwrap();
+            assert_eq!(statements.len(), bound, "{}", subject);
+            let bindings = policy
+                .get_bindings(PrincipalType::User, subject)
+                .await
+                .unwrap();
+            assert_eq!(bindings.changes_at, changes_at, "{}", subject);
+        }
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn gc() {
+        let db = pool().await;
+        let gc = GcImpl::new(db.clone());
+        let requests = AuthRequestImpl::new(db.clone());
+        let codes = AuthCodeImpl::new(db.clone());
+        let tokens = RefreshTokenImpl::new(db);
+        for (id, expiry) in [("41001", 100), ("41002", 300)] {
+            requests
+                .put(&AuthRequest {
+                    id: id.to_owned(),
+                    client_id: "1".to_owned(),
+                    expiry,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            codes
+                .put(&AuthCode {
+                    id: id.to_owned(),
+                    client_id: "1".to_owned(),
+                    expiry,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        assert_eq!(gc.purge_auth_requests(200).await.unwrap(), 1);
+        assert_eq!(gc.purge_auth_codes(200).await.unwrap(), 1);
+        let mut request = AuthRequest {
+            id: "41001".to_owned(),
+            ..Default::default()
+        };
+        assert!(requests.get(&mut request).await.is_err());
+        request.id = "41002".to_owned();
+        requests.get(&mut request).await.unwrap();
+
+        let now = chrono::Utc::now().naive_utc();
+        for (id, last_used_at) in
+            [("41001", now - chrono::Duration::days(2)), ("41002", now)]
+        {
+            tokens
+                .put(&RefreshToken {
+                    id: id.to_owned(),
+                    client_id: "1".to_owned(),
+                    last_used_at,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        assert_eq!(gc.purge_refresh_tokens(None, None).await.unwrap(), 0);
+        let used_before = now - chrono::Duration::days(1);
+        assert_eq!(
+            gc.purge_refresh_tokens(None, Some(used_before))
+                .await
+                .unwrap(),
+            1
+        );
+        let created_before = now + chrono::Duration::hours(1);
+        assert_eq!(
+            gc.purge_refresh_tokens(Some(created_before), Some(used_before))
+                .await
+                .unwrap(),
+            1
+        );
+
+        // deleted tokens are left out of list but counted unscoped
+        let token = RefreshToken {
+            id: "41003".to_owned(),
+            client_id: "1".to_owned(),
+            last_used_at: now,
+            ..Default::default()
+        };
+        tokens.put(&token).await.unwrap();
+        let mut list = List::default();
+        tokens.list(&(), &mut list).await.unwrap();
+        assert!(list.data.iter().any(|v| v.id == "41003"));
+        tokens.delete(&token).await.unwrap();
+        let mut list = List::default();
+        tokens.list(&(), &mut list).await.unwrap();
+        assert!(list.data.iter().all(|v| v.id != "41003"));
+        assert_eq!(
+            tokens.count(&(), true).await.unwrap(),
+            tokens.count(&(), false).await.unwrap() + 1
+        );
+        gc.purge_refresh_tokens(Some(created_before), None)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn oidc_list() {
+        let db = pool().await;
+        clear(&db, "client", 42001).await;
+        let requests = AuthRequestImpl::new(db.clone());
+        let codes = AuthCodeImpl::new(db.clone());
+        let clients = ClientImpl::new(db.clone());
+        let keys = KeysImpl::new(db);
+        // far from expired, so the gc test running along keeps them
+        for id in ["42001", "42002"] {
+            requests
+                .put(&AuthRequest {
+                    id: id.to_owned(),
+                    client_id: "1".to_owned(),
+                    expiry: i64::MAX,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            codes
+                .put(&AuthCode {
+                    id: id.to_owned(),
+                    client_id: "1".to_owned(),
+                    expiry: i64::MAX,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        let mut list = List::default();
+        requests.list(&(), &mut list).await.unwrap();
+        assert!(list.data.iter().any(|v| v.id == "42002"));
+        let mut list = List::default();
+        codes.list(&(), &mut list).await.unwrap();
+        assert!(list.data.iter().any(|v| v.id == "42002"));
+        assert!(requests.count(&(), false).await.unwrap() >= 2);
+        assert!(codes.count(&(), false).await.unwrap() >= 2);
+
+        let client = Client {
+            id: "42001".to_owned(),
+            name: "oidc-list".to_owned(),
+            ..Default::default()
+        };
+        clients.put(&client).await.unwrap();
+        clients.delete(&client).await.unwrap();
+        assert!(
+            clients.count(&(), true).await.unwrap()
+                > clients.count(&(), false).await.unwrap()
+        );
+        let mut list = List::default();
+        clients.list_deleted(&(), &mut list).await.unwrap();
+        assert!(list.data.iter().any(|v| v.id == "42001"));
+        clients.restore(&client).await.unwrap();
+        let mut output = client.clone();
+        clients.get(&mut output).await.unwrap();
+        assert_eq!(output.resource_version, 3);
+        clients.delete(&client).await.unwrap();
+        keys.put(&Keys {
+            id: "42001".to_owned(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert!(keys.count(&(), false).await.unwrap() >= 1);
+
+        for id in ["42001", "42002"] {
+            requests
+                .delete(&AuthRequest {
+                    id: id.to_owned(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            codes
+                .delete(&AuthCode {
+                    id: id.to_owned(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use serde_json::value::RawValue;
+use sqlx::{types::Json, Postgres, QueryBuilder, Row};
+
+use cim_slo::{errors, Result};
+
+use super::{parse_id, purge};
+use crate::{
+    offlinesession::{ListParams, OfflineSession, RefreshTokenRef},
+    Conn, Interface, List,
+};
+
+#[derive(Clone, Debug)]
+pub struct OfflineSessionImpl {
+    conn: Conn<Postgres>,
+}
+
+impl OfflineSessionImpl {
+    pub fn new(pool: impl Into<Conn<Postgres>>) -> Self {
+        Self { conn: pool.into() }
+    }
+}
+
+#[async_trait]
+impl Interface for OfflineSessionImpl {
+    type T = OfflineSession;
+    type L = ListParams;
+
+    #[tracing::instrument]
+    async fn put(&self, content: &Self::T) -> Result<()> {
+        sqlx::query(
+            r#"INSERT INTO "offline_session"
+            ("id","user_id","conn_id","refresh","connector_data")
+            VALUES($1,$2,$3,$4,$5)
+            ON CONFLICT ("id") DO UPDATE SET
+            "user_id" = EXCLUDED."user_id",
+            "conn_id" = EXCLUDED."conn_id",
+            "refresh" = EXCLUDED."refresh",
+            "connector_data" = EXCLUDED."connector_data",
+            "deleted" = 0,
+            "deleted_at" = NULL,
+            "updated_at" = CURRENT_TIMESTAMP;"#,
+        )
+        .bind(parse_id(&content.id)?)
+        .bind(&content.user_id)
+        .bind(&content.conn_id)
+        .bind(Json(&content.refresh))
+        .bind(content.connector_data.as_ref().map(|v| v.to_string()))
+        .execute(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        let id = input
+            .id
+            .parse::<i64>()
+            .map_err(|err| errors::bad_request(&err))?;
+
+        sqlx::query(
+            r#"UPDATE "offline_session" SET "deleted" = "id","deleted_at"= CURRENT_TIMESTAMP
+            WHERE id = $1 AND "deleted" = 0;"#,
+        )
+        .bind(id)
+        .execute(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        let id = output
+            .id
+            .parse::<i64>()
+            .map_err(|err| errors::bad_request(&err))?;
+        let row = match sqlx::query(
+            r#"SELECT "id","user_id","conn_id","refresh","connector_data"
+             FROM "offline_session"
+            WHERE id = $1 AND deleted = 0;"#,
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.conn.acquire().await?)
+        .await
+        {
+            Ok(v) => match v {
+                Some(value) => Ok(value),
+                None => Err(errors::not_found("no rows")),
+            },
+            Err(err) => Err(errors::any(err)),
+        }?;
+        output.id = row
+            .try_get::<i64, _>("id")
+            .map_err(errors::any)?
+            .to_string();
+        output.refresh = row
+            .try_get::<Json<HashMap<String, RefreshTokenRef>>, _>("refresh")
+            .map_err(errors::any)?
+            .0;
+        output.connector_data = row
+            .try_get::<Option<String>, _>("connector_data")
+            .map_err(errors::any)?
+            .map(|v| RawValue::from_string(v).unwrap());
+        output.user_id =
+            row.try_get::<String, _>("user_id").map_err(errors::any)?;
+        output.conn_id =
+            row.try_get::<String, _>("conn_id").map_err(errors::any)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        let filter = opts.filter()?.not_deleted();
+        // 查询total
+        if !opts.pagination.count_disable {
+            let mut builder = QueryBuilder::new(
+                r#"SELECT COUNT(*) as count FROM "offline_session""#,
+            );
+            filter.push(&mut builder);
+            output.total = builder
+                .build()
+                .fetch_one(&mut *self.conn.acquire().await?)
+                .await
+                .map_err(errors::any)?
+                .try_get("count")
+                .map_err(errors::any)?;
+        }
+
+        // 查询列表
+        output.limit = opts.pagination.limit;
+        output.offset = opts.pagination.offset;
+
+        let mut builder = QueryBuilder::new(
+            r#"SELECT "id","user_id","conn_id","refresh","connector_data"
+            FROM "offline_session""#,
+        );
+        opts.pagination
+            .seek(filter, ListParams::SORTABLE)?
+            .push(&mut builder);
+        opts.pagination.push(&mut builder, ListParams::SORTABLE)?;
+        let rows = builder
+            .build()
+            .fetch_all(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?;
+        for row in rows.iter() {
+            output.data.push(Self::T {
+                id: row
+                    .try_get::<i64, _>("id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                user_id: row
+                    .try_get::<String, _>("user_id")
+                    .map_err(errors::any)?,
+                conn_id: row
+                    .try_get::<String, _>("conn_id")
+                    .map_err(errors::any)?,
+                refresh: row
+                    .try_get::<Json<HashMap<String, RefreshTokenRef>>, _>(
+                        "refresh",
+                    )
+                    .map_err(errors::any)?
+                    .0,
+                connector_data: row
+                    .try_get::<Option<String>, _>("connector_data")
+                    .map_err(errors::any)?
+                    .map(|v| RawValue::from_string(v).unwrap()),
+            })
+        }
+        output.next_cursor = opts
+            .pagination
+            .next_cursor(&output.data, ListParams::SORTABLE)?;
+        Ok(())
+    }
+
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let mut filter = opts.filter()?;
+        if !unscoped {
+            filter = filter.not_deleted();
+        }
+        let mut builder = QueryBuilder::new(
+            r#"SELECT COUNT(*) as count FROM "offline_session""#,
+        );
+        filter.push(&mut builder);
+        builder
+            .build()
+            .fetch_one(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?
+            .try_get("count")
+            .map_err(errors::any)
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        purge(&self.conn, "offline_session", before).await
+    }
+}
@@ -0,0 +1,476 @@
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use sqlx::{types::Json, Postgres, QueryBuilder, Row};
+
+use cim_pim::{PrincipalType, Request, Statement};
+use cim_slo::{errors, Result};
+
+use super::{
+    label, lock, parse_id, parse_option_id, purge, restore, soft_delete,
+    write_query, write_row,
+};
+use crate::{
+    discarded,
+    expiry::next_change,
+    label::Labels,
+    policy::{self, Bindings, ListParams, Policy, StatementStore},
+    policy_binding::BindingsType,
+    role_binding::UserType,
+    stored, Conn, Interface, List,
+};
+
+#[derive(Clone, Debug)]
+pub struct PolicyImpl {
+    conn: Conn<Postgres>,
+}
+
+impl PolicyImpl {
+    pub fn new(pool: impl Into<Conn<Postgres>>) -> Self {
+        Self { conn: pool.into() }
+    }
+
+    /// select lists the rows matching opts, the soft deleted ones when
+    /// deleted
+    async fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<Policy>,
+        deleted: bool,
+    ) -> Result<()> {
+        let filter = opts.filter()?.deleted(deleted);
+        // 查询total
+        if !opts.pagination.count_disable {
+            let mut builder =
+                QueryBuilder::new(r#"SELECT COUNT(*) as count FROM "policy""#);
+            filter.push(&mut builder);
+            output.total = builder
+                .build()
+                .fetch_one(&mut *self.conn.acquire().await?)
+                .await
+                .map_err(errors::any)?
+                .try_get("count")
+                .map_err(errors::any)?;
+        }
+
+        // 查询列表
+        output.limit = opts.pagination.limit;
+        output.offset = opts.pagination.offset;
+
+        let mut builder = QueryBuilder::new(
+            r#"SELECT "id","account_id","desc","version","statement",
+                "resource_version","labels","annotations","created_at","updated_at"
+                FROM "policy""#,
+        );
+        opts.pagination
+            .seek(filter, ListParams::SORTABLE)?
+            .push(&mut builder);
+        opts.pagination.push(&mut builder, ListParams::SORTABLE)?;
+        let rows = builder
+            .build()
+            .fetch_all(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?;
+        for row in rows.iter() {
+            output.data.push(Policy {
+                id: row
+                    .try_get::<i64, _>("id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                account_id: row
+                    .try_get::<Option<i64>, _>("account_id")
+                    .map_err(errors::any)?
+                    .map(|v| v.to_string()),
+                desc: row.try_get("desc").map_err(errors::any)?,
+                version: row.try_get("version").map_err(errors::any)?,
+                statement: row
+                    .try_get::<Json<Vec<Statement>>, _>("statement")
+                    .map_err(errors::any)?
+                    .0,
+                resource_version: row
+                    .try_get::<i64, _>("resource_version")
+                    .map_err(errors::any)?
+                    as u64,
+                labels: row
+                    .try_get::<Json<Labels>, _>("labels")
+                    .map_err(errors::any)?
+                    .0,
+                annotations: row
+                    .try_get::<Json<Labels>, _>("annotations")
+                    .map_err(errors::any)?
+                    .0,
+                created_at: row.try_get("created_at").map_err(errors::any)?,
+                updated_at: row.try_get("updated_at").map_err(errors::any)?,
+            });
+        }
+
+        output.next_cursor = opts
+            .pagination
+            .next_cursor(&output.data, ListParams::SORTABLE)?;
+        Ok(())
+    }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it
+    async fn write(
+        &self,
+        input: &Policy,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
+        let query = write_query(
+            "policy",
+            &[
+                "account_id",
+                "desc",
+                "version",
+                "statement",
+                "labels",
+                "annotations",
+            ],
+            resource_version.is_some(),
+        );
+        let query = sqlx::query(&query)
+            .bind(parse_id(&input.id)?)
+            .bind(parse_option_id(&input.account_id)?)
+            .bind(&input.desc)
+            .bind(&input.version)
+            .bind(Json(&input.statement))
+            .bind(Json(&input.labels))
+            .bind(Json(&input.annotations));
+        write_row(&self.conn, query, "policy", &input.id, resource_version)
+            .await?;
+        label(&self.conn, "policy", &input.id, &input.labels).await?;
+
+        Ok(())
+    }
+
+    /// remove soft deletes input, when resource_version is given only
+    /// if the stored one is still at it
+    async fn remove(
+        &self,
+        input: &Policy,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
+        let id = input
+            .id
+            .parse::<i64>()
+            .map_err(|err| errors::bad_request(&err))?;
+        if sqlx::query(
+            r#"SELECT COUNT(*) as count FROM "policy_binding" WHERE "policy_id" = $1 AND "deleted" = 0"#,
+        )
+        .bind(id)
+        .fetch_one(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?.try_get::<i64,_>("count").map_err(errors::any)?!=0{
+            return Err(errors::forbidden(&"can't delete policy, because it is used".to_string()));
+        };
+        soft_delete(&self.conn, "policy", &input.id, resource_version).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Interface for PolicyImpl {
+    type T = Policy;
+    type L = ListParams;
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        // the row and its labels are indexed in the same transaction
+        self.conn
+            .scoped(
+                |conn| async move { Self { conn }.write(input, None).await },
+            )
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        self.remove(input, None).await
+    }
+
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        let id = output
+            .id
+            .parse::<i64>()
+            .map_err(|err| errors::bad_request(&err))?;
+        let row = match sqlx::query(
+            r#"SELECT "id","account_id","desc","version","statement",
+                "resource_version","labels","annotations","created_at","updated_at"
+                FROM "policy"
+                WHERE id = $1 AND "deleted" = 0;"#,
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.conn.acquire().await?)
+        .await
+        {
+            Ok(v) => match v {
+                Some(value) => Ok(value),
+                None => Err(errors::not_found("no rows")),
+            },
+            Err(err) => Err(errors::any(err)),
+        }?;
+
+        output.id = row
+            .try_get::<i64, _>("id")
+            .map_err(errors::any)?
+            .to_string();
+        output.account_id = row
+            .try_get::<Option<i64>, _>("account_id")
+            .map_err(errors::any)?
+            .map(|v| v.to_string());
+        output.desc = row.try_get("desc").map_err(errors::any)?;
+        output.version = row.try_get("version").map_err(errors::any)?;
+        output.statement = row
+            .try_get::<Json<Vec<Statement>>, _>("statement")
+            .map_err(errors::any)?
+            .0;
+        output.resource_version = row
+            .try_get::<i64, _>("resource_version")
+            .map_err(errors::any)? as u64;
+        output.labels = row
+            .try_get::<Json<Labels>, _>("labels")
+            .map_err(errors::any)?
+            .0;
+        output.annotations = row
+            .try_get::<Json<Labels>, _>("annotations")
+            .map_err(errors::any)?
+            .0;
+        output.created_at = row.try_get("created_at").map_err(errors::any)?;
+        output.updated_at = row.try_get("updated_at").map_err(errors::any)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, false).await
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let mut filter = opts.filter()?;
+        if !unscoped {
+            filter = filter.not_deleted();
+        }
+        let mut builder =
+            QueryBuilder::new(r#"SELECT COUNT(*) as count FROM "policy""#);
+        filter.push(&mut builder);
+        builder
+            .build()
+            .fetch_one(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?
+            .try_get("count")
+            .map_err(errors::any)
+    }
+
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.conn
+            .scoped(|conn| async move {
+                Self { conn }.write(input, Some(resource_version)).await
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "policy", &input.id).await?;
+                let previous = stored(&store, input).await?;
+                store.write(input, resource_version).await?;
+                Ok(previous)
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "policy", &input.id).await?;
+                discarded(&store, input, resource_version).await
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.remove(input, Some(resource_version)).await
+    }
+
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true).await
+    }
+
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        restore(&self.conn, "policy", &input.id).await
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        purge(&self.conn, "policy", before).await
+    }
+}
+
+#[async_trait]
+impl StatementStore for PolicyImpl {
+    async fn get_statement(&self, req: &Request) -> Result<Vec<Statement>> {
+        let user_id = policy::user_id(req.principal_type, &req.subject)?;
+        let user_type = u8::from(&UserType::from(req.principal_type)) as i16;
+        let bindings_type =
+            u8::from(&BindingsType::from(req.principal_type)) as i16;
+        let rows = sqlx::query(
+            r#"WITH RECURSIVE "member_group" ("id") AS (
+                SELECT "group_id" FROM "group_user"
+                WHERE "user_id" = $1 AND "deleted" = 0
+                AND ("not_before" IS NULL OR "not_before" <= timezone('UTC', now()))
+                AND ("expires_at" IS NULL OR "expires_at" > timezone('UTC', now()))
+                UNION
+                SELECT g."parent_id" FROM "group" g
+                JOIN "member_group" m ON g."id" = m."id"
+                WHERE g."deleted" = 0 AND g."parent_id" IS NOT NULL
+            )
+            SELECT "statement" FROM "policy"
+            WHERE "deleted" = 0 AND "id" IN (
+                SELECT "policy_id" FROM "policy_binding"
+                WHERE "bindings_type" = $2 AND "bindings_id" = $4 AND "deleted" = 0
+                AND ("not_before" IS NULL OR "not_before" <= timezone('UTC', now()))
+                AND ("expires_at" IS NULL OR "expires_at" > timezone('UTC', now()))
+                UNION
+                SELECT a3."policy_id" FROM "member_group" a1
+                JOIN "group" a2 ON a1."id" = a2."id"
+                JOIN "policy_binding" a3 ON a2."id"::VARCHAR = a3."bindings_id"
+                WHERE a2."deleted" = 0 AND a3."bindings_type" = 2 AND
+                a3."deleted" = 0
+                AND (a3."not_before" IS NULL OR a3."not_before" <= timezone('UTC', now()))
+                AND (a3."expires_at" IS NULL OR a3."expires_at" > timezone('UTC', now()))
+                UNION
+                SELECT b3."policy_id" FROM "role_binding" b1
+                JOIN "role" b2 ON b1."role_id" = b2."id"
+                JOIN "policy_binding" b3 ON b2."id"::VARCHAR = b3."bindings_id"
+                WHERE b1."user_type" = $3 AND b1."user_id" = $4 AND
+                b1."deleted" = 0 AND b2."deleted" = 0 AND
+                b3."bindings_type" = 3 AND b3."deleted" = 0
+                AND (b1."not_before" IS NULL OR b1."not_before" <= timezone('UTC', now()))
+                AND (b1."expires_at" IS NULL OR b1."expires_at" > timezone('UTC', now()))
+                AND (b3."not_before" IS NULL OR b3."not_before" <= timezone('UTC', now()))
+                AND (b3."expires_at" IS NULL OR b3."expires_at" > timezone('UTC', now()))
+            );"#,
+        )
+            .bind(user_id)
+            .bind(bindings_type)
+            .bind(user_type)
+            .bind(&req.subject)
+            .fetch_all(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?;
+
+        let mut result = Vec::with_capacity(rows.len());
+
+        for row in rows.iter() {
+            let Json(mut statement) = row
+                .try_get::<Json<Vec<Statement>>, _>("statement")
+                .map_err(errors::any)?;
+            result.append(&mut statement);
+        }
+        Ok(result)
+    }
+
+    async fn get_bindings(
+        &self,
+        principal_type: PrincipalType,
+        subject: &str,
+    ) -> Result<Bindings> {
+        let user_id = policy::user_id(principal_type, subject)?;
+        let user_type = u8::from(&UserType::from(principal_type)) as i16;
+        let bindings_type =
+            u8::from(&BindingsType::from(principal_type)) as i16;
+        let rows = sqlx::query(
+            r#"WITH RECURSIVE "member_group" ("id") AS (
+                SELECT "group_id" FROM "group_user"
+                WHERE "user_id" = $1 AND "deleted" = 0
+                UNION
+                SELECT g."parent_id" FROM "group" g
+                JOIN "member_group" m ON g."id" = m."id"
+                WHERE g."deleted" = 0 AND g."parent_id" IS NOT NULL
+            )
+            SELECT 'group' AS "kind", "id", NULL AS "not_before",
+                NULL AS "expires_at"
+            FROM "member_group"
+            UNION ALL
+            SELECT 'window', "id", "not_before", "expires_at"
+            FROM "group_user" WHERE "user_id" = $1 AND "deleted" = 0
+            UNION ALL
+            SELECT 'role', "role_id", "not_before", "expires_at"
+            FROM "role_binding"
+            WHERE "user_type" = $3 AND "user_id" = $2 AND "deleted" = 0
+            UNION ALL
+            SELECT 'policy', "policy_id", "not_before", "expires_at"
+            FROM "policy_binding"
+            WHERE "deleted" = 0 AND (
+                ("bindings_type" = $4 AND "bindings_id" = $2)
+                OR ("bindings_type" = 2 AND "bindings_id" IN (
+                    SELECT "id"::VARCHAR FROM "member_group"))
+                OR ("bindings_type" = 3 AND "bindings_id" IN (
+                    SELECT "role_id"::VARCHAR FROM "role_binding"
+                    WHERE "user_type" = $3 AND "user_id" = $2 AND "deleted" = 0))
+            );"#,
+        )
+        .bind(user_id)
+        .bind(subject)
+        .bind(user_type)
+        .bind(bindings_type)
+        .fetch_all(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+
+        let mut result = Bindings::default();
+        let mut windows = Vec::with_capacity(rows.len());
+        for row in rows.iter() {
+            windows.push((
+                row.try_get("not_before").map_err(errors::any)?,
+                row.try_get("expires_at").map_err(errors::any)?,
+            ));
+            let id = row.try_get::<i64, _>("id").map_err(errors::any)?;
+            match row.try_get::<&str, _>("kind").map_err(errors::any)? {
+                "group" => result.groups.push(id.to_string()),
+                "role" => result.roles.push(id.to_string()),
+                "policy" => result.policies.push(id.to_string()),
+                _ => {}
+            }
+        }
+        result.changes_at = next_change(windows, Utc::now().naive_utc());
+        Ok(result)
+    }
+}
@@ -0,0 +1,304 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use sqlx::{Postgres, QueryBuilder, Row};
+
+use cim_slo::{errors, Result};
+
+use super::{
+    lock, parse_id, purge, restore, soft_delete, write_query, write_row,
+};
+use crate::{
+    discarded,
+    policy_binding::{ListParams, PolicyBinding},
+    stored, Conn, Interface, List,
+};
+
+#[derive(Clone, Debug)]
+pub struct PolicyBindingImpl {
+    conn: Conn<Postgres>,
+}
+
+impl PolicyBindingImpl {
+    pub fn new(pool: impl Into<Conn<Postgres>>) -> Self {
+        Self { conn: pool.into() }
+    }
+
+    /// select lists the rows matching opts, the soft deleted ones when
+    /// deleted
+    async fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<PolicyBinding>,
+        deleted: bool,
+    ) -> Result<()> {
+        let filter = opts.filter()?.deleted(deleted);
+        // 查询total
+        if !opts.pagination.count_disable {
+            let mut builder = QueryBuilder::new(
+                r#"SELECT COUNT(*) as count FROM "policy_binding""#,
+            );
+            filter.push(&mut builder);
+            output.total = builder
+                .build()
+                .fetch_one(&mut *self.conn.acquire().await?)
+                .await
+                .map_err(errors::any)?
+                .try_get("count")
+                .map_err(errors::any)?;
+        }
+
+        // 查询列表
+        output.limit = opts.pagination.limit;
+        output.offset = opts.pagination.offset;
+
+        let mut builder = QueryBuilder::new(
+            r#"SELECT "id","policy_id","bindings_type","bindings_id",
+                "not_before","expires_at",
+                "resource_version","created_at","updated_at"
+                FROM "policy_binding""#,
+        );
+        opts.pagination
+            .seek(filter, ListParams::SORTABLE)?
+            .push(&mut builder);
+        opts.pagination.push(&mut builder, ListParams::SORTABLE)?;
+        let rows = builder
+            .build()
+            .fetch_all(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?;
+        for row in rows.iter() {
+            output.data.push(PolicyBinding {
+                id: row
+                    .try_get::<i64, _>("id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                policy_id: row
+                    .try_get::<i64, _>("policy_id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                bindings_type: (row
+                    .try_get::<i16, _>("bindings_type")
+                    .map_err(errors::any)?
+                    as u8)
+                    .into(),
+                bindings_id: row.try_get("bindings_id").map_err(errors::any)?,
+                resource_version: row
+                    .try_get::<i64, _>("resource_version")
+                    .map_err(errors::any)?
+                    as u64,
+                not_before: row.try_get("not_before").map_err(errors::any)?,
+                expires_at: row.try_get("expires_at").map_err(errors::any)?,
+                created_at: row.try_get("created_at").map_err(errors::any)?,
+                updated_at: row.try_get("updated_at").map_err(errors::any)?,
+            });
+        }
+
+        output.next_cursor = opts
+            .pagination
+            .next_cursor(&output.data, ListParams::SORTABLE)?;
+        Ok(())
+    }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it
+    async fn write(
+        &self,
+        input: &PolicyBinding,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
+        let binding_type: u8 = (&input.bindings_type).into();
+        let query = write_query(
+            "policy_binding",
+            &[
+                "policy_id",
+                "bindings_type",
+                "bindings_id",
+                "not_before",
+                "expires_at",
+            ],
+            resource_version.is_some(),
+        );
+        let query = sqlx::query(&query)
+            .bind(parse_id(&input.id)?)
+            .bind(parse_id(&input.policy_id)?)
+            .bind(binding_type as i16)
+            .bind(&input.bindings_id)
+            .bind(input.not_before)
+            .bind(input.expires_at);
+        write_row(
+            &self.conn,
+            query,
+            "policy_binding",
+            &input.id,
+            resource_version,
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Interface for PolicyBindingImpl {
+    type T = PolicyBinding;
+    type L = ListParams;
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        self.write(input, None).await
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        soft_delete(&self.conn, "policy_binding", &input.id, None).await
+    }
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        let id = output
+            .id
+            .parse::<i64>()
+            .map_err(|err| errors::bad_request(&err))?;
+        let row = match sqlx::query(
+            r#"SELECT "id","policy_id","bindings_type","bindings_id",
+                "not_before","expires_at",
+                "resource_version","created_at","updated_at"
+                FROM "policy_binding"
+                WHERE id = $1 AND "deleted" = 0;"#,
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.conn.acquire().await?)
+        .await
+        {
+            Ok(v) => match v {
+                Some(value) => Ok(value),
+                None => Err(errors::not_found("no rows")),
+            },
+            Err(err) => Err(errors::any(err)),
+        }?;
+
+        output.id = row
+            .try_get::<i64, _>("id")
+            .map_err(errors::any)?
+            .to_string();
+        output.policy_id = row
+            .try_get::<i64, _>("policy_id")
+            .map_err(errors::any)?
+            .to_string();
+        output.bindings_type = (row
+            .try_get::<i16, _>("bindings_type")
+            .map_err(errors::any)? as u8)
+            .into();
+        output.bindings_id = row.try_get("bindings_id").map_err(errors::any)?;
+        output.resource_version = row
+            .try_get::<i64, _>("resource_version")
+            .map_err(errors::any)? as u64;
+        output.not_before = row.try_get("not_before").map_err(errors::any)?;
+        output.expires_at = row.try_get("expires_at").map_err(errors::any)?;
+        output.created_at = row.try_get("created_at").map_err(errors::any)?;
+        output.updated_at = row.try_get("updated_at").map_err(errors::any)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, false).await
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let mut filter = opts.filter()?;
+        if !unscoped {
+            filter = filter.not_deleted();
+        }
+        let mut builder = QueryBuilder::new(
+            r#"SELECT COUNT(*) as count FROM "policy_binding""#,
+        );
+        filter.push(&mut builder);
+        builder
+            .build()
+            .fetch_one(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?
+            .try_get("count")
+            .map_err(errors::any)
+    }
+
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.write(input, Some(resource_version)).await
+    }
+
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "policy_binding", &input.id).await?;
+                let previous = stored(&store, input).await?;
+                store.write(input, resource_version).await?;
+                Ok(previous)
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "policy_binding", &input.id).await?;
+                discarded(&store, input, resource_version).await
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        soft_delete(
+            &self.conn,
+            "policy_binding",
+            &input.id,
+            Some(resource_version),
+        )
+        .await
+    }
+
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true).await
+    }
+
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        restore(&self.conn, "policy_binding", &input.id).await
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        purge(&self.conn, "policy_binding", before).await
+    }
+}
@@ -0,0 +1,218 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use serde_json::value::RawValue;
+use sqlx::{types::Json, Postgres, Row};
+
+use cim_slo::{errors, Result};
+
+use super::{parse_id, purge};
+use crate::{refresh_token::RefreshToken, Claim, Conn, Interface, List};
+
+#[derive(Clone, Debug)]
+pub struct RefreshTokenImpl {
+    conn: Conn<Postgres>,
+}
+
+impl RefreshTokenImpl {
+    pub fn new(pool: impl Into<Conn<Postgres>>) -> Self {
+        Self { conn: pool.into() }
+    }
+}
+
+#[async_trait]
+impl Interface for RefreshTokenImpl {
+    type T = RefreshToken;
+    type L = ();
+
+    #[tracing::instrument]
+    async fn put(&self, content: &Self::T) -> Result<()> {
+        let client_id = content
+            .client_id
+            .parse::<i64>()
+            .map_err(|err| errors::bad_request(&err))?;
+
+        sqlx::query(
+            r#"INSERT INTO "refresh_token"
+            ("id","client_id","scopes","nonce","token","obsolete_token",
+            "claim","connector_id","connector_data","last_used_at")
+            VALUES($1,$2,$3,$4,$5,$6,$7,$8,$9,$10)
+            ON CONFLICT ("id") DO UPDATE SET
+            "client_id" = EXCLUDED."client_id",
+            "scopes" = EXCLUDED."scopes",
+            "nonce" = EXCLUDED."nonce",
+            "token" = EXCLUDED."token",
+            "obsolete_token" = EXCLUDED."obsolete_token",
+            "claim" = EXCLUDED."claim",
+            "connector_id" = EXCLUDED."connector_id",
+            "connector_data" = EXCLUDED."connector_data",
+            "last_used_at" = EXCLUDED."last_used_at",
+            "deleted" = 0,
+            "deleted_at" = NULL,
+            "updated_at" = CURRENT_TIMESTAMP;"#,
+        )
+        .bind(parse_id(&content.id)?)
+        .bind(client_id)
+        .bind(Json(&content.scopes))
+        .bind(&content.nonce)
+        .bind(&content.token)
+        .bind(&content.obsolete_token)
+        .bind(Json(&content.claim))
+        .bind(&content.connector_id)
+        .bind(content.connector_data.as_ref().map(|v| v.to_string()))
+        .bind(content.last_used_at)
+        .execute(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        let id = input
+            .id
+            .parse::<i64>()
+            .map_err(|err| errors::bad_request(&err))?;
+
+        sqlx::query(
+            r#"UPDATE "refresh_token" SET "deleted" = "id","deleted_at"= CURRENT_TIMESTAMP
+            WHERE id = $1 AND "deleted" = 0;"#,
+        )
+        .bind(id)
+        .execute(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+        Ok(())
+    }
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        let id = output
+            .id
+            .parse::<i64>()
+            .map_err(|err| errors::bad_request(&err))?;
+
+        let row = match sqlx::query(
+            r#"SELECT "id","client_id","scopes","nonce","token","obsolete_token",
+            "claim","connector_id","connector_data","last_used_at","created_at","updated_at"
+            FROM "refresh_token"
+            WHERE id = $1 AND deleted = 0;"#,
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.conn.acquire().await?)
+        .await
+        {
+            Ok(v) => match v {
+                Some(value) => Ok(value),
+                None => Err(errors::not_found("no rows")),
+            },
+            Err(err) => Err(errors::any(err)),
+        }?;
+
+        output.id = row
+            .try_get::<i64, _>("id")
+            .map_err(errors::any)?
+            .to_string();
+        output.client_id = row
+            .try_get::<i64, _>("client_id")
+            .map_err(errors::any)?
+            .to_string();
+        output.scopes = row
+            .try_get::<Json<Vec<String>>, _>("scopes")
+            .map_err(errors::any)?
+            .0;
+        output.nonce = row.try_get("nonce").map_err(errors::any)?;
+        output.token = row.try_get("token").map_err(errors::any)?;
+        output.obsolete_token =
+            row.try_get("obsolete_token").map_err(errors::any)?;
+        output.claim = row
+            .try_get::<Json<Claim>, _>("claim")
+            .map_err(errors::any)?
+            .0;
+        output.connector_id =
+            row.try_get("connector_id").map_err(errors::any)?;
+        output.connector_data = row
+            .try_get::<Option<String>, _>("connector_data")
+            .map_err(errors::any)?
+            .map(|v| RawValue::from_string(v).unwrap());
+        output.created_at = row.try_get("created_at").map_err(errors::any)?;
+        output.updated_at = row.try_get("updated_at").map_err(errors::any)?;
+        output.last_used_at =
+            row.try_get("last_used_at").map_err(errors::any)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        _opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        let rows = sqlx::query(
+            r#"SELECT "id","client_id","scopes","nonce","token","obsolete_token",
+            "claim","connector_id","connector_data","last_used_at","created_at","updated_at"
+            FROM "refresh_token"
+            WHERE "deleted" = 0;"#,
+        )
+        .fetch_all(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+        for row in rows.iter() {
+            output.data.push(Self::T {
+                id: row
+                    .try_get::<i64, _>("id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                client_id: row
+                    .try_get::<i64, _>("client_id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                scopes: row
+                    .try_get::<Json<Vec<String>>, _>("scopes")
+                    .map_err(errors::any)?
+                    .0,
+                nonce: row.try_get("nonce").map_err(errors::any)?,
+                token: row.try_get("token").map_err(errors::any)?,
+                obsolete_token: row
+                    .try_get("obsolete_token")
+                    .map_err(errors::any)?,
+                claim: row
+                    .try_get::<Json<Claim>, _>("claim")
+                    .map_err(errors::any)?
+                    .0,
+                connector_id: row
+                    .try_get("connector_id")
+                    .map_err(errors::any)?,
+                connector_data: row
+                    .try_get::<Option<String>, _>("connector_data")
+                    .map_err(errors::any)?
+                    .map(|v| RawValue::from_string(v).unwrap()),
+                last_used_at: row
+                    .try_get("last_used_at")
+                    .map_err(errors::any)?,
+                created_at: row.try_get("created_at").map_err(errors::any)?,
+                updated_at: row.try_get("updated_at").map_err(errors::any)?,
+            });
+        }
+        output.total = output.data.len() as i64;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, _opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let sql = if unscoped {
+            r#"SELECT COUNT(*) as count FROM "refresh_token";"#
+        } else {
+            r#"SELECT COUNT(*) as count FROM "refresh_token" WHERE "deleted" = 0;"#
+        };
+        sqlx::query(sql)
+            .fetch_one(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?
+            .try_get("count")
+            .map_err(errors::any)
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        purge(&self.conn, "refresh_token", before).await
+    }
+}
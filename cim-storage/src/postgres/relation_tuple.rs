@@ -0,0 +1,310 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use sqlx::{Postgres, QueryBuilder, Row};
+
+use cim_pim::rebac::{Subject, TupleReader};
+use cim_slo::{errors, Result};
+
+use super::{
+    lock, parse_id, purge, restore, soft_delete, write_query, write_row,
+};
+use crate::{
+    discarded,
+    relation_tuple::{ListParams, RelationTuple},
+    stored, Conn, Interface, List,
+};
+
+#[derive(Clone, Debug)]
+pub struct RelationTupleImpl {
+    conn: Conn<Postgres>,
+}
+
+impl RelationTupleImpl {
+    pub fn new(pool: impl Into<Conn<Postgres>>) -> Self {
+        Self { conn: pool.into() }
+    }
+
+    /// select lists the rows matching opts, the soft deleted ones when
+    /// deleted
+    async fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<RelationTuple>,
+        deleted: bool,
+    ) -> Result<()> {
+        let filter = opts.filter()?.deleted(deleted);
+        // 查询total
+        if !opts.pagination.count_disable {
+            let mut builder = QueryBuilder::new(
+                r#"SELECT COUNT(*) as count FROM "relation_tuple""#,
+            );
+            filter.push(&mut builder);
+            output.total = builder
+                .build()
+                .fetch_one(&mut *self.conn.acquire().await?)
+                .await
+                .map_err(errors::any)?
+                .try_get("count")
+                .map_err(errors::any)?;
+        }
+
+        // 查询列表
+        output.limit = opts.pagination.limit;
+        output.offset = opts.pagination.offset;
+
+        let mut builder = QueryBuilder::new(
+            r#"SELECT "id","account_id","object","relation","subject",
+                "resource_version","created_at","updated_at"
+                FROM "relation_tuple""#,
+        );
+        opts.pagination
+            .seek(filter, ListParams::SORTABLE)?
+            .push(&mut builder);
+        opts.pagination.push(&mut builder, ListParams::SORTABLE)?;
+        let rows = builder
+            .build()
+            .fetch_all(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?;
+        for row in rows.iter() {
+            output.data.push(RelationTuple {
+                id: row
+                    .try_get::<i64, _>("id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                account_id: row
+                    .try_get::<i64, _>("account_id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                object: row.try_get("object").map_err(errors::any)?,
+                relation: row.try_get("relation").map_err(errors::any)?,
+                subject: row.try_get("subject").map_err(errors::any)?,
+                resource_version: row
+                    .try_get::<i64, _>("resource_version")
+                    .map_err(errors::any)?
+                    as u64,
+                created_at: row.try_get("created_at").map_err(errors::any)?,
+                updated_at: row.try_get("updated_at").map_err(errors::any)?,
+            });
+        }
+
+        output.next_cursor = opts
+            .pagination
+            .next_cursor(&output.data, ListParams::SORTABLE)?;
+        Ok(())
+    }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it
+    async fn write(
+        &self,
+        input: &RelationTuple,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
+        let query = write_query(
+            "relation_tuple",
+            &["account_id", "object", "relation", "subject"],
+            resource_version.is_some(),
+        );
+        let query = sqlx::query(&query)
+            .bind(parse_id(&input.id)?)
+            .bind(parse_id(&input.account_id)?)
+            .bind(&input.object)
+            .bind(&input.relation)
+            .bind(&input.subject);
+        write_row(
+            &self.conn,
+            query,
+            "relation_tuple",
+            &input.id,
+            resource_version,
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Interface for RelationTupleImpl {
+    type T = RelationTuple;
+    type L = ListParams;
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        self.write(input, None).await
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        soft_delete(&self.conn, "relation_tuple", &input.id, None).await
+    }
+
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        let id = output
+            .id
+            .parse::<i64>()
+            .map_err(|err| errors::bad_request(&err))?;
+        let row = match sqlx::query(
+            r#"SELECT "id","account_id","object","relation","subject",
+                "resource_version","created_at","updated_at"
+                FROM "relation_tuple"
+                WHERE id = $1 AND "deleted" = 0;"#,
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.conn.acquire().await?)
+        .await
+        {
+            Ok(v) => match v {
+                Some(value) => Ok(value),
+                None => Err(errors::not_found("no rows")),
+            },
+            Err(err) => Err(errors::any(err)),
+        }?;
+
+        output.id = row
+            .try_get::<i64, _>("id")
+            .map_err(errors::any)?
+            .to_string();
+        output.account_id = row
+            .try_get::<i64, _>("account_id")
+            .map_err(errors::any)?
+            .to_string();
+        output.object = row.try_get("object").map_err(errors::any)?;
+        output.relation = row.try_get("relation").map_err(errors::any)?;
+        output.subject = row.try_get("subject").map_err(errors::any)?;
+        output.resource_version = row
+            .try_get::<i64, _>("resource_version")
+            .map_err(errors::any)? as u64;
+        output.created_at = row.try_get("created_at").map_err(errors::any)?;
+        output.updated_at = row.try_get("updated_at").map_err(errors::any)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, false).await
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let mut filter = opts.filter()?;
+        if !unscoped {
+            filter = filter.not_deleted();
+        }
+        let mut builder = QueryBuilder::new(
+            r#"SELECT COUNT(*) as count FROM "relation_tuple""#,
+        );
+        filter.push(&mut builder);
+        builder
+            .build()
+            .fetch_one(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?
+            .try_get("count")
+            .map_err(errors::any)
+    }
+
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.write(input, Some(resource_version)).await
+    }
+
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "relation_tuple", &input.id).await?;
+                let previous = stored(&store, input).await?;
+                store.write(input, resource_version).await?;
+                Ok(previous)
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "relation_tuple", &input.id).await?;
+                discarded(&store, input, resource_version).await
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        soft_delete(
+            &self.conn,
+            "relation_tuple",
+            &input.id,
+            Some(resource_version),
+        )
+        .await
+    }
+
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true).await
+    }
+
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        restore(&self.conn, "relation_tuple", &input.id).await
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        purge(&self.conn, "relation_tuple", before).await
+    }
+}
+
+#[async_trait]
+impl TupleReader for RelationTupleImpl {
+    async fn read(
+        &self,
+        object: &str,
+        relation: &str,
+    ) -> anyhow::Result<Vec<Subject>> {
+        let rows = sqlx::query(
+            r#"SELECT "subject" FROM "relation_tuple"
+            WHERE "object" = $1 AND "relation" = $2 AND "deleted" = 0;"#,
+        )
+        .bind(object)
+        .bind(relation)
+        .fetch_all(&mut *self.conn.acquire().await?)
+        .await?;
+        let mut result = Vec::with_capacity(rows.len());
+        for row in rows.iter() {
+            result.push(row.try_get::<String, _>("subject")?.parse()?);
+        }
+        Ok(result)
+    }
+}
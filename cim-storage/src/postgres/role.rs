@@ -0,0 +1,331 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use sqlx::{types::Json, Postgres, QueryBuilder, Row};
+
+use cim_slo::{errors, Result};
+
+use super::{
+    label, lock, parse_id, purge, restore, soft_delete, write_query, write_row,
+};
+use crate::{
+    discarded,
+    label::Labels,
+    role::{ListParams, Role},
+    stored, Conn, Interface, List,
+};
+
+#[derive(Clone, Debug)]
+pub struct RoleImpl {
+    conn: Conn<Postgres>,
+}
+
+impl RoleImpl {
+    pub fn new(pool: impl Into<Conn<Postgres>>) -> Self {
+        Self { conn: pool.into() }
+    }
+
+    /// select lists the rows matching opts, the soft deleted ones when
+    /// deleted
+    async fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<Role>,
+        deleted: bool,
+    ) -> Result<()> {
+        let filter = opts.filter()?.deleted(deleted);
+        // 查询total
+        if !opts.pagination.count_disable {
+            let mut builder =
+                QueryBuilder::new(r#"SELECT COUNT(*) as count FROM "role""#);
+            filter.push(&mut builder);
+            output.total = builder
+                .build()
+                .fetch_one(&mut *self.conn.acquire().await?)
+                .await
+                .map_err(errors::any)?
+                .try_get("count")
+                .map_err(errors::any)?;
+        }
+
+        // 查询列表
+        output.limit = opts.pagination.limit;
+        output.offset = opts.pagination.offset;
+
+        let mut builder = QueryBuilder::new(
+            r#"SELECT "id","account_id","name","desc",
+                "resource_version","labels","annotations","created_at","updated_at"
+                FROM "role""#,
+        );
+        opts.pagination
+            .seek(filter, ListParams::SORTABLE)?
+            .push(&mut builder);
+        opts.pagination.push(&mut builder, ListParams::SORTABLE)?;
+        let rows = builder
+            .build()
+            .fetch_all(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?;
+        for row in rows.iter() {
+            output.data.push(Role {
+                id: row
+                    .try_get::<i64, _>("id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                account_id: row
+                    .try_get::<i64, _>("account_id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                name: row.try_get("name").map_err(errors::any)?,
+                desc: row.try_get("desc").map_err(errors::any)?,
+                resource_version: row
+                    .try_get::<i64, _>("resource_version")
+                    .map_err(errors::any)?
+                    as u64,
+                labels: row
+                    .try_get::<Json<Labels>, _>("labels")
+                    .map_err(errors::any)?
+                    .0,
+                annotations: row
+                    .try_get::<Json<Labels>, _>("annotations")
+                    .map_err(errors::any)?
+                    .0,
+                created_at: row.try_get("created_at").map_err(errors::any)?,
+                updated_at: row.try_get("updated_at").map_err(errors::any)?,
+            });
+        }
+
+        output.next_cursor = opts
+            .pagination
+            .next_cursor(&output.data, ListParams::SORTABLE)?;
+        Ok(())
+    }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it
+    async fn write(
+        &self,
+        input: &Role,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
+        let query = write_query(
+            "role",
+            &["account_id", "name", "desc", "labels", "annotations"],
+            resource_version.is_some(),
+        );
+        let query = sqlx::query(&query)
+            .bind(parse_id(&input.id)?)
+            .bind(parse_id(&input.account_id)?)
+            .bind(&input.name)
+            .bind(&input.desc)
+            .bind(Json(&input.labels))
+            .bind(Json(&input.annotations));
+        write_row(&self.conn, query, "role", &input.id, resource_version)
+            .await?;
+        label(&self.conn, "role", &input.id, &input.labels).await?;
+
+        Ok(())
+    }
+
+    /// remove soft deletes input, when resource_version is given only
+    /// if the stored one is still at it
+    async fn remove(
+        &self,
+        input: &Role,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
+        let id = input
+            .id
+            .parse::<i64>()
+            .map_err(|err| errors::bad_request(&err))?;
+        if sqlx::query(
+            r#"SELECT COUNT(*) as count FROM "role_bindings" WHERE "role_id" = $1 AND "deleted" = 0"#,
+        )
+        .bind(id)
+        .fetch_one(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?.try_get::<i64,_>("count").map_err(errors::any)?!=0{
+            return Err(errors::forbidden(&"can't delete role, because it is attached to user".to_string()));
+        };
+        if sqlx::query(
+            r#"SELECT COUNT(*) as count FROM "policy_binding" WHERE "bindings_type" = 3 AND "bindings_id" = $1 AND "deleted" = 0"#,
+        )
+        .bind(id.to_string())
+        .fetch_one(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?.try_get::<i64,_>("count").map_err(errors::any)?!=0{
+            return Err(errors::forbidden(&"can't delete role, because it is attached by policy".to_string()));
+        };
+        soft_delete(&self.conn, "role", &input.id, resource_version).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Interface for RoleImpl {
+    type T = Role;
+    type L = ListParams;
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        // the row and its labels are indexed in the same transaction
+        self.conn
+            .scoped(
+                |conn| async move { Self { conn }.write(input, None).await },
+            )
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        self.remove(input, None).await
+    }
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        let id = output
+            .id
+            .parse::<i64>()
+            .map_err(|err| errors::bad_request(&err))?;
+        let row = match sqlx::query(
+            r#"SELECT "id","account_id","name","desc",
+                "resource_version","labels","annotations","created_at","updated_at"
+                FROM "role"
+                WHERE id = $1 AND "deleted" = 0;"#,
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.conn.acquire().await?)
+        .await
+        {
+            Ok(v) => match v {
+                Some(value) => Ok(value),
+                None => Err(errors::not_found("no rows")),
+            },
+            Err(err) => Err(errors::any(err)),
+        }?;
+
+        output.id = row
+            .try_get::<i64, _>("id")
+            .map_err(errors::any)?
+            .to_string();
+        output.account_id = row
+            .try_get::<i64, _>("account_id")
+            .map_err(errors::any)?
+            .to_string();
+        output.name = row.try_get("name").map_err(errors::any)?;
+        output.desc = row.try_get("desc").map_err(errors::any)?;
+        output.resource_version = row
+            .try_get::<i64, _>("resource_version")
+            .map_err(errors::any)? as u64;
+        output.labels = row
+            .try_get::<Json<Labels>, _>("labels")
+            .map_err(errors::any)?
+            .0;
+        output.annotations = row
+            .try_get::<Json<Labels>, _>("annotations")
+            .map_err(errors::any)?
+            .0;
+        output.created_at = row.try_get("created_at").map_err(errors::any)?;
+        output.updated_at = row.try_get("updated_at").map_err(errors::any)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, false).await
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let mut filter = opts.filter()?;
+        if !unscoped {
+            filter = filter.not_deleted();
+        }
+        let mut builder =
+            QueryBuilder::new(r#"SELECT COUNT(*) as count FROM "role""#);
+        filter.push(&mut builder);
+        builder
+            .build()
+            .fetch_one(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?
+            .try_get("count")
+            .map_err(errors::any)
+    }
+
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.conn
+            .scoped(|conn| async move {
+                Self { conn }.write(input, Some(resource_version)).await
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "role", &input.id).await?;
+                let previous = stored(&store, input).await?;
+                store.write(input, resource_version).await?;
+                Ok(previous)
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "role", &input.id).await?;
+                discarded(&store, input, resource_version).await
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.remove(input, Some(resource_version)).await
+    }
+
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true).await
+    }
+
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        restore(&self.conn, "role", &input.id).await
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        purge(&self.conn, "role", before).await
+    }
+}
@@ -0,0 +1,493 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use rand::Rng;
+use sqlx::{types::Json, Postgres, QueryBuilder, Row};
+
+use cim_slo::{
+    crypto::password::{encrypt, is_encoded},
+    errors, Result,
+};
+
+use super::{
+    label, lock, parse_id, purge, restore, soft_delete, write_query, write_row,
+};
+use crate::{
+    discarded,
+    label::Labels,
+    stored,
+    user::{ListParams, User},
+    ClaimOpts, Conn, Interface, List,
+};
+
+#[derive(Clone, Debug)]
+pub struct UserImpl {
+    conn: Conn<Postgres>,
+}
+
+impl UserImpl {
+    pub fn new(pool: impl Into<Conn<Postgres>>) -> Self {
+        Self { conn: pool.into() }
+    }
+
+    /// select lists the rows matching opts, the soft deleted ones when
+    /// deleted
+    async fn select(
+        &self,
+        opts: &ListParams,
+        output: &mut List<User>,
+        deleted: bool,
+    ) -> Result<()> {
+        let filter = opts.filter()?.deleted(deleted);
+        // 查询total
+        if !opts.pagination.count_disable {
+            let mut builder =
+                QueryBuilder::new(r#"SELECT COUNT(*) as count FROM "user""#);
+            filter.push(&mut builder);
+            output.total = builder
+                .build()
+                .fetch_one(&mut *self.conn.acquire().await?)
+                .await
+                .map_err(errors::any)?
+                .try_get("count")
+                .map_err(errors::any)?;
+        }
+
+        // 查询列表
+        output.limit = opts.pagination.limit;
+        output.offset = opts.pagination.offset;
+
+        let mut builder = QueryBuilder::new(
+            r#"SELECT "id","account_id","desc","email","email_verified",
+                "name","given_name","family_name","middle_name","nickname",
+                "preferred_username","profile","picture","website","gender",
+                "birthday","birthdate","zoneinfo","locale","phone_number",
+                "phone_number_verified","address",
+                "resource_version","labels","annotations","created_at","updated_at"
+                FROM "user""#,
+        );
+        opts.pagination
+            .seek(filter, ListParams::SORTABLE)?
+            .push(&mut builder);
+        opts.pagination.push(&mut builder, ListParams::SORTABLE)?;
+        let rows = builder
+            .build()
+            .fetch_all(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?;
+        for row in rows.iter() {
+            let mut address = None;
+            if let Some(v) = row
+                .try_get::<Option<String>, _>("address")
+                .map_err(errors::any)?
+            {
+                address = Some(serde_json::from_str(&v).map_err(errors::any)?);
+            }
+
+            output.data.push(User {
+                id: row
+                    .try_get::<i64, _>("id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                account_id: row
+                    .try_get::<i64, _>("account_id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                desc: row.try_get("desc").map_err(errors::any)?,
+                claim: ClaimOpts {
+                    email: row.try_get("email").map_err(errors::any)?,
+                    email_verified: row
+                        .try_get("email_verified")
+                        .map_err(errors::any)?,
+                    name: row.try_get("name").map_err(errors::any)?,
+                    given_name: row
+                        .try_get("given_name")
+                        .map_err(errors::any)?,
+                    family_name: row
+                        .try_get("family_name")
+                        .map_err(errors::any)?,
+                    middle_name: row
+                        .try_get("middle_name")
+                        .map_err(errors::any)?,
+                    nickname: row.try_get("nickname").map_err(errors::any)?,
+                    preferred_username: row
+                        .try_get("preferred_username")
+                        .map_err(errors::any)?,
+                    profile: row.try_get("profile").map_err(errors::any)?,
+                    picture: row.try_get("picture").map_err(errors::any)?,
+                    website: row.try_get("website").map_err(errors::any)?,
+                    gender: row.try_get("gender").map_err(errors::any)?,
+                    birthday: row.try_get("birthday").map_err(errors::any)?,
+                    birthdate: row.try_get("birthdate").map_err(errors::any)?,
+                    zoneinfo: row.try_get("zoneinfo").map_err(errors::any)?,
+                    locale: row.try_get("locale").map_err(errors::any)?,
+                    phone_number: row
+                        .try_get("phone_number")
+                        .map_err(errors::any)?,
+                    phone_number_verified: row
+                        .try_get("phone_number_verified")
+                        .map_err(errors::any)?,
+                    address,
+                },
+                secret: None,
+                password: None,
+                resource_version: row
+                    .try_get::<i64, _>("resource_version")
+                    .map_err(errors::any)?
+                    as u64,
+                labels: row
+                    .try_get::<Json<Labels>, _>("labels")
+                    .map_err(errors::any)?
+                    .0,
+                annotations: row
+                    .try_get::<Json<Labels>, _>("annotations")
+                    .map_err(errors::any)?
+                    .0,
+                created_at: row.try_get("created_at").map_err(errors::any)?,
+                updated_at: row.try_get("updated_at").map_err(errors::any)?,
+            });
+        }
+
+        output.next_cursor = opts
+            .pagination
+            .next_cursor(&output.data, ListParams::SORTABLE)?;
+        Ok(())
+    }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it
+    async fn write(
+        &self,
+        input: &User,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
+        let mut address = None;
+        if let Some(v) = &input.claim.address {
+            address = Some(serde_json::to_string(&v).map_err(errors::any)?)
+        }
+
+        let temp_password = input
+            .password
+            .as_ref()
+            .ok_or_else(|| errors::bad_request("password is required"))?;
+        // a hashed password is kept along with the secret it was hashed with
+        let (secret, password) = match &input.secret {
+            Some(secret) if is_encoded(temp_password) => {
+                (secret.clone(), temp_password.clone())
+            }
+            _ => {
+                let secret = rand::rng()
+                    .sample_iter(&rand::distr::Alphanumeric)
+                    .take(64)
+                    .map(char::from)
+                    .collect::<String>();
+                let password = encrypt(temp_password, &secret)?;
+                (secret, password)
+            }
+        };
+
+        let query = write_query(
+            "user",
+            &[
+                "account_id",
+                "desc",
+                "email",
+                "email_verified",
+                "name",
+                "given_name",
+                "family_name",
+                "middle_name",
+                "nickname",
+                "preferred_username",
+                "profile",
+                "picture",
+                "website",
+                "gender",
+                "birthday",
+                "birthdate",
+                "zoneinfo",
+                "locale",
+                "phone_number",
+                "phone_number_verified",
+                "address",
+                "secret",
+                "password",
+                "labels",
+                "annotations",
+            ],
+            resource_version.is_some(),
+        );
+        let query = sqlx::query(&query)
+            .bind(parse_id(&input.id)?)
+            .bind(parse_id(&input.account_id)?)
+            .bind(&input.desc)
+            .bind(&input.claim.email)
+            .bind(input.claim.email_verified)
+            .bind(&input.claim.name)
+            .bind(&input.claim.given_name)
+            .bind(&input.claim.family_name)
+            .bind(&input.claim.middle_name)
+            .bind(&input.claim.nickname)
+            .bind(&input.claim.preferred_username)
+            .bind(&input.claim.profile)
+            .bind(&input.claim.picture)
+            .bind(&input.claim.website)
+            .bind(&input.claim.gender)
+            .bind(&input.claim.birthday)
+            .bind(&input.claim.birthdate)
+            .bind(&input.claim.zoneinfo)
+            .bind(&input.claim.locale)
+            .bind(&input.claim.phone_number)
+            .bind(input.claim.phone_number_verified)
+            .bind(address)
+            .bind(secret)
+            .bind(password)
+            .bind(Json(&input.labels))
+            .bind(Json(&input.annotations));
+        write_row(&self.conn, query, "user", &input.id, resource_version)
+            .await?;
+        label(&self.conn, "user", &input.id, &input.labels).await?;
+        Ok(())
+    }
+
+    /// remove soft deletes input, when resource_version is given only
+    /// if the stored one is still at it
+    async fn remove(
+        &self,
+        input: &User,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
+        let id = input
+            .id
+            .parse::<i64>()
+            .map_err(|err| errors::bad_request(&err))?;
+        if sqlx::query(
+            r#"SELECT COUNT(*) as count FROM "group_user" WHERE "user_id" = $1 AND "deleted" = 0"#,
+        )
+        .bind(id)
+        .fetch_one(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?.try_get::<i64,_>("count").map_err(errors::any)?!=0{
+            return Err(errors::forbidden(&"can't delete user, because it is attached to group".to_string()));
+        };
+        if sqlx::query(
+            r#"SELECT COUNT(*) as count FROM "policy_binding" WHERE "bindings_type" = 1 AND "bindings_id" = $1 AND "deleted" = 0"#,
+        )
+        .bind(id.to_string())
+        .fetch_one(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?.try_get::<i64,_>("count").map_err(errors::any)?!=0{
+            return Err(errors::forbidden(&"can't delete user, because it is attached by policy".to_string()));
+        };
+        soft_delete(&self.conn, "user", &input.id, resource_version).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Interface for UserImpl {
+    type T = User;
+    type L = ListParams;
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        // the row and its labels are indexed in the same transaction
+        self.conn
+            .scoped(
+                |conn| async move { Self { conn }.write(input, None).await },
+            )
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        self.remove(input, None).await
+    }
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        let id = output
+            .id
+            .parse::<i64>()
+            .map_err(|err| errors::bad_request(&err))?;
+        let row = match sqlx::query(
+            r#"SELECT "id","account_id","desc","email","email_verified",
+                "name","given_name","family_name","middle_name","nickname",
+                "preferred_username","profile","picture","website","gender",
+                "birthday","birthdate","zoneinfo","locale","phone_number",
+                "phone_number_verified","address","secret","password",
+                "resource_version","labels","annotations","created_at","updated_at"
+                FROM "user"
+                WHERE id = $1 AND "deleted" = 0;"#,
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.conn.acquire().await?)
+        .await
+        {
+            Ok(v) => match v {
+                Some(value) => Ok(value),
+                None => Err(errors::not_found("no rows")),
+            },
+            Err(err) => Err(errors::any(err)),
+        }?;
+
+        let mut address = None;
+        if let Some(v) = row
+            .try_get::<Option<String>, _>("address")
+            .map_err(errors::any)?
+        {
+            address = Some(serde_json::from_str(&v).map_err(errors::any)?);
+        }
+
+        output.id = row
+            .try_get::<i64, _>("id")
+            .map_err(errors::any)?
+            .to_string();
+        output.account_id = row
+            .try_get::<i64, _>("account_id")
+            .map_err(errors::any)?
+            .to_string();
+        output.resource_version = row
+            .try_get::<i64, _>("resource_version")
+            .map_err(errors::any)? as u64;
+        output.labels = row
+            .try_get::<Json<Labels>, _>("labels")
+            .map_err(errors::any)?
+            .0;
+        output.annotations = row
+            .try_get::<Json<Labels>, _>("annotations")
+            .map_err(errors::any)?
+            .0;
+        output.created_at = row.try_get("created_at").map_err(errors::any)?;
+        output.updated_at = row.try_get("updated_at").map_err(errors::any)?;
+        output.desc = row.try_get("desc").map_err(errors::any)?;
+        output.claim = ClaimOpts {
+            email: row.try_get("email").map_err(errors::any)?,
+            email_verified: row
+                .try_get("email_verified")
+                .map_err(errors::any)?,
+            name: row.try_get("name").map_err(errors::any)?,
+            given_name: row.try_get("given_name").map_err(errors::any)?,
+            family_name: row.try_get("family_name").map_err(errors::any)?,
+            middle_name: row.try_get("middle_name").map_err(errors::any)?,
+            nickname: row.try_get("nickname").map_err(errors::any)?,
+            preferred_username: row
+                .try_get("preferred_username")
+                .map_err(errors::any)?,
+            profile: row.try_get("profile").map_err(errors::any)?,
+            picture: row.try_get("picture").map_err(errors::any)?,
+            website: row.try_get("website").map_err(errors::any)?,
+            gender: row.try_get("gender").map_err(errors::any)?,
+            birthday: row.try_get("birthday").map_err(errors::any)?,
+            birthdate: row.try_get("birthdate").map_err(errors::any)?,
+            zoneinfo: row.try_get("zoneinfo").map_err(errors::any)?,
+            locale: row.try_get("locale").map_err(errors::any)?,
+            phone_number: row.try_get("phone_number").map_err(errors::any)?,
+            phone_number_verified: row
+                .try_get("phone_number_verified")
+                .map_err(errors::any)?,
+            address,
+        };
+        output.secret = row.try_get("secret").map_err(errors::any)?;
+        output.password = row.try_get("password").map_err(errors::any)?;
+        Ok(())
+    }
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, false).await
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let mut filter = opts.filter()?;
+        if !unscoped {
+            filter = filter.not_deleted();
+        }
+        let mut builder =
+            QueryBuilder::new(r#"SELECT COUNT(*) as count FROM "user""#);
+        filter.push(&mut builder);
+        builder
+            .build()
+            .fetch_one(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?
+            .try_get("count")
+            .map_err(errors::any)
+    }
+
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.conn
+            .scoped(|conn| async move {
+                Self { conn }.write(input, Some(resource_version)).await
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "user", &input.id).await?;
+                let previous = stored(&store, input).await?;
+                store.write(input, resource_version).await?;
+                Ok(previous)
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "user", &input.id).await?;
+                discarded(&store, input, resource_version).await
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.remove(input, Some(resource_version)).await
+    }
+
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(opts, output, true).await
+    }
+
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        restore(&self.conn, "user", &input.id).await
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        purge(&self.conn, "user", before).await
+    }
+}
@@ -0,0 +1,804 @@
+use std::cmp::Ordering;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::{Database, QueryBuilder};
+
+use cim_slo::{errors, Result};
+
+use crate::Pagination;
+
+/// Dialect is the per database part of the query builder
+pub trait Dialect: Database {
+    const QUOTE: char;
+    /// TEXT is the type a value is cast to, to compare it with a text column
+    const TEXT: &'static str;
+
+    fn bind(builder: &mut QueryBuilder<'_, Self>, param: Param);
+}
+
+impl Dialect for sqlx::MySql {
+    const QUOTE: char = '`';
+    const TEXT: &'static str = "CHAR";
+
+    fn bind(builder: &mut QueryBuilder<'_, Self>, param: Param) {
+        match param {
+            Param::Int(v) => builder.push_bind(v),
+            Param::Str(v) => builder.push_bind(v),
+            Param::Time(v) => builder.push_bind(v),
+        };
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Dialect for sqlx::Sqlite {
+    const QUOTE: char = '`';
+    const TEXT: &'static str = "TEXT";
+
+    fn bind(builder: &mut QueryBuilder<'_, Self>, param: Param) {
+        match param {
+            Param::Int(v) => builder.push_bind(v),
+            Param::Str(v) => builder.push_bind(v),
+            Param::Time(v) => builder.push_bind(v),
+        };
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl Dialect for sqlx::Postgres {
+    const QUOTE: char = '"';
+    const TEXT: &'static str = "VARCHAR";
+
+    fn bind(builder: &mut QueryBuilder<'_, Self>, param: Param) {
+        match param {
+            Param::Int(v) => builder.push_bind(v),
+            Param::Str(v) => builder.push_bind(v),
+            Param::Time(v) => builder.push_bind(v),
+        };
+    }
+}
+
+/// Param is a value bound to a query, it never becomes part of the sql text
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Serialize, Deserialize)]
+pub enum Param {
+    Int(i64),
+    Str(String),
+    Time(NaiveDateTime),
+}
+
+impl Param {
+    /// id binds ids as numbers like the tables store them
+    pub fn id(v: &str) -> Self {
+        match v.parse() {
+            Ok(v) => Param::Int(v),
+            Err(_) => Param::Str(v.to_owned()),
+        }
+    }
+}
+
+impl From<i64> for Param {
+    fn from(v: i64) -> Self {
+        Param::Int(v)
+    }
+}
+
+impl From<u8> for Param {
+    fn from(v: u8) -> Self {
+        Param::Int(v.into())
+    }
+}
+
+impl From<&str> for Param {
+    fn from(v: &str) -> Self {
+        Param::Str(v.to_owned())
+    }
+}
+
+impl From<&String> for Param {
+    fn from(v: &String) -> Self {
+        Param::Str(v.clone())
+    }
+}
+
+impl From<NaiveDateTime> for Param {
+    fn from(v: NaiveDateTime) -> Self {
+        Param::Time(v)
+    }
+}
+
+/// Keyed is implemented by the models which can be listed with a cursor,
+/// key returns the value of a sortable column
+pub trait Keyed {
+    fn key(&self, column: &str) -> Option<Param>;
+}
+
+/// After are the sort columns, whether they are descending and the values
+/// of the last row of the previous page
+pub type After = Vec<(&'static str, bool, Param)>;
+
+#[derive(Clone, Debug)]
+enum Cond {
+    Eq(&'static str, Param),
+    Cmp(&'static str, &'static str, Param),
+    NotDeleted,
+    Deleted,
+    Active(Param),
+    After(After),
+    In {
+        column: &'static str,
+        table: &'static str,
+        select: &'static str,
+        filter: Filter,
+        negated: bool,
+        text: bool,
+    },
+    Any(Vec<Filter>),
+    OneOf(&'static str, Vec<Param>),
+    Tree {
+        column: &'static str,
+        table: &'static str,
+        parent: &'static str,
+        seed: Filter,
+        up: bool,
+    },
+}
+
+/// Filter is the WHERE clause of a query, columns are fixed by the stores
+/// and every value is bound
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    conds: Vec<Cond>,
+}
+
+impl Filter {
+    /// id matches column with an id, ids which are not numbers are a bad
+    /// request
+    pub fn id(self, column: &'static str, value: Option<&str>) -> Result<Self> {
+        let value = value
+            .map(|v| v.parse::<i64>().map_err(|err| errors::bad_request(&err)))
+            .transpose()?;
+        Ok(self.eq(column, value))
+    }
+
+    pub fn eq<V: Into<Param>>(
+        mut self,
+        column: &'static str,
+        value: Option<V>,
+    ) -> Self {
+        if let Some(value) = value {
+            self.conds.push(Cond::Eq(column, value.into()));
+        }
+        self
+    }
+
+    /// ge matches the rows whose column is at least value
+    pub fn ge<V: Into<Param>>(
+        mut self,
+        column: &'static str,
+        value: Option<V>,
+    ) -> Self {
+        if let Some(value) = value {
+            self.conds.push(Cond::Cmp(column, " >= ", value.into()));
+        }
+        self
+    }
+
+    /// lt matches the rows whose column is below value
+    pub fn lt<V: Into<Param>>(
+        mut self,
+        column: &'static str,
+        value: Option<V>,
+    ) -> Self {
+        if let Some(value) = value {
+            self.conds.push(Cond::Cmp(column, " < ", value.into()));
+        }
+        self
+    }
+
+    pub fn not_deleted(mut self) -> Self {
+        self.conds.push(Cond::NotDeleted);
+        self
+    }
+
+    /// active matches the bindings in effect at now, from not_before
+    /// until expires_at, a null bound leaves its side open
+    pub fn active(mut self, now: NaiveDateTime) -> Self {
+        self.conds.push(Cond::Active(now.into()));
+        self
+    }
+
+    /// deleted keeps the soft deleted rows when deleted, the others like
+    /// not_deleted otherwise
+    pub fn deleted(mut self, deleted: bool) -> Self {
+        self.conds.push(match deleted {
+            true => Cond::Deleted,
+            false => Cond::NotDeleted,
+        });
+        self
+    }
+
+    /// in_select matches column with `select` of the rows in table which
+    /// match filter
+    pub fn in_select(
+        mut self,
+        column: &'static str,
+        table: &'static str,
+        select: &'static str,
+        filter: Filter,
+    ) -> Self {
+        self.conds.push(Cond::In {
+            column,
+            table,
+            select,
+            filter,
+            negated: false,
+            text: false,
+        });
+        self
+    }
+
+    /// in_text_select is in_select for a text column, `select` is cast to
+    /// text like the ids held by the columns naming an entity of any kind
+    pub fn in_text_select(
+        mut self,
+        column: &'static str,
+        table: &'static str,
+        select: &'static str,
+        filter: Filter,
+    ) -> Self {
+        self.conds.push(Cond::In {
+            column,
+            table,
+            select,
+            filter,
+            negated: false,
+            text: true,
+        });
+        self
+    }
+
+    /// not_in_select matches column with none of `select` of the rows in
+    /// table which match filter
+    pub fn not_in_select(
+        mut self,
+        column: &'static str,
+        table: &'static str,
+        select: &'static str,
+        filter: Filter,
+    ) -> Self {
+        self.conds.push(Cond::In {
+            column,
+            table,
+            select,
+            filter,
+            negated: true,
+            text: false,
+        });
+        self
+    }
+
+    /// any matches the rows matching one of filters, none if there is none
+    pub fn any(mut self, filters: Vec<Filter>) -> Self {
+        self.conds.push(Cond::Any(filters));
+        self
+    }
+
+    /// one_of matches the rows whose column is one of values, none if
+    /// there is no value
+    pub fn one_of(mut self, column: &'static str, values: Vec<Param>) -> Self {
+        self.conds.push(Cond::OneOf(column, values));
+        self
+    }
+
+    /// in_tree matches column with the ids of the rows of table matching
+    /// seed and of the rows they reach following the parent column, towards
+    /// the roots if up and the leaves otherwise. Soft deleted rows are not
+    /// followed
+    pub fn in_tree(
+        mut self,
+        column: &'static str,
+        table: &'static str,
+        parent: &'static str,
+        seed: Filter,
+        up: bool,
+    ) -> Self {
+        self.conds.push(Cond::Tree {
+            column,
+            table,
+            parent,
+            seed,
+            up,
+        });
+        self
+    }
+
+    /// push writes the WHERE clause, nothing if there is no condition
+    pub fn push<DB: Dialect>(&self, builder: &mut QueryBuilder<'_, DB>) {
+        if self.conds.is_empty() {
+            return;
+        }
+        builder.push(" WHERE ");
+        self.push_conds(builder);
+    }
+
+    fn push_conds<DB: Dialect>(&self, builder: &mut QueryBuilder<'_, DB>) {
+        for (i, cond) in self.conds.iter().enumerate() {
+            if i > 0 {
+                builder.push(" AND ");
+            }
+            match cond {
+                Cond::Eq(column, param) => {
+                    push_ident(builder, column);
+                    builder.push(" = ");
+                    DB::bind(builder, param.clone());
+                }
+                Cond::Cmp(column, op, param) => {
+                    push_ident(builder, column);
+                    builder.push(op);
+                    DB::bind(builder, param.clone());
+                }
+                Cond::NotDeleted => {
+                    push_ident(builder, "deleted");
+                    builder.push(" = 0");
+                }
+                Cond::Deleted => {
+                    push_ident(builder, "deleted");
+                    builder.push(" <> 0");
+                }
+                Cond::Active(now) => {
+                    builder.push("(");
+                    push_ident(builder, "not_before");
+                    builder.push(" IS NULL OR ");
+                    push_ident(builder, "not_before");
+                    builder.push(" <= ");
+                    DB::bind(builder, now.clone());
+                    builder.push(") AND (");
+                    push_ident(builder, "expires_at");
+                    builder.push(" IS NULL OR ");
+                    push_ident(builder, "expires_at");
+                    builder.push(" > ");
+                    DB::bind(builder, now.clone());
+                    builder.push(")");
+                }
+                Cond::After(keys) => {
+                    // (a > x) OR (a = x AND b > y) OR ...
+                    builder.push("(");
+                    for i in 0..keys.len() {
+                        if i > 0 {
+                            builder.push(" OR ");
+                        }
+                        builder.push("(");
+                        for (column, _, param) in &keys[..i] {
+                            push_ident(builder, column);
+                            builder.push(" = ");
+                            DB::bind(builder, param.clone());
+                            builder.push(" AND ");
+                        }
+                        let (column, desc, param) = &keys[i];
+                        push_ident(builder, column);
+                        builder.push(if *desc { " < " } else { " > " });
+                        DB::bind(builder, param.clone());
+                        builder.push(")");
+                    }
+                    builder.push(")");
+                }
+                Cond::In {
+                    column,
+                    table,
+                    select,
+                    filter,
+                    negated,
+                    text,
+                } => {
+                    push_ident(builder, column);
+                    builder.push(if *negated { " NOT IN" } else { " IN" });
+                    builder.push(" (SELECT ");
+                    if *text {
+                        builder.push("CAST(");
+                        push_ident(builder, select);
+                        builder.push(format_args!(" AS {})", DB::TEXT));
+                    } else {
+                        push_ident(builder, select);
+                    }
+                    builder.push(" FROM ");
+                    push_ident(builder, table);
+                    filter.push(builder);
+                    builder.push(")");
+                }
+                Cond::Tree {
+                    column,
+                    table,
+                    parent,
+                    seed,
+                    up,
+                } => {
+                    // UNION drops the rows already reached, it ends on cycles
+                    let (select, join) = if *up {
+                        (*parent, "id")
+                    } else {
+                        ("id", *parent)
+                    };
+                    push_ident(builder, column);
+                    builder.push(" IN (WITH RECURSIVE ");
+                    push_ident(builder, "tree");
+                    builder.push(" (");
+                    push_ident(builder, "id");
+                    builder.push(") AS (SELECT ");
+                    push_ident(builder, "id");
+                    builder.push(" FROM ");
+                    push_ident(builder, table);
+                    seed.push(builder);
+                    builder.push(" UNION SELECT t.");
+                    push_ident(builder, select);
+                    builder.push(" FROM ");
+                    push_ident(builder, table);
+                    builder.push(" t JOIN ");
+                    push_ident(builder, "tree");
+                    builder.push(" ON t.");
+                    push_ident(builder, join);
+                    builder.push(" = ");
+                    push_ident(builder, "tree");
+                    builder.push(".");
+                    push_ident(builder, "id");
+                    builder.push(" WHERE t.");
+                    push_ident(builder, "deleted");
+                    builder.push(" = 0) SELECT ");
+                    push_ident(builder, "id");
+                    builder.push(" FROM ");
+                    push_ident(builder, "tree");
+                    builder.push(")");
+                }
+                Cond::Any(filters) if filters.is_empty() => {
+                    builder.push("1 = 0");
+                }
+                Cond::Any(filters) => {
+                    builder.push("(");
+                    for (i, filter) in filters.iter().enumerate() {
+                        if i > 0 {
+                            builder.push(" OR ");
+                        }
+                        builder.push("(");
+                        if filter.conds.is_empty() {
+                            builder.push("1 = 1");
+                        } else {
+                            filter.push_conds(builder);
+                        }
+                        builder.push(")");
+                    }
+                    builder.push(")");
+                }
+                Cond::OneOf(_, values) if values.is_empty() => {
+                    builder.push("1 = 0");
+                }
+                Cond::OneOf(column, values) => {
+                    push_ident(builder, column);
+                    builder.push(" IN (");
+                    for (i, param) in values.iter().enumerate() {
+                        if i > 0 {
+                            builder.push(",");
+                        }
+                        DB::bind(builder, param.clone());
+                    }
+                    builder.push(")");
+                }
+            }
+        }
+    }
+}
+
+pub fn push_ident<DB: Dialect>(
+    builder: &mut QueryBuilder<'_, DB>,
+    ident: &str,
+) {
+    builder.push(format_args!("{q}{ident}{q}", q = DB::QUOTE));
+}
+
+#[derive(Serialize, Deserialize)]
+struct Cursor {
+    order_by: Option<String>,
+    keys: Vec<Param>,
+}
+
+impl Pagination {
+    /// keys are the sort columns and whether they are descending, `id`
+    /// breaks ties so that every row has a distinct position
+    pub fn keys(
+        &self,
+        sortable: &[&'static str],
+    ) -> Result<Vec<(&'static str, bool)>> {
+        let mut keys = self.sort(sortable)?;
+        if !keys.iter().any(|(column, _)| *column == "id") {
+            let desc = keys.last().is_some_and(|(_, desc)| *desc);
+            keys.push(("id", desc));
+        }
+        Ok(keys)
+    }
+
+    /// after decodes the cursor into the keys of the last row of the
+    /// previous page
+    pub fn after(&self, sortable: &[&'static str]) -> Result<Option<After>> {
+        let Some(cursor) = &self.cursor else {
+            return Ok(None);
+        };
+        let invalid = || errors::bad_request("invalid cursor");
+        let cursor: Cursor = URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|v| serde_json::from_slice(&v).ok())
+            .ok_or_else(invalid)?;
+        if cursor.order_by != self.order_by {
+            return Err(errors::bad_request(
+                "cursor was issued for another order_by",
+            ));
+        }
+        let keys = self.keys(sortable)?;
+        if keys.len() != cursor.keys.len() {
+            return Err(invalid());
+        }
+        Ok(Some(
+            keys.into_iter()
+                .zip(cursor.keys)
+                .map(|((column, desc), param)| (column, desc, param))
+                .collect(),
+        ))
+    }
+
+    /// seek narrows filter to the rows after the cursor, the total is
+    /// counted without it
+    pub fn seek(
+        &self,
+        mut filter: Filter,
+        sortable: &[&'static str],
+    ) -> Result<Filter> {
+        if let Some(keys) = self.after(sortable)? {
+            filter.conds.push(Cond::After(keys));
+        }
+        Ok(filter)
+    }
+
+    /// next_cursor continues after the last row of a full page, models
+    /// without a value for one of the keys can only be paged by offset
+    pub fn next_cursor<T: Keyed>(
+        &self,
+        rows: &[T],
+        sortable: &[&'static str],
+    ) -> Result<Option<String>> {
+        if self.limit == 0 || rows.len() as u64 != self.limit {
+            return Ok(None);
+        }
+        let Some(last) = rows.last() else {
+            return Ok(None);
+        };
+        let keys: Option<Vec<Param>> = self
+            .keys(sortable)?
+            .into_iter()
+            .map(|(column, _)| last.key(column))
+            .collect();
+        let Some(keys) = keys else {
+            return Ok(None);
+        };
+        let cursor = serde_json::to_vec(&Cursor {
+            order_by: self.order_by.clone(),
+            keys,
+        })
+        .map_err(errors::any)?;
+        Ok(Some(URL_SAFE_NO_PAD.encode(cursor)))
+    }
+
+    /// push writes ORDER BY, LIMIT and OFFSET, order_by may only name the
+    /// sortable columns of the entity. OFFSET is ignored with a cursor.
+    pub fn push<DB: Dialect>(
+        &self,
+        builder: &mut QueryBuilder<'_, DB>,
+        sortable: &[&'static str],
+    ) -> Result<()> {
+        for (i, (column, desc)) in self.keys(sortable)?.into_iter().enumerate()
+        {
+            builder.push(if i == 0 { " ORDER BY " } else { "," });
+            push_ident(builder, column);
+            builder.push(if desc { " DESC" } else { " ASC" });
+        }
+        let offset = if self.cursor.is_some() {
+            0
+        } else {
+            self.offset
+        };
+        if self.limit > 0 {
+            builder.push(" LIMIT ");
+            DB::bind(builder, Param::Int(self.limit as i64));
+        }
+        if offset > 0 {
+            // sqlite and mysql only take OFFSET after LIMIT
+            if self.limit == 0 {
+                builder.push(" LIMIT ");
+                DB::bind(builder, Param::Int(i64::MAX));
+            }
+            builder.push(" OFFSET ");
+            DB::bind(builder, Param::Int(offset as i64));
+        }
+        Ok(())
+    }
+}
+
+/// is_after reports whether row comes after the cursor keys, it's how the
+/// memory store seeks
+pub(crate) fn is_after<T: Keyed>(keys: &After, row: &T) -> bool {
+    for (column, desc, param) in keys {
+        let ordering = row
+            .key(column)
+            .partial_cmp(&Some(param.clone()))
+            .unwrap_or(Ordering::Equal);
+        let ordering = if *desc { ordering.reverse() } else { ordering };
+        if ordering != Ordering::Equal {
+            return ordering == Ordering::Greater;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{Execute, MySql};
+
+    use super::*;
+
+    #[test]
+    fn filter() {
+        let filter = Filter::default()
+            .id("id", Some("1"))
+            .unwrap()
+            .eq("version", Some("v1"))
+            .eq::<&str>("name", None)
+            .in_select(
+                "id",
+                "policy_binding",
+                "policy_id",
+                Filter::default()
+                    .eq("bindings_type", Some(1u8))
+                    .not_deleted(),
+            )
+            .not_deleted();
+        let mut builder = QueryBuilder::<MySql>::new("SELECT * FROM `policy`");
+        filter.push(&mut builder);
+        assert_eq!(
+            builder.build().sql(),
+            "SELECT * FROM `policy` WHERE `id` = ? AND `version` = ? AND \
+             `id` IN (SELECT `policy_id` FROM `policy_binding` WHERE \
+             `bindings_type` = ? AND `deleted` = 0) AND `deleted` = 0"
+        );
+
+        let mut builder = QueryBuilder::<MySql>::new("SELECT * FROM `policy`");
+        Filter::default().push(&mut builder);
+        assert_eq!(builder.build().sql(), "SELECT * FROM `policy`");
+
+        assert!(Filter::default().id("id", Some("1 OR 1=1")).is_err());
+
+        let mut builder = QueryBuilder::<MySql>::new("SELECT * FROM `audit`");
+        let at = NaiveDateTime::default();
+        Filter::default()
+            .ge("created_at", Some(at))
+            .lt("created_at", Some(at))
+            .push(&mut builder);
+        assert_eq!(
+            builder.build().sql(),
+            "SELECT * FROM `audit` WHERE `created_at` >= ? AND \
+             `created_at` < ?"
+        );
+
+        let mut builder = QueryBuilder::<MySql>::new("SELECT * FROM `group`");
+        Filter::default()
+            .in_tree(
+                "id",
+                "group",
+                "parent_id",
+                Filter::default().id("id", Some("1")).unwrap(),
+                false,
+            )
+            .push(&mut builder);
+        assert_eq!(
+            builder.build().sql(),
+            "SELECT * FROM `group` WHERE `id` IN (WITH RECURSIVE `tree` \
+             (`id`) AS (SELECT `id` FROM `group` WHERE `id` = ? UNION \
+             SELECT t.`id` FROM `group` t JOIN `tree` ON t.`parent_id` = \
+             `tree`.`id` WHERE t.`deleted` = 0) SELECT `id` FROM `tree`)"
+        );
+
+        let mut builder =
+            QueryBuilder::<MySql>::new("SELECT * FROM `policy_binding`");
+        let account = || Filter::default().id("account_id", Some("1")).unwrap();
+        Filter::default()
+            .any(vec![
+                Filter::default()
+                    .eq("bindings_type", Some(1u8))
+                    .in_text_select("bindings_id", "user", "id", account()),
+                Filter::default(),
+            ])
+            .push(&mut builder);
+        assert_eq!(
+            builder.build().sql(),
+            "SELECT * FROM `policy_binding` WHERE ((`bindings_type` = ? AND \
+             `bindings_id` IN (SELECT CAST(`id` AS CHAR) FROM `user` WHERE \
+             `account_id` = ?)) OR (1 = 1))"
+        );
+    }
+
+    #[test]
+    fn pagination() {
+        let sortable = &["id", "name", "created_at"];
+        let mut pagination = Pagination {
+            limit: 10,
+            offset: 20,
+            order_by: Some("name,created_at desc".to_owned()),
+            cursor: None,
+            count_disable: false,
+        };
+        let mut builder = QueryBuilder::<MySql>::new("");
+        pagination.push(&mut builder, sortable).unwrap();
+        assert_eq!(
+            builder.build().sql(),
+            " ORDER BY `name` ASC,`created_at` DESC,`id` DESC LIMIT ? OFFSET ?"
+        );
+
+        pagination.order_by = Some("desc".to_owned());
+        let mut builder = QueryBuilder::<MySql>::new("");
+        assert!(pagination.push(&mut builder, sortable).is_err());
+
+        pagination.order_by = Some("id; DROP TABLE `user`".to_owned());
+        let mut builder = QueryBuilder::<MySql>::new("");
+        assert!(pagination.push(&mut builder, sortable).is_err());
+    }
+
+    struct Row(i64, &'static str);
+
+    impl Keyed for Row {
+        fn key(&self, column: &str) -> Option<Param> {
+            match column {
+                "id" => Some(Param::Int(self.0)),
+                "name" => Some(self.1.into()),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn cursor() {
+        let sortable = &["id", "name", "created_at"];
+        let mut pagination = Pagination {
+            limit: 2,
+            offset: 5,
+            order_by: Some("name desc".to_owned()),
+            ..Default::default()
+        };
+        let cursor = pagination
+            .next_cursor(&[Row(1, "b"), Row(2, "a")], sortable)
+            .unwrap();
+        assert!(cursor.is_some());
+        assert!(pagination
+            .next_cursor(&[Row(1, "b")], sortable)
+            .unwrap()
+            .is_none());
+
+        pagination.cursor = cursor;
+        let mut builder = QueryBuilder::<MySql>::new("SELECT * FROM `role`");
+        pagination
+            .seek(Filter::default().not_deleted(), sortable)
+            .unwrap()
+            .push(&mut builder);
+        pagination.push(&mut builder, sortable).unwrap();
+        assert_eq!(
+            builder.build().sql(),
+            "SELECT * FROM `role` WHERE `deleted` = 0 AND ((`name` < ?) OR \
+             (`name` = ? AND `id` < ?)) ORDER BY `name` DESC,`id` DESC LIMIT ?"
+        );
+        let after = pagination.after(sortable).unwrap().unwrap();
+        assert!(is_after(&after, &Row(1, "a")));
+        assert!(!is_after(&after, &Row(3, "a")));
+        assert!(!is_after(&after, &Row(1, "b")));
+
+        pagination.order_by = Some("name".to_owned());
+        assert!(pagination.after(sortable).is_err());
+        pagination.cursor = Some("not a cursor".to_owned());
+        assert!(pagination.after(sortable).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use cim_slo::Result;
+
+use crate::{Filter, Keyed, Pagination, Param};
+
+/// RelationTuple stores `object#relation@subject`, object is written as
+/// `namespace:id` and subject is an id or a subject set `namespace:id#relation`
+#[derive(
+    Debug, Default, Deserialize, Serialize, PartialEq, Clone, utoipa::ToSchema,
+)]
+pub struct RelationTuple {
+    pub id: String,
+    pub account_id: String,
+    pub object: String,
+    pub relation: String,
+    pub subject: String,
+    #[serde(default)]
+    pub resource_version: u64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct Content {
+    #[validate(length(min = 3, max = 255))]
+    pub object: String,
+    #[validate(length(min = 1, max = 64))]
+    pub relation: String,
+    #[validate(length(min = 1, max = 255))]
+    pub subject: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct ListParams {
+    #[validate(length(min = 1))]
+    pub id: Option<String>,
+    #[validate(length(min = 1))]
+    pub account_id: Option<String>,
+    #[validate(length(min = 1))]
+    pub object: Option<String>,
+    #[validate(length(min = 1))]
+    pub relation: Option<String>,
+    #[validate(length(min = 1))]
+    pub subject: Option<String>,
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub pagination: Pagination,
+}
+
+impl ListParams {
+    /// SORTABLE are the columns order_by may name
+    pub const SORTABLE: &'static [&'static str] = &[
+        "id",
+        "account_id",
+        "object",
+        "relation",
+        "subject",
+        "created_at",
+        "updated_at",
+    ];
+
+    /// filter is the WHERE clause of the sql stores
+    pub(crate) fn filter(&self) -> Result<Filter> {
+        Ok(Filter::default()
+            .id("id", self.id.as_deref())?
+            .id("account_id", self.account_id.as_deref())?
+            .eq("object", self.object.as_ref())
+            .eq("relation", self.relation.as_ref())
+            .eq("subject", self.subject.as_ref()))
+    }
+}
+
+impl Keyed for RelationTuple {
+    fn key(&self, column: &str) -> Option<Param> {
+        match column {
+            "id" => Some(Param::id(&self.id)),
+            "account_id" => Some(Param::id(&self.account_id)),
+            "object" => Some((&self.object).into()),
+            "relation" => Some((&self.relation).into()),
+            "subject" => Some((&self.subject).into()),
+            "created_at" => Some(self.created_at.into()),
+            "updated_at" => Some(self.updated_at.into()),
+            _ => None,
+        }
+    }
+}
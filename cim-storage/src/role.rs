@@ -2,14 +2,27 @@ use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
-use crate::Pagination;
+use cim_slo::Result;
 
-#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone, utoipa::ToSchema)]
+use crate::{
+    label::{check_annotations, check_labels, Labels, Selector},
+    Filter, Keyed, Pagination, Param,
+};
+
+#[derive(
+    Debug, Default, Deserialize, Serialize, PartialEq, Clone, utoipa::ToSchema,
+)]
 pub struct Role {
     pub id: String,
     pub account_id: String,
     pub name: String,
     pub desc: String,
+    #[serde(default)]
+    pub labels: Labels,
+    #[serde(default)]
+    pub annotations: Labels,
+    #[serde(default)]
+    pub resource_version: u64,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
@@ -20,6 +33,12 @@ pub struct Content {
     pub name: String,
     #[validate(length(min = 1, max = 255))]
     pub desc: String,
+    #[serde(default)]
+    #[validate(custom(function = "check_labels"))]
+    pub labels: Labels,
+    #[serde(default)]
+    #[validate(custom(function = "check_annotations"))]
+    pub annotations: Labels,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -30,7 +49,47 @@ pub struct ListParams {
     pub account_id: Option<String>,
     #[validate(length(min = 1))]
     pub user_id: Option<String>,
+    /// label_selector selects by labels, e.g. `env=prod,tier in (web,api)`
+    pub label_selector: Option<Selector>,
     #[serde(flatten)]
     #[validate(nested)]
     pub pagination: Pagination,
 }
+
+impl ListParams {
+    /// SORTABLE are the columns order_by may name
+    pub const SORTABLE: &'static [&'static str] =
+        &["id", "account_id", "name", "created_at", "updated_at"];
+
+    /// filter is the WHERE clause of the sql stores
+    pub(crate) fn filter(&self) -> Result<Filter> {
+        let mut filter = Filter::default()
+            .id("id", self.id.as_deref())?
+            .id("account_id", self.account_id.as_deref())?;
+        if let Some(user_id) = &self.user_id {
+            filter = filter.in_select(
+                "id",
+                "role_binding",
+                "role_id",
+                Filter::default().eq("user_id", Some(user_id)).not_deleted(),
+            );
+        }
+        if let Some(selector) = &self.label_selector {
+            filter = selector.filter(filter, "role");
+        }
+        Ok(filter)
+    }
+}
+
+impl Keyed for Role {
+    fn key(&self, column: &str) -> Option<Param> {
+        match column {
+            "id" => Some(Param::id(&self.id)),
+            "account_id" => Some(Param::id(&self.account_id)),
+            "name" => Some((&self.name).into()),
+            "created_at" => Some(self.created_at.into()),
+            "updated_at" => Some(self.updated_at.into()),
+            _ => None,
+        }
+    }
+}
@@ -1,8 +1,11 @@
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
-use crate::Pagination;
+use cim_pim::PrincipalType;
+use cim_slo::Result;
+
+use crate::{expiry::check_window, Filter, Keyed, Pagination, Param};
 
 #[derive(
     Debug, Default, Deserialize, Serialize, PartialEq, Clone, utoipa::ToSchema,
@@ -12,33 +15,98 @@ pub struct RoleBinding {
     pub role_id: String,
     pub user_type: UserType,
     pub user_id: String,
+    /// not_before is when the binding takes effect, right away when none
+    #[serde(default)]
+    pub not_before: Option<NaiveDateTime>,
+    /// expires_at is when the binding ends, it's deleted once past it
+    #[serde(default)]
+    pub expires_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub resource_version: u64,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
 
 #[derive(Debug, Deserialize, Validate)]
+#[validate(schema(function = "check_content"))]
 pub struct Content {
     #[validate(length(min = 1, max = 255))]
     pub role_id: String,
     pub user_type: UserType,
     #[validate(length(min = 1, max = 255))]
     pub user_id: String,
+    pub not_before: Option<NaiveDateTime>,
+    pub expires_at: Option<NaiveDateTime>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
 pub struct ListParams {
     #[validate(length(min = 1))]
     pub id: Option<String>,
+    /// account_id lists the bindings of the roles of the account
+    #[validate(length(min = 1))]
+    pub account_id: Option<String>,
     #[validate(length(min = 1))]
     pub role_id: Option<String>,
     pub user_type: Option<UserType>,
     #[validate(length(min = 1))]
     pub user_id: Option<String>,
+    /// expires_before lists the bindings which expire before it
+    pub expires_before: Option<NaiveDateTime>,
     #[serde(flatten)]
     #[validate(nested)]
     pub pagination: Pagination,
 }
 
+impl ListParams {
+    /// SORTABLE are the columns order_by may name
+    pub const SORTABLE: &'static [&'static str] = &[
+        "id",
+        "role_id",
+        "user_type",
+        "user_id",
+        "created_at",
+        "updated_at",
+    ];
+
+    /// filter is the WHERE clause of the sql stores
+    pub(crate) fn filter(&self) -> Result<Filter> {
+        let mut filter = Filter::default()
+            .id("id", self.id.as_deref())?
+            .id("role_id", self.role_id.as_deref())?
+            .eq("user_type", self.user_type.as_ref().map(u8::from))
+            .eq("user_id", self.user_id.as_ref())
+            .lt("expires_at", self.expires_before);
+        if let Some(account_id) = &self.account_id {
+            filter = filter.in_select(
+                "role_id",
+                "role",
+                "id",
+                Filter::default().id("account_id", Some(account_id))?,
+            );
+        }
+        Ok(filter)
+    }
+}
+
+fn check_content(content: &Content) -> Result<(), ValidationError> {
+    check_window(content.not_before, content.expires_at)
+}
+
+impl Keyed for RoleBinding {
+    fn key(&self, column: &str) -> Option<Param> {
+        match column {
+            "id" => Some(Param::id(&self.id)),
+            "role_id" => Some(Param::id(&self.role_id)),
+            "user_type" => Some(u8::from(&self.user_type).into()),
+            "user_id" => Some((&self.user_id).into()),
+            "created_at" => Some(self.created_at.into()),
+            "updated_at" => Some(self.updated_at.into()),
+            _ => None,
+        }
+    }
+}
+
 #[derive(
     Debug, Default, Deserialize, Serialize, Clone, PartialEq, utoipa::ToSchema,
 )]
@@ -69,3 +137,23 @@ impl From<u8> for UserType {
         }
     }
 }
+
+impl From<PrincipalType> for UserType {
+    fn from(principal_type: PrincipalType) -> Self {
+        match principal_type {
+            PrincipalType::User => UserType::User,
+            PrincipalType::Application => UserType::Application,
+            PrincipalType::FederatedUser => UserType::FederatedUser,
+        }
+    }
+}
+
+impl From<&UserType> for PrincipalType {
+    fn from(user_type: &UserType) -> Self {
+        match user_type {
+            UserType::User => PrincipalType::User,
+            UserType::Application => PrincipalType::Application,
+            UserType::FederatedUser => PrincipalType::FederatedUser,
+        }
+    }
+}
@@ -0,0 +1,495 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use cim_slo::{
+    crypto::password::{is_encoded, verify},
+    errors, Result,
+};
+
+use crate::{
+    client::Client,
+    connector::Connector,
+    group::Group,
+    group_user::GroupUser,
+    policy::Policy,
+    policy_binding::{BindingsType, PolicyBinding},
+    role::Role,
+    role_binding::{RoleBinding, UserType},
+    user::User,
+};
+
+/// VERSION is the version of the snapshot documents written, the only one
+/// read
+pub const VERSION: &str = "v1";
+
+/// VOLATILE are the fields the stores maintain, they are never imported
+const VOLATILE: &[&str] = &["resource_version", "created_at", "updated_at"];
+
+/// Snapshot is the IAM state of an account as one document, it's exported
+/// from an environment and imported in another or the same one
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct Snapshot {
+    pub version: String,
+    pub account_id: String,
+    #[serde(default)]
+    pub users: Vec<User>,
+    #[serde(default)]
+    pub groups: Vec<Group>,
+    #[serde(default)]
+    pub group_users: Vec<GroupUser>,
+    #[serde(default)]
+    pub roles: Vec<Role>,
+    #[serde(default)]
+    pub role_bindings: Vec<RoleBinding>,
+    #[serde(default)]
+    pub policies: Vec<Policy>,
+    #[serde(default)]
+    pub policy_bindings: Vec<PolicyBinding>,
+    #[serde(default)]
+    pub clients: Vec<Client>,
+    /// connectors are shared by every account, only the operators carry
+    /// them
+    #[serde(default)]
+    pub connectors: Vec<Connector>,
+}
+
+impl Snapshot {
+    pub fn new(account_id: &str) -> Self {
+        Self {
+            version: VERSION.to_owned(),
+            account_id: account_id.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// check the snapshot is of a version read, that its entities belong to
+    /// its account and that the bindings bind the entities it carries
+    pub fn check(&self) -> Result<()> {
+        if self.version != VERSION {
+            return Err(errors::bad_request(&format!(
+                "unsupported snapshot version {}",
+                self.version
+            )));
+        }
+        let foreign = |kind: &str, id: &str| {
+            Err(errors::bad_request(&format!(
+                "{} {} is not of account {}",
+                kind, id, self.account_id
+            )))
+        };
+        let users: HashSet<_> = self.users.iter().map(|v| &v.id).collect();
+        let groups: HashSet<_> = self.groups.iter().map(|v| &v.id).collect();
+        let roles: HashSet<_> = self.roles.iter().map(|v| &v.id).collect();
+        let unknown = |kind: &str, id: &str, bound: &str| {
+            Err(errors::bad_request(&format!(
+                "{} {} refers to {} which is not in the snapshot",
+                kind, id, bound
+            )))
+        };
+
+        for v in &self.users {
+            if v.account_id != self.account_id {
+                return foreign("user", &v.id);
+            }
+        }
+        for v in &self.groups {
+            if v.account_id != self.account_id {
+                return foreign("group", &v.id);
+            }
+            if let Some(parent_id) = &v.parent_id {
+                if !groups.contains(parent_id) {
+                    return unknown("group", &v.id, parent_id);
+                }
+            }
+        }
+        for v in &self.roles {
+            if v.account_id != self.account_id {
+                return foreign("role", &v.id);
+            }
+        }
+        for v in &self.policies {
+            if v.account_id.as_ref() != Some(&self.account_id) {
+                return foreign("policy", &v.id);
+            }
+        }
+        for v in &self.clients {
+            if v.account_id != self.account_id {
+                return foreign("client", &v.id);
+            }
+        }
+        for v in &self.group_users {
+            if !groups.contains(&v.group_id) {
+                return unknown("group_user", &v.id, &v.group_id);
+            }
+            if !users.contains(&v.user_id) {
+                return unknown("group_user", &v.id, &v.user_id);
+            }
+        }
+        for v in &self.role_bindings {
+            if !roles.contains(&v.role_id) {
+                return unknown("role_binding", &v.id, &v.role_id);
+            }
+            if v.user_type == UserType::User && !users.contains(&v.user_id) {
+                return unknown("role_binding", &v.id, &v.user_id);
+            }
+        }
+        for v in &self.policy_bindings {
+            let bound = match v.bindings_type {
+                BindingsType::User => &users,
+                BindingsType::Group => &groups,
+                BindingsType::Role => &roles,
+                // applications and federated users are bound by subject,
+                // a snapshot holds neither
+                BindingsType::Application | BindingsType::FederatedUser => {
+                    continue
+                }
+            };
+            if !bound.contains(&v.bindings_id) {
+                return unknown("policy_binding", &v.id, &v.bindings_id);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Create,
+    Update,
+}
+
+/// Change is how importing an entity changes the stored state, fields are
+/// the top level fields which differ on an update
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change {
+    pub kind: &'static str,
+    pub id: String,
+    pub action: Action,
+    pub fields: Vec<String>,
+}
+
+/// Entity is a model carried by snapshots
+pub trait Entity: Serialize + Sized {
+    const KIND: &'static str;
+
+    fn id(&self) -> &str;
+    /// keyed is the entity with only its id, to get the stored one
+    fn keyed(id: &str) -> Self;
+    /// adopt takes from the stored entity what importing doesn't change:
+    /// the fields the store maintains and the credentials left out
+    fn adopt(&mut self, stored: &Self) -> Result<()>;
+    /// check the entity can be created, it fails when credentials are
+    /// left out
+    fn creatable(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// diff is the change importing the entity makes over stored, None if
+    /// it makes none. The entity adopts from stored first
+    fn diff(&mut self, stored: Option<&Self>) -> Result<Option<Change>> {
+        let Some(stored) = stored else {
+            self.creatable()?;
+            return Ok(Some(Change {
+                kind: Self::KIND,
+                id: self.id().to_owned(),
+                action: Action::Create,
+                fields: Vec::new(),
+            }));
+        };
+        self.adopt(stored)?;
+        let fields = diff(
+            serde_json::to_value(&*self).map_err(errors::any)?,
+            serde_json::to_value(stored).map_err(errors::any)?,
+        );
+        Ok((!fields.is_empty()).then(|| Change {
+            kind: Self::KIND,
+            id: self.id().to_owned(),
+            action: Action::Update,
+            fields,
+        }))
+    }
+}
+
+/// diff is the top level fields of value and other which differ
+fn diff(value: Value, other: Value) -> Vec<String> {
+    let (Value::Object(mut value), Value::Object(mut other)) = (value, other)
+    else {
+        return Vec::new();
+    };
+    for field in VOLATILE {
+        value.remove(*field);
+        other.remove(*field);
+    }
+    let mut fields: Vec<String> = value
+        .iter()
+        .filter(|(k, v)| other.get(*k) != Some(*v))
+        .map(|(k, _)| k.clone())
+        .collect();
+    fields.extend(other.keys().filter(|k| !value.contains_key(*k)).cloned());
+    fields.sort();
+    fields
+}
+
+macro_rules! entity {
+    ($t:ty, $kind:literal) => {
+        impl Entity for $t {
+            const KIND: &'static str = $kind;
+
+            fn id(&self) -> &str {
+                &self.id
+            }
+
+            fn keyed(id: &str) -> Self {
+                Self {
+                    id: id.to_owned(),
+                    ..Default::default()
+                }
+            }
+
+            fn adopt(&mut self, stored: &Self) -> Result<()> {
+                self.resource_version = stored.resource_version;
+                self.created_at = stored.created_at;
+                self.updated_at = stored.updated_at;
+                Ok(())
+            }
+        }
+    };
+}
+
+entity!(Group, "group");
+entity!(GroupUser, "group_user");
+entity!(Role, "role");
+entity!(RoleBinding, "role_binding");
+entity!(Policy, "policy");
+entity!(PolicyBinding, "policy_binding");
+
+impl Entity for User {
+    const KIND: &'static str = "user";
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn keyed(id: &str) -> Self {
+        Self {
+            id: id.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// a user without a password, or with the stored one in plaintext,
+    /// keeps the stored hash and the secret it was hashed with
+    fn adopt(&mut self, stored: &Self) -> Result<()> {
+        self.resource_version = stored.resource_version;
+        self.created_at = stored.created_at;
+        self.updated_at = stored.updated_at;
+        let unchanged = match (&self.password, &stored.password) {
+            (None, _) => true,
+            (Some(password), Some(hash)) if !is_encoded(password) => {
+                verify(hash, password, stored.secret.as_deref().unwrap_or(""))?
+            }
+            _ => false,
+        };
+        if unchanged {
+            self.secret = stored.secret.clone();
+            self.password = stored.password.clone();
+        }
+        Ok(())
+    }
+
+    fn creatable(&self) -> Result<()> {
+        match &self.password {
+            None => Err(errors::bad_request(&format!(
+                "user {} is new and has no password",
+                self.id
+            ))),
+            Some(password) if is_encoded(password) && self.secret.is_none() => {
+                Err(errors::bad_request(&format!(
+                    "user {} has a hashed password without its secret",
+                    self.id
+                )))
+            }
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+impl Entity for Client {
+    const KIND: &'static str = "client";
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn keyed(id: &str) -> Self {
+        Self {
+            id: id.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// a client without a secret keeps the stored one
+    fn adopt(&mut self, stored: &Self) -> Result<()> {
+        self.created_at = stored.created_at;
+        self.updated_at = stored.updated_at;
+        if self.secret.is_empty() {
+            self.secret = stored.secret.clone();
+        }
+        Ok(())
+    }
+
+    fn creatable(&self) -> Result<()> {
+        if self.secret.is_empty() {
+            return Err(errors::bad_request(&format!(
+                "client {} is new and has no secret",
+                self.id
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Entity for Connector {
+    const KIND: &'static str = "connector";
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn keyed(id: &str) -> Self {
+        Self {
+            id: id.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    /// a connector without its config keeps the stored one, with the data
+    /// it keeps along
+    fn adopt(&mut self, stored: &Self) -> Result<()> {
+        if self.config.is_empty() {
+            self.config = stored.config.clone();
+            self.connector_data = stored.connector_data.clone();
+        }
+        Ok(())
+    }
+
+    fn creatable(&self) -> Result<()> {
+        if self.config.is_empty() {
+            return Err(errors::bad_request(&format!(
+                "connector {} is new and has no config",
+                self.id
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use cim_slo::crypto::password::encrypt;
+
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        let mut snapshot = Snapshot::new("1");
+        snapshot.users.push(User {
+            id: "1".to_owned(),
+            account_id: "1".to_owned(),
+            ..Default::default()
+        });
+        snapshot.groups.push(Group {
+            id: "2".to_owned(),
+            account_id: "1".to_owned(),
+            ..Default::default()
+        });
+        snapshot.group_users.push(GroupUser {
+            id: "3".to_owned(),
+            group_id: "2".to_owned(),
+            user_id: "1".to_owned(),
+            ..Default::default()
+        });
+        snapshot.policy_bindings.push(PolicyBinding {
+            id: "4".to_owned(),
+            policy_id: "5".to_owned(),
+            bindings_type: BindingsType::Group,
+            bindings_id: "2".to_owned(),
+            ..Default::default()
+        });
+        snapshot
+    }
+
+    #[test]
+    fn check() {
+        assert!(snapshot().check().is_ok());
+
+        let mut other = snapshot();
+        other.version = "v0".to_owned();
+        assert!(other.check().is_err());
+        let mut other = snapshot();
+        other.groups[0].account_id = "2".to_owned();
+        assert!(other.check().is_err());
+        let mut other = snapshot();
+        other.group_users[0].user_id = "6".to_owned();
+        assert!(other.check().is_err());
+        let mut other = snapshot();
+        other.policy_bindings[0].bindings_type = BindingsType::Role;
+        assert!(other.check().is_err());
+    }
+
+    #[test]
+    fn entity_diff() {
+        let secret = "secret".to_owned();
+        let password = "ag1234567890123456789";
+        let stored = User {
+            id: "1".to_owned(),
+            account_id: "1".to_owned(),
+            secret: Some(secret.clone()),
+            password: Some(encrypt(password, &secret).unwrap()),
+            resource_version: 3,
+            created_at: Utc::now().naive_utc(),
+            ..Default::default()
+        };
+        let mut user = User {
+            id: "1".to_owned(),
+            account_id: "1".to_owned(),
+            ..Default::default()
+        };
+        assert!(user.clone().diff(Some(&stored)).unwrap().is_none());
+        assert!(user.clone().diff(None).is_err());
+
+        user.password = Some(password.to_owned());
+        assert!(user.clone().diff(Some(&stored)).unwrap().is_none());
+        assert_eq!(user.diff(None).unwrap().unwrap().action, Action::Create);
+
+        let mut user = stored.clone();
+        user.desc = "desc".to_owned();
+        user.password = Some("ag0987654321098765432".to_owned());
+        user.resource_version = 0;
+        let change = user.diff(Some(&stored)).unwrap().unwrap();
+        assert_eq!(change.action, Action::Update);
+        assert_eq!(change.fields, vec!["desc", "password"]);
+        assert_eq!(user.resource_version, 3);
+
+        // a redacted connector keeps the stored config
+        let stored = Connector {
+            id: "2".to_owned(),
+            name: "a".to_owned(),
+            config: r#"{"secret":"s"}"#.to_owned(),
+            resource_version: 2,
+            ..Default::default()
+        };
+        let mut connector = Connector {
+            id: "2".to_owned(),
+            name: "a".to_owned(),
+            ..Default::default()
+        };
+        assert!(connector.clone().diff(None).is_err());
+        assert!(connector.diff(Some(&stored)).unwrap().is_none());
+        assert_eq!(connector.config, stored.config);
+    }
+}
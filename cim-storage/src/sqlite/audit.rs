@@ -0,0 +1,113 @@
+use async_trait::async_trait;
+use sqlx::{types::Json, QueryBuilder, Row, Sqlite};
+
+use cim_slo::{errors, Result};
+
+use crate::{
+    audit::{Audit, AuditStore, ListParams},
+    Conn, List,
+};
+
+#[derive(Clone, Debug)]
+pub struct AuditImpl {
+    conn: Conn<Sqlite>,
+}
+
+impl AuditImpl {
+    pub fn new(pool: impl Into<Conn<Sqlite>>) -> Self {
+        Self { conn: pool.into() }
+    }
+}
+
+#[async_trait]
+impl AuditStore for AuditImpl {
+    #[tracing::instrument]
+    async fn append(&self, input: &Audit) -> Result<()> {
+        sqlx::query(
+            r#"INSERT INTO `audit`
+            (`actor`,`trace_id`,`source_ip`,`kind`,`entity_id`,`action`,
+            `before`,`after`)
+            VALUES(?,?,?,?,?,?,?,?);"#,
+        )
+        .bind(&input.actor)
+        .bind(&input.trace_id)
+        .bind(&input.source_ip)
+        .bind(&input.kind)
+        .bind(&input.entity_id)
+        .bind(&input.action)
+        .bind(input.before.as_ref().map(Json))
+        .bind(input.after.as_ref().map(Json))
+        .execute(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        opts: &ListParams,
+        output: &mut List<Audit>,
+    ) -> Result<()> {
+        let filter = opts.filter();
+        // 查询total
+        if !opts.pagination.count_disable {
+            let mut builder =
+                QueryBuilder::new(r#"SELECT COUNT(*) as count FROM `audit`"#);
+            filter.push(&mut builder);
+            output.total = builder
+                .build()
+                .fetch_one(&mut *self.conn.acquire().await?)
+                .await
+                .map_err(errors::any)?
+                .try_get("count")
+                .map_err(errors::any)?;
+        }
+
+        // 查询列表
+        output.limit = opts.pagination.limit;
+        output.offset = opts.pagination.offset;
+
+        let mut builder = QueryBuilder::new(
+            r#"SELECT `id`,`actor`,`trace_id`,`source_ip`,`kind`,`entity_id`,
+                `action`,`before`,`after`,`created_at`
+                FROM `audit`"#,
+        );
+        opts.pagination
+            .seek(filter, ListParams::SORTABLE)?
+            .push(&mut builder);
+        opts.pagination.push(&mut builder, ListParams::SORTABLE)?;
+        let rows = builder
+            .build()
+            .fetch_all(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?;
+        for row in rows.iter() {
+            let json = |column| {
+                row.try_get::<Option<Json<serde_json::Value>>, _>(column)
+                    .map(|v| v.map(|Json(v)| v))
+                    .map_err(errors::any)
+            };
+            output.data.push(Audit {
+                id: row
+                    .try_get::<i64, _>("id")
+                    .map_err(errors::any)?
+                    .to_string(),
+                actor: row.try_get("actor").map_err(errors::any)?,
+                trace_id: row.try_get("trace_id").map_err(errors::any)?,
+                source_ip: row.try_get("source_ip").map_err(errors::any)?,
+                kind: row.try_get("kind").map_err(errors::any)?,
+                entity_id: row.try_get("entity_id").map_err(errors::any)?,
+                action: row.try_get("action").map_err(errors::any)?,
+                before: json("before")?,
+                after: json("after")?,
+                created_at: row.try_get("created_at").map_err(errors::any)?,
+            });
+        }
+
+        output.next_cursor = opts
+            .pagination
+            .next_cursor(&output.data, ListParams::SORTABLE)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,264 @@
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use sqlx::{sqlite::SqliteRow, types::Json, Row, Sqlite};
+
+use cim_slo::{errors, Result};
+
+use super::{label, lock, purge, restore, soft_delete, write_query, write_row};
+use crate::{
+    client::Client, discarded, label::Labels, stored, Conn, Interface, List,
+};
+
+#[derive(Clone, Debug)]
+pub struct ClientImpl {
+    conn: Conn<Sqlite>,
+}
+
+impl ClientImpl {
+    pub fn new(pool: impl Into<Conn<Sqlite>>) -> Self {
+        Self { conn: pool.into() }
+    }
+
+    /// write puts input, when resource_version is given only if the
+    /// stored one is still at it
+    async fn write(
+        &self,
+        input: &Client,
+        resource_version: Option<u64>,
+    ) -> Result<()> {
+        let query = write_query(
+            "client",
+            &[
+                "secret",
+                "redirect_uris",
+                "trusted_peers",
+                "name",
+                "logo_url",
+                "account_id",
+                "labels",
+                "annotations",
+            ],
+            resource_version.is_some(),
+        );
+        let query = sqlx::query(&query)
+            .bind(&input.id)
+            .bind(&input.secret)
+            .bind(Json(&input.redirect_uris))
+            .bind(Json(&input.trusted_peers))
+            .bind(&input.name)
+            .bind(&input.logo_url)
+            .bind(&input.account_id)
+            .bind(Json(&input.labels))
+            .bind(Json(&input.annotations))
+            .bind(&input.id);
+        write_row(&self.conn, query, "client", &input.id, resource_version)
+            .await?;
+        label(&self.conn, "client", &input.id, &input.labels).await?;
+        Ok(())
+    }
+
+    /// select lists the soft deleted rows when deleted, the live ones
+    /// otherwise
+    async fn select(
+        &self,
+        output: &mut List<Client>,
+        deleted: bool,
+    ) -> Result<()> {
+        let deleted = match deleted {
+            true => "<> 0",
+            false => "= 0",
+        };
+        let rows = sqlx::query(&format!(
+            r#"SELECT `id`,`secret`,`redirect_uris`,`trusted_peers`,`name`,`logo_url`,`account_id`,
+                `resource_version`,`labels`,`annotations`,`created_at`,`updated_at`
+                FROM `client`
+                WHERE `deleted` {deleted};"#
+        ))
+        .fetch_all(&mut *self.conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+        for row in rows.iter() {
+            output.data.push(client(row)?);
+        }
+        Ok(())
+    }
+}
+
+fn client(row: &SqliteRow) -> Result<Client> {
+    Ok(Client {
+        id: row
+            .try_get::<u64, _>("id")
+            .map_err(errors::any)?
+            .to_string(),
+        secret: row.try_get("secret").map_err(errors::any)?,
+        redirect_uris: row
+            .try_get::<Json<Vec<String>>, _>("redirect_uris")
+            .map_err(errors::any)?
+            .0,
+        trusted_peers: row
+            .try_get::<Json<Vec<String>>, _>("trusted_peers")
+            .map_err(errors::any)?
+            .0,
+        name: row.try_get("name").map_err(errors::any)?,
+        logo_url: row.try_get("logo_url").map_err(errors::any)?,
+        account_id: row.try_get("account_id").map_err(errors::any)?,
+        labels: row
+            .try_get::<Json<Labels>, _>("labels")
+            .map_err(errors::any)?
+            .0,
+        annotations: row
+            .try_get::<Json<Labels>, _>("annotations")
+            .map_err(errors::any)?
+            .0,
+        resource_version: row
+            .try_get::<i64, _>("resource_version")
+            .map_err(errors::any)? as u64,
+        created_at: row.try_get("created_at").map_err(errors::any)?,
+        updated_at: row.try_get("updated_at").map_err(errors::any)?,
+    })
+}
+
+#[async_trait]
+impl Interface for ClientImpl {
+    type T = Client;
+    type L = ();
+
+    #[tracing::instrument]
+    async fn put(&self, input: &Self::T) -> Result<()> {
+        // the row and its labels are indexed in the same transaction
+        self.conn
+            .scoped(
+                |conn| async move { Self { conn }.write(input, None).await },
+            )
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn delete(&self, input: &Self::T) -> Result<()> {
+        soft_delete(&self.conn, "client", &input.id, None).await
+    }
+
+    #[tracing::instrument]
+    async fn get(&self, output: &mut Self::T) -> Result<()> {
+        let id = output
+            .id
+            .parse::<i64>()
+            .map_err(|err| errors::bad_request(&err))?;
+        let row = match sqlx::query(
+            r#"SELECT `id`,`secret`,`redirect_uris`,`trusted_peers`,`name`,`logo_url`,`account_id`,
+                `resource_version`,`labels`,`annotations`,`created_at`,`updated_at`
+                FROM `client`
+                WHERE id = ? AND `deleted` = 0;"#,
+        )
+        .bind(id)
+        .fetch_optional(&mut *self.conn.acquire().await?)
+        .await
+        {
+            Ok(v) => match v {
+                Some(value) => Ok(value),
+                None => Err(errors::not_found("no rows")),
+            },
+            Err(err) => Err(errors::any(err)),
+        }?;
+        *output = client(&row)?;
+        Ok(())
+    }
+
+    #[tracing::instrument]
+    async fn list(
+        &self,
+        _opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(output, false).await
+    }
+
+    #[tracing::instrument]
+    async fn count(&self, _opts: &Self::L, unscoped: bool) -> Result<i64> {
+        let sql = if unscoped {
+            r#"SELECT COUNT(*) as count FROM `client`;"#
+        } else {
+            r#"SELECT COUNT(*) as count FROM `client` WHERE `deleted` = 0;"#
+        };
+        sqlx::query(sql)
+            .fetch_one(&mut *self.conn.acquire().await?)
+            .await
+            .map_err(errors::any)?
+            .try_get("count")
+            .map_err(errors::any)
+    }
+
+    #[tracing::instrument]
+    async fn put_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.conn
+            .scoped(|conn| async move {
+                Self { conn }.write(input, Some(resource_version)).await
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn replace(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "client", &input.id).await?;
+                let previous = stored(&store, input).await?;
+                store.write(input, resource_version).await?;
+                Ok(previous)
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn discard(
+        &self,
+        input: &Self::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<Self::T>> {
+        self.conn
+            .scoped(|conn| async move {
+                let store = Self { conn };
+                lock(&store.conn, "client", &input.id).await?;
+                discarded(&store, input, resource_version).await
+            })
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn delete_if(
+        &self,
+        input: &Self::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        soft_delete(&self.conn, "client", &input.id, Some(resource_version))
+            .await
+    }
+
+    #[tracing::instrument]
+    async fn list_deleted(
+        &self,
+        _opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.select(output, true).await
+    }
+
+    #[tracing::instrument]
+    async fn restore(&self, input: &Self::T) -> Result<()> {
+        restore(&self.conn, "client", &input.id).await
+    }
+
+    #[tracing::instrument]
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        purge(&self.conn, "client", before).await
+    }
+}
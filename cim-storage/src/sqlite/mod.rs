@@ -0,0 +1,1404 @@
+mod audit;
+mod authcode;
+mod authrequest;
+mod changelog;
+mod client;
+mod connector;
+mod gc;
+mod group;
+mod group_user;
+mod key;
+mod offlinesession;
+mod policy;
+mod policy_binding;
+mod refresh_token;
+mod relation_tuple;
+mod role;
+mod role_binding;
+mod user;
+
+use chrono::NaiveDateTime;
+use sqlx::{query::Query, sqlite::SqliteArguments, QueryBuilder, Sqlite};
+
+use cim_slo::{errors, Result};
+
+use crate::{label, Conn};
+
+pub use audit::AuditImpl;
+pub use authcode::AuthCodeImpl;
+pub use authrequest::AuthRequestImpl;
+pub use changelog::ChangelogImpl;
+pub use client::ClientImpl;
+pub use connector::ConnectorImpl;
+pub use gc::GcImpl;
+pub use group::GroupImpl;
+pub use group_user::GroupUserImpl;
+pub use key::KeysImpl;
+pub use offlinesession::OfflineSessionImpl;
+pub use policy::PolicyImpl;
+pub use policy_binding::PolicyBindingImpl;
+pub use refresh_token::RefreshTokenImpl;
+pub use relation_tuple::RelationTupleImpl;
+pub use role::RoleImpl;
+pub use role_binding::RoleBindingImpl;
+pub use user::UserImpl;
+
+/// write_query writes row `id` of table with columns, binding the id, the
+/// columns and the id again. The resource_version is bumped from the stored
+/// one and created_at is kept, a soft deleted row is left alone. When
+/// guarded only the live row still at the resource_version bound last is
+/// written
+fn write_query(table: &str, columns: &[&str], guarded: bool) -> String {
+    if guarded {
+        let set: Vec<_> =
+            columns.iter().map(|v| format!("`{v}` = ?")).collect();
+        format!(
+            "UPDATE `{table}` SET `id` = ?, {},
+                `resource_version` = `resource_version` + 1
+                WHERE `id` = ? AND `resource_version` = ? AND `deleted` = 0;",
+            set.join(",")
+        )
+    } else {
+        let names: Vec<_> = columns.iter().map(|v| format!("`{v}`")).collect();
+        let set: Vec<_> = columns
+            .iter()
+            .map(|v| format!("`{v}` = excluded.`{v}`"))
+            .collect();
+        format!(
+            "INSERT INTO `{table}` (`id`,{},`resource_version`)
+                SELECT ?,{},1 WHERE NOT EXISTS (
+                    SELECT 1 FROM `{table}` WHERE `id` = ? AND `deleted` <> 0
+                )
+                ON CONFLICT (`id`) DO UPDATE SET {},
+                `resource_version` = `resource_version` + 1;",
+            names.join(","),
+            vec!["?"; columns.len()].join(","),
+            set.join(",")
+        )
+    }
+}
+
+/// write_row runs query built on write_query, it conflicts when the row isn't
+/// at resource_version any more or is soft deleted
+async fn write_row<'q>(
+    conn: &Conn<Sqlite>,
+    query: Query<'q, Sqlite, SqliteArguments<'q>>,
+    table: &str,
+    id: &str,
+    resource_version: Option<u64>,
+) -> Result<()> {
+    let query = match resource_version {
+        Some(v) => query.bind(v as i64),
+        None => query,
+    };
+    let result = query
+        .execute(&mut *conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+    if result.rows_affected() != 0 {
+        return Ok(());
+    }
+    Err(match resource_version {
+        Some(v) => errors::conflict(&format!(
+            "{table} {id} is not at resource version {v}"
+        )),
+        None => errors::conflict(&format!(
+            "{table} {id} is deleted, it can only be restored"
+        )),
+    })
+}
+
+/// soft_delete marks row id of table deleted, when resource_version is
+/// given only if it is still at it, bumping it
+async fn soft_delete(
+    conn: &Conn<Sqlite>,
+    table: &str,
+    id: &str,
+    resource_version: Option<u64>,
+) -> Result<()> {
+    let key = id.parse::<i64>().map_err(|err| errors::bad_request(&err))?;
+    let guard = match resource_version {
+        Some(_) => "AND `resource_version` = ?",
+        None => "",
+    };
+    let query = format!(
+        "UPDATE `{table}` SET `deleted` = `id`,
+            `deleted_at` = CURRENT_TIMESTAMP,
+            `resource_version` = `resource_version` + 1
+            WHERE `id` = ? {guard} AND `deleted` = 0;"
+    );
+    let query = sqlx::query(&query).bind(key);
+    let query = match resource_version {
+        Some(v) => query.bind(v as i64),
+        None => query,
+    };
+    let result = query
+        .execute(&mut *conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+    match resource_version {
+        Some(v) if result.rows_affected() == 0 => Err(errors::conflict(
+            &format!("{table} {id} is not at resource version {v}"),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// lock makes the transaction of conn the writer of the database before
+/// row id of table is read, so that no other write lands in between
+async fn lock(conn: &Conn<Sqlite>, table: &str, id: &str) -> Result<()> {
+    let key = id.parse::<i64>().map_err(|err| errors::bad_request(&err))?;
+    sqlx::query(&format!("UPDATE `{table}` SET `id` = `id` WHERE `id` = ?;"))
+        .bind(key)
+        .execute(&mut *conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+    Ok(())
+}
+
+/// restore undeletes row id of table, bumping its resource_version. It
+/// conflicts with a live row holding the same unique columns
+async fn restore(conn: &Conn<Sqlite>, table: &str, id: &str) -> Result<()> {
+    let key = id.parse::<i64>().map_err(|err| errors::bad_request(&err))?;
+    let result = sqlx::query(&format!(
+        "UPDATE `{table}` SET `deleted` = 0, `deleted_at` = NULL,
+            `resource_version` = `resource_version` + 1
+            WHERE `id` = ? AND `deleted` <> 0;"
+    ))
+    .bind(key)
+    .execute(&mut *conn.acquire().await?)
+    .await
+    .map_err(|err| match err.as_database_error() {
+        Some(v) if v.is_unique_violation() => {
+            errors::conflict(&format!("{table} {id} is taken by a live one"))
+        }
+        _ => errors::any(err),
+    })?;
+    if result.rows_affected() == 0 {
+        return Err(errors::not_found(&format!("{table} {id} is not deleted")));
+    }
+    Ok(())
+}
+
+/// purge hard deletes the rows of table soft deleted before
+async fn purge(
+    conn: &Conn<Sqlite>,
+    table: &str,
+    before: NaiveDateTime,
+) -> Result<u64> {
+    let result = sqlx::query(&format!(
+        "DELETE FROM `{table}`
+            WHERE `deleted` <> 0 AND `deleted_at` < ?;"
+    ))
+    .bind(before)
+    .execute(&mut *conn.acquire().await?)
+    .await
+    .map_err(errors::any)?;
+    if label::KINDS.contains(&table) {
+        sqlx::query(&format!(
+            r#"DELETE FROM `label` WHERE `kind` = ?
+                AND `entity_id` NOT IN (SELECT `id` FROM `{table}`);"#
+        ))
+        .bind(table)
+        .execute(&mut *conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+    }
+    Ok(result.rows_affected())
+}
+
+/// label indexes the labels of row id of kind for the label selectors,
+/// replacing the ones it had
+async fn label(
+    conn: &Conn<Sqlite>,
+    kind: &str,
+    id: &str,
+    labels: &label::Labels,
+) -> Result<()> {
+    let key = id.parse::<i64>().map_err(|err| errors::bad_request(&err))?;
+    sqlx::query(r#"DELETE FROM `label` WHERE `kind` = ? AND `entity_id` = ?;"#)
+        .bind(kind)
+        .bind(key)
+        .execute(&mut *conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+    if labels.is_empty() {
+        return Ok(());
+    }
+    let mut builder = QueryBuilder::new(
+        r#"INSERT INTO `label` (`kind`,`entity_id`,`name`,`value`) "#,
+    );
+    builder.push_values(labels, |mut b, (name, value)| {
+        b.push_bind(kind)
+            .push_bind(key)
+            .push_bind(name)
+            .push_bind(value);
+    });
+    builder
+        .build()
+        .execute(&mut *conn.acquire().await?)
+        .await
+        .map_err(errors::any)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Timelike, Utc};
+    use cim_pim::{rebac::TupleReader, PrincipalType, Request};
+
+    use super::*;
+    use crate::{
+        audit::{self, Audit, AuditStore},
+        authcode::AuthCode,
+        authrequest::AuthRequest,
+        changelog::ChangeStore,
+        client::Client,
+        connection_manager,
+        gc::GcStore,
+        group::{self, Group},
+        group_user::{self, GroupUser},
+        key::{KeyRing, Keys, PublicKeys},
+        label::Labels,
+        policy::{self, Policy, StatementStore},
+        policy_binding::{BindingsType, PolicyBinding},
+        refresh_token::RefreshToken,
+        relation_tuple::RelationTuple,
+        role::Role,
+        role_binding::{RoleBinding, UserType},
+        user::{self, User},
+        Interface, List, Pagination, Pool, TxConn,
+    };
+
+    async fn pool() -> sqlx::SqlitePool {
+        match connection_manager("sqlite::memory:", 1, 1, true)
+            .await
+            .unwrap()
+        {
+            Pool::Sqlite(pool) => pool,
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn user_crud() {
+        let store = UserImpl::new(pool().await);
+        let mut input = User {
+            id: "1".to_owned(),
+            account_id: "1".to_owned(),
+            desc: "admin".to_owned(),
+            password: Some("Pa$$w0rd".to_owned()),
+            ..Default::default()
+        };
+        input.claim.name = Some("o'neil".to_owned());
+        store.put(&input).await.unwrap();
+
+        let mut output = User {
+            id: "1".to_owned(),
+            ..Default::default()
+        };
+        store.get(&mut output).await.unwrap();
+        assert_eq!(output.claim.name.as_deref(), Some("o'neil"));
+        assert!(output.secret.is_some());
+
+        let mut list = List::default();
+        store
+            .list(
+                &user::ListParams {
+                    id: None,
+                    account_id: Some("1".to_owned()),
+                    group_id: None,
+                    effective_group_id: None,
+                    label_selector: None,
+                    pagination: Pagination::default(),
+                },
+                &mut list,
+            )
+            .await
+            .unwrap();
+        assert_eq!(list.total, 1);
+        assert_eq!(list.data.len(), 1);
+
+        store.delete(&output).await.unwrap();
+        assert!(store.get(&mut output).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn cursor() {
+        let store = UserImpl::new(pool().await);
+        for id in 1..=5 {
+            store
+                .put(&User {
+                    id: id.to_string(),
+                    account_id: "1".to_owned(),
+                    password: Some("Pa$$w0rd".to_owned()),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        let mut opts = user::ListParams {
+            id: None,
+            account_id: None,
+            group_id: None,
+            effective_group_id: None,
+            label_selector: None,
+            pagination: Pagination {
+                limit: 2,
+                order_by: Some("created_at desc".to_owned()),
+                ..Default::default()
+            },
+        };
+        let mut ids = Vec::new();
+        loop {
+            let mut list = List::default();
+            store.list(&opts, &mut list).await.unwrap();
+            assert_eq!(list.total, 5);
+            ids.extend(list.data.into_iter().map(|v| v.id));
+            match list.next_cursor {
+                Some(cursor) => opts.pagination.cursor = Some(cursor),
+                None => break,
+            }
+        }
+        assert_eq!(ids, vec!["5", "4", "3", "2", "1"]);
+
+        opts.pagination.order_by = Some("id".to_owned());
+        assert!(store.list(&opts, &mut List::default()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn get_statement() {
+        let pool = pool().await;
+        GroupImpl::new(pool.clone())
+            .put(&Group {
+                id: "10".to_owned(),
+                account_id: "1".to_owned(),
+                name: "dev".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let group_user = GroupUserImpl::new(pool.clone());
+        group_user
+            .put(&GroupUser {
+                id: "20".to_owned(),
+                group_id: "10".to_owned(),
+                user_id: "2".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let policy = PolicyImpl::new(pool.clone());
+        for id in ["30", "31"] {
+            policy
+                .put(&Policy {
+                    id: id.to_owned(),
+                    version: "v1.0.0".to_owned(),
+                    statement: serde_json::from_value(serde_json::json!([{
+                        "effect": "Allow",
+                        "subjects": ["2"],
+                        "actions": ["get"],
+                        "resources": [format!("crn:iam:policy:{}", id)],
+                    }]))
+                    .unwrap(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        let policy_binding = PolicyBindingImpl::new(pool.clone());
+        policy_binding
+            .put(&PolicyBinding {
+                id: "40".to_owned(),
+                policy_id: "30".to_owned(),
+                bindings_type: BindingsType::Group,
+                bindings_id: "10".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let mut output = PolicyBinding {
+            id: "40".to_owned(),
+            ..Default::default()
+        };
+        policy_binding.get(&mut output).await.unwrap();
+        assert_eq!(output.bindings_id, "10");
+
+        let statements = policy
+            .get_statement(&Request {
+                resource: "crn:iam:policy:30".to_owned(),
+                action: "get".to_owned(),
+                subject: "2".to_owned(),
+                principal_type: PrincipalType::User,
+                context: Default::default(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(statements.len(), 1);
+        assert_eq!(statements[0].resources, vec!["crn:iam:policy:30"]);
+
+        let bindings =
+            policy.get_bindings(PrincipalType::User, "2").await.unwrap();
+        assert_eq!(bindings.groups, vec!["10"]);
+        assert!(bindings.roles.is_empty());
+        assert_eq!(bindings.policies, vec!["30"]);
+
+        let count = group_user
+            .count(
+                &group_user::ListParams {
+                    id: None,
+                    account_id: None,
+                    group_id: Some("10".to_owned()),
+                    user_id: None,
+                    expires_before: None,
+                    pagination: Pagination::default(),
+                },
+                false,
+            )
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+
+        let mut list = List::default();
+        let mut opts = policy::ListParams {
+            id: None,
+            version: None,
+            account_id: None,
+            group_id: None,
+            user_id: None,
+            role_id: None,
+            label_selector: None,
+            pagination: Pagination {
+                order_by: Some("id desc".to_owned()),
+                ..Default::default()
+            },
+        };
+        opts.group_id = Some("10".to_owned());
+        policy.list(&opts, &mut list).await.unwrap();
+        assert_eq!(list.total, 1);
+        assert_eq!(list.data[0].id, "30");
+
+        opts.pagination.order_by = Some("statement desc".to_owned());
+        assert!(policy.list(&opts, &mut List::default()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn relation_tuple() {
+        let store = RelationTupleImpl::new(pool().await);
+        store
+            .put(&RelationTuple {
+                id: "1".to_owned(),
+                account_id: "1".to_owned(),
+                object: "document:readme".to_owned(),
+                relation: "viewer".to_owned(),
+                subject: "group:eng#member".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let subjects = store.read("document:readme", "viewer").await.unwrap();
+        assert_eq!(subjects.len(), 1);
+        assert_eq!(subjects[0].to_string(), "group:eng#member");
+    }
+
+    #[tokio::test]
+    async fn resource_version() {
+        let store = GroupImpl::new(pool().await);
+        let mut group = Group {
+            id: "1".to_owned(),
+            account_id: "1".to_owned(),
+            ..Default::default()
+        };
+        store.put(&group).await.unwrap();
+        store.get(&mut group).await.unwrap();
+        assert_eq!(group.resource_version, 1);
+
+        group.name = "first".to_owned();
+        store.put_if(&group, 1).await.unwrap();
+        group.name = "second".to_owned();
+        let err = store.put_if(&group, 1).await.unwrap_err();
+        assert!(err.to_string().starts_with("Conflict."));
+        store.get(&mut group).await.unwrap();
+        assert_eq!(group.resource_version, 2);
+        assert_eq!(group.name, "first");
+
+        // a put bumps the stored version, whatever version it is given
+        store
+            .put(&Group {
+                resource_version: 0,
+                ..group.clone()
+            })
+            .await
+            .unwrap();
+        store.get(&mut group).await.unwrap();
+        assert_eq!(group.resource_version, 3);
+
+        assert!(store.delete_if(&group, 2).await.is_err());
+        store.delete_if(&group, 3).await.unwrap();
+        assert!(store.get(&mut group).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn audit() {
+        let store = AuditImpl::new(pool().await);
+        // appended records stay, so every run audits as another actor
+        let actor = chrono::Utc::now().timestamp_micros().to_string();
+        for (kind, entity_id) in
+            [("policy", "1"), ("policy", "2"), ("group", "1")]
+        {
+            store
+                .append(&Audit {
+                    actor: actor.clone(),
+                    trace_id: "trace".to_owned(),
+                    source_ip: "127.0.0.1".to_owned(),
+                    kind: kind.to_owned(),
+                    entity_id: entity_id.to_owned(),
+                    action: "put".to_owned(),
+                    before: None,
+                    after: Some(serde_json::json!({"id": entity_id})),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        let mut opts = audit::ListParams {
+            actor: Some(actor.clone()),
+            kind: Some("policy".to_owned()),
+            entity_id: None,
+            since: None,
+            until: None,
+            pagination: Pagination {
+                order_by: Some("id desc".to_owned()),
+                ..Default::default()
+            },
+        };
+        let mut list = List::default();
+        store.list(&opts, &mut list).await.unwrap();
+        assert_eq!(list.total, 2);
+        assert_eq!(list.data[0].entity_id, "2");
+        assert_eq!(list.data[0].before, None);
+        assert_eq!(list.data[0].after, Some(serde_json::json!({"id": "2"})));
+
+        let created_at = list.data[0].created_at;
+        opts.kind = None;
+        opts.until = Some(created_at - chrono::Duration::hours(1));
+        let mut list = List::default();
+        store.list(&opts, &mut list).await.unwrap();
+        assert_eq!(list.total, 0);
+        opts.since = Some(created_at - chrono::Duration::hours(1));
+        opts.until = None;
+        store.list(&opts, &mut list).await.unwrap();
+        assert_eq!(list.total, 3);
+    }
+
+    #[tokio::test]
+    async fn changelog() {
+        let store = ChangelogImpl::new(pool().await);
+        let last = store.last().await.unwrap();
+        store.append("group", "{}").await.unwrap();
+        store.append("user", "[]").await.unwrap();
+        let changes = store.since(last, 10).await.unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].kind, "group");
+        assert!(changes[0].id < changes[1].id);
+        assert_eq!(store.last().await.unwrap(), changes[1].id);
+        assert_eq!(store.since(changes[0].id, 10).await.unwrap().len(), 1);
+
+        let future =
+            chrono::Utc::now().naive_utc() + chrono::Duration::hours(1);
+        assert!(store.purge(future).await.unwrap() >= 2);
+        assert!(store.since(last, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn transaction() {
+        let pool = connection_manager("sqlite::memory:", 1, 1, true)
+            .await
+            .unwrap();
+        let group = Group {
+            id: "10".to_owned(),
+            account_id: "1".to_owned(),
+            name: "dev".to_owned(),
+            ..Default::default()
+        };
+        for commit in [false, true] {
+            let tx = pool.begin().await.unwrap();
+            let TxConn::Sqlite(conn) = tx.conn() else {
+                unreachable!()
+            };
+            GroupImpl::new(conn.clone()).put(&group).await.unwrap();
+            if commit {
+                tx.commit().await.unwrap();
+            } else {
+                tx.rollback().await.unwrap();
+            }
+            let Pool::Sqlite(pool) = &pool else {
+                unreachable!()
+            };
+            let found = GroupImpl::new(pool.clone())
+                .get(&mut group.clone())
+                .await
+                .is_ok();
+            assert_eq!(found, commit);
+        }
+    }
+
+    #[tokio::test]
+    async fn soft_delete() {
+        let store = GroupImpl::new(pool().await);
+        let mut group = Group {
+            id: "1".to_owned(),
+            account_id: "1".to_owned(),
+            ..Default::default()
+        };
+        store.put(&group).await.unwrap();
+        store.delete(&group).await.unwrap();
+        let opts = group::ListParams {
+            id: None,
+            account_id: Some("1".to_owned()),
+            parent_id: None,
+            user_id: None,
+            effective_user_id: None,
+            label_selector: None,
+            pagination: Pagination::default(),
+        };
+        let mut output = List::default();
+        store.list(&opts, &mut output).await.unwrap();
+        assert!(output.data.is_empty());
+        store.list_deleted(&opts, &mut output).await.unwrap();
+        assert_eq!(output.data.len(), 1);
+
+        store.restore(&group).await.unwrap();
+        store.get(&mut group).await.unwrap();
+        assert_eq!(group.resource_version, 3);
+        assert!(store.restore(&group).await.is_err());
+
+        // discard returns the live entity it deleted, none when there is
+        // none
+        let previous = store.discard(&group, Some(3)).await.unwrap();
+        assert_eq!(previous.unwrap().resource_version, 3);
+        assert!(store.discard(&group, None).await.unwrap().is_none());
+        store.restore(&group).await.unwrap();
+        store.delete(&group).await.unwrap();
+        let before =
+            chrono::Utc::now().naive_utc() - chrono::Duration::hours(1);
+        assert_eq!(store.purge(before).await.unwrap(), 0);
+        let before =
+            chrono::Utc::now().naive_utc() + chrono::Duration::hours(1);
+        assert!(store.purge(before).await.unwrap() == 1);
+        assert_eq!(store.count(&opts, true).await.unwrap(), 0);
+        assert!(store.restore(&group).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn labels() {
+        let db = pool().await;
+        let store = GroupImpl::new(db.clone());
+        for (id, env, tier) in [
+            ("11", "prod", "web"),
+            ("12", "prod", "api"),
+            ("13", "test", "web"),
+        ] {
+            store
+                .put(&Group {
+                    id: id.to_owned(),
+                    account_id: "1".to_owned(),
+                    labels: Labels::from([
+                        ("env".to_owned(), env.to_owned()),
+                        ("tier".to_owned(), tier.to_owned()),
+                    ]),
+                    annotations: Labels::from([(
+                        "note".to_owned(),
+                        format!("group {id}"),
+                    )]),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        let mut group = Group {
+            id: "13".to_owned(),
+            ..Default::default()
+        };
+        store.get(&mut group).await.unwrap();
+        assert_eq!(group.labels["env"], "test");
+        assert_eq!(group.annotations["note"], "group 13");
+        group.labels.remove("env");
+        store.put(&group).await.unwrap();
+
+        for (selector, ids) in [
+            ("env=prod", vec!["11", "12"]),
+            ("env=prod,tier!=web", vec!["12"]),
+            ("tier in (web,api),env notin (prod)", vec!["13"]),
+            ("!env", vec!["13"]),
+            ("env", vec!["11", "12"]),
+            ("owner", vec![]),
+        ] {
+            let mut output = List::default();
+            store
+                .list(
+                    &group::ListParams {
+                        id: None,
+                        account_id: Some("1".to_owned()),
+                        parent_id: None,
+                        user_id: None,
+                        effective_user_id: None,
+                        label_selector: Some(selector.parse().unwrap()),
+                        pagination: Pagination::default(),
+                    },
+                    &mut output,
+                )
+                .await
+                .unwrap();
+            let selected: Vec<_> =
+                output.data.iter().map(|v| v.id.as_str()).collect();
+            assert_eq!(selected, ids, "{}", selector);
+        }
+
+        store.delete(&group).await.unwrap();
+        let before =
+            chrono::Utc::now().naive_utc() + chrono::Duration::hours(1);
+        assert_eq!(store.purge(before).await.unwrap(), 1);
+        let indexed: i64 = sqlx::query_scalar(
+            r#"SELECT COUNT(*) FROM `label` WHERE `entity_id` = ?"#,
+        )
+        .bind(13i64)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert_eq!(indexed, 0);
+    }
+
+    #[tokio::test]
+    async fn nested_groups() {
+        let db = pool().await;
+        let group = GroupImpl::new(db.clone());
+        for (id, parent_id) in
+            [("11", None), ("12", Some("11")), ("13", Some("12"))]
+        {
+            group
+                .put(&Group {
+                    id: id.to_owned(),
+                    account_id: "1".to_owned(),
+                    parent_id: parent_id.map(str::to_owned),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        UserImpl::new(db.clone())
+            .put(&User {
+                id: "3".to_owned(),
+                account_id: "1".to_owned(),
+                password: Some("Pa$$w0rd".to_owned()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        GroupUserImpl::new(db.clone())
+            .put(&GroupUser {
+                id: "20".to_owned(),
+                group_id: "13".to_owned(),
+                user_id: "3".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let policy = PolicyImpl::new(db.clone());
+        policy
+            .put(&Policy {
+                id: "30".to_owned(),
+                version: "v1.0.0".to_owned(),
+                statement: serde_json::from_value(serde_json::json!([{
+                    "effect": "Allow",
+                    "subjects": ["3"],
+                    "actions": ["get"],
+                    "resources": ["crn:iam:group:11"],
+                }]))
+                .unwrap(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        PolicyBindingImpl::new(db.clone())
+            .put(&PolicyBinding {
+                id: "40".to_owned(),
+                policy_id: "30".to_owned(),
+                bindings_type: BindingsType::Group,
+                bindings_id: "11".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        // the member of the nested group gets the policy of its ancestors
+        let statements = policy
+            .get_statement(&Request {
+                resource: "crn:iam:group:11".to_owned(),
+                action: "get".to_owned(),
+                subject: "3".to_owned(),
+                principal_type: PrincipalType::User,
+                context: Default::default(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(statements.len(), 1);
+        let mut bindings =
+            policy.get_bindings(PrincipalType::User, "3").await.unwrap();
+        bindings.groups.sort();
+        assert_eq!(bindings.groups, vec!["11", "12", "13"]);
+        assert_eq!(bindings.policies, vec!["30"]);
+
+        // a membership not in effect yet doesn't make a member
+        group
+            .put(&Group {
+                id: "14".to_owned(),
+                account_id: "1".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        GroupUserImpl::new(db.clone())
+            .put(&GroupUser {
+                id: "21".to_owned(),
+                group_id: "14".to_owned(),
+                user_id: "3".to_owned(),
+                not_before: Some(Utc::now().naive_utc() + Duration::hours(1)),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let mut users = List::default();
+        UserImpl::new(db.clone())
+            .list(
+                &user::ListParams {
+                    id: None,
+                    account_id: None,
+                    group_id: None,
+                    effective_group_id: Some("11".to_owned()),
+                    label_selector: None,
+                    pagination: Pagination::default(),
+                },
+                &mut users,
+            )
+            .await
+            .unwrap();
+        assert_eq!(users.data.len(), 1);
+        for (parent_id, user_id, effective_user_id, ids) in [
+            (Some("11"), None, None, vec!["12"]),
+            (None, Some("3"), None, vec!["13", "14"]),
+            (None, None, Some("3"), vec!["11", "12", "13"]),
+        ] {
+            let mut output = List::default();
+            group
+                .list(
+                    &group::ListParams {
+                        id: None,
+                        account_id: Some("1".to_owned()),
+                        parent_id: parent_id.map(str::to_owned),
+                        user_id: user_id.map(str::to_owned),
+                        effective_user_id: effective_user_id.map(str::to_owned),
+                        label_selector: None,
+                        pagination: Pagination::default(),
+                    },
+                    &mut output,
+                )
+                .await
+                .unwrap();
+            let mut listed: Vec<_> =
+                output.data.iter().map(|v| v.id.as_str()).collect();
+            listed.sort();
+            assert_eq!(listed, ids);
+        }
+
+        let mut users = List::default();
+        UserImpl::new(db.clone())
+            .list(
+                &user::ListParams {
+                    id: None,
+                    account_id: None,
+                    group_id: None,
+                    effective_group_id: Some("14".to_owned()),
+                    label_selector: None,
+                    pagination: Pagination::default(),
+                },
+                &mut users,
+            )
+            .await
+            .unwrap();
+        assert!(users.data.is_empty());
+
+        let mut root = Group {
+            id: "11".to_owned(),
+            ..Default::default()
+        };
+        group.get(&mut root).await.unwrap();
+        root.parent_id = Some("13".to_owned());
+        assert!(group.put(&root).await.is_err());
+        let middle = Group {
+            id: "12".to_owned(),
+            ..Default::default()
+        };
+        assert!(group.delete(&middle).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn principals() {
+        let db = pool().await;
+        RoleImpl::new(db.clone())
+            .put(&Role {
+                id: "61".to_owned(),
+                account_id: "1".to_owned(),
+                name: "deployer-61".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let policy = PolicyImpl::new(db.clone());
+        policy
+            .put(&Policy {
+                id: "62".to_owned(),
+                version: "v1.0.0".to_owned(),
+                statement: serde_json::from_value(serde_json::json!([{
+                    "effect": "Allow",
+                    "subjects": ["<.*>"],
+                    "actions": ["update"],
+                    "resources": ["crn:iam:deployment:62"],
+                }]))
+                .unwrap(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        PolicyBindingImpl::new(db.clone())
+            .put(&PolicyBinding {
+                id: "63".to_owned(),
+                policy_id: "62".to_owned(),
+                bindings_type: BindingsType::Role,
+                bindings_id: "61".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let role_binding = RoleBindingImpl::new(db.clone());
+        for (id, user_type, user_id) in [
+            ("64", UserType::Application, "66"),
+            ("65", UserType::FederatedUser, "github|66"),
+        ] {
+            role_binding
+                .put(&RoleBinding {
+                    id: id.to_owned(),
+                    role_id: "61".to_owned(),
+                    user_type,
+                    user_id: user_id.to_owned(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        // applications and federated users are bound policies directly by
+        // their subject
+        policy
+            .put(&Policy {
+                id: "67".to_owned(),
+                version: "v1.0.0".to_owned(),
+                statement: serde_json::from_value(serde_json::json!([{
+                    "effect": "Allow",
+                    "subjects": ["<.*>"],
+                    "actions": ["get"],
+                    "resources": ["crn:iam:deployment:67"],
+                }]))
+                .unwrap(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let policy_binding = PolicyBindingImpl::new(db.clone());
+        for (id, bindings_type, bindings_id) in [
+            ("68", BindingsType::Application, "66"),
+            ("69", BindingsType::FederatedUser, "github|66"),
+        ] {
+            policy_binding
+                .put(&PolicyBinding {
+                    id: id.to_owned(),
+                    policy_id: "67".to_owned(),
+                    bindings_type,
+                    bindings_id: bindings_id.to_owned(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        // the user sharing its id with the application is bound neither the
+        // role nor the policy
+        for (principal_type, subject, bound) in [
+            (PrincipalType::Application, "66", 1),
+            (PrincipalType::FederatedUser, "github|66", 1),
+            (PrincipalType::User, "66", 0),
+            (PrincipalType::Application, "github|66", 0),
+        ] {
+            let statements = policy
+                .get_statement(&Request {
+                    resource: "crn:iam:deployment:62".to_owned(),
+                    action: "update".to_owned(),
+                    subject: subject.to_owned(),
+                    principal_type,
+                    context: Default::default(),
+                })
+                .await
+                .unwrap();
+            assert_eq!(
+                statements.len(),
+                2 * bound,
+                "{:?} {}",
+                principal_type,
+                subject
+            );
+            let bindings =
+                policy.get_bindings(principal_type, subject).await.unwrap();
+            assert!(bindings.groups.is_empty());
+            assert_eq!(bindings.roles.len(), bound);
+            assert_eq!(bindings.policies.len(), 2 * bound);
+        }
+    }
+
+    #[tokio::test]
+    async fn windows() {
+        let db = pool().await;
+        // whole seconds, which every store keeps as is
+        let now = Utc::now().naive_utc().with_nanosecond(0).unwrap();
+        let hour = Duration::hours(1);
+        GroupImpl::new(db.clone())
+            .put(&Group {
+                id: "71".to_owned(),
+                account_id: "1".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let policy = PolicyImpl::new(db.clone());
+        policy
+            .put(&Policy {
+                id: "72".to_owned(),
+                version: "v1.0.0".to_owned(),
+                statement: serde_json::from_value(serde_json::json!([{
+                    "effect": "Allow",
+                    "subjects": ["<.*>"],
+                    "actions": ["read"],
+                    "resources": ["crn:iam:document:72"],
+                }]))
+                .unwrap(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        // the membership takes effect in an hour
+        GroupUserImpl::new(db.clone())
+            .put(&GroupUser {
+                id: "73".to_owned(),
+                group_id: "71".to_owned(),
+                user_id: "76".to_owned(),
+                not_before: Some(now + hour),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        // the role binding expired an hour ago
+        RoleBindingImpl::new(db.clone())
+            .put(&RoleBinding {
+                id: "74".to_owned(),
+                role_id: "75".to_owned(),
+                user_type: UserType::User,
+                user_id: "77".to_owned(),
+                expires_at: Some(now - hour),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let policy_binding = PolicyBindingImpl::new(db.clone());
+        for (id, bindings_type, bindings_id, expires_at) in [
+            ("81", BindingsType::Group, "71", None),
+            ("82", BindingsType::Role, "75", None),
+            ("83", BindingsType::User, "78", Some(now + hour * 2)),
+        ] {
+            policy_binding
+                .put(&PolicyBinding {
+                    id: id.to_owned(),
+                    policy_id: "72".to_owned(),
+                    bindings_type,
+                    bindings_id: bindings_id.to_owned(),
+                    expires_at,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        for (subject, bound, changes_at) in [
+            ("76", 0, Some(now + hour)),
+            ("77", 0, None),
+            ("78", 1, Some(now + hour * 2)),
+        ] {
+            let statements = policy
+                .get_statement(&Request {
+                    resource: "crn:iam:document:72".to_owned(),
+                    action: "read".to_owned(),
+                    subject: subject.to_owned(),
+                    principal_type: PrincipalType::User,
+                    context: Default::default(),
+                })
+                .await
+                .unwrap();
+            assert_eq!(statements.len(), bound, "{}", subject);
+            let bindings = policy
+                .get_bindings(PrincipalType::User, subject)
+                .await
+                .unwrap();
+            assert_eq!(bindings.changes_at, changes_at, "{}", subject);
+        }
+    }
+
+    #[tokio::test]
+    async fn gc() {
+        let db = pool().await;
+        let gc = GcImpl::new(db.clone());
+        let requests = AuthRequestImpl::new(db.clone());
+        let codes = AuthCodeImpl::new(db.clone());
+        let tokens = RefreshTokenImpl::new(db);
+        for (id, expiry) in [("1", 100), ("2", 300)] {
+            requests
+                .put(&AuthRequest {
+                    id: id.to_owned(),
+                    client_id: "1".to_owned(),
+                    expiry,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            codes
+                .put(&AuthCode {
+                    id: id.to_owned(),
+                    client_id: "1".to_owned(),
+                    expiry,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        assert_eq!(gc.purge_auth_requests(200).await.unwrap(), 1);
+        assert_eq!(gc.purge_auth_codes(200).await.unwrap(), 1);
+        let mut request = AuthRequest {
+            id: "1".to_owned(),
+            ..Default::default()
+        };
+        assert!(requests.get(&mut request).await.is_err());
+        request.id = "2".to_owned();
+        requests.get(&mut request).await.unwrap();
+
+        let now = chrono::Utc::now().naive_utc();
+        for (id, last_used_at) in
+            [("1", now - chrono::Duration::days(2)), ("2", now)]
+        {
+            tokens
+                .put(&RefreshToken {
+                    id: id.to_owned(),
+                    client_id: "1".to_owned(),
+                    last_used_at,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        assert_eq!(gc.purge_refresh_tokens(None, None).await.unwrap(), 0);
+        let used_before = now - chrono::Duration::days(1);
+        assert_eq!(
+            gc.purge_refresh_tokens(None, Some(used_before))
+                .await
+                .unwrap(),
+            1
+        );
+        let created_before = now + chrono::Duration::hours(1);
+        assert_eq!(
+            gc.purge_refresh_tokens(Some(created_before), Some(used_before))
+                .await
+                .unwrap(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn oidc_list() {
+        let db = pool().await;
+        let requests = AuthRequestImpl::new(db.clone());
+        let codes = AuthCodeImpl::new(db.clone());
+        let tokens = RefreshTokenImpl::new(db.clone());
+        let clients = ClientImpl::new(db.clone());
+        for id in ["1", "2"] {
+            requests
+                .put(&AuthRequest {
+                    id: id.to_owned(),
+                    client_id: "1".to_owned(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            codes
+                .put(&AuthCode {
+                    id: id.to_owned(),
+                    client_id: "1".to_owned(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            tokens
+                .put(&RefreshToken {
+                    id: id.to_owned(),
+                    client_id: "1".to_owned(),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            clients
+                .put(&Client {
+                    id: id.to_owned(),
+                    name: format!("client-{id}"),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+        let keys = KeysImpl::new(db);
+        keys.put(&Keys {
+            id: "1".to_owned(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let mut list = List::default();
+        requests.list(&(), &mut list).await.unwrap();
+        assert_eq!((list.total, list.data[1].id.as_str()), (2, "2"));
+        let mut list = List::default();
+        codes.list(&(), &mut list).await.unwrap();
+        assert_eq!((list.total, list.data[1].client_id.as_str()), (2, "1"));
+        assert_eq!(requests.count(&(), false).await.unwrap(), 2);
+        assert_eq!(codes.count(&(), false).await.unwrap(), 2);
+
+        tokens
+            .delete(&RefreshToken {
+                id: "1".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let mut list = List::default();
+        tokens.list(&(), &mut list).await.unwrap();
+        assert_eq!((list.total, list.data[0].id.as_str()), (1, "2"));
+        assert_eq!(tokens.count(&(), false).await.unwrap(), 1);
+        assert_eq!(tokens.count(&(), true).await.unwrap(), 2);
+        assert_eq!(clients.count(&(), false).await.unwrap(), 2);
+        assert_eq!(keys.count(&(), false).await.unwrap(), 1);
+
+        let mut client = Client {
+            id: "1".to_owned(),
+            ..Default::default()
+        };
+        clients.delete(&client).await.unwrap();
+        let mut list = List::default();
+        clients.list_deleted(&(), &mut list).await.unwrap();
+        assert_eq!(list.data.len(), 1);
+        assert_eq!(list.data[0].id, "1");
+        clients.restore(&client).await.unwrap();
+        clients.get(&mut client).await.unwrap();
+        assert_eq!(client.resource_version, 3);
+
+        let key = Keys {
+            id: "1".to_owned(),
+            ..Default::default()
+        };
+        keys.delete(&key).await.unwrap();
+        let mut list = List::default();
+        keys.list_deleted(&(), &mut list).await.unwrap();
+        assert_eq!(list.data.len(), 1);
+        keys.restore(&key).await.unwrap();
+        assert!(keys.restore(&key).await.is_err());
+        assert_eq!(keys.count(&(), false).await.unwrap(), 1);
+
+        let before = Utc::now().naive_utc() + Duration::hours(1);
+        assert_eq!(tokens.purge(before).await.unwrap(), 1);
+        assert_eq!(tokens.count(&(), true).await.unwrap(), 1);
+        assert_eq!(clients.purge(before).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn sealed_keys() {
+        let pool = pool().await;
+        let (a, b) = (base64_key(1), base64_key(2));
+        let sealed = |spec: &str| {
+            KeysImpl::new(pool.clone()).sealed(KeyRing::parse(spec).unwrap())
+        };
+        let signing_key = |pool: &sqlx::SqlitePool| {
+            let pool = pool.clone();
+            async move {
+                sqlx::query_scalar::<_, String>(
+                    "SELECT `signing_key` FROM `key` WHERE `id` = 1",
+                )
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+            }
+        };
+        let input = Keys {
+            id: "1".to_owned(),
+            ..Default::default()
+        };
+        KeysImpl::new(pool.clone()).put(&input).await.unwrap();
+        assert!(signing_key(&pool).await.contains("\"kty\""));
+
+        // plain rows are read and sealed again by the first key
+        let store = sealed(&format!("a:{a}"));
+        let mut output = Keys {
+            id: "1".to_owned(),
+            ..Default::default()
+        };
+        store.get(&mut output).await.unwrap();
+        assert_eq!(output.signing_key, input.signing_key);
+        assert_eq!(output.resource_version, 1);
+        store.put_if(&output, 1).await.unwrap();
+        assert!(store.put_if(&output, 1).await.is_err());
+        let raw = signing_key(&pool).await;
+        assert!(raw.contains("\"kek\":\"a\"") && !raw.contains("\"kty\""));
+        assert!(KeysImpl::new(pool.clone()).get(&mut output).await.is_err());
+
+        // b rotates a out once the rows are sealed again
+        let store = sealed(&format!("b:{b},a:{a}"));
+        let mut list = List::default();
+        store.list(&(), &mut list).await.unwrap();
+        assert_eq!(list.data[0].signing_key, input.signing_key);
+        store.put(&list.data[0]).await.unwrap();
+        assert!(signing_key(&pool).await.contains("\"kek\":\"b\""));
+        sealed(&format!("b:{b}")).get(&mut output).await.unwrap();
+        assert_eq!(output.signing_key, input.signing_key);
+        assert_eq!(output.resource_version, 3);
+        // the keys round trip through json, the public view leaves the
+        // private key out
+        let json = serde_json::to_value(&output).unwrap();
+        assert_eq!(serde_json::from_value::<Keys>(json).unwrap(), output);
+        let json = serde_json::to_value(PublicKeys::from(output.clone()));
+        assert!(json.unwrap().get("signing_key").is_none());
+        assert!(sealed(&format!("a:{a}")).get(&mut output).await.is_err());
+
+        assert!(KeyRing::parse("a").is_err());
+        assert!(KeyRing::parse("a:c2hvcnQ=").is_err());
+        assert!(KeyRing::parse(&format!("a:{a},a:{b}")).is_err());
+    }
+
+    fn base64_key(seed: u8) -> String {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+        STANDARD.encode([seed; 32])
+    }
+}
@@ -0,0 +1,263 @@
+use std::{
+    fmt,
+    future::Future,
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use sqlx::{pool::PoolConnection, Database, MySql, Transaction};
+use tokio::sync::{MappedMutexGuard, MutexGuard};
+use tracing::warn;
+
+use cim_slo::{errors, Result};
+
+use crate::{changelog::ChangeStore, memory};
+
+/// Conn is where the sql stores run their queries, either the connection
+/// pool or a transaction shared by every store built from the same `Tx`
+pub enum Conn<DB: Database> {
+    Pool(sqlx::Pool<DB>),
+    Tx(Arc<tokio::sync::Mutex<Option<Transaction<'static, DB>>>>),
+}
+
+impl<DB: Database> Clone for Conn<DB> {
+    fn clone(&self) -> Self {
+        match self {
+            Conn::Pool(pool) => Conn::Pool(pool.clone()),
+            Conn::Tx(tx) => Conn::Tx(Arc::clone(tx)),
+        }
+    }
+}
+
+impl<DB: Database> fmt::Debug for Conn<DB> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Conn::Pool(pool) => f.debug_tuple("Pool").field(pool).finish(),
+            Conn::Tx(_) => f.debug_tuple("Tx").finish(),
+        }
+    }
+}
+
+impl<DB: Database> From<sqlx::Pool<DB>> for Conn<DB> {
+    fn from(pool: sqlx::Pool<DB>) -> Self {
+        Conn::Pool(pool)
+    }
+}
+
+impl<DB: Database> Conn<DB> {
+    async fn begin(pool: &sqlx::Pool<DB>) -> Result<Self> {
+        let tx = pool.begin().await.map_err(errors::any)?;
+        Ok(Conn::Tx(Arc::new(tokio::sync::Mutex::new(Some(tx)))))
+    }
+
+    /// acquire hands out a connection for a single query, queries of a
+    /// transaction are serialized on its connection
+    pub async fn acquire(&self) -> Result<ConnGuard<'_, DB>> {
+        match self {
+            Conn::Pool(pool) => {
+                Ok(ConnGuard::Pool(pool.acquire().await.map_err(errors::any)?))
+            }
+            Conn::Tx(tx) => {
+                MutexGuard::try_map(tx.lock().await, |v| v.as_mut())
+                    .map(ConnGuard::Tx)
+                    .map_err(|_| errors::any(TxFinished))
+            }
+        }
+    }
+
+    /// scoped runs f on a transaction, the one of self or else a new one
+    /// of the pool, committed when f succeeds
+    pub(crate) async fn scoped<R, F, Fut>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(Self) -> Fut,
+        Fut: Future<Output = Result<R>>,
+    {
+        let Conn::Pool(pool) = self else {
+            return f(self.clone()).await;
+        };
+        let conn = Self::begin(pool).await?;
+        match f(conn.clone()).await {
+            Ok(value) => conn.finish(true).await.map(|_| value),
+            Err(err) => {
+                // the error of f is what the caller has to see, a failed
+                // rollback only leaves the connection to be discarded
+                if let Err(rollback) = conn.finish(false).await {
+                    warn!("rollback failed: {}", rollback);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    async fn finish(&self, commit: bool) -> Result<()> {
+        let tx = match self {
+            Conn::Pool(_) => return Ok(()),
+            Conn::Tx(tx) => tx.lock().await.take(),
+        };
+        let tx = tx.ok_or_else(|| errors::any(TxFinished))?;
+        if commit {
+            tx.commit().await.map_err(errors::any)
+        } else {
+            tx.rollback().await.map_err(errors::any)
+        }
+    }
+}
+
+pub enum ConnGuard<'a, DB: Database> {
+    Pool(PoolConnection<DB>),
+    Tx(MappedMutexGuard<'a, Transaction<'static, DB>>),
+}
+
+impl<DB: Database> fmt::Debug for ConnGuard<'_, DB> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnGuard").finish()
+    }
+}
+
+impl<DB: Database> Deref for ConnGuard<'_, DB> {
+    type Target = DB::Connection;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            ConnGuard::Pool(conn) => conn,
+            ConnGuard::Tx(tx) => tx,
+        }
+    }
+}
+
+impl<DB: Database> DerefMut for ConnGuard<'_, DB> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            ConnGuard::Pool(conn) => conn,
+            ConnGuard::Tx(tx) => tx,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TxFinished;
+
+impl fmt::Display for TxFinished {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("transaction is already committed or rolled back")
+    }
+}
+
+impl std::error::Error for TxFinished {}
+
+/// TxConn is the backend connection of a transaction, stores built from it
+/// take part in the transaction
+#[derive(Clone, Debug)]
+pub enum TxConn {
+    MySql(Conn<MySql>),
+    #[cfg(feature = "sqlite")]
+    Sqlite(Conn<sqlx::Sqlite>),
+    #[cfg(feature = "postgres")]
+    Postgres(Conn<sqlx::Postgres>),
+    Memory(memory::Database),
+}
+
+impl TxConn {
+    /// changelog is the changelog running in the transaction
+    pub fn changelog(&self) -> Arc<dyn ChangeStore> {
+        match self {
+            TxConn::MySql(conn) => {
+                Arc::new(crate::mariadb::ChangelogImpl::new(conn.clone()))
+            }
+            #[cfg(feature = "sqlite")]
+            TxConn::Sqlite(conn) => {
+                Arc::new(crate::sqlite::ChangelogImpl::new(conn.clone()))
+            }
+            #[cfg(feature = "postgres")]
+            TxConn::Postgres(conn) => {
+                Arc::new(crate::postgres::ChangelogImpl::new(conn.clone()))
+            }
+            TxConn::Memory(db) => {
+                Arc::new(memory::ChangelogImpl::new(db.clone()))
+            }
+        }
+    }
+}
+
+pub(crate) type Hook = Box<dyn FnOnce() + Send>;
+
+/// Tx is a unit of work across stores, the writes of every store built
+/// from `Tx::conn` commit or roll back together. Dropping it without a
+/// commit rolls back.
+pub struct Tx {
+    conn: TxConn,
+    hooks: Arc<Mutex<Vec<Hook>>>,
+}
+
+impl fmt::Debug for Tx {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tx").field("conn", &self.conn).finish()
+    }
+}
+
+impl Tx {
+    pub(crate) async fn begin(pool: &crate::Pool) -> Result<Self> {
+        let conn = match pool {
+            crate::Pool::MySql(pool) => TxConn::MySql(Conn::begin(pool).await?),
+            #[cfg(feature = "sqlite")]
+            crate::Pool::Sqlite(pool) => {
+                TxConn::Sqlite(Conn::begin(pool).await?)
+            }
+            #[cfg(feature = "postgres")]
+            crate::Pool::Postgres(pool) => {
+                TxConn::Postgres(Conn::begin(pool).await?)
+            }
+            crate::Pool::Memory(db) => TxConn::Memory(db.begin()),
+        };
+        Ok(Self {
+            conn,
+            hooks: Arc::default(),
+        })
+    }
+
+    pub fn conn(&self) -> &TxConn {
+        &self.conn
+    }
+
+    /// after_commit defers f until the transaction is committed, it's
+    /// dropped on rollback
+    pub fn after_commit<F: FnOnce() + Send + 'static>(&self, f: F) {
+        self.hooks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Box::new(f));
+    }
+
+    pub(crate) fn hooks(&self) -> Arc<Mutex<Vec<Hook>>> {
+        Arc::clone(&self.hooks)
+    }
+
+    pub async fn commit(self) -> Result<()> {
+        match &self.conn {
+            TxConn::MySql(conn) => conn.finish(true).await?,
+            #[cfg(feature = "sqlite")]
+            TxConn::Sqlite(conn) => conn.finish(true).await?,
+            #[cfg(feature = "postgres")]
+            TxConn::Postgres(conn) => conn.finish(true).await?,
+            TxConn::Memory(db) => db.commit()?,
+        }
+        let hooks = std::mem::take(
+            &mut *self.hooks.lock().unwrap_or_else(PoisonError::into_inner),
+        );
+        for hook in hooks {
+            hook();
+        }
+        Ok(())
+    }
+
+    pub async fn rollback(self) -> Result<()> {
+        match &self.conn {
+            TxConn::MySql(conn) => conn.finish(false).await,
+            #[cfg(feature = "sqlite")]
+            TxConn::Sqlite(conn) => conn.finish(false).await,
+            #[cfg(feature = "postgres")]
+            TxConn::Postgres(conn) => conn.finish(false).await,
+            TxConn::Memory(_) => Ok(()),
+        }
+    }
+}
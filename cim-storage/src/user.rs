@@ -1,12 +1,17 @@
-use chrono::NaiveDateTime;
+use chrono::{NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 
-use cim_slo::regexp::check_password;
+use cim_slo::{regexp::check_password, Result};
 
-use crate::{ClaimOpts, Pagination};
+use crate::{
+    label::{check_annotations, check_labels, Labels, Selector},
+    ClaimOpts, Filter, Keyed, Pagination, Param,
+};
 
-#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Clone, utoipa::ToSchema)]
+#[derive(
+    Debug, Default, Deserialize, Serialize, PartialEq, Clone, utoipa::ToSchema,
+)]
 pub struct User {
     pub id: String,
     pub account_id: String,
@@ -17,6 +22,14 @@ pub struct User {
     pub secret: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub password: Option<String>,
+    #[serde(default)]
+    pub labels: Labels,
+    #[serde(default)]
+    pub annotations: Labels,
+    /// resource_version is bumped on every write, conditional writes
+    /// compare it with the stored one
+    #[serde(default)]
+    pub resource_version: u64,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
@@ -31,6 +44,12 @@ pub struct Content {
     pub claim: ClaimOpts,
     #[validate(custom(function = "check_password"))]
     pub password: String,
+    #[serde(default)]
+    #[validate(custom(function = "check_labels"))]
+    pub labels: Labels,
+    #[serde(default)]
+    #[validate(custom(function = "check_annotations"))]
+    pub annotations: Labels,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -41,7 +60,65 @@ pub struct ListParams {
     pub account_id: Option<String>,
     #[validate(length(min = 1))]
     pub group_id: Option<String>,
+    /// effective_group_id selects the members of a group and of the groups
+    /// nested in it at any depth, whose membership is in effect
+    #[validate(length(min = 1))]
+    pub effective_group_id: Option<String>,
+    /// label_selector selects by labels, e.g. `env=prod,tier in (web,api)`
+    pub label_selector: Option<Selector>,
     #[serde(flatten)]
     #[validate(nested)]
     pub pagination: Pagination,
 }
+
+impl ListParams {
+    /// SORTABLE are the columns order_by may name
+    pub const SORTABLE: &'static [&'static str] =
+        &["id", "account_id", "created_at", "updated_at"];
+
+    /// filter is the WHERE clause of the sql stores
+    pub(crate) fn filter(&self) -> Result<Filter> {
+        let mut filter = Filter::default()
+            .id("id", self.id.as_deref())?
+            .id("account_id", self.account_id.as_deref())?;
+        if let Some(group_id) = &self.group_id {
+            filter = filter.in_select(
+                "id",
+                "group_user",
+                "user_id",
+                Filter::default()
+                    .id("group_id", Some(group_id))?
+                    .not_deleted(),
+            );
+        }
+        if let Some(group_id) = &self.effective_group_id {
+            let groups =
+                Filter::default().id("id", Some(group_id))?.not_deleted();
+            filter = filter.in_select(
+                "id",
+                "group_user",
+                "user_id",
+                Filter::default()
+                    .in_tree("group_id", "group", "parent_id", groups, false)
+                    .not_deleted()
+                    .active(Utc::now().naive_utc()),
+            );
+        }
+        if let Some(selector) = &self.label_selector {
+            filter = selector.filter(filter, "user");
+        }
+        Ok(filter)
+    }
+}
+
+impl Keyed for User {
+    fn key(&self, column: &str) -> Option<Param> {
+        match column {
+            "id" => Some(Param::id(&self.id)),
+            "account_id" => Some(Param::id(&self.account_id)),
+            "created_at" => Some(self.created_at.into()),
+            "updated_at" => Some(self.updated_at.into()),
+            _ => None,
+        }
+    }
+}
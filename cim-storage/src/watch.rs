@@ -1,15 +1,34 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
 use async_trait::async_trait;
-use chrono::Utc;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use tracing::error;
 
-use cim_slo::Result;
-use cim_watch::{WatchGuard, Watcher, WatcherHub};
+use cim_slo::{errors, Result};
+use cim_watch::{Overflow, Subscription, WatchGuard, Watcher, WatcherHub};
 
-use crate::{Event, Interface, List, WatchInterface};
+use crate::{
+    changelog::{Change, ChangeStore},
+    stored,
+    transaction::Hook,
+    Action, Event, Interface, List, Tx, WatchInterface,
+};
 
 #[derive(Clone)]
 pub struct WatchStore<I: Interface> {
     store: I,
     watch_hub: WatcherHub<Event<I::T>>,
+    // events held back until the transaction commits
+    pending: Option<Arc<Mutex<Vec<Hook>>>>,
+    // kind and changelog the events are appended to, a Tailer brings them
+    // to watch_hub instead of notify
+    changelog: Option<(&'static str, Arc<dyn ChangeStore>)>,
+    redact: Option<fn(&mut I::T)>,
 }
 
 impl<I: Interface> WatchStore<I> {
@@ -17,7 +36,84 @@ impl<I: Interface> WatchStore<I> {
         Self {
             store,
             watch_hub: WatcherHub::default(),
+            pending: None,
+            changelog: None,
+            redact: None,
+        }
+    }
+
+    /// changelog appends the events to changelog as kind, so that they
+    /// reach the watchers of every replica feeding a Tailer with self
+    pub fn changelog(
+        mut self,
+        kind: &'static str,
+        changelog: Arc<dyn ChangeStore>,
+    ) -> Self {
+        self.changelog = Some((kind, changelog));
+        self
+    }
+
+    /// redact clears the fields of an entity that must not leave the store
+    /// in its events
+    pub fn redact(mut self, redact: fn(&mut I::T)) -> Self {
+        self.redact = Some(redact);
+        self
+    }
+
+    /// transaction wraps store, running in tx, with the watchers of self,
+    /// they are notified of its events only once tx is committed
+    pub fn transaction(&self, store: I, tx: &Tx) -> Self {
+        Self {
+            store,
+            watch_hub: self.watch_hub.clone(),
+            pending: Some(tx.hooks()),
+            changelog: self
+                .changelog
+                .as_ref()
+                .map(|(kind, _)| (*kind, tx.conn().changelog())),
+            redact: self.redact,
+        }
+    }
+
+    pub fn into_inner(self) -> I {
+        self.store
+    }
+
+    /// written is input as the write left it stored when there is no
+    /// changelog, the revision is then its resource_version
+    async fn written(&self, input: &I::T) -> Result<I::T> {
+        if self.changelog.is_some() {
+            return Ok(input.clone());
         }
+        Ok(stored(&self.store, input)
+            .await?
+            .unwrap_or_else(|| input.clone()))
+    }
+
+    /// notify publishes event, through the changelog if there is one, the
+    /// revision is stamped when it reaches the watchers
+    async fn notify(&self, mut event: Event<I::T>) -> Result<()> {
+        if let Some(redact) = self.redact {
+            redact(event.get_mut());
+            if let Some(previous) = &mut event.previous {
+                redact(previous);
+            }
+        }
+        if let Some((kind, changelog)) = &self.changelog {
+            let payload = serde_json::to_string(&event).map_err(errors::any)?;
+            return changelog.append(kind, &payload).await;
+        }
+        match &self.pending {
+            Some(pending) => {
+                let watch_hub = self.watch_hub.clone();
+                pending
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push(Box::new(move || publish(&watch_hub, event)));
+            }
+            None => publish(&self.watch_hub, event),
+        }
+        Ok(())
     }
 }
 
@@ -25,17 +121,28 @@ impl<I: Interface> WatchStore<I> {
 impl<I: Interface> WatchInterface for WatchStore<I> {
     fn watch<W: Watcher<Event<Self::T>>>(
         &self,
-        since_modify: usize,
+        since: u64,
         handler: W,
-    ) -> Box<dyn WatchGuard + Send> {
-        self.watch_hub.watch(since_modify, handler)
+    ) -> Result<Box<dyn WatchGuard + Send>> {
+        self.watch_hub
+            .watch(since as usize, handler)
+            .map_err(|err| errors::gone(&err))
+    }
+
+    fn subscribe(
+        &self,
+        since: u64,
+        capacity: usize,
+        overflow: Overflow<Event<Self::T>>,
+    ) -> Result<Subscription<Event<Self::T>>> {
+        self.watch_hub
+            .subscribe(since as usize, capacity, overflow)
+            .map_err(|err| errors::gone(&err))
     }
 
     async fn create(&self, input: &Self::T) -> Result<()> {
         self.store.put(input).await?;
-        self.watch_hub
-            .notify(Utc::now().timestamp() as usize, Event::Add(input.clone()));
-        Ok(())
+        self.notify(Event::add(self.written(input).await?)).await
     }
 }
 
@@ -44,19 +151,11 @@ impl<I: Interface> Interface for WatchStore<I> {
     type T = I::T;
     type L = I::L;
     async fn put(&self, input: &I::T) -> Result<()> {
-        self.store.put(input).await?;
-        self.watch_hub
-            .notify(Utc::now().timestamp() as usize, Event::Put(input.clone()));
-        Ok(())
+        self.replace(input, None).await.map(drop)
     }
 
     async fn delete(&self, input: &I::T) -> Result<()> {
-        self.store.delete(input).await?;
-        self.watch_hub.notify(
-            Utc::now().timestamp() as usize,
-            Event::Delete(input.clone()),
-        );
-        Ok(())
+        self.discard(input, None).await.map(drop)
     }
 
     async fn get(&self, output: &mut I::T) -> Result<()> {
@@ -74,4 +173,539 @@ impl<I: Interface> Interface for WatchStore<I> {
     async fn count(&self, opts: &Self::L, unscoped: bool) -> Result<i64> {
         self.store.count(opts, unscoped).await
     }
+
+    async fn put_if(&self, input: &I::T, resource_version: u64) -> Result<()> {
+        self.replace(input, Some(resource_version)).await.map(drop)
+    }
+
+    /// replace publishes the put with the entity it replaced, as read by
+    /// the write
+    async fn replace(
+        &self,
+        input: &I::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<I::T>> {
+        let previous = self.store.replace(input, resource_version).await?;
+        let value = self.written(input).await?;
+        self.notify(Event::put(previous.clone(), value)).await?;
+        Ok(previous)
+    }
+
+    async fn delete_if(
+        &self,
+        input: &I::T,
+        resource_version: u64,
+    ) -> Result<()> {
+        self.discard(input, Some(resource_version)).await.map(drop)
+    }
+
+    /// discard publishes the delete of the entity read by the write, none
+    /// when there was no live one
+    async fn discard(
+        &self,
+        input: &I::T,
+        resource_version: Option<u64>,
+    ) -> Result<Option<I::T>> {
+        let previous = self.store.discard(input, resource_version).await?;
+        if let Some(previous) = &previous {
+            self.notify(Event::delete(previous.clone())).await?;
+        }
+        Ok(previous)
+    }
+
+    async fn list_deleted(
+        &self,
+        opts: &Self::L,
+        output: &mut List<Self::T>,
+    ) -> Result<()> {
+        self.store.list_deleted(opts, output).await
+    }
+
+    /// restore publishes the put of the restored entity as stored, input
+    /// may only carry its id
+    async fn restore(&self, input: &I::T) -> Result<()> {
+        self.store.restore(input).await?;
+        let mut output = input.clone();
+        self.store.get(&mut output).await?;
+        self.notify(Event::put(None, output)).await
+    }
+
+    async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+        self.store.purge(before).await
+    }
+}
+
+/// publish stamps event with the resource_version its entity is stored at,
+/// a deleted one is past the version it's published with. Without a
+/// changelog the revisions only order the events of an entity
+fn publish<T: Clone + Serialize + Send + Sync + 'static>(
+    watch_hub: &WatcherHub<Event<T>>,
+    mut event: Event<T>,
+) {
+    let version = serde_json::to_value(event.get())
+        .ok()
+        .and_then(|v| v.get("resource_version")?.as_u64())
+        .unwrap_or_default();
+    event.revision = match event.action {
+        Action::Delete(_) => version + 1,
+        _ => version,
+    };
+    watch_hub.notify(event.revision as usize, event);
+}
+
+type Feed = Box<dyn Fn(u64, &str) -> Result<()> + Send + Sync>;
+
+// changes read from the changelog by a single poll
+const BATCH: i64 = 1000;
+
+/// Tailer polls the changelog and notifies the watchers of the stores it
+/// feeds, of their own writes as well as those of other replicas. The id
+/// of a change is the revision of its event.
+///
+/// A transaction may commit after another one got a bigger id, so an id
+/// missing below the newest change is looked for again on every poll,
+/// until it shows up or has been missing for longer than grace. The
+/// changes above it are held back meanwhile, watchers see them in order.
+pub struct Tailer {
+    changelog: Arc<dyn ChangeStore>,
+    feeds: HashMap<&'static str, Feed>,
+    grace: Duration,
+    // every id up to the cursor is delivered or given up, None until the
+    // first poll starts it at the newest change
+    cursor: Option<u64>,
+    // ids above the cursor already delivered
+    seen: BTreeSet<u64>,
+    // changes above the oldest gap, delivered once it closes or expires
+    held: BTreeMap<u64, Change>,
+    // ids above the cursor missing since the instant
+    gaps: BTreeMap<u64, Instant>,
+}
+
+impl Tailer {
+    pub fn new(changelog: Arc<dyn ChangeStore>, grace: Duration) -> Self {
+        Self {
+            changelog,
+            feeds: HashMap::new(),
+            grace,
+            cursor: None,
+            seen: BTreeSet::new(),
+            held: BTreeMap::new(),
+            gaps: BTreeMap::new(),
+        }
+    }
+
+    /// feed delivers the changes of store to its watchers, the store must
+    /// append to the changelog of self
+    pub fn feed<I: Interface>(&mut self, store: &WatchStore<I>) {
+        let Some((kind, _)) = store.changelog else {
+            return;
+        };
+        let watch_hub = store.watch_hub.clone();
+        self.feeds.insert(
+            kind,
+            Box::new(move |revision, payload| {
+                let mut event: Event<I::T> =
+                    serde_json::from_str(payload).map_err(errors::any)?;
+                event.revision = revision;
+                watch_hub.notify(revision as usize, event);
+                Ok(())
+            }),
+        );
+    }
+
+    /// poll delivers the changes appended since the last poll up to the
+    /// oldest gap still waited for, returning how many there were
+    pub async fn poll(&mut self) -> Result<usize> {
+        let Some(cursor) = self.cursor else {
+            self.cursor = Some(self.changelog.last().await?);
+            return Ok(0);
+        };
+        let now = Instant::now();
+        let mut next = cursor + 1;
+        for change in self.changelog.since(cursor, BATCH).await? {
+            // a jump longer than a batch isn't waited for
+            if change.id - next <= BATCH as u64 {
+                for id in next..change.id {
+                    if !self.seen.contains(&id) {
+                        self.gaps.entry(id).or_insert(now);
+                    }
+                }
+            }
+            next = change.id + 1;
+            self.gaps.remove(&change.id);
+            if !self.seen.contains(&change.id) {
+                self.held.entry(change.id).or_insert(change);
+            }
+        }
+
+        // the changes below the oldest gap still worth waiting for are
+        // delivered, the cursor stays below it
+        self.gaps
+            .retain(|_, found| now.duration_since(*found) < self.grace);
+        let gap = self.gaps.keys().next().copied();
+        let held = match gap {
+            Some(gap) => self.held.split_off(&gap),
+            None => BTreeMap::new(),
+        };
+        let mut delivered = 0;
+        for (id, change) in std::mem::replace(&mut self.held, held) {
+            self.seen.insert(id);
+            delivered += 1;
+            if let Some(feed) = self.feeds.get(change.kind.as_str()) {
+                if let Err(err) = feed(id, &change.payload) {
+                    error!("change {} of {}: {}", id, change.kind, err);
+                }
+            }
+        }
+        let cursor = match gap {
+            Some(gap) => gap - 1,
+            None => self.seen.last().copied().unwrap_or(cursor),
+        }
+        .max(cursor);
+        self.seen = self.seen.split_off(&(cursor + 1));
+        self.cursor = Some(cursor);
+        Ok(delivered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use chrono::NaiveDateTime;
+    use futures_util::StreamExt;
+
+    use super::*;
+    use crate::{connection_manager, group::Group, memory, Action, TxConn};
+
+    #[tokio::test]
+    async fn transaction_events_after_commit() {
+        let pool = connection_manager("memory://", 1, 1, false).await.unwrap();
+        let db = match &pool {
+            crate::Pool::Memory(db) => db.clone(),
+            _ => unreachable!(),
+        };
+        let store = WatchStore::new(memory::GroupImpl::new(db));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::clone(&events);
+        let _guard = store
+            .watch(0, move |event: Event<Group>| {
+                received.lock().unwrap().push(event.get().id.clone());
+            })
+            .unwrap();
+
+        let group = Group {
+            id: "1".to_owned(),
+            ..Default::default()
+        };
+        let tx = pool.begin().await.unwrap();
+        let TxConn::Memory(conn) = tx.conn() else {
+            unreachable!()
+        };
+        let tx_store =
+            store.transaction(memory::GroupImpl::new(conn.clone()), &tx);
+        tx_store.create(&group).await.unwrap();
+        assert!(events.lock().unwrap().is_empty());
+        assert!(store.get(&mut group.clone()).await.is_err());
+        tx.commit().await.unwrap();
+        assert_eq!(*events.lock().unwrap(), vec!["1"]);
+        assert!(store.get(&mut group.clone()).await.is_ok());
+
+        let tx = pool.begin().await.unwrap();
+        let TxConn::Memory(conn) = tx.conn() else {
+            unreachable!()
+        };
+        let tx_store =
+            store.transaction(memory::GroupImpl::new(conn.clone()), &tx);
+        tx_store.delete(&group).await.unwrap();
+        tx.rollback().await.unwrap();
+        assert_eq!(events.lock().unwrap().len(), 1);
+        assert!(store.get(&mut group.clone()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn changelog_across_replicas() {
+        let pool = connection_manager("memory://", 1, 1, false).await.unwrap();
+        let db = match &pool {
+            crate::Pool::Memory(db) => db.clone(),
+            _ => unreachable!(),
+        };
+        let replica = |db: &memory::Database| {
+            WatchStore::new(memory::GroupImpl::new(db.clone())).changelog(
+                "group",
+                Arc::new(memory::ChangelogImpl::new(db.clone())),
+            )
+        };
+        let (writer, reader) = (replica(&db), replica(&db));
+        let mut tailer = Tailer::new(
+            Arc::new(memory::ChangelogImpl::new(db.clone())),
+            Duration::from_secs(10),
+        );
+        tailer.feed(&reader);
+        assert_eq!(tailer.poll().await.unwrap(), 0);
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::clone(&events);
+        let _guard = reader
+            .watch(0, move |event: Event<Group>| {
+                received.lock().unwrap().push(event.revision);
+            })
+            .unwrap();
+        let group = Group {
+            id: "1".to_owned(),
+            ..Default::default()
+        };
+        writer.create(&group).await.unwrap();
+
+        // events written in a transaction show up once it commits
+        let tx = pool.begin().await.unwrap();
+        let TxConn::Memory(conn) = tx.conn() else {
+            unreachable!()
+        };
+        writer
+            .transaction(memory::GroupImpl::new(conn.clone()), &tx)
+            .delete(&group)
+            .await
+            .unwrap();
+        assert_eq!(tailer.poll().await.unwrap(), 1);
+        assert_eq!(*events.lock().unwrap(), vec![1]);
+        tx.commit().await.unwrap();
+        assert_eq!(tailer.poll().await.unwrap(), 1);
+        // the revision of an event is the id of its change
+        assert_eq!(*events.lock().unwrap(), vec![1, 2]);
+        assert_eq!(tailer.poll().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn revisions() {
+        let store = WatchStore::new(memory::GroupImpl::new(Default::default()));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::clone(&events);
+        let _guard = store
+            .watch(0, move |event: Event<Group>| {
+                received.lock().unwrap().push(event);
+            })
+            .unwrap();
+        let group = Group {
+            id: "1".to_owned(),
+            name: "a".to_owned(),
+            ..Default::default()
+        };
+        store.create(&group).await.unwrap();
+        store.put(&group).await.unwrap();
+        // the deleted entity is the stored one, not the input
+        let keyed = Group {
+            id: "1".to_owned(),
+            ..Default::default()
+        };
+        store.delete(&keyed).await.unwrap();
+        store.restore(&keyed).await.unwrap();
+        store.delete(&Group::default()).await.unwrap();
+        let events = events.lock().unwrap();
+        // the revisions are the resource versions of the entity
+        assert_eq!(
+            events.iter().map(|v| v.revision).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4]
+        );
+        assert!(
+            matches!(&events[2].action, Action::Delete(v) if v.name == "a")
+        );
+        assert!(matches!(&events[3].action, Action::Put(v) if v.name == "a"));
+
+        let json = serde_json::to_value(&events[1]).unwrap();
+        assert_eq!(json["revision"], events[1].revision);
+        assert_eq!(json["Put"]["id"], "1");
+        let event: Event<Group> = serde_json::from_value(json).unwrap();
+        assert!(matches!(event.action, Action::Put(_)));
+    }
+
+    #[tokio::test]
+    async fn put_carries_previous() {
+        let store = WatchStore::new(memory::GroupImpl::new(Default::default()))
+            .redact(|group| group.desc.clear());
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::clone(&events);
+        let _guard = store
+            .watch(0, move |event: Event<Group>| {
+                received.lock().unwrap().push(event);
+            })
+            .unwrap();
+        let mut group = Group {
+            id: "1".to_owned(),
+            name: "a".to_owned(),
+            desc: "secret".to_owned(),
+            ..Default::default()
+        };
+        store.put(&group).await.unwrap();
+        store.get(&mut group).await.unwrap();
+        group.name = "b".to_owned();
+        store.put_if(&group, group.resource_version).await.unwrap();
+
+        let events = events.lock().unwrap();
+        assert!(events[0].previous.is_none());
+        assert!(events[0].diff().is_none());
+        let previous = events[1].previous.as_ref().unwrap();
+        assert_eq!(previous.name, "a");
+        // the previous state is redacted like the new one
+        assert!(previous.desc.is_empty());
+        // the put is of the stored entity, with the fields the store bumped
+        let diff = serde_json::to_value(events[1].diff().unwrap()).unwrap();
+        assert_eq!(
+            diff.as_array()
+                .unwrap()
+                .iter()
+                .map(|v| v["path"].as_str().unwrap())
+                .collect::<Vec<_>>(),
+            vec!["/name", "/resource_version", "/updated_at"]
+        );
+        assert_eq!(diff[0]["value"], "b");
+    }
+
+    #[tokio::test]
+    async fn subscription_coalesces() {
+        let store = WatchStore::new(memory::GroupImpl::new(Default::default()));
+        let overflow = Overflow::Coalesce {
+            supersedes: Event::supersedes,
+            fold: Event::fold,
+        };
+        let mut subscription = store.subscribe(0, 2, overflow).unwrap();
+        let group = |id: &str, name: &str| Group {
+            id: id.to_owned(),
+            name: name.to_owned(),
+            ..Default::default()
+        };
+        store.create(&group("1", "a")).await.unwrap();
+        for (id, name) in [("2", "b"), ("1", "c")] {
+            store.put(&group(id, name)).await.unwrap();
+        }
+        // the last event of group 1 replaces the first one, it adds it
+        let event = subscription.next().await.unwrap();
+        assert_eq!(event.get().name, "b");
+        let event = subscription.next().await.unwrap();
+        assert_eq!(event.get().name, "c");
+        assert!(matches!(event.action, Action::Add(_)));
+
+        // a put folded into another one replaces what the first replaced
+        for name in ["d", "e", "f"] {
+            store.put(&group("1", name)).await.unwrap();
+        }
+        let event = subscription.next().await.unwrap();
+        assert_eq!(event.get().name, "e");
+        assert_eq!(event.previous.unwrap().name, "c");
+        let event = subscription.next().await.unwrap();
+        assert_eq!(event.get().name, "f");
+        assert_eq!(event.previous.unwrap().name, "e");
+    }
+
+    #[derive(Default)]
+    struct Changes(Mutex<Vec<Change>>);
+
+    impl Changes {
+        fn commit(&self, id: u64) {
+            let mut changes = self.0.lock().unwrap();
+            changes.push(Change {
+                id,
+                kind: "group".to_owned(),
+                payload: serde_json::to_string(&Event::add(Group {
+                    id: id.to_string(),
+                    ..Default::default()
+                }))
+                .unwrap(),
+                ..Default::default()
+            });
+            changes.sort_by_key(|v| v.id);
+        }
+    }
+
+    #[async_trait]
+    impl ChangeStore for Changes {
+        async fn append(&self, kind: &str, payload: &str) -> Result<()> {
+            let mut changes = self.0.lock().unwrap();
+            let id = changes.last().map(|v| v.id).unwrap_or(0) + 1;
+            changes.push(Change {
+                id,
+                kind: kind.to_owned(),
+                payload: payload.to_owned(),
+                created_at: chrono::Utc::now().naive_utc(),
+            });
+            Ok(())
+        }
+
+        async fn since(&self, id: u64, limit: i64) -> Result<Vec<Change>> {
+            let changes = self.0.lock().unwrap();
+            Ok(changes
+                .iter()
+                .filter(|v| v.id > id)
+                .take(limit as usize)
+                .cloned()
+                .collect())
+        }
+
+        async fn last(&self) -> Result<u64> {
+            Ok(self.0.lock().unwrap().last().map(|v| v.id).unwrap_or(0))
+        }
+
+        async fn purge(&self, before: NaiveDateTime) -> Result<u64> {
+            let mut changes = self.0.lock().unwrap();
+            let count = changes.len();
+            changes.retain(|v| v.created_at >= before);
+            Ok((count - changes.len()) as u64)
+        }
+    }
+
+    #[tokio::test]
+    async fn tailer_gaps() {
+        let changes = Arc::new(Changes::default());
+        changes.commit(1);
+        let store = WatchStore::new(memory::GroupImpl::new(Default::default()))
+            .changelog("group", changes.clone());
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::clone(&events);
+        let _guard = store
+            .watch(0, move |event: Event<Group>| {
+                received.lock().unwrap().push(event.get().id.clone());
+            })
+            .unwrap();
+
+        let mut tailer = Tailer::new(changes.clone(), Duration::from_secs(60));
+        tailer.feed(&store);
+        tailer.poll().await.unwrap();
+        // 2 and 3 commit after 4
+        changes.commit(4);
+        assert_eq!(tailer.poll().await.unwrap(), 0);
+        changes.commit(3);
+        assert_eq!(tailer.poll().await.unwrap(), 0);
+        assert_eq!(tailer.cursor, Some(1));
+        changes.commit(2);
+        assert_eq!(tailer.poll().await.unwrap(), 3);
+        assert_eq!(tailer.poll().await.unwrap(), 0);
+        assert_eq!(*events.lock().unwrap(), vec!["2", "3", "4"]);
+        assert_eq!(tailer.cursor, Some(4));
+
+        // a gap isn't waited for longer than grace
+        let mut tailer = Tailer::new(changes.clone(), Duration::ZERO);
+        tailer.feed(&store);
+        tailer.poll().await.unwrap();
+        changes.commit(6);
+        assert_eq!(tailer.poll().await.unwrap(), 1);
+        assert_eq!(tailer.cursor, Some(6));
+        changes.commit(5);
+        assert_eq!(tailer.poll().await.unwrap(), 0);
+
+        // the writes of the store are appended after the newest change
+        store
+            .create(&Group {
+                id: "7".to_owned(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(tailer.poll().await.unwrap(), 1);
+        assert_eq!(events.lock().unwrap().last().unwrap(), "7");
+        assert_eq!(tailer.cursor, Some(7));
+        // the committed changes are from the epoch, the appended one isn't
+        let before = chrono::Utc::now().naive_utc() - Duration::from_secs(60);
+        assert_eq!(changes.purge(before).await.unwrap(), 6);
+        assert_eq!(changes.since(0, BATCH).await.unwrap().len(), 1);
+    }
 }
@@ -1,10 +1,15 @@
 mod queue;
+mod subscription;
 
 use std::{
+    collections::VecDeque,
+    error::Error,
     fmt,
-    sync::{Arc, RwLock},
+    sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock},
 };
 
+pub use subscription::{Overflow, Subscription};
+
 pub trait Watcher<T>: Send + Sync + 'static {
     fn notify(&self, event: T);
 }
@@ -21,14 +26,67 @@ where
 struct WatcherInner<T> {
     since_modify: usize,
     watcher: Box<dyn Watcher<T>>,
+    // events not delivered yet, and whether a thread is delivering them
+    queue: Mutex<(VecDeque<T>, bool)>,
 }
 
-impl<T: 'static> Watcher<T> for WatcherInner<T> {
-    fn notify(&self, event: T) {
-        self.watcher.notify(event);
+impl<T: 'static> WatcherInner<T> {
+    fn queue(&self) -> MutexGuard<'_, (VecDeque<T>, bool)> {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn push(&self, event: T) {
+        self.queue().0.push_back(event);
+    }
+
+    /// deliver calls the watcher with the queued events in order, unless
+    /// another thread already is, which then delivers them too. No lock is
+    /// held during a call.
+    fn deliver(&self) {
+        {
+            let mut queue = self.queue();
+            if queue.1 {
+                return;
+            }
+            queue.1 = true;
+        }
+        loop {
+            let event = {
+                let mut queue = self.queue();
+                match queue.0.pop_front() {
+                    Some(event) => event,
+                    None => {
+                        queue.1 = false;
+                        return;
+                    }
+                }
+            };
+            self.watcher.notify(event);
+        }
+    }
+}
+
+/// TooOld is the error of a watch since a revision whose following events
+/// aren't all kept anymore, the watcher should list again and watch since
+/// the revision of the list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TooOld {
+    /// oldest is the smallest revision a watch may start from
+    pub oldest: usize,
+}
+
+impl fmt::Display for TooOld {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the events are kept since revision {} only, list again",
+            self.oldest
+        )
     }
 }
 
+impl Error for TooOld {}
+
 #[derive(Clone)]
 pub struct WatcherHub<T> {
     inner: Arc<WatcherHubInner<T>>,
@@ -36,7 +94,12 @@ pub struct WatcherHub<T> {
 
 struct WatcherHubInner<T> {
     watchers: RwLock<Vec<Arc<WatcherInner<T>>>>,
+    subscribers: RwLock<Vec<(usize, subscription::Shared<T>)>>,
     event_history: RwLock<queue::QueueHistory<T>>,
+    // held while an event is queued to the watchers, or a watcher or a
+    // subscriber is registered, so that they all get the events in the
+    // same order
+    delivery: Mutex<()>,
 }
 
 impl<T> fmt::Debug for WatcherHub<T> {
@@ -58,60 +121,143 @@ impl<T> WatcherHub<T>
 where
     T: Clone + Sync + Send + 'static,
 {
+    /// new keeps the last cap events to replay, a cap of 0 is taken for 1
     pub fn new(cap: usize) -> Self {
         Self {
             inner: Arc::new(WatcherHubInner {
                 watchers: RwLock::new(Vec::new()),
+                subscribers: RwLock::new(Vec::new()),
                 event_history: RwLock::new(queue::QueueHistory::new(cap)),
+                delivery: Mutex::new(()),
             }),
         }
     }
 
+    /// watch replays to handler the events after since_modify, then
+    /// notifies it of the new ones, each of them once and in order. A
+    /// since_modify of 0 watches the new events only. The handler is
+    /// called on a thread notifying, or watching for the replay, without
+    /// a lock of self held.
     pub fn watch<W: Watcher<T>>(
         &self,
         since_modify: usize,
         handler: W,
-    ) -> Box<dyn WatchGuard + Send> {
-        {
-            let event_history_ref = self.inner.event_history.read().unwrap();
-            if let Some(event) = event_history_ref.scan(since_modify) {
-                handler.notify(event.to_owned());
-                return Box::new(Empty);
-            }
-        }
-        let handler_ref = Arc::new(WatcherInner {
-            since_modify,
-            watcher: Box::new(handler),
-        }) as Arc<WatcherInner<T>>;
-        {
+    ) -> Result<Box<dyn WatchGuard + Send>, TooOld> {
+        let handler_ref = {
+            // no event is queued until the handler is registered, so that
+            // none slips in between the replay and the registration
+            let _delivery = self.delivery();
+            let events = replay(
+                &self.inner.event_history.read().unwrap(),
+                since_modify,
+            )?;
+            let handler_ref = Arc::new(WatcherInner {
+                since_modify,
+                watcher: Box::new(handler),
+                queue: Mutex::new((events.into(), false)),
+            });
             let mut watchers_ref = self.inner.watchers.write().unwrap();
             watchers_ref.push(Arc::clone(&handler_ref));
-        }
-        Box::new(Remove {
+            handler_ref
+        };
+        handler_ref.deliver();
+        Ok(Box::new(Remove {
             inner: Arc::clone(&self.inner),
             watcher: handler_ref,
-        })
+        }))
     }
 
-    pub fn add(&self, modify: usize, event: T) {
-        let mut event_history_ref = self.inner.event_history.write().unwrap();
-        event_history_ref.push(modify, event);
+    /// subscribe is watch as a stream, buffering up to capacity events
+    /// the subscriber hasn't polled yet. Once full, overflow tells what
+    /// becomes of the new ones.
+    pub fn subscribe(
+        &self,
+        since_modify: usize,
+        capacity: usize,
+        overflow: Overflow<T>,
+    ) -> Result<Subscription<T>, TooOld> {
+        let mut buffer = subscription::Buffer::new(capacity, overflow);
+        // no event is queued until the subscriber is registered, an event
+        // is either replayed or pushed to its buffer
+        let _delivery = self.delivery();
+        let events =
+            replay(&self.inner.event_history.read().unwrap(), since_modify)?;
+        for event in events {
+            buffer.replay(event);
+        }
+        let buffer = Arc::new(Mutex::new(buffer));
+        self.inner
+            .subscribers
+            .write()
+            .unwrap()
+            .push((since_modify, Arc::clone(&buffer)));
+        Ok(Subscription {
+            inner: Arc::clone(&self.inner),
+            buffer,
+        })
     }
 
     pub fn notify(&self, modify: usize, event: T) {
-        self.add(modify, event.clone());
-        self.notify_watchers(modify, event);
-    }
-
-    pub fn notify_watchers(&self, modify: usize, event: T) {
-        let watchers_ref = self.inner.watchers.read().unwrap();
-        for watcher in watchers_ref.iter() {
-            if watcher.since_modify >= modify {
-                continue;
+        // a watch either replays the event or is notified of it
+        let (watchers, wakers) = {
+            let _delivery = self.delivery();
+            let mut event_history_ref =
+                self.inner.event_history.write().unwrap();
+            event_history_ref.push(modify, event.clone());
+            let wakers: Vec<_> = self
+                .inner
+                .subscribers
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|(since_modify, _)| *since_modify < modify)
+                .filter_map(|(_, buffer)| {
+                    subscription::lock(buffer).push(event.clone())
+                })
+                .collect();
+            let watchers: Vec<_> = self
+                .inner
+                .watchers
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|watcher| watcher.since_modify < modify)
+                .cloned()
+                .collect();
+            for watcher in watchers.iter() {
+                watcher.push(event.clone());
             }
-            watcher.notify(event.clone());
+            (watchers, wakers)
+        };
+        for waker in wakers {
+            waker.wake();
+        }
+        for watcher in watchers {
+            watcher.deliver();
         }
     }
+
+    fn delivery(&self) -> MutexGuard<'_, ()> {
+        self.inner
+            .delivery
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// replay returns the events of history after since_modify, none for a
+/// since_modify of 0
+fn replay<T: Clone>(
+    history: &queue::QueueHistory<T>,
+    since_modify: usize,
+) -> Result<Vec<T>, TooOld> {
+    if since_modify == 0 {
+        return Ok(Vec::new());
+    }
+    let events = history
+        .scan(since_modify)
+        .map_err(|oldest| TooOld { oldest })?;
+    Ok(events.cloned().collect())
 }
 
 pub trait WatchGuard {
@@ -131,18 +277,15 @@ impl<T> Drop for Remove<T> {
     fn drop(&mut self) {
         let mut watchers_ref = self.inner.watchers.write().unwrap();
         watchers_ref.retain(|h| !Arc::ptr_eq(h, &self.watcher));
-        println!("remove");
     }
 }
 
-struct Empty;
-
-impl WatchGuard for Empty {}
-
 #[cfg(test)]
 mod tests {
     use std::{sync::mpsc, thread};
 
+    use futures::{executor::block_on, StreamExt};
+
     use super::*;
 
     #[derive(Clone, Debug)]
@@ -158,9 +301,11 @@ mod tests {
         let evt1 = evt.clone();
         let s1 = thread::spawn(move || {
             let (tx, rx) = mpsc::sync_channel::<Event<usize>>(100);
-            let _remove = evt1.watch(9, move |event| {
-                tx.send(event).unwrap();
-            });
+            let _remove = evt1
+                .watch(9, move |event| {
+                    tx.send(event).unwrap();
+                })
+                .unwrap();
             while let Ok(v) = rx.recv() {
                 match v {
                     Event::Add(value) => match value {
@@ -181,9 +326,11 @@ mod tests {
         let evt2 = evt.clone();
         let s2 = thread::spawn(move || {
             let (tx, rx) = mpsc::sync_channel::<Event<usize>>(100);
-            let _remove = evt2.watch(9, move |event| {
-                tx.send(event).unwrap();
-            });
+            let _remove = evt2
+                .watch(9, move |event| {
+                    tx.send(event).unwrap();
+                })
+                .unwrap();
             while let Ok(v) = rx.recv() {
                 match v {
                     Event::Add(value) => match value {
@@ -218,4 +365,148 @@ mod tests {
         s2.join().unwrap();
         s3.join().unwrap();
     }
+
+    #[test]
+    fn replay() {
+        let hub = WatcherHub::new(3);
+        for v in 1..=3 {
+            hub.notify(v, v);
+        }
+        let (tx, rx) = mpsc::channel();
+        let _remove =
+            hub.watch(1, move |event| tx.send(event).unwrap()).unwrap();
+        hub.notify(4, 4);
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![2, 3, 4]);
+
+        // 1 and 2 are no longer kept
+        hub.notify(5, 5);
+        assert_eq!(hub.watch(1, |_| {}).err(), Some(TooOld { oldest: 2 }));
+
+        // nothing is replayed to a watch from 0
+        let (tx, rx) = mpsc::channel();
+        let _remove =
+            hub.watch(0, move |event| tx.send(event).unwrap()).unwrap();
+        hub.notify(6, 6);
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![6]);
+
+        // a hub of no capacity keeps the last event
+        let hub = WatcherHub::new(0);
+        hub.notify(1, 1);
+        hub.notify(2, 2);
+        let (tx, rx) = mpsc::channel();
+        let _remove =
+            hub.watch(1, move |event| tx.send(event).unwrap()).unwrap();
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn replay_while_notified() {
+        let hub = WatcherHub::new(1000);
+        hub.notify(1, 1);
+        let writer = hub.clone();
+        let s = thread::spawn(move || {
+            for v in 2..=1000 {
+                writer.notify(v, v);
+            }
+        });
+        let (tx, rx) = mpsc::channel();
+        let _remove =
+            hub.watch(1, move |event| tx.send(event).unwrap()).unwrap();
+        s.join().unwrap();
+        // whether replayed or notified, each event comes once and in order
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            (2..=1000).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn subscribe() {
+        let hub = WatcherHub::new(10);
+        for v in 1..=3 {
+            hub.notify(v, v);
+        }
+        let mut subscription = hub.subscribe(1, 2, Overflow::Drop).unwrap();
+        let events: Vec<_> = block_on(subscription.by_ref().take(2).collect());
+        assert_eq!(events, vec![2, 3]);
+        hub.notify(4, 4);
+        assert_eq!(block_on(subscription.next()), Some(4));
+
+        // the buffer holds 2 events, the next ones are dropped
+        for v in 5..=7 {
+            hub.notify(v, v);
+        }
+        let events: Vec<_> = block_on(subscription.by_ref().take(2).collect());
+        assert_eq!(events, vec![5, 6]);
+        hub.notify(8, 8);
+        assert_eq!(block_on(subscription.next()), Some(8));
+
+        drop(subscription);
+        assert!(hub.inner.subscribers.read().unwrap().is_empty());
+    }
+
+    #[test]
+    fn disconnect() {
+        let hub = WatcherHub::new(10);
+        let subscription = hub.subscribe(0, 2, Overflow::Disconnect).unwrap();
+        for v in 1..=4 {
+            hub.notify(v, v);
+        }
+        // the buffered events come before the end
+        let events: Vec<_> = block_on(subscription.collect());
+        assert_eq!(events, vec![1, 2]);
+    }
+
+    #[test]
+    fn coalesce() {
+        // events are (key, revision, oldest revision it stands for), a
+        // newer one supersedes one of its key
+        let hub = WatcherHub::new(10);
+        let subscription = hub
+            .subscribe(
+                0,
+                3,
+                Overflow::Coalesce {
+                    supersedes: |new: &(u8, usize, usize), old| new.0 == old.0,
+                    fold: |new, old| new.2 = old.2,
+                },
+            )
+            .unwrap();
+        for (v, key) in [(1, 1), (2, 2), (3, 1), (4, 1), (5, 2), (6, 3)] {
+            hub.notify(v, (key, v, v));
+        }
+        // 1 is folded into 3, the next one of its key, and 2 into 5. 6
+        // supersedes none of the buffered events.
+        let events: Vec<_> = block_on(subscription.collect());
+        assert_eq!(events, vec![(1, 3, 1), (1, 4, 4), (2, 5, 2)]);
+    }
+
+    #[test]
+    fn notify_from_handler() {
+        let hub = WatcherHub::new(10);
+        let (tx, rx) = mpsc::channel();
+        let writer = hub.clone();
+        let _remove = hub
+            .watch(0, move |event| {
+                // no lock is held while a handler is called
+                if event == 1 {
+                    writer.notify(2, 2);
+                }
+                tx.send(event).unwrap();
+            })
+            .unwrap();
+        hub.notify(1, 1);
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    fn subscribe_across_threads() {
+        let hub = WatcherHub::new(10);
+        let mut subscription =
+            hub.subscribe(0, 1, Overflow::Disconnect).unwrap();
+        let writer = hub.clone();
+        let s = thread::spawn(move || writer.notify(1, 1));
+        assert_eq!(block_on(subscription.next()), Some(1));
+        s.join().unwrap();
+    }
 }
@@ -4,77 +4,82 @@ struct QueueEvent<T> {
     value: T,
 }
 
+/// QueueHistory keeps the last events pushed, up to its capacity
 #[derive(Debug)]
 pub(crate) struct QueueHistory<T> {
     queue: Queue<QueueEvent<T>>,
-    last_modify: usize,
-    start_modify: usize,
+    // the biggest modify of the events pushed out, a scan from below it
+    // would miss some
+    truncated: usize,
 }
 
 #[derive(Debug)]
 struct Queue<T> {
     events: Vec<T>,
+    // the events kept, a Vec may be given a bigger capacity than asked
+    cap: usize,
     front: usize,
     back: usize,
 }
 
 impl<T> Queue<T> {
-    fn push(&mut self, item: T) {
-        if self.events.len() == self.events.capacity() {
-            self.events[self.back] = item;
-            self.front = (self.front + 1) % self.events.capacity();
+    /// push appends item, returning the oldest one when it's full
+    fn push(&mut self, item: T) -> Option<T> {
+        let mut evicted = None;
+        if self.events.len() == self.cap {
+            evicted =
+                Some(std::mem::replace(&mut self.events[self.back], item));
+            self.front = (self.front + 1) % self.cap;
         } else {
             self.events.push(item);
         }
-        self.back = (self.back + 1) % self.events.capacity();
+        self.back = (self.back + 1) % self.cap;
+        evicted
+    }
+
+    /// iter goes through the items from the oldest one
+    fn iter(&self) -> impl Iterator<Item = &T> {
+        let (newer, older) = self.events.split_at(self.front);
+        older.iter().chain(newer)
     }
 }
 
 impl<T> QueueHistory<T> {
+    /// new keeps the last cap events, at least the last one
     pub(crate) fn new(cap: usize) -> Self {
+        let cap = cap.max(1);
         Self {
             queue: Queue {
                 events: Vec::with_capacity(cap),
+                cap,
                 front: 0,
                 back: 0,
             },
-            last_modify: 0,
-            start_modify: 0,
+            truncated: 0,
         }
     }
 
     pub(crate) fn push(&mut self, modify: usize, value: T) {
-        self.last_modify = modify;
-        self.queue.push(QueueEvent { modify, value });
-        let front = &self.queue.events[self.queue.front];
-        self.start_modify = front.modify;
+        if let Some(evicted) = self.queue.push(QueueEvent { modify, value }) {
+            self.truncated = self.truncated.max(evicted.modify);
+        }
     }
 
-    pub(crate) fn scan(&self, modify: usize) -> Option<&T> {
-        // 如果 index 小于 start_index 或大于或等于 last_index，则它不在队列的范围内
-        if modify < self.start_modify || modify > self.last_modify {
-            return None;
-        }
-        // 初始化当前索引位置
-        let mut current_index = self.queue.front;
-        if current_index + 1 > self.queue.events.len() {
-            return None;
-        }
-        loop {
-            // 获取当前索引位置的事件
-            let item = &self.queue.events[current_index];
-            // 检查 key 是否匹配
-            if item.modify >= modify {
-                return Some(&item.value);
-            }
-            // 移动到下一个事件
-            current_index = (current_index + 1) % self.queue.events.capacity();
-            if current_index == self.queue.back {
-                // 遍历队列中的事件，直到找到匹配项或到达队尾
-                // 没有找到匹配的项
-                return None;
-            }
+    /// scan returns the events after modify in the order they were pushed,
+    /// or the oldest modify a scan may start from when some of them are
+    /// no longer kept
+    pub(crate) fn scan(
+        &self,
+        modify: usize,
+    ) -> Result<impl Iterator<Item = &T>, usize> {
+        if modify < self.truncated {
+            return Err(self.truncated);
         }
+        Ok(self
+            .queue
+            .iter()
+            .filter(move |v| v.modify > modify)
+            .map(|v| &v.value))
     }
 }
 
@@ -84,12 +89,29 @@ mod tests {
 
     #[test]
     fn queue() {
-        let mut q = QueueHistory::new(2);
-        for i in 0..3 {
+        let mut q = QueueHistory::new(3);
+        for i in 1..3 {
             q.push(i, i);
         }
-        println!("{:?}", q);
-        assert!(q.scan(1).is_some());
-        assert!(q.scan(3).is_none());
+        assert_eq!(q.scan(0).unwrap().collect::<Vec<_>>(), vec![&1, &2]);
+        for i in 3..6 {
+            q.push(i, i);
+        }
+        assert_eq!(q.scan(2).unwrap().collect::<Vec<_>>(), vec![&3, &4, &5]);
+        assert_eq!(q.scan(4).unwrap().collect::<Vec<_>>(), vec![&5]);
+        assert!(q.scan(5).unwrap().next().is_none());
+        assert_eq!(q.scan(1).err(), Some(2));
+
+        // the events are scanned in the order they were pushed
+        q.push(8, 8);
+        q.push(7, 7);
+        assert_eq!(q.scan(4).unwrap().collect::<Vec<_>>(), vec![&5, &8, &7]);
+
+        // a capacity of 0 keeps the last event
+        let mut q = QueueHistory::new(0);
+        q.push(1, 1);
+        q.push(2, 2);
+        assert_eq!(q.scan(1).unwrap().collect::<Vec<_>>(), vec![&2]);
+        assert_eq!(q.scan(0).err(), Some(1));
     }
 }
@@ -0,0 +1,164 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll, Waker},
+};
+
+use futures_util::Stream;
+
+use crate::WatcherHubInner;
+
+/// Overflow is what a subscription does with a new event once its buffer
+/// is full
+pub enum Overflow<T> {
+    /// Drop discards the new event
+    Drop,
+    /// Disconnect ends the subscription after the buffered events, the
+    /// subscriber may subscribe again since the last one it got
+    Disconnect,
+    /// Coalesce removes the oldest buffered event the new one supersedes,
+    /// as supersedes tells with the newer event first, and disconnects
+    /// when there is none. What the subscriber won't get of the removed
+    /// event is folded into the next buffered one it supersedes, or the
+    /// new one, which goes last: the events stay in the order they were
+    /// published.
+    Coalesce {
+        supersedes: fn(&T, &T) -> bool,
+        fold: fn(&mut T, T),
+    },
+}
+
+impl<T> Clone for Overflow<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Overflow<T> {}
+
+impl<T> fmt::Debug for Overflow<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Drop => f.write_str("Drop"),
+            Self::Disconnect => f.write_str("Disconnect"),
+            Self::Coalesce { .. } => f.write_str("Coalesce"),
+        }
+    }
+}
+
+/// Buffer holds the events of a subscription until they are polled
+pub(crate) struct Buffer<T> {
+    events: VecDeque<T>,
+    capacity: usize,
+    overflow: Overflow<T>,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+impl<T> Buffer<T> {
+    pub(crate) fn new(capacity: usize, overflow: Overflow<T>) -> Self {
+        Self {
+            events: VecDeque::new(),
+            capacity,
+            overflow,
+            closed: false,
+            waker: None,
+        }
+    }
+
+    /// replay appends an event published before the subscription, they
+    /// aren't bounded by the capacity as the history is
+    pub(crate) fn replay(&mut self, event: T) {
+        self.events.push_back(event);
+    }
+
+    /// push appends event as overflow tells, returning the waker of the
+    /// subscriber to wake once no lock is held
+    pub(crate) fn push(&mut self, mut event: T) -> Option<Waker> {
+        if self.closed {
+            return None;
+        }
+        if self.events.len() < self.capacity {
+            self.events.push_back(event);
+        } else {
+            match self.overflow {
+                Overflow::Drop => return None,
+                Overflow::Disconnect => self.closed = true,
+                Overflow::Coalesce { supersedes, fold } => {
+                    let older = self
+                        .events
+                        .iter()
+                        .position(|v| supersedes(&event, v))
+                        .and_then(|i| Some((i, self.events.remove(i)?)));
+                    let Some((i, older)) = older else {
+                        self.closed = true;
+                        return self.waker.take();
+                    };
+                    match self
+                        .events
+                        .range_mut(i..)
+                        .find(|v| supersedes(v, &older))
+                    {
+                        Some(next) => fold(next, older),
+                        None => fold(&mut event, older),
+                    }
+                    self.events.push_back(event);
+                }
+            }
+        }
+        self.waker.take()
+    }
+}
+
+pub(crate) type Shared<T> = Arc<Mutex<Buffer<T>>>;
+
+pub(crate) fn lock<T>(
+    buffer: &Shared<T>,
+) -> std::sync::MutexGuard<'_, Buffer<T>> {
+    buffer.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Subscription is the stream of the events of a hub, it stops receiving
+/// them once dropped
+pub struct Subscription<T> {
+    pub(crate) inner: Arc<WatcherHubInner<T>>,
+    pub(crate) buffer: Shared<T>,
+}
+
+impl<T> fmt::Debug for Subscription<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subscription").finish()
+    }
+}
+
+impl<T> Stream for Subscription<T> {
+    type Item = T;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let mut buffer = lock(&self.buffer);
+        if let Some(event) = buffer.events.pop_front() {
+            return Poll::Ready(Some(event));
+        }
+        if buffer.closed {
+            return Poll::Ready(None);
+        }
+        buffer.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        let mut subscribers = self
+            .inner
+            .subscribers
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        subscribers.retain(|(_, buffer)| !Arc::ptr_eq(buffer, &self.buffer));
+    }
+}
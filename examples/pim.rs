@@ -2,7 +2,9 @@ use std::collections::HashMap;
 
 use serde_json::json;
 
-use cim::pim::{Effect, JsonCondition, Pim, Regexp, Request, Statement};
+use cim::pim::{
+    Effect, JsonCondition, Pim, PrincipalType, Regexp, Request, Statement,
+};
 
 fn main() -> anyhow::Result<()> {
     let sts = vec![Statement {
@@ -115,6 +117,7 @@ fn main() -> anyhow::Result<()> {
             resource: "myrn:some.domain.com:resource:123".to_owned(),
             action: "delete".to_owned(),
             subject: "peter".to_owned(),
+            principal_type: PrincipalType::User,
             context: HashMap::from([
                 (
                     "owner".to_owned(),
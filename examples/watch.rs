@@ -14,9 +14,11 @@ fn main() {
     let evt1 = evt.clone();
     let s1 = thread::spawn(move || {
         let (tx, rx) = mpsc::sync_channel::<Event<usize>>(100);
-        let _remove = evt1.watch(9, move |event| {
-            tx.send(event).unwrap();
-        });
+        let _remove = evt1
+            .watch(9, move |event| {
+                tx.send(event).unwrap();
+            })
+            .unwrap();
         while let Ok(v) = rx.recv() {
             match v {
                 Event::Add(value) => match value {
@@ -37,9 +39,11 @@ fn main() {
     let evt2 = evt.clone();
     let s2 = thread::spawn(move || {
         let (tx, rx) = mpsc::sync_channel::<Event<usize>>(100);
-        let _remove = evt2.watch(9, move |event| {
-            tx.send(event).unwrap();
-        });
+        let _remove = evt2
+            .watch(9, move |event| {
+                tx.send(event).unwrap();
+            })
+            .unwrap();
         while let Ok(v) = rx.recv() {
             match v {
                 Event::Add(value) => match value {